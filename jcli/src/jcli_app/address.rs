@@ -1,7 +1,9 @@
-use crate::jcli_app::utils::key_parser::parse_pub_key;
+use crate::jcli_app::utils::{io, key_parser::parse_pub_key};
 use chain_addr::{AddressReadable, Discrimination, Kind};
 use chain_crypto::bech32::Bech32 as _;
 use chain_crypto::{AsymmetricPublicKey, Ed25519, PublicKey};
+use jormungandr_lib::interfaces::{MultisigDeclaration, MultisigDeclarationError};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -16,6 +18,9 @@ pub enum Address {
 
     /// create an address from the the single public key
     Account(AccountArgs),
+
+    /// create a multisig account address from the public keys of its owners
+    Multisig(MultisigArgs),
 }
 
 #[derive(StructOpt)]
@@ -63,8 +68,38 @@ pub struct AccountArgs {
     discrimination_data: DiscriminationData,
 }
 
+#[derive(StructOpt)]
+pub struct MultisigArgs {
+    /// the number of signatures of the owners needed to spend from the
+    /// account
+    #[structopt(long = "threshold")]
+    threshold: u8,
+
+    /// the public key of an owner in bech32 encoding, repeated for each
+    /// owner. The order of the owners gives the index of their signatures
+    /// in the witnesses of the account.
+    #[structopt(
+        long = "owner",
+        parse(try_from_str = "parse_pub_key"),
+        raw(required = "true", number_of_values = "1")
+    )]
+    owners: Vec<PublicKey<Ed25519>>,
+
+    /// the YAML file to write the declaration of the account in, needed to
+    /// add the witnesses of the owners to a transaction. If omitted it is
+    /// not written.
+    #[structopt(long = "declaration")]
+    declaration: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    discrimination_data: DiscriminationData,
+}
+
 custom_error! {pub Error
-    MultisigAddressNotSupported = "multisig addresses are not supported",
+    MultisigDeclarationInvalid { source: MultisigDeclarationError } = "invalid multisig account",
+    DeclarationFileWriteFailed { source: std::io::Error, path: PathBuf }
+        = @{{ let _ = source; format_args!("could not write declaration file '{}'", path.display()) }},
+    DeclarationSerializationFailed { source: serde_yaml::Error } = "could not serialize the declaration",
 }
 
 impl Address {
@@ -92,6 +127,7 @@ impl Address {
                 account_args.key,
                 account_args.discrimination_data.testing,
             ),
+            Address::Multisig(multisig_args) => mk_multisig(multisig_args)?,
         }
         Ok(())
     }
@@ -111,7 +147,7 @@ fn address_info(address: &AddressReadable) -> Result<(), Error> {
     match kind {
         Kind::Single(single) => println!("public key: {}", single.to_bech32_str()),
         Kind::Account(account) => println!("account: {}", account.to_bech32_str()),
-        Kind::Multisig(_) => return Err(Error::MultisigAddressNotSupported),
+        Kind::Multisig(identifier) => println!("multisig: {}", hex::encode(&identifier)),
         Kind::Group(pubk, groupk) => {
             println!("public key: {}", pubk.to_bech32_str());
             println!("group key:  {}", groupk.to_bech32_str());
@@ -132,6 +168,24 @@ fn mk_account(prefix: &str, s: PublicKey<Ed25519>, testing: bool) {
     mk_address_1(prefix, s, testing, Kind::Account)
 }
 
+fn mk_multisig(args: MultisigArgs) -> Result<(), Error> {
+    let owners = args.owners.into_iter().map(Into::into).collect();
+    let declaration = MultisigDeclaration::new(args.threshold, owners)?;
+    if let Some(path) = &args.declaration {
+        let file = io::open_file_write(&Some(path)).map_err(|source| {
+            Error::DeclarationFileWriteFailed {
+                source,
+                path: path.clone(),
+            }
+        })?;
+        serde_yaml::to_writer(file, &declaration)?;
+    }
+    let discrimination = mk_discrimination(args.discrimination_data.testing);
+    let kind = Kind::Multisig(declaration.identifier());
+    mk_address(&args.discrimination_data.prefix, discrimination, kind);
+    Ok(())
+}
+
 fn mk_discrimination(testing: bool) -> Discrimination {
     if testing {
         Discrimination::Test
@@ -11,6 +11,8 @@ use std::io::{BufRead, Write};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+mod testnet;
+
 custom_error! {pub Error
     InputInvalid { source: std::io::Error, path: PathBuf }
         = @{{ let _ = source; format_args!("invalid input file path '{}'", path.display()) }},
@@ -22,6 +24,11 @@ custom_error! {pub Error
     BlockSerializationFailed { source: std::io::Error, filler: CustomErrorFiller } = "failed to serialize block",
     GenesisSerializationFailed { source: serde_yaml::Error, filler: CustomErrorFiller } = "failed to serialize genesis",
     BuildingGenesisFromBlock0Failed { source: Block0ConfigurationError } = "failed to build genesis from block 0",
+    Rand { source: rand::Error } = "error while using random source",
+    TestnetWithoutPool = "a test network needs at least one stake pool",
+    TestnetPortsExhausted { pools: usize } = "not enough ports left for {pools} nodes",
+    TestnetFileSerializationFailed { source: serde_yaml::Error, path: PathBuf }
+        = @{{ let _ = source; format_args!("failed to serialize '{}'", path.display()) }},
 }
 
 impl Genesis {
@@ -31,6 +38,7 @@ impl Genesis {
             Genesis::Encode(create_arguments) => encode_block_0(create_arguments),
             Genesis::Decode(info_arguments) => decode_block_0(info_arguments),
             Genesis::Hash(hash_arguments) => print_hash(hash_arguments),
+            Genesis::Testnet(testnet) => testnet.exec(),
         }
     }
 }
@@ -92,6 +100,10 @@ pub enum Genesis {
 
     /// print the block hash (aka the block id) of the block 0
     Hash(Input),
+
+    /// generate the genesis file, the block 0, and the configuration and
+    /// secret files of the nodes of a test network
+    Testnet(testnet::Testnet),
 }
 
 #[derive(StructOpt)]
@@ -0,0 +1,510 @@
+//! generation of all the files of a test network
+//!
+//! From the number of stake pools, the wallets and the consensus, the
+//! generator writes the genesis file, the genesis block, and for each stake
+//! pool the configuration and the secret file of the node running it:
+//!
+//! ```text
+//! <output>/genesis.yaml
+//! <output>/block-0.bin
+//! <output>/wallet<N>.sk
+//! <output>/pool<N>/node_config.yaml
+//! <output>/pool<N>/node_secret.yaml
+//! <output>/pool<N>/owner.sk          (genesis praos only)
+//! ```
+//!
+//! Every node is a BFT leader. With the genesis praos consensus, each stake
+//! pool is also registered in the genesis block, its owner funded like the
+//! wallets and delegating to it. The keys come from a ChaCha RNG: with the
+//! same seed and genesis date, the same files are generated. The files of
+//! the secret keys are only accessible to their owner, as the node requires.
+
+use super::Error;
+use crate::jcli_app::{key::Seed, utils::error::CustomErrorFiller};
+use chain_addr::Discrimination;
+use chain_core::property::{Block as _, Serialize as _};
+use chain_crypto::{Curve25519_2HashDH, Ed25519, SumEd25519_12};
+use chain_impl_mockchain::{
+    account::DelegationType,
+    block::{Block, ConsensusVersion},
+    certificate::{
+        PoolOwnersSigned, PoolPermissions, PoolRegistration, PoolSignature, SignedCertificate,
+        StakeDelegation,
+    },
+    key::EitherEd25519SecretKey,
+    leadership::genesis::GenesisPraosLeader,
+    ledger::Ledger,
+    rewards::TaxType,
+    transaction::{
+        AccountBindingSignature, SingleAccountBindingSignature, Transaction,
+        UnspecifiedAccountIdentifier,
+    },
+};
+use chain_time::DurationSeconds;
+use jormungandr_lib::{
+    crypto::key::SigningKey,
+    interfaces::{
+        BftSecret, Block0Configuration, Block0ConfigurationBuilder, GenesisPraosSecret, Initial,
+        InitialUTxO, NodeConfig, NodeConfigBuilder, NodeSecret, TrustedPeer,
+    },
+    time::SecondsSinceUnixEpoch,
+};
+use rand::{rngs::EntropyRng, CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write as _,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+const GENESIS_FILE: &str = "genesis.yaml";
+const BLOCK0_FILE: &str = "block-0.bin";
+const NODE_CONFIG_FILE: &str = "node_config.yaml";
+const NODE_SECRET_FILE: &str = "node_secret.yaml";
+const OWNER_KEY_FILE: &str = "owner.sk";
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Testnet {
+    /// the number of stake pools, each one run by a node
+    #[structopt(long = "pools", default_value = "1")]
+    pools: usize,
+
+    /// the number of account wallets funded in the genesis block
+    #[structopt(long = "wallets", default_value = "0")]
+    wallets: usize,
+
+    /// the initial funds of each wallet, and of each stake pool owner
+    #[structopt(long = "funds", default_value = "1000000000")]
+    funds: u64,
+
+    /// the consensus of the blockchain: `bft` or `genesis_praos`
+    #[structopt(
+        long = "consensus",
+        default_value = "bft",
+        parse(try_from_str = "parse_consensus")
+    )]
+    consensus: ConsensusVersion,
+
+    /// the seed of the keys (32 bytes in hexadecimal). Without seed, the
+    /// keys are different at each generation
+    #[structopt(long = "seed", parse(try_from_str))]
+    seed: Option<Seed>,
+
+    /// the start of the blockchain, in seconds since the UNIX epoch. Now
+    /// by default
+    #[structopt(long = "block0-date")]
+    block0_date: Option<SecondsSinceUnixEpoch>,
+
+    /// the P2P port of the first node, the next nodes take the next ports
+    #[structopt(long = "p2p-port", default_value = "8299")]
+    p2p_port: u16,
+
+    /// the REST port of the first node, the next nodes take the next ports
+    #[structopt(long = "rest-port", default_value = "8443")]
+    rest_port: u16,
+
+    /// the directory to write the files in, created if missing
+    #[structopt(long = "output", parse(from_os_str))]
+    output_dir: PathBuf,
+}
+
+fn parse_consensus(s: &str) -> Result<ConsensusVersion, String> {
+    match s {
+        "bft" => Ok(ConsensusVersion::Bft),
+        "genesis_praos" => Ok(ConsensusVersion::GenesisPraos),
+        other => Err(format!(
+            "unknown consensus '{}', expected `bft` or `genesis_praos`",
+            other
+        )),
+    }
+}
+
+struct Pool {
+    leader_key: SigningKey<Ed25519>,
+    public_id: String,
+    /// the stake pool registered in the genesis block, with the genesis
+    /// praos consensus
+    stake_pool: Option<StakePool>,
+}
+
+struct StakePool {
+    owner_key: SigningKey<Ed25519>,
+    kes_key: SigningKey<SumEd25519_12>,
+    vrf_key: SigningKey<Curve25519_2HashDH>,
+    registration: PoolRegistration,
+}
+
+/// the generated network, before it is written
+struct Network {
+    genesis: Block0Configuration,
+    wallets: Vec<SigningKey<Ed25519>>,
+    pools: Vec<Pool>,
+}
+
+impl Testnet {
+    pub fn exec(self) -> Result<(), Error> {
+        if self.pools == 0 {
+            return Err(Error::TestnetWithoutPool);
+        }
+        let last_port = self.pools as u64 - 1;
+        if u64::from(self.p2p_port) + last_port > u64::from(std::u16::MAX)
+            || u64::from(self.rest_port) + last_port > u64::from(std::u16::MAX)
+        {
+            return Err(Error::TestnetPortsExhausted { pools: self.pools });
+        }
+
+        let mut rng = match self.seed.as_ref() {
+            Some(seed) => ChaChaRng::from_seed(seed.0),
+            None => ChaChaRng::from_rng(EntropyRng::new())?,
+        };
+        let network = self.generate(&mut rng);
+        let block = network.genesis.to_block();
+        Ledger::new(block.id(), block.fragments())?;
+        self.write(&network, &block)?;
+        println!("{}", block.id());
+        Ok(())
+    }
+
+    fn generate<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Network {
+        let discrimination = Discrimination::Test;
+        let wallets: Vec<SigningKey<Ed25519>> = (0..self.wallets)
+            .map(|_| SigningKey::generate(&mut *rng))
+            .collect();
+        let pools: Vec<Pool> = (0..self.pools)
+            .map(|_| Pool::generate(self.consensus, &mut *rng))
+            .collect();
+        let stake_pools: Vec<&StakePool> = pools
+            .iter()
+            .filter_map(|pool| pool.stake_pool.as_ref())
+            .collect();
+
+        let mut builder = Block0ConfigurationBuilder::new(discrimination, self.consensus);
+        if let Some(block0_date) = self.block0_date {
+            builder.with_block0_date(block0_date);
+        }
+        builder.with_consensus_leader_ids(
+            pools
+                .iter()
+                .map(|pool| pool.leader_key.identifier().into())
+                .collect(),
+        );
+        // the owners are funded before delegating, and the pools registered
+        // before the delegations to them
+        builder.add_funds(
+            wallets
+                .iter()
+                .chain(stake_pools.iter().map(|stake_pool| &stake_pool.owner_key))
+                .map(|key| InitialUTxO {
+                    address: key.identifier().to_account_address(discrimination).into(),
+                    value: self.funds.into(),
+                })
+                .collect(),
+        );
+        for stake_pool in stake_pools.iter() {
+            builder.add_initial(Initial::Cert(stake_pool.registration_certificate().into()));
+        }
+        for stake_pool in stake_pools.iter() {
+            builder.add_initial(Initial::Cert(stake_pool.delegation_certificate().into()));
+        }
+
+        Network {
+            genesis: builder.build(),
+            wallets,
+            pools,
+        }
+    }
+
+    fn p2p_address(&self, index: usize) -> String {
+        format!("/ip4/127.0.0.1/tcp/{}", self.p2p_port as usize + index)
+    }
+
+    fn node_config(&self, network: &Network, index: usize, pool_dir: &Path) -> NodeConfig {
+        let pool = &network.pools[index];
+        let trusted_peers = network
+            .pools
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(other, peer)| TrustedPeer {
+                address: self.p2p_address(other),
+                id: peer.public_id.clone(),
+            })
+            .collect();
+        NodeConfigBuilder::new(self.p2p_address(index), pool.public_id.clone())
+            .add_secret_file(pool_dir.join(NODE_SECRET_FILE))
+            .with_storage(pool_dir.join("storage"))
+            .with_rest(SocketAddr::from((
+                [127, 0, 0, 1],
+                self.rest_port + index as u16,
+            )))
+            .with_trusted_peers(trusted_peers)
+            .with_allow_private_addresses(true)
+            .build()
+    }
+
+    fn write(&self, network: &Network, block: &Block) -> Result<(), Error> {
+        create_dir(&self.output_dir)?;
+        write_yaml(&self.output_dir.join(GENESIS_FILE), &network.genesis)?;
+        let block0_path = self.output_dir.join(BLOCK0_FILE);
+        block
+            .serialize(create_file(&block0_path)?)
+            .map_err(|source| Error::BlockSerializationFailed {
+                source,
+                filler: CustomErrorFiller,
+            })?;
+
+        for (index, wallet) in network.wallets.iter().enumerate() {
+            let path = self.output_dir.join(format!("wallet{}.sk", index));
+            write_key(&path, wallet)?;
+        }
+
+        for (index, pool) in network.pools.iter().enumerate() {
+            let pool_dir = self.output_dir.join(format!("pool{}", index));
+            create_dir(&pool_dir)?;
+            write_yaml(
+                &pool_dir.join(NODE_CONFIG_FILE),
+                &self.node_config(network, index, &pool_dir),
+            )?;
+            write_secret_yaml(&pool_dir.join(NODE_SECRET_FILE), &pool.secret())?;
+            if let Some(stake_pool) = pool.stake_pool.as_ref() {
+                write_key(&pool_dir.join(OWNER_KEY_FILE), &stake_pool.owner_key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pool {
+    fn generate<R: RngCore + CryptoRng>(consensus: ConsensusVersion, rng: &mut R) -> Self {
+        let leader_key = SigningKey::generate(&mut *rng);
+        let mut public_id = [0; 24];
+        rng.fill_bytes(&mut public_id);
+        let stake_pool = match consensus {
+            ConsensusVersion::Bft => None,
+            ConsensusVersion::GenesisPraos => Some(StakePool::generate(rng)),
+        };
+        Pool {
+            leader_key,
+            public_id: hex::encode(&public_id),
+            stake_pool,
+        }
+    }
+
+    fn secret(&self) -> NodeSecret {
+        NodeSecret {
+            bft: Some(BftSecret {
+                signing_key: self.leader_key.clone(),
+            }),
+            genesis: self.stake_pool.as_ref().map(StakePool::secret),
+        }
+    }
+}
+
+impl StakePool {
+    fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let owner_key: SigningKey<Ed25519> = SigningKey::generate(&mut *rng);
+        let kes_key: SigningKey<SumEd25519_12> = SigningKey::generate(&mut *rng);
+        let vrf_key: SigningKey<Curve25519_2HashDH> = SigningKey::generate(&mut *rng);
+        let registration = PoolRegistration {
+            serial: rng.gen(),
+            owners: vec![owner_key.identifier().into_public_key()],
+            operators: vec![].into(),
+            permissions: PoolPermissions::new(1),
+            start_validity: DurationSeconds(0).into(),
+            rewards: TaxType::zero(),
+            reward_account: None,
+            keys: GenesisPraosLeader {
+                kes_public_key: kes_key.identifier().into_public_key(),
+                vrf_public_key: vrf_key.identifier().into_public_key(),
+            },
+        };
+        StakePool {
+            owner_key,
+            kes_key,
+            vrf_key,
+            registration,
+        }
+    }
+
+    fn owner_secret_key(&self) -> EitherEd25519SecretKey {
+        EitherEd25519SecretKey::Normal(self.owner_key.clone().into_secret_key())
+    }
+
+    fn registration_certificate(&self) -> SignedCertificate {
+        let builder = Transaction::block0_payload_builder(&self.registration);
+        let signature =
+            SingleAccountBindingSignature::new(&self.owner_secret_key(), &builder.get_auth_data());
+        SignedCertificate::PoolRegistration(
+            self.registration.clone(),
+            PoolSignature::Owners(PoolOwnersSigned {
+                signatures: vec![(0, signature)],
+            }),
+        )
+    }
+
+    fn delegation_certificate(&self) -> SignedCertificate {
+        let delegation = StakeDelegation {
+            account_id: UnspecifiedAccountIdentifier::from_single_account(
+                self.owner_key.identifier().into_public_key().into(),
+            ),
+            delegation: DelegationType::Full(self.registration.to_id()),
+        };
+        let builder = Transaction::block0_payload_builder(&delegation);
+        let signature =
+            AccountBindingSignature::new_single(&self.owner_secret_key(), &builder.get_auth_data());
+        SignedCertificate::StakeDelegation(delegation, signature)
+    }
+
+    fn secret(&self) -> GenesisPraosSecret {
+        let node_id: [u8; 32] = self.registration.to_id().into();
+        GenesisPraosSecret {
+            node_id: node_id.into(),
+            sig_key: self.kes_key.clone(),
+            vrf_key: self.vrf_key.clone(),
+        }
+    }
+}
+
+fn create_dir(path: &Path) -> Result<(), Error> {
+    fs::create_dir_all(path).map_err(|source| Error::OutputInvalid {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+fn create_file(path: &Path) -> Result<fs::File, Error> {
+    fs::File::create(path).map_err(|source| Error::OutputInvalid {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+/// the node refuses the secret files other users can access
+///
+/// The mode given on opening only applies to a new file, the permissions of
+/// a file left by a previous run are restricted before anything is written.
+fn create_secret_file(path: &Path) -> Result<fs::File, Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let output_invalid = |source| Error::OutputInvalid {
+        source,
+        path: path.to_path_buf(),
+    };
+    let file = options.open(path).map_err(output_invalid)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(output_invalid)?;
+    }
+    Ok(file)
+}
+
+fn write_yaml<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    write_yaml_to(create_file(path)?, path, value)
+}
+
+fn write_secret_yaml<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    write_yaml_to(create_secret_file(path)?, path, value)
+}
+
+fn write_yaml_to<T: Serialize>(file: fs::File, path: &Path, value: &T) -> Result<(), Error> {
+    serde_yaml::to_writer(file, value).map_err(|source| Error::TestnetFileSerializationFailed {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+fn write_key(path: &Path, key: &SigningKey<Ed25519>) -> Result<(), Error> {
+    writeln!(create_secret_file(path)?, "{}", key.to_bech32_str()).map_err(|source| {
+        Error::OutputInvalid {
+            source,
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn testnet(consensus: ConsensusVersion) -> Testnet {
+        Testnet {
+            pools: 3,
+            wallets: 2,
+            funds: 1_000,
+            consensus,
+            seed: None,
+            block0_date: Some(SecondsSinceUnixEpoch::default()),
+            p2p_port: 8299,
+            rest_port: 8443,
+            output_dir: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_same_genesis() {
+        for consensus in [ConsensusVersion::Bft, ConsensusVersion::GenesisPraos].iter() {
+            let testnet = testnet(*consensus);
+            let genesis = |seed| {
+                let network = testnet.generate(&mut ChaChaRng::from_seed([seed; 32]));
+                serde_yaml::to_string(&network.genesis).unwrap()
+            };
+            assert_eq!(genesis(1), genesis(1));
+            assert_ne!(genesis(1), genesis(2));
+        }
+    }
+
+    #[test]
+    fn generated_genesis_block_is_valid() {
+        for consensus in [ConsensusVersion::Bft, ConsensusVersion::GenesisPraos].iter() {
+            let network = testnet(*consensus).generate(&mut ChaChaRng::from_seed([1; 32]));
+            let block = network.genesis.to_block();
+            Ledger::new(block.id(), block.fragments()).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_files_are_only_accessible_to_their_owner() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let output_dir = mktemp::Temp::new_dir().unwrap();
+        let testnet = Testnet {
+            output_dir: output_dir.to_path_buf(),
+            ..testnet(ConsensusVersion::GenesisPraos)
+        };
+        let network = testnet.generate(&mut ChaChaRng::from_seed([1; 32]));
+        testnet
+            .write(&network, &network.genesis.to_block())
+            .unwrap();
+        let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let pool_dir = output_dir.join("pool0");
+        assert_eq!(mode(pool_dir.join(NODE_SECRET_FILE)), 0o600);
+        assert_eq!(mode(pool_dir.join(OWNER_KEY_FILE)), 0o600);
+        assert_eq!(mode(output_dir.join("wallet0.sk")), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_secret_files_are_restricted_to_their_owner() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let output_dir = mktemp::Temp::new_dir().unwrap();
+        let path = output_dir.join(NODE_SECRET_FILE);
+        fs::write(&path, "left by a previous run").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_secret_yaml(&path, &"secret").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(!fs::read_to_string(&path).unwrap().contains("previous run"));
+    }
+}
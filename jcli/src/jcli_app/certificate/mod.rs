@@ -9,6 +9,8 @@ use structopt::StructOpt;
 mod get_stake_pool_id;
 mod new_stake_delegation;
 mod new_stake_pool_registration;
+mod new_stake_pool_retirement;
+mod new_stake_pool_update;
 mod sign;
 
 pub(crate) use self::sign::{pool_owner_sign, stake_delegation_account_binding_sign};
@@ -57,6 +59,10 @@ pub enum Certificate {
 pub enum NewArgs {
     /// build a stake pool registration certificate
     StakePoolRegistration(new_stake_pool_registration::StakePoolRegistration),
+    /// build a stake pool retirement certificate
+    StakePoolRetirement(new_stake_pool_retirement::StakePoolRetirement),
+    /// build a stake pool update certificate, changing the keys of the pool
+    StakePoolUpdate(new_stake_pool_update::StakePoolUpdate),
     /// build a stake delegation certificate
     StakeDelegation(new_stake_delegation::StakeDelegation),
 }
@@ -83,6 +89,8 @@ impl NewArgs {
     pub fn exec(self) -> Result<(), Error> {
         match self {
             NewArgs::StakePoolRegistration(args) => args.exec()?,
+            NewArgs::StakePoolRetirement(args) => args.exec()?,
+            NewArgs::StakePoolUpdate(args) => args.exec()?,
             NewArgs::StakeDelegation(args) => args.exec()?,
         }
         Ok(())
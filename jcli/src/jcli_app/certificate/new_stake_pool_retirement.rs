@@ -0,0 +1,32 @@
+use crate::jcli_app::certificate::{write_cert, Error};
+use chain_crypto::Blake2b256;
+use chain_impl_mockchain::certificate::{Certificate, PoolRetirement};
+use chain_time::DurationSeconds;
+use std::ops::Deref;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct StakePoolRetirement {
+    /// hex-encoded id of the stake pool to retire
+    #[structopt(long = "pool-id", name = "POOL_ID")]
+    pub pool_id: Blake2b256,
+    /// the time the stake pool retires at
+    #[structopt(long = "retirement-time", name = "SECONDS-SINCE-START")]
+    pub retirement_time: u64,
+    /// print the output certificate in the given file, if no file given
+    /// the output will be printed in the standard output
+    pub output: Option<PathBuf>,
+}
+
+impl StakePoolRetirement {
+    pub fn exec(self) -> Result<(), Error> {
+        let content = PoolRetirement {
+            pool_id: self.pool_id.into(),
+            retirement_time: DurationSeconds::from(self.retirement_time).into(),
+        };
+
+        let cert = Certificate::PoolRetirement(content);
+        write_cert(self.output.as_ref().map(|x| x.deref()), cert.into())
+    }
+}
@@ -0,0 +1,73 @@
+use crate::jcli_app::certificate::{write_cert, Error};
+use crate::jcli_app::utils::key_parser::parse_pub_key;
+use chain_crypto::{Blake2b256, Curve25519_2HashDH, PublicKey, SumEd25519_12};
+use chain_impl_mockchain::{
+    certificate::{Certificate, PoolUpdate},
+    leadership::genesis::GenesisPraosLeader,
+};
+use chain_time::DurationSeconds;
+use std::ops::Deref;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct StakePoolUpdate {
+    /// hex-encoded id of the stake pool to update
+    #[structopt(long = "pool-id", name = "POOL_ID")]
+    pub pool_id: Blake2b256,
+    /// the time the update is effective from
+    #[structopt(long = "start-validity", name = "SECONDS-SINCE-START")]
+    pub start_validity: u64,
+    /// public key of the block signing key the stake pool has now
+    #[structopt(
+        long = "previous-kes-key",
+        name = "PREVIOUS_KES_KEY",
+        parse(try_from_str = "parse_pub_key")
+    )]
+    pub previous_kes_key: PublicKey<SumEd25519_12>,
+    /// public key of the VRF key the stake pool has now
+    #[structopt(
+        long = "previous-vrf-key",
+        name = "PREVIOUS_VRF_KEY",
+        parse(try_from_str = "parse_pub_key")
+    )]
+    pub previous_vrf_key: PublicKey<Curve25519_2HashDH>,
+    /// public key of the new block signing key
+    #[structopt(
+        long = "kes-key",
+        name = "KES_KEY",
+        parse(try_from_str = "parse_pub_key")
+    )]
+    pub kes_key: PublicKey<SumEd25519_12>,
+    /// public key of the new VRF key
+    #[structopt(
+        long = "vrf-key",
+        name = "VRF_KEY",
+        parse(try_from_str = "parse_pub_key")
+    )]
+    pub vrf_key: PublicKey<Curve25519_2HashDH>,
+    /// print the output certificate in the given file, if no file given
+    /// the output will be printed in the standard output
+    pub output: Option<PathBuf>,
+}
+
+impl StakePoolUpdate {
+    pub fn exec(self) -> Result<(), Error> {
+        let previous_keys = GenesisPraosLeader {
+            kes_public_key: self.previous_kes_key,
+            vrf_public_key: self.previous_vrf_key,
+        };
+        let content = PoolUpdate {
+            pool_id: self.pool_id.into(),
+            start_validity: DurationSeconds::from(self.start_validity).into(),
+            previous_keys: previous_keys.digest(),
+            updated_keys: GenesisPraosLeader {
+                kes_public_key: self.kes_key,
+                vrf_public_key: self.vrf_key,
+            },
+        };
+
+        let cert = Certificate::PoolUpdate(content);
+        write_cert(self.output.as_ref().map(|x| x.deref()), cert.into())
+    }
+}
@@ -0,0 +1,254 @@
+use crate::jcli_app::debug::{fragment, Error};
+use crate::jcli_app::utils::{error::CustomErrorFiller, io, OutputFormat};
+use chain_core::property::{Block as _, Deserialize as _};
+use chain_impl_mockchain::{
+    block::{Block as MockBlock, Proof},
+    header::Header,
+    leadership::{Leadership, Verification},
+    ledger::Ledger,
+};
+use serde_json::{json, Value};
+use std::io::{BufRead, Read as _};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Block {
+    /// file containing the block, as written by the node or by
+    /// `jcli genesis encode`. If not provided, it will be read from stdin.
+    #[structopt(short, long)]
+    input: Option<PathBuf>,
+
+    /// the block is hex-encoded, as in the responses of the REST API
+    #[structopt(long)]
+    hex: bool,
+
+    /// file containing the genesis block of the blockchain. If provided, the
+    /// signature of the header is verified against the leaders of the
+    /// genesis block, and the witnesses of the fragments with its hash
+    #[structopt(long)]
+    genesis: Option<PathBuf>,
+
+    /// set the address prefix to use when displaying the addresses
+    #[structopt(long, default_value = "ca")]
+    prefix: String,
+
+    #[structopt(flatten)]
+    output_format: OutputFormat,
+}
+
+impl Block {
+    pub fn exec(self) -> Result<(), Error> {
+        let mut reader = io::open_file_read(&self.input).map_err(|source| Error::InputInvalid {
+            source,
+            path: self.input.clone().unwrap_or_default(),
+        })?;
+        let bytes = if self.hex {
+            let mut hex_str = String::new();
+            reader.read_line(&mut hex_str)?;
+            hex::decode(hex_str.trim())?
+        } else {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            bytes
+        };
+        let block =
+            MockBlock::deserialize(bytes.as_ref()).map_err(|source| Error::BlockMalformed {
+                source,
+                filler: CustomErrorFiller,
+            })?;
+
+        let genesis = self.load_genesis()?;
+
+        let header = &block.header;
+        let producer = match header.proof() {
+            Proof::None => json!({ "kind": "none" }),
+            Proof::Bft(_) => json!({ "kind": "bft" }),
+            Proof::GenesisPraos(_) => json!({
+                "kind": "genesis_praos",
+                "pool_id": header.get_stakepool_id().map(|id| id.to_string()),
+            }),
+        };
+        let (content_hash, content_size) = block.contents.compute_hash_size();
+        let contents = if content_hash != header.block_content_hash() {
+            json!({
+                "status": "invalid",
+                "reason": "the hash of the contents does not match the header",
+            })
+        } else if content_size != header.block_content_size() {
+            json!({
+                "status": "invalid",
+                "reason": "the size of the contents does not match the header",
+            })
+        } else {
+            json!({ "status": "valid" })
+        };
+        let signature = verify_signature(header, genesis.as_ref())?;
+        let verify = genesis.as_ref().map(|genesis| fragment::VerifyContext {
+            block0_hash: genesis.header.hash(),
+            spending_counters: Vec::new(),
+        });
+        let fragments: Vec<_> = block
+            .contents
+            .iter()
+            .map(|fragment| fragment::describe(fragment, &self.prefix, verify.as_ref()))
+            .collect();
+        let description = json!({
+            "id": header.hash().to_string(),
+            "parent_id": header.block_parent_hash().to_string(),
+            "date": header.block_date().to_string(),
+            "chain_length": u32::from(header.chain_length()),
+            "content_hash": header.block_content_hash().to_string(),
+            "content_size": header.block_content_size(),
+            "producer": producer,
+            "verification": {
+                "contents": contents,
+                "signature": signature,
+            },
+            "fragments": fragments,
+        });
+        println!("{}", self.output_format.format_json(description)?);
+        Ok(())
+    }
+
+    fn load_genesis(&self) -> Result<Option<MockBlock>, Error> {
+        let path = match &self.genesis {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let reader = io::open_file_read(&self.genesis).map_err(|source| Error::InputInvalid {
+            source,
+            path: path.clone(),
+        })?;
+        MockBlock::deserialize(reader)
+            .map(Some)
+            .map_err(|source| Error::GenesisMalformed {
+                source,
+                filler: CustomErrorFiller,
+            })
+    }
+}
+
+/// verify the signature of the header with the leadership of the genesis
+/// block, the one of the node in the epoch of the genesis block. The BFT
+/// leaders stay the same in the later epochs, unless updated by a proposal,
+/// but the stake distribution and the nonce of genesis praos depend on the
+/// chain.
+fn verify_signature(header: &Header, genesis: Option<&MockBlock>) -> Result<Value, Error> {
+    let genesis = match genesis {
+        Some(genesis) => genesis,
+        None => return Ok(unchecked("the genesis block is not given")),
+    };
+    match header.proof() {
+        Proof::None => return Ok(unchecked("the block is not signed")),
+        Proof::GenesisPraos(_)
+            if header.block_date().epoch != genesis.header.block_date().epoch =>
+        {
+            return Ok(unchecked(
+                "the genesis praos leadership after the epoch of the genesis block depends on the chain",
+            ))
+        }
+        Proof::Bft(_) | Proof::GenesisPraos(_) => (),
+    }
+    let ledger = Ledger::new(genesis.id(), genesis.fragments())
+        .map_err(|source| Error::GenesisInvalid { source })?;
+    let leadership = Leadership::new(header.block_date().epoch, &ledger);
+    Ok(match leadership.verify(header) {
+        Verification::Success => json!({ "status": "valid" }),
+        Verification::Failure(error) => json!({
+            "status": "invalid",
+            "reason": error.to_string(),
+        }),
+    })
+}
+
+fn unchecked(reason: &str) -> Value {
+    json!({
+        "status": "unchecked",
+        "reason": reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_addr::Discrimination;
+    use chain_crypto::Ed25519;
+    use chain_impl_mockchain::{
+        block::ConsensusVersion,
+        fragment::Contents,
+        header::{BlockDate, BlockVersion, HeaderBuilderNew},
+    };
+    use jormungandr_lib::{crypto::key::SigningKey, interfaces::Block0ConfigurationBuilder};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    fn genesis(leader: &SigningKey<Ed25519>) -> MockBlock {
+        let mut builder =
+            Block0ConfigurationBuilder::new(Discrimination::Test, ConsensusVersion::Bft);
+        builder.with_consensus_leader_ids(vec![leader.identifier().into()]);
+        builder.build().to_block()
+    }
+
+    /// a block of the slot `slot_id` following the genesis block, its
+    /// header signed as if it was of the slot `signed_slot_id`
+    fn bft_block(
+        genesis: &MockBlock,
+        leader: &SigningKey<Ed25519>,
+        slot_id: u32,
+        signed_slot_id: u32,
+    ) -> MockBlock {
+        let contents = Contents::empty();
+        let leader_id = leader.identifier().into_public_key().into();
+        let builder = |slot_id| {
+            HeaderBuilderNew::new(BlockVersion::Ed25519Signed, &contents)
+                .set_parent(
+                    &genesis.header.hash(),
+                    genesis.header.chain_length().increase(),
+                )
+                .set_date(BlockDate { epoch: 0, slot_id })
+                .to_bft_builder()
+                .unwrap()
+                .set_consensus_data(&leader_id)
+        };
+        let signature = leader
+            .clone()
+            .into_secret_key()
+            .sign_slice(builder(signed_slot_id).get_authenticated_data());
+        let header = builder(slot_id)
+            .set_signature(signature.into())
+            .generalize();
+        MockBlock { header, contents }
+    }
+
+    #[test]
+    fn signature_of_the_leader_is_valid() {
+        let leader = SigningKey::generate(ChaChaRng::from_seed([1; 32]));
+        let genesis = genesis(&leader);
+        let block = bft_block(&genesis, &leader, 1, 1);
+        let outcome = verify_signature(&block.header, Some(&genesis)).unwrap();
+        assert_eq!(outcome["status"], "valid");
+    }
+
+    #[test]
+    fn tampered_header_is_invalid() {
+        let leader = SigningKey::generate(ChaChaRng::from_seed([1; 32]));
+        let genesis = genesis(&leader);
+        let block = bft_block(&genesis, &leader, 1, 2);
+        let outcome = verify_signature(&block.header, Some(&genesis)).unwrap();
+        assert_eq!(outcome["status"], "invalid");
+    }
+
+    #[test]
+    fn signature_of_another_key_is_invalid() {
+        let leader = SigningKey::generate(ChaChaRng::from_seed([1; 32]));
+        let other = SigningKey::generate(ChaChaRng::from_seed([2; 32]));
+        let genesis = genesis(&leader);
+        let block = bft_block(&genesis, &other, 1, 1);
+        let outcome = verify_signature(&block.header, Some(&genesis)).unwrap();
+        assert_eq!(outcome["status"], "invalid");
+        let outcome = verify_signature(&block.header, None).unwrap();
+        assert_eq!(outcome["status"], "unchecked");
+    }
+}
@@ -0,0 +1,275 @@
+//! the contents of a fragment as shown by the debug commands, and the offline
+//! verification of its witnesses
+//!
+//! A witness is only verified when everything it signs is known without the
+//! ledger: the hash of the genesis block, and for an account input the
+//! spending counter of the account. The public key of a UTxO is in the
+//! address of the spent output, not in the fragment, so the UTxO witnesses
+//! are left unchecked. A legacy UTxO witness carries its public key, so its
+//! signature is verified, but as the key is not checked against the spent
+//! output the witness is reported unchecked unless the signature fails.
+
+use chain_addr::{Address, AddressReadable};
+use chain_crypto::Verification;
+use chain_impl_mockchain::{
+    account::SpendingCounter,
+    fragment::Fragment,
+    header::HeaderId,
+    transaction::{
+        InputEnum, Payload, Transaction, Witness, WitnessAccountData, WitnessUtxoData,
+        WitnessUtxoVersion,
+    },
+};
+use serde_json::{json, Value};
+use std::fmt::Debug;
+
+/// what is needed to verify the witnesses
+pub struct VerifyContext {
+    pub block0_hash: HeaderId,
+    /// the spending counters of the account inputs, in the order of the
+    /// inputs
+    pub spending_counters: Vec<u32>,
+}
+
+pub fn describe(fragment: &Fragment, prefix: &str, verify: Option<&VerifyContext>) -> Value {
+    let (kind, transaction) = match fragment {
+        Fragment::Initial(_) => ("initial", None),
+        Fragment::OldUtxoDeclaration(_) => ("old_utxo_declaration", None),
+        Fragment::Transaction(tx) => ("transaction", Some(transaction(tx, prefix, verify))),
+        Fragment::OwnerStakeDelegation(tx) => (
+            "owner_stake_delegation",
+            Some(transaction(tx, prefix, verify)),
+        ),
+        Fragment::StakeDelegation(tx) => {
+            ("stake_delegation", Some(transaction(tx, prefix, verify)))
+        }
+        Fragment::PoolRegistration(tx) => {
+            ("pool_registration", Some(transaction(tx, prefix, verify)))
+        }
+        Fragment::PoolRetirement(tx) => ("pool_retirement", Some(transaction(tx, prefix, verify))),
+        Fragment::PoolUpdate(tx) => ("pool_update", Some(transaction(tx, prefix, verify))),
+        Fragment::UpdateProposal(_) => ("update_proposal", None),
+        Fragment::UpdateVote(_) => ("update_vote", None),
+    };
+    let mut description = json!({
+        "id": fragment.hash().to_string(),
+        "kind": kind,
+    });
+    if let Some(transaction) = transaction {
+        description["transaction"] = transaction;
+    }
+    description
+}
+
+fn transaction<P>(tx: &Transaction<P>, prefix: &str, verify: Option<&VerifyContext>) -> Value
+where
+    P: Payload + Debug,
+{
+    let slice = tx.as_slice();
+    let inputs: Vec<_> = slice
+        .inputs()
+        .iter()
+        .map(|input| match input.to_enum() {
+            InputEnum::UtxoInput(pointer) => json!({
+                "kind": "utxo",
+                "fragment_id": pointer.transaction_id.to_string(),
+                "index": pointer.output_index,
+                "value": pointer.value.0,
+            }),
+            InputEnum::AccountInput(id, value) => json!({
+                "kind": "account",
+                "account": id.to_single_account().map(|account| account.to_string()),
+                "value": value.0,
+            }),
+        })
+        .collect();
+    let outputs: Vec<_> = slice
+        .outputs()
+        .iter()
+        .map(|output| {
+            json!({
+                "address": readable(prefix, &output.address),
+                "value": output.value.0,
+            })
+        })
+        .collect();
+    let witnesses: Vec<_> = slice.witnesses().iter().map(|w| witness_kind(&w)).collect();
+    let mut description = json!({
+        "sign_data_hash": slice.transaction_sign_data_hash().to_string(),
+        "inputs": inputs,
+        "outputs": outputs,
+        "witnesses": witnesses,
+    });
+    if P::HAS_DATA {
+        description["certificate"] = json!(format!("{:?}", slice.payload().into_payload()));
+    }
+    if let Some(context) = verify {
+        description["verification"] = json!(verify_witnesses(tx, context));
+    }
+    description
+}
+
+fn readable(prefix: &str, address: &Address) -> String {
+    AddressReadable::from_address(prefix, address).to_string()
+}
+
+fn witness_kind(witness: &Witness) -> &'static str {
+    match witness {
+        Witness::Utxo(_) => "utxo",
+        Witness::OldUtxo(..) => "legacy_utxo",
+        Witness::Account(_) => "account",
+        Witness::Multisig(_) => "multisig",
+    }
+}
+
+/// the outcome of the verification of each witness, in the order of the
+/// inputs
+fn verify_witnesses<P: Payload>(tx: &Transaction<P>, context: &VerifyContext) -> Vec<Value> {
+    let slice = tx.as_slice();
+    let sign_data_hash = slice.transaction_sign_data_hash();
+    let mut spending_counters = context.spending_counters.iter();
+    let witnesses: Vec<_> = slice.witnesses().iter().collect();
+    let inputs: Vec<_> = slice.inputs().iter().map(|input| input.to_enum()).collect();
+    if witnesses.len() != inputs.len() {
+        return vec![outcome(
+            None,
+            "invalid",
+            format!("{} witnesses for {} inputs", witnesses.len(), inputs.len()),
+        )];
+    }
+    inputs
+        .into_iter()
+        .zip(witnesses)
+        .enumerate()
+        .map(|(index, (input, witness))| match (input, witness) {
+            (InputEnum::AccountInput(id, _), Witness::Account(signature)) => {
+                let account = match id.to_single_account() {
+                    Some(account) => account,
+                    None => return outcome(Some(index), "unchecked", "multisig account"),
+                };
+                let counter = match spending_counters.next() {
+                    Some(counter) => SpendingCounter::from(*counter),
+                    None => {
+                        return outcome(Some(index), "unchecked", "no spending counter given")
+                    }
+                };
+                let data = WitnessAccountData::new(&context.block0_hash, &sign_data_hash, &counter);
+                match signature.verify(account.as_ref(), &data) {
+                    Verification::Success => outcome(Some(index), "valid", ""),
+                    Verification::Failed => outcome(
+                        Some(index),
+                        "invalid",
+                        "the signature does not match the account, the genesis block hash or the spending counter",
+                    ),
+                }
+            }
+            (InputEnum::UtxoInput(_), Witness::OldUtxo(public_key, signature)) => {
+                let data = WitnessUtxoData::new(
+                    &context.block0_hash,
+                    &sign_data_hash,
+                    WitnessUtxoVersion::Legacy,
+                );
+                match signature.verify(public_key, &data) {
+                    Verification::Success => outcome(
+                        Some(index),
+                        "unchecked",
+                        "the signature matches its public key, which is not checked against the spent output",
+                    ),
+                    Verification::Failed => outcome(
+                        Some(index),
+                        "invalid",
+                        "the signature does not match its public key or the genesis block hash",
+                    ),
+                }
+            }
+            (InputEnum::UtxoInput(_), Witness::Utxo(_)) => outcome(
+                Some(index),
+                "unchecked",
+                "the public key of the spent output is not in the fragment",
+            ),
+            (_, Witness::Multisig(_)) => outcome(Some(index), "unchecked", "multisig witness"),
+            (_, witness) => outcome(
+                Some(index),
+                "invalid",
+                format!("{} witness for this kind of input", witness_kind(&witness)),
+            ),
+        })
+        .collect()
+}
+
+fn outcome(input: Option<usize>, status: &str, reason: impl Into<String>) -> Value {
+    let reason = reason.into();
+    let mut outcome = json!({ "status": status });
+    if let Some(input) = input {
+        outcome["input"] = json!(input);
+    }
+    if !reason.is_empty() {
+        outcome["reason"] = json!(reason);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_addr::{Discrimination, Kind};
+    use chain_crypto::{Ed25519, Ed25519Bip32, SecretKey};
+    use chain_impl_mockchain::{fee::LinearFee, transaction::OutputPolicy, value::Value};
+    use jormungandr_lib::{crypto::hash::Hash, transaction::TransactionBuilder};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    fn block0_hash(seed: u8) -> HeaderId {
+        Hash::from([seed; 32]).into_hash()
+    }
+
+    /// a transaction spending a legacy UTxO, witnessed for the given block0
+    fn legacy_transaction(block0_hash: &HeaderId) -> Fragment {
+        let secret_key = SecretKey::<Ed25519Bip32>::generate(ChaChaRng::from_seed([1; 32]));
+        let receiver = SecretKey::<Ed25519>::generate(ChaChaRng::from_seed([2; 32])).to_public();
+        let mut builder = TransactionBuilder::new();
+        builder.add_utxo_input(Hash::from([3; 32]), 0, Value(100));
+        builder.add_output(
+            Address(Discrimination::Test, Kind::Single(receiver)),
+            Value(100),
+        );
+        let (mut witnessing, _) = builder
+            .finalize(&LinearFee::new(0, 0, 0), OutputPolicy::Forget)
+            .unwrap();
+        witnessing
+            .witness_legacy_utxo(block0_hash, secret_key)
+            .unwrap();
+        witnessing.seal().unwrap().fragment().unwrap()
+    }
+
+    fn verification(fragment: &Fragment, block0_hash: HeaderId) -> Vec<serde_json::Value> {
+        let context = VerifyContext {
+            block0_hash,
+            spending_counters: Vec::new(),
+        };
+        match fragment {
+            Fragment::Transaction(tx) => verify_witnesses(tx, &context),
+            _ => panic!("not a transaction"),
+        }
+    }
+
+    #[test]
+    fn matching_legacy_witness_is_unchecked() {
+        let fragment = legacy_transaction(&block0_hash(0));
+
+        let outcomes = verification(&fragment, block0_hash(0));
+
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0]["status"], "unchecked");
+        assert_eq!(outcomes[0]["input"], 0);
+    }
+
+    #[test]
+    fn legacy_witness_of_another_block0_is_invalid() {
+        let fragment = legacy_transaction(&block0_hash(0));
+
+        let outcomes = verification(&fragment, block0_hash(1));
+
+        assert_eq!(outcomes[0]["status"], "invalid");
+    }
+}
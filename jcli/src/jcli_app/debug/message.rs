@@ -1,23 +1,42 @@
-use crate::jcli_app::debug::Error;
-use crate::jcli_app::utils::{error::CustomErrorFiller, io};
+use crate::jcli_app::debug::{fragment, Error};
+use crate::jcli_app::utils::{error::CustomErrorFiller, io, OutputFormat};
 use chain_core::property::Deserialize as _;
-use chain_impl_mockchain::fragment::Fragment as MockFragment;
+use chain_impl_mockchain::{fragment::Fragment as MockFragment, header::HeaderId};
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
 pub struct Message {
     /// file containing hex-encoded message. If not provided, it will be read from stdin.
     #[structopt(short, long)]
     input: Option<PathBuf>,
+
+    /// the hash of the block0, the first block of the blockchain. If provided,
+    /// the witnesses of the message are verified
+    #[structopt(long, parse(try_from_str))]
+    genesis_block_hash: Option<HeaderId>,
+
+    /// the spending counter of an account input, once for each account input
+    /// in the order of the inputs. The witness of an account input without a
+    /// counter is not verified
+    #[structopt(long)]
+    account_spending_counter: Vec<u32>,
+
+    /// set the address prefix to use when displaying the addresses
+    #[structopt(long, default_value = "ca")]
+    prefix: String,
+
+    #[structopt(flatten)]
+    output_format: OutputFormat,
 }
 
 impl Message {
     pub fn exec(self) -> Result<(), Error> {
         let reader = io::open_file_read(&self.input).map_err(|source| Error::InputInvalid {
             source,
-            path: self.input.unwrap_or_default(),
+            path: self.input.clone().unwrap_or_default(),
         })?;
         let mut hex_str = String::new();
         BufReader::new(reader).read_line(&mut hex_str)?;
@@ -28,7 +47,14 @@ impl Message {
                 filler: CustomErrorFiller,
             }
         })?;
-        println!("{:#?}", message);
+        let verify = self
+            .genesis_block_hash
+            .map(|block0_hash| fragment::VerifyContext {
+                block0_hash,
+                spending_counters: self.account_spending_counter,
+            });
+        let description = fragment::describe(&message, &self.prefix, verify.as_ref());
+        println!("{}", self.output_format.format_json(description)?);
         Ok(())
     }
 }
@@ -1,6 +1,8 @@
+mod block;
+mod fragment;
 mod message;
 
-use crate::jcli_app::utils::error::CustomErrorFiller;
+use crate::jcli_app::utils::{error::CustomErrorFiller, output_format};
 use hex::FromHexError;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -8,7 +10,12 @@ use structopt::StructOpt;
 #[derive(StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 pub enum Debug {
-    /// Decode hex-encoded message an display its content
+    /// Decode a block and display its content, checking its contents against
+    /// its header. The signature of the header is verified when the genesis
+    /// block is given
+    Block(block::Block),
+    /// Decode hex-encoded message an display its content. The witnesses are
+    /// verified when the genesis block hash is given
     Message(message::Message),
 }
 
@@ -18,11 +25,16 @@ custom_error! {pub Error
         = @{{ let _ = source; format_args!("invalid input file path '{}'", path.display()) }},
     HexMalformed { source: FromHexError } = "hex encoding malformed",
     MessageMalformed { source: std::io::Error, filler: CustomErrorFiller } = "message malformed",
+    BlockMalformed { source: std::io::Error, filler: CustomErrorFiller } = "block malformed",
+    GenesisMalformed { source: std::io::Error, filler: CustomErrorFiller } = "genesis block malformed",
+    GenesisInvalid { source: chain_impl_mockchain::ledger::Error } = "genesis block invalid",
+    OutputFormatFailed { source: output_format::Error } = "formatting output failed",
 }
 
 impl Debug {
     pub fn exec(self) -> Result<(), Error> {
         match self {
+            Debug::Block(block) => block.exec(),
             Debug::Message(message) => message.exec(),
         }
     }
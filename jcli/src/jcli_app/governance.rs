@@ -0,0 +1,185 @@
+use crate::jcli_app::utils::{
+    error::CustomErrorFiller,
+    io::{self, ReadYamlError},
+    key_parser::parse_pub_key,
+};
+use chain_core::property::Serialize as _;
+use chain_crypto::{Ed25519, PublicKey};
+use chain_impl_mockchain::{
+    fragment::{ConfigParams, Fragment},
+    leadership::bft::LeaderId,
+    update::{
+        SignedUpdateProposal, SignedUpdateVote, UpdateProposal, UpdateProposalWithProposer,
+        UpdateVote,
+    },
+};
+use jormungandr_lib::{crypto::hash::Hash, interfaces::UpdateProposalChanges};
+use std::{io::Write as _, path::PathBuf};
+use structopt::StructOpt;
+
+/// Update proposals of the BFT leaders and their votes
+///
+/// A proposal changes the settings of the blockchain at the start of the
+/// epoch following the votes of more than half of the BFT leaders. The
+/// messages are sent to a node with `jcli rest v0 message post`, and the
+/// proposals open to the votes are listed by
+/// `jcli rest v0 update-proposals get`.
+#[derive(StructOpt)]
+#[structopt(name = "governance", rename_all = "kebab-case")]
+pub enum Governance {
+    /// create the message of an update proposal
+    Proposal(ProposalArgs),
+    /// create the message of the vote of a BFT leader for an update proposal
+    Vote(VoteArgs),
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct ProposalArgs {
+    /// the public key of the BFT leader making the proposal, in bech32
+    #[structopt(long = "proposer", parse(try_from_str = "parse_pub_key"))]
+    proposer: PublicKey<Ed25519>,
+
+    /// the YAML file of the changed settings, named as in the blockchain
+    /// configuration of the genesis file. If omitted they will be read from
+    /// the standard input.
+    #[structopt(name = "CHANGES_FILE")]
+    changes: Option<PathBuf>,
+
+    /// the file to write the hex-encoded message in. If omitted it will be
+    /// printed to the standard output.
+    #[structopt(long = "output")]
+    output: Option<PathBuf>,
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct VoteArgs {
+    /// the public key of the voting BFT leader, in bech32
+    #[structopt(long = "voter", parse(try_from_str = "parse_pub_key"))]
+    voter: PublicKey<Ed25519>,
+
+    /// the hex-encoded fragment ID of the proposal, as printed when its
+    /// message is posted
+    #[structopt(name = "PROPOSAL_ID")]
+    proposal_id: Hash,
+
+    /// the file to write the hex-encoded message in. If omitted it will be
+    /// printed to the standard output.
+    #[structopt(long = "output")]
+    output: Option<PathBuf>,
+}
+
+custom_error! {pub Error
+    ChangesInvalid { source: ReadYamlError } = "could not read the changes of the proposal",
+    NoChanges = "the update proposal does not change any setting",
+    OutputInvalid { source: std::io::Error } = "could not write the message",
+    MessageSerializationFailed { source: std::io::Error, filler: CustomErrorFiller }
+        = "serialization of message to bytes failed",
+}
+
+impl Governance {
+    pub fn exec(self) -> Result<(), Error> {
+        match self {
+            Governance::Proposal(args) => {
+                let changes: UpdateProposalChanges = io::read_yaml(&args.changes)?;
+                if changes.is_empty() {
+                    return Err(Error::NoChanges);
+                }
+                let fragment = proposal_fragment(args.proposer, changes.into());
+                write_message(&fragment, &args.output)
+            }
+            Governance::Vote(args) => {
+                let fragment = vote_fragment(args.voter, args.proposal_id);
+                write_message(&fragment, &args.output)
+            }
+        }
+    }
+}
+
+fn proposal_fragment(proposer: PublicKey<Ed25519>, changes: ConfigParams) -> Fragment {
+    Fragment::UpdateProposal(SignedUpdateProposal {
+        proposal: UpdateProposalWithProposer {
+            proposal: UpdateProposal { changes },
+            proposer_id: LeaderId::from(proposer),
+        },
+    })
+}
+
+fn vote_fragment(voter: PublicKey<Ed25519>, proposal_id: Hash) -> Fragment {
+    Fragment::UpdateVote(SignedUpdateVote {
+        vote: UpdateVote {
+            proposal_id: proposal_id.into_digest_of(),
+            voter_id: LeaderId::from(voter),
+        },
+    })
+}
+
+fn write_message(fragment: &Fragment, output: &Option<PathBuf>) -> Result<(), Error> {
+    let bytes =
+        fragment
+            .serialize_as_vec()
+            .map_err(|source| Error::MessageSerializationFailed {
+                source,
+                filler: CustomErrorFiller,
+            })?;
+    let mut output = io::open_file_write(output)?;
+    writeln!(output, "{}", hex::encode(&bytes))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::IntoBuf as _;
+    use chain_core::property::{Deserialize as _, Fragment as _};
+    use chain_crypto::SecretKey;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::convert::TryFrom;
+
+    fn public_key(seed: u8) -> PublicKey<Ed25519> {
+        SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([seed; 32])).to_public()
+    }
+
+    fn round_trip(fragment: &Fragment) -> Fragment {
+        let bytes = fragment.serialize_as_vec().unwrap();
+        Fragment::deserialize(bytes.as_slice().into_buf()).unwrap()
+    }
+
+    #[test]
+    fn proposal_has_the_changes_of_the_yaml_file() {
+        let changes: UpdateProposalChanges =
+            serde_yaml::from_str("slot_duration: 4\nproposal_expiration: 10\n").unwrap();
+        let fragment = round_trip(&proposal_fragment(public_key(1), changes.clone().into()));
+        match fragment {
+            Fragment::UpdateProposal(update_proposal) => {
+                let proposal = update_proposal.proposal;
+                assert_eq!(proposal.proposer_id, LeaderId::from(public_key(1)));
+                let decoded = UpdateProposalChanges::try_from(proposal.proposal.changes).unwrap();
+                assert_eq!(decoded, changes);
+            }
+            _ => panic!("not an update proposal"),
+        }
+    }
+
+    #[test]
+    fn vote_is_for_the_fragment_of_the_proposal() {
+        let proposal = proposal_fragment(public_key(1), ConfigParams::new());
+        let proposal_id = Hash::from(proposal.id());
+        match round_trip(&vote_fragment(public_key(2), proposal_id)) {
+            Fragment::UpdateVote(update_vote) => {
+                assert_eq!(update_vote.vote.proposal_id, proposal.id());
+                assert_eq!(update_vote.vote.voter_id, LeaderId::from(public_key(2)));
+            }
+            _ => panic!("not an update vote"),
+        }
+    }
+
+    #[test]
+    fn proposal_changes_settings() {
+        let changes: UpdateProposalChanges = serde_yaml::from_str("{}").unwrap();
+        assert!(changes.is_empty());
+        let changes: Result<UpdateProposalChanges, _> = serde_yaml::from_str("block0_date: 0");
+        assert!(changes.is_err());
+    }
+}
@@ -7,9 +7,14 @@ use chain_crypto::{
 };
 use ed25519_bip32::{DerivationError, DerivationScheme};
 use hex::FromHexError;
+use jormungandr_lib::crypto::{
+    node_id::{PrivateId, PrivateIdError},
+    passphrase::{zeroize, Encrypted, EncryptedFile, Passphrase},
+};
 use rand::{rngs::EntropyRng, SeedableRng};
 use rand_chacha::ChaChaRng;
 use std::{
+    fs::{self, File, OpenOptions},
     io::{Read, Write},
     path::{Path, PathBuf},
 };
@@ -34,6 +39,8 @@ custom_error! { pub Error
         = "signature bech32 has invalid HRP: '{actual_hrp}', expected: '{expected_hrp}'",
     SignatureVerification = "signature verification failed",
     Derivation { source: DerivationError } = "failed to derive from BIP32 public key",
+    SecretFormat { source: serde_yaml::Error } = "cannot serialize the encrypted secrets",
+    PrivateId { source: PrivateIdError } = "invalid private node ID",
 }
 
 #[derive(StructOpt, Debug)]
@@ -53,6 +60,52 @@ pub enum Key {
     Verify(Verify),
     /// derive a child key
     Derive(Derive),
+    /// manage the ID of a node in the P2P network
+    NodeId(NodeId),
+    /// encrypt a secret file of the node with a passphrase
+    EncryptSecret(EncryptSecret),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub enum NodeId {
+    /// generate a random private ID, for the file of `p2p.private_id_file`
+    /// in the node configuration
+    Generate(GenerateNodeId),
+    /// get the node ID out of a given private ID
+    Derive(DeriveNodeId),
+    /// replace the private ID in the given file by a new one and print the
+    /// new node ID, announced by the node once it reloads its configuration
+    Rotate(RotateNodeId),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct GenerateNodeId {
+    /// output the private ID to the given file, only accessible by its owner,
+    /// or to stdout if not provided
+    #[structopt(name = "OUTPUT_FILE")]
+    output: Option<PathBuf>,
+
+    /// optional seed to generate the private ID, for the same entropy the
+    /// same ID will be generated (32 bytes in hexadecimal). Do not use if you
+    /// are not sure.
+    #[structopt(long = "seed", short = "s", name = "SEED", parse(try_from_str))]
+    seed: Option<Seed>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DeriveNodeId {
+    /// path to the private ID, if no value is passed, standard input will be
+    /// used
+    #[structopt(name = "INPUT_FILE")]
+    input: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RotateNodeId {
+    /// path to the private ID file of the node, `p2p.private_id_file`
+    #[structopt(name = "PRIVATE_ID_FILE")]
+    path: PathBuf,
 }
 
 #[derive(StructOpt, Debug)]
@@ -146,6 +199,22 @@ pub struct Verify {
     data: Option<PathBuf>,
 }
 
+#[derive(StructOpt, Debug)]
+pub struct EncryptSecret {
+    /// path to file with the passphrase, as given to the node with
+    /// `--secrets-passphrase-file`
+    #[structopt(long = "passphrase-file")]
+    passphrase_file: PathBuf,
+
+    /// path to file to write the encrypted secrets into, only accessible by
+    /// its owner, if no value is passed, standard output will be used
+    #[structopt(long = "output", short = "o")]
+    output: Option<PathBuf>,
+
+    /// path to the secret file to encrypt, if no value is passed, standard input will be used
+    input: Option<PathBuf>,
+}
+
 #[derive(StructOpt, Debug)]
 pub struct Derive {
     /// the parent key to derive a child key from
@@ -199,6 +268,10 @@ impl Key {
             Key::Sign(args) => args.exec(),
             Key::Verify(args) => args.exec(),
             Key::Derive(args) => args.exec(),
+            Key::NodeId(NodeId::Generate(args)) => args.exec(),
+            Key::NodeId(NodeId::Derive(args)) => args.exec(),
+            Key::NodeId(NodeId::Rotate(args)) => args.exec(),
+            Key::EncryptSecret(args) => args.exec(),
         }
     }
 }
@@ -218,6 +291,122 @@ impl Generate {
     }
 }
 
+impl GenerateNodeId {
+    fn exec(self) -> Result<(), Error> {
+        let mut rng = if let Some(seed) = self.seed {
+            ChaChaRng::from_seed(seed.0)
+        } else {
+            ChaChaRng::from_rng(EntropyRng::new())?
+        };
+        let private_id = PrivateId::generate(&mut rng);
+        let mut output =
+            io::open_file_write(&self.output).map_err(|source| Error::InvalidOutput {
+                source,
+                path: io::path_to_path_buf(&self.output),
+            })?;
+        if let Some(ref path) = self.output {
+            restrict_to_owner(path)?;
+        }
+        writeln!(output, "{}", private_id)?;
+        Ok(())
+    }
+}
+
+impl DeriveNodeId {
+    fn exec(self) -> Result<(), Error> {
+        let private_id: PrivateId = io::read_line(&self.input)?.parse()?;
+        println!("{}", private_id.public_id());
+        Ok(())
+    }
+}
+
+impl RotateNodeId {
+    fn exec(self) -> Result<(), Error> {
+        let mut rng = ChaChaRng::from_rng(EntropyRng::new())?;
+        let private_id = PrivateId::generate(&mut rng);
+        replace_private_id(&self.path, &private_id)?;
+        println!("{}", private_id.public_id());
+        Ok(())
+    }
+}
+
+/// write the private ID to a temporary file renamed over the given one: a
+/// node reloading meanwhile reads the old or the new private ID, never a
+/// partial one
+fn replace_private_id(path: &Path, private_id: &PrivateId) -> Result<(), Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let output_error = |source| Error::InvalidOutput {
+        source,
+        path: path.to_path_buf(),
+    };
+    let mut file = create_owner_only(&tmp_path).map_err(output_error)?;
+    let result = writeln!(file, "{}", private_id)
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.map_err(output_error)
+}
+
+#[cfg(unix)]
+fn create_owner_only(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt as _;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_owner_only(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// the node refuses the private ID files other users can access
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt as _;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).map_err(|source| {
+        Error::InvalidOutput {
+            source,
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+impl EncryptSecret {
+    fn exec(self) -> Result<(), Error> {
+        let passphrase =
+            std::fs::read(&self.passphrase_file).map_err(|source| Error::InvalidInput {
+                source,
+                path: self.passphrase_file.clone(),
+            })?;
+        let passphrase = Passphrase::new(passphrase);
+        let mut secrets = Vec::new();
+        io::open_file_read(&self.input)?.read_to_end(&mut secrets)?;
+        let mut rng = ChaChaRng::from_rng(EntropyRng::new())?;
+        let encrypted = Encrypted::encrypt(&mut rng, &passphrase, &secrets);
+        zeroize(&mut secrets);
+        let content = serde_yaml::to_string(&EncryptedFile {
+            encrypted: Some(encrypted),
+        })?;
+        writeln!(io::open_file_write(&self.output)?, "{}", content)?;
+        if let Some(ref path) = self.output {
+            restrict_to_owner(path)?;
+        }
+        Ok(())
+    }
+}
+
 impl ToPublic {
     fn exec(self) -> Result<(), Error> {
         let bech32 = read_bech32(&self.input_key)?;
@@ -415,7 +604,7 @@ fn bytes_to_priv_key<K: AsymmetricKey>(bytes: &[u8]) -> Result<String, Error> {
 }
 
 #[derive(Debug)]
-struct Seed([u8; 32]);
+pub(crate) struct Seed(pub(crate) [u8; 32]);
 impl std::str::FromStr for Seed {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -430,3 +619,83 @@ impl std::str::FromStr for Seed {
         Ok(Seed(bytes))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "jcli-key-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn read_private_id(path: &Path) -> PrivateId {
+        fs::read_to_string(path).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn generated_private_id_is_owner_only() {
+        let path = temp_path("generated");
+        GenerateNodeId {
+            output: Some(path.clone()),
+            seed: Some(Seed([1; 32])),
+        }
+        .exec()
+        .unwrap();
+        let private_id = read_private_id(&path);
+        let metadata = fs::metadata(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        assert_eq!(
+            private_id.public_id(),
+            PrivateId::generate(&mut rng).public_id()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = metadata;
+    }
+
+    #[test]
+    fn rotation_replaces_the_private_id() {
+        let path = temp_path("rotated");
+        let mut rng = ChaChaRng::from_seed([2; 32]);
+        let old = PrivateId::generate(&mut rng);
+        let new = PrivateId::generate(&mut rng);
+        replace_private_id(&path, &old).unwrap();
+
+        replace_private_id(&path, &new).unwrap();
+        let rotated = read_private_id(&path);
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_left = Path::new(&tmp_path).exists();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rotated.public_id(), new.public_id());
+        assert_ne!(rotated.public_id(), old.public_id());
+        assert!(!tmp_left);
+    }
+
+    #[test]
+    fn rotation_does_not_reuse_a_stale_temporary_file() {
+        let path = temp_path("stale");
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, "stale").unwrap();
+        let mut rng = ChaChaRng::from_seed([3; 32]);
+
+        let result = replace_private_id(&path, &PrivateId::generate(&mut rng));
+        let written = path.exists();
+        fs::remove_file(&tmp_path).unwrap();
+
+        assert!(result.is_err());
+        assert!(!written);
+    }
+}
@@ -3,6 +3,7 @@ mod auto_completion;
 mod block;
 mod certificate;
 mod debug;
+mod governance;
 mod key;
 mod rest;
 mod transaction;
@@ -47,6 +48,8 @@ pub enum JCliCommand {
     Debug(debug::Debug),
     /// Certificate generation tool
     Certificate(certificate::Certificate),
+    /// Update proposals of the BFT leaders and their votes
+    Governance(governance::Governance),
     /// Auto completion
     AutoCompletion(auto_completion::AutoCompletion),
     /// Utilities that perform specialized tasks
@@ -80,6 +83,7 @@ impl JCliCommand {
             Transaction(transaction) => transaction.exec()?,
             Debug(debug) => debug.exec()?,
             Certificate(certificate) => certificate.exec()?,
+            Governance(governance) => governance.exec()?,
             AutoCompletion(auto_completion) => auto_completion.exec::<Self>()?,
             Utils(utils) => utils.exec()?,
         };
@@ -23,6 +23,7 @@ custom_error! {pub Error
     InputFileYamlMalformed { source: serde_yaml::Error } = "input yaml is not valid",
     InputSerializationFailed { source: serde_json::Error, filler: CustomErrorFiller } = "failed to serialize input",
     InputHexMalformed { source: FromHexError } = "input hex encoding is not valid",
+    HeaderMalformed { source: std::io::Error, filler: CustomErrorFiller } = "node returned a malformed block header",
 }
 
 impl From<ReadYamlError> for Error {
@@ -27,11 +27,11 @@ impl Account {
             output_format,
             account_id,
         } = self;
-        let url = addr
+        let (url, connection) = addr
             .with_segments(&["v0", "account", &account_id.to_url_arg()])?
-            .into_url();
+            .into_parts();
         let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
         response.ok_response()?;
         let state = response.body().json_value()?;
         let formatted = output_format.format_json(state)?;
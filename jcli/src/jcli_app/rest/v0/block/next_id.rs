@@ -32,11 +32,11 @@ fn exec_get(
     debug: DebugFlag,
     count: Option<usize>,
 ) -> Result<(), Error> {
-    let url = addr
+    let (url, connection) = addr
         .with_segments(&["v0", "block", &block_id, "next_id"])?
-        .into_url();
+        .into_parts();
     let builder = reqwest::Client::new().get(url).query(&[("count", count)]);
-    let response = RestApiSender::new(builder, &debug).send()?;
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     let body = response.body().binary();
     for block_id in body.chunks(Blake2b256::HASH_SIZE) {
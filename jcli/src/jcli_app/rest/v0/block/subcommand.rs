@@ -27,9 +27,11 @@ impl Subcommand {
 }
 
 fn exec_get(block_id: String, addr: HostAddr, debug: DebugFlag) -> Result<(), Error> {
-    let url = addr.with_segments(&["v0", "block", &block_id])?.into_url();
+    let (url, connection) = addr
+        .with_segments(&["v0", "block", &block_id])?
+        .into_parts();
     let builder = reqwest::Client::new().get(url);
-    let response = RestApiSender::new(builder, &debug).send()?;
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     let body = response.body().binary();
     println!("{}", hex::encode(&body));
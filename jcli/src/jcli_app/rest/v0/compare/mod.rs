@@ -0,0 +1,254 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{
+    error::CustomErrorFiller, Connection, DebugFlag, HostAddr, OutputFormat, RestApiSender,
+};
+use chain_core::property::Deserialize as _;
+use chain_impl_mockchain::header::Header;
+use reqwest::Url;
+use serde_json::json;
+use structopt::StructOpt;
+
+/// Compare the chains of two nodes
+///
+/// The block headers are walked back from the tips of both nodes until their
+/// common ancestor, the forks being the blocks of each node after it.
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Compare {
+    /// API address of the first node, e.g. `http://127.0.0.1:8443/api`
+    #[structopt(long)]
+    host_a: Url,
+    /// API address of the second node
+    #[structopt(long)]
+    host_b: Url,
+    /// the most blocks walked back on both nodes before giving up
+    #[structopt(long, default_value = "1000")]
+    max_depth: u32,
+    #[structopt(flatten)]
+    connection: Connection,
+    #[structopt(flatten)]
+    debug: DebugFlag,
+    #[structopt(flatten)]
+    output_format: OutputFormat,
+}
+
+/// a block of the chain of a node, the blocks being walked from the tip
+#[derive(Clone)]
+struct ChainBlock {
+    id: String,
+    parent_id: String,
+    chain_length: u32,
+}
+
+impl Compare {
+    pub fn exec(self) -> Result<(), Error> {
+        let a = HostAddr::new(self.host_a, self.connection.clone());
+        let b = HostAddr::new(self.host_b, self.connection);
+        let debug = &self.debug;
+        let tip_a = get_header(&a, &get_tip(&a, debug)?, debug)?;
+        let tip_b = get_header(&b, &get_tip(&b, debug)?, debug)?;
+        let ancestor = common_ancestor(
+            &tip_a,
+            &tip_b,
+            self.max_depth,
+            |id| get_header(&a, id, debug),
+            |id| get_header(&b, id, debug),
+        )?;
+
+        let side = |tip: &ChainBlock| {
+            json!({
+                "tip": tip.id,
+                "chain_length": tip.chain_length,
+                "fork_length": ancestor
+                    .as_ref()
+                    .map(|ancestor| tip.chain_length - ancestor.chain_length),
+            })
+        };
+        // a node behind the other one is on the same chain
+        let diverged = ancestor
+            .as_ref()
+            .map(|ancestor| ancestor.id != tip_a.id && ancestor.id != tip_b.id);
+        let common_ancestor = ancestor.as_ref().map(|ancestor| {
+            json!({
+                "id": ancestor.id,
+                "chain_length": ancestor.chain_length,
+            })
+        });
+        let report = json!({
+            "a": side(&tip_a),
+            "b": side(&tip_b),
+            "diverged": diverged,
+            "common_ancestor": common_ancestor,
+        });
+        println!("{}", self.output_format.format_json(report)?);
+        Ok(())
+    }
+}
+
+/// walk back from both tips, the longest chain first, until they reach the
+/// same block, `None` if no block is shared within `max_depth` steps
+fn common_ancestor<A, B>(
+    tip_a: &ChainBlock,
+    tip_b: &ChainBlock,
+    max_depth: u32,
+    mut get_a: A,
+    mut get_b: B,
+) -> Result<Option<ChainBlock>, Error>
+where
+    A: FnMut(&str) -> Result<ChainBlock, Error>,
+    B: FnMut(&str) -> Result<ChainBlock, Error>,
+{
+    let mut block_a = tip_a.clone();
+    let mut block_b = tip_b.clone();
+    let mut depth = 0;
+    loop {
+        if block_a.id == block_b.id {
+            return Ok(Some(block_a));
+        }
+        // with different genesis blocks, the nodes share no block
+        if depth >= max_depth || (block_a.chain_length == 0 && block_b.chain_length == 0) {
+            return Ok(None);
+        }
+        if block_a.chain_length >= block_b.chain_length {
+            block_a = get_a(&block_a.parent_id)?;
+        }
+        if block_b.chain_length > block_a.chain_length {
+            block_b = get_b(&block_b.parent_id)?;
+        }
+        depth += 1;
+    }
+}
+
+fn get_tip(addr: &HostAddr, debug: &DebugFlag) -> Result<String, Error> {
+    let (url, connection) = addr.clone().with_segments(&["v0", "tip"])?.into_parts();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &connection, debug).send()?;
+    response.ok_response()?;
+    let tip = response.body().text().as_ref().trim().to_owned();
+    Ok(tip)
+}
+
+fn get_header(addr: &HostAddr, block_id: &str, debug: &DebugFlag) -> Result<ChainBlock, Error> {
+    let (url, connection) = addr
+        .clone()
+        .with_segments(&["v1", "block", block_id, "header"])?
+        .into_parts();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &connection, debug).send()?;
+    response.ok_response()?;
+    let header =
+        Header::deserialize(response.body().binary()).map_err(|source| Error::HeaderMalformed {
+            source,
+            filler: CustomErrorFiller,
+        })?;
+    Ok(ChainBlock {
+        id: header.hash().to_string(),
+        parent_id: header.block_parent_hash().to_string(),
+        chain_length: u32::from(header.chain_length()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// the blocks of a chain, `fork` naming the blocks after the
+    /// `chain_length` of `from`
+    fn extend(chain: &mut HashMap<String, ChainBlock>, from: &str, fork: &str, len: u32) -> String {
+        let mut parent = chain[from].clone();
+        for _ in 0..len {
+            let block = ChainBlock {
+                id: format!("{}-{}", fork, parent.chain_length + 1),
+                parent_id: parent.id.clone(),
+                chain_length: parent.chain_length + 1,
+            };
+            chain.insert(block.id.clone(), block.clone());
+            parent = block;
+        }
+        parent.id
+    }
+
+    fn genesis(id: &str) -> HashMap<String, ChainBlock> {
+        let block = ChainBlock {
+            id: id.to_owned(),
+            parent_id: "none".to_owned(),
+            chain_length: 0,
+        };
+        vec![(id.to_owned(), block)].into_iter().collect()
+    }
+
+    /// the common ancestor and the number of blocks fetched from each node
+    fn walk(
+        chain_a: &HashMap<String, ChainBlock>,
+        tip_a: &str,
+        chain_b: &HashMap<String, ChainBlock>,
+        tip_b: &str,
+        max_depth: u32,
+    ) -> (Option<String>, usize, usize) {
+        let (mut fetched_a, mut fetched_b) = (0, 0);
+        let ancestor = common_ancestor(
+            &chain_a[tip_a],
+            &chain_b[tip_b],
+            max_depth,
+            |id| {
+                fetched_a += 1;
+                Ok(chain_a[id].clone())
+            },
+            |id| {
+                fetched_b += 1;
+                Ok(chain_b[id].clone())
+            },
+        )
+        .unwrap();
+        (ancestor.map(|block| block.id), fetched_a, fetched_b)
+    }
+
+    #[test]
+    fn node_behind_is_on_the_same_chain() {
+        let mut chain = genesis("genesis");
+        let behind = extend(&mut chain, "genesis", "main", 5);
+        let ahead = extend(&mut chain, &behind, "main", 3);
+
+        let (ancestor, fetched_a, fetched_b) = walk(&chain, &ahead, &chain, &behind, 1000);
+        assert_eq!(ancestor, Some(behind));
+        assert_eq!((fetched_a, fetched_b), (3, 0));
+    }
+
+    #[test]
+    fn forks_meet_at_their_common_ancestor() {
+        let mut chain = genesis("genesis");
+        let fork_point = extend(&mut chain, "genesis", "main", 4);
+        let tip_a = extend(&mut chain, &fork_point, "a", 3);
+        let tip_b = extend(&mut chain, &fork_point, "b", 2);
+
+        let (ancestor, fetched_a, fetched_b) = walk(&chain, &tip_a, &chain, &tip_b, 1000);
+        assert_eq!(ancestor, Some(fork_point));
+        assert_eq!((fetched_a, fetched_b), (3, 2));
+    }
+
+    #[test]
+    fn chains_of_different_genesis_blocks_share_no_block() {
+        let mut chain_a = genesis("genesis-a");
+        let tip_a = extend(&mut chain_a, "genesis-a", "a", 2);
+        let mut chain_b = genesis("genesis-b");
+        let tip_b = extend(&mut chain_b, "genesis-b", "b", 3);
+
+        let (ancestor, _, _) = walk(&chain_a, &tip_a, &chain_b, &tip_b, 1000);
+        assert_eq!(ancestor, None);
+    }
+
+    #[test]
+    fn walk_gives_up_at_the_maximum_depth() {
+        let mut chain = genesis("genesis");
+        let fork_point = extend(&mut chain, "genesis", "main", 2);
+        let tip_a = extend(&mut chain, &fork_point, "a", 10);
+        let tip_b = extend(&mut chain, &fork_point, "b", 10);
+
+        let (ancestor, fetched_a, fetched_b) = walk(&chain, &tip_a, &chain, &tip_b, 4);
+        assert_eq!(ancestor, None);
+        assert_eq!((fetched_a, fetched_b), (4, 4));
+        let (ancestor, _, _) = walk(&chain, &tip_a, &chain, &tip_b, 10);
+        assert_eq!(ancestor, Some(fork_point));
+    }
+}
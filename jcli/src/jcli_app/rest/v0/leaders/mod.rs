@@ -1,5 +1,6 @@
 use crate::jcli_app::rest::Error;
 use crate::jcli_app::utils::{io, DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use reqwest::{header, RequestBuilder};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -26,6 +27,9 @@ pub enum Leaders {
         /// If not provided, YAML will be read from stdin.
         #[structopt(short, long)]
         file: Option<PathBuf>,
+        /// Bearer token configured as `rest.admin_token` on the node
+        #[structopt(long)]
+        token: Option<String>,
     },
     /// Delete leader
     Delete {
@@ -35,6 +39,9 @@ pub enum Leaders {
         debug: DebugFlag,
         /// ID of deleted leader
         id: u32,
+        /// Bearer token configured as `rest.admin_token` on the node
+        #[structopt(long)]
+        token: Option<String>,
     },
 
     /// Leadership log operations
@@ -63,8 +70,18 @@ impl Leaders {
                 debug,
                 output_format,
             } => get(addr, debug, output_format),
-            Leaders::Post { addr, debug, file } => post(addr, debug, file),
-            Leaders::Delete { id, addr, debug } => delete(addr, debug, id),
+            Leaders::Post {
+                addr,
+                debug,
+                file,
+                token,
+            } => post(addr, debug, file, token),
+            Leaders::Delete {
+                id,
+                addr,
+                debug,
+                token,
+            } => delete(addr, debug, id, token),
             Leaders::Logs(GetLogs::Get {
                 addr,
                 debug,
@@ -75,9 +92,9 @@ impl Leaders {
 }
 
 fn get(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
-    let url = addr.with_segments(&["v0", "leaders"])?.into_url();
+    let (url, connection) = addr.with_segments(&["v0", "leaders"])?.into_parts();
     let builder = reqwest::Client::new().get(url);
-    let response = RestApiSender::new(builder, &debug).send()?;
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     let leaders = response.body().json_value()?;
     let formatted = output_format.format_json(leaders)?;
@@ -85,11 +102,23 @@ fn get(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<
     Ok(())
 }
 
-fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), Error> {
-    let url = addr.with_segments(&["v0", "leaders"])?.into_url();
-    let builder = reqwest::Client::new().post(url);
+fn with_token(builder: RequestBuilder, token: Option<String>) -> RequestBuilder {
+    match token {
+        Some(token) => builder.header(header::AUTHORIZATION, format!("Bearer {}", token)),
+        None => builder,
+    }
+}
+
+fn post(
+    addr: HostAddr,
+    debug: DebugFlag,
+    file: Option<PathBuf>,
+    token: Option<String>,
+) -> Result<(), Error> {
+    let (url, connection) = addr.with_segments(&["v0", "leaders"])?.into_parts();
+    let builder = with_token(reqwest::Client::new().post(url), token);
     let input: serde_json::Value = io::read_yaml(&file)?;
-    let response = RestApiSender::new(builder, &debug)
+    let response = RestApiSender::new(builder, &connection, &debug)
         .with_json_body(&input)?
         .send()?;
     response.ok_response()?;
@@ -97,21 +126,21 @@ fn post(addr: HostAddr, debug: DebugFlag, file: Option<PathBuf>) -> Result<(), E
     Ok(())
 }
 
-fn delete(addr: HostAddr, debug: DebugFlag, id: u32) -> Result<(), Error> {
-    let url = addr
+fn delete(addr: HostAddr, debug: DebugFlag, id: u32, token: Option<String>) -> Result<(), Error> {
+    let (url, connection) = addr
         .with_segments(&["v0", "leaders", &id.to_string()])?
-        .into_url();
-    let builder = reqwest::Client::new().delete(url);
-    let response = RestApiSender::new(builder, &debug).send()?;
+        .into_parts();
+    let builder = with_token(reqwest::Client::new().delete(url), token);
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     println!("Success");
     Ok(())
 }
 
 fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
-    let url = addr.with_segments(&["v0", "leaders", "logs"])?.into_url();
+    let (url, connection) = addr.with_segments(&["v0", "leaders", "logs"])?.into_parts();
     let builder = reqwest::Client::new().get(url);
-    let response = RestApiSender::new(builder, &debug).send()?;
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     let logs = response.body().json_value()?;
     let formatted = output_format.format_json(logs)?;
@@ -52,9 +52,11 @@ impl Message {
 }
 
 fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
-    let url = addr.with_segments(&["v0", "fragment", "logs"])?.into_url();
+    let (url, connection) = addr
+        .with_segments(&["v0", "fragment", "logs"])?
+        .into_parts();
     let builder = reqwest::Client::new().get(url);
-    let response = RestApiSender::new(builder, &debug).send()?;
+    let response = RestApiSender::new(builder, &connection, &debug).send()?;
     response.ok_response()?;
     let status = response.body().json_value()?;
     let formatted = output_format.format_json(status)?;
@@ -65,7 +67,7 @@ fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Re
 fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Result<(), Error> {
     let msg_hex = io::read_line(&file)?;
     let msg_bin = hex::decode(&msg_hex)?;
-    let url = addr.with_segments(&["v0", "message"])?.into_url();
+    let (url, connection) = addr.with_segments(&["v0", "message"])?.into_parts();
     let builder = reqwest::Client::new().post(url);
     let fragment = Fragment::deserialize(msg_bin.as_slice().into_buf()).map_err(|e| {
         Error::InputFragmentMalformed {
@@ -73,7 +75,7 @@ fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Resu
             filler: CustomErrorFiller,
         }
     })?;
-    let response = RestApiSender::new(builder, &debug)
+    let response = RestApiSender::new(builder, &connection, &debug)
         .with_binary_body(msg_bin)
         .send()?;
     response.ok_response()?;
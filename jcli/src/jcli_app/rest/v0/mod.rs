@@ -1,14 +1,19 @@
 mod account;
 mod block;
+mod compare;
 mod leaders;
 mod message;
 mod network;
 mod node;
+mod reload;
+mod rewards;
 mod settings;
 mod shutdown;
 mod stake;
+mod stake_pool;
 mod stake_pools;
 mod tip;
+mod update_proposals;
 mod utxo;
 
 use crate::jcli_app::rest::Error;
@@ -21,6 +26,8 @@ pub enum V0 {
     Account(account::Account),
     /// Block operations
     Block(block::Block),
+    /// Compare the chains of two nodes
+    Compare(compare::Compare),
     /// Node leaders operations
     Leaders(leaders::Leaders),
     /// Message sending
@@ -29,16 +36,24 @@ pub enum V0 {
     Network(network::Network),
     /// Node information
     Node(node::Node),
+    /// Reload the node configuration
+    Reload(reload::Reload),
+    /// Rewards history
+    Rewards(rewards::Rewards),
     /// Node settings
     Settings(settings::Settings),
     /// Stake information
     Stake(stake::Stake),
+    /// Stake pool information
+    StakePool(stake_pool::StakePool),
     /// Stake pools operations
     StakePools(stake_pools::StakePools),
     /// Shutdown node
     Shutdown(shutdown::Shutdown),
     /// Blockchain tip information
     Tip(tip::Tip),
+    /// Update proposals of the BFT leaders
+    UpdateProposals(update_proposals::UpdateProposals),
     /// UTXO information
     Utxo(utxo::Utxo),
 }
@@ -48,15 +63,20 @@ impl V0 {
         match self {
             V0::Account(account) => account.exec(),
             V0::Block(block) => block.exec(),
+            V0::Compare(compare) => compare.exec(),
             V0::Leaders(leaders) => leaders.exec(),
             V0::Message(message) => message.exec(),
             V0::Network(network) => network.exec(),
             V0::Node(node) => node.exec(),
+            V0::Reload(reload) => reload.exec(),
+            V0::Rewards(rewards) => rewards.exec(),
             V0::Settings(settings) => settings.exec(),
             V0::Stake(stake) => stake.exec(),
+            V0::StakePool(stake_pool) => stake_pool.exec(),
             V0::StakePools(stake_pools) => stake_pools.exec(),
             V0::Shutdown(shutdown) => shutdown.exec(),
             V0::Tip(tip) => tip.exec(),
+            V0::UpdateProposals(update_proposals) => update_proposals.exec(),
             V0::Utxo(utxo) => utxo.exec(),
         }
     }
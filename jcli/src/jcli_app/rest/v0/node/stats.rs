@@ -1,5 +1,10 @@
 use crate::jcli_app::rest::Error;
 use crate::jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use serde_json::Value;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -13,6 +18,11 @@ pub enum Stats {
         debug: DebugFlag,
         #[structopt(flatten)]
         output_format: OutputFormat,
+        /// get the information again every given number of seconds, until
+        /// interrupted, and show how it changed as a dashboard. The output
+        /// format is not used then
+        #[structopt(long, value_name = "interval")]
+        watch: Option<u64>,
     },
 }
 
@@ -22,14 +32,227 @@ impl Stats {
             addr,
             debug,
             output_format,
+            watch,
         } = self;
-        let url = addr.with_segments(&["v0", "node", "stats"])?.into_url();
-        let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
-        response.ok_response()?;
-        let status = response.body().json_value()?;
+        if let Some(interval) = watch {
+            return watch_stats(addr, &debug, Duration::from_secs(interval.max(1)));
+        }
+        let status = get_stats(addr, &debug)?;
         let formatted = output_format.format_json(status)?;
         println!("{}", formatted);
         Ok(())
     }
 }
+
+fn get_stats(addr: HostAddr, debug: &DebugFlag) -> Result<Value, Error> {
+    let (url, connection) = addr.with_segments(&["v0", "node", "stats"])?.into_parts();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &connection, debug).send()?;
+    response.ok_response()?;
+    Ok(response.body().json_value()?)
+}
+
+/// the number of the fragments of the pool, counted by the node for the fee
+/// estimate
+fn get_pending(addr: HostAddr, debug: &DebugFlag) -> Result<usize, Error> {
+    let (url, connection) = addr
+        .with_segments(&["v1", "fees", "estimate"])?
+        .into_parts();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &connection, debug).send()?;
+    response.ok_response()?;
+    Ok(pending_of(&response.body().json_value()?))
+}
+
+fn pending_of(estimate: &Value) -> usize {
+    estimate["pendingFragments"].as_u64().unwrap_or(0) as usize
+}
+
+fn get_sample(addr: &HostAddr, debug: &DebugFlag) -> Result<Sample, Error> {
+    let stats = get_stats(addr.clone(), debug)?;
+    let pending = get_pending(addr.clone(), debug)?;
+    Ok(Sample::new(&stats, pending))
+}
+
+/// draw the dashboard after every interval, the node being asked again after
+/// the next interval when it does not answer
+fn watch_stats(addr: HostAddr, debug: &DebugFlag, interval: Duration) -> Result<(), Error> {
+    let mut previous = None;
+    loop {
+        let sample = get_sample(&addr, debug);
+        // clear the terminal and go back to its top left corner
+        print!("\x1b[2J\x1b[H");
+        match sample {
+            Ok(sample) => {
+                print!("{}", sample.render(previous.as_ref()));
+                previous = Some(sample);
+            }
+            Err(e) => {
+                if let Some(previous) = &previous {
+                    print!("{}", previous.render(None));
+                }
+                println!(
+                    "\nthe node did not answer, asking again in {}s: {}",
+                    interval.as_secs(),
+                    e
+                );
+            }
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// the information shown on the dashboard at a time
+struct Sample {
+    at: Instant,
+    state: String,
+    uptime: u64,
+    height: Option<u64>,
+    date: Option<String>,
+    hash: Option<String>,
+    tx_recv: u64,
+    block_recv: u64,
+    peers_established: u64,
+    peers_connecting: u64,
+    pending: usize,
+}
+
+impl Sample {
+    fn new(stats: &Value, pending: usize) -> Self {
+        let number = |key: &str| stats[key].as_u64().unwrap_or(0);
+        let text = |key: &str| stats[key].as_str().map(str::to_owned);
+        // a string in `/api/v0`, a number in `/api/v1`
+        let height = match &stats["lastBlockHeight"] {
+            Value::String(height) => height.parse().ok(),
+            height => height.as_u64(),
+        };
+        Sample {
+            at: Instant::now(),
+            state: text("state").unwrap_or_default(),
+            uptime: number("uptime"),
+            height,
+            date: text("lastBlockDate"),
+            hash: text("lastBlockHash"),
+            tx_recv: number("txRecvCnt"),
+            block_recv: number("blockRecvCnt"),
+            peers_established: number("peerConnectionsEstablished"),
+            peers_connecting: number("peerConnectionsConnecting"),
+            pending,
+        }
+    }
+
+    fn render(&self, previous: Option<&Sample>) -> String {
+        let delta = |current: u64, of: fn(&Sample) -> u64| match previous {
+            Some(previous) => format!(" ({:+})", current as i64 - of(previous) as i64),
+            None => String::new(),
+        };
+        let blocks_per_sec = previous.and_then(|previous| {
+            let blocks = self.height? as f64 - previous.height? as f64;
+            let millis = self.at.duration_since(previous.at).as_millis() as f64;
+            Some(blocks * 1000.0 / millis)
+        });
+
+        let mut lines = Vec::new();
+        lines.push(format!("state:      {}", self.state));
+        lines.push(format!("uptime:     {}s", self.uptime));
+        match self.height {
+            Some(height) => lines.push(format!(
+                "height:     {}{}",
+                height,
+                delta(height, |sample| sample.height.unwrap_or(0))
+            )),
+            None => lines.push("height:     -".to_owned()),
+        }
+        if let Some(blocks_per_sec) = blocks_per_sec {
+            lines.push(format!("blocks/sec: {:.2}", blocks_per_sec));
+        }
+        lines.push(format!(
+            "last block: {} {}",
+            self.date.as_ref().map_or("-", String::as_str),
+            self.hash.as_ref().map_or("", String::as_str)
+        ));
+        lines.push(format!(
+            "peers:      {}{} established, {} connecting",
+            self.peers_established,
+            delta(self.peers_established, |sample| sample.peers_established),
+            self.peers_connecting
+        ));
+        lines.push(format!(
+            "received:   {}{} blocks, {}{} fragments",
+            self.block_recv,
+            delta(self.block_recv, |sample| sample.block_recv),
+            self.tx_recv,
+            delta(self.tx_recv, |sample| sample.tx_recv)
+        ));
+        lines.push(format!(
+            "pool:       {}{} pending fragments",
+            self.pending,
+            delta(self.pending as u64, |sample| sample.pending as u64)
+        ));
+        let mut dashboard = lines.join("\n");
+        dashboard.push('\n');
+        dashboard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stats(height: Value, tx_recv: u64) -> Value {
+        json!({
+            "state": "Running",
+            "uptime": 20042,
+            "lastBlockHeight": height,
+            "lastBlockDate": "20.34",
+            "lastBlockHash": "6f9a0b4e",
+            "txRecvCnt": tx_recv,
+            "blockRecvCnt": 1107,
+            "peerConnectionsEstablished": 12,
+            "peerConnectionsConnecting": 2,
+        })
+    }
+
+    #[test]
+    fn height_is_read_from_both_api_versions() {
+        assert_eq!(
+            Sample::new(&stats(json!("202906"), 0), 0).height,
+            Some(202906)
+        );
+        assert_eq!(
+            Sample::new(&stats(json!(202906), 0), 0).height,
+            Some(202906)
+        );
+        assert_eq!(Sample::new(&json!({}), 0).height, None);
+    }
+
+    #[test]
+    fn pending_fragments_are_read_from_the_fee_estimate() {
+        let estimate = json!({ "fee": 4, "pendingFragments": 310, "blocksToInclusion": 2 });
+        assert_eq!(pending_of(&estimate), 310);
+    }
+
+    #[test]
+    fn dashboard_shows_the_changes_since_the_previous_sample() {
+        let previous = Sample::new(&stats(json!(202901), 5440), 12);
+        let mut sample = Sample::new(&stats(json!(202906), 5452), 9);
+        sample.at = previous.at + Duration::from_secs(10);
+
+        let dashboard = sample.render(Some(&previous));
+
+        assert!(dashboard.contains("height:     202906 (+5)\n"));
+        assert!(dashboard.contains("blocks/sec: 0.50\n"));
+        assert!(dashboard.contains("received:   1107 (+0) blocks, 5452 (+12) fragments\n"));
+        assert!(dashboard.contains("pool:       9 (-3) pending fragments\n"));
+    }
+
+    #[test]
+    fn first_dashboard_has_no_changes() {
+        let dashboard = Sample::new(&stats(json!(202906), 5452), 9).render(None);
+
+        assert!(dashboard.contains("height:     202906\n"));
+        assert!(!dashboard.contains("blocks/sec"));
+        assert!(!dashboard.contains('('));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use reqwest::header;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Reload {
+    /// Reload the configuration file of the node, and its private node ID
+    Post {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// Bearer token configured as `rest.admin_token` on the node
+        #[structopt(long)]
+        token: Option<String>,
+    },
+}
+
+impl Reload {
+    pub fn exec(self) -> Result<(), Error> {
+        let Reload::Post {
+            addr,
+            debug,
+            output_format,
+            token,
+        } = self;
+        let (url, connection) = addr.with_segments(&["v0", "reload"])?.into_parts();
+        let mut builder = reqwest::Client::new().post(url);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
+        response.ok_response()?;
+        let report = response.body().json_value()?;
+        let formatted = output_format.format_json(report)?;
+        println!("{}", formatted);
+        Ok(())
+    }
+}
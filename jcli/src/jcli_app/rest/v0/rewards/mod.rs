@@ -0,0 +1,67 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{AccountId, DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Rewards {
+    /// Get the rewards distributed at the start of an epoch
+    Epoch {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// Epoch the rewards were distributed at the start of
+        epoch: u32,
+    },
+    /// Get the rewards received by an account at each epoch
+    Account {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// An Account ID either in the form of an address of kind account, or an account public key
+        #[structopt(parse(try_from_str = "AccountId::try_from_str"))]
+        account_id: AccountId,
+    },
+}
+
+impl Rewards {
+    pub fn exec(self) -> Result<(), Error> {
+        let (addr, debug, output_format, segment, id) = match self {
+            Rewards::Epoch {
+                addr,
+                debug,
+                output_format,
+                epoch,
+            } => (addr, debug, output_format, "epoch", epoch.to_string()),
+            Rewards::Account {
+                addr,
+                debug,
+                output_format,
+                account_id,
+            } => (
+                addr,
+                debug,
+                output_format,
+                "account",
+                account_id.to_url_arg(),
+            ),
+        };
+        // the rewards are only served by the `v1` API
+        let (url, connection) = addr
+            .with_segments(&["v1", "rewards", segment, &id])?
+            .into_parts();
+        let builder = reqwest::Client::new().get(url);
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
+        response.ok_response()?;
+        let rewards = response.body().json_value()?;
+        let formatted = output_format.format_json(rewards)?;
+        println!("{}", formatted);
+        Ok(())
+    }
+}
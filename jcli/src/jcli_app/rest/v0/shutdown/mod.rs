@@ -1,5 +1,6 @@
 use crate::jcli_app::rest::Error;
 use crate::jcli_app::utils::{DebugFlag, HostAddr, RestApiSender};
+use reqwest::header;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -11,15 +12,21 @@ pub enum Shutdown {
         addr: HostAddr,
         #[structopt(flatten)]
         debug: DebugFlag,
+        /// Bearer token configured as `rest.admin_token` on the node
+        #[structopt(long)]
+        token: Option<String>,
     },
 }
 
 impl Shutdown {
     pub fn exec(self) -> Result<(), Error> {
-        let Shutdown::Get { addr, debug } = self;
-        let url = addr.with_segments(&["v0", "shutdown"])?.into_url();
-        let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
+        let Shutdown::Get { addr, debug, token } = self;
+        let (url, connection) = addr.with_segments(&["v0", "shutdown"])?.into_parts();
+        let mut builder = reqwest::Client::new().get(url);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
         response.ok_response()?;
         println!("Success");
         Ok(())
@@ -23,9 +23,9 @@ impl Stake {
             debug,
             output_format,
         } = self;
-        let url = addr.with_segments(&["v0", "stake"])?.into_url();
+        let (url, connection) = addr.with_segments(&["v0", "stake"])?.into_parts();
         let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
         response.ok_response()?;
         let status = response.body().json_value()?;
         let formatted = output_format.format_json(status)?;
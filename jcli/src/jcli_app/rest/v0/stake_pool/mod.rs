@@ -0,0 +1,41 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum StakePool {
+    /// Get the registration of a stake pool
+    Get {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// hex-encoded id of the stake pool
+        pool_id: String,
+    },
+}
+
+impl StakePool {
+    pub fn exec(self) -> Result<(), Error> {
+        let StakePool::Get {
+            addr,
+            debug,
+            output_format,
+            pool_id,
+        } = self;
+        // the stake pool is only served by the `v1` API
+        let (url, connection) = addr
+            .with_segments(&["v1", "stake_pool", &pool_id])?
+            .into_parts();
+        let builder = reqwest::Client::new().get(url);
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
+        response.ok_response()?;
+        let status = response.body().json_value()?;
+        let formatted = output_format.format_json(status)?;
+        println!("{}", formatted);
+        Ok(())
+    }
+}
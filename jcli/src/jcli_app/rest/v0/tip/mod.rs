@@ -19,9 +19,9 @@ impl Tip {
         let (addr, debug) = match self {
             Tip::Get { addr, debug } => (addr, debug),
         };
-        let url = addr.with_segments(&["v0", "tip"])?.into_url();
+        let (url, connection) = addr.with_segments(&["v0", "tip"])?.into_parts();
         let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
         response.ok_response()?;
         let tip = response.body().text();
         println!("{}", tip.as_ref());
@@ -0,0 +1,39 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{DebugFlag, HostAddr, OutputFormat, RestApiSender};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum UpdateProposals {
+    /// Get the update proposals open to the votes of the BFT leaders, with
+    /// their votes and the last tally
+    Get {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+    },
+}
+
+impl UpdateProposals {
+    pub fn exec(self) -> Result<(), Error> {
+        let UpdateProposals::Get {
+            addr,
+            debug,
+            output_format,
+        } = self;
+        // the update proposals are only served by the `v1` API
+        let (url, connection) = addr
+            .with_segments(&["v1", "update_proposals"])?
+            .into_parts();
+        let builder = reqwest::Client::new().get(url);
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
+        response.ok_response()?;
+        let status = response.body().json_value()?;
+        let formatted = output_format.format_json(status)?;
+        println!("{}", formatted);
+        Ok(())
+    }
+}
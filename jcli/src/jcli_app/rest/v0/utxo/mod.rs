@@ -38,16 +38,16 @@ impl Utxo {
             addr,
             debug,
         } = self.subcommand;
-        let url = addr
+        let (url, connection) = addr
             .with_segments(&[
                 "v0",
                 "utxo",
                 &self.fragment_id,
                 &self.output_index.to_string(),
             ])?
-            .into_url();
+            .into_parts();
         let builder = reqwest::Client::new().get(url);
-        let response = RestApiSender::new(builder, &debug).send()?;
+        let response = RestApiSender::new(builder, &connection, &debug).send()?;
         response.ok_response()?;
         let status = response.body().json_value()?;
         let formatted = output_format.format_json(status)?;
@@ -1,67 +1,256 @@
-use crate::jcli_app::transaction::{common, Error};
+use crate::jcli_app::transaction::{common, mk_witness::MULTISIG_OWNER_WITNESS_HRP, Error};
 use crate::jcli_app::utils::io;
 use bech32::{Bech32, FromBase32 as _};
 use chain_core::mempack::{ReadBuf, Readable as _};
-use chain_impl_mockchain::transaction::Witness;
-use std::path::PathBuf;
+use chain_crypto::{Ed25519, PublicKey, Signature};
+use chain_impl_mockchain::{
+    multisig::{TreeIndex, WitnessBuilder},
+    transaction::{Witness, WitnessMultisigData},
+};
+use jormungandr_lib::interfaces::MultisigDeclaration;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
+const PUBLIC_KEY_SIZE: usize = 32;
+
 #[derive(StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 pub struct AddWitness {
     #[structopt(flatten)]
     pub common: common::CommonTransaction,
 
-    pub witness: PathBuf,
+    /// the declaration file of a multisig account, written by
+    /// `jcli address multisig`. The witness files are then the witnesses of
+    /// its owners, made with `jcli transaction make-witness --type multisig`,
+    /// and are put together in the witness of the account.
+    #[structopt(long = "multisig")]
+    pub multisig: Option<PathBuf>,
+
+    /// the file of the witness, or the files of the witnesses of the owners
+    /// of a multisig account
+    #[structopt(raw(required = "true"))]
+    pub witnesses: Vec<PathBuf>,
 }
 
 impl AddWitness {
     pub fn exec(self) -> Result<(), Error> {
         let mut transaction = self.common.load()?;
 
-        let witness = self.witness()?;
+        let witness = match &self.multisig {
+            None => match self.witnesses.as_slice() {
+                [witness] => read_witness(witness)?,
+                witnesses => {
+                    return Err(Error::WitnessFilesTooMany {
+                        count: witnesses.len(),
+                    })
+                }
+            },
+            Some(declaration) => {
+                let declaration = read_declaration(declaration)?;
+                let owner_witnesses = self
+                    .witnesses
+                    .iter()
+                    .map(|path| read_owner_witness(path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                multisig_witness(&declaration, owner_witnesses)?
+            }
+        };
 
         transaction.add_witness(witness)?;
 
         self.common.store(&transaction)?;
         Ok(())
     }
+}
 
-    fn witness(&self) -> Result<Witness, Error> {
-        const HRP: &'static str = "witness";
+/// the public key and the signature of an owner of a multisig account,
+/// with the file they were read from
+struct OwnerWitness {
+    key: PublicKey<Ed25519>,
+    signature: Signature<WitnessMultisigData, Ed25519>,
+    path: PathBuf,
+}
 
-        let bech32_str =
-            io::read_line(&Some(&self.witness)).map_err(|source| Error::WitnessFileReadFailed {
-                source,
-                path: self.witness.clone(),
-            })?;
+fn read_declaration(path: &Path) -> Result<MultisigDeclaration, Error> {
+    let declaration: MultisigDeclaration =
+        io::read_yaml(&Some(path)).map_err(|source| Error::MultisigDeclarationReadFailed {
+            source,
+            path: path.to_path_buf(),
+        })?;
+    declaration.check()?;
+    Ok(declaration)
+}
 
-        let bech32: Bech32 =
-            bech32_str
-                .trim()
-                .parse()
-                .map_err(|source| Error::WitnessFileBech32Malformed {
-                    source,
-                    path: self.witness.clone(),
-                })?;
-        if bech32.hrp() != HRP {
-            return Err(Error::WitnessFileBech32HrpInvalid {
-                expected: HRP,
-                actual: bech32.hrp().to_string(),
-                path: self.witness.clone(),
-            });
-        }
-        let bytes = Vec::from_base32(bech32.data()).map_err(|source| {
-            Error::WitnessFileBech32Malformed {
-                source,
-                path: self.witness.clone(),
+fn multisig_witness(
+    declaration: &MultisigDeclaration,
+    owner_witnesses: Vec<OwnerWitness>,
+) -> Result<Witness, Error> {
+    let mut indexed: Vec<(TreeIndex, OwnerWitness)> = Vec::new();
+    for owner_witness in owner_witnesses {
+        let index = declaration.owner_index(&owner_witness.key).ok_or_else(|| {
+            Error::MultisigOwnerUnknown {
+                path: owner_witness.path.clone(),
             }
         })?;
-        Witness::read(&mut ReadBuf::from(&bytes)).map_err(|source| {
-            Error::WitnessFileDeserializationFailed {
+        if indexed.iter().any(|(other, _)| other == &index) {
+            return Err(Error::MultisigOwnerWitnessDuplicated {
+                path: owner_witness.path,
+            });
+        }
+        indexed.push((index, owner_witness));
+    }
+    if indexed.len() < declaration.threshold as usize {
+        return Err(Error::MultisigOwnerWitnessesMissing {
+            actual: indexed.len(),
+            threshold: declaration.threshold,
+        });
+    }
+
+    // the signatures are expected in the order of the owners
+    indexed.sort_by_key(|(_, owner_witness)| {
+        declaration
+            .owners
+            .iter()
+            .position(|owner| owner.as_ref() == &owner_witness.key)
+    });
+    let mut builder = WitnessBuilder::new();
+    for (index, owner_witness) in indexed {
+        builder.append(index, owner_witness.key, owner_witness.signature);
+    }
+    Ok(Witness::Multisig(builder.finalize()))
+}
+
+fn read_owner_witness(path: &Path) -> Result<OwnerWitness, Error> {
+    let bytes = read_bech32(path, MULTISIG_OWNER_WITNESS_HRP)?;
+    if bytes.len() < PUBLIC_KEY_SIZE {
+        return Err(Error::MultisigOwnerWitnessMalformed {
+            path: path.to_path_buf(),
+        });
+    }
+    let (key, signature) = bytes.split_at(PUBLIC_KEY_SIZE);
+    match (
+        PublicKey::from_binary(key),
+        Signature::from_binary(signature),
+    ) {
+        (Ok(key), Ok(signature)) => Ok(OwnerWitness {
+            key,
+            signature,
+            path: path.to_path_buf(),
+        }),
+        _ => Err(Error::MultisigOwnerWitnessMalformed {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+fn read_witness(path: &Path) -> Result<Witness, Error> {
+    let bytes = read_bech32(path, "witness")?;
+    Witness::read(&mut ReadBuf::from(&bytes)).map_err(|source| {
+        Error::WitnessFileDeserializationFailed {
+            source,
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+fn read_bech32(path: &Path, hrp: &'static str) -> Result<Vec<u8>, Error> {
+    let bech32_str = io::read_line(&Some(path)).map_err(|source| Error::WitnessFileReadFailed {
+        source,
+        path: path.to_path_buf(),
+    })?;
+
+    let bech32: Bech32 =
+        bech32_str
+            .trim()
+            .parse()
+            .map_err(|source| Error::WitnessFileBech32Malformed {
                 source,
-                path: self.witness.clone(),
+                path: path.to_path_buf(),
+            })?;
+    if bech32.hrp() != hrp {
+        return Err(Error::WitnessFileBech32HrpInvalid {
+            expected: hrp,
+            actual: bech32.hrp().to_string(),
+            path: path.to_path_buf(),
+        });
+    }
+    Vec::from_base32(bech32.data()).map_err(|source| Error::WitnessFileBech32Malformed {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_crypto::SecretKey;
+    use chain_impl_mockchain::{account::SpendingCounter, header::HeaderId};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const HASH: &str = "c355a02d3b5337ad0e5f5940582675229f25bc03e7feebc3aa929738e1fec35e";
+
+    fn secret_keys(count: u8) -> Vec<SecretKey<Ed25519>> {
+        (0..count)
+            .map(|seed| SecretKey::generate(&mut StdRng::from_seed([seed; 32])))
+            .collect()
+    }
+
+    fn declaration(threshold: u8, keys: &[SecretKey<Ed25519>]) -> MultisigDeclaration {
+        let owners = keys.iter().map(|key| key.to_public().into()).collect();
+        MultisigDeclaration::new(threshold, owners).unwrap()
+    }
+
+    fn owner_witness(key: &SecretKey<Ed25519>) -> OwnerWitness {
+        let block0: HeaderId = HASH.parse().unwrap();
+        let data =
+            WitnessMultisigData::new(&block0, &HASH.parse().unwrap(), &SpendingCounter::from(0));
+        OwnerWitness {
+            key: key.to_public(),
+            signature: key.sign(&data),
+            path: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn owner_witnesses_are_put_in_the_order_of_the_owners() {
+        let keys = secret_keys(3);
+        let declaration = declaration(2, &keys);
+        let in_order = multisig_witness(
+            &declaration,
+            vec![owner_witness(&keys[0]), owner_witness(&keys[2])],
+        )
+        .unwrap();
+        let reversed = multisig_witness(
+            &declaration,
+            vec![owner_witness(&keys[2]), owner_witness(&keys[0])],
+        )
+        .unwrap();
+        assert_eq!(in_order, reversed);
+    }
+
+    #[test]
+    fn owner_witnesses_reach_the_threshold() {
+        let keys = secret_keys(4);
+        let declaration = declaration(2, &keys[..3]);
+        match multisig_witness(&declaration, vec![owner_witness(&keys[0])]) {
+            Err(Error::MultisigOwnerWitnessesMissing { actual, threshold }) => {
+                assert_eq!((actual, threshold), (1, 2))
             }
-        })
+            _ => panic!("a witness under the threshold was made"),
+        }
+        match multisig_witness(
+            &declaration,
+            vec![owner_witness(&keys[0]), owner_witness(&keys[3])],
+        ) {
+            Err(Error::MultisigOwnerUnknown { .. }) => (),
+            _ => panic!("the witness of a key which is not an owner was added"),
+        }
+        match multisig_witness(
+            &declaration,
+            vec![owner_witness(&keys[1]), owner_witness(&keys[1])],
+        ) {
+            Err(Error::MultisigOwnerWitnessDuplicated { .. }) => (),
+            _ => panic!("an owner signed twice"),
+        }
     }
 }
@@ -0,0 +1,157 @@
+use crate::jcli_app::{
+    transaction::{common, staging::Staging, Error},
+    utils::{Connection, DebugFlag, HostAddr, OutputFormat, RestApiSender},
+};
+use chain_impl_mockchain::fee::LinearFee;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct EstimateFee {
+    #[structopt(flatten)]
+    common: common::CommonTransaction,
+
+    #[structopt(flatten)]
+    fee: common::CommonFees,
+
+    /// node API address to take the fee settings from, in place of the
+    /// `--fee-*` options. The congestion of the node's fragment pool is
+    /// reported too, the number of blocks to the inclusion assuming the
+    /// blocks are full and the fragments of the pool go first. E.g.
+    /// `--from-node http://127.0.0.1:8443/api`
+    #[structopt(long)]
+    from_node: Option<Url>,
+
+    #[structopt(flatten)]
+    connection: Connection,
+
+    #[structopt(flatten)]
+    debug: DebugFlag,
+
+    #[structopt(flatten)]
+    output_format: OutputFormat,
+}
+
+/// the response of `/api/v1/fees/estimate`, only the parts used here
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeEstimate {
+    fees: NodeFees,
+    pending_fragments: u64,
+    blocks_to_inclusion: u64,
+}
+
+#[derive(Deserialize)]
+struct NodeFees {
+    constant: u64,
+    coefficient: u64,
+    certificate: u64,
+}
+
+impl EstimateFee {
+    pub fn exec(self) -> Result<(), Error> {
+        let staging = self.common.load()?;
+        let node_estimate = match self.from_node {
+            None => None,
+            Some(host) => Some(request_estimate(host, self.connection, &self.debug)?),
+        };
+        let info = estimate(&staging, &self.fee.linear_fee(), node_estimate)?;
+        println!("{}", self.output_format.format_json(info)?);
+        Ok(())
+    }
+}
+
+/// the fee of the staged transaction, with the fee settings and the
+/// congestion of the node if asked to it
+fn estimate(
+    staging: &Staging,
+    fee_algo: &LinearFee,
+    node_estimate: Option<NodeEstimate>,
+) -> Result<Value, Error> {
+    Ok(match node_estimate {
+        None => json!({ "fee": staging.checked_fees(fee_algo)?.0 }),
+        Some(estimate) => {
+            let fees = estimate.fees;
+            let fee_algo = LinearFee::new(fees.constant, fees.coefficient, fees.certificate);
+            json!({
+                "fee": staging.checked_fees(&fee_algo)?.0,
+                "pendingFragments": estimate.pending_fragments,
+                "blocksToInclusion": estimate.blocks_to_inclusion,
+            })
+        }
+    })
+}
+
+fn request_estimate(
+    host: Url,
+    connection: Connection,
+    debug: &DebugFlag,
+) -> Result<NodeEstimate, Error> {
+    let (url, connection) = HostAddr::new(host, connection)
+        .with_segments(&["v1", "fees", "estimate"])?
+        .into_parts();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &connection, debug).send()?;
+    response.ok_response()?;
+    Ok(response.body().json()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_impl_mockchain::{key::Hash, transaction::Input, value};
+    use std::str::FromStr;
+
+    fn staging(inputs: usize) -> Staging {
+        let hash =
+            Hash::from_str("c355a02d3b5337ad0e5f5940582675229f25bc03e7feebc3aa929738e1fec35e")
+                .unwrap();
+        let mut input_ptr = [0u8; chain_impl_mockchain::transaction::INPUT_PTR_SIZE];
+        input_ptr.clone_from_slice(hash.as_ref());
+        let mut staging = Staging::new();
+        for index in 0..inputs {
+            let input = Input::new(index as u8, value::Value(200), input_ptr);
+            staging.add_input(input.into()).unwrap();
+        }
+        staging
+    }
+
+    #[test]
+    fn fee_is_computed_with_the_given_settings() {
+        let fee_algo = LinearFee::new(10, 2, 0);
+
+        let info = estimate(&staging(2), &fee_algo, None).unwrap();
+
+        assert_eq!(info, json!({ "fee": 14 }));
+    }
+
+    #[test]
+    fn fee_is_computed_with_the_settings_of_the_node() {
+        let response = r#"{
+            "fee": 4,
+            "fees": { "constant": 2, "coefficient": 1, "certificate": 4 },
+            "pendingFragments": 310,
+            "maxFragmentsPerBlock": 250,
+            "blocksToInclusion": 2
+        }"#;
+        let node_estimate: NodeEstimate = serde_json::from_str(response).unwrap();
+
+        let info = estimate(&staging(3), &LinearFee::new(0, 0, 0), Some(node_estimate)).unwrap();
+
+        assert_eq!(
+            info,
+            json!({ "fee": 5, "pendingFragments": 310, "blocksToInclusion": 2 })
+        );
+    }
+
+    #[test]
+    fn transaction_with_too_many_inputs_is_refused() {
+        match estimate(&staging(256), &LinearFee::new(10, 2, 0), None) {
+            Err(Error::TxInputOutputCountInvalid { inputs, .. }) => assert_eq!(inputs, 256),
+            res => panic!("unexpected outcome {:?}", res.map(|_| ())),
+        }
+    }
+}
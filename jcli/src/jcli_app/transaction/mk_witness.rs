@@ -9,7 +9,7 @@ use chain_core::property::Serialize as _;
 use chain_impl_mockchain::{
     account::SpendingCounter,
     header::HeaderId,
-    transaction::{TransactionSignDataHash, Witness},
+    transaction::{TransactionSignDataHash, Witness, WitnessMultisigData},
 };
 use std::{io::Write, path::PathBuf};
 use structopt::StructOpt;
@@ -25,7 +25,8 @@ pub struct MkWitness {
     /// If omitted it will be printed to the standard output.
     pub output: Option<PathBuf>,
 
-    /// the type of witness to build: account, UTxO or Legacy UtxO
+    /// the type of witness to build: account, UTxO, Legacy UtxO or the
+    /// witness of an owner of a multisig account
     #[structopt(long = "type", parse(try_from_str))]
     pub witness_type: WitnessType,
 
@@ -33,8 +34,8 @@ pub struct MkWitness {
     #[structopt(long = "genesis-block-hash", parse(try_from_str))]
     pub genesis_block_hash: HeaderId,
 
-    /// value is mandatory is `--type=account' It is the counter for
-    /// every time the account is being utilized.
+    /// value is mandatory is `--type=account' or `--type=multisig'. It is
+    /// the counter for every time the account is being utilized.
     #[structopt(long = "account-spending-counter")]
     pub account_spending_counter: Option<u32>,
 
@@ -47,8 +48,13 @@ pub enum WitnessType {
     UTxO,
     OldUTxO,
     Account,
+    Multisig,
 }
 
+/// the prefix of the witness of an owner of a multisig account, which holds
+/// the public key of the owner followed by its signature
+pub const MULTISIG_OWNER_WITNESS_HRP: &str = "owner_witness";
+
 impl std::str::FromStr for WitnessType {
     type Err = &'static str;
 
@@ -57,7 +63,10 @@ impl std::str::FromStr for WitnessType {
             "utxo" => Ok(WitnessType::UTxO),
             "legacy-utxo" => Ok(WitnessType::OldUTxO),
             "account" => Ok(WitnessType::Account),
-            _ => Err("Invalid witness type, expected `utxo', `legacy-utxo' or `account'"),
+            "multisig" => Ok(WitnessType::Multisig),
+            _ => {
+                Err("Invalid witness type, expected `utxo', `legacy-utxo', `account' or `multisig'")
+            }
         }
     }
 }
@@ -87,17 +96,32 @@ impl MkWitness {
                     &secret_key,
                 )
             }
+            WitnessType::Multisig => return self.make_multisig_owner_witness(),
         };
 
         self.write_witness(&witness)
     }
 
+    fn make_multisig_owner_witness(&self) -> Result<(), Error> {
+        let account_spending_counter = self
+            .account_spending_counter
+            .ok_or(Error::MakeWitnessAccountCounterMissing)
+            .map(SpendingCounter::from)?;
+
+        let secret_key = read_ed25519_secret_key_from_file(&self.secret)?;
+        let data = WitnessMultisigData::new(
+            &self.genesis_block_hash,
+            &self.sign_data_hash,
+            &account_spending_counter,
+        );
+        let signature = secret_key.sign(&data);
+
+        let mut bytes = secret_key.to_public().as_ref().to_vec();
+        bytes.extend_from_slice(signature.as_ref());
+        self.write_bech32(MULTISIG_OWNER_WITNESS_HRP, &bytes)
+    }
+
     fn write_witness(&self, witness: &Witness) -> Result<(), Error> {
-        let mut writer =
-            io::open_file_write(&self.output).map_err(|source| Error::WitnessFileWriteFailed {
-                source,
-                path: self.output.clone().unwrap_or_default(),
-            })?;
         let bytes =
             witness
                 .serialize_as_vec()
@@ -106,8 +130,17 @@ impl MkWitness {
                     filler: CustomErrorFiller,
                 })?;
 
+        self.write_bech32("witness", &bytes)
+    }
+
+    fn write_bech32(&self, hrp: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut writer =
+            io::open_file_write(&self.output).map_err(|source| Error::WitnessFileWriteFailed {
+                source,
+                path: self.output.clone().unwrap_or_default(),
+            })?;
         let base32 = bytes.to_base32();
-        let bech32 = Bech32::new("witness".to_owned(), base32)?;
+        let bech32 = Bech32::new(hrp.to_owned(), base32)?;
         writeln!(writer, "{}", bech32).map_err(|source| Error::WitnessFileWriteFailed {
             source,
             path: self.output.clone().unwrap_or_default(),
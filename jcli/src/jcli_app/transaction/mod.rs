@@ -5,6 +5,7 @@ mod add_output;
 mod add_witness;
 mod auth;
 mod common;
+mod estimate_fee;
 mod finalize;
 mod info;
 mod mk_witness;
@@ -15,9 +16,10 @@ mod staging;
 use self::staging::StagingKind;
 use crate::jcli_app::certificate;
 use crate::jcli_app::utils::error::CustomErrorFiller;
-use crate::jcli_app::utils::{key_parser, output_format};
+use crate::jcli_app::utils::{host_addr, io::ReadYamlError, key_parser, output_format, rest_api};
 use chain_core::property::Serialize as _;
 use chain_impl_mockchain as chain;
+use jormungandr_lib::interfaces::MultisigDeclarationError;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -34,7 +36,8 @@ pub enum Transaction {
     AddAccount(add_account::AddAccount),
     /// add output to the transaction
     AddOutput(add_output::AddOutput),
-    /// add output to the finalized transaction
+    /// add a witness to the finalized transaction, or put together the
+    /// witnesses of the owners of a multisig account
     AddWitness(add_witness::AddWitness),
     /// set a certificate to the Transaction. If there is already
     /// an extra certificate in the transaction it will be replaced
@@ -53,6 +56,9 @@ pub enum Transaction {
     DataForWitness(common::CommonTransaction),
     /// display the info regarding a given transaction
     Info(info::Info),
+    /// compute the fee of the transaction with the given fee settings, or
+    /// with the ones of a node
+    EstimateFee(estimate_fee::EstimateFee),
     /// create witnesses
     MakeWitness(mk_witness::MkWitness),
     /// make auth
@@ -92,6 +98,19 @@ custom_error! { pub Error
         = @{{ let _ = source; format_args!("could not parse data in witness file '{}'", path.display()) }},
     WitnessFileSerializationFailed { source: std::io::Error, filler: CustomErrorFiller }
         = "could not serialize witness data",
+    WitnessFilesTooMany { count: usize }
+        = "{count} witness files given, only the witnesses of the owners of a multisig account are added together",
+    MultisigDeclarationReadFailed { source: ReadYamlError, path: PathBuf }
+        = @{{ let _ = source; format_args!("could not read multisig declaration file '{}'", path.display()) }},
+    MultisigDeclarationInvalid { source: MultisigDeclarationError } = "invalid multisig declaration",
+    MultisigOwnerWitnessMalformed { path: PathBuf }
+        = @{{ format_args!("could not parse multisig owner witness in '{}'", path.display()) }},
+    MultisigOwnerUnknown { path: PathBuf }
+        = @{{ format_args!("the witness in '{}' is not from an owner of the multisig account", path.display()) }},
+    MultisigOwnerWitnessDuplicated { path: PathBuf }
+        = @{{ format_args!("the owner of the witness in '{}' has already signed", path.display()) }},
+    MultisigOwnerWitnessesMissing { actual: usize, threshold: u8 }
+        = "{actual} witnesses of owners of the multisig account, {threshold} are needed",
     InfoFileWriteFailed { source: std::io::Error, path: PathBuf }
         = @{{ let _ = source; format_args!("could not write info file '{}'", path.display()) }},
     OutputFormatFailed { source: output_format::Error } = "formatting output failed",
@@ -122,6 +141,10 @@ custom_error! { pub Error
         = "serialization of message to bytes failed",
     InfoCalculationFailed { source: chain::value::ValueError } = "calculation of info failed",
     FeeCalculationFailed = "fee calculation failed",
+    TxInputOutputCountInvalid { inputs: usize, outputs: usize }
+        = "too many inputs or outputs in the transaction: {inputs} inputs and {outputs} outputs, the maximum is 255 of each",
+    FeeEstimateRequestFailed { source: rest_api::Error } = "could not get the fee settings from the node",
+    FeeEstimateHostInvalid { source: host_addr::Error } = "invalid node address",
     InfoExpectedSingleAccount = "expected a single account, multisig is not supported yet",
     MakeWitnessLegacyUtxoUnsupported = "making legacy UTxO witness unsupported",
     MakeWitnessAccountCounterMissing = "making account witness requires passing spending counter",
@@ -156,6 +179,7 @@ impl Transaction {
             Transaction::Id(common) => display_id(common),
             Transaction::DataForWitness(common) => display_data_for_witness(common),
             Transaction::Info(info) => info.exec(),
+            Transaction::EstimateFee(estimate_fee) => estimate_fee.exec(),
             Transaction::MakeWitness(mk_witness) => mk_witness.exec(),
             Transaction::Auth(auth) => auth.exec(),
             Transaction::ToMessage(common) => display_message(common),
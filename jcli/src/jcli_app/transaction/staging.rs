@@ -439,6 +439,19 @@ impl Staging {
         fee_algorithm.calculate(cert_slice, inputs_count, outputs_count)
     }
 
+    /// the fees of the transaction, refused if it has more inputs or outputs
+    /// than a transaction can hold instead of counting them modulo 256
+    pub fn checked_fees(&self, fee_algorithm: &impl FeeAlgorithm) -> Result<Value, Error> {
+        let max = u8::max_value() as usize;
+        if self.inputs.len() > max || self.outputs.len() > max {
+            return Err(Error::TxInputOutputCountInvalid {
+                inputs: self.inputs.len(),
+                outputs: self.outputs.len(),
+            });
+        }
+        Ok(self.fees(fee_algorithm))
+    }
+
     pub fn balance(&self, fee_algorithm: &impl FeeAlgorithm) -> Result<Balance, ValueError> {
         let fees = self.fees(fee_algorithm);
         let inputs = Value::sum(self.inputs().iter().map(|i| i.value.into()))?;
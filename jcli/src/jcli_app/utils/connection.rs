@@ -0,0 +1,379 @@
+//! the options of the connection to the REST API of the node
+//!
+//! Without any option, or with `--tls-ca` only, the requests are sent with
+//! `reqwest`. A unix socket, a pinned certificate or a server name different
+//! from the host of the URL need a connection made by hand: the request is
+//! then sent over it with the HTTP/1 client of `hyper`, which is given the
+//! same time as `reqwest` to connect and get the whole response.
+
+use crate::jcli_app::utils::CustomErrorFiller;
+use hyper::{client::conn, Body};
+use native_tls::{Certificate, TlsConnector};
+use reqwest::{Client, ClientBuilder, Request, Response};
+use std::{
+    fs, io,
+    net::ToSocketAddrs as _,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::{net::TcpStream, prelude::*, runtime::current_thread::Runtime, timer::Timeout};
+
+/// the time given to the node to answer a request, the default of `reqwest`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Connection {
+    /// path of the unix domain socket the node serves its REST API on,
+    /// as set in `rest.unix_socket`. The host of the node API address is then
+    /// only sent in the `Host` header
+    #[structopt(long, env = "JORMUNGANDR_RESTAPI_SOCKET", parse(from_os_str))]
+    unix_socket: Option<PathBuf>,
+    /// PEM file of a certificate authority to trust, in addition to the ones
+    /// of the system
+    #[structopt(long, parse(from_os_str))]
+    tls_ca: Option<PathBuf>,
+    /// PEM or DER file of the certificate of the node. The request is only
+    /// sent if the node presents this very certificate
+    #[structopt(long, parse(from_os_str))]
+    tls_cert: Option<PathBuf>,
+    /// the name sent in the SNI extension and checked against the
+    /// certificate of the node, the host of the node API address if not set
+    #[structopt(long)]
+    tls_server_name: Option<String>,
+}
+
+custom_error! { pub Error
+    CertificateFileInvalid { source: io::Error, path: PathBuf }
+        = @{ format_args!("could not read certificate file '{}'", path.display()) },
+    CertificateMalformed { path: PathBuf }
+        = @{ format_args!("certificate file '{}' is not a valid PEM or DER certificate", path.display()) },
+    ClientInitFailed { source: reqwest::Error } = "could not initialize the HTTP client",
+    RequestFailed { source: reqwest::Error, filler: CustomErrorFiller }
+        = "could not send the request",
+    UnixSocketWithTls = "TLS options are not supported over a unix socket",
+    TlsOverHttp = "TLS options need a node API address with the `https://` prefix",
+    HostMissing = "the node API address has no host",
+    HostUnresolved { source: io::Error } = "could not resolve the host of the node",
+    RuntimeInitFailed { source: io::Error, filler: CustomErrorFiller }
+        = "could not initialize the runtime",
+    ConnectFailed { source: io::Error, filler: CustomErrorFiller }
+        = "could not connect with node",
+    TlsInitFailed { source: native_tls::Error } = "could not initialize TLS",
+    TlsHandshakeFailed { source: native_tls::Error, filler: CustomErrorFiller }
+        = "TLS handshake with node failed",
+    CertificateMismatch = "node presented a certificate other than the pinned one",
+    ExchangeFailed { source: hyper::Error } = "communication with node failed",
+    HttpRequestInvalid { source: http::Error } = "request is not valid",
+    TimedOut = "connection with node timed out",
+}
+
+impl Connection {
+    /// send the request to the node
+    pub fn execute(&self, request: Request) -> Result<Response, Error> {
+        if self.unix_socket.is_none() && self.tls_cert.is_none() && self.tls_server_name.is_none() {
+            return self
+                .client()?
+                .execute(request)
+                .map_err(|source| Error::RequestFailed {
+                    source,
+                    filler: CustomErrorFiller,
+                });
+        }
+        let mut runtime = Runtime::new().map_err(|source| Error::RuntimeInitFailed {
+            source,
+            filler: CustomErrorFiller,
+        })?;
+        let http_request = into_http_request(&request)?;
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        match self.unix_socket {
+            Some(ref path) => {
+                if self.tls_ca.is_some()
+                    || self.tls_cert.is_some()
+                    || self.tls_server_name.is_some()
+                {
+                    return Err(Error::UnixSocketWithTls);
+                }
+                let stream = connect_unix(&mut runtime, path, deadline)?;
+                exchange(&mut runtime, stream, http_request, deadline)
+            }
+            None => {
+                let stream = self.connect_tls(&mut runtime, &request, deadline)?;
+                exchange(&mut runtime, stream, http_request, deadline)
+            }
+        }
+    }
+
+    fn client(&self) -> Result<Client, Error> {
+        let mut builder = ClientBuilder::new();
+        if let Some(ref path) = self.tls_ca {
+            let pem = read_file(path)?;
+            let certificate =
+                reqwest::Certificate::from_pem(&pem).map_err(|_| Error::CertificateMalformed {
+                    path: path.to_path_buf(),
+                })?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn connect_tls(
+        &self,
+        runtime: &mut Runtime,
+        request: &Request,
+        deadline: Instant,
+    ) -> Result<tokio_tls::TlsStream<TcpStream>, Error> {
+        let url = request.url();
+        if url.scheme() != "https" {
+            return Err(Error::TlsOverHttp);
+        }
+        let host = url.host_str().ok_or(Error::HostMissing)?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or(Error::HostMissing)?;
+        let server_name = self.tls_server_name.as_ref().map_or(host, String::as_str);
+
+        let mut builder = TlsConnector::builder();
+        if let Some(ref path) = self.tls_ca {
+            builder.add_root_certificate(read_certificate(path)?);
+        }
+        // the pinned certificate is trusted as its own authority, so the
+        // self-signed certificates of the nodes can be pinned
+        let pinned = match self.tls_cert {
+            Some(ref path) => {
+                let certificate = read_certificate(path)?;
+                builder.add_root_certificate(certificate.clone());
+                Some(certificate.to_der()?)
+            }
+            None => None,
+        };
+        let connector = tokio_tls::TlsConnector::from(builder.build()?);
+
+        let connect = TcpStream::connect(&address)
+            .map_err(|source| Error::ConnectFailed {
+                source,
+                filler: CustomErrorFiller,
+            })
+            .and_then(move |tcp| {
+                connector
+                    .connect(server_name, tcp)
+                    .map_err(|source| Error::TlsHandshakeFailed {
+                        source,
+                        filler: CustomErrorFiller,
+                    })
+            });
+        let stream = block_on(runtime, connect, deadline)?;
+        if let Some(pinned) = pinned {
+            let presented = stream
+                .get_ref()
+                .peer_certificate()?
+                .map(|certificate| certificate.to_der())
+                .transpose()?;
+            if presented.as_ref() != Some(&pinned) {
+                return Err(Error::CertificateMismatch);
+            }
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(
+    runtime: &mut Runtime,
+    path: &Path,
+    deadline: Instant,
+) -> Result<tokio::net::UnixStream, Error> {
+    let connect = tokio::net::UnixStream::connect(path).map_err(|source| Error::ConnectFailed {
+        source,
+        filler: CustomErrorFiller,
+    });
+    block_on(runtime, connect, deadline)
+}
+
+#[cfg(not(unix))]
+fn connect_unix(
+    _runtime: &mut Runtime,
+    _path: &Path,
+    _deadline: Instant,
+) -> Result<TcpStream, Error> {
+    Err(Error::ConnectFailed {
+        source: io::Error::new(
+            io::ErrorKind::Other,
+            "unix sockets are not supported on this platform",
+        ),
+        filler: CustomErrorFiller,
+    })
+}
+
+/// the request in origin form, the connection being already made to the node
+fn into_http_request(request: &Request) -> Result<http::Request<Body>, Error> {
+    let url = request.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    let host = url.host_str().ok_or(Error::HostMissing)?;
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    };
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| bytes.to_vec())
+        .unwrap_or_default();
+    let mut builder = http::Request::builder();
+    builder.method(request.method().clone()).uri(path);
+    for (name, value) in request.headers() {
+        builder.header(name, value.clone());
+    }
+    if !request.headers().contains_key(http::header::HOST) {
+        builder.header(http::header::HOST, host);
+    }
+    Ok(builder.body(Body::from(body))?)
+}
+
+fn exchange<S>(
+    runtime: &mut Runtime,
+    stream: S,
+    request: http::Request<Body>,
+    deadline: Instant,
+) -> Result<Response, Error>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut sender, connection) = block_on(runtime, conn::handshake(stream), deadline)?;
+    runtime.spawn(connection.map_err(|_| ()));
+    let response = block_on(runtime, sender.send_request(request), deadline)?;
+    let (parts, body) = response.into_parts();
+    let body = block_on(runtime, body.concat2(), deadline)?;
+    Ok(http::Response::from_parts(parts, body.to_vec()).into())
+}
+
+/// run the future to its end, failed with `TimedOut` once the deadline is
+/// over
+fn block_on<F>(runtime: &mut Runtime, future: F, deadline: Instant) -> Result<F::Item, Error>
+where
+    F: Future,
+    Error: From<F::Error>,
+{
+    runtime
+        .block_on(Timeout::new_at(future, deadline))
+        .map_err(|e| match e.into_inner() {
+            Some(e) => e.into(),
+            // the deadline is over, or the timer is gone with the runtime
+            None => Error::TimedOut,
+        })
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|source| Error::CertificateFileInvalid {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+fn read_certificate(path: &Path) -> Result<Certificate, Error> {
+    let data = read_file(path)?;
+    Certificate::from_pem(&data)
+        .or_else(|_| Certificate::from_der(&data))
+        .map_err(|_| Error::CertificateMalformed {
+            path: path.to_path_buf(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read as _, Write as _},
+        net::TcpListener,
+        thread,
+    };
+
+    fn connect(runtime: &mut Runtime, listener: &TcpListener) -> TcpStream {
+        let address = listener.local_addr().unwrap();
+        runtime.block_on(TcpStream::connect(&address)).unwrap()
+    }
+
+    fn get(path: &str) -> http::Request<Body> {
+        http::Request::get(path)
+            .header(http::header::HOST, "node")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn request_is_sent_in_origin_form() {
+        let request = Client::new()
+            .get("http://node:8443/api/v0/tip?format=json")
+            .build()
+            .unwrap();
+        let http_request = into_http_request(&request).unwrap();
+        assert_eq!(http_request.uri(), "/api/v0/tip?format=json");
+        assert_eq!(http_request.headers()[http::header::HOST], "node:8443");
+    }
+
+    #[test]
+    fn response_is_read_to_its_end() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut runtime = Runtime::new().unwrap();
+        let stream = connect(&mut runtime, &listener);
+        let node = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+        });
+
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        let mut response = exchange(&mut runtime, stream, get("/api/v0/tip"), deadline).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().unwrap(), "ok");
+        node.join().unwrap();
+    }
+
+    #[test]
+    fn tls_server_name_is_refused_over_unix_socket() {
+        let connection = Connection {
+            unix_socket: Some(PathBuf::from("node.sock")),
+            tls_ca: None,
+            tls_cert: None,
+            tls_server_name: Some("node".to_owned()),
+        };
+        let request = Client::new()
+            .get("https://node/api/v0/tip")
+            .build()
+            .unwrap();
+        match connection.execute(request) {
+            Err(Error::UnixSocketWithTls) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("the request was sent"),
+        }
+    }
+
+    #[test]
+    fn silent_node_times_out() {
+        // the connection is established by the system, the node never
+        // answers the request
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut runtime = Runtime::new().unwrap();
+        let stream = connect(&mut runtime, &listener);
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        match exchange(&mut runtime, stream, get("/api/v0/tip"), deadline) {
+            Err(Error::TimedOut) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("the silent node answered"),
+        }
+    }
+}
@@ -1,15 +1,22 @@
+use crate::jcli_app::utils::Connection;
 use reqwest::Url;
 use structopt::StructOpt;
 
-#[derive(StructOpt)]
+#[derive(Clone, StructOpt)]
 pub struct HostAddr {
     /// node API address. Must always have `http://` or `https://` prefix.
     /// E.g. `-h http://127.0.0.1`, `--host https://node.com:8443/cardano/api`
     #[structopt(short, long, env = "JORMUNGANDR_RESTAPI_URL")]
     host: Url,
+    #[structopt(flatten)]
+    connection: Connection,
 }
 
 impl HostAddr {
+    pub fn new(host: Url, connection: Connection) -> Self {
+        HostAddr { host, connection }
+    }
+
     pub fn with_segments(mut self, segments: &[&str]) -> Result<Self, Error> {
         let result = self.host.path_segments_mut().map(|mut host_segments| {
             host_segments.extend(segments);
@@ -21,8 +28,9 @@ impl HostAddr {
         }
     }
 
-    pub fn into_url(self) -> Url {
-        self.host
+    /// the URL and the options of the connection to the node
+    pub fn into_parts(self) -> (Url, Connection) {
+        (self.host, self.connection)
     }
 }
 
@@ -1,4 +1,5 @@
 mod account_id;
+mod connection;
 mod debug_flag;
 
 pub mod error;
@@ -10,6 +11,7 @@ pub mod output_format;
 pub mod rest_api;
 
 pub use self::account_id::AccountId;
+pub use self::connection::Connection;
 pub use self::debug_flag::DebugFlag;
 pub use self::error::CustomErrorFiller;
 pub use self::host_addr::HostAddr;
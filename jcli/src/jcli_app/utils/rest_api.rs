@@ -1,6 +1,8 @@
-use crate::jcli_app::utils::{open_api_verifier, CustomErrorFiller, DebugFlag, OpenApiVerifier};
+use crate::jcli_app::utils::{
+    connection, open_api_verifier, Connection, CustomErrorFiller, DebugFlag, OpenApiVerifier,
+};
 use hex;
-use reqwest::{self, header::HeaderValue, Client, Request, RequestBuilder, Response};
+use reqwest::{self, header::HeaderValue, Request, RequestBuilder, Response};
 use serde::{self, Serialize};
 use serde_json::error::Error as SerdeJsonError;
 use std::fmt;
@@ -10,6 +12,7 @@ pub const DESERIALIZATION_ERROR_MSG: &'static str = "node returned malformed dat
 pub struct RestApiSender<'a> {
     builder: RequestBuilder,
     body: RestApiRequestBody,
+    connection: &'a Connection,
     debug_flag: &'a DebugFlag,
 }
 
@@ -31,6 +34,7 @@ pub enum RestApiResponseBody {
 
 custom_error! { pub Error
     RequestFailed { source: reqwest::Error } = @{ reqwest_error_msg(source) },
+    ConnectionFailed { source: connection::Error } = "could not reach node",
     VerificationFailed { source: open_api_verifier::Error } = "request didn't pass verification",
     RequestJsonSerializationError { source: SerdeJsonError, filler: CustomErrorFiller }
         = "failed to serialize request JSON",
@@ -57,10 +61,15 @@ fn reqwest_error_msg(err: &reqwest::Error) -> &'static str {
 }
 
 impl<'a> RestApiSender<'a> {
-    pub fn new(builder: RequestBuilder, debug_flag: &'a DebugFlag) -> Self {
+    pub fn new(
+        builder: RequestBuilder,
+        connection: &'a Connection,
+        debug_flag: &'a DebugFlag,
+    ) -> Self {
         Self {
             builder,
             body: RestApiRequestBody::none(),
+            connection,
             debug_flag,
         }
     }
@@ -81,7 +90,7 @@ impl<'a> RestApiSender<'a> {
         OpenApiVerifier::load_from_env()?.verify_request(&request, &self.body)?;
         self.debug_flag.write_request(&request, &self.body);
         self.body.apply_body(&mut request);
-        let response_raw = Client::new().execute(request)?;
+        let response_raw = self.connection.execute(request)?;
         let response = RestApiResponse::new(response_raw)?;
         self.debug_flag.write_response(&response);
         Ok(response)
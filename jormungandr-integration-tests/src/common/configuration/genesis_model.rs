@@ -6,42 +6,27 @@ extern crate rand;
 extern crate rand_chacha;
 extern crate serde_derive;
 use self::serde_derive::{Deserialize, Serialize};
-use jormungandr_lib::interfaces::Value;
+use chain_impl_mockchain::{block::ConsensusVersion, fee::LinearFee, milli::Milli};
+use jormungandr_lib::{
+    crypto::key::Identifier,
+    interfaces::{
+        ActiveSlotCoefficient, BFTSlotsRatio, Block0ConfigurationBuilder, InitialUTxO,
+        KESUpdateSpeed, LegacyUTxO, NumberOfSlotsPerEpoch, SlotDuration, Value,
+    },
+    time::SecondsSinceUnixEpoch,
+};
 use std::path::PathBuf;
 use std::vec::Vec;
 
 use self::chain_addr::{Address, Discrimination};
 use self::chain_addr::{AddressReadable, Kind};
-use self::chain_crypto::bech32::Bech32;
 use self::chain_crypto::{Ed25519, Ed25519Extended, KeyPair, PublicKey, SecretKey};
 use self::rand::SeedableRng;
 use self::rand_chacha::ChaChaRng;
 
 use super::file_utils;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BlockchainConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub block0_date: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub discrimination: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub block0_consensus: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub slot_duration: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub slots_per_epoch: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub epoch_stability_depth: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub consensus_leader_ids: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub bft_slots_ratio: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub consensus_genesis_praos_active_slot_coeff: Option<String>,
-    pub linear_fees: LinearFees,
-    pub kes_update_speed: u32,
-}
+pub use jormungandr_lib::interfaces::{Block0Configuration, Initial};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct LinearFees {
@@ -60,34 +45,71 @@ impl From<chain_impl_mockchain::fee::LinearFee> for LinearFees {
     }
 }
 
+impl From<LinearFees> for LinearFee {
+    fn from(fees: LinearFees) -> LinearFee {
+        LinearFee::new(fees.constant, fees.coefficient, fees.certificate)
+    }
+}
+
+/// the funds of an address, readable as printed by jcli
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Fund {
     pub value: Value,
     pub address: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum Initial {
-    Fund(Vec<Fund>),
-    Cert(String),
-    LegacyFund(Vec<Fund>),
+impl Fund {
+    pub fn to_initial_utxo(&self) -> InitialUTxO {
+        InitialUTxO {
+            address: self.address.parse().expect("invalid address of the funds"),
+            value: self.value,
+        }
+    }
+
+    /// the funds of a legacy address, in the base58 form
+    pub fn to_legacy_utxo(&self) -> LegacyUTxO {
+        LegacyUTxO {
+            address: self
+                .address
+                .parse()
+                .expect("invalid legacy address of the funds"),
+            value: self.value,
+        }
+    }
+}
+
+/// the initial funds of the genesis file
+pub fn initial_funds(funds: &[Fund]) -> Initial {
+    Initial::Fund(funds.iter().map(Fund::to_initial_utxo).collect())
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct GenesisYaml {
-    pub blockchain_configuration: BlockchainConfig,
-    pub initial: Vec<Initial>,
+/// the initial legacy funds of the genesis file
+pub fn initial_legacy_funds(funds: &[Fund]) -> Initial {
+    Initial::LegacyFund(funds.iter().map(Fund::to_legacy_utxo).collect())
+}
+
+/// a ratio such as `0.222`
+pub fn milli(ratio: &str) -> Milli {
+    ratio.parse().expect("invalid ratio")
 }
 
-impl GenesisYaml {
-    pub fn serialize(genesis_yaml: &GenesisYaml) -> PathBuf {
-        let content = serde_yaml::to_string(&genesis_yaml).unwrap();
+/// the genesis files of the tests, on a test network led by two BFT leaders
+pub trait Block0ConfigurationExt {
+    fn new() -> Self;
+    fn new_with_funds(initial_funds: &[Fund]) -> Self;
+    fn new_with_legacy_funds(legacy_funds: &[Fund]) -> Self;
+    fn new_with_funds_and_legacy(initial_funds: &[Fund], legacy_funds: &[Fund]) -> Self;
+    fn serialize(genesis: &Self) -> PathBuf;
+}
+
+impl Block0ConfigurationExt for Block0Configuration {
+    fn serialize(genesis: &Block0Configuration) -> PathBuf {
+        let content = serde_yaml::to_string(genesis).unwrap();
         let input_yaml_file_path = file_utils::create_file_in_temp("genesis.yaml", &content);
         input_yaml_file_path
     }
 
-    pub fn new() -> GenesisYaml {
+    fn new() -> Block0Configuration {
         let sk1: SecretKey<Ed25519Extended> =
             SecretKey::generate(&mut ChaChaRng::from_seed([1; 32]));
         let pk1: PublicKey<Ed25519> = sk1.to_public();
@@ -112,55 +134,46 @@ impl GenesisYaml {
                 value: 100.into(),
             },
         ];
-        GenesisYaml::new_with_funds(&initial_funds)
+        Block0Configuration::new_with_funds(&initial_funds)
     }
 
-    pub fn new_with_funds(initial_funds: &[Fund]) -> GenesisYaml {
-        GenesisYaml::new_with_funds_and_legacy(initial_funds, &[])
+    fn new_with_funds(initial_funds: &[Fund]) -> Block0Configuration {
+        Block0Configuration::new_with_funds_and_legacy(initial_funds, &[])
     }
 
-    pub fn new_with_legacy_funds(legacy_funds: &[Fund]) -> GenesisYaml {
-        GenesisYaml::new_with_funds_and_legacy(&[], legacy_funds)
+    fn new_with_legacy_funds(legacy_funds: &[Fund]) -> Block0Configuration {
+        Block0Configuration::new_with_funds_and_legacy(&[], legacy_funds)
     }
 
-    pub fn new_with_funds_and_legacy(initial_funds: &[Fund], legacy_funds: &[Fund]) -> GenesisYaml {
+    fn new_with_funds_and_legacy(
+        initial_funds: &[Fund],
+        legacy_funds: &[Fund],
+    ) -> Block0Configuration {
         let leader_1: KeyPair<Ed25519Extended> =
             KeyPair::generate(&mut ChaChaRng::from_seed([1; 32]));
         let leader_2: KeyPair<Ed25519Extended> =
             KeyPair::generate(&mut ChaChaRng::from_seed([2; 32]));
-        let leader_1_pk = leader_1.public_key().to_bech32_str();
-        let leader_2_pk = leader_2.public_key().to_bech32_str();
 
-        let mut initial = Vec::new();
-        if initial_funds.len() > 0 {
-            initial.push(Initial::Fund(initial_funds.iter().cloned().collect()))
-        }
-        if legacy_funds.len() > 0 {
-            initial.push(Initial::LegacyFund(legacy_funds.iter().cloned().collect()))
+        let mut builder =
+            Block0ConfigurationBuilder::new(Discrimination::Test, ConsensusVersion::Bft);
+        builder
+            .with_block0_date(SecondsSinceUnixEpoch::from_secs(1554185140))
+            .with_slot_duration(SlotDuration::new(1).unwrap())
+            .with_slots_per_epoch(NumberOfSlotsPerEpoch::new(100).unwrap())
+            .with_epoch_stability_depth(2600)
+            .with_consensus_leader_ids(vec![
+                Identifier::from(leader_1.public_key().clone()).into(),
+                Identifier::from(leader_2.public_key().clone()).into(),
+            ])
+            .with_bft_slots_ratio(BFTSlotsRatio::new(milli("0.222")).unwrap())
+            .with_active_slot_coefficient(ActiveSlotCoefficient::new(milli("0.444")).unwrap())
+            .with_kes_update_speed(KESUpdateSpeed::new(12 * 3600).unwrap());
+        if !initial_funds.is_empty() {
+            builder.add_initial(self::initial_funds(initial_funds));
         }
-
-        GenesisYaml {
-            blockchain_configuration: BlockchainConfig {
-                block0_date: Some(1554185140),
-                discrimination: Some(String::from("test")),
-                block0_consensus: Some(String::from("bft")),
-                slot_duration: Some(1),
-                slots_per_epoch: Some(100),
-                epoch_stability_depth: Some(2600),
-                consensus_leader_ids: Some(vec![
-                    String::from(leader_1_pk),
-                    String::from(leader_2_pk),
-                ]),
-                bft_slots_ratio: Some("0.222".to_owned()),
-                consensus_genesis_praos_active_slot_coeff: Some("0.444".to_owned()),
-                linear_fees: LinearFees {
-                    constant: 0,
-                    coefficient: 0,
-                    certificate: 0,
-                },
-                kes_update_speed: 12 * 3600,
-            },
-            initial,
+        if !legacy_funds.is_empty() {
+            builder.add_initial(initial_legacy_funds(legacy_funds));
         }
+        builder.build()
     }
 }
@@ -1,8 +1,8 @@
 #![allow(dead_code)]
 
-use crate::common::configuration::genesis_model::GenesisYaml;
-use crate::common::configuration::node_config_model::NodeConfig;
-use crate::common::configuration::secret_model::SecretModel;
+use crate::common::configuration::genesis_model::{Block0Configuration, Block0ConfigurationExt};
+use crate::common::configuration::node_config_model::{NodeConfig, NodeConfigExt};
+use crate::common::configuration::secret_model::{NodeSecret, NodeSecretExt};
 use crate::common::data::address::AddressDataProvider;
 use crate::common::file_utils;
 use chain_core::mempack;
@@ -17,9 +17,9 @@ pub struct JormungandrConfig {
     pub genesis_block_hash: String,
     pub node_config_path: PathBuf,
     pub secret_model_path: PathBuf,
-    pub genesis_yaml: GenesisYaml,
+    pub genesis_yaml: Block0Configuration,
     pub node_config: NodeConfig,
-    pub secret_model: SecretModel,
+    pub secret_model: NodeSecret,
     pub log_file_path: PathBuf,
 }
 
@@ -28,6 +28,10 @@ impl JormungandrConfig {
         self.node_config.get_node_address()
     }
 
+    pub fn get_admin_token(&self) -> Option<&str> {
+        self.node_config.get_admin_token()
+    }
+
     pub fn refresh_node_dynamic_params(&mut self) {
         self.node_config.regenerate_ports();
         self.update_node_config();
@@ -39,10 +43,10 @@ impl JormungandrConfig {
     }
 
     pub fn new() -> Self {
-        JormungandrConfig::from(GenesisYaml::new(), NodeConfig::new())
+        JormungandrConfig::from(Block0Configuration::new(), NodeConfig::new())
     }
 
-    pub fn from(genesis_yaml: GenesisYaml, node_config: NodeConfig) -> Self {
+    pub fn from(genesis_yaml: Block0Configuration, node_config: NodeConfig) -> Self {
         JormungandrConfig {
             genesis_block_path: PathBuf::from(""),
             genesis_block_hash: String::from(""),
@@ -51,7 +55,7 @@ impl JormungandrConfig {
             log_file_path: PathBuf::from(""),
             genesis_yaml: genesis_yaml,
             node_config: node_config,
-            secret_model: SecretModel::empty(),
+            secret_model: NodeSecret::empty(),
         }
     }
 
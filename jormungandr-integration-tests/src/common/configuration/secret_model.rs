@@ -1,61 +1,52 @@
 #![allow(dead_code)]
 
-extern crate rand;
-extern crate serde_derive;
-use self::serde_derive::{Deserialize, Serialize};
 use crate::common::file_utils;
-use std::option::Option;
+use jormungandr_lib::crypto::key::SigningKey;
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SecretModel {
-    pub bft: Option<BFT>,
-    pub genesis: Option<Genesis>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BFT {
-    pub signing_key: String,
-}
+pub use jormungandr_lib::interfaces::{BftSecret, GenesisPraosSecret, NodeSecret};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Genesis {
-    pub sig_key: String,
-    pub vrf_key: String,
-    pub node_id: String,
+/// the secret files of the tests, made of the keys printed by jcli
+pub trait NodeSecretExt {
+    fn empty() -> Self;
+    fn new_bft(signing_key: &str) -> Self;
+    fn new_genesis(signing_key: &str, vrf_key: &str, node_id: &str) -> Self;
+    fn serialize(secret: &Self) -> PathBuf;
 }
 
-impl SecretModel {
-    pub fn serialize(secret_model: &SecretModel) -> PathBuf {
-        let content = serde_yaml::to_string(&secret_model).expect("Canot serialize secret model");
-        let node_config_file_path = file_utils::create_file_in_temp("node.secret", &content);
-        node_config_file_path
-    }
-
-    pub fn empty() -> Self {
-        SecretModel {
+impl NodeSecretExt for NodeSecret {
+    fn empty() -> Self {
+        NodeSecret {
             bft: None,
             genesis: None,
         }
     }
 
-    pub fn new_bft(signing_key: &str) -> Self {
-        SecretModel {
-            bft: Some(BFT {
-                signing_key: signing_key.to_string(),
+    fn new_bft(signing_key: &str) -> Self {
+        NodeSecret {
+            bft: Some(BftSecret {
+                signing_key: SigningKey::from_bech32_str(signing_key)
+                    .expect("invalid BFT signing key"),
             }),
             genesis: None,
         }
     }
 
-    pub fn new_genesis(signing_key: &str, vrf_key: &str, node_id: &str) -> Self {
-        SecretModel {
-            genesis: Some(Genesis {
-                sig_key: signing_key.to_string(),
-                vrf_key: vrf_key.to_string(),
-                node_id: node_id.to_string(),
-            }),
+    fn new_genesis(signing_key: &str, vrf_key: &str, node_id: &str) -> Self {
+        NodeSecret {
             bft: None,
+            genesis: Some(GenesisPraosSecret {
+                node_id: node_id.parse().expect("invalid stake pool ID"),
+                sig_key: SigningKey::from_bech32_str(signing_key).expect("invalid KES key"),
+                vrf_key: SigningKey::from_bech32_str(vrf_key).expect("invalid VRF key"),
+            }),
         }
     }
+
+    fn serialize(secret: &NodeSecret) -> PathBuf {
+        let content = serde_yaml::to_string(secret).expect("Canot serialize secret model");
+        let node_secret_file_path = file_utils::create_file_in_temp("node.secret", &content);
+        file_utils::make_owner_only(&node_secret_file_path);
+        node_secret_file_path
+    }
 }
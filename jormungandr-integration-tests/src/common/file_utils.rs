@@ -66,6 +66,18 @@ fn trim_new_line_at_end(mut content: String) -> String {
     content
 }
 
+/// Restricts the file to its owner, as the node refuses the secret files
+/// other users can access
+#[cfg(unix)]
+pub fn make_owner_only(path: &PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path.as_os_str(), fs::Permissions::from_mode(0o600))
+        .expect("cannot set permissions");
+}
+
+#[cfg(not(unix))]
+pub fn make_owner_only(_path: &PathBuf) {}
+
 pub fn make_readonly(path: &PathBuf) {
     if !path.exists() {
         std::fs::File::create(&path).unwrap();
@@ -57,6 +57,52 @@ impl CertificateCommands {
         command
     }
 
+    pub fn get_stake_pool_retirement_command(
+        &self,
+        stake_pool_id: &str,
+        retirement_time: u64,
+    ) -> Command {
+        let mut command = Command::new(configuration::get_jcli_app().as_os_str());
+        command
+            .arg("certificate")
+            .arg("new")
+            .arg("stake-pool-retirement")
+            .arg("--pool-id")
+            .arg(&stake_pool_id)
+            .arg("--retirement-time")
+            .arg(&retirement_time.to_string());
+        command
+    }
+
+    pub fn get_stake_pool_update_command(
+        &self,
+        stake_pool_id: &str,
+        start_validity: u64,
+        previous_kes_key: &str,
+        previous_vrf_key: &str,
+        kes_key: &str,
+        vrf_key: &str,
+    ) -> Command {
+        let mut command = Command::new(configuration::get_jcli_app().as_os_str());
+        command
+            .arg("certificate")
+            .arg("new")
+            .arg("stake-pool-update")
+            .arg("--pool-id")
+            .arg(&stake_pool_id)
+            .arg("--start-validity")
+            .arg(&start_validity.to_string())
+            .arg("--previous-kes-key")
+            .arg(&previous_kes_key)
+            .arg("--previous-vrf-key")
+            .arg(&previous_vrf_key)
+            .arg("--kes-key")
+            .arg(&kes_key)
+            .arg("--vrf-key")
+            .arg(&vrf_key);
+        command
+    }
+
     pub fn get_stake_pool_id_command(
         &self,
         input_file: &PathBuf,
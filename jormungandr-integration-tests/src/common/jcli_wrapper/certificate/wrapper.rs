@@ -4,12 +4,7 @@ use crate::common::{
     file_assert, file_utils, process_assert,
     process_utils::{self, output_extensions::ProcessOutput},
 };
-use chain_impl_mockchain::{
-    certificate::PoolId, testing::builders::cert_builder::build_stake_pool_retirement_cert,
-};
-use std::{path::PathBuf, str::FromStr};
-
-use jormungandr_lib::interfaces::Certificate;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct JCLICertificateWrapper {
@@ -142,9 +137,36 @@ impl JCLICertificateWrapper {
     }
 
     pub fn assert_new_stake_pool_retirement(&self, stake_pool_id: &str) -> String {
-        let pool_id = PoolId::from_str(&stake_pool_id).unwrap();
-        let start_validity = 0u64;
-        let certificate = build_stake_pool_retirement_cert(pool_id, start_validity);
-        format!("{}", Certificate::from(certificate).to_bech32().unwrap())
+        println!("Running new stake pool retirement...");
+        let output = process_utils::run_process_and_get_output(
+            self.commands
+                .get_stake_pool_retirement_command(&stake_pool_id, 0),
+        );
+        let certification = output.as_single_line();
+        process_assert::assert_process_exited_successfully(output);
+        certification
+    }
+
+    pub fn assert_new_stake_pool_update(
+        &self,
+        stake_pool_id: &str,
+        previous_kes_key: &str,
+        previous_vrf_key: &str,
+        kes_key: &str,
+        vrf_key: &str,
+    ) -> String {
+        println!("Running new stake pool update...");
+        let output =
+            process_utils::run_process_and_get_output(self.commands.get_stake_pool_update_command(
+                &stake_pool_id,
+                0,
+                &previous_kes_key,
+                &previous_vrf_key,
+                &kes_key,
+                &vrf_key,
+            ));
+        let certification = output.as_single_line();
+        process_assert::assert_process_exited_successfully(output);
+        certification
     }
 }
@@ -60,7 +60,7 @@ pub fn get_rest_stats_command(host: &str) -> Command {
 }
 
 /// Get rest stat command.
-pub fn get_rest_shutdown_command(host: &str) -> Command {
+pub fn get_rest_shutdown_command(host: &str, token: Option<&str>) -> Command {
     let mut command = get_jcli_command();
     command
         .arg("rest")
@@ -69,10 +69,26 @@ pub fn get_rest_shutdown_command(host: &str) -> Command {
         .arg("get")
         .arg("-h")
         .arg(&host);
+    if let Some(token) = token {
+        command.arg("--token").arg(token);
+    }
     command
 }
 
 /// Get rest stat command.
+pub fn get_rest_account_rewards_command(address: &str, host: &str) -> Command {
+    let mut command = get_jcli_command();
+    command
+        .arg("rest")
+        .arg("v0")
+        .arg("rewards")
+        .arg("account")
+        .arg(&address)
+        .arg("-h")
+        .arg(&host);
+    command
+}
+
 pub fn get_rest_account_stats_command(address: &str, host: &str) -> Command {
     let mut command = get_jcli_command();
     command
@@ -2,7 +2,7 @@
 
 use jormungandr_lib::crypto::hash::Hash;
 use jormungandr_lib::interfaces::{
-    AccountState, FragmentLog, FragmentStatus, SettingsDto, UTxOInfo, UTxOOutputInfo,
+    AccountReward, AccountState, FragmentLog, FragmentStatus, SettingsDto, UTxOInfo, UTxOOutputInfo,
 };
 
 pub mod certificate;
@@ -12,11 +12,11 @@ pub mod jcli_transaction_wrapper;
 pub use jcli_transaction_wrapper::JCLITransactionWrapper;
 
 use super::configuration;
-use super::configuration::genesis_model::GenesisYaml;
 use super::file_assert;
 use super::file_utils;
 use super::process_assert;
 use super::process_utils::{self, output_extensions::ProcessOutput, Wait};
+use serde::Serialize;
 use std::{collections::BTreeMap, path::PathBuf};
 
 use chain_addr::Discrimination;
@@ -32,8 +32,11 @@ pub fn assert_genesis_encode(
     file_assert::assert_file_exists_and_not_empty(path_to_output_block);
 }
 
-pub fn assert_genesis_encode_fails(genesis_yaml: &GenesisYaml, expected_msg: &str) {
-    let input_yaml_file_path = GenesisYaml::serialize(&genesis_yaml);
+/// the genesis file is either a `Block0Configuration` or, to check the
+/// values it cannot hold, its YAML value
+pub fn assert_genesis_encode_fails<G: Serialize>(genesis_yaml: &G, expected_msg: &str) {
+    let content = serde_yaml::to_string(genesis_yaml).unwrap();
+    let input_yaml_file_path = file_utils::create_file_in_temp("genesis.yaml", &content);
     let path_to_output_block = file_utils::get_path_in_temp("block-0.bin");
     process_assert::assert_process_failed_and_matches_message(
         jcli_commands::get_genesis_encode_command(&input_yaml_file_path, &path_to_output_block),
@@ -294,9 +297,20 @@ pub fn assert_rest_account_get_stats(address: &str, host: &str) -> AccountState
     serde_yaml::from_str(&content).unwrap()
 }
 
-pub fn assert_rest_shutdown(host: &str) {
-    let output =
-        process_utils::run_process_and_get_output(jcli_commands::get_rest_shutdown_command(&host));
+pub fn assert_rest_account_get_rewards(address: &str, host: &str) -> Vec<AccountReward> {
+    let output = process_utils::run_process_and_get_output(
+        jcli_commands::get_rest_account_rewards_command(&address, &host),
+    );
+    let content = output.as_lossy_string();
+    process_assert::assert_process_exited_successfully(output);
+
+    serde_yaml::from_str(&content).unwrap()
+}
+
+pub fn assert_rest_shutdown(host: &str, token: Option<&str>) {
+    let output = process_utils::run_process_and_get_output(
+        jcli_commands::get_rest_shutdown_command(&host, token),
+    );
     process_assert::assert_process_exited_successfully(output);
 }
 
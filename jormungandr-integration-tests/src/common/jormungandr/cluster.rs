@@ -0,0 +1,398 @@
+//! clusters of nodes started from the same genesis block
+//!
+//! The first node is a BFT leader writing the genesis block, with the
+//! consensus leader IDs of the other leaders of the cluster. The nodes started
+//! after it get the genesis block from it and trust all the nodes started
+//! before them, each node on its own ports. The cluster is returned once all
+//! the nodes have the same tip.
+//!
+//! With a faulty network, each node advertises the address of its proxy, and
+//! the faults of the links between the nodes are set from the cluster.
+
+extern crate custom_error;
+
+use self::custom_error::custom_error;
+use super::{ConfigurationBuilder, JormungandrProcess, Role, Starter, StartupError};
+use crate::common::{
+    configuration::{
+        genesis_model::{Fund, LinearFees},
+        jormungandr_config::JormungandrConfig,
+        node_config_model::TrustedPeer,
+        secret_model::{NodeSecret, NodeSecretExt},
+    },
+    jcli_wrapper,
+    network_faults::{FaultyNetwork, Proxy},
+    process_utils,
+};
+use jormungandr_lib::{client::JormungandrClient, crypto::hash::Hash};
+use std::time::{Duration, Instant};
+
+custom_error! {pub ClusterError
+    NodeNotStarted { alias: String, source: StartupError } = "node {alias} did not start",
+    SyncTimeout { timeout: u64, tips: String } = "the nodes did not sync after {timeout} s, tips: {tips}",
+    HeightTimeout { height: u32, timeout: u64 } = "the nodes did not reach the height {height} after {timeout} s",
+}
+
+const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(120);
+const DEFAULT_SLOT_DURATION: u32 = 2;
+
+pub struct ClusterBuilder {
+    nodes: usize,
+    leaders: usize,
+    funds: Vec<Fund>,
+    slot_duration: u32,
+    slots_per_epoch: Option<u32>,
+    linear_fees: Option<LinearFees>,
+    rewards: Option<u64>,
+    sync_timeout: Duration,
+    faulty_network: bool,
+}
+
+impl ClusterBuilder {
+    /// a cluster of `nodes` nodes, the first one being the only leader
+    pub fn new(nodes: usize) -> Self {
+        ClusterBuilder {
+            nodes,
+            leaders: 1,
+            funds: Vec::new(),
+            slot_duration: DEFAULT_SLOT_DURATION,
+            slots_per_epoch: None,
+            linear_fees: None,
+            rewards: None,
+            sync_timeout: DEFAULT_SYNC_TIMEOUT,
+            faulty_network: false,
+        }
+    }
+
+    pub fn with_nodes(&mut self, nodes: usize) -> &mut Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// the number of BFT leaders, the first nodes of the cluster
+    pub fn with_leaders(&mut self, leaders: usize) -> &mut Self {
+        self.leaders = leaders;
+        self
+    }
+
+    pub fn with_funds(&mut self, funds: Vec<Fund>) -> &mut Self {
+        self.funds = funds;
+        self
+    }
+
+    pub fn with_slot_duration(&mut self, slot_duration: u32) -> &mut Self {
+        self.slot_duration = slot_duration;
+        self
+    }
+
+    pub fn with_slots_per_epoch(&mut self, slots_per_epoch: u32) -> &mut Self {
+        self.slots_per_epoch = Some(slots_per_epoch);
+        self
+    }
+
+    pub fn with_linear_fees(&mut self, linear_fees: LinearFees) -> &mut Self {
+        self.linear_fees = Some(linear_fees);
+        self
+    }
+
+    /// the value of the reward pot in the genesis block
+    pub fn with_rewards(&mut self, rewards: u64) -> &mut Self {
+        self.rewards = Some(rewards);
+        self
+    }
+
+    pub fn with_sync_timeout(&mut self, sync_timeout: Duration) -> &mut Self {
+        self.sync_timeout = sync_timeout;
+        self
+    }
+
+    /// put the nodes behind proxies, to inject faults between them
+    pub fn with_faulty_network(&mut self) -> &mut Self {
+        self.faulty_network = true;
+        self
+    }
+
+    pub fn start(&self) -> Result<Cluster, ClusterError> {
+        assert!(
+            self.leaders >= 1 && self.leaders <= self.nodes,
+            "a cluster of {} nodes cannot have {} leaders",
+            self.nodes,
+            self.leaders
+        );
+
+        let leader_secret_keys: Vec<String> = (1..self.leaders)
+            .map(|_| jcli_wrapper::assert_key_generate("ed25519"))
+            .collect();
+        let leader_ids = leader_secret_keys
+            .iter()
+            .map(|secret_key| jcli_wrapper::assert_key_to_public_default(secret_key))
+            .collect();
+
+        let mut bootstrap_builder = ConfigurationBuilder::new();
+        bootstrap_builder
+            .with_funds(self.funds.clone())
+            .with_slot_duration(self.slot_duration)
+            .with_consensus_leaders_ids(leader_ids);
+        if let Some(slots_per_epoch) = self.slots_per_epoch {
+            bootstrap_builder.with_slots_per_epoch(slots_per_epoch);
+        }
+        if let Some(linear_fees) = self.linear_fees.as_ref() {
+            bootstrap_builder.with_linear_fees(linear_fees.clone());
+        }
+        if let Some(rewards) = self.rewards {
+            bootstrap_builder.with_rewards(rewards);
+        }
+        let network = if self.faulty_network {
+            Some(FaultyNetwork::new())
+        } else {
+            None
+        };
+
+        let mut bootstrap_config = bootstrap_builder.build();
+        let proxy = network
+            .as_ref()
+            .map(|network| put_behind_proxy(network, &node_alias(0), &mut bootstrap_config));
+        allow_private_addresses(&mut bootstrap_config);
+
+        let mut nodes = vec![ClusterNode::start(
+            node_alias(0),
+            bootstrap_config.clone(),
+            Role::Leader,
+            network.as_ref(),
+            proxy,
+        )?];
+
+        for index in 1..self.nodes {
+            let trusted_peers = nodes
+                .iter()
+                .map(|node| TrustedPeer {
+                    address: node.config().node_config.p2p.public_address.clone(),
+                    id: node.config().node_config.p2p.public_id.clone(),
+                })
+                .collect();
+            let mut config = ConfigurationBuilder::new()
+                .with_trusted_peers(trusted_peers)
+                .with_block_hash(bootstrap_config.genesis_block_hash.clone())
+                .build();
+            config.genesis_yaml = bootstrap_config.genesis_yaml.clone();
+            config.genesis_block_path = bootstrap_config.genesis_block_path.clone();
+
+            let role = match leader_secret_keys.get(index - 1) {
+                Some(secret_key) => {
+                    config.secret_model = NodeSecret::new_bft(secret_key);
+                    config.secret_model_path = NodeSecret::serialize(&config.secret_model);
+                    Role::Leader
+                }
+                None => Role::Passive,
+            };
+            let alias = node_alias(index);
+            let proxy = network
+                .as_ref()
+                .map(|network| put_behind_proxy(network, &alias, &mut config));
+            allow_private_addresses(&mut config);
+
+            nodes.push(ClusterNode::start(
+                alias,
+                config,
+                role,
+                network.as_ref(),
+                proxy,
+            )?);
+        }
+
+        let cluster = Cluster {
+            nodes,
+            genesis_block_hash: bootstrap_config.genesis_block_hash,
+            network,
+        };
+        cluster.wait_for_sync(self.sync_timeout)?;
+        Ok(cluster)
+    }
+}
+
+fn node_alias(index: usize) -> String {
+    format!("node{}", index)
+}
+
+/// the node listens on its port and advertises the port of its proxy
+fn put_behind_proxy(network: &FaultyNetwork, alias: &str, config: &mut JormungandrConfig) -> Proxy {
+    let port = config
+        .node_config
+        .p2p
+        .listen_address
+        .rsplit('/')
+        .next()
+        .and_then(|port| port.parse::<u16>().ok())
+        .expect("cannot extract port from p2p.listen_address");
+    let proxy = network.proxy(alias, ([127, 0, 0, 1], port).into());
+    config.node_config.p2p.public_address = proxy.multiaddr();
+    proxy
+}
+
+/// the nodes of the cluster gossip on the loopback interface
+fn allow_private_addresses(config: &mut JormungandrConfig) {
+    config.node_config.p2p.allow_private_addresses = true;
+    config.update_node_config();
+}
+
+pub struct ClusterNode {
+    alias: String,
+    role: Role,
+    process: JormungandrProcess,
+    rest: JormungandrClient,
+    proxy: Option<Proxy>,
+}
+
+impl ClusterNode {
+    fn start(
+        alias: String,
+        config: JormungandrConfig,
+        role: Role,
+        network: Option<&FaultyNetwork>,
+        proxy: Option<Proxy>,
+    ) -> Result<Self, ClusterError> {
+        let process = Starter::new()
+            .config(config)
+            .role(role)
+            .start()
+            .map_err(|source| ClusterError::NodeNotStarted {
+                alias: alias.clone(),
+                source,
+            })?;
+        let rest_listen = process
+            .config
+            .node_config
+            .rest
+            .as_ref()
+            .expect("the nodes of a cluster serve the REST API")
+            .listen;
+        if let Some(network) = network {
+            network.register_process(&alias, process.child.id());
+        }
+        let mut rest = JormungandrClient::new(&rest_listen.to_string());
+        if let Some(admin_token) = process.config.get_admin_token() {
+            rest = rest.with_admin_token(admin_token);
+        }
+        Ok(ClusterNode {
+            alias,
+            role,
+            process,
+            rest,
+            proxy,
+        })
+    }
+
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn process(&self) -> &JormungandrProcess {
+        &self.process
+    }
+
+    pub fn config(&self) -> &JormungandrConfig {
+        &self.process.config
+    }
+
+    /// the client of the REST API of the node
+    pub fn rest(&self) -> &JormungandrClient {
+        &self.rest
+    }
+
+    /// the tip of the node, none if the node does not respond
+    pub fn tip(&self) -> Option<Hash> {
+        self.rest.tip().ok()
+    }
+
+    /// the height of the tip of the node, none if the node does not respond
+    pub fn height(&self) -> Option<u32> {
+        self.rest.node_stats().ok()?.last_block_height
+    }
+}
+
+pub struct Cluster {
+    nodes: Vec<ClusterNode>,
+    genesis_block_hash: String,
+    network: Option<FaultyNetwork>,
+}
+
+impl Cluster {
+    pub fn nodes(&self) -> &[ClusterNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, alias: &str) -> Option<&ClusterNode> {
+        self.nodes.iter().find(|node| node.alias == alias)
+    }
+
+    pub fn leaders(&self) -> impl Iterator<Item = &ClusterNode> {
+        self.nodes.iter().filter(|node| match node.role {
+            Role::Leader => true,
+            Role::Passive => false,
+        })
+    }
+
+    pub fn genesis_block_hash(&self) -> &str {
+        &self.genesis_block_hash
+    }
+
+    /// the faults between the nodes, from a cluster with a faulty network
+    pub fn network(&self) -> &FaultyNetwork {
+        self.network
+            .as_ref()
+            .expect("the cluster was started without a faulty network")
+    }
+
+    pub fn tips(&self) -> Vec<Option<Hash>> {
+        self.nodes.iter().map(ClusterNode::tip).collect()
+    }
+
+    /// whether all the nodes respond with the same tip
+    pub fn is_synced(&self) -> bool {
+        let tips = self.tips();
+        match tips.first() {
+            Some(Some(tip)) => tips.iter().all(|other| other.as_ref() == Some(tip)),
+            _ => false,
+        }
+    }
+
+    /// wait until all the nodes have the same tip
+    pub fn wait_for_sync(&self, timeout: Duration) -> Result<(), ClusterError> {
+        let start = Instant::now();
+        while !self.is_synced() {
+            if start.elapsed() > timeout {
+                return Err(ClusterError::SyncTimeout {
+                    timeout: timeout.as_secs(),
+                    tips: format!("{:?}", self.tips()),
+                });
+            }
+            process_utils::sleep(1);
+        }
+        Ok(())
+    }
+
+    /// wait until all the nodes have the same tip, at `height` or above
+    pub fn wait_for_height(&self, height: u32, timeout: Duration) -> Result<(), ClusterError> {
+        let start = Instant::now();
+        loop {
+            let reached = self.nodes.iter().all(|node| {
+                node.height()
+                    .map_or(false, |node_height| node_height >= height)
+            });
+            if reached && self.is_synced() {
+                return Ok(());
+            }
+            if start.elapsed() > timeout {
+                return Err(ClusterError::HeightTimeout {
+                    height,
+                    timeout: timeout.as_secs(),
+                });
+            }
+            process_utils::sleep(1);
+        }
+    }
+}
@@ -22,8 +22,8 @@ pub fn get_start_jormungandr_as_leader_node_command(
         .arg("--config")
         .arg(config_path.as_os_str())
         .arg("--genesis-block")
-        .arg(genesis_block_path.as_os_str())
-        .stderr(get_stdio_from_log_file(&log_file_path));
+        .arg(genesis_block_path.as_os_str());
+    redirect_output(&mut command, log_file_path);
     println!("Running start jormungandr command: {:?}", &command);
     command
 }
@@ -39,12 +39,27 @@ pub fn get_start_jormungandr_as_passive_node_command(
         .arg("--config")
         .arg(config_path.as_os_str())
         .arg("--genesis-block-hash")
-        .arg(&genesis_block_hash)
-        .stderr(get_stdio_from_log_file(&log_file_path));
+        .arg(&genesis_block_hash);
+    redirect_output(&mut command, log_file_path);
     println!("Running start jormungandr command: {:?}", &command);
     command
 }
 
+/// the file capturing the standard output of the node logging to `log_file_path`
+pub fn get_stdout_file_path(log_file_path: &PathBuf) -> PathBuf {
+    log_file_path.with_extension("stdout")
+}
+
+/// the logs, and the panics with their backtrace, go to the log file
+fn redirect_output(command: &mut Command, log_file_path: &PathBuf) {
+    command
+        .env("RUST_BACKTRACE", "1")
+        .stdout(get_stdio_from_log_file(&get_stdout_file_path(
+            log_file_path,
+        )))
+        .stderr(get_stdio_from_log_file(log_file_path));
+}
+
 #[cfg(windows)]
 fn get_stdio_from_log_file(log_file_path: &PathBuf) -> std::process::Stdio {
     use std::os::windows::io::{FromRawHandle, IntoRawHandle};
@@ -1,21 +1,33 @@
 use crate::common::{
     configuration::{
-        genesis_model::{Fund, GenesisYaml, Initial, LinearFees},
+        genesis_model::{
+            milli, Block0Configuration, Block0ConfigurationExt, Fund, Initial, LinearFees,
+        },
         jormungandr_config::JormungandrConfig,
-        node_config_model::{Log, NodeConfig, TrustedPeer},
-        secret_model::SecretModel,
+        node_config_model::{Log, NodeConfig, NodeConfigExt, TrustedPeer},
+        secret_model::{NodeSecret, NodeSecretExt},
     },
     file_utils, jcli_wrapper,
     startup::build_genesis_block,
 };
 
-use jormungandr_lib::interfaces::Mempool;
+use chain_crypto::Ed25519;
+use chain_impl_mockchain::block::ConsensusVersion;
+use jormungandr_lib::{
+    crypto::key::Identifier,
+    interfaces::{
+        ActiveSlotCoefficient, BFTSlotsRatio, ConsensusLeaderId, KESUpdateSpeed, ListenMode,
+        Mempool, NumberOfSlotsPerEpoch, SlotDuration,
+    },
+};
+use std::convert::TryFrom as _;
 
 pub struct ConfigurationBuilder {
     funds: Vec<Fund>,
     trusted_peers: Option<Vec<TrustedPeer>>,
     public_address: Option<String>,
     listen_address: Option<String>,
+    client_only: bool,
     block0_hash: Option<String>,
     block0_consensus: Option<String>,
     log: Option<Log>,
@@ -26,6 +38,7 @@ pub struct ConfigurationBuilder {
     epoch_stability_depth: Option<u32>,
     kes_update_speed: u32,
     linear_fees: LinearFees,
+    rewards: Option<u64>,
     certs: Vec<String>,
     consensus_leader_ids: Vec<String>,
     mempool: Option<Mempool>,
@@ -40,6 +53,7 @@ impl ConfigurationBuilder {
             trusted_peers: None,
             listen_address: None,
             public_address: None,
+            client_only: false,
             block0_hash: None,
             block0_consensus: Some("bft".to_string()),
             slots_per_epoch: None,
@@ -51,6 +65,7 @@ impl ConfigurationBuilder {
                 coefficient: 0,
                 certificate: 0,
             },
+            rewards: None,
             bft_slots_ratio: Some("0.222".to_owned()),
             consensus_genesis_praos_active_slot_coeff: Some("0.1".to_owned()),
             kes_update_speed: 12 * 3600,
@@ -83,6 +98,12 @@ impl ConfigurationBuilder {
         self
     }
 
+    /// the value of the reward pot in the genesis block
+    pub fn with_rewards(&mut self, rewards: u64) -> &mut Self {
+        self.rewards = Some(rewards);
+        self
+    }
+
     pub fn with_consensus_leaders_ids(&mut self, consensus_leader_ids: Vec<String>) -> &mut Self {
         self.consensus_leader_ids = consensus_leader_ids;
         self
@@ -136,6 +157,13 @@ impl ConfigurationBuilder {
         self
     }
 
+    /// a node not listening for the connections of the peers, overriding
+    /// the public and listen addresses
+    pub fn with_client_only(&mut self) -> &mut Self {
+        self.client_only = true;
+        self
+    }
+
     pub fn with_block_hash(&mut self, block0_hash: String) -> &mut Self {
         self.block0_hash = Some(block0_hash.clone());
         self
@@ -155,6 +183,11 @@ impl ConfigurationBuilder {
         if let Some(public_address) = &self.public_address {
             node_config.p2p.public_address = public_address.to_string();
         }
+        if self.client_only {
+            node_config.p2p.public_address.clear();
+            node_config.p2p.listen_address.clear();
+            node_config.p2p.listen = Some(ListenMode::None);
+        }
         if let Some(mempool) = &self.mempool {
             node_config.mempool = mempool.clone();
         }
@@ -167,39 +200,58 @@ impl ConfigurationBuilder {
         let secret_key = jcli_wrapper::assert_key_generate("ed25519");
         let public_key = jcli_wrapper::assert_key_to_public_default(&secret_key);
 
-        let mut genesis_model = GenesisYaml::new_with_funds(&self.funds);
+        let mut genesis_model = Block0Configuration::new_with_funds(&self.funds);
+        let blockchain_configuration = &mut genesis_model.blockchain_configuration;
 
         let mut leaders_ids = vec![public_key];
         leaders_ids.append(&mut self.consensus_leader_ids.clone());
-        genesis_model.blockchain_configuration.consensus_leader_ids = Some(leaders_ids.clone());
-        genesis_model.blockchain_configuration.block0_consensus = self.block0_consensus.clone();
-        genesis_model.blockchain_configuration.bft_slots_ratio = self.bft_slots_ratio.clone();
-        genesis_model.blockchain_configuration.kes_update_speed = self.kes_update_speed.clone();
+        blockchain_configuration.consensus_leader_ids = leaders_ids
+            .iter()
+            .map(|id| consensus_leader_id(id))
+            .collect();
+        if let Some(block0_consensus) = &self.block0_consensus {
+            blockchain_configuration.block0_consensus = consensus_version(block0_consensus);
+        }
+        if let Some(bft_slots_ratio) = &self.bft_slots_ratio {
+            blockchain_configuration.bft_slots_ratio =
+                BFTSlotsRatio::new(milli(bft_slots_ratio)).expect("invalid BFT slots ratio");
+        }
+        blockchain_configuration.kes_update_speed =
+            KESUpdateSpeed::new(self.kes_update_speed).expect("invalid KES update speed");
 
-        if self.slots_per_epoch.is_some() {
-            genesis_model.blockchain_configuration.slots_per_epoch = self.slots_per_epoch;
+        if let Some(slots_per_epoch) = self.slots_per_epoch {
+            blockchain_configuration.slots_per_epoch =
+                NumberOfSlotsPerEpoch::new(slots_per_epoch).expect("invalid slots per epoch");
         }
-        if self.slot_duration.is_some() {
-            genesis_model.blockchain_configuration.slot_duration = self.slot_duration;
+        if let Some(slot_duration) = self.slot_duration {
+            blockchain_configuration.slot_duration = u8::try_from(slot_duration)
+                .ok()
+                .and_then(SlotDuration::new)
+                .expect("invalid slot duration");
         }
         if self.epoch_stability_depth.is_some() {
-            genesis_model.blockchain_configuration.epoch_stability_depth =
-                self.epoch_stability_depth;
+            blockchain_configuration.epoch_stability_depth = self.epoch_stability_depth;
         }
-
-        genesis_model
-            .blockchain_configuration
-            .consensus_genesis_praos_active_slot_coeff =
-            self.consensus_genesis_praos_active_slot_coeff.clone();
-        genesis_model.blockchain_configuration.linear_fees = self.linear_fees.clone();
-        let certs = self.certs.iter().cloned().map(Initial::Cert);
+        if let Some(active_slot_coeff) = &self.consensus_genesis_praos_active_slot_coeff {
+            blockchain_configuration.consensus_genesis_praos_active_slot_coeff =
+                ActiveSlotCoefficient::new(milli(active_slot_coeff))
+                    .expect("invalid active slot coefficient");
+        }
+        blockchain_configuration.linear_fees = self.linear_fees.clone().into();
+        if self.rewards.is_some() {
+            blockchain_configuration.rewards = self.rewards;
+        }
+        let certs = self
+            .certs
+            .iter()
+            .map(|cert| Initial::Cert(cert.parse().expect("invalid initial certificate")));
         genesis_model.initial.extend(certs);
         let path_to_output_block = build_genesis_block(&genesis_model);
 
         let mut config = JormungandrConfig::from(genesis_model, node_config);
 
-        let secret_model = SecretModel::new_bft(&secret_key);
-        let secret_model_path = SecretModel::serialize(&secret_model);
+        let secret_model = NodeSecret::new_bft(&secret_key);
+        let secret_model_path = NodeSecret::serialize(&secret_model);
 
         config.secret_model = secret_model;
         config.secret_model_path = secret_model_path;
@@ -214,3 +266,18 @@ impl ConfigurationBuilder {
         config
     }
 }
+
+/// a leader ID as printed by jcli
+fn consensus_leader_id(public_key: &str) -> ConsensusLeaderId {
+    Identifier::<Ed25519>::from_bech32_str(public_key)
+        .expect("invalid consensus leader ID")
+        .into()
+}
+
+fn consensus_version(consensus: &str) -> ConsensusVersion {
+    match consensus {
+        "bft" => ConsensusVersion::Bft,
+        "genesis" | "genesis_praos" => ConsensusVersion::GenesisPraos,
+        other => panic!("unknown consensus '{}'", other),
+    }
+}
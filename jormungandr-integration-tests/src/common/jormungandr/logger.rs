@@ -30,6 +30,8 @@ pub enum Level {
 }
 
 const SUCCESFULLY_CREATED_BLOCK_MSG: &str = "block from leader event successfully stored";
+const PANIC_MSG: &str = "panicked at";
+const BACKTRACE_MSG: &str = "stack backtrace:";
 
 // TODO: convert strings to enums for level/task/
 // TODO: convert ts to DateTime
@@ -83,6 +85,25 @@ impl JormungandrLogger {
             .any(|x| self.get_log_content().contains(x)))
     }
 
+    /// the message of the panic of the node, `thread '...' panicked at '...'`
+    pub fn get_panic_message(&self) -> Option<String> {
+        self.get_lines_from_log()
+            .find(|line| line.contains(PANIC_MSG))
+            .map(|line| line.trim().to_owned())
+    }
+
+    /// the frames of the backtrace printed after a panic, with
+    /// `RUST_BACKTRACE` set
+    pub fn get_backtrace(&self) -> Vec<String> {
+        self.get_lines_from_log()
+            .skip_while(|line| !line.contains(BACKTRACE_MSG))
+            .skip(1)
+            .take_while(|line| self.try_parse_line_as_entry(line).is_err())
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
     pub fn print_raw_log(&self) {
         println!("{}", self.get_log_content());
     }
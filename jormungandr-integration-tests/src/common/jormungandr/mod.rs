@@ -1,3 +1,4 @@
+pub mod cluster;
 pub mod commands;
 mod configuration_builder;
 pub mod logger;
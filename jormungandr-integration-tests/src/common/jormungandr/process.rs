@@ -1,7 +1,47 @@
-use super::logger::JormungandrLogger;
-use crate::common::{configuration::jormungandr_config::JormungandrConfig, jcli_wrapper};
-use std::path::PathBuf;
-use std::process::Child;
+extern crate custom_error;
+
+use self::custom_error::custom_error;
+use super::{commands, logger::JormungandrLogger};
+use crate::common::{
+    configuration::jormungandr_config::JormungandrConfig, jcli_wrapper, process_utils,
+};
+use regex::Regex;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+custom_error! {pub SupervisorError
+    Crashed { crash: Crash } = "the node crashed: {crash}",
+    ForbiddenMessage { pattern: String, line: String } = "the log matches the forbidden pattern '{pattern}': {line}",
+    Timeout { pattern: String, timeout: u64 } = "no log matching '{pattern}' after {timeout} s",
+    InvalidPattern { source: regex::Error } = "invalid log pattern",
+}
+
+/// the exit of a node which was not shut down
+#[derive(Debug, Clone)]
+pub struct Crash {
+    pub status: ExitStatus,
+    /// the message of the panic, if the node panicked
+    pub panic: Option<String>,
+    pub backtrace: Vec<String>,
+}
+
+impl fmt::Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.status)?;
+        if let Some(panic) = self.panic.as_ref() {
+            write!(f, ", {}", panic)?;
+        }
+        for frame in self.backtrace.iter() {
+            write!(f, "\n    {}", frame)?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub struct JormungandrProcess {
@@ -9,6 +49,9 @@ pub struct JormungandrProcess {
     pub logger: JormungandrLogger,
     pub config: JormungandrConfig,
     description: String,
+    stdout_file_path: PathBuf,
+    forbidden_patterns: Vec<Regex>,
+    retain_files: bool,
 }
 
 impl JormungandrProcess {
@@ -30,13 +73,19 @@ impl JormungandrProcess {
         JormungandrProcess {
             child: child,
             description: description,
+            stdout_file_path: commands::get_stdout_file_path(&log_file_path),
             logger: JormungandrLogger::new(log_file_path.clone()),
             config: config,
+            forbidden_patterns: Vec::new(),
+            retain_files: false,
         }
     }
 
     pub fn shutdown(&self) {
-        jcli_wrapper::assert_rest_shutdown(&self.config.get_node_address());
+        jcli_wrapper::assert_rest_shutdown(
+            &self.config.get_node_address(),
+            self.config.get_admin_token(),
+        );
     }
 
     pub fn assert_no_errors_in_log(&self) {
@@ -58,14 +107,141 @@ impl JormungandrProcess {
     pub fn config(&self) -> JormungandrConfig {
         self.config.clone()
     }
+
+    /// what the node wrote on its standard output
+    pub fn stdout(&self) -> String {
+        fs::read_to_string(&self.stdout_file_path).unwrap_or_default()
+    }
+
+    /// the exit status of the node, none while it runs
+    pub fn status(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().and_then(|status| status)
+    }
+
+    /// the crash of the node, with its panic and backtrace, none while it runs
+    pub fn crash(&mut self) -> Option<Crash> {
+        let status = self.status()?;
+        Some(Crash {
+            status,
+            panic: self.logger.get_panic_message(),
+            backtrace: self.logger.get_backtrace(),
+        })
+    }
+
+    pub fn assert_not_crashed(&mut self) {
+        if let Some(crash) = self.crash() {
+            panic!("{} crashed: {}", self.description, crash);
+        }
+    }
+
+    /// fail the log checks of the process if a line of the log matches
+    /// `pattern`
+    pub fn forbid_in_log(&mut self, pattern: &str) -> Result<&mut Self, SupervisorError> {
+        self.forbidden_patterns.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// the first line of the log matching a forbidden pattern
+    pub fn check_forbidden_in_log(&self) -> Result<(), SupervisorError> {
+        for line in self.logger.get_lines_from_log() {
+            if let Some(pattern) = self
+                .forbidden_patterns
+                .iter()
+                .find(|pattern| pattern.is_match(&line))
+            {
+                return Err(SupervisorError::ForbiddenMessage {
+                    pattern: pattern.as_str().to_owned(),
+                    line,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn assert_no_forbidden_in_log(&self) {
+        if let Err(e) = self.check_forbidden_in_log() {
+            panic!("{}: {}", self.description, e);
+        }
+    }
+
+    /// wait for a line of the log matching `pattern`, failing as soon as the
+    /// node crashes or logs a forbidden line
+    pub fn wait_for_log(
+        &mut self,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<String, SupervisorError> {
+        let expected = Regex::new(pattern)?;
+        let start = Instant::now();
+        loop {
+            self.check_forbidden_in_log()?;
+            if let Some(line) = self
+                .logger
+                .get_lines_from_log()
+                .find(|line| expected.is_match(line))
+            {
+                return Ok(line);
+            }
+            if let Some(crash) = self.crash() {
+                return Err(SupervisorError::Crashed { crash });
+            }
+            if start.elapsed() > timeout {
+                return Err(SupervisorError::Timeout {
+                    pattern: pattern.to_owned(),
+                    timeout: timeout.as_secs(),
+                });
+            }
+            process_utils::sleep(1);
+        }
+    }
+
+    /// keep the storage and the logs of the node once the process is
+    /// dropped, to restart the node from them
+    pub fn retain_files(&mut self) {
+        self.retain_files = true;
+    }
+
+    /// the temporary directories of the storage and of the logs
+    fn temp_dirs(&self) -> Vec<PathBuf> {
+        let temp_root = std::env::temp_dir();
+        // the paths given by `file_utils::get_path_in_temp` are alone in
+        // their directory
+        let mut dirs: Vec<PathBuf> = self
+            .config
+            .node_config
+            .storage
+            .iter()
+            .chain(Some(&self.logger.log_file_path))
+            .filter_map(|path| path.parent())
+            .filter(|dir| dir.parent() == Some(temp_root.as_path()))
+            .map(Path::to_path_buf)
+            .collect();
+        dirs.dedup();
+        dirs
+    }
 }
 
 impl Drop for JormungandrProcess {
     fn drop(&mut self) {
+        if thread::panicking() {
+            if let Some(crash) = self.crash() {
+                println!("{} crashed: {}", self.description, crash);
+            }
+        }
         self.logger.print_error_and_invalid_logs();
         match self.child.kill() {
             Err(e) => println!("Could not kill {}: {}", self.description, e),
             Ok(_) => println!("Successfully killed {}", self.description),
         }
+        // reap the process, so its ports are free once dropped
+        let _ = self.child.wait();
+
+        if !self.retain_files {
+            for dir in self.temp_dirs() {
+                if let Err(e) = fs::remove_dir_all(&dir) {
+                    println!("Could not remove {:?}: {}", dir, e);
+                }
+            }
+        }
     }
 }
@@ -288,8 +288,9 @@ impl Starter {
     }
 }
 
-pub fn restart_jormungandr_node(process: JormungandrProcess, role: Role) -> JormungandrProcess {
+pub fn restart_jormungandr_node(mut process: JormungandrProcess, role: Role) -> JormungandrProcess {
     let config = process.config.clone();
+    process.retain_files();
     std::mem::drop(process);
 
     Starter::new()
@@ -0,0 +1,514 @@
+//! sustained fragment traffic against a node or a cluster
+//!
+//! The generator owns its wallets, to be funded in the genesis block with
+//! [`LoadGenerator::funds`]. Each wallet sends its transactions to itself, one
+//! at a time: an account wallet spends `value_per_transaction`, a UTxO wallet
+//! spends its whole UTxO, the fees being the only thing the wallets lose. The
+//! transactions are sent at the configured rate, in turn to each of the nodes,
+//! and followed in the fragment logs of the node they were sent to, to measure
+//! how long they take to get in a block and how many fragments the node keeps
+//! pending.
+//!
+//! The soak test `sustained_load_of_accounts_and_utxos` runs it against a
+//! local node, as a benchmark of the hardware running the tests:
+//! `cargo test --features soak-test sustained_load -- --nocapture` prints the
+//! report.
+//!
+//! [`LoadGenerator::funds`]: ./struct.LoadGenerator.html#method.funds
+
+extern crate custom_error;
+
+use self::custom_error::custom_error;
+use crate::common::configuration::genesis_model::Fund;
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::{Ed25519Extended, SecretKey};
+use chain_impl_mockchain::{
+    account::SpendingCounter, fee::LinearFee, fragment::Fragment, key::EitherEd25519SecretKey,
+    transaction::OutputPolicy, value::Value,
+};
+use jormungandr_lib::{
+    client::{self, JormungandrClient},
+    crypto::{account::Identifier, hash::Hash},
+    interfaces,
+    transaction::{self, TransactionBuilder},
+};
+use std::{
+    collections::HashMap,
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+custom_error! {pub LoadError
+    Client { source: client::Error } = "request to the node failed",
+    Transaction { source: transaction::Error } = "cannot build the transaction",
+    NoNodes = "no node to send the transactions to",
+    UtxoNotFound { address: String } = "no UTxO of {address} in the genesis block",
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    /// the transactions sent per second
+    pub tps: u32,
+    /// how long the transactions are sent
+    pub duration: Duration,
+    /// how long to wait for the pending transactions once the sending is over
+    pub drain_timeout: Duration,
+    /// the share of the transactions spending UTxOs, the others spending
+    /// accounts
+    pub utxo_ratio: f64,
+    /// the wallets sending the transactions, each one having at most one
+    /// transaction pending
+    pub wallets: usize,
+    /// the genesis funds of each wallet
+    pub funds: u64,
+    /// the value an account wallet sends to itself
+    pub value_per_transaction: u64,
+    /// how often the fragment logs are polled
+    pub poll_interval: Duration,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            tps: 10,
+            duration: Duration::from_secs(60),
+            drain_timeout: Duration::from_secs(30),
+            utxo_ratio: 0.5,
+            wallets: 100,
+            funds: 1_000_000,
+            value_per_transaction: 1,
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+enum Funds {
+    Account {
+        counter: u32,
+    },
+    Utxo {
+        fragment_id: Hash,
+        index: u8,
+        value: u64,
+    },
+}
+
+struct Wallet {
+    secret_key: EitherEd25519SecretKey,
+    address: Address,
+    funds: Funds,
+    pending: Option<Hash>,
+}
+
+impl Wallet {
+    fn generate(utxo: bool) -> Self {
+        let secret_key = EitherEd25519SecretKey::Extended(SecretKey::<Ed25519Extended>::generate(
+            rand::rngs::OsRng::new().unwrap(),
+        ));
+        let public_key = secret_key.to_public();
+        let (kind, funds) = if utxo {
+            let funds = Funds::Utxo {
+                fragment_id: [0; 32].into(),
+                index: 0,
+                value: 0,
+            };
+            (Kind::Single(public_key), funds)
+        } else {
+            (Kind::Account(public_key), Funds::Account { counter: 0 })
+        };
+        Wallet {
+            secret_key,
+            address: Address(Discrimination::Test, kind),
+            funds,
+            pending: None,
+        }
+    }
+
+    fn is_utxo(&self) -> bool {
+        match self.funds {
+            Funds::Utxo { .. } => true,
+            Funds::Account { .. } => false,
+        }
+    }
+}
+
+/// a transaction waiting to be in a block
+struct Pending {
+    wallet: usize,
+    node: usize,
+    sent_at: Instant,
+    /// the funds of the wallet once the transaction is in a block
+    funds: Funds,
+}
+
+pub struct LoadGenerator {
+    config: LoadConfig,
+    wallets: Vec<Wallet>,
+}
+
+impl LoadGenerator {
+    pub fn new(config: LoadConfig) -> Self {
+        let utxo_wallets = (config.wallets as f64 * config.utxo_ratio).round() as usize;
+        let wallets = (0..config.wallets)
+            .map(|index| Wallet::generate(index < utxo_wallets))
+            .collect();
+        LoadGenerator { config, wallets }
+    }
+
+    /// the initial funds of the wallets, for the genesis block
+    pub fn funds(&self) -> Vec<Fund> {
+        self.wallets
+            .iter()
+            .map(|wallet| Fund {
+                address: interfaces::Address::from(wallet.address.clone()).to_string(),
+                value: self.config.funds.into(),
+            })
+            .collect()
+    }
+
+    /// send the transactions to the nodes, started from the genesis block
+    /// `block0_hash` with the funds of the wallets
+    pub fn run(
+        &mut self,
+        nodes: &[JormungandrClient],
+        block0_hash: &Hash,
+    ) -> Result<LoadReport, LoadError> {
+        let first_node = nodes.first().ok_or(LoadError::NoNodes)?;
+        let fees = first_node.settings()?.fees;
+        self.find_utxos(first_node, block0_hash)?;
+
+        let mut run = Run {
+            wallets: &mut self.wallets,
+            nodes,
+            block0_hash: block0_hash.clone(),
+            fees,
+            config: &self.config,
+            pending: HashMap::new(),
+            report: LoadReport::default(),
+            cursors: [0, 0],
+        };
+
+        let interval = Duration::from_secs(1) / self.config.tps.max(1);
+        let start = Instant::now();
+        let end = start + self.config.duration;
+        let drain_end = end + self.config.drain_timeout;
+        let mut next_send = start;
+        let mut next_poll = start;
+        loop {
+            let now = Instant::now();
+            if now >= end && (run.pending.is_empty() || now >= drain_end) {
+                break;
+            }
+            if now >= next_poll {
+                run.poll()?;
+                next_poll = now + self.config.poll_interval;
+            }
+            if now < end && now >= next_send {
+                run.send_next();
+                next_send += interval;
+                // do not burst to catch up after a slow request
+                if next_send + Duration::from_secs(1) < now {
+                    next_send = now;
+                }
+            }
+            let next = if now < end {
+                next_send.min(next_poll)
+            } else {
+                next_poll
+            };
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            }
+        }
+
+        let mut report = run.report;
+        report.pending = run.pending.len();
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    /// the UTxOs of the wallets in the genesis block
+    fn find_utxos(
+        &mut self,
+        node: &JormungandrClient,
+        block0_hash: &Hash,
+    ) -> Result<(), LoadError> {
+        let block0 = node.block(block0_hash)?;
+        let mut utxos = Vec::new();
+        for fragment in block0.contents.iter() {
+            if let Fragment::Transaction(tx) = fragment {
+                let fragment_id: Hash = fragment.id().into();
+                for (index, output) in tx.as_slice().outputs().iter().enumerate() {
+                    utxos.push((
+                        output.address.clone(),
+                        fragment_id,
+                        index as u8,
+                        output.value.0,
+                    ));
+                }
+            }
+        }
+        for wallet in self.wallets.iter_mut().filter(|wallet| wallet.is_utxo()) {
+            let (_, fragment_id, index, value) = utxos
+                .iter()
+                .find(|(address, _, _, _)| *address == wallet.address)
+                .cloned()
+                .ok_or_else(|| LoadError::UtxoNotFound {
+                    address: interfaces::Address::from(wallet.address.clone()).to_string(),
+                })?;
+            wallet.funds = Funds::Utxo {
+                fragment_id,
+                index,
+                value,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// the state of a running load
+struct Run<'a> {
+    wallets: &'a mut Vec<Wallet>,
+    nodes: &'a [JormungandrClient],
+    block0_hash: Hash,
+    fees: LinearFee,
+    config: &'a LoadConfig,
+    pending: HashMap<Hash, Pending>,
+    report: LoadReport,
+    /// where to look for the next idle wallet, for the accounts and the UTxOs
+    cursors: [usize; 2],
+}
+
+impl<'a> Run<'a> {
+    /// the fee of a transaction of one input and one output
+    fn fee(&self) -> u64 {
+        self.fees.constant + 2 * self.fees.coefficient
+    }
+
+    fn send_next(&mut self) {
+        let utxo = rand::random::<f64>() < self.config.utxo_ratio;
+        let wallet = match self.next_idle_wallet(utxo) {
+            Some(wallet) => wallet,
+            None => {
+                self.report.skipped += 1;
+                return;
+            }
+        };
+        let node = self.report.sent as usize % self.nodes.len();
+        let sent = self
+            .build(wallet)
+            .map_err(LoadError::from)
+            .and_then(|(fragment, funds)| {
+                self.nodes[node].send_fragment(&fragment)?;
+                Ok((fragment.id().into(), funds))
+            });
+        match sent {
+            Ok((fragment_id, funds)) => {
+                self.report.sent += 1;
+                self.wallets[wallet].pending = Some(fragment_id);
+                self.pending.insert(
+                    fragment_id,
+                    Pending {
+                        wallet,
+                        node,
+                        sent_at: Instant::now(),
+                        funds,
+                    },
+                );
+            }
+            Err(e) => {
+                println!("cannot send a transaction: {}", e);
+                self.report.send_errors += 1;
+            }
+        }
+    }
+
+    fn next_idle_wallet(&mut self, utxo: bool) -> Option<usize> {
+        let cursor = &mut self.cursors[utxo as usize];
+        let count = self.wallets.len();
+        for offset in 0..count {
+            let index = (*cursor + offset) % count;
+            let wallet = &self.wallets[index];
+            if wallet.is_utxo() == utxo && wallet.pending.is_none() {
+                *cursor = index + 1;
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// the transaction of the wallet to itself, with the funds of the wallet
+    /// once the transaction is in a block
+    fn build(&self, wallet: usize) -> Result<(Fragment, Funds), transaction::Error> {
+        let wallet = &self.wallets[wallet];
+        let fee = self.fee();
+        let block0_hash = self.block0_hash.into_hash();
+        let mut builder = TransactionBuilder::new();
+        match wallet.funds {
+            Funds::Account { counter } => {
+                let value = self.config.value_per_transaction;
+                builder
+                    .add_account_input(&wallet.address, Value(value + fee))?
+                    .add_output(wallet.address.clone(), Value(value));
+                let (mut witnessing, _) = builder.finalize(&self.fees, OutputPolicy::Forget)?;
+                witnessing.witness_account(
+                    &block0_hash,
+                    SpendingCounter::from(counter),
+                    &wallet.secret_key,
+                )?;
+                let fragment = witnessing.seal()?.fragment()?;
+                let funds = Funds::Account {
+                    counter: counter + 1,
+                };
+                Ok((fragment, funds))
+            }
+            Funds::Utxo {
+                fragment_id,
+                index,
+                value,
+            } => {
+                let output_value = value.saturating_sub(fee);
+                builder
+                    .add_utxo_input(fragment_id, index, Value(value))
+                    .add_output(wallet.address.clone(), Value(output_value));
+                let (mut witnessing, _) = builder.finalize(&self.fees, OutputPolicy::Forget)?;
+                witnessing.witness_utxo(&block0_hash, &wallet.secret_key)?;
+                let fragment = witnessing.seal()?.fragment()?;
+                let funds = Funds::Utxo {
+                    fragment_id: fragment.id().into(),
+                    index: 0,
+                    value: output_value,
+                };
+                Ok((fragment, funds))
+            }
+        }
+    }
+
+    /// follow the pending transactions in the fragment logs of the nodes
+    fn poll(&mut self) -> Result<(), LoadError> {
+        let nodes = self.nodes;
+        for (node, client) in nodes.iter().enumerate() {
+            let logs = client.fragment_logs()?;
+            self.report
+                .mempool_sizes
+                .push(logs.iter().filter(|log| log.is_pending()).count());
+
+            for log in logs.iter().filter(|log| !log.is_pending()) {
+                let is_ours = self
+                    .pending
+                    .get(log.fragment_id())
+                    .map_or(false, |pending| pending.node == node);
+                if !is_ours {
+                    continue;
+                }
+                let pending = self.pending.remove(log.fragment_id()).unwrap();
+                let index = pending.wallet;
+                self.wallets[index].pending = None;
+                if log.is_in_a_block() {
+                    self.report.in_block += 1;
+                    self.report.latencies.push(pending.sent_at.elapsed());
+                    self.wallets[index].funds = pending.funds;
+                } else {
+                    self.report.rejected += 1;
+                    self.resync(index, client)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the counter of an account after a rejected transaction
+    fn resync(&mut self, wallet: usize, client: &JormungandrClient) -> Result<(), LoadError> {
+        let wallet = &mut self.wallets[wallet];
+        if let Kind::Account(public_key) = wallet.address.kind() {
+            let state = client.account_state(&Identifier::from(public_key.clone()))?;
+            wallet.funds = Funds::Account {
+                counter: state.counter(),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub duration: Duration,
+    pub sent: u64,
+    pub in_block: u64,
+    pub rejected: u64,
+    /// still pending at the end of the run
+    pub pending: usize,
+    /// not sent because all the wallets of the kind had a transaction pending
+    pub skipped: u64,
+    pub send_errors: u64,
+    /// from the sending to the polling of the fragment log in a block
+    pub latencies: Vec<Duration>,
+    /// the number of pending fragments of a node, at each poll
+    pub mempool_sizes: Vec<usize>,
+}
+
+impl LoadReport {
+    /// the transactions in a block per second
+    pub fn tps(&self) -> f64 {
+        self.in_block as f64 / (self.duration.as_millis() as f64 / 1000.0).max(1.0)
+    }
+
+    /// the latency under which `percent` of the transactions got in a block
+    pub fn latency_percentile(&self, percent: f64) -> Option<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let last = latencies.len().checked_sub(1)?;
+        let index = ((last as f64) * percent / 100.0).round() as usize;
+        latencies.get(index.min(last)).cloned()
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        Some(self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32)
+    }
+
+    pub fn max_mempool_size(&self) -> usize {
+        self.mempool_sizes.iter().cloned().max().unwrap_or(0)
+    }
+
+    pub fn mean_mempool_size(&self) -> f64 {
+        if self.mempool_sizes.is_empty() {
+            return 0.0;
+        }
+        self.mempool_sizes.iter().sum::<usize>() as f64 / self.mempool_sizes.len() as f64
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "duration:        {:?}", self.duration)?;
+        writeln!(
+            f,
+            "transactions:    {} sent, {} in a block, {} rejected, {} pending",
+            self.sent, self.in_block, self.rejected, self.pending
+        )?;
+        writeln!(
+            f,
+            "not sent:        {} with no idle wallet, {} errors",
+            self.skipped, self.send_errors
+        )?;
+        writeln!(f, "throughput:      {:.2} tx/s", self.tps())?;
+        writeln!(
+            f,
+            "latency:         mean {:?}, p50 {:?}, p95 {:?}, max {:?}",
+            self.mean_latency(),
+            self.latency_percentile(50.0),
+            self.latency_percentile(95.0),
+            self.latency_percentile(100.0)
+        )?;
+        write!(
+            f,
+            "mempool:         mean {:.1}, max {} pending fragments",
+            self.mean_mempool_size(),
+            self.max_mempool_size()
+        )
+    }
+}
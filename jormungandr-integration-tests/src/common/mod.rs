@@ -8,6 +8,9 @@ pub mod file_assert;
 pub mod file_utils;
 pub mod jcli_wrapper;
 pub mod jormungandr;
+pub mod load_generator;
+pub mod network_faults;
 pub mod process_assert;
 pub mod process_utils;
+pub mod scenario;
 pub mod startup;
@@ -0,0 +1,466 @@
+//! fault injection between the nodes of a test cluster
+//!
+//! Each node gets a TCP proxy in front of its P2P port, the node advertising
+//! the address of its proxy, so the connections between the nodes go through
+//! the proxies. The faults are applied to the data relayed from one node to
+//! another:
+//!
+//! * the latency delays each chunk of data;
+//! * the drop rate is the probability for a chunk to be lost. TCP does not
+//!   lose data, so the connection is closed instead, as it would be by a peer
+//!   after the loss;
+//! * the partition cuts the connections between the nodes of different
+//!   groups and refuses the new ones, until the network is healed.
+//!
+//! The proxy finds the node connecting to it from the process owning the
+//! socket, on Linux. Elsewhere, or for unknown processes, the source of a
+//! connection is unknown and a partition cuts the connections to all the
+//! nodes of a group.
+
+use crate::common::configuration::get_available_port;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const LOCALHOST: &str = "127.0.0.1";
+
+/// how often the idle connections check the faults
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkFaults {
+    pub latency: Duration,
+    /// probability in `[0, 1]` of losing a chunk of data
+    pub drop_rate: f64,
+}
+
+/// the faults of the network of a cluster, shared by the proxies of the nodes
+#[derive(Clone, Default)]
+pub struct FaultyNetwork(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    /// the faults of the links without their own faults
+    faults: LinkFaults,
+    /// the faults from a node to another
+    links: HashMap<(String, String), LinkFaults>,
+    partition: Option<Vec<Vec<String>>>,
+    /// the node of each process
+    processes: HashMap<u32, String>,
+}
+
+impl FaultyNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// start the proxy of the node `alias` listening on `target`
+    pub fn proxy(&self, alias: &str, target: SocketAddr) -> Proxy {
+        Proxy::start(self.clone(), alias.to_owned(), target)
+    }
+
+    /// tell the proxies the node `alias` is run by the process `pid`
+    pub fn register_process(&self, alias: &str, pid: u32) {
+        let mut inner = self.0.lock().unwrap();
+        inner.processes.insert(pid, alias.to_owned());
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.0.lock().unwrap().faults.latency = latency;
+    }
+
+    pub fn set_drop_rate(&self, drop_rate: f64) {
+        self.0.lock().unwrap().faults.drop_rate = drop_rate;
+    }
+
+    /// the faults of the data sent by the node `from` to the node `to`
+    pub fn set_link_faults(&self, from: &str, to: &str, faults: LinkFaults) {
+        let mut inner = self.0.lock().unwrap();
+        inner.links.insert((from.to_owned(), to.to_owned()), faults);
+    }
+
+    /// split the nodes in groups, the nodes not listed are not partitioned
+    pub fn partition(&self, groups: &[&[&str]]) {
+        let groups = groups
+            .iter()
+            .map(|group| group.iter().map(|alias| alias.to_string()).collect())
+            .collect();
+        self.0.lock().unwrap().partition = Some(groups);
+    }
+
+    /// remove the partition and all the faults
+    pub fn heal(&self) {
+        let mut inner = self.0.lock().unwrap();
+        inner.faults = LinkFaults::default();
+        inner.links.clear();
+        inner.partition = None;
+    }
+
+    fn source_of(&self, pid: Option<u32>) -> Option<String> {
+        let inner = self.0.lock().unwrap();
+        pid.and_then(|pid| inner.processes.get(&pid).cloned())
+    }
+
+    fn known_pids(&self) -> Vec<u32> {
+        self.0.lock().unwrap().processes.keys().cloned().collect()
+    }
+
+    /// the faults of the link, none if the link is cut
+    fn link_faults(&self, from: Option<&str>, to: &str) -> Option<LinkFaults> {
+        let inner = self.0.lock().unwrap();
+        if let Some(groups) = inner.partition.as_ref() {
+            let group_of = |alias: &str| {
+                groups
+                    .iter()
+                    .position(|group| group.iter().any(|a| a == alias))
+            };
+            match (from.map(group_of), group_of(to)) {
+                (Some(Some(from_group)), Some(to_group)) if from_group != to_group => return None,
+                (None, Some(_)) => return None,
+                _ => {}
+            }
+        }
+        let faults = from
+            .and_then(|from| inner.links.get(&(from.to_owned(), to.to_owned())))
+            .cloned()
+            .unwrap_or(inner.faults);
+        Some(faults)
+    }
+}
+
+/// the proxy in front of the P2P port of a node, stopped when dropped
+pub struct Proxy {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    /// the thread accepting the connections, which joins the threads
+    /// relaying them once stopped
+    accept: Option<thread::JoinHandle<()>>,
+}
+
+impl Proxy {
+    fn start(network: FaultyNetwork, alias: String, target: SocketAddr) -> Self {
+        let address: SocketAddr = format!("{}:{}", LOCALHOST, get_available_port())
+            .parse()
+            .unwrap();
+        let listener = TcpListener::bind(address).expect("cannot bind the proxy");
+        listener
+            .set_nonblocking(true)
+            .expect("cannot set the proxy non-blocking");
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_stop = stop.clone();
+        let accept = thread::spawn(move || {
+            let mut relays = Vec::new();
+            while !accept_stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((inbound, peer)) => {
+                        let source =
+                            network.source_of(process_of(peer, address, &network.known_pids()));
+                        relays.extend(relay(
+                            network.clone(),
+                            inbound,
+                            target,
+                            source,
+                            alias.clone(),
+                            accept_stop.clone(),
+                        ));
+                        relays.retain(|relay: &Relay| !relay.done.load(Ordering::SeqCst));
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL)
+                    }
+                    Err(e) => {
+                        println!("proxy of {} cannot accept connections: {}", alias, e);
+                        break;
+                    }
+                }
+            }
+            for relay in relays {
+                let _ = relay.thread.join();
+            }
+        });
+        Proxy {
+            address,
+            stop,
+            accept: Some(accept),
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// the P2P address the node advertises
+    pub fn multiaddr(&self) -> String {
+        format!("/ip4/{}/tcp/{}", self.address.ip(), self.address.port())
+    }
+}
+
+impl Drop for Proxy {
+    /// stop the proxy and wait for its connections to be closed, so no
+    /// thread of the proxy outlives it
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+/// a thread relaying a connection in one direction
+struct Relay {
+    thread: thread::JoinHandle<()>,
+    done: Arc<AtomicBool>,
+}
+
+impl Relay {
+    fn spawn<F>(f: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let done = Arc::new(AtomicBool::new(false));
+        let thread_done = done.clone();
+        let thread = thread::spawn(move || {
+            f();
+            thread_done.store(true, Ordering::SeqCst);
+        });
+        Relay { thread, done }
+    }
+}
+
+/// relay the connection to the node in both directions, until it is closed
+/// or the proxy is stopped
+fn relay(
+    network: FaultyNetwork,
+    inbound: TcpStream,
+    target: SocketAddr,
+    source: Option<String>,
+    alias: String,
+    stop: Arc<AtomicBool>,
+) -> Vec<Relay> {
+    if network
+        .link_faults(source.as_ref().map(String::as_str), &alias)
+        .is_none()
+    {
+        let _ = inbound.shutdown(Shutdown::Both);
+        return Vec::new();
+    }
+    let outbound = match TcpStream::connect(target) {
+        Ok(outbound) => outbound,
+        Err(_) => {
+            let _ = inbound.shutdown(Shutdown::Both);
+            return Vec::new();
+        }
+    };
+    let (inbound_copy, outbound_copy) = match (inbound.try_clone(), outbound.try_clone()) {
+        (Ok(inbound_copy), Ok(outbound_copy)) => (inbound_copy, outbound_copy),
+        _ => return Vec::new(),
+    };
+    let reverse_network = network.clone();
+    let reverse_source = source.clone();
+    let reverse_alias = alias.clone();
+    let reverse_stop = stop.clone();
+    // the link from the source to the node, and the link back
+    vec![
+        Relay::spawn(move || {
+            pipe(inbound, outbound, &stop, move || {
+                network.link_faults(source.as_ref().map(String::as_str), &alias)
+            })
+        }),
+        Relay::spawn(move || {
+            pipe(
+                outbound_copy,
+                inbound_copy,
+                &reverse_stop,
+                move || match reverse_source.as_ref() {
+                    Some(source) => reverse_network.link_faults(Some(&reverse_alias), source),
+                    None => reverse_network.link_faults(None, &reverse_alias),
+                },
+            )
+        }),
+    ]
+}
+
+fn pipe<F>(mut from: TcpStream, mut to: TcpStream, stop: &AtomicBool, faults: F)
+where
+    F: Fn() -> Option<LinkFaults>,
+{
+    let _ = from.set_read_timeout(Some(POLL_INTERVAL));
+    let mut buffer = [0; BUFFER_SIZE];
+    while !stop.load(Ordering::SeqCst) {
+        let size = match from.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                if faults().is_none() {
+                    break;
+                }
+                continue;
+            }
+            Err(_) => break,
+        };
+        let faults = match faults() {
+            Some(faults) => faults,
+            None => break,
+        };
+        if faults.latency > Duration::from_secs(0) {
+            thread::sleep(faults.latency);
+        }
+        if faults.drop_rate > 0.0 && rand::random::<f64>() < faults.drop_rate {
+            break;
+        }
+        if to.write_all(&buffer[..size]).is_err() {
+            break;
+        }
+    }
+    let _ = from.shutdown(Shutdown::Both);
+    let _ = to.shutdown(Shutdown::Both);
+}
+
+/// the process among `pids` connected from `peer` to `local`
+#[cfg(target_os = "linux")]
+fn process_of(peer: SocketAddr, local: SocketAddr, pids: &[u32]) -> Option<u32> {
+    use std::fs;
+
+    let inode = socket_inode(peer, local)?;
+    let socket = format!("socket:[{}]", inode);
+    pids.iter().cloned().find(|pid| {
+        fs::read_dir(format!("/proc/{}/fd", pid))
+            .map(|fds| {
+                fds.filter_map(Result::ok).any(|fd| {
+                    fs::read_link(fd.path())
+                        .map(|link| link.to_string_lossy() == socket)
+                        .unwrap_or(false)
+                })
+            })
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_of(_peer: SocketAddr, _local: SocketAddr, _pids: &[u32]) -> Option<u32> {
+    None
+}
+
+/// the inode of the client socket connected from `peer` to `local`
+#[cfg(target_os = "linux")]
+fn socket_inode(peer: SocketAddr, local: SocketAddr) -> Option<String> {
+    let table = std::fs::read_to_string("/proc/net/tcp").ok()?;
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 9
+            && parse_proc_address(fields[1]) == Some(peer)
+            && parse_proc_address(fields[2]) == Some(local)
+        {
+            Some(fields[9].to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// the addresses of `/proc/net/tcp`, `0100007F:1F90` for `127.0.0.1:8080`
+#[cfg(target_os = "linux")]
+fn parse_proc_address(address: &str) -> Option<SocketAddr> {
+    let mut parts = address.split(':');
+    let ip = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let port = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let ip = std::net::Ipv4Addr::from(ip.to_ne_bytes());
+    Some(SocketAddr::new(ip.into(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a server echoing the data of its connections
+    fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind((LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                thread::spawn(move || {
+                    let mut reader = stream.try_clone().unwrap();
+                    let _ = io::copy(&mut reader, &mut stream);
+                });
+            }
+        });
+        address
+    }
+
+    fn connect(proxy: &Proxy) -> TcpStream {
+        let stream = TcpStream::connect(proxy.address()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    fn ping(stream: &mut TcpStream) {
+        stream.write_all(b"ping").unwrap();
+        let mut echo = [0; 4];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"ping");
+    }
+
+    fn assert_closed(stream: &mut TcpStream) {
+        let mut buffer = [0; 4];
+        match stream.read(&mut buffer) {
+            Ok(0) => {}
+            Err(ref e)
+                if e.kind() != io::ErrorKind::WouldBlock && e.kind() != io::ErrorKind::TimedOut => {
+            }
+            other => panic!("the connection is still open: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn data_is_relayed_to_the_node() {
+        let network = FaultyNetwork::new();
+        let proxy = network.proxy("node", echo_server());
+        ping(&mut connect(&proxy));
+    }
+
+    #[test]
+    fn dropped_proxy_closes_its_connections() {
+        let network = FaultyNetwork::new();
+        let proxy = network.proxy("node", echo_server());
+        let address = proxy.address();
+        let mut stream = connect(&proxy);
+        ping(&mut stream);
+
+        drop(proxy);
+        assert_closed(&mut stream);
+        assert!(TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn partition_refuses_the_connections_of_unknown_sources_until_healed() {
+        let network = FaultyNetwork::new();
+        let proxy = network.proxy("node", echo_server());
+        let mut stream = connect(&proxy);
+        ping(&mut stream);
+
+        network.partition(&[&["other"], &["node"]]);
+        assert_closed(&mut stream);
+        assert_closed(&mut connect(&proxy));
+
+        network.heal();
+        ping(&mut connect(&proxy));
+    }
+}
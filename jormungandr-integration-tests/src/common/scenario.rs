@@ -0,0 +1,462 @@
+//! declarative end-to-end scenarios
+//!
+//! A scenario names its wallets and stake pools, lists the steps to run, and
+//! runs them in order against a cluster started from a genesis block funding
+//! the wallets. The steps send their transactions to the first node of the
+//! cluster and panic when an assertion fails, like the other `assert_`
+//! helpers.
+//!
+//! ```ignore
+//! ScenarioBuilder::new()
+//!     .with_wallet("alice", 1_000_000)
+//!     .with_wallet("bob", 0)
+//!     .send("alice", "bob", 1_000)
+//!     .wait_for_blocks(2)
+//!     .assert_balance("bob", 1_000)
+//!     .run();
+//! ```
+//!
+//! The rewards of a scenario are drawn from the reward pot of its genesis
+//! block at the start of each epoch:
+//!
+//! ```ignore
+//! ScenarioBuilder::new()
+//!     .with_slots_per_epoch(10)
+//!     .with_rewards(1_000_000)
+//!     .with_wallet("alice", 1_000_000)
+//!     .create_stake_pool("alice", "pool")
+//!     .delegate("alice", "pool")
+//!     .wait_for_rewards("alice")
+//!     .run();
+//! ```
+
+use crate::common::{
+    configuration::genesis_model::{Fund, LinearFees},
+    data::address::Account,
+    file_utils,
+    jcli_wrapper::{
+        self, certificate::wrapper::JCLICertificateWrapper,
+        jcli_transaction_wrapper::JCLITransactionWrapper,
+    },
+    jormungandr::cluster::{Cluster, ClusterBuilder, ClusterNode},
+    process_utils::{self, Wait},
+    startup,
+};
+use chain_crypto::{Curve25519_2HashDH, SumEd25519_12};
+use chain_impl_mockchain::value::Value as StdValue;
+use jormungandr_lib::{crypto::hash::Hash, interfaces::Value};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// send `value` from the account of a wallet to another, the sender
+    /// paying the fees
+    Send {
+        from: String,
+        to: String,
+        value: u64,
+    },
+    /// register a stake pool owned by a wallet
+    CreateStakePool {
+        owner: String,
+        pool: String,
+    },
+    /// delegate the stake of a wallet to a stake pool
+    Delegate {
+        wallet: String,
+        pool: String,
+    },
+    /// wait until the nodes are synced `blocks` blocks above the current tip
+    WaitForBlocks(u32),
+    WaitForSync,
+    AssertBalance {
+        wallet: String,
+        value: u64,
+    },
+    /// the balance of the wallet is strictly greater than `value`
+    AssertBalanceAbove {
+        wallet: String,
+        value: u64,
+    },
+    AssertDelegated {
+        wallet: String,
+        pool: String,
+    },
+    /// wait until the account of the wallet has received rewards
+    WaitForRewards {
+        wallet: String,
+    },
+}
+
+pub struct ScenarioBuilder {
+    cluster: ClusterBuilder,
+    wallets: Vec<(String, u64)>,
+    steps: Vec<Step>,
+    wait: Wait,
+    timeout: Duration,
+}
+
+impl ScenarioBuilder {
+    pub fn new() -> Self {
+        ScenarioBuilder {
+            cluster: ClusterBuilder::new(1),
+            wallets: Vec::new(),
+            steps: Vec::new(),
+            wait: Wait::new(Duration::from_secs(1), 30),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// the nodes of the cluster, the first `leaders` being BFT leaders
+    pub fn with_nodes(&mut self, nodes: usize, leaders: usize) -> &mut Self {
+        self.cluster.with_nodes(nodes).with_leaders(leaders);
+        self
+    }
+
+    pub fn with_slots_per_epoch(&mut self, slots_per_epoch: u32) -> &mut Self {
+        self.cluster.with_slots_per_epoch(slots_per_epoch);
+        self
+    }
+
+    pub fn with_linear_fees(&mut self, fees: LinearFees) -> &mut Self {
+        self.cluster.with_linear_fees(fees);
+        self
+    }
+
+    /// the value of the reward pot in the genesis block
+    pub fn with_rewards(&mut self, rewards: u64) -> &mut Self {
+        self.cluster.with_rewards(rewards);
+        self
+    }
+
+    /// an account wallet, with `funds` in the genesis block
+    pub fn with_wallet(&mut self, alias: &str, funds: u64) -> &mut Self {
+        self.wallets.push((alias.to_owned(), funds));
+        self
+    }
+
+    /// how long the steps wait for the transactions and the blocks
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self.wait = timeout.into();
+        self
+    }
+
+    pub fn step(&mut self, step: Step) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn send(&mut self, from: &str, to: &str, value: u64) -> &mut Self {
+        self.step(Step::Send {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            value,
+        })
+    }
+
+    pub fn create_stake_pool(&mut self, owner: &str, pool: &str) -> &mut Self {
+        self.step(Step::CreateStakePool {
+            owner: owner.to_owned(),
+            pool: pool.to_owned(),
+        })
+    }
+
+    pub fn delegate(&mut self, wallet: &str, pool: &str) -> &mut Self {
+        self.step(Step::Delegate {
+            wallet: wallet.to_owned(),
+            pool: pool.to_owned(),
+        })
+    }
+
+    pub fn wait_for_blocks(&mut self, blocks: u32) -> &mut Self {
+        self.step(Step::WaitForBlocks(blocks))
+    }
+
+    pub fn wait_for_sync(&mut self) -> &mut Self {
+        self.step(Step::WaitForSync)
+    }
+
+    pub fn assert_balance(&mut self, wallet: &str, value: u64) -> &mut Self {
+        self.step(Step::AssertBalance {
+            wallet: wallet.to_owned(),
+            value,
+        })
+    }
+
+    pub fn assert_balance_above(&mut self, wallet: &str, value: u64) -> &mut Self {
+        self.step(Step::AssertBalanceAbove {
+            wallet: wallet.to_owned(),
+            value,
+        })
+    }
+
+    pub fn assert_delegated(&mut self, wallet: &str, pool: &str) -> &mut Self {
+        self.step(Step::AssertDelegated {
+            wallet: wallet.to_owned(),
+            pool: pool.to_owned(),
+        })
+    }
+
+    pub fn wait_for_rewards(&mut self, wallet: &str) -> &mut Self {
+        self.step(Step::WaitForRewards {
+            wallet: wallet.to_owned(),
+        })
+    }
+
+    /// start the cluster and run the steps, returning the scenario to check
+    /// its state further
+    pub fn run(&mut self) -> Scenario {
+        let wallets: HashMap<String, Account> = self
+            .wallets
+            .iter()
+            .map(|(alias, _)| (alias.clone(), startup::create_new_account_address()))
+            .collect();
+        let funds = self
+            .wallets
+            .iter()
+            .filter(|(_, funds)| *funds > 0)
+            .map(|(alias, funds)| Fund {
+                value: (*funds).into(),
+                address: wallets[alias].address.clone(),
+            })
+            .collect();
+        let cluster = self
+            .cluster
+            .with_funds(funds)
+            .start()
+            .expect("cannot start the cluster of the scenario");
+
+        let mut scenario = Scenario {
+            cluster,
+            wallets,
+            pools: HashMap::new(),
+            wait: self.wait.clone(),
+            timeout: self.timeout,
+        };
+        for step in self.steps.iter() {
+            println!("scenario step: {:?}", step);
+            scenario.run_step(step);
+        }
+        scenario
+    }
+}
+
+pub struct Scenario {
+    cluster: Cluster,
+    wallets: HashMap<String, Account>,
+    /// the stake pool IDs
+    pools: HashMap<String, String>,
+    wait: Wait,
+    timeout: Duration,
+}
+
+impl Scenario {
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+
+    pub fn wallet(&self, alias: &str) -> &Account {
+        self.wallets
+            .get(alias)
+            .unwrap_or_else(|| panic!("no wallet {} in the scenario", alias))
+    }
+
+    pub fn stake_pool_id(&self, alias: &str) -> &str {
+        self.pools
+            .get(alias)
+            .unwrap_or_else(|| panic!("no stake pool {} in the scenario", alias))
+    }
+
+    pub fn balance(&self, wallet: &str) -> u64 {
+        let address = &self.wallet(wallet).address;
+        let state = jcli_wrapper::assert_rest_account_get_stats(address, &self.rest_address());
+        StdValue::from(*state.value()).0
+    }
+
+    /// the sum of the rewards received by the account of the wallet
+    pub fn rewards(&self, wallet: &str) -> u64 {
+        let address = &self.wallet(wallet).address;
+        jcli_wrapper::assert_rest_account_get_rewards(address, &self.rest_address())
+            .iter()
+            .map(|reward| StdValue::from(reward.value).0)
+            .sum()
+    }
+
+    pub fn run_step(&mut self, step: &Step) {
+        match step {
+            Step::Send { from, to, value } => self.send(from, to, *value),
+            Step::CreateStakePool { owner, pool } => self.create_stake_pool(owner, pool),
+            Step::Delegate { wallet, pool } => self.delegate(wallet, pool),
+            Step::WaitForBlocks(blocks) => self.wait_for_blocks(*blocks),
+            Step::WaitForSync => self
+                .cluster
+                .wait_for_sync(self.timeout)
+                .expect("the nodes of the scenario do not sync"),
+            Step::AssertBalance { wallet, value } => assert_eq!(
+                self.balance(wallet),
+                *value,
+                "wrong balance of the wallet {}",
+                wallet
+            ),
+            Step::AssertBalanceAbove { wallet, value } => {
+                let balance = self.balance(wallet);
+                assert!(
+                    balance > *value,
+                    "the balance {} of the wallet {} is not above {}",
+                    balance,
+                    wallet,
+                    value
+                );
+            }
+            Step::AssertDelegated { wallet, pool } => {
+                let address = &self.wallet(wallet).address;
+                let pool_id = self.stake_pool_id(pool).to_owned();
+                let state =
+                    jcli_wrapper::assert_rest_account_get_stats(address, &self.rest_address());
+                let pool_id = Hash::from_str(&pool_id).unwrap();
+                assert!(
+                    state
+                        .delegation()
+                        .pools()
+                        .iter()
+                        .any(|(hash, _)| *hash == pool_id),
+                    "the wallet {} is not delegated to the stake pool {}",
+                    wallet,
+                    pool
+                );
+            }
+            Step::WaitForRewards { wallet } => self.wait_for_rewards(wallet),
+        }
+    }
+
+    fn entry_node(&self) -> &ClusterNode {
+        &self.cluster.nodes()[0]
+    }
+
+    fn rest_address(&self) -> String {
+        self.entry_node().process().rest_address()
+    }
+
+    fn fees(&self) -> LinearFees {
+        jcli_wrapper::assert_get_rest_settings(&self.rest_address())
+            .fees
+            .into()
+    }
+
+    fn send(&mut self, from: &str, to: &str, value: u64) {
+        let fees = self.fees();
+        // one input and one output
+        let fee = fees.constant + 2 * fees.coefficient;
+        let receiver = self.wallet(to).address.clone();
+        let sender = self.wallet(from).clone();
+
+        let transaction =
+            JCLITransactionWrapper::new_transaction(self.cluster.genesis_block_hash())
+                .assert_add_account(&sender.address, &Value::from(value + fee))
+                .assert_add_output(&receiver, &Value::from(value))
+                .assert_finalize_with_fee(&sender.address, &fees)
+                .seal_with_witness_for_address(&sender)
+                .assert_to_message();
+        self.post(from, &transaction);
+    }
+
+    fn create_stake_pool(&mut self, owner: &str, pool: &str) {
+        let account = self.wallet(owner).clone();
+        let kes = startup::create_new_key_pair::<Curve25519_2HashDH>();
+        let vrf = startup::create_new_key_pair::<SumEd25519_12>();
+
+        let certificate_wrapper = JCLICertificateWrapper::new();
+        let certificate = certificate_wrapper.assert_new_stake_pool_registration(
+            &vrf.identifier().to_bech32_str(),
+            &format!("{}", self.pools.len() + 1),
+            &kes.identifier().to_bech32_str(),
+            0u32,
+            1u32,
+            &account.public_key,
+        );
+        let certificate_file = file_utils::create_file_in_temp("stake_pool.cert", &certificate);
+
+        self.post_certificate(owner, &certificate);
+        let pool_id = certificate_wrapper.assert_get_stake_pool_id(&certificate_file);
+        assert!(
+            jcli_wrapper::assert_rest_get_stake_pools(&self.rest_address()).contains(&pool_id),
+            "cannot find the stake pool {} in the blockchain",
+            pool
+        );
+        self.pools.insert(pool.to_owned(), pool_id);
+    }
+
+    fn delegate(&mut self, wallet: &str, pool: &str) {
+        let account = self.wallet(wallet).clone();
+        let certificate = JCLICertificateWrapper::new()
+            .assert_new_stake_delegation(self.stake_pool_id(pool), &account.public_key);
+        self.post_certificate(wallet, &certificate);
+    }
+
+    /// a transaction from the wallet paying the fees of the certificate
+    fn post_certificate(&mut self, wallet: &str, certificate: &str) {
+        let fees = self.fees();
+        let fee = fees.certificate + fees.coefficient + fees.constant;
+        let account = self.wallet(wallet).clone();
+        let owner_key =
+            file_utils::create_file_in_temp("stake_key.private_key", &account.private_key);
+
+        let transaction =
+            JCLITransactionWrapper::new_transaction(self.cluster.genesis_block_hash())
+                .assert_add_account(&account.address, &Value::from(fee))
+                .assert_add_certificate(certificate)
+                .assert_finalize_with_fee(&account.address, &fees)
+                .seal_with_witness_for_address(&account)
+                .assert_add_auth(&owner_key)
+                .assert_to_message();
+        self.post(wallet, &transaction);
+    }
+
+    /// post the transaction, wait until it is in a block and count it in the
+    /// spending counter of the wallet
+    fn post(&mut self, wallet: &str, transaction: &str) {
+        jcli_wrapper::assert_transaction_in_block_with_wait(
+            transaction,
+            &self.rest_address(),
+            &self.wait,
+        );
+        self.wallets
+            .get_mut(wallet)
+            .expect("the wallet exists")
+            .confirm_transaction();
+    }
+
+    fn wait_for_rewards(&self, wallet: &str) {
+        let start = Instant::now();
+        while self.rewards(wallet) == 0 {
+            assert!(
+                start.elapsed() < self.timeout,
+                "the wallet {} of the scenario receives no rewards",
+                wallet
+            );
+            process_utils::sleep(1);
+        }
+    }
+
+    fn wait_for_blocks(&self, blocks: u32) {
+        let start = Instant::now();
+        let height = loop {
+            if let Some(height) = self.entry_node().height() {
+                break height;
+            }
+            assert!(
+                start.elapsed() < self.timeout,
+                "the node of the scenario does not respond"
+            );
+            process_utils::sleep(1);
+        };
+        self.cluster
+            .wait_for_height(height + blocks, self.timeout)
+            .expect("the nodes of the scenario do not create the blocks");
+    }
+}
@@ -1,5 +1,5 @@
 use crate::common::{
-    configuration::genesis_model::GenesisYaml,
+    configuration::genesis_model::{Block0Configuration, Block0ConfigurationExt},
     data::address::{Account, Delegation, Utxo},
     file_utils, jcli_wrapper,
 };
@@ -8,14 +8,14 @@ use chain_crypto::AsymmetricKey;
 use jormungandr_lib::crypto::key::KeyPair;
 use std::path::PathBuf;
 
-pub fn get_genesis_block_hash(genesis_yaml: &GenesisYaml) -> String {
+pub fn get_genesis_block_hash(genesis_yaml: &Block0Configuration) -> String {
     let path_to_output_block = build_genesis_block(&genesis_yaml);
 
     jcli_wrapper::assert_genesis_hash(&path_to_output_block)
 }
 
-pub fn build_genesis_block(genesis_yaml: &GenesisYaml) -> PathBuf {
-    let input_yaml_file_path = GenesisYaml::serialize(&genesis_yaml);
+pub fn build_genesis_block(genesis_yaml: &Block0Configuration) -> PathBuf {
+    let input_yaml_file_path = Block0Configuration::serialize(&genesis_yaml);
     let path_to_output_block = file_utils::get_path_in_temp("block-0.bin");
     jcli_wrapper::assert_genesis_encode(&input_yaml_file_path, &path_to_output_block);
 
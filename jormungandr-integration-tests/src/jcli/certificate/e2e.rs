@@ -38,3 +38,79 @@ pub fn test_create_and_sign_new_stake_delegation() {
 
     file_assert::assert_file_exists_and_not_empty(&signed_cert);
 }
+
+#[test]
+pub fn test_create_and_sign_new_stake_pool_retirement() {
+    let owner = create_new_key_pair::<Ed25519>();
+    let kes = create_new_key_pair::<SumEd25519_12>();
+    let vrf = create_new_key_pair::<Curve25519_2HashDH>();
+
+    let serial_id = "13919597664319319060838570079442950054";
+
+    let certificate_wrapper = JCLICertificateWrapper::new();
+    let certificate = certificate_wrapper.assert_new_stake_pool_registration(
+        &kes.identifier().to_bech32_str(),
+        &serial_id,
+        &vrf.identifier().to_bech32_str(),
+        0,
+        1,
+        &owner.identifier().to_bech32_str(),
+    );
+
+    let input_file = file_utils::create_file_in_temp("certificate", &certificate);
+    let stake_pool_id = certificate_wrapper.assert_get_stake_pool_id(&input_file);
+    let certificate = certificate_wrapper.assert_new_stake_pool_retirement(&stake_pool_id);
+
+    assert_ne!(certificate, "", "retirement cert is empty");
+
+    let retirement_file = file_utils::create_file_in_temp("retirement_cert", &certificate);
+    let signed_cert = file_utils::get_path_in_temp("signed_retirement_cert");
+    let owner_private_key_file =
+        file_utils::create_file_in_temp("owner.private", &owner.signing_key().to_bech32_str());
+
+    certificate_wrapper.assert_sign(&owner_private_key_file, &retirement_file, &signed_cert);
+
+    file_assert::assert_file_exists_and_not_empty(&signed_cert);
+}
+
+#[test]
+pub fn test_create_and_sign_new_stake_pool_update() {
+    let owner = create_new_key_pair::<Ed25519>();
+    let kes = create_new_key_pair::<SumEd25519_12>();
+    let vrf = create_new_key_pair::<Curve25519_2HashDH>();
+    let new_kes = create_new_key_pair::<SumEd25519_12>();
+    let new_vrf = create_new_key_pair::<Curve25519_2HashDH>();
+
+    let serial_id = "13919597664319319060838570079442950054";
+
+    let certificate_wrapper = JCLICertificateWrapper::new();
+    let certificate = certificate_wrapper.assert_new_stake_pool_registration(
+        &kes.identifier().to_bech32_str(),
+        &serial_id,
+        &vrf.identifier().to_bech32_str(),
+        0,
+        1,
+        &owner.identifier().to_bech32_str(),
+    );
+
+    let input_file = file_utils::create_file_in_temp("certificate", &certificate);
+    let stake_pool_id = certificate_wrapper.assert_get_stake_pool_id(&input_file);
+    let certificate = certificate_wrapper.assert_new_stake_pool_update(
+        &stake_pool_id,
+        &kes.identifier().to_bech32_str(),
+        &vrf.identifier().to_bech32_str(),
+        &new_kes.identifier().to_bech32_str(),
+        &new_vrf.identifier().to_bech32_str(),
+    );
+
+    assert_ne!(certificate, "", "update cert is empty");
+
+    let update_file = file_utils::create_file_in_temp("update_cert", &certificate);
+    let signed_cert = file_utils::get_path_in_temp("signed_update_cert");
+    let owner_private_key_file =
+        file_utils::create_file_in_temp("owner.private", &owner.signing_key().to_bech32_str());
+
+    certificate_wrapper.assert_sign(&owner_private_key_file, &update_file, &signed_cert);
+
+    file_assert::assert_file_exists_and_not_empty(&signed_cert);
+}
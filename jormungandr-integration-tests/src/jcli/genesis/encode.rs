@@ -1,6 +1,8 @@
 use crate::common::{
     configuration::{
-        genesis_model::{Fund, GenesisYaml, Initial},
+        genesis_model::{
+            initial_funds, initial_legacy_funds, Block0Configuration, Block0ConfigurationExt, Fund,
+        },
         jormungandr_config::JormungandrConfig,
     },
     file_utils, jcli_wrapper, startup,
@@ -9,7 +11,7 @@ use chain_addr::Discrimination;
 
 #[test]
 pub fn test_genesis_block_is_built_from_correct_yaml() {
-    startup::build_genesis_block(&GenesisYaml::new());
+    startup::build_genesis_block(&Block0Configuration::new());
 }
 
 #[test]
@@ -18,7 +20,7 @@ pub fn test_genesis_with_empty_consenus_leaders_list_fails_to_build() {
     config
         .genesis_yaml
         .blockchain_configuration
-        .consensus_leader_ids = Some(vec![]);
+        .consensus_leader_ids = vec![];
     jcli_wrapper::assert_genesis_encode_fails(
         &config.genesis_yaml,
         r"Missing consensus leader id list in the initial fragment",
@@ -29,8 +31,8 @@ pub fn test_genesis_with_empty_consenus_leaders_list_fails_to_build() {
 pub fn test_genesis_for_production_is_successfully_built() {
     let mut config = JormungandrConfig::new();
     config.genesis_yaml.initial.clear();
-    config.genesis_yaml.blockchain_configuration.discrimination = Some("production".to_string());
-    let input_yaml_file_path = GenesisYaml::serialize(&config.genesis_yaml);
+    config.genesis_yaml.blockchain_configuration.discrimination = Discrimination::Production;
+    let input_yaml_file_path = Block0Configuration::serialize(&config.genesis_yaml);
     let path_to_output_block = file_utils::get_path_in_temp("block0.bin");
     jcli_wrapper::assert_genesis_encode(&input_yaml_file_path, &path_to_output_block);
 }
@@ -42,20 +44,22 @@ pub fn test_genesis_for_prod_with_initial_funds_for_testing_address_fail_to_buil
     let test_address = jcli_wrapper::assert_address_single(&public_key, Discrimination::Test);
 
     let mut config = JormungandrConfig::new();
-    config.genesis_yaml.initial = vec![Initial::Fund(vec![Fund {
+    config.genesis_yaml.initial = vec![initial_funds(&[Fund {
         value: 100.into(),
         address: test_address.clone(),
     }])];
-    config.genesis_yaml.blockchain_configuration.discrimination = Some("production".to_string());
+    config.genesis_yaml.blockchain_configuration.discrimination = Discrimination::Production;
     jcli_wrapper::assert_genesis_encode_fails(&config.genesis_yaml, "Invalid discrimination");
 }
 
 #[test]
 pub fn test_genesis_for_prod_with_wrong_discrimination_fail_to_build() {
-    let mut config = JormungandrConfig::new();
-    config.genesis_yaml.blockchain_configuration.discrimination = Some("prod".to_string());
+    let config = JormungandrConfig::new();
+    // a discrimination the configuration cannot hold
+    let mut genesis_yaml = serde_yaml::to_value(&config.genesis_yaml).unwrap();
+    genesis_yaml["blockchain_configuration"]["discrimination"] = "prod".into();
     jcli_wrapper::assert_genesis_encode_fails(
-        &config.genesis_yaml,
+        &genesis_yaml,
         "blockchain_configuration.discrimination: unknown variant `prod`, expected `test` or `production`",
     );
 }
@@ -64,7 +68,7 @@ pub fn test_genesis_for_prod_with_wrong_discrimination_fail_to_build() {
 pub fn test_genesis_without_initial_funds_is_built_successfully() {
     let mut config = JormungandrConfig::new();
     config.genesis_yaml.initial.clear();
-    let input_yaml_file_path = GenesisYaml::serialize(&config.genesis_yaml);
+    let input_yaml_file_path = Block0Configuration::serialize(&config.genesis_yaml);
     let path_to_output_block = file_utils::get_path_in_temp("block0.bin");
     jcli_wrapper::assert_genesis_encode(&input_yaml_file_path, &path_to_output_block);
 }
@@ -74,7 +78,7 @@ pub fn test_genesis_with_many_initial_funds_is_built_successfully() {
     let mut config = JormungandrConfig::new();
     let address_1 = startup::create_new_account_address();
     let address_2 = startup::create_new_account_address();
-    let initial_funds = Initial::Fund(vec![
+    let initial_funds = initial_funds(&[
         Fund {
             value: 100.into(),
             address: address_1.address,
@@ -85,7 +89,7 @@ pub fn test_genesis_with_many_initial_funds_is_built_successfully() {
         },
     ]);
     config.genesis_yaml.initial.push(initial_funds);
-    let input_yaml_file_path = GenesisYaml::serialize(&config.genesis_yaml);
+    let input_yaml_file_path = Block0Configuration::serialize(&config.genesis_yaml);
     let path_to_output_block = file_utils::get_path_in_temp("block0.bin");
     jcli_wrapper::assert_genesis_encode(&input_yaml_file_path, &path_to_output_block);
 }
@@ -93,8 +97,8 @@ pub fn test_genesis_with_many_initial_funds_is_built_successfully() {
 #[test]
 pub fn test_genesis_with_legacy_funds_is_built_successfully() {
     let mut config = JormungandrConfig::new();
-    let legacy_funds = Initial::LegacyFund(
-            vec![
+    let legacy_funds = initial_legacy_funds(
+            &[
                 Fund{
                     value: 100.into(),
                     address: "DdzFFzCqrht5TM5GznWhJ3GTpKawtJuA295F8igwXQXyt2ih1TL1XKnZqRBQBoLpyYVKfNKgCXPBUYruUneC83KjGK6QNAoBSqRJovbG".to_string()
@@ -102,7 +106,7 @@ pub fn test_genesis_with_legacy_funds_is_built_successfully() {
             ]
         );
     config.genesis_yaml.initial.push(legacy_funds);
-    let input_yaml_file_path = GenesisYaml::serialize(&config.genesis_yaml);
+    let input_yaml_file_path = Block0Configuration::serialize(&config.genesis_yaml);
     let path_to_output_block = file_utils::get_path_in_temp("block0.bin");
     jcli_wrapper::assert_genesis_encode(&input_yaml_file_path, &path_to_output_block);
 }
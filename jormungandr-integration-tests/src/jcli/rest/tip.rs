@@ -1,5 +1,7 @@
 use crate::common::{
-    configuration::node_config_model::NodeConfig, jcli_wrapper, jormungandr::starter::Starter,
+    configuration::node_config_model::{NodeConfig, NodeConfigExt},
+    jcli_wrapper,
+    jormungandr::starter::Starter,
     process_assert,
 };
 
@@ -61,6 +61,7 @@ pub fn test_jormungandr_with_wrong_logger_fails_to_start() {
         .with_log(Log(vec![LogEntry {
             format: Some("xml".to_string()),
             level: None,
+            output: None,
         }]))
         .build();
     Starter::new().config(config).start_fail(
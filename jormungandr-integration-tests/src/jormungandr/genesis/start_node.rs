@@ -1,5 +1,8 @@
 use crate::common::{
-    configuration::{genesis_model::Fund, secret_model::SecretModel},
+    configuration::{
+        genesis_model::Fund,
+        secret_model::{NodeSecret, NodeSecretExt},
+    },
     file_utils,
     jcli_wrapper::certificate::wrapper::JCLICertificateWrapper,
     jormungandr::{ConfigurationBuilder, Starter},
@@ -62,12 +65,12 @@ pub fn test_genesis_stake_pool_with_account_faucet_starts_successfully() {
         }])
         .build();
 
-    let secret = SecretModel::new_genesis(
+    let secret = NodeSecret::new_genesis(
         &pool_kes.signing_key().to_bech32_str(),
         &pool_vrf.signing_key().to_bech32_str(),
         &stake_pool_id,
     );
-    let secret_file = SecretModel::serialize(&secret);
+    let secret_file = NodeSecret::serialize(&secret);
     config.secret_model = secret;
     config.secret_model_path = secret_file;
     let _jormungandr = Starter::new().config(config).start().unwrap();
@@ -134,12 +137,12 @@ pub fn test_genesis_stake_pool_with_utxo_faucet_starts_successfully() {
         ])
         .build();
 
-    let secret = SecretModel::new_genesis(
+    let secret = NodeSecret::new_genesis(
         &pool_kes.signing_key().to_bech32_str(),
         &pool_vrf.signing_key().to_bech32_str(),
         &stake_pool_id,
     );
-    let secret_file = SecretModel::serialize(&secret);
+    let secret_file = NodeSecret::serialize(&secret);
     config.secret_model = secret;
     config.secret_model_path = secret_file;
 
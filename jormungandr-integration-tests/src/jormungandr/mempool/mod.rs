@@ -26,6 +26,8 @@ pub fn test_log_ttl() {
             fragment_ttl: Duration::from_secs(10).into(),
             log_ttl: Duration::from_secs(log_ttl_timeout).into(),
             garbage_collection_interval: Duration::from_secs(garbage_collection_interval).into(),
+            persistent_log: None,
+            fragment_limits: Default::default(),
         })
         .build();
 
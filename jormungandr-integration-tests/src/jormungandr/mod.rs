@@ -2,3 +2,4 @@ pub mod bft;
 pub mod genesis;
 pub mod mempool;
 pub mod recovery;
+pub mod scenario;
@@ -0,0 +1,29 @@
+use crate::common::{configuration::genesis_model::LinearFees, scenario::ScenarioBuilder};
+
+#[test]
+pub fn transfer_then_delegate() {
+    let scenario = ScenarioBuilder::new()
+        .with_linear_fees(LinearFees {
+            constant: 10,
+            coefficient: 1,
+            certificate: 100,
+        })
+        // the fees are kept by the cluster of two nodes
+        .with_nodes(2, 1)
+        .with_wallet("alice", 1_000_000)
+        .with_wallet("bob", 0)
+        .send("alice", "bob", 1_000)
+        .assert_balance("alice", 1_000_000 - 1_000 - 12)
+        .assert_balance("bob", 1_000)
+        .create_stake_pool("alice", "pool")
+        .delegate("bob", "pool")
+        .assert_delegated("bob", "pool")
+        .wait_for_blocks(2)
+        .run();
+
+    scenario
+        .cluster()
+        .nodes()
+        .iter()
+        .for_each(|node| node.process().assert_no_errors_in_log());
+}
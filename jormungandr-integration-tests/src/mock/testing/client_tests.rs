@@ -5,7 +5,7 @@ use crate::mock::{
 };
 
 use crate::common::{
-    configuration::genesis_model::Fund, jcli_wrapper, jcli_wrapper::JCLITransactionWrapper,
+    configuration::{genesis_model::Fund, node_config_model::NodeConfigExt}, jcli_wrapper, jcli_wrapper::JCLITransactionWrapper,
     jormungandr::{logger::Level, Starter,ConfigurationBuilder},startup
 };
 use chain_core::property::FromStr;
@@ -190,7 +190,7 @@ pub fn push_headers() {
             .genesis_yaml
             .blockchain_configuration
             .slots_per_epoch
-            .unwrap(),
+            .into(),
     );
 
     let block = GenesisPraosBlockBuilder::new()
@@ -220,7 +220,7 @@ pub fn upload_block_incompatible_protocol() {
             .genesis_yaml
             .blockchain_configuration
             .slots_per_epoch
-            .unwrap(),
+            .into(),
     );
 
     let block = GenesisPraosBlockBuilder::new()
@@ -263,7 +263,7 @@ pub fn upload_block_nonexisting_stake_pool() {
             .genesis_yaml
             .blockchain_configuration
             .slots_per_epoch
-            .unwrap(),
+            .into(),
     );
 
     let block = GenesisPraosBlockBuilder::new()
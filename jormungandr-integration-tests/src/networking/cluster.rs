@@ -0,0 +1,16 @@
+use crate::common::jormungandr::cluster::ClusterBuilder;
+use std::time::Duration;
+
+#[test]
+#[ignore]
+pub fn cluster_nodes_follow_the_leaders() {
+    let cluster = ClusterBuilder::new(3).with_leaders(2).start().unwrap();
+
+    cluster
+        .wait_for_height(3, Duration::from_secs(60))
+        .expect("the cluster nodes do not follow the leaders");
+
+    for node in cluster.nodes() {
+        node.process().assert_no_errors_in_log();
+    }
+}
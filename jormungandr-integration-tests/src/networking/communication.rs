@@ -52,3 +52,56 @@ pub fn two_nodes_communication() {
         &trusted_jormungandr.rest_address(),
     );
 }
+
+#[test]
+#[ignore]
+pub fn client_only_node_communication() {
+    let sender = startup::create_new_utxo_address();
+    let reciever = startup::create_new_utxo_address();
+
+    let leader_config = ConfigurationBuilder::new()
+        .with_funds(vec![Fund {
+            address: sender.address.clone(),
+            value: 100.into(),
+        }])
+        .build();
+
+    let _leader_jormungandr = Starter::new()
+        .config(leader_config.clone())
+        .start()
+        .unwrap();
+
+    let client_config = ConfigurationBuilder::new()
+        .with_client_only()
+        .with_trusted_peers(vec![TrustedPeer {
+            address: leader_config.node_config.p2p.public_address.clone(),
+            id: leader_config.node_config.p2p.public_id.clone(),
+        }])
+        .with_block_hash(leader_config.genesis_block_hash.clone())
+        .build();
+
+    let client_jormungandr = Starter::new()
+        .config(client_config.clone())
+        .passive()
+        .start()
+        .unwrap();
+
+    let node_stats = jcli_wrapper::assert_rest_stats(&client_jormungandr.rest_address());
+    assert_eq!(
+        node_stats.get("clientOnly").map(String::as_str),
+        Some("true"),
+        "the node without a listen address is not reported as a client only"
+    );
+
+    let utxo = leader_config.block0_utxo_for_address(&sender);
+    let transaction_message = JCLITransactionWrapper::build_transaction_from_utxo(
+        &utxo,
+        &utxo.associated_fund(),
+        &sender,
+        &utxo.associated_fund(),
+        &reciever,
+        &client_config.genesis_block_hash,
+    );
+
+    jcli_wrapper::assert_post_transaction(&transaction_message, &client_jormungandr.rest_address());
+}
@@ -0,0 +1,43 @@
+use crate::common::jormungandr::cluster::{Cluster, ClusterBuilder};
+use std::time::Duration;
+
+fn faulty_cluster() -> Cluster {
+    ClusterBuilder::new(4)
+        .with_leaders(2)
+        .with_faulty_network()
+        .start()
+        .unwrap()
+}
+
+#[test]
+#[ignore]
+pub fn cluster_recovers_from_a_partition() {
+    let cluster = faulty_cluster();
+
+    cluster
+        .network()
+        .partition(&[&["node0", "node2"], &["node1", "node3"]]);
+    std::thread::sleep(Duration::from_secs(30));
+    assert!(
+        !cluster.is_synced(),
+        "the partitioned leaders should create different chains"
+    );
+
+    cluster.network().heal();
+    cluster
+        .wait_for_sync(Duration::from_secs(120))
+        .expect("the cluster does not recover from the partition");
+}
+
+#[test]
+#[ignore]
+pub fn cluster_syncs_with_latency_and_drops() {
+    let cluster = faulty_cluster();
+
+    cluster.network().set_latency(Duration::from_millis(200));
+    cluster.network().set_drop_rate(0.01);
+
+    cluster
+        .wait_for_height(5, Duration::from_secs(180))
+        .expect("the cluster does not sync on a faulty network");
+}
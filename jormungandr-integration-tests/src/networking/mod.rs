@@ -1,2 +1,4 @@
+pub mod cluster;
 pub mod communication;
+pub mod faults;
 pub mod testnet;
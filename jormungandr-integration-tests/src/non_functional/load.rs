@@ -0,0 +1,40 @@
+#![cfg(feature = "soak-test")]
+
+use crate::common::{
+    jormungandr::{ConfigurationBuilder, Starter},
+    load_generator::{LoadConfig, LoadGenerator},
+};
+use jormungandr_lib::{client::JormungandrClient, crypto::hash::Hash};
+use std::{str::FromStr, time::Duration};
+
+#[test]
+pub fn sustained_load_of_accounts_and_utxos() {
+    let mut generator = LoadGenerator::new(LoadConfig {
+        tps: 20,
+        duration: Duration::from_secs(600),
+        ..Default::default()
+    });
+
+    let config = ConfigurationBuilder::new()
+        .with_funds(generator.funds())
+        .with_slot_duration(1)
+        .build();
+    let jormungandr = Starter::new().config(config.clone()).start().unwrap();
+    let rest =
+        JormungandrClient::new(&config.node_config.rest.as_ref().unwrap().listen.to_string());
+
+    let report = generator
+        .run(
+            &[rest],
+            &Hash::from_str(&config.genesis_block_hash).unwrap(),
+        )
+        .unwrap();
+    println!("{}", report);
+
+    assert_eq!(report.rejected, 0, "transactions rejected under load");
+    assert_eq!(
+        report.pending, 0,
+        "transactions still pending after the load"
+    );
+    jormungandr.assert_no_errors_in_log();
+}
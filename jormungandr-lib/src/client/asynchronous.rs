@@ -0,0 +1,239 @@
+use super::{
+    base_url, requests, AddressTransaction, ApiRequest, Body, Error, NodeReady, NodeStats,
+    OutputLevels, PeerStats, ReloadReport, StakeDistribution, SubscriptionEvents, SubscriptionId,
+};
+use crate::{
+    crypto::{account::Identifier, hash::Hash},
+    interfaces::{
+        AccountReward, AccountState, Address, EnclaveLeaderId, EpochRewards, FragmentLog,
+        LeadershipLog, SettingsDto, UTxOOutputInfo,
+    },
+};
+use chain_impl_mockchain::{block::Block, fragment::Fragment};
+use futures::{future, Future, Stream};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    r#async::Client,
+};
+use serde::Serialize;
+
+/// client of the REST API of a node, returning the futures of the responses
+#[derive(Clone)]
+pub struct AsyncJormungandrClient {
+    client: Client,
+    base_url: String,
+    admin_token: Option<String>,
+}
+
+impl AsyncJormungandrClient {
+    /// the client of the node listening on `address`, a host and port or the
+    /// URL of the node
+    pub fn new(address: &str) -> Self {
+        AsyncJormungandrClient {
+            client: Client::new(),
+            base_url: base_url(address),
+            admin_token: None,
+        }
+    }
+
+    /// the token of the administrative requests, see the `admin_token` of
+    /// the `rest` configuration of the node
+    pub fn with_admin_token<S: Into<String>>(self, admin_token: S) -> Self {
+        AsyncJormungandrClient {
+            admin_token: Some(admin_token.into()),
+            ..self
+        }
+    }
+
+    fn send<T>(&self, request: ApiRequest<T>) -> impl Future<Item = T, Error = Error> {
+        let url = format!("{}/{}", self.base_url, request.path);
+        let mut builder = self.client.request(request.method, &url);
+        if let (true, Some(token)) = (request.admin, self.admin_token.as_ref()) {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder = match request.body {
+            Some(Body::Json(body)) => builder.header(CONTENT_TYPE, "application/json").body(body),
+            Some(Body::Binary(body)) => builder
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(body),
+            None => builder,
+        };
+        let decode = request.decode;
+        builder
+            .send()
+            .and_then(|response| {
+                let status = response.status();
+                response
+                    .into_body()
+                    .concat2()
+                    .map(move |body| (status, body.to_vec()))
+            })
+            .map_err(Error::from)
+            .and_then(move |(status, body)| decode(status, body))
+    }
+
+    pub fn account_state(
+        &self,
+        account: &Identifier,
+    ) -> impl Future<Item = AccountState, Error = Error> {
+        self.send(requests::account_state(account))
+    }
+
+    /// the transactions of the main chain involving `address`, the node has
+    /// to run the chain index
+    pub fn address_transactions(
+        &self,
+        address: &Address,
+    ) -> impl Future<Item = Vec<AddressTransaction>, Error = Error> {
+        self.send(requests::address_transactions(address))
+    }
+
+    pub fn block(&self, block_id: &Hash) -> impl Future<Item = Block, Error = Error> {
+        self.send(requests::block(block_id))
+    }
+
+    /// the IDs of the blocks of the main chain following `block_id`, up to
+    /// `count` of them
+    pub fn next_block_ids(
+        &self,
+        block_id: &Hash,
+        count: u64,
+    ) -> impl Future<Item = Vec<Hash>, Error = Error> {
+        self.send(requests::next_block_ids(block_id, count))
+    }
+
+    pub fn fragment_logs(&self) -> impl Future<Item = Vec<FragmentLog>, Error = Error> {
+        self.send(requests::fragment_logs())
+    }
+
+    pub fn leaders(&self) -> impl Future<Item = Vec<EnclaveLeaderId>, Error = Error> {
+        self.send(requests::leaders())
+    }
+
+    /// start a leader with the given secret, the JSON of a secret file
+    pub fn add_leader<S: Serialize>(
+        &self,
+        secret: &S,
+    ) -> impl Future<Item = EnclaveLeaderId, Error = Error> {
+        let client = self.clone();
+        future::result(requests::add_leader(secret)).and_then(move |request| client.send(request))
+    }
+
+    pub fn remove_leader(
+        &self,
+        leader_id: EnclaveLeaderId,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.send(requests::remove_leader(leader_id))
+    }
+
+    pub fn leaders_logs(&self) -> impl Future<Item = Vec<LeadershipLog>, Error = Error> {
+        self.send(requests::leaders_logs())
+    }
+
+    pub fn log_levels(&self) -> impl Future<Item = Vec<OutputLevels>, Error = Error> {
+        self.send(requests::log_levels())
+    }
+
+    pub fn set_log_levels(&self, levels: &[OutputLevels]) -> impl Future<Item = (), Error = Error> {
+        let client = self.clone();
+        future::result(requests::set_log_levels(levels))
+            .and_then(move |request| client.send(request))
+    }
+
+    pub fn network_stats(&self) -> impl Future<Item = Vec<PeerStats>, Error = Error> {
+        self.send(requests::network_stats())
+    }
+
+    /// reload the configuration file of the node
+    pub fn reload(&self) -> impl Future<Item = ReloadReport, Error = Error> {
+        self.send(requests::reload())
+    }
+
+    /// the rewards distributed at the start of `epoch`, from the history
+    /// kept by the node
+    pub fn epoch_rewards(&self, epoch: u32) -> impl Future<Item = EpochRewards, Error = Error> {
+        self.send(requests::epoch_rewards(epoch))
+    }
+
+    /// the rewards received by the account, from the history kept by the node
+    pub fn account_rewards(
+        &self,
+        account: &Identifier,
+    ) -> impl Future<Item = Vec<AccountReward>, Error = Error> {
+        self.send(requests::account_rewards(account))
+    }
+
+    pub fn settings(&self) -> impl Future<Item = SettingsDto, Error = Error> {
+        self.send(requests::settings())
+    }
+
+    pub fn stake_distribution(&self) -> impl Future<Item = StakeDistribution, Error = Error> {
+        self.send(requests::stake_distribution())
+    }
+
+    pub fn stake_pools(&self) -> impl Future<Item = Vec<Hash>, Error = Error> {
+        self.send(requests::stake_pools())
+    }
+
+    pub fn shutdown(&self) -> impl Future<Item = (), Error = Error> {
+        self.send(requests::shutdown())
+    }
+
+    pub fn send_fragment(&self, fragment: &Fragment) -> impl Future<Item = (), Error = Error> {
+        self.send(requests::send_fragment(fragment))
+    }
+
+    /// the metrics of the node, in the Prometheus text format
+    pub fn metrics(&self) -> impl Future<Item = String, Error = Error> {
+        self.send(requests::metrics())
+    }
+
+    pub fn node_ready(&self) -> impl Future<Item = NodeReady, Error = Error> {
+        self.send(requests::node_ready())
+    }
+
+    pub fn node_stats(&self) -> impl Future<Item = NodeStats, Error = Error> {
+        self.send(requests::node_stats())
+    }
+
+    /// watch the given addresses, the node has to run the chain index
+    pub fn subscribe(
+        &self,
+        addresses: &[Address],
+    ) -> impl Future<Item = SubscriptionId, Error = Error> {
+        let client = self.clone();
+        future::result(requests::subscribe(addresses)).and_then(move |request| client.send(request))
+    }
+
+    /// the events following the event numbered `after`, 0 for the first
+    /// events of the subscription
+    pub fn subscription_events(
+        &self,
+        id: &str,
+        after: u64,
+    ) -> impl Future<Item = SubscriptionEvents, Error = Error> {
+        self.send(requests::subscription_events(id, after))
+    }
+
+    pub fn unsubscribe(&self, id: &str) -> impl Future<Item = (), Error = Error> {
+        self.send(requests::unsubscribe(id))
+    }
+
+    pub fn tip(&self) -> impl Future<Item = Hash, Error = Error> {
+        self.send(requests::tip())
+    }
+
+    /// the block of the main chain holding the fragment, the node has to run
+    /// the chain index
+    pub fn transaction_block(&self, fragment_id: &Hash) -> impl Future<Item = Hash, Error = Error> {
+        self.send(requests::transaction_block(fragment_id))
+    }
+
+    pub fn utxo(
+        &self,
+        fragment_id: &Hash,
+        output_index: u8,
+    ) -> impl Future<Item = UTxOOutputInfo, Error = Error> {
+        self.send(requests::utxo(fragment_id, output_index))
+    }
+}
@@ -0,0 +1,199 @@
+use super::{
+    base_url, requests, AddressTransaction, ApiRequest, Body, Error, NodeReady, NodeStats,
+    OutputLevels, PeerStats, ReloadReport, StakeDistribution, SubscriptionEvents, SubscriptionId,
+};
+use crate::{
+    crypto::{account::Identifier, hash::Hash},
+    interfaces::{
+        AccountReward, AccountState, Address, EnclaveLeaderId, EpochRewards, FragmentLog,
+        LeadershipLog, SettingsDto, UTxOOutputInfo,
+    },
+};
+use chain_impl_mockchain::{block::Block, fragment::Fragment};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Client,
+};
+use serde::Serialize;
+
+/// client of the REST API of a node, waiting for the responses
+#[derive(Clone)]
+pub struct JormungandrClient {
+    client: Client,
+    base_url: String,
+    admin_token: Option<String>,
+}
+
+impl JormungandrClient {
+    /// the client of the node listening on `address`, a host and port or the
+    /// URL of the node
+    pub fn new(address: &str) -> Self {
+        JormungandrClient {
+            client: Client::new(),
+            base_url: base_url(address),
+            admin_token: None,
+        }
+    }
+
+    /// the token of the administrative requests, see the `admin_token` of
+    /// the `rest` configuration of the node
+    pub fn with_admin_token<S: Into<String>>(self, admin_token: S) -> Self {
+        JormungandrClient {
+            admin_token: Some(admin_token.into()),
+            ..self
+        }
+    }
+
+    fn send<T>(&self, request: ApiRequest<T>) -> Result<T, Error> {
+        let url = format!("{}/{}", self.base_url, request.path);
+        let mut builder = self.client.request(request.method, &url);
+        if let (true, Some(token)) = (request.admin, self.admin_token.as_ref()) {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder = match request.body {
+            Some(Body::Json(body)) => builder.header(CONTENT_TYPE, "application/json").body(body),
+            Some(Body::Binary(body)) => builder
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(body),
+            None => builder,
+        };
+        let mut response = builder.send()?;
+        let mut body = Vec::new();
+        response.copy_to(&mut body)?;
+        (request.decode)(response.status(), body)
+    }
+
+    pub fn account_state(&self, account: &Identifier) -> Result<AccountState, Error> {
+        self.send(requests::account_state(account))
+    }
+
+    /// the transactions of the main chain involving `address`, the node has
+    /// to run the chain index
+    pub fn address_transactions(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<AddressTransaction>, Error> {
+        self.send(requests::address_transactions(address))
+    }
+
+    pub fn block(&self, block_id: &Hash) -> Result<Block, Error> {
+        self.send(requests::block(block_id))
+    }
+
+    /// the IDs of the blocks of the main chain following `block_id`, up to
+    /// `count` of them
+    pub fn next_block_ids(&self, block_id: &Hash, count: u64) -> Result<Vec<Hash>, Error> {
+        self.send(requests::next_block_ids(block_id, count))
+    }
+
+    pub fn fragment_logs(&self) -> Result<Vec<FragmentLog>, Error> {
+        self.send(requests::fragment_logs())
+    }
+
+    pub fn leaders(&self) -> Result<Vec<EnclaveLeaderId>, Error> {
+        self.send(requests::leaders())
+    }
+
+    /// start a leader with the given secret, the JSON of a secret file
+    pub fn add_leader<S: Serialize>(&self, secret: &S) -> Result<EnclaveLeaderId, Error> {
+        self.send(requests::add_leader(secret)?)
+    }
+
+    pub fn remove_leader(&self, leader_id: EnclaveLeaderId) -> Result<(), Error> {
+        self.send(requests::remove_leader(leader_id))
+    }
+
+    pub fn leaders_logs(&self) -> Result<Vec<LeadershipLog>, Error> {
+        self.send(requests::leaders_logs())
+    }
+
+    pub fn log_levels(&self) -> Result<Vec<OutputLevels>, Error> {
+        self.send(requests::log_levels())
+    }
+
+    pub fn set_log_levels(&self, levels: &[OutputLevels]) -> Result<(), Error> {
+        self.send(requests::set_log_levels(levels)?)
+    }
+
+    pub fn network_stats(&self) -> Result<Vec<PeerStats>, Error> {
+        self.send(requests::network_stats())
+    }
+
+    /// reload the configuration file of the node
+    pub fn reload(&self) -> Result<ReloadReport, Error> {
+        self.send(requests::reload())
+    }
+
+    /// the rewards distributed at the start of `epoch`, from the history
+    /// kept by the node
+    pub fn epoch_rewards(&self, epoch: u32) -> Result<EpochRewards, Error> {
+        self.send(requests::epoch_rewards(epoch))
+    }
+
+    /// the rewards received by the account, from the history kept by the node
+    pub fn account_rewards(&self, account: &Identifier) -> Result<Vec<AccountReward>, Error> {
+        self.send(requests::account_rewards(account))
+    }
+
+    pub fn settings(&self) -> Result<SettingsDto, Error> {
+        self.send(requests::settings())
+    }
+
+    pub fn stake_distribution(&self) -> Result<StakeDistribution, Error> {
+        self.send(requests::stake_distribution())
+    }
+
+    pub fn stake_pools(&self) -> Result<Vec<Hash>, Error> {
+        self.send(requests::stake_pools())
+    }
+
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.send(requests::shutdown())
+    }
+
+    pub fn send_fragment(&self, fragment: &Fragment) -> Result<(), Error> {
+        self.send(requests::send_fragment(fragment))
+    }
+
+    /// the metrics of the node, in the Prometheus text format
+    pub fn metrics(&self) -> Result<String, Error> {
+        self.send(requests::metrics())
+    }
+
+    pub fn node_ready(&self) -> Result<NodeReady, Error> {
+        self.send(requests::node_ready())
+    }
+
+    pub fn node_stats(&self) -> Result<NodeStats, Error> {
+        self.send(requests::node_stats())
+    }
+
+    /// watch the given addresses, the node has to run the chain index
+    pub fn subscribe(&self, addresses: &[Address]) -> Result<SubscriptionId, Error> {
+        self.send(requests::subscribe(addresses)?)
+    }
+
+    /// the events following the event numbered `after`, 0 for the first
+    /// events of the subscription
+    pub fn subscription_events(&self, id: &str, after: u64) -> Result<SubscriptionEvents, Error> {
+        self.send(requests::subscription_events(id, after))
+    }
+
+    pub fn unsubscribe(&self, id: &str) -> Result<(), Error> {
+        self.send(requests::unsubscribe(id))
+    }
+
+    pub fn tip(&self) -> Result<Hash, Error> {
+        self.send(requests::tip())
+    }
+
+    /// the block of the main chain holding the fragment, the node has to run
+    /// the chain index
+    pub fn transaction_block(&self, fragment_id: &Hash) -> Result<Hash, Error> {
+        self.send(requests::transaction_block(fragment_id))
+    }
+
+    pub fn utxo(&self, fragment_id: &Hash, output_index: u8) -> Result<UTxOOutputInfo, Error> {
+        self.send(requests::utxo(fragment_id, output_index))
+    }
+}
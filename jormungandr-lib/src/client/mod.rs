@@ -0,0 +1,600 @@
+//! typed client of the REST API of the node
+//!
+//! [`JormungandrClient`] sends the requests and waits for the responses,
+//! [`AsyncJormungandrClient`] returns futures for the tokio runtime. Both
+//! target the latest version of the API, `/api/v1`, and decode the responses
+//! into the types of [`interfaces`](../interfaces/index.html) when they
+//! exist, or into the types of this module otherwise.
+//!
+//! The requests are described once, in this module, and sent by either
+//! client: a request is its method, path and body, and the function decoding
+//! the response.
+//!
+//! [`JormungandrClient`]: ./struct.JormungandrClient.html
+//! [`AsyncJormungandrClient`]: ./struct.AsyncJormungandrClient.html
+
+mod asynchronous;
+mod blocking;
+
+pub use self::asynchronous::AsyncJormungandrClient;
+pub use self::blocking::JormungandrClient;
+
+use crate::{
+    crypto::{account::Identifier, hash::Hash},
+    interfaces::{
+        AccountReward, AccountState, Address, EnclaveLeaderId, EpochRewards, FragmentLog,
+        LeadershipLog, NodeState, SettingsDto, Stats, UTxOOutputInfo,
+    },
+    time::SystemTime,
+};
+use chain_core::{
+    mempack::{ReadBuf, ReadError, Readable as _},
+    property::Serialize as _,
+};
+use chain_impl_mockchain::{block::Block, fragment::Fragment};
+use reqwest::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+custom_error! {pub Error
+    Request { source: reqwest::Error } = "cannot send the request to the node",
+    Status { status: StatusCode, message: String } = "the node responded {status}: {message}",
+    InvalidJson { source: serde_json::Error } = "invalid JSON in the response of the node",
+    InvalidBlock { source: ReadError } = "invalid block in the response of the node",
+    InvalidHash { source: chain_crypto::hash::Error } = "invalid hash in the response of the node",
+    InvalidBlockIds = "the block IDs in the response of the node are truncated",
+}
+
+/// the size of a block ID in the responses of `block/{id}/next_id`
+const BLOCK_ID_SIZE: usize = 32;
+
+/// the state of the node, and the state of its blockchain once its tasks are
+/// started
+#[derive(Debug, Clone)]
+pub struct NodeStats {
+    pub state: NodeState,
+    /// the statistics in the form of the `v0` API, `last_block_height` being
+    /// a string
+    pub stats: Option<Stats>,
+    /// the height of the tip, the number sent by the `v1` API
+    pub last_block_height: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NodeReady {
+    pub ready: bool,
+    pub state: NodeState,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStats {
+    pub node_id: String,
+    pub established_at: SystemTime,
+    pub last_block_received: Option<SystemTime>,
+    pub last_fragment_received: Option<SystemTime>,
+    pub last_gossip_received: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StakeDistribution {
+    pub epoch: u32,
+    /// none unless the consensus is Genesis Praos
+    pub stake: Option<Stake>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Stake {
+    pub unassigned: u64,
+    pub dangling: u64,
+    pub pools: Vec<(Hash, u64)>,
+}
+
+/// a transaction of the main chain involving an address
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AddressTransaction {
+    pub fragment_id: Hash,
+    pub block: Hash,
+}
+
+/// the levels of a log output, see the `log` section of the node
+/// configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLevels {
+    pub level: String,
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+/// the settings applied by a reload of the configuration, and the settings
+/// needing a restart
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ReloadReport {
+    pub applied: Vec<String>,
+    pub rejected: Vec<String>,
+}
+
+/// the random id of a subscription, which gives access to its events
+pub type SubscriptionId = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionEventKind {
+    Applied,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionEvent {
+    pub seq: u64,
+    pub event: SubscriptionEventKind,
+    pub fragment_id: Hash,
+    pub block: Hash,
+    pub date: String,
+    pub addresses: Vec<Address>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionEvents {
+    /// the events dropped by the node since the last poll
+    pub missed: u64,
+    pub events: Vec<SubscriptionEvent>,
+}
+
+/// a request to the node, and how its response is decoded
+struct ApiRequest<T> {
+    method: Method,
+    path: String,
+    body: Option<Body>,
+    /// whether the request needs the admin token
+    admin: bool,
+    decode: fn(StatusCode, Vec<u8>) -> Result<T, Error>,
+}
+
+enum Body {
+    Json(Vec<u8>),
+    Binary(Vec<u8>),
+}
+
+impl<T> ApiRequest<T> {
+    fn new(
+        method: Method,
+        path: String,
+        decode: fn(StatusCode, Vec<u8>) -> Result<T, Error>,
+    ) -> Self {
+        ApiRequest {
+            method,
+            path,
+            body: None,
+            admin: false,
+            decode,
+        }
+    }
+
+    fn with_json<B: Serialize>(self, body: &B) -> Result<Self, Error> {
+        Ok(ApiRequest {
+            body: Some(Body::Json(serde_json::to_vec(body)?)),
+            ..self
+        })
+    }
+
+    fn with_binary(self, body: Vec<u8>) -> Self {
+        ApiRequest {
+            body: Some(Body::Binary(body)),
+            ..self
+        }
+    }
+
+    fn admin(self) -> Self {
+        ApiRequest {
+            admin: true,
+            ..self
+        }
+    }
+}
+
+/// the error of a response, if it is not successful
+fn check_status(status: StatusCode, body: &[u8]) -> Result<(), Error> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::Status {
+            status,
+            message: String::from_utf8_lossy(body).into_owned(),
+        })
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(status: StatusCode, body: Vec<u8>) -> Result<T, Error> {
+    check_status(status, &body)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn decode_empty(status: StatusCode, body: Vec<u8>) -> Result<(), Error> {
+    check_status(status, &body)
+}
+
+fn decode_text(status: StatusCode, body: Vec<u8>) -> Result<String, Error> {
+    check_status(status, &body)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn decode_hash(status: StatusCode, body: Vec<u8>) -> Result<Hash, Error> {
+    Ok(decode_text(status, body)?.trim().parse()?)
+}
+
+fn decode_block(status: StatusCode, body: Vec<u8>) -> Result<Block, Error> {
+    check_status(status, &body)?;
+    Ok(Block::read(&mut ReadBuf::from(&body))?)
+}
+
+fn decode_block_ids(status: StatusCode, body: Vec<u8>) -> Result<Vec<Hash>, Error> {
+    check_status(status, &body)?;
+    if body.len() % BLOCK_ID_SIZE != 0 {
+        return Err(Error::InvalidBlockIds);
+    }
+    Ok(body
+        .chunks(BLOCK_ID_SIZE)
+        .map(|chunk| {
+            let mut id = [0; BLOCK_ID_SIZE];
+            id.copy_from_slice(chunk);
+            Hash::from(id)
+        })
+        .collect())
+}
+
+fn decode_node_stats(status: StatusCode, body: Vec<u8>) -> Result<NodeStats, Error> {
+    check_status(status, &body)?;
+    let mut value: serde_json::Value = serde_json::from_slice(&body)?;
+    let state = serde_json::from_value(value["state"].clone())?;
+    if value.get("lastBlockHash").is_none() {
+        return Ok(NodeStats {
+            state,
+            stats: None,
+            last_block_height: None,
+        });
+    }
+    let last_block_height: u32 = serde_json::from_value(value["lastBlockHeight"].clone())?;
+    // `Stats` has the `lastBlockHeight` of the `v0` API, a string
+    value["lastBlockHeight"] = last_block_height.to_string().into();
+    Ok(NodeStats {
+        state,
+        stats: Some(serde_json::from_value(value)?),
+        last_block_height: Some(last_block_height),
+    })
+}
+
+/// the readiness probe answers `503` with the state until the node is ready
+fn decode_node_ready(_status: StatusCode, body: Vec<u8>) -> Result<NodeReady, Error> {
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// the rejected settings are reported with the `409` status
+fn decode_reload_report(status: StatusCode, body: Vec<u8>) -> Result<ReloadReport, Error> {
+    if status != StatusCode::CONFLICT {
+        check_status(status, &body)?;
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+#[derive(Deserialize)]
+struct TransactionBlock {
+    block: Hash,
+}
+
+fn decode_transaction_block(status: StatusCode, body: Vec<u8>) -> Result<Hash, Error> {
+    decode_json::<TransactionBlock>(status, body).map(|transaction| transaction.block)
+}
+
+#[derive(Deserialize)]
+struct Subscription {
+    id: SubscriptionId,
+}
+
+fn decode_subscription(status: StatusCode, body: Vec<u8>) -> Result<SubscriptionId, Error> {
+    decode_json::<Subscription>(status, body).map(|subscription| subscription.id)
+}
+
+mod requests {
+    use super::*;
+
+    pub fn account_state(account: &Identifier) -> ApiRequest<AccountState> {
+        ApiRequest::new(
+            Method::GET,
+            format!("account/{}", account.to_hex()),
+            decode_json,
+        )
+    }
+
+    pub fn address_transactions(address: &Address) -> ApiRequest<Vec<AddressTransaction>> {
+        ApiRequest::new(
+            Method::GET,
+            format!("address/{}/transactions", address),
+            decode_json,
+        )
+    }
+
+    pub fn block(block_id: &Hash) -> ApiRequest<Block> {
+        ApiRequest::new(Method::GET, format!("block/{}", block_id), decode_block)
+    }
+
+    pub fn next_block_ids(block_id: &Hash, count: u64) -> ApiRequest<Vec<Hash>> {
+        ApiRequest::new(
+            Method::GET,
+            format!("block/{}/next_id?count={}", block_id, count),
+            decode_block_ids,
+        )
+    }
+
+    pub fn fragment_logs() -> ApiRequest<Vec<FragmentLog>> {
+        ApiRequest::new(Method::GET, "fragment/logs".to_owned(), decode_json)
+    }
+
+    pub fn leaders() -> ApiRequest<Vec<EnclaveLeaderId>> {
+        ApiRequest::new(Method::GET, "leaders".to_owned(), decode_json)
+    }
+
+    pub fn add_leader<S: Serialize>(secret: &S) -> Result<ApiRequest<EnclaveLeaderId>, Error> {
+        ApiRequest::new(Method::POST, "leaders".to_owned(), decode_json)
+            .admin()
+            .with_json(secret)
+    }
+
+    pub fn remove_leader(leader_id: EnclaveLeaderId) -> ApiRequest<()> {
+        ApiRequest::new(
+            Method::DELETE,
+            format!("leaders/{}", leader_id),
+            decode_empty,
+        )
+        .admin()
+    }
+
+    pub fn leaders_logs() -> ApiRequest<Vec<LeadershipLog>> {
+        ApiRequest::new(Method::GET, "leaders/logs".to_owned(), decode_json)
+    }
+
+    pub fn log_levels() -> ApiRequest<Vec<OutputLevels>> {
+        ApiRequest::new(Method::GET, "log/levels".to_owned(), decode_json)
+    }
+
+    pub fn set_log_levels(levels: &[OutputLevels]) -> Result<ApiRequest<()>, Error> {
+        ApiRequest::new(Method::PUT, "log/levels".to_owned(), decode_empty)
+            .admin()
+            .with_json(&levels)
+    }
+
+    pub fn network_stats() -> ApiRequest<Vec<PeerStats>> {
+        ApiRequest::new(Method::GET, "network/stats".to_owned(), decode_json)
+    }
+
+    pub fn reload() -> ApiRequest<ReloadReport> {
+        ApiRequest::new(Method::POST, "reload".to_owned(), decode_reload_report).admin()
+    }
+
+    pub fn epoch_rewards(epoch: u32) -> ApiRequest<EpochRewards> {
+        ApiRequest::new(Method::GET, format!("rewards/epoch/{}", epoch), decode_json)
+    }
+
+    pub fn account_rewards(account: &Identifier) -> ApiRequest<Vec<AccountReward>> {
+        ApiRequest::new(
+            Method::GET,
+            format!("rewards/account/{}", account.to_hex()),
+            decode_json,
+        )
+    }
+
+    pub fn settings() -> ApiRequest<SettingsDto> {
+        ApiRequest::new(Method::GET, "settings".to_owned(), decode_json)
+    }
+
+    pub fn stake_distribution() -> ApiRequest<StakeDistribution> {
+        ApiRequest::new(Method::GET, "stake".to_owned(), decode_json)
+    }
+
+    pub fn stake_pools() -> ApiRequest<Vec<Hash>> {
+        ApiRequest::new(Method::GET, "stake_pools".to_owned(), decode_json)
+    }
+
+    pub fn shutdown() -> ApiRequest<()> {
+        ApiRequest::new(Method::POST, "shutdown".to_owned(), decode_empty).admin()
+    }
+
+    pub fn send_fragment(fragment: &Fragment) -> ApiRequest<()> {
+        let bytes = fragment
+            .serialize_as_vec()
+            .expect("fragments serialize in memory");
+        ApiRequest::new(Method::POST, "message".to_owned(), decode_empty).with_binary(bytes)
+    }
+
+    pub fn metrics() -> ApiRequest<String> {
+        ApiRequest::new(Method::GET, "node/metrics".to_owned(), decode_text)
+    }
+
+    pub fn node_ready() -> ApiRequest<NodeReady> {
+        ApiRequest::new(Method::GET, "node/ready".to_owned(), decode_node_ready)
+    }
+
+    pub fn node_stats() -> ApiRequest<NodeStats> {
+        ApiRequest::new(Method::GET, "node/stats".to_owned(), decode_node_stats)
+    }
+
+    pub fn subscribe(addresses: &[Address]) -> Result<ApiRequest<SubscriptionId>, Error> {
+        #[derive(Serialize)]
+        struct SubscriptionRequest<'a> {
+            addresses: &'a [Address],
+        }
+        ApiRequest::new(
+            Method::POST,
+            "subscriptions".to_owned(),
+            decode_subscription,
+        )
+        .with_json(&SubscriptionRequest { addresses })
+    }
+
+    pub fn subscription_events(id: &str, after: u64) -> ApiRequest<SubscriptionEvents> {
+        ApiRequest::new(
+            Method::GET,
+            format!("subscriptions/{}/events?after={}", id, after),
+            decode_json,
+        )
+    }
+
+    pub fn unsubscribe(id: &str) -> ApiRequest<()> {
+        ApiRequest::new(
+            Method::DELETE,
+            format!("subscriptions/{}", id),
+            decode_empty,
+        )
+    }
+
+    pub fn tip() -> ApiRequest<Hash> {
+        ApiRequest::new(Method::GET, "tip".to_owned(), decode_hash)
+    }
+
+    pub fn transaction_block(fragment_id: &Hash) -> ApiRequest<Hash> {
+        ApiRequest::new(
+            Method::GET,
+            format!("transaction/{}", fragment_id),
+            decode_transaction_block,
+        )
+    }
+
+    pub fn utxo(fragment_id: &Hash, output_index: u8) -> ApiRequest<UTxOOutputInfo> {
+        ApiRequest::new(
+            Method::GET,
+            format!("utxo/{}/{}", fragment_id, output_index),
+            decode_json,
+        )
+    }
+}
+
+/// the URL of the `v1` API of the node listening on the given address,
+/// `127.0.0.1:8443` for instance
+fn base_url(address: &str) -> String {
+    if address.starts_with("http://") || address.starts_with("https://") {
+        format!("{}/api/v1", address.trim_end_matches('/'))
+    } else {
+        format!("http://{}/api/v1", address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn administrative_requests_send_the_token() {
+        let levels = [OutputLevels {
+            level: "info".to_owned(),
+            modules: BTreeMap::new(),
+        }];
+        assert!(requests::add_leader(&()).unwrap().admin);
+        assert!(requests::remove_leader(EnclaveLeaderId::new().next()).admin);
+        assert!(requests::set_log_levels(&levels).unwrap().admin);
+        assert!(requests::reload().admin);
+        assert!(requests::shutdown().admin);
+    }
+
+    #[test]
+    fn queries_do_not_send_the_token() {
+        assert!(!requests::tip().admin);
+        assert!(!requests::node_stats().admin);
+        assert!(!requests::leaders().admin);
+        assert!(!requests::log_levels().admin);
+        assert!(!requests::subscribe(&[]).unwrap().admin);
+    }
+
+    #[test]
+    fn shutdown_is_a_post_request() {
+        let request = requests::shutdown();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.path, "shutdown");
+    }
+
+    #[test]
+    fn base_url_targets_the_v1_api() {
+        assert_eq!(base_url("127.0.0.1:8443"), "http://127.0.0.1:8443/api/v1");
+        assert_eq!(
+            base_url("https://node.example:8443/"),
+            "https://node.example:8443/api/v1"
+        );
+    }
+
+    #[test]
+    fn status_errors_carry_the_body() {
+        match decode_empty(StatusCode::SERVICE_UNAVAILABLE, b"not ready".to_vec()) {
+            Err(Error::Status { status, message }) => {
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(message, "not ready");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn block_ids_are_split() {
+        let mut body = vec![1; BLOCK_ID_SIZE];
+        body.extend_from_slice(&[2; BLOCK_ID_SIZE]);
+        let ids = decode_block_ids(StatusCode::OK, body).unwrap();
+        assert_eq!(
+            ids,
+            vec![
+                Hash::from([1; BLOCK_ID_SIZE]),
+                Hash::from([2; BLOCK_ID_SIZE])
+            ]
+        );
+
+        match decode_block_ids(StatusCode::OK, vec![1; BLOCK_ID_SIZE + 1]) {
+            Err(Error::InvalidBlockIds) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hash_is_trimmed() {
+        let hash = Hash::from([3; BLOCK_ID_SIZE]);
+        let body = format!("{}\n", hash).into_bytes();
+        assert_eq!(decode_hash(StatusCode::OK, body).unwrap(), hash);
+    }
+
+    #[test]
+    fn node_stats_before_the_tasks_are_started() {
+        let body = br#"{"state":"Bootstrapping"}"#.to_vec();
+        let stats = decode_node_stats(StatusCode::OK, body).unwrap();
+        assert_eq!(stats.state, NodeState::Bootstrapping);
+        assert!(stats.stats.is_none());
+        assert_eq!(stats.last_block_height, None);
+    }
+
+    #[test]
+    fn node_stats_block_height_is_a_number() {
+        let body = br#"{
+            "state": "Running",
+            "txRecvCnt": 1,
+            "blockRecvCnt": 2,
+            "uptime": 3,
+            "lastBlockHash": "abcd",
+            "lastBlockHeight": 42,
+            "lastBlockDate": "1.2",
+            "lastBlockTime": null,
+            "lastBlockTx": 0,
+            "lastBlockSum": 0,
+            "lastBlockFees": 0
+        }"#
+        .to_vec();
+        let stats = decode_node_stats(StatusCode::OK, body).unwrap();
+        assert_eq!(stats.state, NodeState::Running);
+        assert_eq!(stats.last_block_height, Some(42));
+        assert_eq!(stats.stats.unwrap().last_block_height, "42");
+    }
+
+    #[test]
+    fn rejected_reload_is_reported() {
+        let body = br#"{"applied":["log"],"rejected":["storage"]}"#.to_vec();
+        let report = decode_reload_report(StatusCode::CONFLICT, body).unwrap();
+        assert_eq!(report.applied, vec!["log".to_owned()]);
+        assert_eq!(report.rejected, vec!["storage".to_owned()]);
+
+        assert!(decode_reload_report(StatusCode::UNAUTHORIZED, Vec::new()).is_err());
+    }
+}
@@ -24,8 +24,21 @@
 //! It provides the same interfaces as for the identifier in the
 //! `key` module but limited to Account only.
 //!
+//! # Node IDs
+//!
+//! The [`PrivateId`] of a node is the secret its ID in the P2P network is
+//! derived from.
+//!
+//! # Passphrase encryption
+//!
+//! The [`Encrypted`] data is encrypted with a key derived from a
+//! [`Passphrase`], e.g. to keep the node's secret files encrypted on disk.
+//! The passphrase is zeroized once dropped.
+//!
 
 pub mod account;
 pub mod hash;
 pub mod key;
+pub mod node_id;
+pub mod passphrase;
 pub(crate) mod serde;
@@ -0,0 +1,120 @@
+use crate::crypto::passphrase::zeroize;
+use cryptoxide::{blake2b::Blake2b, digest::Digest};
+use rand_core::{CryptoRng, RngCore};
+use std::{fmt, str::FromStr};
+
+pub const PRIVATE_ID_SIZE: usize = 32;
+
+/// the size of the node IDs of the P2P network
+pub const PUBLIC_ID_SIZE: usize = 24;
+
+/// separates the derivation of the node IDs from the other uses of Blake2b
+const DERIVATION_CONTEXT: &[u8] = b"jormungandr p2p node id";
+
+custom_error! {pub PrivateIdError
+    Hex { source: hex::FromHexError } = "the private node ID is not hexadecimal",
+    Size { size: usize } = "the private node ID is {size} bytes long instead of 32",
+}
+
+/// the secret a node derives its ID in the P2P network from, overwritten
+/// with zeroes once dropped
+///
+/// Only the owner of the private ID can announce the node ID derived from
+/// it, and a new node ID is obtained by generating a new private ID.
+pub struct PrivateId([u8; PRIVATE_ID_SIZE]);
+
+/// the node ID derived from a [`PrivateId`], in the format of the
+/// `p2p.public_id` setting of the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PublicId([u8; PUBLIC_ID_SIZE]);
+
+impl PrivateId {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut bytes = [0; PRIVATE_ID_SIZE];
+        rng.fill_bytes(&mut bytes);
+        PrivateId(bytes)
+    }
+
+    pub fn public_id(&self) -> PublicId {
+        let mut hasher = Blake2b::new(PUBLIC_ID_SIZE);
+        hasher.input(DERIVATION_CONTEXT);
+        hasher.input(&self.0);
+        let mut bytes = [0; PUBLIC_ID_SIZE];
+        hasher.result(&mut bytes);
+        PublicId(bytes)
+    }
+}
+
+impl Drop for PrivateId {
+    fn drop(&mut self) {
+        zeroize(&mut self.0)
+    }
+}
+
+impl fmt::Display for PrivateId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl FromStr for PrivateId {
+    type Err = PrivateIdError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut decoded = hex::decode(s.trim())?;
+        if decoded.len() != PRIVATE_ID_SIZE {
+            let size = decoded.len();
+            zeroize(&mut decoded);
+            return Err(PrivateIdError::Size { size });
+        }
+        let mut bytes = [0; PRIVATE_ID_SIZE];
+        bytes.copy_from_slice(&decoded);
+        zeroize(&mut decoded);
+        Ok(PrivateId(bytes))
+    }
+}
+
+impl PublicId {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for PublicId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn public_id_is_derived_from_the_private_id() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let private_id = PrivateId::generate(&mut rng);
+        let other = PrivateId::generate(&mut rng);
+
+        let parsed: PrivateId = format!("{}\n", private_id).parse().unwrap();
+        assert_eq!(parsed.public_id(), private_id.public_id());
+        assert_ne!(other.public_id(), private_id.public_id());
+        assert_eq!(
+            private_id.public_id().to_string().len(),
+            2 * PUBLIC_ID_SIZE
+        );
+    }
+
+    #[test]
+    fn private_id_of_wrong_size_is_refused() {
+        match "00".repeat(PUBLIC_ID_SIZE).parse::<PrivateId>() {
+            Err(PrivateIdError::Size { size }) => assert_eq!(size, PUBLIC_ID_SIZE),
+            _ => panic!("a node ID was accepted as a private ID"),
+        }
+        match "not hexadecimal".parse::<PrivateId>() {
+            Err(PrivateIdError::Hex { .. }) => (),
+            _ => panic!("a private ID which is not hexadecimal was accepted"),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use cryptoxide::{chacha20poly1305::ChaCha20Poly1305, hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt, ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+/// the number of PBKDF2 iterations used for new encryptions
+pub const ITERATIONS: u32 = 100_000;
+
+/// the most PBKDF2 iterations accepted when decrypting, the count is read
+/// from the file and a huge one would stall the node for as long
+pub const MAX_ITERATIONS: u32 = 10 * ITERATIONS;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+
+/// a passphrase, overwritten with zeroes once dropped
+#[derive(Clone)]
+pub struct Passphrase(Vec<u8>);
+
+/// data encrypted with a key derived from a [`Passphrase`]
+///
+/// The key is derived with PBKDF2-HMAC-SHA512 and the data is encrypted and
+/// authenticated with ChaCha20-Poly1305, the tag is appended to the data.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Encrypted {
+    iterations: u32,
+    #[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    salt: Vec<u8>,
+    #[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    nonce: Vec<u8>,
+    #[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    data: Vec<u8>,
+}
+
+/// the content of an encrypted file, the `encrypted` key tells it apart
+/// from a file in plain text
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EncryptedFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<Encrypted>,
+}
+
+custom_error! {pub DecryptionError
+    Malformed = "the encrypted data is malformed",
+    Authentication = "wrong passphrase or tampered data",
+}
+
+impl Passphrase {
+    /// the passphrase is the content of a file or of an environment
+    /// variable, without its trailing newline
+    pub fn new(mut passphrase: Vec<u8>) -> Self {
+        while let Some(b'\n') | Some(b'\r') = passphrase.last() {
+            passphrase.pop();
+        }
+        Passphrase(passphrase)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn derive_key(&self, salt: &[u8], iterations: u32) -> [u8; KEY_SIZE] {
+        let mut key = [0; KEY_SIZE];
+        let mut mac = Hmac::new(Sha512::new(), &self.0);
+        pbkdf2(&mut mac, salt, iterations, &mut key);
+        key
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        zeroize(&mut self.0)
+    }
+}
+
+impl Encrypted {
+    pub fn encrypt<R>(rng: &mut R, passphrase: &Passphrase, plaintext: &[u8]) -> Self
+    where
+        R: RngCore + CryptoRng,
+    {
+        let mut salt = vec![0; SALT_SIZE];
+        let mut nonce = vec![0; NONCE_SIZE];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let mut key = passphrase.derive_key(&salt, ITERATIONS);
+        let mut data = vec![0; plaintext.len() + TAG_SIZE];
+        let (ciphertext, tag) = data.split_at_mut(plaintext.len());
+        ChaCha20Poly1305::new(&key, &nonce, &[]).encrypt(plaintext, ciphertext, tag);
+        zeroize(&mut key);
+
+        Encrypted {
+            iterations: ITERATIONS,
+            salt,
+            nonce,
+            data,
+        }
+    }
+
+    /// the decrypted data, to be zeroized by the caller once used
+    pub fn decrypt(&self, passphrase: &Passphrase) -> Result<Vec<u8>, DecryptionError> {
+        if self.iterations < ITERATIONS
+            || self.iterations > MAX_ITERATIONS
+            || self.salt.len() != SALT_SIZE
+            || self.nonce.len() != NONCE_SIZE
+            || self.data.len() < TAG_SIZE
+        {
+            return Err(DecryptionError::Malformed);
+        }
+        let (ciphertext, tag) = self.data.split_at(self.data.len() - TAG_SIZE);
+
+        let mut key = passphrase.derive_key(&self.salt, self.iterations);
+        let mut plaintext = vec![0; ciphertext.len()];
+        let authenticated =
+            ChaCha20Poly1305::new(&key, &self.nonce, &[]).decrypt(ciphertext, &mut plaintext, tag);
+        zeroize(&mut key);
+
+        if authenticated {
+            Ok(plaintext)
+        } else {
+            zeroize(&mut plaintext);
+            Err(DecryptionError::Authentication)
+        }
+    }
+}
+
+fn serialize_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        hex::encode(bytes).serialize(serializer)
+    } else {
+        bytes.serialize(serializer)
+    }
+}
+
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(&s).map_err(serde::de::Error::custom)
+    } else {
+        Vec::deserialize(deserializer)
+    }
+}
+
+/// overwrite the given secret material with zeroes, in a way the
+/// compiler will not optimise away.
+pub fn zeroize(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn encrypt_decrypt() {
+        const SECRET: &[u8] = b"bft:\n  signing_key: ed25519_sk1...\n";
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let passphrase = Passphrase::new(b"correct horse battery staple\n".to_vec());
+
+        let encrypted = Encrypted::encrypt(&mut rng, &passphrase, SECRET);
+        let encoded = serde_yaml::to_string(&encrypted).unwrap();
+        let decoded: Encrypted = serde_yaml::from_str(&encoded).unwrap();
+
+        assert_eq!(decoded.decrypt(&passphrase).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn decrypt_with_wrong_passphrase() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let passphrase = Passphrase::new(b"correct horse battery staple".to_vec());
+        let wrong = Passphrase::new(b"incorrect horse battery staple".to_vec());
+
+        let encrypted = Encrypted::encrypt(&mut rng, &passphrase, b"secret");
+
+        match encrypted.decrypt(&wrong) {
+            Err(DecryptionError::Authentication) => (),
+            _ => panic!("decrypted with the wrong passphrase"),
+        }
+    }
+
+    fn assert_malformed(encrypted: &Encrypted, passphrase: &Passphrase) {
+        match encrypted.decrypt(passphrase) {
+            Err(DecryptionError::Malformed) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("decrypted malformed data"),
+        }
+    }
+
+    #[test]
+    fn decrypt_with_iterations_out_of_range() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let passphrase = Passphrase::new(b"correct horse battery staple".to_vec());
+        let encrypted = Encrypted::encrypt(&mut rng, &passphrase, b"secret");
+
+        for iterations in &[0, 1, ITERATIONS - 1, MAX_ITERATIONS + 1, u32::max_value()] {
+            let mut tampered = encrypted.clone();
+            tampered.iterations = *iterations;
+            assert_malformed(&tampered, &passphrase);
+        }
+    }
+
+    #[test]
+    fn decrypt_with_wrong_salt_size() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let passphrase = Passphrase::new(b"correct horse battery staple".to_vec());
+        let encrypted = Encrypted::encrypt(&mut rng, &passphrase, b"secret");
+
+        for salt in &[Vec::new(), vec![0; SALT_SIZE - 1], vec![0; SALT_SIZE + 1]] {
+            let mut tampered = encrypted.clone();
+            tampered.salt = salt.clone();
+            assert_malformed(&tampered, &passphrase);
+        }
+    }
+}
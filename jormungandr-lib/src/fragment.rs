@@ -0,0 +1,63 @@
+//! the transactions carried by the fragments
+//!
+//! The fragments transferring value all carry a transaction, of a payload
+//! type of their own. [`visit_transaction`] reaches the transaction of a
+//! fragment whatever its payload, so the code reading the inputs, outputs
+//! and witnesses does not match on every kind of fragment.
+//!
+//! # Example
+//!
+//! ```
+//! # use chain_impl_mockchain::{fragment::Fragment, transaction::{Payload, Transaction}};
+//! use jormungandr_lib::fragment::{visit_transaction, TransactionVisitor};
+//! use std::fmt::Debug;
+//!
+//! struct InputCount;
+//!
+//! impl TransactionVisitor for InputCount {
+//!     type Output = usize;
+//!
+//!     fn visit<P: Payload + Debug>(self, tx: &Transaction<P>) -> usize {
+//!         tx.as_slice().inputs().iter().count()
+//!     }
+//! }
+//!
+//! fn input_count(fragment: &Fragment) -> usize {
+//!     visit_transaction(fragment, InputCount).unwrap_or(0)
+//! }
+//! ```
+//!
+//! [`visit_transaction`]: ./fn.visit_transaction.html
+
+use chain_impl_mockchain::{
+    fragment::Fragment,
+    transaction::{Payload, Transaction},
+};
+use std::fmt::Debug;
+
+/// an operation on a transaction of any payload
+pub trait TransactionVisitor {
+    type Output;
+
+    fn visit<P: Payload + Debug>(self, tx: &Transaction<P>) -> Self::Output;
+}
+
+/// visit the transaction of the fragment, `None` if the fragment does not
+/// carry a transaction
+pub fn visit_transaction<V: TransactionVisitor>(
+    fragment: &Fragment,
+    visitor: V,
+) -> Option<V::Output> {
+    match fragment {
+        Fragment::Transaction(tx) => Some(visitor.visit(tx)),
+        Fragment::OwnerStakeDelegation(tx) => Some(visitor.visit(tx)),
+        Fragment::StakeDelegation(tx) => Some(visitor.visit(tx)),
+        Fragment::PoolRegistration(tx) => Some(visitor.visit(tx)),
+        Fragment::PoolRetirement(tx) => Some(visitor.visit(tx)),
+        Fragment::PoolUpdate(tx) => Some(visitor.visit(tx)),
+        Fragment::Initial(_)
+        | Fragment::OldUtxoDeclaration(_)
+        | Fragment::UpdateProposal(_)
+        | Fragment::UpdateVote(_) => None,
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    interfaces::{
+        ActiveSlotCoefficient, BFTSlotsRatio, Block0Configuration, BlockchainConfiguration,
+        ConsensusLeaderId, Initial, InitialUTxO, KESUpdateSpeed, NumberOfSlotsPerEpoch,
+        SlotDuration,
+    },
+    time::SecondsSinceUnixEpoch,
+};
+use chain_addr::Discrimination;
+use chain_impl_mockchain::{block::ConsensusVersion, fee::LinearFee};
+
+/// builder of the genesis file, the parameters not set keep the node
+/// defaults
+#[derive(Debug, Clone)]
+pub struct Block0ConfigurationBuilder {
+    blockchain_configuration: BlockchainConfiguration,
+    initial: Vec<Initial>,
+}
+
+impl Block0ConfigurationBuilder {
+    /// the blockchain starts now, without fees
+    pub fn new(discrimination: Discrimination, block0_consensus: ConsensusVersion) -> Self {
+        Block0ConfigurationBuilder {
+            blockchain_configuration: BlockchainConfiguration {
+                block0_date: SecondsSinceUnixEpoch::now(),
+                discrimination,
+                block0_consensus,
+                consensus_leader_ids: Vec::new(),
+                linear_fees: LinearFee::new(0, 0, 0),
+                slots_per_epoch: NumberOfSlotsPerEpoch::default(),
+                slot_duration: SlotDuration::default(),
+                kes_update_speed: KESUpdateSpeed::default(),
+                consensus_genesis_praos_active_slot_coeff: ActiveSlotCoefficient::default(),
+                bft_slots_ratio: BFTSlotsRatio::default(),
+                max_number_of_transactions_per_block: None,
+                epoch_stability_depth: None,
+                treasury: None,
+                rewards: None,
+            },
+            initial: Vec::new(),
+        }
+    }
+
+    pub fn with_block0_date(&mut self, block0_date: SecondsSinceUnixEpoch) -> &mut Self {
+        self.blockchain_configuration.block0_date = block0_date;
+        self
+    }
+
+    pub fn with_consensus_leader_ids(&mut self, leader_ids: Vec<ConsensusLeaderId>) -> &mut Self {
+        self.blockchain_configuration.consensus_leader_ids = leader_ids;
+        self
+    }
+
+    pub fn add_consensus_leader_id(&mut self, leader_id: ConsensusLeaderId) -> &mut Self {
+        self.blockchain_configuration
+            .consensus_leader_ids
+            .push(leader_id);
+        self
+    }
+
+    pub fn with_linear_fees(&mut self, linear_fees: LinearFee) -> &mut Self {
+        self.blockchain_configuration.linear_fees = linear_fees;
+        self
+    }
+
+    pub fn with_slots_per_epoch(&mut self, slots_per_epoch: NumberOfSlotsPerEpoch) -> &mut Self {
+        self.blockchain_configuration.slots_per_epoch = slots_per_epoch;
+        self
+    }
+
+    pub fn with_slot_duration(&mut self, slot_duration: SlotDuration) -> &mut Self {
+        self.blockchain_configuration.slot_duration = slot_duration;
+        self
+    }
+
+    pub fn with_kes_update_speed(&mut self, kes_update_speed: KESUpdateSpeed) -> &mut Self {
+        self.blockchain_configuration.kes_update_speed = kes_update_speed;
+        self
+    }
+
+    pub fn with_active_slot_coefficient(
+        &mut self,
+        active_slot_coefficient: ActiveSlotCoefficient,
+    ) -> &mut Self {
+        self.blockchain_configuration
+            .consensus_genesis_praos_active_slot_coeff = active_slot_coefficient;
+        self
+    }
+
+    pub fn with_bft_slots_ratio(&mut self, bft_slots_ratio: BFTSlotsRatio) -> &mut Self {
+        self.blockchain_configuration.bft_slots_ratio = bft_slots_ratio;
+        self
+    }
+
+    pub fn with_epoch_stability_depth(&mut self, epoch_stability_depth: u32) -> &mut Self {
+        self.blockchain_configuration.epoch_stability_depth = Some(epoch_stability_depth);
+        self
+    }
+
+    pub fn with_treasury(&mut self, treasury: u64) -> &mut Self {
+        self.blockchain_configuration.treasury = Some(treasury);
+        self
+    }
+
+    pub fn with_rewards(&mut self, rewards: u64) -> &mut Self {
+        self.blockchain_configuration.rewards = Some(rewards);
+        self
+    }
+
+    /// the initial funds, added to the funds given so far
+    pub fn add_funds(&mut self, funds: Vec<InitialUTxO>) -> &mut Self {
+        if !funds.is_empty() {
+            self.initial.push(Initial::Fund(funds));
+        }
+        self
+    }
+
+    /// an initial fragment: funds, legacy funds or certificate
+    pub fn add_initial(&mut self, initial: Initial) -> &mut Self {
+        self.initial.push(initial);
+        self
+    }
+
+    pub fn build(&self) -> Block0Configuration {
+        Block0Configuration {
+            blockchain_configuration: self.blockchain_configuration.clone(),
+            initial: self.initial.clone(),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod active_slot_coefficient;
 mod bft_slots_ratio;
+mod builder;
 mod default_values;
 mod initial_config;
 mod initial_fragment;
@@ -7,9 +8,11 @@ mod kes_update_speed;
 mod leader_id;
 mod number_of_slots_per_epoch;
 mod slots_duration;
+mod update_proposal_changes;
 
 pub use self::active_slot_coefficient::ActiveSlotCoefficient;
 pub use self::bft_slots_ratio::BFTSlotsRatio;
+pub use self::builder::Block0ConfigurationBuilder;
 pub use self::default_values::*;
 pub use self::initial_config::BlockchainConfiguration;
 pub use self::initial_fragment::{Initial, InitialUTxO, LegacyUTxO};
@@ -17,6 +20,7 @@ pub use self::kes_update_speed::KESUpdateSpeed;
 pub use self::leader_id::ConsensusLeaderId;
 pub use self::number_of_slots_per_epoch::NumberOfSlotsPerEpoch;
 pub use self::slots_duration::SlotDuration;
+pub use self::update_proposal_changes::{FromUpdateConfigParamsError, UpdateProposalChanges};
 use chain_impl_mockchain::{
     block::{self, Block},
     fragment::{ContentsBuilder, Fragment},
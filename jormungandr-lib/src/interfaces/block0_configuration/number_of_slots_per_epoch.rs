@@ -63,6 +63,12 @@ impl From<NumberOfSlotsPerEpoch> for ConfigParam {
     }
 }
 
+impl From<NumberOfSlotsPerEpoch> for u32 {
+    fn from(number_of_slots_per_epoch: NumberOfSlotsPerEpoch) -> Self {
+        number_of_slots_per_epoch.0
+    }
+}
+
 impl fmt::Display for NumberOfSlotsPerEpoch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -0,0 +1,284 @@
+use crate::interfaces::{
+    ActiveSlotCoefficient, BFTSlotsRatio, ConsensusLeaderId, KESUpdateSpeed, LinearFeeDef,
+    NumberOfSlotsPerEpoch, SlotDuration,
+};
+use chain_impl_mockchain::{config::ConfigParam, fee::LinearFee, fragment::config::ConfigParams};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+
+/// the changes of the settings of the blockchain proposed by a BFT leader in
+/// an update proposal
+///
+/// Only the given settings are changed, at the start of the epoch following
+/// the vote of more than half of the BFT leaders for the proposal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProposalChanges {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots_per_epoch: Option<NumberOfSlotsPerEpoch>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot_duration: Option<SlotDuration>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kes_update_speed: Option<KESUpdateSpeed>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus_genesis_praos_active_slot_coeff: Option<ActiveSlotCoefficient>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bft_slots_ratio: Option<BFTSlotsRatio>,
+
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_linear_fees",
+        deserialize_with = "deserialize_linear_fees"
+    )]
+    pub linear_fees: Option<LinearFee>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_number_of_transactions_per_block: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch_stability_depth: Option<u32>,
+
+    /// the number of epochs an update proposal stays open to the votes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal_expiration: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_bft_leaders: Vec<ConsensusLeaderId>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_bft_leaders: Vec<ConsensusLeaderId>,
+}
+
+type StaticStr = &'static str;
+
+custom_error! {pub FromUpdateConfigParamsError
+    NotUpdatable { name: StaticStr } = "the setting {name} cannot be changed by an update proposal",
+    Duplicate { name: StaticStr } = "the update proposal changes the setting {name} more than once",
+    NumberOfSlotsPerEpoch { source: super::number_of_slots_per_epoch::TryFromNumberOfSlotsPerEpochError } = "Invalid number of slots per epoch",
+    SlotDuration { source: super::slots_duration::TryFromSlotDurationError } = "Invalid slot duration value",
+    ActiveSlotCoefficient { source: super::active_slot_coefficient::TryFromActiveSlotCoefficientError } = "Invalid active slot coefficient value",
+    BFTSlotsRatio { source: super::bft_slots_ratio::TryFromBFTSlotsRatioError } = "Invalid BFT Slot ratio",
+    KESUpdateSpeed { source: super::kes_update_speed::TryFromKESUpdateSpeedError } = "Invalid KES Update speed value",
+}
+
+impl UpdateProposalChanges {
+    pub fn is_empty(&self) -> bool {
+        self == &UpdateProposalChanges::default()
+    }
+}
+
+impl From<UpdateProposalChanges> for ConfigParams {
+    fn from(changes: UpdateProposalChanges) -> Self {
+        let mut params = ConfigParams::new();
+
+        if let Some(slots_per_epoch) = changes.slots_per_epoch {
+            params.push(ConfigParam::from(slots_per_epoch));
+        }
+        if let Some(slot_duration) = changes.slot_duration {
+            params.push(ConfigParam::from(slot_duration));
+        }
+        if let Some(kes_update_speed) = changes.kes_update_speed {
+            params.push(ConfigParam::from(kes_update_speed));
+        }
+        if let Some(coeff) = changes.consensus_genesis_praos_active_slot_coeff {
+            params.push(ConfigParam::from(coeff));
+        }
+        if let Some(bft_slots_ratio) = changes.bft_slots_ratio {
+            params.push(ConfigParam::from(bft_slots_ratio));
+        }
+        if let Some(linear_fees) = changes.linear_fees {
+            params.push(ConfigParam::LinearFee(linear_fees));
+        }
+        if let Some(max) = changes.max_number_of_transactions_per_block {
+            params.push(ConfigParam::MaxNumberOfTransactionsPerBlock(max));
+        }
+        if let Some(epoch_stability_depth) = changes.epoch_stability_depth {
+            params.push(ConfigParam::EpochStabilityDepth(epoch_stability_depth));
+        }
+        if let Some(proposal_expiration) = changes.proposal_expiration {
+            params.push(ConfigParam::ProposalExpiration(proposal_expiration));
+        }
+        for leader in changes.add_bft_leaders {
+            params.push(ConfigParam::AddBftLeader(leader.0));
+        }
+        for leader in changes.remove_bft_leaders {
+            params.push(ConfigParam::RemoveBftLeader(leader.0));
+        }
+
+        params
+    }
+}
+
+impl TryFrom<ConfigParams> for UpdateProposalChanges {
+    type Error = FromUpdateConfigParamsError;
+    fn try_from(params: ConfigParams) -> Result<Self, Self::Error> {
+        let mut changes = UpdateProposalChanges::default();
+
+        for param in params.iter().cloned() {
+            match param {
+                ConfigParam::Block0Date(_) => return not_updatable("block0_date"),
+                ConfigParam::Discrimination(_) => return not_updatable("discrimination"),
+                ConfigParam::ConsensusVersion(_) => return not_updatable("block0_consensus"),
+                ConfigParam::TreasuryAdd(_) => return not_updatable("treasury"),
+                ConfigParam::TreasuryParams(_) => return not_updatable("treasury_parameters"),
+                ConfigParam::RewardPot(_) => return not_updatable("rewards"),
+                ConfigParam::RewardParams(_) => return not_updatable("reward_parameters"),
+                cp @ ConfigParam::SlotsPerEpoch(_) => changes
+                    .slots_per_epoch
+                    .replace(NumberOfSlotsPerEpoch::try_from(cp)?)
+                    .map(|_| "slots_per_epoch"),
+                cp @ ConfigParam::SlotDuration(_) => changes
+                    .slot_duration
+                    .replace(SlotDuration::try_from(cp)?)
+                    .map(|_| "slot_duration"),
+                cp @ ConfigParam::KESUpdateSpeed(_) => changes
+                    .kes_update_speed
+                    .replace(KESUpdateSpeed::try_from(cp)?)
+                    .map(|_| "kes_update_speed"),
+                cp @ ConfigParam::ConsensusGenesisPraosActiveSlotsCoeff(_) => changes
+                    .consensus_genesis_praos_active_slot_coeff
+                    .replace(ActiveSlotCoefficient::try_from(cp)?)
+                    .map(|_| "consensus_genesis_praos_active_slot_coeff"),
+                cp @ ConfigParam::BftSlotsRatio(_) => changes
+                    .bft_slots_ratio
+                    .replace(BFTSlotsRatio::try_from(cp)?)
+                    .map(|_| "bft_slots_ratio"),
+                ConfigParam::LinearFee(param) => {
+                    changes.linear_fees.replace(param).map(|_| "linear_fees")
+                }
+                ConfigParam::MaxNumberOfTransactionsPerBlock(param) => changes
+                    .max_number_of_transactions_per_block
+                    .replace(param)
+                    .map(|_| "max_number_of_transactions_per_block"),
+                ConfigParam::EpochStabilityDepth(param) => changes
+                    .epoch_stability_depth
+                    .replace(param)
+                    .map(|_| "epoch_stability_depth"),
+                ConfigParam::ProposalExpiration(param) => changes
+                    .proposal_expiration
+                    .replace(param)
+                    .map(|_| "proposal_expiration"),
+                ConfigParam::AddBftLeader(leader_id) => {
+                    changes.add_bft_leaders.push(ConsensusLeaderId(leader_id));
+                    None
+                }
+                ConfigParam::RemoveBftLeader(leader_id) => {
+                    changes
+                        .remove_bft_leaders
+                        .push(ConsensusLeaderId(leader_id));
+                    None
+                }
+            }
+            .map(|name| Err(FromUpdateConfigParamsError::Duplicate { name }))
+            .unwrap_or(Ok(()))?;
+        }
+
+        Ok(changes)
+    }
+}
+
+fn not_updatable<T>(name: &'static str) -> Result<T, FromUpdateConfigParamsError> {
+    Err(FromUpdateConfigParamsError::NotUpdatable { name })
+}
+
+#[derive(Serialize, Deserialize)]
+struct LinearFeeWrapper(#[serde(with = "LinearFeeDef")] LinearFee);
+
+fn serialize_linear_fees<S>(
+    linear_fees: &Option<LinearFee>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    linear_fees
+        .clone()
+        .map(LinearFeeWrapper)
+        .serialize(serializer)
+}
+
+fn deserialize_linear_fees<'de, D>(deserializer: D) -> Result<Option<LinearFee>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<LinearFeeWrapper>::deserialize(deserializer).map(|fees| fees.map(|fees| fees.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_impl_mockchain::config::Block0Date;
+    use quickcheck::{Arbitrary, Gen};
+
+    impl Arbitrary for UpdateProposalChanges {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            UpdateProposalChanges {
+                slots_per_epoch: Arbitrary::arbitrary(g),
+                slot_duration: Arbitrary::arbitrary(g),
+                kes_update_speed: Arbitrary::arbitrary(g),
+                consensus_genesis_praos_active_slot_coeff: Arbitrary::arbitrary(g),
+                bft_slots_ratio: Arbitrary::arbitrary(g),
+                linear_fees: if bool::arbitrary(g) {
+                    Some(LinearFee::new(
+                        u64::arbitrary(g),
+                        u64::arbitrary(g),
+                        u64::arbitrary(g),
+                    ))
+                } else {
+                    None
+                },
+                max_number_of_transactions_per_block: Arbitrary::arbitrary(g),
+                epoch_stability_depth: Arbitrary::arbitrary(g),
+                proposal_expiration: Arbitrary::arbitrary(g),
+                add_bft_leaders: Arbitrary::arbitrary(g),
+                remove_bft_leaders: Arbitrary::arbitrary(g),
+            }
+        }
+    }
+
+    quickcheck! {
+        fn serde_encode_decode(changes: UpdateProposalChanges) -> bool {
+            let s = serde_yaml::to_string(&changes).unwrap();
+            let changes_dec: UpdateProposalChanges = serde_yaml::from_str(&s).unwrap();
+
+            changes == changes_dec
+        }
+
+        fn convert_from_to_config_param(changes: UpdateProposalChanges) -> bool {
+            let cps = ConfigParams::from(changes.clone());
+            let changes_dec = UpdateProposalChanges::try_from(cps).unwrap();
+
+            changes == changes_dec
+        }
+    }
+
+    #[test]
+    fn settings_of_the_block0_cannot_be_changed() {
+        let mut params = ConfigParams::new();
+        params.push(ConfigParam::Block0Date(Block0Date(0)));
+        match UpdateProposalChanges::try_from(params) {
+            Err(FromUpdateConfigParamsError::NotUpdatable { name }) => {
+                assert_eq!(name, "block0_date")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn settings_are_changed_once() {
+        let mut params = ConfigParams::new();
+        params.push(ConfigParam::ProposalExpiration(10));
+        params.push(ConfigParam::ProposalExpiration(20));
+        match UpdateProposalChanges::try_from(params) {
+            Err(FromUpdateConfigParamsError::Duplicate { name }) => {
+                assert_eq!(name, "proposal_expiration")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::time::Duration;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -12,6 +13,44 @@ pub struct Mempool {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check of the mempool and the log cache.
     pub garbage_collection_interval: Duration,
+    /// keep the fragment logs in a file too, so they are still available
+    /// after a restart of the node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistent_log: Option<PersistentLog>,
+    /// the limits of the fragments received from the network or the REST API,
+    /// the fragments exceeding them being rejected before reaching the pool
+    #[serde(default)]
+    pub fragment_limits: FragmentLimits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PersistentLog {
+    /// the file the fragment logs are appended to
+    pub path: PathBuf,
+    /// for how long a fragment log is kept in the file after its last update
+    pub retention: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FragmentLimits {
+    /// the maximum size of a fragment, in bytes
+    pub max_size: u32,
+    /// the maximum number of inputs of a transaction
+    pub max_inputs: u8,
+    /// the maximum number of outputs of a transaction
+    pub max_outputs: u8,
+}
+
+impl Default for FragmentLimits {
+    fn default() -> Self {
+        FragmentLimits {
+            max_size: 32 * 1024,
+            max_inputs: 255,
+            max_outputs: 255,
+        }
+    }
 }
 
 impl Default for Mempool {
@@ -20,6 +59,8 @@ impl Default for Mempool {
             fragment_ttl: Duration::new(30 * 60, 0),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            persistent_log: None,
+            fragment_limits: FragmentLimits::default(),
         }
     }
 }
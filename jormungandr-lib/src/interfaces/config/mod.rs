@@ -1,3 +1,10 @@
 mod mempool;
+mod node;
+mod secret;
 
-pub use mempool::Mempool;
+pub use mempool::{FragmentLimits, Mempool, PersistentLog};
+pub use node::{
+    Explorer, ListenMode, Log, LogEntry, LogOutput, NodeConfig, NodeConfigBuilder, P2p, Rest,
+    TopicsOfInterest, Transport, TrustedPeer,
+};
+pub use secret::{BftSecret, GenesisPraosSecret, NodeSecret};
@@ -0,0 +1,320 @@
+use crate::interfaces::Mempool;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, path::PathBuf};
+
+/// the configuration file of a node, with the settings the tools starting
+/// nodes write. The settings left out keep the node defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_files: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<Log>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rest: Option<Rest>,
+    pub p2p: P2p,
+    #[serde(default)]
+    pub mempool: Mempool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explorer: Option<Explorer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Log(pub Vec<LogEntry>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// `off`, `critical`, `error`, `warn`, `info`, `debug` or `trace`
+    pub level: Option<String>,
+    /// `plain` or `json`
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<LogOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    Stdout,
+    Stderr,
+    Syslog,
+    /// a syslog server on the network
+    #[serde(rename = "remote_syslog")]
+    RemoteSyslog {
+        server: SocketAddr,
+        #[serde(default)]
+        transport: Transport,
+        /// the name of the node host in the messages
+        hostname: String,
+    },
+    Journald,
+    /// a Graylog server, `backend` being its host and port
+    Gelf {
+        backend: String,
+        log_id: String,
+        #[serde(default)]
+        transport: Transport,
+    },
+    File(PathBuf),
+}
+
+/// the protocol of the log outputs sending the messages over the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Udp
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rest {
+    pub listen: SocketAddr,
+    /// the token of the administrative endpoints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct P2p {
+    /// the multiaddr of the node, `/ip4/127.0.0.1/tcp/8299` for instance,
+    /// empty for a client-only node
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub public_address: String,
+    pub public_id: String,
+    /// the multiaddr to listen on, if different from the public address
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub listen_address: String,
+    /// `none` for a client-only node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<ListenMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trusted_peers: Option<Vec<TrustedPeer>>,
+    pub topics_of_interest: TopicsOfInterest,
+    /// required to connect nodes on the private networks, as the test
+    /// networks are
+    #[serde(default)]
+    pub allow_private_addresses: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedPeer {
+    pub address: String,
+    pub id: String,
+}
+
+/// the interest levels, `low`, `normal` or `high`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicsOfInterest {
+    pub messages: String,
+    pub blocks: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explorer {
+    pub enabled: bool,
+}
+
+/// builder of the configuration file of a node
+#[derive(Debug, Clone)]
+pub struct NodeConfigBuilder {
+    config: NodeConfig,
+}
+
+impl NodeConfigBuilder {
+    /// a node listening on its public address, with a high interest in the
+    /// messages and blocks
+    pub fn new<S: Into<String>>(public_address: S, public_id: S) -> Self {
+        let public_address = public_address.into();
+        NodeConfigBuilder {
+            config: NodeConfig {
+                secret_files: Vec::new(),
+                storage: None,
+                log: None,
+                rest: None,
+                p2p: P2p {
+                    listen_address: public_address.clone(),
+                    public_address,
+                    listen: None,
+                    public_id: public_id.into(),
+                    trusted_peers: None,
+                    topics_of_interest: TopicsOfInterest {
+                        messages: "high".to_owned(),
+                        blocks: "high".to_owned(),
+                    },
+                    allow_private_addresses: false,
+                },
+                mempool: Mempool::default(),
+                explorer: None,
+            },
+        }
+    }
+
+    pub fn add_secret_file(&mut self, secret_file: PathBuf) -> &mut Self {
+        self.config.secret_files.push(secret_file);
+        self
+    }
+
+    pub fn with_storage(&mut self, storage: PathBuf) -> &mut Self {
+        self.config.storage = Some(storage);
+        self
+    }
+
+    pub fn with_log(&mut self, log: Log) -> &mut Self {
+        self.config.log = Some(log);
+        self
+    }
+
+    pub fn with_rest(&mut self, listen: SocketAddr) -> &mut Self {
+        self.config.rest = Some(Rest {
+            listen,
+            admin_token: None,
+        });
+        self
+    }
+
+    /// the admin token of the REST API, to be set after `with_rest`
+    pub fn with_admin_token<S: Into<String>>(&mut self, admin_token: S) -> &mut Self {
+        if let Some(rest) = self.config.rest.as_mut() {
+            rest.admin_token = Some(admin_token.into());
+        }
+        self
+    }
+
+    pub fn with_listen_address<S: Into<String>>(&mut self, listen_address: S) -> &mut Self {
+        self.config.p2p.listen_address = listen_address.into();
+        self
+    }
+
+    /// a node not listening for the connections of the peers
+    pub fn with_client_only(&mut self) -> &mut Self {
+        self.config.p2p.public_address.clear();
+        self.config.p2p.listen_address.clear();
+        self.config.p2p.listen = Some(ListenMode::None);
+        self
+    }
+
+    pub fn with_trusted_peers(&mut self, trusted_peers: Vec<TrustedPeer>) -> &mut Self {
+        self.config.p2p.trusted_peers = Some(trusted_peers);
+        self
+    }
+
+    pub fn with_topics_of_interest(&mut self, topics_of_interest: TopicsOfInterest) -> &mut Self {
+        self.config.p2p.topics_of_interest = topics_of_interest;
+        self
+    }
+
+    pub fn with_allow_private_addresses(&mut self, allow_private_addresses: bool) -> &mut Self {
+        self.config.p2p.allow_private_addresses = allow_private_addresses;
+        self
+    }
+
+    pub fn with_mempool(&mut self, mempool: Mempool) -> &mut Self {
+        self.config.mempool = mempool;
+        self
+    }
+
+    pub fn with_explorer(&mut self, enabled: bool) -> &mut Self {
+        self.config.explorer = Some(Explorer { enabled });
+        self
+    }
+
+    pub fn build(&self) -> NodeConfig {
+        self.config.clone()
+    }
+}
+
+/// whether the node accepts the connections of the peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenMode {
+    /// on the listen address, or else on the public address
+    Address,
+    /// never, the node runs as a client only
+    None,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn built_config_serde_yaml_encode_decode() {
+        let config = NodeConfigBuilder::new(
+            "/ip4/127.0.0.1/tcp/8299",
+            "ed25519_pk1hdhe4mnus0uxaf25gxeryskvwtytlzeuvan8glp3n63ztvv0v78qczpm32",
+        )
+        .with_storage(PathBuf::from("storage"))
+        .with_log(Log(vec![LogEntry {
+            level: Some("info".to_owned()),
+            format: Some("json".to_owned()),
+            output: Some(LogOutput::File(PathBuf::from("node.log"))),
+        }]))
+        .with_rest("127.0.0.1:8443".parse().unwrap())
+        .with_admin_token("token")
+        .with_trusted_peers(vec![TrustedPeer {
+            address: "/ip4/127.0.0.1/tcp/8300".to_owned(),
+            id: "ed25519_pk1hdhe4mnus0uxaf25gxeryskvwtytlzeuvan8glp3n63ztvv0v78qczpm32".to_owned(),
+        }])
+        .build();
+
+        let s = serde_yaml::to_string(&config).unwrap();
+        let config_dec: NodeConfig = serde_yaml::from_str(&s).unwrap();
+
+        assert_eq!(config, config_dec);
+    }
+
+    #[test]
+    fn network_log_outputs_have_the_node_format() {
+        let gelf: LogOutput = serde_yaml::from_str(
+            "gelf:\n  backend: \"graylog:12201\"\n  log_id: node\n  transport: tcp\n",
+        )
+        .unwrap();
+        assert_eq!(
+            gelf,
+            LogOutput::Gelf {
+                backend: "graylog:12201".to_owned(),
+                log_id: "node".to_owned(),
+                transport: Transport::Tcp,
+            }
+        );
+
+        let syslog = LogOutput::RemoteSyslog {
+            server: "10.0.0.1:514".parse().unwrap(),
+            transport: Transport::Udp,
+            hostname: "node".to_owned(),
+        };
+        let s = serde_yaml::to_string(&syslog).unwrap();
+        let value: serde_yaml::Value = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(
+            value["remote_syslog"]["server"],
+            serde_yaml::Value::from("10.0.0.1:514")
+        );
+        assert_eq!(serde_yaml::from_str::<LogOutput>(&s).unwrap(), syslog);
+    }
+
+    #[test]
+    fn client_only_config_has_no_address() {
+        let config = NodeConfigBuilder::new(
+            "/ip4/127.0.0.1/tcp/8299",
+            "ed25519_pk1hdhe4mnus0uxaf25gxeryskvwtytlzeuvan8glp3n63ztvv0v78qczpm32",
+        )
+        .with_client_only()
+        .build();
+
+        let s = serde_yaml::to_string(&config.p2p).unwrap();
+        let p2p: serde_yaml::Value = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(p2p["listen"], serde_yaml::Value::from("none"));
+        assert!(p2p.get("public_address").is_none());
+        assert!(p2p.get("listen_address").is_none());
+        let p2p_dec: P2p = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(p2p_dec.listen, Some(ListenMode::None));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::crypto::{hash::Hash, key::SigningKey};
+use chain_crypto::{Curve25519_2HashDH, Ed25519, SumEd25519_12};
+use serde::{Deserialize, Serialize};
+
+/// the secret file of a node, given with `--secret`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeSecret {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bft: Option<BftSecret>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genesis: Option<GenesisPraosSecret>,
+}
+
+/// the key of a BFT leader
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BftSecret {
+    pub signing_key: SigningKey<Ed25519>,
+}
+
+/// the keys of a stake pool, `node_id` being the ID of its registration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisPraosSecret {
+    pub node_id: Hash,
+    pub sig_key: SigningKey<SumEd25519_12>,
+    pub vrf_key: SigningKey<Curve25519_2HashDH>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn bft_secret_serde_yaml_encode_decode() {
+        let signing_key = SigningKey::<Ed25519>::generate(StdRng::from_seed([1; 32]));
+        let secret = NodeSecret {
+            bft: Some(BftSecret {
+                signing_key: signing_key.clone(),
+            }),
+            genesis: None,
+        };
+
+        let s = serde_yaml::to_string(&secret).unwrap();
+        let value: serde_yaml::Value = serde_yaml::from_str(&s).unwrap();
+        assert!(value.get("genesis").is_none());
+        let secret_dec: NodeSecret = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(
+            secret_dec.bft.unwrap().signing_key.to_bech32_str(),
+            signing_key.to_bech32_str()
+        );
+    }
+}
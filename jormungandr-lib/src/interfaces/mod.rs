@@ -7,12 +7,16 @@ mod config;
 mod fragment_log;
 mod leadership_log;
 mod linear_fee;
+mod multisig_declaration;
 mod old_address;
+mod rewards;
 mod settings;
+mod stake_snapshot;
 mod stats;
 mod transaction_input;
 mod transaction_output;
 mod transaction_witness;
+mod update_proposal;
 mod utxo_info;
 mod value;
 
@@ -30,11 +34,17 @@ pub use self::leadership_log::{
     EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
 };
 pub use self::linear_fee::LinearFeeDef;
+pub use self::multisig_declaration::{
+    MultisigDeclaration, MultisigDeclarationError, MULTISIG_OWNERS_MAX,
+};
 pub use self::old_address::OldAddress;
+pub use self::rewards::{AccountReward, EpochRewards, PoolRewards};
 pub use self::settings::*;
-pub use self::stats::{NodeState, Stats};
+pub use self::stake_snapshot::{PoolStake, StakeSnapshot};
+pub use self::stats::{EpochProduction, MissedSlots, NodeState, PoolStats, Stats};
 pub use self::transaction_input::{TransactionInput, TransactionInputType};
 pub use self::transaction_output::TransactionOutput;
 pub use self::transaction_witness::TransactionWitness;
+pub use self::update_proposal::{UpdateProposalState, UpdateProposalTally, UpdateProposals};
 pub use self::utxo_info::{UTxOInfo, UTxOOutputInfo};
 pub use self::value::Value;
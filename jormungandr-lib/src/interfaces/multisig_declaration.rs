@@ -0,0 +1,154 @@
+use crate::crypto::key::Identifier;
+use chain_crypto::{Ed25519, PublicKey};
+use chain_impl_mockchain::multisig::{DeclElement, Declaration, Index, TreeIndex};
+use serde::{Deserialize, Serialize};
+
+/// the maximum number of owners of a multisig account
+pub const MULTISIG_OWNERS_MAX: usize = 8;
+
+custom_error! {pub MultisigDeclarationError
+    NoOwner = "a multisig account needs at least one owner",
+    TooManyOwners { owners: usize } = "{owners} owners, a multisig account has at most 8",
+    ThresholdInvalid { threshold: u8, owners: usize }
+        = "a threshold of {threshold} signatures is invalid for {owners} owners",
+    DuplicateOwner { owner: String } = "the owner {owner} is declared more than once",
+}
+
+/// the owners of a multisig account and the number of their signatures
+/// needed to spend from it
+///
+/// The order of the owners gives the index of their signatures in the
+/// witnesses of the account, and changing it changes the address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultisigDeclaration {
+    pub threshold: u8,
+    pub owners: Vec<Identifier<Ed25519>>,
+}
+
+impl MultisigDeclaration {
+    pub fn new(
+        threshold: u8,
+        owners: Vec<Identifier<Ed25519>>,
+    ) -> Result<Self, MultisigDeclarationError> {
+        let declaration = MultisigDeclaration { threshold, owners };
+        declaration.check()?;
+        Ok(declaration)
+    }
+
+    /// check the declaration can be registered in the ledger, to be done
+    /// on the declarations read from a file
+    pub fn check(&self) -> Result<(), MultisigDeclarationError> {
+        let owners = self.owners.len();
+        if owners == 0 {
+            return Err(MultisigDeclarationError::NoOwner);
+        }
+        if owners > MULTISIG_OWNERS_MAX {
+            return Err(MultisigDeclarationError::TooManyOwners { owners });
+        }
+        if self.threshold == 0 || self.threshold as usize > owners {
+            return Err(MultisigDeclarationError::ThresholdInvalid {
+                threshold: self.threshold,
+                owners,
+            });
+        }
+        for (i, owner) in self.owners.iter().enumerate() {
+            if self.owners[..i].contains(owner) {
+                return Err(MultisigDeclarationError::DuplicateOwner {
+                    owner: owner.to_bech32_str(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_declaration(&self) -> Declaration {
+        let owners = self
+            .owners
+            .iter()
+            .map(|owner| DeclElement::from_publickey(owner.as_ref()))
+            .collect();
+        Declaration::new(self.threshold, owners)
+    }
+
+    /// the identifier of the account, as found in its address
+    pub fn identifier(&self) -> [u8; 32] {
+        self.to_declaration().to_identifier().into()
+    }
+
+    /// the index of the signature of the given owner in the witnesses of
+    /// the account, `None` if the key is not one of the owners
+    pub fn owner_index(&self, key: &PublicKey<Ed25519>) -> Option<TreeIndex> {
+        self.owners
+            .iter()
+            .position(|owner| owner.as_ref() == key)
+            .and_then(|position| Index::from_u8(position as u8))
+            .map(TreeIndex::D1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_crypto::SecretKey;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    fn owners(count: usize) -> Vec<Identifier<Ed25519>> {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        (0..count)
+            .map(|_| SecretKey::<Ed25519>::generate(&mut rng).to_public().into())
+            .collect()
+    }
+
+    #[test]
+    fn declaration_is_read_from_yaml() {
+        let declaration = MultisigDeclaration::new(2, owners(3)).unwrap();
+        let yaml = serde_yaml::to_string(&declaration).unwrap();
+        let decoded: MultisigDeclaration = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(decoded, declaration);
+        assert!(yaml.contains(&declaration.owners[0].to_bech32_str()));
+    }
+
+    #[test]
+    fn invalid_declarations_are_refused() {
+        match MultisigDeclaration::new(1, Vec::new()) {
+            Err(MultisigDeclarationError::NoOwner) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match MultisigDeclaration::new(1, owners(MULTISIG_OWNERS_MAX + 1)) {
+            Err(MultisigDeclarationError::TooManyOwners { owners }) => {
+                assert_eq!(owners, MULTISIG_OWNERS_MAX + 1)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        for threshold in &[0, 4] {
+            match MultisigDeclaration::new(*threshold, owners(3)) {
+                Err(MultisigDeclarationError::ThresholdInvalid { .. }) => (),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        let mut duplicated = owners(2);
+        duplicated.push(duplicated[0].clone());
+        match MultisigDeclaration::new(2, duplicated) {
+            Err(MultisigDeclarationError::DuplicateOwner { .. }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn owners_are_indexed_in_order() {
+        let owners = owners(4);
+        let declaration = MultisigDeclaration::new(2, owners[..3].to_vec()).unwrap();
+        for (position, owner) in owners[..3].iter().enumerate() {
+            assert_eq!(
+                declaration.owner_index(owner.as_ref()),
+                Index::from_u8(position as u8).map(TreeIndex::D1)
+            );
+        }
+        assert_eq!(declaration.owner_index(owners[3].as_ref()), None);
+
+        let other = MultisigDeclaration::new(3, owners[..3].to_vec()).unwrap();
+        assert_ne!(other.identifier(), declaration.identifier());
+    }
+}
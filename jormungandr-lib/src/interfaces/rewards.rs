@@ -0,0 +1,80 @@
+use crate::{crypto::hash::Hash, interfaces::Value};
+use chain_crypto::PublicKey;
+use chain_impl_mockchain::{account::AccountAlg, ledger::EpochRewardsInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// the rewards distributed at the start of an epoch, for the blocks of the
+/// previous epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct EpochRewards {
+    /// the epoch the rewards were distributed at the start of
+    pub epoch: u32,
+    /// the value taken from the reward pot
+    pub drawn: Value,
+    /// the fees of the previous epoch added to the rewards
+    pub fees: Value,
+    /// the part of the rewards sent to the treasury
+    pub treasury: Value,
+    pub stake_pools: BTreeMap<Hash, PoolRewards>,
+    /// the reward of each account, by account public key
+    pub accounts: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct PoolRewards {
+    /// the rewards of the owners of the pool, the pool's tax
+    pub owners: Value,
+    /// the rewards of the accounts delegating to the pool
+    pub delegators: Value,
+}
+
+/// the reward received by an account at the start of an epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct AccountReward {
+    pub epoch: u32,
+    pub value: Value,
+}
+
+impl EpochRewards {
+    pub fn new(epoch: u32, info: &EpochRewardsInfo) -> Self {
+        EpochRewards {
+            epoch,
+            drawn: info.drawn.into(),
+            fees: info.fees.into(),
+            treasury: info.treasury.into(),
+            stake_pools: info
+                .stake_pools
+                .iter()
+                .map(|(pool_id, (owners, delegators))| {
+                    (
+                        pool_id.clone().into(),
+                        PoolRewards {
+                            owners: (*owners).into(),
+                            delegators: (*delegators).into(),
+                        },
+                    )
+                })
+                .collect(),
+            accounts: info
+                .accounts
+                .iter()
+                .map(|(account, value)| {
+                    let key: PublicKey<AccountAlg> = account.clone().into();
+                    (key.to_string(), (*value).into())
+                })
+                .collect(),
+        }
+    }
+
+    /// the reward of the account of the given public key, in hexadecimal
+    pub fn account_reward(&self, account: &str) -> Option<AccountReward> {
+        self.accounts.get(account).map(|value| AccountReward {
+            epoch: self.epoch,
+            value: *value,
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{crypto::hash::Hash, interfaces::Value};
+use chain_crypto::PublicKey;
+use chain_impl_mockchain::{account::AccountAlg, stake::StakeDistribution};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// the stake distribution the leaders of an epoch are elected with, as
+/// taken at the transition to the epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct StakeSnapshot {
+    pub epoch: u32,
+    /// the stake of the accounts not delegating to any pool
+    pub unassigned: Value,
+    /// the stake of the accounts delegating to a pool which does not exist
+    pub dangling: Value,
+    pub stake_pools: BTreeMap<Hash, PoolStake>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct PoolStake {
+    pub total: Value,
+    /// the stake of each account delegating to the pool, by account public
+    /// key
+    pub delegators: BTreeMap<String, Value>,
+}
+
+impl StakeSnapshot {
+    pub fn new(epoch: u32, distribution: &StakeDistribution) -> Self {
+        StakeSnapshot {
+            epoch,
+            unassigned: distribution.unassigned.0.into(),
+            dangling: distribution.dangling.0.into(),
+            stake_pools: distribution
+                .to_pools
+                .iter()
+                .map(|(pool_id, pool)| {
+                    let delegators = pool
+                        .stake_owners
+                        .accounts
+                        .iter()
+                        .map(|(account, stake)| {
+                            let key: PublicKey<AccountAlg> = account.clone().into();
+                            (key.to_string(), stake.0.into())
+                        })
+                        .collect();
+                    (
+                        pool_id.clone().into(),
+                        PoolStake {
+                            total: pool.total.total_stake.0.into(),
+                            delegators,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// the stake of each delegator, one line per pool and account after a
+    /// header line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("epoch,pool_id,account,stake\n");
+        for (pool_id, pool) in &self.stake_pools {
+            for (account, stake) in &pool.delegators {
+                writeln!(csv, "{},{},{},{}", self.epoch, pool_id, account, stake).unwrap();
+            }
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_has_a_line_per_delegator() {
+        let pool_id = Hash::from([1; 32]);
+        let mut delegators = BTreeMap::new();
+        delegators.insert("aa".to_owned(), Value::from(10));
+        delegators.insert("bb".to_owned(), Value::from(32));
+        let mut stake_pools = BTreeMap::new();
+        stake_pools.insert(
+            pool_id,
+            PoolStake {
+                total: Value::from(42),
+                delegators,
+            },
+        );
+        let snapshot = StakeSnapshot {
+            epoch: 7,
+            unassigned: Value::from(0),
+            dangling: Value::from(0),
+            stake_pools,
+        };
+
+        let csv = snapshot.to_csv();
+
+        let pool_id = pool_id.to_string();
+        assert_eq!(
+            csv,
+            format!(
+                "epoch,pool_id,account,stake\n7,{0},aa,10\n7,{0},bb,32\n",
+                pool_id
+            )
+        );
+    }
+}
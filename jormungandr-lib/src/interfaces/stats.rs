@@ -15,6 +15,79 @@ pub struct Stats {
     pub last_block_tx: u32,
     pub last_block_sum: u32,
     pub last_block_fees: u32,
+    #[serde(default)]
+    pub pool_stats: Option<PoolStats>,
+    /// how far the clock of the node was ahead of the time servers at the
+    /// last sampling, in milliseconds
+    #[serde(default)]
+    pub clock_drift_millis: Option<i64>,
+    /// the blocks requested by the peers found in the cache of the recent
+    /// blocks, and the ones not found
+    #[serde(default)]
+    pub block_cache_hits: u64,
+    #[serde(default)]
+    pub block_cache_misses: u64,
+    /// the connections of the peers established, and the ones being
+    /// established
+    #[serde(default)]
+    pub peer_connections_established: u64,
+    #[serde(default)]
+    pub peer_connections_connecting: u64,
+    /// the node does not listen for the connections of the peers
+    #[serde(default)]
+    pub client_only: bool,
+    /// the space left on the storage volume at the last check, in bytes
+    #[serde(default)]
+    pub disk_space_available: Option<u64>,
+    /// the space left is below the hard threshold, the new fragments are
+    /// refused
+    #[serde(default)]
+    pub disk_space_low: bool,
+}
+
+/// operational metrics of the leaders running on the node
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct PoolStats {
+    /// slots scheduled and blocks produced, for the latest epochs
+    pub epochs: Vec<EpochProduction>,
+    /// average time between the creation of one of our blocks and the
+    /// first time a peer fetched it, in milliseconds
+    pub avg_propagation_delay_ms: Option<u64>,
+    pub missed_slots: MissedSlots,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct EpochProduction {
+    pub epoch: u32,
+    pub scheduled: u64,
+    pub produced: u64,
+}
+
+/// slots our leaders were elected for but did not create a block for,
+/// by reason
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct MissedSlots {
+    /// the tip was already at or past the slot
+    pub tip_behind: u64,
+    /// the slot was over when the node woke up for it
+    pub too_late: u64,
+    /// the enclave failed to sign the block
+    pub enclave_error: u64,
+    /// building the block did not fit in the slot
+    pub timeout: u64,
+    /// the node was not synced yet
+    #[serde(default)]
+    pub syncing: u64,
+    /// the clock of the node drifted from the time servers
+    #[serde(default)]
+    pub clock_drift: u64,
+    /// the tip was too many slots behind the slot, see
+    /// `leadership.max_slots_behind`
+    #[serde(default)]
+    pub stale_tip: u64,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -24,5 +97,7 @@ pub enum NodeState {
     PreparingBlock0,
     Bootstrapping,
     StartingWorkers,
+    Syncing,
     Running,
+    ShuttingDown,
 }
@@ -0,0 +1,45 @@
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{BlockDate, ConsensusLeaderId, UpdateProposalChanges},
+};
+use serde::{Deserialize, Serialize};
+
+/// the update proposals of the BFT leaders still open to their votes, in the
+/// state of the tip of the blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct UpdateProposals {
+    /// the number of BFT leaders voting on the proposals
+    pub bft_leaders: usize,
+    /// the number of votes accepting a proposal, more than half of the
+    /// BFT leaders
+    pub votes_needed: usize,
+    pub proposals: Vec<UpdateProposalState>,
+    /// the last tally of the votes, at the start of an epoch
+    pub last_tally: Option<UpdateProposalTally>,
+}
+
+/// an update proposal and the BFT leaders who voted for it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct UpdateProposalState {
+    /// the ID of the fragment of the proposal, voted for by the leaders
+    pub id: Hash,
+    pub proposer: ConsensusLeaderId,
+    pub proposal_date: BlockDate,
+    /// the last epoch the votes are tallied at, the proposal expiring
+    /// after that
+    pub last_tally_epoch: u32,
+    /// none if the proposal changes settings which cannot be updated
+    pub changes: Option<UpdateProposalChanges>,
+    pub votes: Vec<ConsensusLeaderId>,
+}
+
+/// the proposals accepted and the ones expired at the start of an epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct UpdateProposalTally {
+    pub epoch: u32,
+    pub accepted: Vec<Hash>,
+    pub expired: Vec<Hash>,
+}
@@ -4,6 +4,11 @@ extern crate quickcheck;
 #[macro_use(custom_error)]
 extern crate custom_error;
 
+#[cfg(feature = "client")]
+pub mod client;
 pub mod crypto;
+pub mod fragment;
 pub mod interfaces;
+pub mod schedule;
 pub mod time;
+pub mod transaction;
@@ -0,0 +1,328 @@
+//! recurring times, in the format of the crontab
+//!
+//! A schedule is five fields separated by spaces: the minute (0-59), the hour
+//! (0-23), the day of the month (1-31), the month (1-12) and the day of the
+//! week (0-7, Sunday being 0 or 7). A field is `*`, a value, a range `1-5`,
+//! a list `1,3,5`, or any of them with a step as in `*/15` or `0-30/10`.
+//!
+//! As with cron, when both the day of the month and the day of the week are
+//! restricted, a day matching either of them is in the schedule. The times
+//! are in UTC. A schedule matching no day, as `0 0 31 2 *`, is invalid.
+//!
+//! # Example
+//!
+//! ```
+//! # use jormungandr_lib::schedule::Schedule;
+//!
+//! // at 03:30 every day, and every 15 minutes from 01:00 to 04:45 on Sundays
+//! let daily: Schedule = "30 3 * * *".parse().unwrap();
+//! let sundays: Schedule = "*/15 1-4 * * 0".parse().unwrap();
+//! ```
+
+use chrono::{prelude::*, Duration as ChronoDuration};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt, str,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// the times are looked for within this number of days, a schedule only
+/// matching a 29th of February on a given day of the week fits in it
+const SEARCH_DAYS: i64 = 366 * 28;
+
+custom_error! {pub ScheduleError
+    FieldCount { count: usize } = "expected 5 fields in the schedule, found {count}",
+    InvalidField { field: &'static str, value: String } = "invalid {field} field: '{value}'",
+    NoDay { schedule: String } = "no day matches the schedule '{schedule}'",
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+}
+
+const MINUTE: Field = Field {
+    name: "minute",
+    min: 0,
+    max: 59,
+};
+const HOUR: Field = Field {
+    name: "hour",
+    min: 0,
+    max: 23,
+};
+const DAY: Field = Field {
+    name: "day of month",
+    min: 1,
+    max: 31,
+};
+const MONTH: Field = Field {
+    name: "month",
+    min: 1,
+    max: 12,
+};
+const WEEKDAY: Field = Field {
+    name: "day of week",
+    min: 0,
+    max: 7,
+};
+
+impl Schedule {
+    /// the first time of the schedule strictly after the given one, to the
+    /// minute. None if the given time is before the UNIX epoch
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        let mut next = Utc.timestamp(secs - secs % 60 + 60, 0);
+        let limit = next + ChronoDuration::days(SEARCH_DAYS);
+        while next < limit {
+            if !self.matches_day(&next) {
+                next = next.date().succ().and_hms(0, 0, 0);
+            } else if self.hours & (1 << next.hour()) == 0 {
+                next = next.date().and_hms(next.hour(), 0, 0) + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << next.minute()) == 0 {
+                next = next + ChronoDuration::minutes(1);
+            } else {
+                return Some(UNIX_EPOCH + Duration::from_secs(next.timestamp() as u64));
+            }
+        }
+        None
+    }
+
+    /// whether some day of some year is in the schedule. A day of a month
+    /// falls on every day of the week over the years, so only the days of
+    /// the month required on top of the day of the week can exclude all
+    /// the days, when none of them is in the months
+    fn matches_some_day(&self) -> bool {
+        if !self.any_day && !self.any_weekday {
+            return true;
+        }
+        (1..=12)
+            .filter(|month| self.months & (1 << month) != 0)
+            .any(|month| {
+                let last_day = match month {
+                    2 => 29,
+                    4 | 6 | 9 | 11 => 30,
+                    _ => 31,
+                };
+                (1..=last_day).any(|day| self.days & (1 << day) != 0)
+            })
+    }
+
+    fn matches_day(&self, time: &DateTime<Utc>) -> bool {
+        let month = self.months & (1 << time.month()) != 0;
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        month
+            && if self.any_day || self.any_weekday {
+                day && weekday
+            } else {
+                day || weekday
+            }
+    }
+}
+
+/// the values of the field as a bit set, and whether it is unrestricted
+fn parse_field(field: &Field, value: &str) -> Result<(u64, bool), ScheduleError> {
+    let invalid = || ScheduleError::InvalidField {
+        field: field.name,
+        value: value.to_owned(),
+    };
+    let number = |s: &str| s.parse::<u32>().map_err(|_| invalid());
+    let mut set = 0;
+    for part in value.split(',') {
+        let mut split = part.splitn(2, '/');
+        let range = split.next().unwrap_or("");
+        let step = match split.next() {
+            Some(step) => Some(number(step)?)
+                .filter(|step| *step > 0)
+                .ok_or_else(invalid)?,
+            None => 1,
+        };
+        let (start, end) = if range == "*" {
+            (field.min, field.max)
+        } else {
+            let mut bounds = range.splitn(2, '-');
+            let start = number(bounds.next().unwrap_or(""))?;
+            match bounds.next() {
+                Some(end) => (start, number(end)?),
+                // `5/10` is every 10 from 5
+                None if step > 1 => (start, field.max),
+                None => (start, start),
+            }
+        };
+        if start < field.min || end > field.max || start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+    Ok((set, value.starts_with('*')))
+}
+
+/* --------------------- Display ------------------------------------------- */
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl str::FromStr for Schedule {
+    type Err = ScheduleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(ScheduleError::FieldCount {
+                count: fields.len(),
+            });
+        }
+        let (minutes, _) = parse_field(&MINUTE, fields[0])?;
+        let (hours, _) = parse_field(&HOUR, fields[1])?;
+        let (days, any_day) = parse_field(&DAY, fields[2])?;
+        let (months, _) = parse_field(&MONTH, fields[3])?;
+        let (mut weekdays, any_weekday) = parse_field(&WEEKDAY, fields[4])?;
+        // Sunday is either 0 or 7
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        let schedule = Schedule {
+            source: fields.join(" "),
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day,
+            any_weekday,
+        };
+        if schedule.matches_some_day() {
+            Ok(schedule)
+        } else {
+            Err(ScheduleError::NoDay {
+                schedule: schedule.source,
+            })
+        }
+    }
+}
+
+/* --------------------- Serde --------------------------------------------- */
+
+impl Serialize for Schedule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.source.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(s: &str) -> SystemTime {
+        let time: DateTime<Utc> = s.parse().unwrap();
+        UNIX_EPOCH + Duration::from_secs(time.timestamp() as u64)
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<SystemTime> {
+        schedule.parse::<Schedule>().unwrap().next_after(at(after))
+    }
+
+    #[test]
+    fn next_time_of_the_schedule() {
+        assert_eq!(
+            next("30 3 * * *", "2020-01-10T02:00:00Z"),
+            Some(at("2020-01-10T03:30:00Z"))
+        );
+        // strictly after the given time
+        assert_eq!(
+            next("30 3 * * *", "2020-01-10T03:30:00Z"),
+            Some(at("2020-01-11T03:30:00Z"))
+        );
+        assert_eq!(
+            next("*/15 1-4 * * *", "2020-01-10T04:50:10Z"),
+            Some(at("2020-01-11T01:00:00Z"))
+        );
+        // the 10th of January 2020 is a Friday
+        assert_eq!(
+            next("0 2 * * 7", "2020-01-10T00:00:00Z"),
+            Some(at("2020-01-12T02:00:00Z"))
+        );
+        // either the day of the month or the day of the week
+        assert_eq!(
+            next("0 0 15 * 1", "2020-01-10T00:00:00Z"),
+            Some(at("2020-01-13T00:00:00Z"))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2020-03-01T00:00:00Z"),
+            Some(at("2024-02-29T00:00:00Z"))
+        );
+        // the 29th of February 2032 is the next one on a Sunday
+        assert_eq!(
+            next("0 0 29 2 */7", "2020-03-01T00:00:00Z"),
+            Some(at("2032-02-29T00:00:00Z"))
+        );
+        // the 31st of February if it is a Monday, so every Monday of February
+        assert_eq!(
+            next("0 0 31 2 1", "2020-01-10T00:00:00Z"),
+            Some(at("2020-02-03T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_schedules() {
+        for schedule in &[
+            "* * * *",
+            "60 * * * *",
+            "* 5-2 * * *",
+            "*/0 * * * *",
+            "a * * * *",
+            "0 0 31 2 *",
+            "0 0 30,31 2 *",
+            "0 0 31 4,6,9,11 *",
+            "0 0 31 2 */2",
+        ] {
+            assert!(
+                schedule.parse::<Schedule>().is_err(),
+                "'{}' is not a valid schedule",
+                schedule
+            );
+        }
+    }
+
+    #[test]
+    fn serde_schedule() {
+        let schedule: Schedule = serde_yaml::from_str("\"0  3 * * 1-5\"").unwrap();
+        assert_eq!(schedule.to_string(), "0 3 * * 1-5");
+        let encoded = serde_yaml::to_string(&schedule).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Schedule>(&encoded).unwrap(),
+            schedule
+        );
+    }
+}
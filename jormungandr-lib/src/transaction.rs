@@ -0,0 +1,621 @@
+//! building and signing transactions in-process
+//!
+//! The steps of `jcli transaction`, without the staging file:
+//!
+//! 1. [`TransactionBuilder`] takes the inputs, the outputs and the optional
+//!    certificate, and is finalized with the fee algorithm of the blockchain,
+//!    balancing the transaction with the output policy;
+//! 2. [`TransactionWitnessing`] takes one witness per input, in the order of
+//!    the inputs, made from the data to sign and the secret keys;
+//! 3. [`SealedTransaction`] is the transaction to send, as a fragment. The
+//!    certificates of the stake delegations and of the stake pools are signed
+//!    by the account or the pool owners there.
+//!
+//! [`TransactionBuilder`]: ./struct.TransactionBuilder.html
+//! [`TransactionWitnessing`]: ./struct.TransactionWitnessing.html
+//! [`SealedTransaction`]: ./struct.SealedTransaction.html
+
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{TransactionInput, TransactionInputType},
+};
+use chain_addr::{Address, Kind};
+use chain_crypto::{Ed25519, Ed25519Bip32, PublicKey, SecretKey};
+use chain_impl_mockchain::{
+    account::SpendingCounter,
+    certificate::{
+        Certificate, CertificatePayload, PoolOwnersSigned, PoolRegistration, PoolSignature,
+        StakeDelegation,
+    },
+    fee::FeeAlgorithm,
+    fragment::Fragment,
+    header::HeaderId,
+    key::EitherEd25519SecretKey,
+    transaction::{
+        self, AccountBindingSignature, Balance, Input, InputOutputBuilder, NoExtra, Output,
+        OutputPolicy, Payload, SetAuthData, SetIOs, SingleAccountBindingSignature, Transaction,
+        TransactionSignDataHash, TxBuilder, TxBuilderState, UnspecifiedAccountIdentifier, Witness,
+    },
+    value::{Value, ValueError},
+};
+use std::convert::TryFrom;
+
+custom_error! {pub Error
+    AccountAddressSingle = "invalid input account, this is a UTxO address",
+    AccountAddressGroup = "invalid input account, this is a UTxO address with delegation",
+    InputOutputCountInvalid = "too many inputs or outputs in the transaction",
+    ValueInvalid { source: ValueError } = "the values of the transaction overflow",
+    FinalizationFailed { source: transaction::Error } = "transaction finalization failed",
+    TooManyWitnesses { max: usize } = "the transaction has all its {max} witnesses",
+    WitnessCountInvalid { actual: usize, expected: usize }
+        = "invalid number of witnesses in the transaction: {actual}, should be {expected}",
+    PayloadAuthNeeded = "the certificate of the transaction needs to be signed",
+    PayloadAuthNotNeeded = "the transaction has no certificate to sign",
+    SigningKeysMissing = "no signing keys given to sign the certificate",
+    SigningKeyCountInvalid { actual: usize } = "expecting one signing key, got {actual}",
+    SigningKeyNotFound { index: usize } = "the signing key {index} is not expected by the certificate",
+}
+
+/// the inputs, outputs and certificate of a transaction being balanced
+#[derive(Debug, Clone, Default)]
+pub struct TransactionBuilder {
+    inputs: Vec<Input>,
+    outputs: Vec<Output<Address>>,
+    certificate: Option<Certificate>,
+}
+
+/// a balanced transaction waiting for the witnesses of its inputs
+#[derive(Debug, Clone)]
+pub struct TransactionWitnessing {
+    inputs: Vec<Input>,
+    outputs: Vec<Output<Address>>,
+    certificate: Option<Certificate>,
+    witnesses: Vec<Witness>,
+}
+
+/// a transaction with all its witnesses
+#[derive(Debug, Clone)]
+pub struct SealedTransaction {
+    inputs: Vec<Input>,
+    outputs: Vec<Output<Address>>,
+    certificate: Option<Certificate>,
+    witnesses: Vec<Witness>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_input(&mut self, input: Input) -> &mut Self {
+        self.inputs.push(input);
+        self
+    }
+
+    /// spend the output `output_index` of the transaction `fragment_id`
+    pub fn add_utxo_input(
+        &mut self,
+        fragment_id: Hash,
+        output_index: u8,
+        value: Value,
+    ) -> &mut Self {
+        self.add_input(
+            TransactionInput {
+                input: TransactionInputType::Utxo(fragment_id.into(), output_index),
+                value: value.into(),
+            }
+            .into(),
+        )
+    }
+
+    /// spend `value` from the account of the given address
+    pub fn add_account_input(
+        &mut self,
+        account: &Address,
+        value: Value,
+    ) -> Result<&mut Self, Error> {
+        let account_id = match account.kind() {
+            Kind::Account(key) => {
+                UnspecifiedAccountIdentifier::from_single_account(key.clone().into())
+            }
+            Kind::Multisig(key) => {
+                UnspecifiedAccountIdentifier::from_multi_account(key.clone().into())
+            }
+            Kind::Single(_) => return Err(Error::AccountAddressSingle),
+            Kind::Group(_, _) => return Err(Error::AccountAddressGroup),
+        };
+        Ok(self.add_input(
+            TransactionInput {
+                input: TransactionInputType::Account(account_id.into()),
+                value: value.into(),
+            }
+            .into(),
+        ))
+    }
+
+    pub fn add_output(&mut self, address: Address, value: Value) -> &mut Self {
+        self.outputs.push(Output { address, value });
+        self
+    }
+
+    pub fn set_certificate(&mut self, certificate: Certificate) -> &mut Self {
+        self.certificate = Some(certificate);
+        self
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Output<Address>] {
+        &self.outputs
+    }
+
+    /// the fees of the transaction as it is now
+    pub fn fees(&self, fee_algorithm: &impl FeeAlgorithm) -> Result<Value, Error> {
+        fees(
+            &self.inputs,
+            &self.outputs,
+            self.certificate.as_ref(),
+            fee_algorithm,
+        )
+    }
+
+    /// the value left once the outputs and the fees are paid by the inputs
+    pub fn balance(&self, fee_algorithm: &impl FeeAlgorithm) -> Result<Balance, Error> {
+        let fees = self.fees(fee_algorithm)?;
+        let inputs = Value::sum(self.inputs.iter().map(|input| input.value))?;
+        let outputs = Value::sum(self.outputs.iter().map(|output| output.value))?;
+        let spent = (outputs + fees)?;
+        if inputs > spent {
+            Ok(Balance::Positive((inputs - spent)?))
+        } else if inputs < spent {
+            Ok(Balance::Negative((spent - inputs)?))
+        } else {
+            Ok(Balance::Zero)
+        }
+    }
+
+    /// balance the transaction, the outputs added by the output policy are
+    /// appended to the outputs of the transaction
+    pub fn finalize(
+        mut self,
+        fee_algorithm: &impl FeeAlgorithm,
+        output_policy: OutputPolicy,
+    ) -> Result<(TransactionWitnessing, Balance), Error> {
+        let (balance, added_outputs) = match self.certificate.clone() {
+            None => self.seal(&NoExtra, fee_algorithm, output_policy)?,
+            Some(Certificate::PoolRegistration(c)) => {
+                self.seal(&c, fee_algorithm, output_policy)?
+            }
+            Some(Certificate::PoolUpdate(c)) => self.seal(&c, fee_algorithm, output_policy)?,
+            Some(Certificate::PoolRetirement(c)) => self.seal(&c, fee_algorithm, output_policy)?,
+            Some(Certificate::StakeDelegation(c)) => self.seal(&c, fee_algorithm, output_policy)?,
+            Some(Certificate::OwnerStakeDelegation(c)) => {
+                self.seal(&c, fee_algorithm, output_policy)?
+            }
+        };
+        self.outputs.extend(added_outputs);
+        let transaction = TransactionWitnessing {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            certificate: self.certificate,
+            witnesses: Vec::new(),
+        };
+        Ok((transaction, balance))
+    }
+
+    fn seal<P: Payload>(
+        &self,
+        payload: &P,
+        fee_algorithm: &impl FeeAlgorithm,
+        output_policy: OutputPolicy,
+    ) -> Result<(Balance, Vec<Output<Address>>), Error> {
+        let ios = input_output_builder(&self.inputs, &self.outputs)?;
+        let payload_data = payload.payload_data();
+        let (balance, added_outputs, _) =
+            ios.seal_with_output_policy(payload_data.borrow(), fee_algorithm, output_policy)?;
+        Ok((balance, added_outputs))
+    }
+}
+
+impl TransactionWitnessing {
+    /// the data the witnesses sign
+    pub fn sign_data_hash(&self) -> TransactionSignDataHash {
+        match self.certificate.as_ref() {
+            None => sign_data_hash(
+                &self.inputs,
+                &self.outputs,
+                TxBuilder::new().set_nopayload(),
+            ),
+            Some(Certificate::PoolRegistration(c)) => {
+                sign_data_hash(&self.inputs, &self.outputs, TxBuilder::new().set_payload(c))
+            }
+            Some(Certificate::PoolUpdate(c)) => {
+                sign_data_hash(&self.inputs, &self.outputs, TxBuilder::new().set_payload(c))
+            }
+            Some(Certificate::PoolRetirement(c)) => {
+                sign_data_hash(&self.inputs, &self.outputs, TxBuilder::new().set_payload(c))
+            }
+            Some(Certificate::StakeDelegation(c)) => {
+                sign_data_hash(&self.inputs, &self.outputs, TxBuilder::new().set_payload(c))
+            }
+            Some(Certificate::OwnerStakeDelegation(c)) => {
+                sign_data_hash(&self.inputs, &self.outputs, TxBuilder::new().set_payload(c))
+            }
+        }
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Output<Address>] {
+        &self.outputs
+    }
+
+    /// add the witness of the next input
+    pub fn add_witness(&mut self, witness: Witness) -> Result<&mut Self, Error> {
+        if self.witnesses.len() >= self.inputs.len() {
+            return Err(Error::TooManyWitnesses {
+                max: self.inputs.len(),
+            });
+        }
+        self.witnesses.push(witness);
+        Ok(self)
+    }
+
+    /// add the witness of the next input, a UTxO of an Ed25519 address
+    pub fn witness_utxo(
+        &mut self,
+        block0_hash: &HeaderId,
+        secret_key: &EitherEd25519SecretKey,
+    ) -> Result<&mut Self, Error> {
+        let witness = Witness::new_utxo(block0_hash, &self.sign_data_hash(), secret_key);
+        self.add_witness(witness)
+    }
+
+    /// add the witness of the next input, a UTxO of a legacy address
+    pub fn witness_legacy_utxo(
+        &mut self,
+        block0_hash: &HeaderId,
+        secret_key: SecretKey<Ed25519Bip32>,
+    ) -> Result<&mut Self, Error> {
+        let witness = Witness::new_old_utxo(block0_hash, &self.sign_data_hash(), secret_key);
+        self.add_witness(witness)
+    }
+
+    /// add the witness of the next input, an account whose counter is
+    /// `spending_counter`
+    pub fn witness_account(
+        &mut self,
+        block0_hash: &HeaderId,
+        spending_counter: SpendingCounter,
+        secret_key: &EitherEd25519SecretKey,
+    ) -> Result<&mut Self, Error> {
+        let witness = Witness::new_account(
+            block0_hash,
+            &self.sign_data_hash(),
+            &spending_counter,
+            secret_key,
+        );
+        self.add_witness(witness)
+    }
+
+    pub fn seal(self) -> Result<SealedTransaction, Error> {
+        if self.witnesses.len() != self.inputs.len() {
+            return Err(Error::WitnessCountInvalid {
+                actual: self.witnesses.len(),
+                expected: self.inputs.len(),
+            });
+        }
+        Ok(SealedTransaction {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            certificate: self.certificate,
+            witnesses: self.witnesses,
+        })
+    }
+}
+
+impl SealedTransaction {
+    /// whether the certificate has to be signed, see `auth_fragment`
+    pub fn need_auth(&self) -> bool {
+        self.certificate
+            .as_ref()
+            .map_or(false, |certificate| certificate.need_auth())
+    }
+
+    /// the fragment of a transaction whose certificate, if any, is not signed
+    pub fn fragment(&self) -> Result<Fragment, Error> {
+        match self.certificate.as_ref() {
+            None => Ok(self.make_fragment(&NoExtra, &(), Fragment::Transaction)),
+            Some(Certificate::OwnerStakeDelegation(c)) => {
+                Ok(self.make_fragment(c, &(), Fragment::OwnerStakeDelegation))
+            }
+            Some(_) => Err(Error::PayloadAuthNeeded),
+        }
+    }
+
+    /// the fragment of a transaction whose certificate is signed with the
+    /// given keys: the key of the delegating account, or the keys of the pool
+    /// owners
+    pub fn auth_fragment(&self, keys: &[EitherEd25519SecretKey]) -> Result<Fragment, Error> {
+        if keys.is_empty() {
+            return Err(Error::SigningKeysMissing);
+        }
+        match self.certificate.clone() {
+            None | Some(Certificate::OwnerStakeDelegation(_)) => Err(Error::PayloadAuthNotNeeded),
+            Some(Certificate::StakeDelegation(c)) => {
+                let builder = self.builder_after_witness(TxBuilder::new().set_payload(&c));
+                let signature = stake_delegation_sign(&c, keys, builder)?;
+                Ok(self.make_fragment(&c, &signature, Fragment::StakeDelegation))
+            }
+            Some(Certificate::PoolRegistration(c)) => {
+                let builder = self.builder_after_witness(TxBuilder::new().set_payload(&c));
+                let signature = pool_owners_sign(Some(&c), keys, builder)?;
+                Ok(self.make_fragment(&c, &signature, Fragment::PoolRegistration))
+            }
+            Some(Certificate::PoolRetirement(c)) => {
+                let builder = self.builder_after_witness(TxBuilder::new().set_payload(&c));
+                let signature = pool_owners_sign(None, keys, builder)?;
+                Ok(self.make_fragment(&c, &signature, Fragment::PoolRetirement))
+            }
+            Some(Certificate::PoolUpdate(c)) => {
+                let builder = self.builder_after_witness(TxBuilder::new().set_payload(&c));
+                let signature = pool_owners_sign(None, keys, builder)?;
+                Ok(self.make_fragment(&c, &signature, Fragment::PoolUpdate))
+            }
+        }
+    }
+
+    fn builder_after_witness<P: Payload>(
+        &self,
+        builder: TxBuilderState<SetIOs<P>>,
+    ) -> TxBuilderState<SetAuthData<P>> {
+        builder
+            .set_ios(&self.inputs, &self.outputs)
+            .set_witnesses(&self.witnesses)
+    }
+
+    fn make_fragment<P: Payload, F>(&self, payload: &P, auth: &P::Auth, to_fragment: F) -> Fragment
+    where
+        F: FnOnce(Transaction<P>) -> Fragment,
+    {
+        let builder = self.builder_after_witness(TxBuilder::new().set_payload(payload));
+        to_fragment(builder.set_payload_auth(auth))
+    }
+}
+
+fn input_output_builder(
+    inputs: &[Input],
+    outputs: &[Output<Address>],
+) -> Result<InputOutputBuilder, Error> {
+    InputOutputBuilder::new(inputs.iter(), outputs.iter())
+        .map_err(|_| Error::InputOutputCountInvalid)
+}
+
+fn fees(
+    inputs: &[Input],
+    outputs: &[Output<Address>],
+    certificate: Option<&Certificate>,
+    fee_algorithm: &impl FeeAlgorithm,
+) -> Result<Value, Error> {
+    let certificate_payload = certificate.map(CertificatePayload::from);
+    let certificate_slice = certificate_payload
+        .as_ref()
+        .map(CertificatePayload::as_slice);
+    let inputs = u8::try_from(inputs.len()).map_err(|_| Error::InputOutputCountInvalid)?;
+    let outputs = u8::try_from(outputs.len()).map_err(|_| Error::InputOutputCountInvalid)?;
+    Ok(fee_algorithm.calculate(certificate_slice, inputs, outputs))
+}
+
+fn sign_data_hash<P>(
+    inputs: &[Input],
+    outputs: &[Output<Address>],
+    builder: TxBuilderState<SetIOs<P>>,
+) -> TransactionSignDataHash {
+    builder
+        .set_ios(inputs, outputs)
+        .get_auth_data_for_witness()
+        .hash()
+}
+
+fn stake_delegation_sign(
+    delegation: &StakeDelegation,
+    keys: &[EitherEd25519SecretKey],
+    builder: TxBuilderState<SetAuthData<StakeDelegation>>,
+) -> Result<AccountBindingSignature, Error> {
+    let key = match keys {
+        [key] => key,
+        keys => return Err(Error::SigningKeyCountInvalid { actual: keys.len() }),
+    };
+    if let Some(account_id) = delegation.account_id.to_single_account() {
+        let account_key: PublicKey<Ed25519> = account_id.into();
+        if account_key != key.to_public() {
+            return Err(Error::SigningKeyNotFound { index: 0 });
+        }
+    }
+    Ok(AccountBindingSignature::new_single(
+        key,
+        &builder.get_auth_data(),
+    ))
+}
+
+/// sign with the keys of the owners, matched with the owners of the pool
+/// registration if given, or else in the order of the owners
+fn pool_owners_sign<P: Payload>(
+    registration: Option<&PoolRegistration>,
+    keys: &[EitherEd25519SecretKey],
+    builder: TxBuilderState<SetAuthData<P>>,
+) -> Result<PoolSignature, Error> {
+    let mut indexed_keys = Vec::with_capacity(keys.len());
+    for (index, key) in keys.iter().enumerate() {
+        let owner_index = match registration {
+            None => index,
+            Some(registration) => {
+                let public_key = key.to_public();
+                registration
+                    .owners
+                    .iter()
+                    .position(|owner| owner == &public_key)
+                    .ok_or(Error::SigningKeyNotFound { index })?
+            }
+        };
+        indexed_keys.push((owner_index as u8, key));
+    }
+    let auth_data = builder.get_auth_data();
+    let signatures = indexed_keys
+        .into_iter()
+        .map(|(index, key)| (index, SingleAccountBindingSignature::new(key, &auth_data)))
+        .collect();
+    Ok(PoolSignature::Owners(PoolOwnersSigned { signatures }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_addr::Discrimination;
+    use chain_impl_mockchain::fee::LinearFee;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng as _;
+
+    /// 2 + 1 per input and output
+    fn fee_algorithm() -> LinearFee {
+        LinearFee::new(2, 1, 0)
+    }
+
+    fn secret_key(seed: u8) -> SecretKey<Ed25519> {
+        SecretKey::generate(&mut ChaChaRng::from_seed([seed; 32]))
+    }
+
+    fn address(seed: u8) -> Address {
+        Address(
+            Discrimination::Test,
+            Kind::Single(secret_key(seed).to_public()),
+        )
+    }
+
+    fn builder(input: u64, outputs: &[u64]) -> TransactionBuilder {
+        let mut builder = TransactionBuilder::new();
+        builder.add_utxo_input(Hash::from([1; 32]), 0, Value(input));
+        for (seed, output) in outputs.iter().enumerate() {
+            builder.add_output(address(seed as u8), Value(*output));
+        }
+        builder
+    }
+
+    fn balance_value(balance: Balance) -> (&'static str, u64) {
+        match balance {
+            Balance::Positive(value) => ("positive", value.0),
+            Balance::Negative(value) => ("negative", value.0),
+            Balance::Zero => ("zero", 0),
+        }
+    }
+
+    #[test]
+    fn fees_follow_the_inputs_and_outputs() {
+        assert_eq!(builder(100, &[]).fees(&fee_algorithm()).unwrap(), Value(3));
+        assert_eq!(
+            builder(100, &[10, 10]).fees(&fee_algorithm()).unwrap(),
+            Value(5)
+        );
+    }
+
+    #[test]
+    fn fees_of_too_many_outputs_are_refused() {
+        let outputs = vec![1; 256];
+        match builder(100, &outputs).fees(&fee_algorithm()) {
+            Err(Error::InputOutputCountInvalid) => {}
+            res => panic!("unexpected outcome {:?}", res),
+        }
+    }
+
+    #[test]
+    fn balance_accounts_for_the_fees() {
+        let fees = fee_algorithm();
+        let balance =
+            |outputs: &[u64]| balance_value(builder(100, outputs).balance(&fees).unwrap());
+        assert_eq!(balance(&[96]), ("zero", 0));
+        assert_eq!(balance(&[50]), ("positive", 46));
+        assert_eq!(balance(&[100]), ("negative", 4));
+    }
+
+    #[test]
+    fn change_output_takes_the_balance_left() {
+        let change = address(100);
+        let (transaction, balance) = builder(100, &[50])
+            .finalize(&fee_algorithm(), OutputPolicy::One(change.clone()))
+            .unwrap();
+        assert_eq!(balance_value(balance), ("zero", 0));
+        // the change output pays for its own fee
+        let outputs = transaction.outputs();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[1].address, change);
+        assert_eq!(outputs[1].value, Value(45));
+    }
+
+    #[test]
+    fn balance_left_is_forgotten_without_change() {
+        let (transaction, balance) = builder(100, &[50])
+            .finalize(&fee_algorithm(), OutputPolicy::Forget)
+            .unwrap();
+        assert_eq!(balance_value(balance), ("positive", 46));
+        assert_eq!(transaction.outputs().len(), 1);
+    }
+
+    #[test]
+    fn balanced_transaction_gets_no_change() {
+        let (transaction, balance) = builder(100, &[96])
+            .finalize(&fee_algorithm(), OutputPolicy::One(address(100)))
+            .unwrap();
+        assert_eq!(balance_value(balance), ("zero", 0));
+        assert_eq!(transaction.outputs().len(), 1);
+    }
+
+    #[test]
+    fn insufficient_funds_are_refused() {
+        assert!(builder(100, &[97])
+            .finalize(&fee_algorithm(), OutputPolicy::Forget)
+            .is_err());
+        assert!(builder(2, &[])
+            .finalize(&fee_algorithm(), OutputPolicy::One(address(100)))
+            .is_err());
+    }
+
+    #[test]
+    fn account_input_needs_an_account_address() {
+        match TransactionBuilder::new().add_account_input(&address(0), Value(10)) {
+            Err(Error::AccountAddressSingle) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn one_witness_per_input() {
+        let (mut transaction, _) = builder(100, &[96])
+            .finalize(&fee_algorithm(), OutputPolicy::Forget)
+            .unwrap();
+        match transaction.clone().seal() {
+            Err(Error::WitnessCountInvalid {
+                actual: 0,
+                expected: 1,
+            }) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        let block0_hash = Hash::from([0; 32]).into_hash();
+        let secret_key = EitherEd25519SecretKey::Normal(secret_key(0));
+        transaction.witness_utxo(&block0_hash, &secret_key).unwrap();
+        match transaction.witness_utxo(&block0_hash, &secret_key) {
+            Err(Error::TooManyWitnesses { max: 1 }) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        let sealed = transaction.seal().unwrap();
+        assert!(!sealed.need_auth());
+        match sealed.fragment().unwrap() {
+            Fragment::Transaction(_) => {}
+            _ => panic!("not a transaction fragment"),
+        }
+    }
+}
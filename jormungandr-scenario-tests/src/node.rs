@@ -353,7 +353,21 @@ impl NodeController {
     }
 
     pub fn shutdown(&self) -> Result<bool> {
-        let result = self.get("shutdown")?.text()?;
+        self.progress_bar.log_info("GET 'shutdown'");
+
+        let result = reqwest::Client::new()
+            .get(&format!("{}/shutdown", self.base_url()))
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Bearer {}", self.settings.config.rest.admin_token),
+            )
+            .send()
+            .map_err(|err| {
+                self.progress_bar
+                    .log_err(format!("Failed to send request {}", &err));
+                err
+            })?
+            .text()?;
 
         if result == "Success" {
             self.progress_bar.log_info("shuting down");
@@ -415,8 +429,17 @@ impl Node {
         )
         .chain_err(|| format!("cannot write in {:?}", config_file))?;
 
+        // the node refuses the secret files all users can read
+        let mut secret_options = std::fs::OpenOptions::new();
+        secret_options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            secret_options.mode(0o600);
+        }
         serde_yaml::to_writer(
-            std::fs::File::create(&config_secret)
+            secret_options
+                .open(&config_secret)
                 .chain_err(|| format!("Cannot create file {:?}", config_secret))?,
             node_settings.secrets(),
         )
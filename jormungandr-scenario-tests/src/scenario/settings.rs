@@ -5,7 +5,7 @@ use crate::{
     },
     style, NodeAlias, Wallet, WalletAlias, WalletType,
 };
-use chain_crypto::{Curve25519_2HashDH, Ed25519, SumEd25519_12};
+use chain_crypto::Ed25519;
 use chain_impl_mockchain::{
     block::ConsensusVersion,
     certificate::{PoolPermissions, PoolSignature},
@@ -16,8 +16,11 @@ use chain_impl_mockchain::{
 };
 use chain_time::DurationSeconds;
 use jormungandr_lib::{
-    crypto::{hash::Hash, key::SigningKey},
-    interfaces::{Block0Configuration, BlockchainConfiguration, Initial, InitialUTxO},
+    crypto::key::SigningKey,
+    interfaces::{
+        BftSecret, Block0Configuration, BlockchainConfiguration, GenesisPraosSecret, Initial,
+        InitialUTxO, NodeSecret,
+    },
 };
 use rand_core::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
@@ -63,6 +66,9 @@ pub struct NodeConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rest {
     pub listen: SocketAddr,
+    /// the node refuses the administrative requests (such as shutdown)
+    /// without a token
+    pub admin_token: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,28 +91,6 @@ pub struct TrustedPeer {
     id: poldercast::Id,
 }
 
-/// Node Secret(s)
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct NodeSecret {
-    bft: Option<Bft>,
-    genesis: Option<GenesisPraos>,
-}
-
-/// hold the node's bft secret setting
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct Bft {
-    signing_key: SigningKey<Ed25519>,
-}
-
-/// the genesis praos setting
-///
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct GenesisPraos {
-    node_id: Hash,
-    sig_key: SigningKey<SumEd25519_12>,
-    vrf_key: SigningKey<Curve25519_2HashDH>,
-}
-
 impl Settings {
     pub fn prepare<RNG>(
         topology: TopologyTemplate,
@@ -214,7 +198,7 @@ impl Settings {
                             },
                         };
                         let node_id = stake_pool_info.to_id();
-                        node.secret.genesis = Some(GenesisPraos {
+                        node.secret.genesis = Some(GenesisPraosSecret {
                             sig_key: kes_signing_key,
                             vrf_key: vrf_signing_key,
                             node_id: {
@@ -287,7 +271,7 @@ impl Settings {
                     } else {
                         let signing_key = SigningKey::generate(context.rng_mut());
                         let identifier = signing_key.identifier();
-                        node.secret.bft = Some(Bft { signing_key });
+                        node.secret.bft = Some(BftSecret { signing_key });
                         identifier
                     }
                 } else {
@@ -375,7 +359,10 @@ impl NodeSetting {
         NodeSetting {
             alias,
             config: NodeConfig::prepare(context),
-            secret: NodeSecret::prepare(context),
+            secret: NodeSecret {
+                bft: None,
+                genesis: None,
+            },
             node_topology: template,
         }
     }
@@ -410,18 +397,6 @@ impl NodeSetting {
     }
 }
 
-impl NodeSecret {
-    pub fn prepare<RNG>(_context: &mut Context<RNG>) -> Self
-    where
-        RNG: RngCore + CryptoRng,
-    {
-        NodeSecret {
-            bft: None,
-            genesis: None,
-        }
-    }
-}
-
 impl NodeConfig {
     pub fn prepare<RNG>(context: &mut Context<RNG>) -> Self
     where
@@ -440,8 +415,11 @@ impl Rest {
     where
         RNG: RngCore,
     {
+        let mut admin_token = [0; 16];
+        context.rng_mut().fill_bytes(&mut admin_token);
         Rest {
             listen: context.generate_new_rest_listen_address(),
+            admin_token: hex::encode(admin_token),
         }
     }
 }
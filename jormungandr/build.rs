@@ -1,3 +1,11 @@
+use std::{env, path::PathBuf, process::Command};
+
+/// the protocol files of the standard services served next to the node
+/// service
+const STANDARD_PROTOS: [&str; 2] = ["proto/health.proto", "proto/reflection.proto"];
+/// the protocol file of the node service, described by the reflection
+const NODE_PROTO_DIR: &str = "../chain-deps/network-grpc/proto";
+
 fn main() {
     let version = versionisator::Version::new(
         env!("CARGO_MANIFEST_DIR"),
@@ -8,4 +16,35 @@ fn main() {
     println!("cargo:rustc-env=FULL_VERSION={}", version.full());
     println!("cargo:rustc-env=SIMPLE_VERSION={}", version.simple());
     println!("cargo:rustc-env=SOURCE_VERSION={}", version.hash());
+
+    compile_standard_services();
+}
+
+/// generate the servers of the standard services and write the descriptors
+/// of all the services served, for the reflection
+fn compile_standard_services() {
+    tower_grpc_build::Config::new()
+        .enable_server(true)
+        .enable_client(false)
+        .build(&STANDARD_PROTOS, &["proto"])
+        .unwrap_or_else(|e| panic!("failed to compile the standard services: {}", e));
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let status = Command::new(prost_build::protoc())
+        .arg("--include_imports")
+        .arg("-o")
+        .arg(out_dir.join("services.bin"))
+        .arg("-I")
+        .arg("proto")
+        .arg("-I")
+        .arg(NODE_PROTO_DIR)
+        .arg("-I")
+        .arg(prost_build::protoc_include())
+        .args(&["node.proto", "health.proto", "reflection.proto"])
+        .status()
+        .expect("failed to run protoc");
+    assert!(
+        status.success(),
+        "protoc failed to write the descriptors of the services"
+    );
 }
@@ -1,5 +1,6 @@
 pub use network_core::gossip::Gossip;
 
+use chain_addr::Discrimination;
 pub use chain_impl_mockchain::{
     block::{Block, ConsensusVersion},
     config::{self, Block0Date, ConfigParam},
@@ -29,12 +30,15 @@ custom_error! {pub Block0Malformed
     NoDiscrimination = "missing `discrimination' value in the block0",
     NoSlotDuration = "missing `slot_duration' value in the block0",
     NoSlotsPerEpoch = "missing `slots_per_epoch' value in the block0",
+    NoKesUpdateSpeed = "missing `kes_update_speed' value in the block0",
 }
 
 pub trait Block0DataSource {
     fn slot_duration(&self) -> Result<Duration, Block0Error>;
     fn slots_per_epoch(&self) -> Result<u32, Block0Error>;
     fn start_time(&self) -> Result<SystemTime, Block0Error>;
+    fn kes_update_speed(&self) -> Result<Duration, Block0Error>;
+    fn discrimination(&self) -> Result<Discrimination, Block0Error>;
 }
 
 impl Block0DataSource for Block {
@@ -64,6 +68,24 @@ impl Block0DataSource for Block {
         }
         Err(Block0Malformed::NoStartTime.into())
     }
+
+    fn kes_update_speed(&self) -> Result<Duration, Block0Error> {
+        for config in initial(self)?.iter() {
+            if let ConfigParam::KESUpdateSpeed(speed) = config {
+                return Ok(Duration::from_secs(*speed as u64));
+            }
+        }
+        Err(Block0Malformed::NoKesUpdateSpeed.into())
+    }
+
+    fn discrimination(&self) -> Result<Discrimination, Block0Error> {
+        for config in initial(self)?.iter() {
+            if let ConfigParam::Discrimination(discrimination) = config {
+                return Ok(*discrimination);
+            }
+        }
+        Err(Block0Malformed::NoDiscrimination.into())
+    }
 }
 
 fn initial(block: &Block) -> Result<&ConfigParams, Block0Malformed> {
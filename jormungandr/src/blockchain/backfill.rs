@@ -0,0 +1,147 @@
+//! the backfill of the chain index
+//!
+//! The blocks of the main chain not indexed yet, all of them for a new or
+//! rebuilt index, are indexed in the background once the node is started.
+//! Until the index reaches the tip, the blocks added to the main chain are
+//! left to the backfill and the index is not queried. With `check`, the index
+//! is first compared with the storage and repaired. A failed backfill is
+//! logged and run again after `RETRY_DELAY`, from where it stopped.
+
+use crate::{
+    blockcfg::HeaderHash,
+    blockchain::{Blockchain, IndexError, Tip},
+    utils::task::TokioServiceInfo,
+};
+use chain_storage::error::Error as StorageError;
+use slog::Logger;
+use std::time::{Duration, Instant};
+use tokio::{prelude::*, timer::Delay};
+
+/// the wait before running a failed backfill again
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct Backfill {
+    blockchain: Blockchain,
+    tip: Tip,
+    block0_hash: HeaderHash,
+    check: bool,
+}
+
+impl Backfill {
+    pub fn new(blockchain: Blockchain, tip: Tip, block0_hash: HeaderHash, check: bool) -> Self {
+        Backfill {
+            blockchain,
+            tip,
+            block0_hash,
+            check,
+        }
+    }
+
+    pub fn run(self, info: TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        future::loop_fn(self, move |backfill| {
+            let error_logger = logger.clone();
+            backfill.run_once(logger.clone()).then(move |res| match res {
+                Ok(()) => future::Either::A(future::ok(future::Loop::Break(()))),
+                Err((backfill, e)) => {
+                    error!(
+                        error_logger,
+                        "the chain index backfill failed, the index is not used until it is run again";
+                        "reason" => %e,
+                        "retry_in" => ?RETRY_DELAY,
+                    );
+                    future::Either::B(
+                        Delay::new(Instant::now() + RETRY_DELAY)
+                            .map_err(move |e| error!(error_logger, "timer error"; "reason" => %e))
+                            .map(move |()| future::Loop::Continue(backfill)),
+                    )
+                }
+            })
+        })
+    }
+
+    /// check and backfill the index, returning the backfill on failure to
+    /// run it again
+    fn run_once(self, logger: Logger) -> impl Future<Item = (), Error = (Self, IndexError)> {
+        let check_logger = logger.clone();
+        let retry = self.clone();
+        let subscriptions = self.blockchain.subscriptions().clone();
+        let index = self
+            .blockchain
+            .index()
+            .expect("backfill of a disabled chain index")
+            .clone();
+        let storage = self.blockchain.storage().clone();
+        let tip = self.tip;
+        let check = self.check;
+
+        let check_tip = tip.clone();
+        let check_index = index.clone();
+        let check_storage = storage.clone();
+
+        storage
+            .get(self.block0_hash)
+            .map_err(IndexError::from)
+            .and_then(|block0| block0.ok_or_else(|| IndexError::from(StorageError::BlockNotFound)))
+            .and_then(move |block0| {
+                if !check {
+                    return future::Either::A(future::ok(block0));
+                }
+                info!(check_logger, "checking the chain index against the storage");
+                future::Either::B(
+                    check_tip
+                        .get_ref()
+                        .and_then(move |tip_ref| {
+                            check_index
+                                .check(check_storage, block0.clone(), tip_ref.hash())
+                                .map(move |report| (block0, report))
+                        })
+                        .map(move |(block0, report)| {
+                            if report.repaired() {
+                                warn!(
+                                    check_logger,
+                                    "the chain index drifted from the storage, repaired";
+                                    "checked" => report.checked,
+                                    "removed_blocks" => report.removed_blocks,
+                                    "removed_entries" => report.removed_entries,
+                                );
+                            } else {
+                                info!(
+                                    check_logger,
+                                    "the chain index is consistent with the storage";
+                                    "checked" => report.checked,
+                                );
+                            }
+                            block0
+                        }),
+                )
+            })
+            .and_then(move |block0| {
+                info!(logger, "backfilling the chain index");
+                future::loop_fn((), move |()| {
+                    let index = index.clone();
+                    let catch_up_index = index.clone();
+                    let storage = storage.clone();
+                    let subscriptions = subscriptions.clone();
+                    let block0 = block0.clone();
+                    let logger = logger.clone();
+                    tip.get_ref()
+                        .and_then(move |tip_ref| {
+                            catch_up_index
+                                .catch_up(storage, subscriptions, block0, tip_ref, logger.clone())
+                                .map(move |()| logger)
+                        })
+                        .map(move |logger| {
+                            if index.finish_backfill() {
+                                info!(logger, "the chain index reached the tip");
+                                future::Loop::Break(())
+                            } else {
+                                future::Loop::Continue(())
+                            }
+                        })
+                })
+            })
+            .map_err(move |e: IndexError| (retry, e))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::blockchain::Ref;
+use crate::{blockcfg::HeaderHash, blockchain::Ref};
 use std::{convert::Infallible, sync::Arc};
 use tokio::{prelude::*, sync::lock::Lock};
 
@@ -54,6 +54,22 @@ impl Branches {
             })
     }
 
+    /// the hashes of the blocks the branches point to
+    pub fn heads(&self) -> impl Future<Item = Vec<HeaderHash>, Error = Infallible> {
+        let mut branches = self.clone();
+        future::poll_fn(move || Ok(branches.inner.poll_lock()))
+            .and_then(|guard| {
+                future::join_all(
+                    guard
+                        .branches
+                        .iter()
+                        .map(|branch| branch.get_ref())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .map(|refs| refs.iter().map(|reference| reference.hash()).collect())
+    }
+
     fn apply(
         &mut self,
         candidate: Arc<Ref>,
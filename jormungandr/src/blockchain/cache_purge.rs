@@ -0,0 +1,59 @@
+//! removal of the expired entries of the blockchain caches
+//!
+//! Neither the cache of the block `Ref`s nor the candidate forest remove
+//! their expired entries by themselves, they are purged periodically.
+
+use crate::{
+    blockchain::{Blockchain, CandidateForest},
+    utils::task::TokioServiceInfo,
+};
+use std::time::Duration;
+use tokio::{prelude::*, timer::Interval};
+
+pub struct CachePurge {
+    blockchain: Blockchain,
+    candidate_forest: CandidateForest,
+    interval: Duration,
+}
+
+impl CachePurge {
+    pub fn new(
+        blockchain: Blockchain,
+        candidate_forest: CandidateForest,
+        interval: Duration,
+    ) -> Self {
+        CachePurge {
+            blockchain,
+            candidate_forest,
+            interval,
+        }
+    }
+
+    pub fn run(self, info: TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let error_logger = logger.clone();
+        let blockchain = self.blockchain;
+        let candidate_forest = self.candidate_forest;
+
+        Interval::new_interval(self.interval)
+            .for_each(move |_instant| {
+                let logger = logger.clone();
+                let blockchain = blockchain.clone();
+                blockchain
+                    .purge_ref_cache()
+                    .join(candidate_forest.purge())
+                    .map(move |_| {
+                        let stats = blockchain.ref_cache_stats();
+                        debug!(
+                            logger,
+                            "purged the blockchain caches" ;
+                            "ref_cache_entries" => stats.entries,
+                            "ref_cache_hits" => stats.hits,
+                            "ref_cache_misses" => stats.misses,
+                            "ref_cache_evictions" => stats.evictions,
+                        );
+                    })
+            })
+            .map_err(move |e| error!(error_logger, "cache purge timer error"; "reason" => %e))
+    }
+}
@@ -49,16 +49,26 @@ See Internal documentation for more details: doc/internal_design.md
 [`Branch`]: ./struct.Branch.html
 */
 
-use super::{branch::Branches, reference_cache::RefCache};
+use super::{
+    branch::Branches,
+    chain_selection::ChainSelection,
+    epoch_hooks::EpochHooks,
+    received::ReceivedHeaders,
+    reference_cache::{RefCache, RefCacheStats},
+    subscriptions::Subscriptions,
+};
 use crate::{
     blockcfg::{
         Block, Block0Error, BlockDate, ChainLength, Epoch, Header, HeaderHash, Leadership, Ledger,
         LedgerParameters,
     },
-    blockchain::{Branch, Checkpoints, Multiverse, Ref, Storage},
+    blockchain::{Branch, ChainIndex, Checkpoints, Governance, IoStats, Multiverse, Ref, Storage},
     start_up::NodeStorage,
 };
-use chain_impl_mockchain::{leadership::Verification, ledger};
+use chain_impl_mockchain::{
+    leadership::{LeadershipConsensus, Verification},
+    ledger::{self, EpochRewardsInfo, RewardsInfoParameters},
+};
 use chain_storage::error::Error as StorageError;
 use chain_time::TimeFrame;
 use std::{convert::Infallible, sync::Arc, time::Duration};
@@ -181,6 +191,27 @@ pub struct Blockchain {
     ledgers: Multiverse<Arc<Ledger>>,
 
     storage: Storage,
+
+    /// the index of the transactions and addresses of the main chain,
+    /// if enabled
+    index: Option<ChainIndex>,
+
+    /// the addresses watched by the clients of the REST API, the events
+    /// being found with the index
+    subscriptions: Subscriptions,
+
+    /// the tasks told of the epoch transitions of the main chain
+    epoch_hooks: EpochHooks,
+
+    /// the headers of the blocks applied from the network
+    received_headers: ReceivedHeaders,
+
+    /// the maximum number of blocks the tip may be rolled back by when
+    /// switching to another branch
+    max_rollback_depth: Option<u64>,
+
+    /// the fork choice between the tip and the other branches
+    chain_selection: Arc<dyn ChainSelection>,
 }
 
 pub enum PreCheckedHeader {
@@ -219,6 +250,19 @@ pub enum PreCheckedHeader {
     },
 }
 
+/// the checks of a header done ahead of `Blockchain::post_check_verified_header`
+pub enum HeaderVerification {
+    /// none, the header is fully verified
+    Unverified,
+    /// the leadership, with the given schedule (see `verify_header_leadership`).
+    /// It is verified again if that is not the schedule of the header's
+    /// epoch in this branch.
+    VerifiedWith(Arc<Leadership>),
+    /// the header is part of a chain known to be valid, only its linkage
+    /// to its parent is checked by `pre_check_header`
+    Trusted,
+}
+
 pub struct PostCheckedHeader {
     header: Header,
     epoch_leadership_schedule: Arc<Leadership>,
@@ -226,15 +270,32 @@ pub struct PostCheckedHeader {
     parent_ledger_state: Arc<Ledger>,
     time_frame: Arc<TimeFrame>,
     previous_epoch_state: Option<Arc<Ref>>,
+    epoch_rewards_info: Option<Arc<EpochRewardsInfo>>,
+    parent_date: BlockDate,
+    parent_governance: Arc<Governance>,
 }
 
 impl Blockchain {
-    pub fn new(storage: NodeStorage, ref_cache_ttl: Duration) -> Self {
+    pub fn new(
+        storage: NodeStorage,
+        io_stats: IoStats,
+        ref_cache_ttl: Duration,
+        ref_cache_capacity: Option<usize>,
+        max_rollback_depth: Option<u64>,
+        chain_selection: Arc<dyn ChainSelection>,
+        index: Option<ChainIndex>,
+    ) -> Self {
         Blockchain {
             branches: Branches::new(),
-            ref_cache: RefCache::new(ref_cache_ttl),
+            ref_cache: RefCache::new(ref_cache_ttl, ref_cache_capacity),
             ledgers: Multiverse::new(),
-            storage: Storage::new(storage),
+            storage: Storage::new(storage, io_stats),
+            index,
+            subscriptions: Subscriptions::default(),
+            epoch_hooks: EpochHooks::default(),
+            received_headers: ReceivedHeaders::default(),
+            max_rollback_depth,
+            chain_selection,
         }
     }
 
@@ -242,6 +303,40 @@ impl Blockchain {
         &self.storage
     }
 
+    pub fn index(&self) -> Option<&ChainIndex> {
+        self.index.as_ref()
+    }
+
+    pub fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    pub fn epoch_hooks(&self) -> &EpochHooks {
+        &self.epoch_hooks
+    }
+
+    pub fn received_headers(&self) -> &ReceivedHeaders {
+        &self.received_headers
+    }
+
+    pub fn max_rollback_depth(&self) -> Option<u64> {
+        self.max_rollback_depth
+    }
+
+    pub fn chain_selection(&self) -> &dyn ChainSelection {
+        &*self.chain_selection
+    }
+
+    /// the usage of the cache of the block `Ref`s
+    pub fn ref_cache_stats(&self) -> RefCacheStats {
+        self.ref_cache.stats()
+    }
+
+    /// remove the expired `Ref`s from the cache
+    pub fn purge_ref_cache(&self) -> impl Future<Item = (), Error = tokio::timer::Error> {
+        self.ref_cache.purge()
+    }
+
     pub fn branches(&self) -> &Branches {
         &self.branches
     }
@@ -260,16 +355,48 @@ impl Blockchain {
         leadership: Arc<Leadership>,
         ledger_parameters: Arc<LedgerParameters>,
         previous_epoch_state: Option<Arc<Ref>>,
+        epoch_rewards_info: Option<Arc<EpochRewardsInfo>>,
+        governance: Arc<Governance>,
     ) -> impl Future<Item = Arc<Ref>, Error = Infallible> {
-        let chain_length = header.chain_length();
+        let blockchain = self.clone();
 
-        let multiverse = self.ledgers.clone();
-        let ref_cache = self.ref_cache.clone();
+        self.create_reference(
+            header_hash,
+            header,
+            ledger,
+            time_frame,
+            leadership,
+            ledger_parameters,
+            previous_epoch_state,
+            epoch_rewards_info,
+            governance,
+        )
+        .and_then(move |reference| {
+            blockchain
+                .cache_ref(Arc::clone(&reference))
+                .map(|()| reference)
+        })
+    }
 
-        multiverse
+    /// create the `Ref` of a block, without adding it to the `RefCache`
+    fn create_reference(
+        &mut self,
+        header_hash: HeaderHash,
+        header: Header,
+        ledger: Arc<Ledger>,
+        time_frame: Arc<TimeFrame>,
+        leadership: Arc<Leadership>,
+        ledger_parameters: Arc<LedgerParameters>,
+        previous_epoch_state: Option<Arc<Ref>>,
+        epoch_rewards_info: Option<Arc<EpochRewardsInfo>>,
+        governance: Arc<Governance>,
+    ) -> impl Future<Item = Arc<Ref>, Error = Infallible> {
+        let chain_length = header.chain_length();
+
+        self.ledgers
             .insert(chain_length, header_hash, ledger.clone())
-            .and_then(move |ledger_gcroot| {
-                let reference = Ref::new(
+            .map(move |ledger_gcroot| {
+                Arc::new(Ref::new(
                     ledger_gcroot,
                     ledger,
                     time_frame,
@@ -277,48 +404,52 @@ impl Blockchain {
                     ledger_parameters,
                     header,
                     previous_epoch_state,
-                );
-                let reference = Arc::new(reference);
-                ref_cache
-                    .insert(header_hash, Arc::clone(&reference))
-                    .map(|()| reference)
+                    epoch_rewards_info,
+                    governance,
+                ))
             })
     }
 
+    /// add the `Ref` of a block to the `RefCache`, once the block is stored
+    pub fn cache_ref(&self, reference: Arc<Ref>) -> impl Future<Item = (), Error = Infallible> {
+        self.ref_cache.insert(reference.hash(), reference)
+    }
+
     /// get `Ref` of the given header hash
     ///
     /// once the `Ref` is in hand, it means we have the Leadership schedule associated
     /// to this block and the `Ledger` state after this block.
     ///
+    /// If the block is in the storage but not in the cache, its `Ref` is rebuilt
+    /// from the closest ancestor still in the cache (see `load_ref_from_storage`).
+    ///
     /// If the future returns `None` it means we don't know about this block locally
     /// and it might be necessary to contacts the network to retrieve a missing
     /// branch
-    ///
-    /// TODO: the case where the block is in storage but not yet in the cache
-    ///       is not implemented
     pub fn get_ref(
         &mut self,
         header_hash: HeaderHash,
     ) -> impl Future<Item = Option<Arc<Ref>>, Error = Error> {
         let get_ref_cache_future = self.ref_cache.get(header_hash.clone());
         let block_exists_future = self.storage.block_exists(header_hash);
+        let mut self1 = self.clone();
 
         get_ref_cache_future
             .map_err(|_: Infallible| unreachable!())
-            .and_then(|maybe_ref| {
+            .and_then(move |maybe_ref| {
                 if maybe_ref.is_none() {
                     future::Either::A(
                         block_exists_future
                             .map_err(|e| {
                                 Error::with_chain(e, "cannot check if the block is in the storage")
                             })
-                            .and_then(|block_exists| {
+                            .and_then(move |block_exists| {
                                 if block_exists {
-                                    unimplemented!(
-                                        "method to load a Ref from the storage is not yet there"
+                                    future::Either::A(
+                                        self1.load_ref_from_storage(header_hash).map(Some),
                                     )
                                 } else {
-                                    future::ok(None)
+                                    future::Either::B(future::ok(None))
                                 }
                             }),
                     )
@@ -328,6 +459,74 @@ impl Blockchain {
             })
     }
 
+    /// rebuild the `Ref` of a block of the storage that is no longer in the
+    /// cache, applying the blocks again from its closest ancestor still in
+    /// the cache. The block0 is always in the cache so there is one.
+    ///
+    /// The storage may hold blocks which were never verified, e.g. the blocks
+    /// of an interrupted bootstrap or of a branch that failed to apply, so
+    /// the headers are verified again as the blocks of the network are.
+    fn load_ref_from_storage(
+        &mut self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Arc<Ref>, Error = Error> {
+        let ref_cache = self.ref_cache.clone();
+        let storage = self.storage.clone();
+        let stream_storage = self.storage.clone();
+        let blockchain = self.clone();
+
+        future::loop_fn(header_hash, move |hash| {
+            let ref_cache = ref_cache.clone();
+            storage
+                .get_block_info(hash)
+                .map_err(|e| Error::with_chain(e, "cannot load the block from the storage"))
+                .and_then(|block_info| {
+                    block_info.ok_or_else(|| ErrorKind::MissingParentBlock(hash).into())
+                })
+                .and_then(move |block_info| {
+                    let parent_hash = block_info.parent_id();
+                    ref_cache
+                        .get(parent_hash)
+                        .map_err(|_: Infallible| unreachable!())
+                        .map(move |maybe_parent| match maybe_parent {
+                            Some(parent) => future::Loop::Break(parent),
+                            None => future::Loop::Continue(parent_hash),
+                        })
+                })
+        })
+        .and_then(move |ancestor: Arc<Ref>| {
+            stream_storage
+                .stream_from_to(ancestor.hash(), header_hash)
+                .map_err(|e| Error::with_chain(e, "cannot iterate the blocks of the storage"))
+                .and_then(move |stream| {
+                    stream
+                        .map_err(|e| Error::with_chain(e, "cannot load the block from the storage"))
+                        .fold((blockchain, ancestor), |(mut blockchain, parent), block| {
+                            let mut apply_blockchain = blockchain.clone();
+                            future::result(pre_verify_link(&block.header, parent.header()))
+                                .map_err(|e| {
+                                    ErrorKind::BlockHeaderVerificationFailed(e.to_string()).into()
+                                })
+                                .and_then(move |()| {
+                                    blockchain
+                                        .post_check_verified_header(
+                                            block.header.clone(),
+                                            parent,
+                                            HeaderVerification::Unverified,
+                                        )
+                                        .map(move |post_checked| (post_checked, block))
+                                })
+                                .and_then(move |(post_checked, block)| {
+                                    apply_blockchain
+                                        .apply_stored_block(post_checked, &block)
+                                        .map(move |block_ref| (apply_blockchain, block_ref))
+                                })
+                        })
+                })
+        })
+        .map(|(_, block_ref)| block_ref)
+    }
+
     /// load the header's parent `Ref`.
     fn load_header_parent(
         &mut self,
@@ -395,41 +594,102 @@ impl Blockchain {
             })
     }
 
+    /// same as `pre_check_header`, the parent being `pending`, the `Ref` of
+    /// a block applied but not stored yet, if the header follows it. Such a
+    /// block is not in the cache nor in the storage yet, and neither are its
+    /// descendants.
+    pub fn pre_check_header_after(
+        &mut self,
+        header: Header,
+        pending: Option<Arc<Ref>>,
+    ) -> impl Future<Item = PreCheckedHeader, Error = Error> {
+        match pending {
+            Some(parent_ref) if parent_ref.hash() == header.block_parent_hash() => {
+                future::Either::A(future::result(
+                    pre_verify_link(&header, parent_ref.header())
+                        .map(|()| PreCheckedHeader::HeaderWithCache { header, parent_ref })
+                        .map_err(|e| {
+                            ErrorKind::BlockHeaderVerificationFailed(e.to_string()).into()
+                        }),
+                ))
+            }
+            _ => future::Either::B(self.pre_check_header(header, false)),
+        }
+    }
+
     /// check the header cryptographic properties and leadership's schedule
     ///
     /// on success returns the PostCheckedHeader:
     ///
     /// * the header,
-    /// * the ledger state associated to the parent block
+    /// * the ledger state associated to the parent block, with the rewards
+    ///   of the previous epoch distributed if the header starts an epoch
     /// * the leadership schedule associated to the header
     pub fn post_check_header(
         &mut self,
         header: Header,
         parent: Arc<Ref>,
     ) -> impl Future<Item = PostCheckedHeader, Error = Error> {
-        let parent_ledger_state = parent.ledger().clone();
+        self.post_check_verified_header(header, parent, HeaderVerification::Unverified)
+    }
 
+    /// same as `post_check_header`, skipping the checks of the header
+    /// already done as given by `verification`.
+    pub fn post_check_verified_header(
+        &mut self,
+        header: Header,
+        parent: Arc<Ref>,
+        verification: HeaderVerification,
+    ) -> impl Future<Item = PostCheckedHeader, Error = Error> {
         let current_date = header.block_date();
+        let parent_date = parent.block_date();
+        let parent_governance = Arc::clone(parent.governance());
+
+        let (
+            epoch_leadership_schedule,
+            epoch_ledger_parameters,
+            parent_ledger_state,
+            time_frame,
+            previous_epoch_state,
+            epoch_rewards_info,
+        ) = match new_epoch_leadership_from(current_date.epoch, parent) {
+            Ok(epoch) => epoch,
+            Err(e) => return future::err(e),
+        };
 
-        let (epoch_leadership_schedule, epoch_ledger_parameters, time_frame, previous_epoch_state) =
-            new_epoch_leadership_from(current_date.epoch, parent);
-
-        match epoch_leadership_schedule.verify(&header) {
-            Verification::Success => future::ok(PostCheckedHeader {
-                header,
-                epoch_leadership_schedule,
-                epoch_ledger_parameters,
-                parent_ledger_state,
-                time_frame,
-                previous_epoch_state,
-            }),
-            Verification::Failure(error) => {
-                future::err(ErrorKind::BlockHeaderVerificationFailed(error.to_string()).into())
+        let verified = match verification {
+            HeaderVerification::Unverified => false,
+            HeaderVerification::VerifiedWith(leadership) => {
+                Arc::ptr_eq(&leadership, &epoch_leadership_schedule)
             }
-        }
+            HeaderVerification::Trusted => true,
+        };
+        let verification = if verified {
+            Ok(())
+        } else {
+            verify_header_leadership(&epoch_leadership_schedule, &header)
+        };
+
+        future::result(verification.map(move |()| PostCheckedHeader {
+            header,
+            epoch_leadership_schedule,
+            epoch_ledger_parameters,
+            parent_ledger_state,
+            time_frame,
+            previous_epoch_state,
+            epoch_rewards_info,
+            parent_date,
+            parent_governance,
+        }))
     }
 
-    fn apply_block(
+    /// Apply the block on the blockchain from a post checked header,
+    /// without adding it to the storage.
+    ///
+    /// The `Ref` of the block is not cached: the block is to be stored with
+    /// `store_block`, and its `Ref` cached with `cache_ref` once it is,
+    /// before the tip is moved to it or to any of its descendants.
+    pub fn apply_block(
         &mut self,
         post_checked_header: PostCheckedHeader,
         block: &Block,
@@ -441,6 +701,12 @@ impl Blockchain {
         let ledger = post_checked_header.parent_ledger_state;
         let time_frame = post_checked_header.time_frame;
         let previous_epoch_state = post_checked_header.previous_epoch_state;
+        let epoch_rewards_info = post_checked_header.epoch_rewards_info;
+        let governance = Governance::apply_block(
+            &post_checked_header.parent_governance,
+            post_checked_header.parent_date,
+            block,
+        );
 
         debug_assert!(block.header.hash() == block_id);
 
@@ -455,7 +721,7 @@ impl Blockchain {
         )
         .and_then(move |new_ledger| {
             self1
-                .create_and_store_reference(
+                .create_reference(
                     block_id,
                     header,
                     Arc::new(new_ledger),
@@ -463,6 +729,8 @@ impl Blockchain {
                     epoch_leadership_schedule,
                     epoch_ledger_parameters,
                     previous_epoch_state,
+                    epoch_rewards_info,
+                    governance,
                 )
                 .map_err(|_: Infallible| unreachable!())
         })
@@ -475,16 +743,42 @@ impl Blockchain {
         post_checked_header: PostCheckedHeader,
         block: Block,
     ) -> impl Future<Item = Arc<Ref>, Error = Error> {
-        let mut storage = self.storage.clone();
+        let blockchain = self.clone();
         self.apply_block(post_checked_header, &block)
             .and_then(move |block_ref| {
-                storage
-                    .put_block(block)
-                    .or_else(|err| match err {
-                        StorageError::BlockAlreadyPresent => Ok(()),
-                        err => Err(err.into()),
-                    })
-                    .and_then(move |()| Ok(block_ref))
+                blockchain.store_block(block).and_then(move |()| {
+                    blockchain
+                        .cache_ref(Arc::clone(&block_ref))
+                        .map(|()| block_ref)
+                        .map_err(|_: Infallible| unreachable!())
+                })
+            })
+    }
+
+    /// Apply a block already in the storage and cache its `Ref`.
+    fn apply_stored_block(
+        &mut self,
+        post_checked_header: PostCheckedHeader,
+        block: &Block,
+    ) -> impl Future<Item = Arc<Ref>, Error = Error> {
+        let blockchain = self.clone();
+        self.apply_block(post_checked_header, block)
+            .and_then(move |block_ref| {
+                blockchain
+                    .cache_ref(Arc::clone(&block_ref))
+                    .map(|()| block_ref)
+                    .map_err(|_: Infallible| unreachable!())
+            })
+    }
+
+    /// Add the block to the storage, once its parent is stored.
+    pub fn store_block(&self, block: Block) -> impl Future<Item = (), Error = Error> {
+        self.storage
+            .clone()
+            .put_block(block)
+            .or_else(|err| match err {
+                StorageError::BlockAlreadyPresent => Ok(()),
+                err => Err(err.into()),
             })
     }
 
@@ -505,6 +799,7 @@ impl Blockchain {
         let block0_id = block0_header.hash();
         let block0_id_1 = block0_header.hash();
         let block0_date = block0_header.block_date().clone();
+        let block0_governance = Governance::from_block0(&block0);
 
         let mut self1 = self.clone();
         let mut branches = self.branches.clone();
@@ -555,6 +850,8 @@ impl Blockchain {
                         Arc::new(block0_leadership),
                         Arc::new(ledger_parameters),
                         None,
+                        None,
+                        Arc::new(block0_governance),
                     )
                     .map_err(|_: Infallible| unreachable!())
             })
@@ -703,7 +1000,7 @@ impl Blockchain {
                                         }
                                     })
                                     .and_then(move |post_checked_header: PostCheckedHeader| {
-                                        self6.apply_block(post_checked_header, &block)
+                                        self6.apply_stored_block(post_checked_header, &block)
                                     })
                                     .and_then(move |new_ref| {
                                         branch
@@ -718,6 +1015,34 @@ impl Blockchain {
             })
     }
 
+    /// apply the block0, already in the storage, without replaying the
+    /// blocks stored after it. These are left to be checked and applied one
+    /// by one, like the blocks received from the network.
+    ///
+    /// # Errors
+    ///
+    /// * the block0 is not already in the storage: `ErrorKind::Block0NotAlreadyInStorage`;
+    /// * the block0 does build a valid `Ledger`: `ErrorKind::Block0InitialLedgerError`;
+    /// * other errors while interacting with the storage (IO errors)
+    pub fn load_block0_from_storage(
+        &mut self,
+        block0: Block,
+    ) -> impl Future<Item = Branch, Error = Error> {
+        let mut self1 = self.clone();
+
+        self.storage
+            .block_exists(block0.header.hash())
+            .map_err(|e| Error::with_chain(e, "Cannot check if block0 is in storage"))
+            .and_then(|existence| {
+                if !existence {
+                    future::err(ErrorKind::Block0NotAlreadyInStorage.into())
+                } else {
+                    future::ok(())
+                }
+            })
+            .and_then(move |()| self1.apply_block0(block0))
+    }
+
     pub fn get_checkpoints(
         &self,
         branch: Branch,
@@ -726,15 +1051,37 @@ impl Blockchain {
     }
 }
 
+/// check the header cryptographic properties against the leadership's
+/// schedule. This does not need any other state than the schedule so the
+/// headers of an epoch can be verified independently of each other.
+pub fn verify_header_leadership(leadership: &Leadership, header: &Header) -> Result<(), Error> {
+    match leadership.verify(header) {
+        Verification::Success => Ok(()),
+        Verification::Failure(error) => {
+            Err(ErrorKind::BlockHeaderVerificationFailed(error.to_string()).into())
+        }
+    }
+}
+
+/// the leadership, the ledger parameters and the time frame of `epoch`
+/// following `parent`, along the ledger a block of `epoch` applies to. When
+/// `epoch` starts after `parent`, that is the ledger of `parent` with the
+/// rewards of the epoch of `parent` distributed, reported by the last value.
+/// The distribution fails if the rewards overflow the ledger.
 pub fn new_epoch_leadership_from(
     epoch: Epoch,
     parent: Arc<Ref>,
-) -> (
-    Arc<Leadership>,
-    Arc<LedgerParameters>,
-    Arc<TimeFrame>,
-    Option<Arc<Ref>>,
-) {
+) -> Result<
+    (
+        Arc<Leadership>,
+        Arc<LedgerParameters>,
+        Arc<Ledger>,
+        Arc<TimeFrame>,
+        Option<Arc<Ref>>,
+        Option<Arc<EpochRewardsInfo>>,
+    ),
+    Error,
+> {
     let parent_ledger_state = parent.ledger().clone();
     let parent_epoch_leadership_schedule = parent.epoch_leadership_schedule().clone();
     let parent_epoch_ledger_parameters = parent.epoch_ledger_parameters().clone();
@@ -760,21 +1107,39 @@ pub fn new_epoch_leadership_from(
                 parent_ledger_state.clone()
             };
 
+        // the stake pools are rewarded for the blocks of the epoch of the
+        // parent, according to the stake distribution of its leadership
+        let (ledger, epoch_rewards_info) = match parent_epoch_leadership_schedule.consensus() {
+            LeadershipConsensus::GenesisPraos(genesis_praos) => {
+                let (ledger, rewards_info) = parent_ledger_state.distribute_rewards(
+                    genesis_praos.distribution(),
+                    &parent_epoch_ledger_parameters,
+                    RewardsInfoParameters::report_all(),
+                )?;
+                (Arc::new(ledger), Some(Arc::new(rewards_info)))
+            }
+            _ => (parent_ledger_state, None),
+        };
+
         let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
         let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
         let previous_epoch_state = Some(parent);
-        (
+        Ok((
             leadership,
             ledger_parameters,
+            ledger,
             parent_time_frame,
             previous_epoch_state,
-        )
+            epoch_rewards_info,
+        ))
     } else {
-        (
+        Ok((
             parent_epoch_leadership_schedule,
             parent_epoch_ledger_parameters,
+            parent_ledger_state,
             parent_time_frame,
             parent.last_ref_previous_epoch().map(Arc::clone),
-        )
+            parent.epoch_rewards_info().map(Arc::clone),
+        ))
     }
 }
@@ -1,9 +1,119 @@
+use crate::blockcfg::BlockDate;
 use crate::blockchain::{Ref, Storage};
+use chain_storage::error::Error as StorageError;
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset},
+    Epoch, TimeEra,
+};
+use tokio::prelude::{future::Either, *};
+
+/// the rule choosing the tip between the current one and a candidate of
+/// another branch, the fork choice of the consensus
+pub trait ChainSelection: Send + Sync {
+    /// the name of the rule, in the logs
+    fn name(&self) -> &'static str;
+
+    /// whether the candidate is preferred to the current tip, along with the
+    /// measures of the branches the choice was made on
+    fn select(&self, current: &Ref, candidate: &Ref) -> Selection;
+}
+
+/// the choice of a `ChainSelection` rule
+pub struct Selection {
+    pub prefer_candidate: bool,
+    /// why the branch is preferred, logged on the switches of the tip
+    pub rationale: String,
+}
+
+/// the branch with the most blocks is preferred, the current tip winning
+/// the ties. This is the rule of the networks.
+pub struct LongestChain;
+
+impl ChainSelection for LongestChain {
+    fn name(&self) -> &'static str {
+        "longest_chain"
+    }
+
+    fn select(&self, current: &Ref, candidate: &Ref) -> Selection {
+        let current_length = u32::from(current.chain_length());
+        let candidate_length = u32::from(candidate.chain_length());
+        Selection {
+            prefer_candidate: longer(current_length, candidate_length),
+            rationale: format!(
+                "chain length {} of the candidate against {} of the tip",
+                candidate_length, current_length
+            ),
+        }
+    }
+}
+
+fn longer(current_length: u32, candidate_length: u32) -> bool {
+    candidate_length > current_length
+}
+
+/// the branch with the most blocks per slot since the genesis block is
+/// preferred, the current tip winning the ties. Only meant for the test
+/// networks, to try the fork choice of the slots left empty by the leaders.
+pub struct Density;
+
+impl Density {
+    /// the chain length and the number of slots since the genesis block
+    fn measures(reference: &Ref) -> (u64, u64) {
+        let slots = slots_since_genesis(
+            reference.epoch_leadership_schedule().era(),
+            reference.block_date(),
+        );
+        (u64::from(u32::from(reference.chain_length())), slots)
+    }
+}
+
+/// the number of slots up to the block date, the slot of the date included.
+/// The era of the date counts the slots of the eras before it.
+fn slots_since_genesis(era: &TimeEra, date: BlockDate) -> u64 {
+    let slot = era.from_era_to_slot(EpochPosition {
+        epoch: Epoch(date.epoch),
+        slot: EpochSlotOffset(date.slot_id),
+    });
+    u64::from(slot) + 1
+}
+
+/// whether the branch of `candidate` blocks in its slots is denser than the
+/// branch of the tip, without rounding the ratios
+fn denser(current: (u64, u64), candidate: (u64, u64)) -> bool {
+    let (current_length, current_slots) = current;
+    let (candidate_length, candidate_slots) = candidate;
+    u128::from(candidate_length) * u128::from(current_slots)
+        > u128::from(current_length) * u128::from(candidate_slots)
+}
+
+impl ChainSelection for Density {
+    fn name(&self) -> &'static str {
+        "density"
+    }
+
+    fn select(&self, current: &Ref, candidate: &Ref) -> Selection {
+        let (current_length, current_slots) = Density::measures(current);
+        let (candidate_length, candidate_slots) = Density::measures(candidate);
+        Selection {
+            prefer_candidate: denser(
+                (current_length, current_slots),
+                (candidate_length, candidate_slots),
+            ),
+            rationale: format!(
+                "{} blocks in {} slots for the candidate against {} blocks in {} slots for the tip",
+                candidate_length, candidate_slots, current_length, current_slots
+            ),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
 pub enum ComparisonResult {
     PreferCurrent,
     PreferCandidate,
+    /// the candidate would be preferred but switching to it rolls back
+    /// more blocks than allowed
+    RollbackTooDeep,
 }
 
 /// chose which of the two Ref is the most interesting to keep as a branch
@@ -13,24 +123,92 @@ pub enum ComparisonResult {
 /// on the same block date is to a requirement to call this function as it will still
 /// work to make a choice as to which of these two Ref is the right choice.
 ///
-pub fn compare_against(storage: &Storage, current: &Ref, candidate: &Ref) -> ComparisonResult {
-    let rollback_possible = check_rollback_up_to(
-        // missing max depth parameter from the protocol settings
-        storage, current, candidate,
-    );
-
-    if rollback_possible && current.chain_length() < candidate.chain_length() {
-        ComparisonResult::PreferCandidate
-    } else {
-        ComparisonResult::PreferCurrent
+/// The choice is made by the `chain_selection` rule, its rationale is
+/// returned along with the result.
+pub fn compare_against(
+    storage: &Storage,
+    chain_selection: &dyn ChainSelection,
+    current: &Ref,
+    candidate: &Ref,
+    max_rollback_depth: Option<u64>,
+) -> impl Future<Item = (ComparisonResult, String), Error = StorageError> {
+    let selection = chain_selection.select(current, candidate);
+    let rationale = selection.rationale;
+    if !selection.prefer_candidate {
+        return Either::A(future::ok((ComparisonResult::PreferCurrent, rationale)));
     }
+
+    Either::B(
+        check_rollback_up_to(storage, current, candidate, max_rollback_depth).map(
+            move |rollback_possible| {
+                if rollback_possible {
+                    (ComparisonResult::PreferCandidate, rationale)
+                } else {
+                    (ComparisonResult::RollbackTooDeep, rationale)
+                }
+            },
+        ),
+    )
 }
 
 fn check_rollback_up_to(
-    // TODO: missing max depth parameter
-    _: &Storage,
-    _ref1: &Ref,
-    _ref2: &Ref,
-) -> bool {
-    true
+    storage: &Storage,
+    ref1: &Ref,
+    ref2: &Ref,
+    max_depth: Option<u64>,
+) -> impl Future<Item = bool, Error = StorageError> {
+    match max_depth {
+        None => Either::A(future::ok(true)),
+        Some(max_depth) => {
+            Either::B(storage.is_within_rollback_depth(ref1.hash(), ref2.hash(), max_depth))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_time::Slot;
+
+    fn date(epoch: u32, slot_id: u32) -> BlockDate {
+        BlockDate { epoch, slot_id }
+    }
+
+    #[test]
+    fn longest_chain_keeps_the_tip_on_ties() {
+        assert!(longer(10, 11));
+        assert!(!longer(10, 10));
+        assert!(!longer(11, 10));
+    }
+
+    #[test]
+    fn density_keeps_the_tip_on_ties() {
+        assert!(denser((10, 20), (11, 20)));
+        assert!(!denser((10, 20), (10, 20)));
+        // the same ratio in more slots
+        assert!(!denser((10, 20), (20, 40)));
+        assert!(!denser((10, 20), (10, 21)));
+    }
+
+    #[test]
+    fn density_of_a_fork_crossing_an_epoch() {
+        let era = TimeEra::new(Slot::from(0), Epoch(0), 10);
+        // the tip has 6 blocks in the 9 first slots of the first epoch, the
+        // candidate forks off it and continues in the next epoch
+        let tip = (6, slots_since_genesis(&era, date(0, 8)));
+        assert_eq!(tip.1, 9);
+        let candidate = (8, slots_since_genesis(&era, date(1, 2)));
+        assert_eq!(candidate.1, 13);
+        assert!(!denser(tip, candidate));
+        let candidate = (10, slots_since_genesis(&era, date(1, 2)));
+        assert!(denser(tip, candidate));
+    }
+
+    #[test]
+    fn slots_of_the_previous_eras_are_counted() {
+        // ten epochs of 10 slots, then epochs of 50 slots
+        let era = TimeEra::new(Slot::from(100), Epoch(10), 50);
+        assert_eq!(slots_since_genesis(&era, date(10, 0)), 101);
+        assert_eq!(slots_since_genesis(&era, date(11, 5)), 156);
+    }
 }
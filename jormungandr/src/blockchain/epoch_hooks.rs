@@ -0,0 +1,207 @@
+//! the hook of the epoch transitions of the main chain
+//!
+//! The block task reports each new tip, and the tasks subscribed to the hook
+//! are told when the tip reaches a new epoch, or the epoch it was in on a
+//! branch diverging before the epoch. The tip of the transition holds the
+//! ledger with the rewards of the previous epoch distributed, and the
+//! leadership with the stake distribution snapshot of the new epoch.
+//!
+//! The transitions are delivered without waiting: the ones a subscriber's
+//! queue has no room for are kept, in order, and delivered with the next
+//! tips. A subscriber dropping its queue is unsubscribed.
+
+use super::Ref;
+use crate::blockcfg::HeaderHash;
+use crate::utils::async_msg::{self, MessageBox, MessageQueue, QueueMonitor};
+use slog::Logger;
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone)]
+pub struct EpochTransition {
+    /// the epoch the main chain entered
+    pub epoch: u32,
+    /// the tip the transition was noticed with, in `epoch`
+    pub tip: Arc<Ref>,
+    /// whether the main chain was in `epoch` already, on another branch
+    pub reentered: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct EpochHooks(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    last: Option<Position>,
+    /// the latest epoch entered
+    latest: Option<u32>,
+    subscribers: Vec<Subscriber>,
+}
+
+/// where a tip is in its epoch
+struct Position {
+    epoch: u32,
+    /// the last block of the previous epoch on the branch of the tip
+    boundary: Option<HeaderHash>,
+}
+
+struct Subscriber {
+    name: &'static str,
+    msg_box: MessageBox<EpochTransition>,
+    /// the transitions the queue had no room for
+    pending: VecDeque<EpochTransition>,
+}
+
+impl EpochHooks {
+    /// the queue of the epoch transitions from now on, holding up to
+    /// `capacity` transitions
+    pub fn subscribe(
+        &self,
+        name: &'static str,
+        capacity: usize,
+    ) -> (MessageQueue<EpochTransition>, QueueMonitor) {
+        let (msg_box, queue) = async_msg::channel(capacity);
+        let monitor = msg_box.monitor().clone();
+        self.0.lock().unwrap().subscribers.push(Subscriber {
+            name,
+            msg_box,
+            pending: VecDeque::new(),
+        });
+        (queue, monitor)
+    }
+
+    /// tell the subscribers if the new tip of the main chain entered an
+    /// epoch, and deliver the transitions still pending
+    pub fn tip_updated(&self, tip: &Arc<Ref>, logger: &Logger) {
+        let position = Position {
+            epoch: tip.block_date().epoch,
+            boundary: tip
+                .last_ref_previous_epoch()
+                .map(|previous| previous.hash()),
+        };
+        let epoch = position.epoch;
+        let mut inner = self.0.lock().unwrap();
+        let entered = transition(inner.last.as_ref(), &position, &tip.block_parent_hash());
+        inner.last = Some(position);
+
+        if entered {
+            let reentered = inner.latest.map_or(false, |latest| epoch <= latest);
+            inner.latest = Some(inner.latest.map_or(epoch, |latest| latest.max(epoch)));
+            info!(
+                logger,
+                "entering a new epoch";
+                "epoch" => epoch,
+                "tip" => %tip.hash(),
+                "reentered" => reentered,
+            );
+            let transition = EpochTransition {
+                epoch,
+                tip: tip.clone(),
+                reentered,
+            };
+            for subscriber in inner.subscribers.iter_mut() {
+                subscriber.pending.push_back(transition.clone());
+            }
+        }
+        let subscribers = mem::replace(&mut inner.subscribers, Vec::new());
+        inner.subscribers = subscribers
+            .into_iter()
+            .filter_map(|mut subscriber| {
+                if subscriber.deliver(entered, logger) {
+                    Some(subscriber)
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+}
+
+impl Subscriber {
+    /// send the pending transitions the queue has room for, `false` if the
+    /// subscriber dropped its queue
+    fn deliver(&mut self, new_transition: bool, logger: &Logger) -> bool {
+        while let Some(transition) = self.pending.pop_front() {
+            match self.msg_box.try_send(transition) {
+                Ok(()) => {}
+                Err(e) => {
+                    if !e.is_full() {
+                        return false;
+                    }
+                    self.pending.push_front(e.into_inner());
+                    if new_transition {
+                        warn!(
+                            logger,
+                            "epoch transition not delivered yet, the queue is full";
+                            "subscriber" => self.name,
+                            "pending" => self.pending.len(),
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// whether the tip at `position` entered its epoch since the tip at `last`:
+/// a later epoch, or the same one on a branch diverging before the epoch.
+/// Before the first reported tip, only the first block of an epoch is a
+/// transition.
+fn transition(last: Option<&Position>, position: &Position, parent: &HeaderHash) -> bool {
+    match last {
+        Some(last) => {
+            position.epoch > last.epoch
+                || (position.epoch == last.epoch && position.boundary != last.boundary)
+        }
+        None => position.boundary.as_ref() == Some(parent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::crypto::hash::Hash;
+
+    fn hash(seed: u8) -> HeaderHash {
+        Hash::from([seed; 32]).into_hash()
+    }
+
+    fn position(epoch: u32, boundary: u8) -> Position {
+        Position {
+            epoch,
+            boundary: Some(hash(boundary)),
+        }
+    }
+
+    #[test]
+    fn later_epoch_is_a_transition() {
+        let last = position(3, 1);
+        assert!(transition(Some(&last), &position(4, 2), &hash(9)));
+        assert!(!transition(Some(&last), &position(3, 1), &hash(9)));
+    }
+
+    #[test]
+    fn epoch_on_another_branch_is_entered_again() {
+        let last = position(3, 1);
+        assert!(transition(Some(&last), &position(3, 2), &hash(9)));
+        // a rollback to an earlier epoch is not entering it
+        assert!(!transition(Some(&last), &position(2, 2), &hash(9)));
+    }
+
+    #[test]
+    fn first_tip_is_a_transition_only_as_the_first_block_of_its_epoch() {
+        let first = position(3, 1);
+        assert!(transition(None, &first, &hash(1)));
+        assert!(!transition(None, &first, &hash(2)));
+        let genesis_epoch = Position {
+            epoch: 0,
+            boundary: None,
+        };
+        assert!(!transition(None, &genesis_epoch, &hash(2)));
+    }
+}
@@ -0,0 +1,357 @@
+//! the update proposals of the BFT leaders and their votes
+//!
+//! A BFT leader proposes changes of the settings of the blockchain with an
+//! update proposal fragment, and the BFT leaders vote for it with update vote
+//! fragments. The votes are tallied at the start of each epoch: a proposal
+//! voted for by more than half of the BFT leaders is accepted and its changes
+//! applied, a proposal left open for more than the proposal expiration is
+//! dropped.
+//!
+//! The ledger applies the proposals and the votes but keeps them to itself,
+//! so the state of each block is followed here from the fragments of the
+//! block once the ledger applied it, with the rules of the ledger.
+
+use crate::blockcfg::{Block, BlockDate, ConfigParam, ConfigParams, Epoch, Fragment, FragmentId};
+use chain_core::property::Fragment as _;
+use chain_impl_mockchain::leadership::bft::LeaderId;
+use jormungandr_lib::interfaces::{
+    ConsensusLeaderId, UpdateProposalState, UpdateProposalTally, UpdateProposals,
+};
+use std::{convert::TryFrom, sync::Arc};
+
+/// the proposal expiration of the ledger when the block0 does not set one
+const DEFAULT_PROPOSAL_EXPIRATION: u32 = 100;
+
+/// the BFT leaders and the proposals open to their votes, in the state of a
+/// block
+#[derive(Clone)]
+pub struct Governance {
+    bft_leaders: Vec<LeaderId>,
+    proposal_expiration: u32,
+    /// in the order of the blocks
+    proposals: Vec<Proposal>,
+    last_tally: Option<Tally>,
+}
+
+#[derive(Clone)]
+pub struct Proposal {
+    pub id: FragmentId,
+    pub proposer: LeaderId,
+    pub date: BlockDate,
+    pub changes: ConfigParams,
+    pub votes: Vec<LeaderId>,
+}
+
+#[derive(Clone)]
+pub struct Tally {
+    pub epoch: Epoch,
+    pub accepted: Vec<FragmentId>,
+    pub expired: Vec<FragmentId>,
+}
+
+impl Governance {
+    /// the state of the block0: its BFT leaders, and no proposal
+    pub fn from_block0(block0: &Block) -> Self {
+        let mut governance = Governance {
+            bft_leaders: Vec::new(),
+            proposal_expiration: DEFAULT_PROPOSAL_EXPIRATION,
+            proposals: Vec::new(),
+            last_tally: None,
+        };
+        for fragment in block0.contents.iter() {
+            if let Fragment::Initial(params) = fragment {
+                governance.apply_changes(params);
+            }
+        }
+        governance
+    }
+
+    /// the state of `block`, following the block dated `parent_date` of the
+    /// state `parent`. The proposals and the votes of the block are not
+    /// checked, the state is only kept if the ledger applies the block.
+    ///
+    /// The state of the parent is shared when the block neither starts an
+    /// epoch nor has proposals or votes.
+    pub fn apply_block(parent: &Arc<Self>, parent_date: BlockDate, block: &Block) -> Arc<Self> {
+        let date = block.header.block_date();
+        let new_epoch = parent_date.epoch < date.epoch;
+        let has_updates = block.contents.iter().any(|fragment| match fragment {
+            Fragment::UpdateProposal(_) | Fragment::UpdateVote(_) => true,
+            _ => false,
+        });
+        if !new_epoch && !has_updates {
+            return Arc::clone(parent);
+        }
+
+        let mut governance = Governance::clone(parent);
+        if new_epoch {
+            governance.tally(date.epoch);
+        }
+        governance.apply_fragments(date, block.contents.iter());
+        Arc::new(governance)
+    }
+
+    fn apply_fragments<'a>(
+        &mut self,
+        date: BlockDate,
+        fragments: impl Iterator<Item = &'a Fragment>,
+    ) {
+        for fragment in fragments {
+            match fragment {
+                Fragment::UpdateProposal(update_proposal) => {
+                    let proposal = &update_proposal.proposal;
+                    self.proposals.push(Proposal {
+                        id: fragment.id(),
+                        proposer: proposal.proposer_id.clone(),
+                        date,
+                        changes: proposal.proposal.changes.clone(),
+                        votes: Vec::new(),
+                    });
+                }
+                Fragment::UpdateVote(update_vote) => {
+                    let vote = &update_vote.vote;
+                    if let Some(proposal) = self
+                        .proposals
+                        .iter_mut()
+                        .find(|proposal| proposal.id == vote.proposal_id)
+                    {
+                        if !proposal.votes.contains(&vote.voter_id) {
+                            proposal.votes.push(vote.voter_id.clone());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// tally the votes at the start of `epoch`. The changes of an accepted
+    /// proposal apply to the proposals tallied after it, as in the ledger.
+    fn tally(&mut self, epoch: Epoch) {
+        let mut tally = Tally {
+            epoch,
+            accepted: Vec::new(),
+            expired: Vec::new(),
+        };
+        for proposal in std::mem::replace(&mut self.proposals, Vec::new()) {
+            if proposal.votes.len() > self.bft_leaders.len() / 2 {
+                self.apply_changes(&proposal.changes);
+                tally.accepted.push(proposal.id);
+            } else if proposal.date.epoch + self.proposal_expiration < epoch {
+                tally.expired.push(proposal.id);
+            } else {
+                self.proposals.push(proposal);
+            }
+        }
+        self.last_tally = Some(tally);
+    }
+
+    /// apply the changes of the settings the votes depend on
+    fn apply_changes(&mut self, changes: &ConfigParams) {
+        for param in changes.iter() {
+            match param {
+                ConfigParam::AddBftLeader(leader) => self.bft_leaders.push(leader.clone()),
+                ConfigParam::RemoveBftLeader(leader) => {
+                    self.bft_leaders.retain(|bft_leader| bft_leader != leader)
+                }
+                ConfigParam::ProposalExpiration(expiration) => {
+                    self.proposal_expiration = *expiration
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub fn bft_leaders(&self) -> &[LeaderId] {
+        &self.bft_leaders
+    }
+
+    /// the number of votes accepting a proposal
+    pub fn votes_needed(&self) -> usize {
+        self.bft_leaders.len() / 2 + 1
+    }
+
+    pub fn proposals(&self) -> &[Proposal] {
+        &self.proposals
+    }
+
+    pub fn last_tally(&self) -> Option<&Tally> {
+        self.last_tally.as_ref()
+    }
+
+    /// the last epoch the votes for the proposal are tallied at
+    pub fn last_tally_epoch(&self, proposal: &Proposal) -> Epoch {
+        proposal.date.epoch + self.proposal_expiration
+    }
+
+    /// the proposals and the last tally, as served by the REST API
+    pub fn to_update_proposals(&self) -> UpdateProposals {
+        let leader_ids = |leaders: &[LeaderId]| {
+            leaders
+                .iter()
+                .cloned()
+                .map(ConsensusLeaderId)
+                .collect::<Vec<_>>()
+        };
+        UpdateProposals {
+            bft_leaders: self.bft_leaders.len(),
+            votes_needed: self.votes_needed(),
+            proposals: self
+                .proposals
+                .iter()
+                .map(|proposal| UpdateProposalState {
+                    id: proposal.id.clone().into(),
+                    proposer: ConsensusLeaderId(proposal.proposer.clone()),
+                    proposal_date: proposal.date.into(),
+                    last_tally_epoch: self.last_tally_epoch(proposal),
+                    changes: TryFrom::try_from(proposal.changes.clone()).ok(),
+                    votes: leader_ids(&proposal.votes),
+                })
+                .collect(),
+            last_tally: self.last_tally.as_ref().map(|tally| UpdateProposalTally {
+                epoch: tally.epoch,
+                accepted: tally.accepted.iter().cloned().map(Into::into).collect(),
+                expired: tally.expired.iter().cloned().map(Into::into).collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::update::{
+        SignedUpdateProposal, SignedUpdateVote, UpdateProposal, UpdateProposalWithProposer,
+        UpdateVote,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn leader(seed: u8) -> LeaderId {
+        let secret_key = SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([seed; 32]));
+        LeaderId::from(secret_key.to_public())
+    }
+
+    /// the state of a block0 with the given leaders and proposal expiration
+    fn governance(leaders: &[LeaderId], proposal_expiration: u32) -> Governance {
+        let mut params = ConfigParams::new();
+        for leader in leaders {
+            params.push(ConfigParam::AddBftLeader(leader.clone()));
+        }
+        params.push(ConfigParam::ProposalExpiration(proposal_expiration));
+        let mut governance = Governance {
+            bft_leaders: Vec::new(),
+            proposal_expiration: DEFAULT_PROPOSAL_EXPIRATION,
+            proposals: Vec::new(),
+            last_tally: None,
+        };
+        governance.apply_changes(&params);
+        governance
+    }
+
+    fn proposal(proposer: &LeaderId, changes: ConfigParams) -> Fragment {
+        Fragment::UpdateProposal(SignedUpdateProposal {
+            proposal: UpdateProposalWithProposer {
+                proposal: UpdateProposal { changes },
+                proposer_id: proposer.clone(),
+            },
+        })
+    }
+
+    fn vote(voter: &LeaderId, proposal_id: FragmentId) -> Fragment {
+        Fragment::UpdateVote(SignedUpdateVote {
+            vote: UpdateVote {
+                proposal_id,
+                voter_id: voter.clone(),
+            },
+        })
+    }
+
+    fn date(epoch: Epoch, slot_id: u32) -> BlockDate {
+        BlockDate { epoch, slot_id }
+    }
+
+    #[test]
+    fn proposal_voted_by_a_majority_is_accepted_at_the_next_epoch() {
+        let leaders = [leader(1), leader(2), leader(3)];
+        let new_leader = leader(4);
+        let mut governance = governance(&leaders, 2);
+
+        let mut changes = ConfigParams::new();
+        changes.push(ConfigParam::AddBftLeader(new_leader.clone()));
+        let proposal = proposal(&leaders[0], changes);
+        let id = proposal.id();
+        governance.apply_fragments(date(0, 1), std::iter::once(&proposal));
+        let votes = [vote(&leaders[0], id.clone()), vote(&leaders[1], id.clone())];
+        governance.apply_fragments(date(0, 2), votes.iter());
+        // a vote counts once
+        governance.apply_fragments(date(0, 3), votes.iter());
+
+        assert_eq!(governance.votes_needed(), 2);
+        assert_eq!(governance.proposals()[0].votes.len(), 2);
+        assert_eq!(governance.last_tally_epoch(&governance.proposals()[0]), 2);
+
+        governance.tally(1);
+        assert!(governance.proposals().is_empty());
+        assert_eq!(governance.last_tally().unwrap().accepted, vec![id.clone()]);
+        assert_eq!(governance.bft_leaders().len(), 4);
+        assert!(governance.bft_leaders().contains(&new_leader));
+        assert_eq!(governance.votes_needed(), 3);
+    }
+
+    #[test]
+    fn proposal_without_a_majority_expires() {
+        let leaders = [leader(1), leader(2), leader(3), leader(4)];
+        let mut governance = governance(&leaders, 1);
+
+        let mut changes = ConfigParams::new();
+        changes.push(ConfigParam::RemoveBftLeader(leaders[3].clone()));
+        let proposal = proposal(&leaders[0], changes);
+        let id = proposal.id();
+        let fragments = [
+            proposal,
+            vote(&leaders[0], id.clone()),
+            vote(&leaders[1], id.clone()),
+        ];
+        governance.apply_fragments(date(0, 1), fragments.iter());
+
+        // half of the leaders is not a majority
+        governance.tally(1);
+        assert_eq!(governance.proposals().len(), 1);
+        assert!(governance.last_tally().unwrap().accepted.is_empty());
+        governance.tally(2);
+        assert_eq!(governance.proposals().len(), 1);
+
+        governance.tally(3);
+        assert!(governance.proposals().is_empty());
+        assert_eq!(governance.last_tally().unwrap().expired, vec![id.clone()]);
+        assert_eq!(governance.bft_leaders().len(), 4);
+    }
+
+    #[test]
+    fn update_proposals_of_the_rest_api() {
+        let leaders = [leader(1), leader(2)];
+        let mut governance = governance(&leaders, 5);
+
+        let mut changes = ConfigParams::new();
+        changes.push(ConfigParam::ProposalExpiration(10));
+        let proposal = proposal(&leaders[1], changes);
+        let id = proposal.id();
+        let fragments = [proposal, vote(&leaders[0], id.clone())];
+        governance.apply_fragments(date(3, 0), fragments.iter());
+
+        let update_proposals = governance.to_update_proposals();
+        assert_eq!(update_proposals.bft_leaders, 2);
+        assert_eq!(update_proposals.votes_needed, 2);
+        assert!(update_proposals.last_tally.is_none());
+        let state = &update_proposals.proposals[0];
+        assert_eq!(state.id, id.clone().into());
+        assert_eq!(state.proposer, ConsensusLeaderId(leaders[1].clone()));
+        assert_eq!(state.last_tally_epoch, 8);
+        assert_eq!(
+            state.changes.as_ref().unwrap().proposal_expiration,
+            Some(10)
+        );
+        assert_eq!(state.votes, vec![ConsensusLeaderId(leaders[0].clone())]);
+    }
+}
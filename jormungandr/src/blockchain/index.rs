@@ -0,0 +1,1271 @@
+//! persistent index of the transactions and addresses of the main chain
+//!
+//! The index is kept in its own [sled](https://docs.rs/sled) database,
+//! whatever the storage backend. It follows the tip of the blockchain: the
+//! blocks are indexed as they become part of the main chain and removed from
+//! the index when a reorg drops them. For each indexed block, the keys it
+//! added are recorded so it can be removed without the block itself. The
+//! writes of a block are committed to the trees at once.
+//!
+//! The outputs spent by an indexed transaction are removed from the index.
+//! They are kept in the record of the block spending them, to be restored
+//! when the block is removed and to find the addresses of the inputs of its
+//! transactions.
+//!
+//! Only the addresses of the current format are indexed, the legacy
+//! addresses of the block0 are not.
+//!
+//! A new index, or one left behind the storage, is backfilled in the
+//! background: it follows the tip only once it reached it, see `Backfill`.
+//! After a crash, the writes of the index and of the storage may not have
+//! reached the disk together; `ChainIndex::check` finds and removes what the
+//! index holds past the last block of the main chain indexed in full.
+
+use crate::{
+    blockcfg::{Block, Fragment, FragmentId, HeaderHash},
+    blockchain::{Ref, Storage, Subscriptions},
+    intercom::ReorgEvent,
+};
+use chain_addr::{Address, Discrimination, Kind};
+use chain_core::property::{Block as _, Deserialize as _, Fragment as _, Serialize as _};
+use chain_impl_mockchain::transaction::{InputEnum, TransactionSlice, Witness};
+use chain_storage::error::Error as StorageError;
+use sled::Transactional as _;
+use slog::Logger;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::prelude::*;
+
+const FRAGMENTS_TREE: &str = "fragments";
+const ADDRESSES_TREE: &str = "addresses";
+const OUTPUTS_TREE: &str = "outputs";
+const UNDO_TREE: &str = "undo";
+const META_TREE: &str = "meta";
+
+const TIP_KEY: &[u8] = b"tip";
+
+// the trees an undo record refers to
+const FRAGMENTS_TAG: u8 = 0;
+const ADDRESSES_TAG: u8 = 1;
+const OUTPUTS_TAG: u8 = 2;
+/// an output spent by the block, with its address
+const SPENT_TAG: u8 = 3;
+
+/// the interval between two logs of the progress of a backfill
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("chain index database error")]
+    Database {
+        #[from]
+        source: sled::Error,
+    },
+    #[error("storage error while indexing the chain")]
+    Storage {
+        #[from]
+        source: StorageError,
+    },
+    #[error("corrupted chain index")]
+    Corrupted {
+        #[from]
+        source: io::Error,
+    },
+    #[error("block {0} is not indexed")]
+    NotIndexed(HeaderHash),
+}
+
+#[derive(Clone)]
+pub struct ChainIndex {
+    db: sled::Db,
+    fragments: sled::Tree,
+    addresses: sled::Tree,
+    outputs: sled::Tree,
+    undo: sled::Tree,
+    meta: sled::Tree,
+    discrimination: Discrimination,
+    /// set until the index reached the tip of the main chain
+    backfill: Arc<Mutex<Option<BackfillState>>>,
+}
+
+/// a transaction involving an address, see `ChainIndex::address_transactions`
+pub struct AddressTransaction {
+    pub fragment_id: FragmentId,
+    pub block: HeaderHash,
+}
+
+/// the progress of the backfill of the index, by chain length
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackfillProgress {
+    /// the last block indexed
+    pub indexed: u32,
+    /// the tip of the main chain the index is brought to
+    pub target: u32,
+}
+
+#[derive(Default)]
+struct BackfillState {
+    progress: BackfillProgress,
+    /// the main chain changed since the target was taken
+    tip_moved: bool,
+}
+
+/// the outcome of `ChainIndex::check`
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// the blocks of the main chain found indexed in full
+    pub checked: u64,
+    /// the indexed blocks removed, damaged or not in the main chain
+    pub removed_blocks: u64,
+    /// the entries removed, of no indexed block
+    pub removed_entries: u64,
+}
+
+impl CheckReport {
+    pub fn repaired(&self) -> bool {
+        self.removed_blocks > 0 || self.removed_entries > 0
+    }
+}
+
+impl ChainIndex {
+    /// open the index in the given directory, creating it if needed. It is
+    /// backfilled before it follows the tip.
+    pub fn open<P: AsRef<Path>>(path: P, discrimination: Discrimination) -> Result<Self, Error> {
+        let db = sled::Config::default().path(path.as_ref()).open()?;
+        Ok(ChainIndex {
+            fragments: db.open_tree(FRAGMENTS_TREE)?,
+            addresses: db.open_tree(ADDRESSES_TREE)?,
+            outputs: db.open_tree(OUTPUTS_TREE)?,
+            undo: db.open_tree(UNDO_TREE)?,
+            meta: db.open_tree(META_TREE)?,
+            db,
+            discrimination,
+            backfill: Arc::new(Mutex::new(Some(BackfillState::default()))),
+        })
+    }
+
+    /// the progress of the backfill, `None` once the index follows the tip
+    pub fn backfill_progress(&self) -> Option<BackfillProgress> {
+        self.backfill
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|state| state.progress)
+    }
+
+    /// update the index to the new tip of the main chain, unless it is
+    /// being backfilled: the backfill then indexes the new blocks
+    pub fn follow_tip<F>(&self, update: F) -> Result<(), Error>
+    where
+        F: FnOnce() -> Result<(), Error>,
+    {
+        let mut backfill = self.backfill.lock().unwrap();
+        match backfill.as_mut() {
+            Some(state) => {
+                state.tip_moved = true;
+                Ok(())
+            }
+            None => update(),
+        }
+    }
+
+    /// called once the backfill brought the index to its target, returns
+    /// whether the index now follows the tip, or the main chain changed
+    /// meanwhile and the backfill goes on
+    pub fn finish_backfill(&self) -> bool {
+        let mut backfill = self.backfill.lock().unwrap();
+        match backfill.as_mut() {
+            Some(state) if state.tip_moved => {
+                state.tip_moved = false;
+                false
+            }
+            _ => {
+                *backfill = None;
+                true
+            }
+        }
+    }
+
+    fn set_backfill_target(&self, target: u32) {
+        if let Some(state) = self.backfill.lock().unwrap().as_mut() {
+            state.progress.target = target;
+        }
+    }
+
+    fn record_backfilled(&self, block: &Block) {
+        if let Some(state) = self.backfill.lock().unwrap().as_mut() {
+            state.progress.indexed = u32::from(block.chain_length());
+        }
+    }
+
+    /// the last block of the index
+    pub fn tip(&self) -> Result<Option<HeaderHash>, Error> {
+        match self.meta.get(TIP_KEY)? {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(HeaderHash::deserialize(bytes.as_ref())?)),
+        }
+    }
+
+    /// the block containing the given transaction
+    pub fn transaction_block(&self, fragment_id: &FragmentId) -> Result<Option<HeaderHash>, Error> {
+        match self.fragments.get(serialize_id(fragment_id)?)? {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(HeaderHash::deserialize(bytes.as_ref())?)),
+        }
+    }
+
+    /// the transactions spending from or sending to the given address,
+    /// ordered from the oldest
+    pub fn address_transactions(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<AddressTransaction>, Error> {
+        let prefix = address_prefix(address);
+        let mut transactions = Vec::new();
+        for entry in self.addresses.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            // the prefix is followed by the chain length of the block
+            let mut fragment_id = &key[prefix.len() + 4..];
+            transactions.push(AddressTransaction {
+                fragment_id: FragmentId::deserialize(&mut fragment_id)?,
+                block: HeaderHash::deserialize(value.as_ref())?,
+            });
+        }
+        Ok(transactions)
+    }
+
+    /// the outputs of the indexed transactions sent to the given address,
+    /// not spent by an indexed transaction, by their fragment and their index
+    /// in it
+    pub fn address_outputs(&self, address: &Address) -> Result<Vec<(FragmentId, u8)>, Error> {
+        let address_bytes = address.to_bytes();
+        let mut outputs = Vec::new();
+        for transaction in self.address_transactions(address)? {
+            let fragment_key = serialize_id(&transaction.fragment_id)?;
+            for entry in self.outputs.scan_prefix(&fragment_key) {
+                let (key, value) = entry?;
+                if value.as_ref() == address_bytes.as_slice() {
+                    outputs.push((transaction.fragment_id, key[fragment_key.len()]));
+                }
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// the addresses the fragment transfers value from or to, the inputs
+    /// spending the outputs of indexed fragments only
+    pub fn addresses_of(&self, fragment: &Fragment) -> Result<Vec<Address>, Error> {
+        Ok(match self.fragment_addresses(fragment, &HashMap::new())? {
+            Some(addresses) => addresses
+                .inputs
+                .into_iter()
+                .chain(addresses.outputs)
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// add the block on top of the index
+    pub fn apply_block(&self, block: &Block) -> Result<(), Error> {
+        let block_hash = block.id();
+        let block_key = serialize_id(&block_hash)?;
+        let chain_length = u32::from(block.chain_length()).to_be_bytes();
+
+        let mut writes = Writes::default();
+        let mut undo = serialize_id(&block.parent_id())?;
+        // the outputs of the block not spent by its next transactions
+        let mut block_outputs = HashMap::new();
+        for fragment in block.contents.iter() {
+            let fragment_id = fragment.id();
+            let fragment_key = serialize_id(&fragment_id)?;
+            let addresses = match self.fragment_addresses(fragment, &block_outputs)? {
+                Some(addresses) => addresses,
+                None => continue,
+            };
+
+            writes
+                .fragments
+                .insert(fragment_key.as_slice(), block_key.as_slice());
+            push_undo(&mut undo, FRAGMENTS_TAG, &fragment_key);
+
+            for (key, address) in addresses.spent.iter() {
+                if block_outputs.remove(key).is_none() {
+                    writes.outputs.remove(key.as_slice());
+                }
+                push_undo(&mut undo, SPENT_TAG, &spent_entry(key, address));
+            }
+
+            for (index, address) in addresses.outputs.iter().enumerate() {
+                let mut key = fragment_key.clone();
+                key.push(index as u8);
+                push_undo(&mut undo, OUTPUTS_TAG, &key);
+                block_outputs.insert(key, address.clone());
+            }
+
+            for mut key in addresses.prefixes() {
+                key.extend_from_slice(&chain_length);
+                key.extend_from_slice(&fragment_key);
+                writes
+                    .addresses
+                    .insert(key.as_slice(), block_key.as_slice());
+                push_undo(&mut undo, ADDRESSES_TAG, &key);
+            }
+        }
+
+        for (key, address) in block_outputs {
+            writes.outputs.insert(key, address.to_bytes());
+        }
+        writes.undo.insert(block_key.as_slice(), undo);
+        writes.meta.insert(TIP_KEY, block_key);
+        self.commit(writes)
+    }
+
+    /// remove the block at the top of the index, returning its parent
+    pub fn rollback_block(&self, block_hash: &HeaderHash) -> Result<HeaderHash, Error> {
+        let block_key = serialize_id(block_hash)?;
+        let undo = self
+            .undo
+            .get(block_key.as_slice())?
+            .ok_or_else(|| Error::NotIndexed(*block_hash))?;
+        let mut bytes = undo.as_ref();
+        let parent = HeaderHash::deserialize(&mut bytes)?;
+
+        let mut writes = Writes::default();
+        let mut outputs = Vec::new();
+        while !bytes.is_empty() {
+            let (tag, key, rest) = read_undo(bytes)?;
+            match tag {
+                FRAGMENTS_TAG => writes.fragments.remove(key),
+                ADDRESSES_TAG => writes.addresses.remove(key),
+                OUTPUTS_TAG => outputs.push(key),
+                SPENT_TAG => {
+                    let (key, address) = read_spent(key)?;
+                    writes.outputs.insert(key, address.to_bytes());
+                }
+                _ => return Err(corrupted("unknown tree in an undo record").into()),
+            };
+            bytes = rest;
+        }
+        // removed after the spent outputs are restored, the outputs spent in
+        // the block they are sent in are among both
+        for key in outputs {
+            writes.outputs.remove(key);
+        }
+
+        writes.meta.insert(TIP_KEY, serialize_id(&parent)?);
+        writes.undo.remove(block_key.as_slice());
+        self.commit(writes)?;
+        Ok(parent)
+    }
+
+    /// roll back the blocks dropped from the main chain when the tip
+    /// switches to another branch, the blocks of the branch are applied
+    /// next
+    pub fn rollback_reorg(&self, reorg: &ReorgEvent) -> Result<(), Error> {
+        for block in reorg.dropped.iter().rev() {
+            self.rollback_block(&block.id())?;
+        }
+        Ok(())
+    }
+
+    /// commit the writes to the trees at once
+    fn commit(&self, writes: Writes) -> Result<(), Error> {
+        let trees = (
+            &self.fragments,
+            &self.addresses,
+            &self.outputs,
+            &self.undo,
+            &self.meta,
+        );
+        trees
+            .transaction(|(fragments, addresses, outputs, undo, meta)| {
+                fragments.apply_batch(writes.fragments.clone())?;
+                addresses.apply_batch(writes.addresses.clone())?;
+                outputs.apply_batch(writes.outputs.clone())?;
+                undo.apply_batch(writes.undo.clone())?;
+                meta.apply_batch(writes.meta.clone())?;
+                Ok(())
+            })
+            .map_err(|e| match e {
+                sled::TransactionError::Storage(source) => Error::Database { source },
+                sled::TransactionError::Abort(()) => unreachable!(),
+            })
+    }
+
+    /// write the buffered writes of the index to the disk
+    pub fn flush(&self) -> Result<(), Error> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// bring the index to the given tip of the storage, after the index was
+    /// created or when the node stopped before indexing the last blocks. The
+    /// events of the subscriptions are recorded as the blocks are rolled
+    /// back and indexed, as when the index follows the tip. The progress is
+    /// logged periodically.
+    pub fn catch_up(
+        &self,
+        storage: Storage,
+        subscriptions: Subscriptions,
+        block0: Block,
+        tip: Arc<Ref>,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = Error> {
+        let target = u32::from(tip.chain_length());
+        let tip = tip.hash();
+        self.set_backfill_target(target);
+
+        let index = self.clone();
+        let rollback_index = self.clone();
+        let apply_index = self.clone();
+        let stream_storage = storage.clone();
+        let rollback_subscriptions = subscriptions.clone();
+        let rollback_logger = logger.clone();
+
+        future::result(self.tip())
+            .and_then(move |index_tip| match index_tip {
+                Some(index_tip) => Ok(index_tip),
+                None => {
+                    index.apply_block(&block0)?;
+                    Ok(block0.id())
+                }
+            })
+            .and_then(move |index_tip| {
+                let index = rollback_index;
+                // drop the indexed blocks no longer in the main chain
+                future::loop_fn(index_tip, move |index_tip| {
+                    let index = index.clone();
+                    let block_storage = storage.clone();
+                    let subscriptions = rollback_subscriptions.clone();
+                    let logger = rollback_logger.clone();
+                    storage
+                        .is_ancestor(index_tip, tip)
+                        .or_else(|e| match e {
+                            // the block was removed from the storage
+                            StorageError::BlockNotFound => Ok(None),
+                            e => Err(Error::from(e)),
+                        })
+                        .and_then(move |distance| match distance {
+                            Some(_) => {
+                                future::Either::A(future::ok(future::Loop::Break(index_tip)))
+                            }
+                            None => future::Either::B(
+                                index
+                                    .rollback_recorded(
+                                        block_storage,
+                                        subscriptions,
+                                        index_tip,
+                                        logger,
+                                    )
+                                    .map(future::Loop::Continue),
+                            ),
+                        })
+                })
+            })
+            .and_then(move |index_tip| {
+                let index = apply_index;
+                if index_tip == tip {
+                    return future::Either::A(future::ok(()));
+                }
+                let mut last_log = Instant::now();
+                future::Either::B(
+                    stream_storage
+                        .stream_from_to(index_tip, tip)
+                        .map_err(Error::from)
+                        .and_then(move |stream| {
+                            stream.map_err(Error::from).for_each(move |block| {
+                                index.apply_block(&block)?;
+                                recorded(
+                                    &logger,
+                                    subscriptions
+                                        .record_applied(&index, std::slice::from_ref(&block)),
+                                );
+                                index.record_backfilled(&block);
+                                if last_log.elapsed() >= PROGRESS_LOG_INTERVAL {
+                                    last_log = Instant::now();
+                                    info!(
+                                        logger,
+                                        "indexing the blocks of the main chain";
+                                        "indexed" => u32::from(block.chain_length()),
+                                        "target" => target,
+                                    );
+                                }
+                                Ok(())
+                            })
+                        }),
+                )
+            })
+    }
+
+    /// remove the block at the top of the index, recording the events of the
+    /// subscriptions. The events of a block removed from the storage are
+    /// missed.
+    fn rollback_recorded(
+        &self,
+        storage: Storage,
+        subscriptions: Subscriptions,
+        block_hash: HeaderHash,
+        logger: Logger,
+    ) -> impl Future<Item = HeaderHash, Error = Error> {
+        let index = self.clone();
+        storage
+            .get(block_hash)
+            .map_err(Error::from)
+            .and_then(move |block| {
+                if let Some(block) = block {
+                    let blocks = std::slice::from_ref(&block);
+                    recorded(&logger, subscriptions.record_rolled_back(&index, blocks));
+                }
+                index.rollback_block(&block_hash)
+            })
+    }
+
+    /// compare the index with the main chain of the storage up to the given
+    /// tip, and repair it: the index is cut back to the last block of the
+    /// main chain it holds in full, the blocks and the entries past it are
+    /// removed. The blocks removed are indexed again by the backfill.
+    pub fn check(
+        &self,
+        storage: Storage,
+        block0: Block,
+        tip: HeaderHash,
+    ) -> impl Future<Item = CheckReport, Error = Error> {
+        let walk_index = self.clone();
+        let repair_index = self.clone();
+        let walk_storage = storage.clone();
+        let block0_hash = block0.id();
+
+        future::result(self.is_fully_indexed(&block0))
+            .and_then(move |block0_indexed| {
+                if !block0_indexed {
+                    return future::Either::A(future::ok(None));
+                }
+                // the last block indexed in full, and the number of blocks
+                // up to it
+                future::Either::B(
+                    walk_storage
+                        .stream_from_to(block0_hash, tip)
+                        .map_err(Error::from)
+                        .and_then(move |stream| {
+                            stream
+                                .map_err(Error::from)
+                                .take_while(move |block| walk_index.is_fully_indexed(block))
+                                .fold((block0_hash, 1), |(_, checked), block| {
+                                    Ok::<_, Error>((block.id(), checked + 1))
+                                })
+                                .map(Some)
+                        }),
+                )
+            })
+            .and_then(move |last_indexed| repair_index.repair(storage, last_indexed))
+    }
+
+    /// whether the block is indexed in full: its undo record follows its
+    /// parent, all its entries are in the trees, and each of its fragments
+    /// transferring value has one
+    fn is_fully_indexed(&self, block: &Block) -> Result<bool, Error> {
+        match self.verify_block(block) {
+            Err(Error::Corrupted { .. }) => Ok(false),
+            res => res,
+        }
+    }
+
+    fn verify_block(&self, block: &Block) -> Result<bool, Error> {
+        let block_key = serialize_id(&block.id())?;
+        let undo = match self.undo.get(block_key.as_slice())? {
+            Some(undo) => undo,
+            None => return Ok(false),
+        };
+        let mut bytes = undo.as_ref();
+        if HeaderHash::deserialize(&mut bytes)? != block.parent_id() {
+            return Ok(false);
+        }
+
+        let of_block =
+            |value: Option<sled::IVec>| value.map_or(false, |v| v.as_ref() == block_key.as_slice());
+        let mut fragment_keys = BTreeSet::new();
+        while !bytes.is_empty() {
+            let (tag, key, rest) = read_undo(bytes)?;
+            let present = match tag {
+                FRAGMENTS_TAG => {
+                    fragment_keys.insert(key.to_vec());
+                    of_block(self.fragments.get(key)?)
+                }
+                ADDRESSES_TAG => of_block(self.addresses.get(key)?),
+                // the output may be spent by a later block
+                OUTPUTS_TAG => true,
+                SPENT_TAG => !self.outputs.contains_key(read_spent(key)?.0)?,
+                _ => return Err(corrupted("unknown tree in an undo record").into()),
+            };
+            if !present {
+                return Ok(false);
+            }
+            bytes = rest;
+        }
+
+        for fragment in block.contents.iter() {
+            if self
+                .fragment_addresses(fragment, &HashMap::new())?
+                .is_some()
+                && !fragment_keys.contains(&serialize_id(&fragment.id())?)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// cut the index back to the given block and the number of blocks up to
+    /// it, emptying it if there is none
+    fn repair(
+        &self,
+        storage: Storage,
+        last_indexed: Option<(HeaderHash, u64)>,
+    ) -> impl Future<Item = CheckReport, Error = Error> {
+        let index = self.clone();
+        let checked = last_indexed.map_or(0, |(_, checked)| checked);
+
+        // the records of the blocks past the last one indexed in full, only
+        // looked up if there are more records than blocks up to it
+        future::result(self.undo_keys())
+            .and_then(move |keys| {
+                let last_indexed = match last_indexed {
+                    Some((last_indexed, _)) if keys.len() as u64 != checked => last_indexed,
+                    Some(_) => return future::Either::A(future::ok(Vec::new())),
+                    None => return future::Either::A(future::ok(keys)),
+                };
+                future::Either::B(
+                    stream::iter_ok(keys)
+                        .and_then(move |block| {
+                            storage
+                                .is_ancestor(block, last_indexed)
+                                .or_else(|e| match e {
+                                    StorageError::BlockNotFound => Ok(None),
+                                    e => Err(Error::from(e)),
+                                })
+                                .map(move |distance| (block, distance.is_none()))
+                        })
+                        .filter_map(|(block, past)| if past { Some(block) } else { None })
+                        .collect(),
+                )
+            })
+            .and_then(move |removed| {
+                for block in removed.iter() {
+                    index.undo.remove(serialize_id(block)?)?;
+                }
+                let removed_entries = index.remove_unindexed_entries()?;
+                match last_indexed {
+                    Some((last_indexed, _)) => {
+                        index.meta.insert(TIP_KEY, serialize_id(&last_indexed)?)?
+                    }
+                    None => index.meta.remove(TIP_KEY)?,
+                };
+                index.flush()?;
+                Ok(CheckReport {
+                    checked,
+                    removed_blocks: removed.len() as u64,
+                    removed_entries,
+                })
+            })
+    }
+
+    fn undo_keys(&self) -> Result<Vec<HeaderHash>, Error> {
+        let mut keys = Vec::new();
+        for key in self.undo.iter().keys() {
+            keys.push(HeaderHash::deserialize(key?.as_ref())?);
+        }
+        Ok(keys)
+    }
+
+    /// remove the entries of the blocks with no undo record: the blocks
+    /// removed by the repair, or whose indexing was interrupted before the
+    /// record was written. Returns the number of entries removed.
+    fn remove_unindexed_entries(&self) -> Result<u64, Error> {
+        let mut removed = 0;
+        for tree in &[&self.fragments, &self.addresses] {
+            let mut unindexed = Vec::new();
+            for entry in tree.iter() {
+                let (key, block_key) = entry?;
+                if !self.undo.contains_key(&block_key)? {
+                    unindexed.push(key);
+                }
+            }
+            for key in unindexed {
+                tree.remove(key)?;
+                removed += 1;
+            }
+        }
+        // the outputs are keyed by the transaction and the index of the output
+        let mut unindexed = Vec::new();
+        for key in self.outputs.iter().keys() {
+            let key = key?;
+            if !self.fragments.contains_key(&key[..key.len() - 1])? {
+                unindexed.push(key);
+            }
+        }
+        for key in unindexed {
+            self.outputs.remove(key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// the addresses involved in the fragment, or `None` if it does not
+    /// transfer any value. The inputs spend the outputs of the index, of
+    /// `block_outputs`, or the outputs spent by the fragment if it is
+    /// indexed.
+    fn fragment_addresses(
+        &self,
+        fragment: &Fragment,
+        block_outputs: &HashMap<Vec<u8>, Address>,
+    ) -> Result<Option<FragmentAddresses>, Error> {
+        let spending = SpentOutputs {
+            index: self,
+            fragment_key: serialize_id(&fragment.id())?,
+            block_outputs,
+            spent: None,
+        };
+        let addresses = match fragment {
+            Fragment::Transaction(tx) => self.transaction_addresses(&tx.as_slice(), spending)?,
+            Fragment::OwnerStakeDelegation(tx) => {
+                self.transaction_addresses(&tx.as_slice(), spending)?
+            }
+            Fragment::StakeDelegation(tx) => {
+                self.transaction_addresses(&tx.as_slice(), spending)?
+            }
+            Fragment::PoolRegistration(tx) => {
+                self.transaction_addresses(&tx.as_slice(), spending)?
+            }
+            Fragment::PoolRetirement(tx) => self.transaction_addresses(&tx.as_slice(), spending)?,
+            Fragment::PoolUpdate(tx) => self.transaction_addresses(&tx.as_slice(), spending)?,
+            Fragment::OldUtxoDeclaration(_) => FragmentAddresses::default(),
+            _ => return Ok(None),
+        };
+        Ok(Some(addresses))
+    }
+
+    fn transaction_addresses<'a, T>(
+        &self,
+        tx: &TransactionSlice<'a, T>,
+        mut spending: SpentOutputs,
+    ) -> Result<FragmentAddresses, Error> {
+        let mut addresses = FragmentAddresses::default();
+        addresses.outputs = tx
+            .outputs()
+            .iter()
+            .map(|output| output.address.clone())
+            .collect();
+
+        for (input, witness) in tx
+            .inputs()
+            .iter()
+            .map(|i| i.to_enum())
+            .zip(tx.witnesses().iter())
+        {
+            match (input, witness) {
+                (InputEnum::AccountInput(id, _value), Witness::Account(_)) => {
+                    if let Some(account) = id.to_single_account() {
+                        let kind = Kind::Account(account.into());
+                        addresses.inputs.push(Address(self.discrimination, kind));
+                    }
+                }
+                (InputEnum::UtxoInput(utxo_pointer), _witness) => {
+                    let mut key = serialize_id(&utxo_pointer.transaction_id)?;
+                    key.push(utxo_pointer.output_index);
+                    if let Some(address) = spending.address(&key)? {
+                        addresses.inputs.push(address.clone());
+                        addresses.spent.push((key, address));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(addresses)
+    }
+}
+
+impl Drop for ChainIndex {
+    fn drop(&mut self) {
+        // the unflushed writes are caught up on the next start
+        let _ = self.db.flush();
+    }
+}
+
+#[derive(Default)]
+struct FragmentAddresses {
+    inputs: Vec<Address>,
+    outputs: Vec<Address>,
+    /// the outputs spent by the inputs, by their key
+    spent: Vec<(Vec<u8>, Address)>,
+}
+
+impl FragmentAddresses {
+    /// the key prefix of every address once, the change outputs are usually
+    /// sent back to an address of the inputs
+    fn prefixes(&self) -> BTreeSet<Vec<u8>> {
+        self.inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(address_prefix)
+            .collect()
+    }
+}
+
+/// the outputs the inputs of a fragment may spend
+struct SpentOutputs<'a> {
+    index: &'a ChainIndex,
+    fragment_key: Vec<u8>,
+    block_outputs: &'a HashMap<Vec<u8>, Address>,
+    /// the outputs spent by the fragment, read from the record of its block
+    /// the first time an output is not found in the index
+    spent: Option<HashMap<Vec<u8>, Address>>,
+}
+
+impl<'a> SpentOutputs<'a> {
+    fn address(&mut self, key: &[u8]) -> Result<Option<Address>, Error> {
+        if let Some(address) = self.block_outputs.get(key) {
+            return Ok(Some(address.clone()));
+        }
+        if let Some(bytes) = self.index.outputs.get(key)? {
+            return Ok(Some(read_address(bytes.as_ref())?));
+        }
+        if self.spent.is_none() {
+            self.spent = Some(self.load()?);
+        }
+        Ok(self
+            .spent
+            .as_ref()
+            .and_then(|spent| spent.get(key))
+            .cloned())
+    }
+
+    fn load(&self) -> Result<HashMap<Vec<u8>, Address>, Error> {
+        let mut spent = HashMap::new();
+        let block_key = match self.index.fragments.get(&self.fragment_key)? {
+            Some(block_key) => block_key,
+            None => return Ok(spent),
+        };
+        let undo = match self.index.undo.get(block_key)? {
+            Some(undo) => undo,
+            None => return Ok(spent),
+        };
+        let mut bytes = undo.as_ref();
+        HeaderHash::deserialize(&mut bytes)?;
+        while !bytes.is_empty() {
+            let (tag, entry, rest) = read_undo(bytes)?;
+            if tag == SPENT_TAG {
+                let (key, address) = read_spent(entry)?;
+                spent.insert(key.to_vec(), address);
+            }
+            bytes = rest;
+        }
+        Ok(spent)
+    }
+}
+
+/// the writes of a block to the trees of the index, committed at once
+#[derive(Default)]
+struct Writes {
+    fragments: sled::Batch,
+    addresses: sled::Batch,
+    outputs: sled::Batch,
+    undo: sled::Batch,
+    meta: sled::Batch,
+}
+
+/// the subscriptions miss the events, the index is still updated
+fn recorded(logger: &Logger, result: Result<(), Error>) {
+    if let Err(e) = result {
+        warn!(logger, "cannot record the events of the subscriptions"; "reason" => %e);
+    }
+}
+
+fn serialize_id(id: &HeaderHash) -> Result<Vec<u8>, Error> {
+    Ok(id.serialize_as_vec()?)
+}
+
+/// the addresses are prefixed by their size so no address is the prefix of
+/// another one
+fn address_prefix(address: &Address) -> Vec<u8> {
+    let bytes = address.to_bytes();
+    let mut prefix = Vec::with_capacity(bytes.len() + 1);
+    prefix.push(bytes.len() as u8);
+    prefix.extend(bytes);
+    prefix
+}
+
+fn push_undo(undo: &mut Vec<u8>, tag: u8, key: &[u8]) {
+    undo.push(tag);
+    undo.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    undo.extend_from_slice(key);
+}
+
+fn read_undo(bytes: &[u8]) -> Result<(u8, &[u8], &[u8]), io::Error> {
+    if bytes.len() < 3 {
+        return Err(corrupted("truncated undo record"));
+    }
+    let tag = bytes[0];
+    let len = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+    let bytes = &bytes[3..];
+    if bytes.len() < len {
+        return Err(corrupted("truncated undo record"));
+    }
+    Ok((tag, &bytes[..len], &bytes[len..]))
+}
+
+/// the key of the output followed by its address
+fn spent_entry(key: &[u8], address: &Address) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(key.len() + 1);
+    entry.push(key.len() as u8);
+    entry.extend_from_slice(key);
+    entry.extend(address.to_bytes());
+    entry
+}
+
+fn read_spent(entry: &[u8]) -> Result<(&[u8], Address), io::Error> {
+    let len = match entry.first() {
+        Some(len) if entry.len() > *len as usize => *len as usize,
+        _ => return Err(corrupted("truncated spent output")),
+    };
+    let entry = &entry[1..];
+    Ok((&entry[..len], read_address(&entry[len..])?))
+}
+
+fn read_address(bytes: &[u8]) -> Result<Address, io::Error> {
+    Address::from_bytes(bytes).map_err(|_| corrupted("invalid address of an output"))
+}
+
+fn corrupted(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, ContentsBuilder, Header};
+    use crate::blockchain::{subscriptions::SubscriptionEventKind, IoStats};
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::{
+        block,
+        key::EitherEd25519SecretKey,
+        transaction::{TxBuilder, Witness},
+        value::Value,
+    };
+    use chain_storage::memory::MemoryBlockStore;
+    use jormungandr_lib::{crypto::hash::Hash, transaction::TransactionBuilder};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{convert::Infallible, path::PathBuf};
+    use tokio::runtime::current_thread::Runtime;
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    fn temp_index(name: &str) -> (ChainIndex, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-chain-index-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        (ChainIndex::open(&path, Discrimination::Test).unwrap(), path)
+    }
+
+    fn secret_key(seed: u8) -> SecretKey<Ed25519> {
+        SecretKey::generate(&mut StdRng::from_seed([seed; 32]))
+    }
+
+    fn address(seed: u8) -> Address {
+        Address(
+            Discrimination::Test,
+            Kind::Single(secret_key(seed).to_public()),
+        )
+    }
+
+    /// a transaction sending `value` to the address, the values telling the
+    /// transactions apart
+    fn transaction(to: &Address, value: u64) -> Fragment {
+        let mut builder = TransactionBuilder::new();
+        builder.add_output(to.clone(), Value(value));
+        let tx = TxBuilder::new()
+            .set_nopayload()
+            .set_ios(builder.inputs(), builder.outputs())
+            .set_witnesses(&[])
+            .set_payload_auth(&());
+        Fragment::Transaction(tx)
+    }
+
+    /// a transaction spending the first output of `from`, sent to the
+    /// address of the seed `from_seed`, to the address
+    fn spending(from: &Fragment, from_seed: u8, to: &Address, value: u64) -> Fragment {
+        let mut builder = TransactionBuilder::new();
+        builder.add_utxo_input(Hash::from(from.id()), 0, Value(value));
+        builder.add_output(to.clone(), Value(value));
+        let block0_hash = Hash::from([0; 32]).into_hash();
+        let secret_key = EitherEd25519SecretKey::Normal(secret_key(from_seed));
+        let witness = Witness::new_utxo(&block0_hash, &builder.sign_data_hash(), &secret_key);
+        let tx = TxBuilder::new()
+            .set_nopayload()
+            .set_ios(builder.inputs(), builder.outputs())
+            .set_witnesses(&[witness])
+            .set_payload_auth(&());
+        Fragment::Transaction(tx)
+    }
+
+    fn block(parent: Option<&Block>, fragments: Vec<Fragment>) -> Block {
+        let mut contents = ContentsBuilder::new();
+        contents.push_many(fragments);
+        let slot_id = parent.map_or(0, |parent| parent.date().slot_id + 1);
+        block::builder(BlockVersion::Genesis, contents.into(), |hdr| {
+            let hdr = match parent {
+                None => hdr.set_genesis(),
+                Some(parent) => hdr.set_parent(&parent.id(), parent.chain_length().increase()),
+            };
+            let header: Result<Header, Infallible> = Ok(hdr
+                .set_date(BlockDate { epoch: 0, slot_id })
+                .to_unsigned_header()
+                .unwrap()
+                .generalize());
+            header
+        })
+        .unwrap()
+    }
+
+    fn storage(runtime: &mut Runtime, blocks: &[&Block]) -> Storage {
+        let mut storage = Storage::new(
+            Box::new(MemoryBlockStore::new()),
+            IoStats::new(None, logger()),
+        );
+        for block in blocks {
+            runtime
+                .block_on(storage.put_block((*block).clone()))
+                .unwrap();
+        }
+        storage
+    }
+
+    #[test]
+    fn indexed_blocks_are_verified_in_full() {
+        let (index, path) = temp_index("verify");
+        let block0 = block(None, Vec::new());
+        let tx = transaction(&address(1), 10);
+        let block1 = block(Some(&block0), vec![tx.clone()]);
+        let unindexed = block(Some(&block0), vec![transaction(&address(1), 20)]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+
+        assert!(index.is_fully_indexed(&block0).unwrap());
+        assert!(index.is_fully_indexed(&block1).unwrap());
+        assert!(!index.is_fully_indexed(&unindexed).unwrap());
+
+        index
+            .fragments
+            .remove(serialize_id(&tx.id()).unwrap())
+            .unwrap();
+
+        assert!(!index.is_fully_indexed(&block1).unwrap());
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn rolled_back_blocks_leave_the_index() {
+        let (index, path) = temp_index("rollback");
+        let block0 = block(None, Vec::new());
+        let tx = transaction(&address(1), 10);
+        let block1 = block(Some(&block0), vec![tx.clone()]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+        assert_eq!(
+            index.transaction_block(&tx.id()).unwrap(),
+            Some(block1.id())
+        );
+        assert_eq!(
+            index.address_outputs(&address(1)).unwrap(),
+            vec![(tx.id(), 0)]
+        );
+
+        assert_eq!(index.rollback_block(&block1.id()).unwrap(), block0.id());
+
+        assert_eq!(index.tip().unwrap(), Some(block0.id()));
+        assert_eq!(index.transaction_block(&tx.id()).unwrap(), None);
+        assert!(index.address_transactions(&address(1)).unwrap().is_empty());
+        assert!(index.address_outputs(&address(1)).unwrap().is_empty());
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn spent_outputs_are_restored_on_rollback() {
+        let (index, path) = temp_index("spent");
+        let block0 = block(None, Vec::new());
+        let tx1 = transaction(&address(1), 10);
+        let block1 = block(Some(&block0), vec![tx1.clone()]);
+        let tx2 = spending(&tx1, 1, &address(2), 10);
+        let block2 = block(Some(&block1), vec![tx2.clone()]);
+        for block in &[&block0, &block1, &block2] {
+            index.apply_block(block).unwrap();
+        }
+
+        assert!(index.address_outputs(&address(1)).unwrap().is_empty());
+        assert_eq!(
+            index.address_outputs(&address(2)).unwrap(),
+            vec![(tx2.id(), 0)]
+        );
+        // the spent output is still found for the input of the transaction
+        assert_eq!(
+            index.addresses_of(&tx2).unwrap(),
+            vec![address(1), address(2)]
+        );
+        assert_eq!(index.address_transactions(&address(1)).unwrap().len(), 2);
+        assert!(index.is_fully_indexed(&block1).unwrap());
+        assert!(index.is_fully_indexed(&block2).unwrap());
+
+        assert_eq!(index.rollback_block(&block2.id()).unwrap(), block1.id());
+
+        assert_eq!(
+            index.address_outputs(&address(1)).unwrap(),
+            vec![(tx1.id(), 0)]
+        );
+        assert!(index.address_outputs(&address(2)).unwrap().is_empty());
+        assert!(index.is_fully_indexed(&block1).unwrap());
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn output_spent_in_its_own_block_is_not_kept() {
+        let (index, path) = temp_index("spent-in-block");
+        let block0 = block(None, Vec::new());
+        let tx1 = transaction(&address(1), 10);
+        let tx2 = spending(&tx1, 1, &address(2), 10);
+        let block1 = block(Some(&block0), vec![tx1.clone(), tx2.clone()]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+
+        assert!(index.address_outputs(&address(1)).unwrap().is_empty());
+        assert_eq!(
+            index.addresses_of(&tx2).unwrap(),
+            vec![address(1), address(2)]
+        );
+        assert!(index.is_fully_indexed(&block1).unwrap());
+
+        index.rollback_block(&block1.id()).unwrap();
+
+        assert!(index.address_outputs(&address(1)).unwrap().is_empty());
+        assert_eq!(index.outputs.len(), 0);
+        assert_eq!(index.undo.len(), 1);
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn entries_of_no_indexed_block_are_removed() {
+        let (index, path) = temp_index("unindexed-entries");
+        let block0 = block(None, Vec::new());
+        let block1 = block(Some(&block0), vec![transaction(&address(1), 10)]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+        assert_eq!(index.remove_unindexed_entries().unwrap(), 0);
+
+        // the indexing of the block interrupted before its undo record
+        index
+            .undo
+            .remove(serialize_id(&block1.id()).unwrap())
+            .unwrap();
+
+        // the fragment, the address and the output
+        assert_eq!(index.remove_unindexed_entries().unwrap(), 3);
+        assert!(index.address_transactions(&address(1)).unwrap().is_empty());
+        assert!(index.address_outputs(&address(1)).unwrap().is_empty());
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn check_cuts_the_index_back_to_the_main_chain() {
+        let mut runtime = Runtime::new().unwrap();
+        let (index, path) = temp_index("check");
+        let block0 = block(None, Vec::new());
+        let block1 = block(Some(&block0), vec![transaction(&address(1), 10)]);
+        let block2 = block(Some(&block1), vec![transaction(&address(1), 20)]);
+        let fork = block(Some(&block1), vec![transaction(&address(2), 30)]);
+        let storage = storage(&mut runtime, &[&block0, &block1, &block2, &fork]);
+        // the index followed the fork, the storage switched to `block2`
+        // without the index
+        for block in &[&block0, &block1, &fork] {
+            index.apply_block(block).unwrap();
+        }
+
+        let report = runtime
+            .block_on(index.check(storage, block0.clone(), block2.id()))
+            .unwrap();
+
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.removed_blocks, 1);
+        assert_eq!(report.removed_entries, 3);
+        assert!(report.repaired());
+        assert_eq!(index.tip().unwrap(), Some(block1.id()));
+        assert!(index.address_transactions(&address(2)).unwrap().is_empty());
+        assert_eq!(index.address_transactions(&address(1)).unwrap().len(), 1);
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn consistent_index_is_not_repaired() {
+        let mut runtime = Runtime::new().unwrap();
+        let (index, path) = temp_index("check-consistent");
+        let block0 = block(None, Vec::new());
+        let block1 = block(Some(&block0), vec![transaction(&address(1), 10)]);
+        let storage = storage(&mut runtime, &[&block0, &block1]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+
+        let report = runtime
+            .block_on(index.check(storage, block0.clone(), block1.id()))
+            .unwrap();
+
+        assert_eq!(report.checked, 2);
+        assert!(!report.repaired());
+        assert_eq!(index.tip().unwrap(), Some(block1.id()));
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn blocks_rolled_back_by_the_backfill_are_recorded() {
+        let mut runtime = Runtime::new().unwrap();
+        let (index, path) = temp_index("backfill-events");
+        let block0 = block(None, Vec::new());
+        let block1 = block(Some(&block0), vec![transaction(&address(1), 10)]);
+        let storage = storage(&mut runtime, &[&block0, &block1]);
+        index.apply_block(&block0).unwrap();
+        index.apply_block(&block1).unwrap();
+        let subscriptions = Subscriptions::default();
+        let id = subscriptions.subscribe(vec![address(1)]).unwrap();
+
+        let parent = runtime
+            .block_on(index.rollback_recorded(
+                storage,
+                subscriptions.clone(),
+                block1.id(),
+                logger(),
+            ))
+            .unwrap();
+
+        assert_eq!(parent, block0.id());
+        let events = subscriptions.poll(&id, 0).unwrap().events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, SubscriptionEventKind::RolledBack);
+        assert_eq!(events[0].block, block1.id().to_string());
+        drop(index);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -1,13 +1,21 @@
+mod backfill;
 mod branch;
+mod cache_purge;
 mod candidate;
 mod chain;
 mod chain_selection;
 mod checkpoints;
+mod epoch_hooks;
+mod governance;
+mod index;
 mod multiverse;
 mod process;
+mod pruning;
+mod received;
 mod reference;
 mod reference_cache;
 mod storage;
+mod subscriptions;
 mod tip;
 
 // Constants
@@ -19,22 +27,39 @@ mod chunk_sizes {
     // This may need to be made into a configuration parameter.
     // The number used here aims for this number of block IDs to fit within
     // a reasonable network path MTU, leaving room for gRPC and TCP/IP framing.
+    // The network splits the request over the peers, in chunks sized for
+    // the throughput of each peer.
     pub const BLOCKS: u64 = 32;
 }
 
 // Re-exports
 
 pub use self::{
+    backfill::Backfill,
     branch::Branch,
+    cache_purge::CachePurge,
     candidate::CandidateForest,
     chain::{
-        new_epoch_leadership_from, Blockchain, Error, ErrorKind, PreCheckedHeader, MAIN_BRANCH_TAG,
+        new_epoch_leadership_from, verify_header_leadership, Blockchain, Error, ErrorKind,
+        HeaderVerification, PreCheckedHeader, MAIN_BRANCH_TAG,
+    },
+    chain_selection::{
+        compare_against, ChainSelection, ComparisonResult, Density, LongestChain, Selection,
     },
-    chain_selection::{compare_against, ComparisonResult},
     checkpoints::Checkpoints,
+    epoch_hooks::{EpochHooks, EpochTransition},
+    governance::Governance,
+    index::{AddressTransaction, BackfillProgress, ChainIndex, Error as IndexError},
     multiverse::Multiverse,
-    process::{handle_input, process_new_ref},
+    process::{handle_input, process_new_ref, NewRefOutcome},
+    pruning::Pruning,
+    received::ReceivedHeaders,
     reference::Ref,
-    storage::Storage,
+    reference_cache::RefCacheStats,
+    storage::{
+        migrate_storage, ArchiveError, ArchiveReader, ArchiveWriter, BlockStore, IoKind, IoStats,
+        MigrationError, SledBlockStore, SqliteBlockStore, Storage,
+    },
+    subscriptions::{SubscribeError, SubscriptionEvents, SubscriptionId, Subscriptions},
     tip::Tip,
 };
@@ -2,13 +2,16 @@ use super::{
     candidate::{self, CandidateForest},
     chain,
     chain_selection::{self, ComparisonResult},
-    Blockchain, Error, ErrorKind, PreCheckedHeader, Ref, Tip, MAIN_BRANCH_TAG,
+    Blockchain, Error, ErrorKind, HeaderVerification, IndexError, PreCheckedHeader, Ref, Storage,
+    Tip, MAIN_BRANCH_TAG,
 };
 use crate::{
-    blockcfg::{Block, FragmentId, Header},
+    blockcfg::{Block, Epoch, FragmentId, Header, HeaderHash, Leadership},
     intercom::{
-        self, BlockMsg, ExplorerMsg, NetworkMsg, PropagateMsg, ReplyHandle, TransactionMsg,
+        self, BlockMsg, DeepReorg, ExplorerMsg, NetworkMsg, NotifierMsg, PropagateMsg, ReorgEvent,
+        ReplyHandle, TransactionMsg, REORG_APPLIED_BLOCKS,
     },
+    log,
     network::p2p::Id as NodeId,
     stats_counter::StatsCounter,
     utils::{
@@ -20,10 +23,17 @@ use chain_core::property::{Block as _, Fragment as _, HasHeader as _, Header as
 use jormungandr_lib::interfaces::FragmentStatus;
 
 use futures::future::{Either, Loop};
+use futures::sync::{mpsc, oneshot};
 use slog::Logger;
 use tokio::prelude::*;
+use tokio::runtime::TaskExecutor;
 
 use std::sync::Arc;
+use std::time::Instant;
+
+/// the number of received blocks whose headers are verified ahead of their
+/// application, and of applied blocks waiting to be stored
+const PIPELINE_DEPTH: usize = 32;
 
 pub fn handle_input(
     info: &TokioServiceInfo,
@@ -34,6 +44,7 @@ pub fn handle_input(
     network_msg_box: &mut MessageBox<NetworkMsg>,
     tx_msg_box: &mut MessageBox<TransactionMsg>,
     explorer_msg_box: Option<&mut MessageBox<ExplorerMsg>>,
+    notifier_msg_box: Option<&mut MessageBox<NotifierMsg>>,
     input: Input<BlockMsg>,
 ) -> impl Future<Item = (), Error = ()> {
     match input {
@@ -44,6 +55,9 @@ pub fn handle_input(
         }
         Input::Input(msg) => {
             let logger = info.logger().clone();
+            let variant = msg.variant_name();
+            let started = Instant::now();
+            let task_stats_counter = stats_counter.clone();
             Either::B(
                 run_handle_input(
                     info,
@@ -54,6 +68,7 @@ pub fn handle_input(
                     network_msg_box.clone(),
                     tx_msg_box.clone(),
                     explorer_msg_box.cloned(),
+                    notifier_msg_box.cloned(),
                     msg,
                 )
                 .map_err(move |e| {
@@ -62,6 +77,10 @@ pub fn handle_input(
                         "Cannot process block event" ;
                         "reason" => %e,
                     );
+                })
+                .then(move |res| {
+                    task_stats_counter.add_message_processed("block", variant, started.elapsed());
+                    res
                 }),
             )
         }
@@ -77,11 +96,13 @@ pub fn run_handle_input(
     network_msg_box: MessageBox<NetworkMsg>,
     mut tx_msg_box: MessageBox<TransactionMsg>,
     explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+    notifier_msg_box: Option<MessageBox<NotifierMsg>>,
     input: BlockMsg,
 ) -> impl Future<Item = (), Error = Error> {
     match input {
-        BlockMsg::LeadershipBlock(block) => {
+        BlockMsg::LeadershipBlock(trace, block) => {
             let logger = info.logger().new(o!(
+                log::KEY_TRACE => trace.to_string(),
                 "hash" => block.header.hash().to_string(),
                 "parent" => block.header.parent_id().to_string(),
                 "date" => block.header.block_date().to_string()));
@@ -90,6 +111,10 @@ pub fn run_handle_input(
                 process_leadership_block(logger.clone(), blockchain.clone(), block.clone());
 
             let fragments = block.fragments().map(|f| f.id()).collect();
+            let reorg_tx_msg_box = tx_msg_box.clone();
+            let reorg_explorer_msg_box = explorer_msg_box.clone();
+
+            let selection_stats_counter = stats_counter.clone();
 
             let update_mempool = process_new_block.and_then(move |new_block_ref| {
                 try_request_fragment_removal(&mut tx_msg_box, fragments, new_block_ref.header())
@@ -107,6 +132,10 @@ pub fn run_handle_input(
                     blockchain_tip,
                     Arc::clone(&new_block_ref),
                     network_msg_box,
+                    reorg_tx_msg_box,
+                    reorg_explorer_msg_box,
+                    notifier_msg_box,
+                    selection_stats_counter,
                 )
             });
 
@@ -143,24 +172,62 @@ pub fn run_handle_input(
 
             Either::A(Either::B(future))
         }
-        BlockMsg::NetworkBlocks(handle) => {
+        BlockMsg::NetworkBlocks(trace, handle) => {
             struct State<S> {
                 stream: S,
                 reply: ReplyHandle<()>,
                 candidate: Option<Arc<Ref>>,
+                committer: mpsc::Sender<(Arc<Ref>, Block)>,
             }
 
-            let logger = info.logger().clone();
+            let logger = info.logger().new(o!(log::KEY_TRACE => trace.to_string()));
             let logger_fold = logger.clone();
             let blockchain_fold = blockchain.clone();
+            let executor = info.executor().clone();
             let (stream, reply) = handle.into_stream_and_reply();
             let stream =
                 stream.map_err(|()| Error::from("Error while processing block input stream"));
+
+            // the headers of the following blocks are verified in parallel
+            // while a block is applied, and the applied blocks are stored
+            // by a separate task in the order they are applied. The `Ref` of
+            // a block is cached once it is stored, the next block being
+            // checked against the candidate until then.
+            let stream = blockchain_tip
+                .get_ref()
+                .map(move |tip| {
+                    let epoch = tip.block_date().epoch;
+                    let leadership = Arc::clone(tip.epoch_leadership_schedule());
+                    stream
+                        .map(move |block| verify_header_ahead(block, epoch, &leadership, &executor))
+                        .buffered(PIPELINE_DEPTH)
+                })
+                .flatten_stream();
+            let (committer, commits) = mpsc::channel(PIPELINE_DEPTH);
+            let store_blockchain = blockchain.clone();
+            let cache_blockchain = blockchain.clone();
+            let committed = oneshot::spawn(
+                commit_blocks(
+                    commits,
+                    move |block| store_blockchain.store_block(block),
+                    move |block_ref| {
+                        cache_blockchain
+                            .cache_ref(block_ref)
+                            .map_err(|never| match never {})
+                    },
+                ),
+                info.executor(),
+            );
+
             let state = State {
                 stream,
                 reply,
                 candidate: None,
+                committer,
             };
+            let reorg_tx_msg_box = tx_msg_box.clone();
+            let reorg_explorer_msg_box = explorer_msg_box.clone();
+            let selection_stats_counter = stats_counter.clone();
             let future = future::loop_fn(state, move |state| {
                 let blockchain = blockchain_fold.clone();
                 let candidate_forest = candidate_forest.clone();
@@ -172,28 +239,47 @@ pub fn run_handle_input(
                     stream,
                     reply,
                     candidate,
+                    committer,
                 } = state;
                 stream
                     .into_future()
                     .map_err(|(e, _)| e)
                     .and_then(move |(maybe_block, stream)| match maybe_block {
-                        Some(block) => Either::A(
+                        Some((block, verification)) => Either::A(
                             process_network_block(
                                 blockchain,
                                 candidate_forest,
                                 block,
+                                candidate.clone(),
+                                verification,
                                 tx_msg_box,
                                 explorer_msg_box,
                                 logger.clone(),
                             )
                             .then(move |res| match res {
-                                Ok(candidate) => {
+                                Ok(applied) => {
                                     stats_counter.add_block_recv_cnt(1);
-                                    Ok(Loop::Continue(State {
-                                        stream,
-                                        reply,
-                                        candidate,
-                                    }))
+                                    let (candidate, commit) = match applied {
+                                        Some((block_ref, block)) => (
+                                            Some(Arc::clone(&block_ref)),
+                                            Either::A(committer.send((block_ref, block))),
+                                        ),
+                                        None => (None, Either::B(future::ok(committer))),
+                                    };
+                                    Either::A(
+                                        commit
+                                            .map_err(|_| {
+                                                "the block storage task has stopped".into()
+                                            })
+                                            .map(move |committer| {
+                                                Loop::Continue(State {
+                                                    stream,
+                                                    reply,
+                                                    candidate,
+                                                    committer,
+                                                })
+                                            }),
+                                    )
                                 }
                                 Err(e) => {
                                     info!(
@@ -202,7 +288,7 @@ pub fn run_handle_input(
                                         "reason" => %e,
                                     );
                                     reply.reply_error(network_block_error_into_reply(e));
-                                    Ok(Loop::Break(candidate))
+                                    Either::B(future::ok(Loop::Break(candidate)))
                                 }
                             }),
                         ),
@@ -212,6 +298,8 @@ pub fn run_handle_input(
                         }
                     })
             })
+            // the tip is not moved to the blocks before they are all stored
+            .then(move |res| committed.then(move |committed| committed.and_then(|()| res)))
             .and_then(move |maybe_updated| match maybe_updated {
                 Some(new_block_ref) => {
                     let future = process_and_propagate_new_ref(
@@ -220,6 +308,10 @@ pub fn run_handle_input(
                         blockchain_tip,
                         Arc::clone(&new_block_ref),
                         network_msg_box,
+                        reorg_tx_msg_box,
+                        reorg_explorer_msg_box,
+                        notifier_msg_box,
+                        selection_stats_counter,
                     );
                     Either::A(future)
                 }
@@ -231,8 +323,10 @@ pub fn run_handle_input(
         BlockMsg::ChainHeaders(handle) => {
             let (stream, reply) = handle.into_stream_and_reply();
             let future = candidate_forest.advance_branch(stream);
+            let executor = info.executor().clone();
+            let logger = info.logger().clone();
 
-            let future = future.then(|resp| match resp {
+            let future = future.then(move |resp| match resp {
                 Err(e) => {
                     reply.reply_error(chain_header_error_into_reply(e));
                     Either::A(future::err::<(), Error>(
@@ -243,9 +337,27 @@ pub fn run_handle_input(
                     if hashes.is_empty() {
                         Either::A(future::ok(()))
                     } else {
+                        let (fetch_handle, fetch_future) =
+                            intercom::unary_reply::<_, intercom::Error>(logger.clone());
+                        executor.spawn(fetch_future.then(move |res| {
+                            match res {
+                                Ok(ref not_fetched) if !not_fetched.is_empty() => {
+                                    warn!(
+                                        logger,
+                                        "blocks of the chain could not be fetched from any peer";
+                                        "blocks" => ?not_fetched,
+                                    );
+                                }
+                                Ok(_) => {}
+                                Err(e) => {
+                                    debug!(logger, "block fetch not replied"; "reason" => %e);
+                                }
+                            }
+                            Ok(())
+                        }));
                         Either::B(
                             network_msg_box
-                                .send(NetworkMsg::GetBlocks(hashes))
+                                .send(NetworkMsg::GetBlocks(hashes, fetch_handle))
                                 .map_err(|_| "cannot request blocks from network".into())
                                 .map(|_| reply.reply_ok(())),
                         )
@@ -271,6 +383,24 @@ fn try_request_fragment_removal(
     tx_msg_box.try_send(TransactionMsg::RemoveTransactions(fragment_ids, status))
 }
 
+/// how the tip is to be affected by a new candidate
+enum TipUpdate {
+    Unchanged,
+    Extend,
+    Switch { old_tip: Arc<Ref> },
+    RefuseDeepReorg(DeepReorg),
+}
+
+/// what the processing of a new candidate did to the tip
+pub enum NewRefOutcome {
+    /// the tip was kept or moved to the candidate, with the reorg event if
+    /// it switched to another branch
+    Processed(Option<Arc<ReorgEvent>>),
+    /// the tip was kept, switching to the preferred branch of the candidate
+    /// rolling back too many blocks
+    DeepReorgRefused(DeepReorg),
+}
+
 /// process a new candidate block on top of the blockchain, this function may:
 ///
 /// * update the current tip if the candidate's parent is the current tip;
@@ -280,68 +410,335 @@ fn try_request_fragment_removal(
 /// If the current tip is not the one being updated we will then trigger
 /// chain selection after updating that other branch as it may be possible that
 /// this branch just became more interesting for the current consensus algorithm.
+///
+/// The chain index is brought to the candidate before the tip moves, so a
+/// failure to update the index leaves the tip where the index is.
+///
+/// Returns the reorg event if the tip switched to another branch.
 pub fn process_new_ref(
     logger: Logger,
-    mut blockchain: Blockchain,
-    mut tip: Tip,
+    blockchain: Blockchain,
+    tip: Tip,
     candidate: Arc<Ref>,
-) -> impl Future<Item = (), Error = Error> {
+) -> impl Future<Item = NewRefOutcome, Error = Error> {
     use tokio::prelude::future::Either::*;
 
     let candidate_hash = candidate.hash();
-    let mut storage = blockchain.storage().clone();
+    let selection_blockchain = blockchain.clone();
+    let index_logger = logger.clone();
 
     tip.clone()
         .get_ref()
+        .map_err(|_: std::convert::Infallible| unreachable!())
         .and_then(move |tip_ref| {
-            if tip_ref.hash() == candidate.block_parent_hash() {
+            let blockchain = selection_blockchain;
+            let tip_update = if tip_ref.hash() == candidate.block_parent_hash() {
                 info!(logger, "update current branch tip");
-                A(A(tip.update_ref(candidate).map(|_| true)))
+                A(future::ok(TipUpdate::Extend))
             } else {
-                match chain_selection::compare_against(blockchain.storage(), &tip_ref, &candidate) {
+                let max_rollback_depth = blockchain.max_rollback_depth();
+                let rule = blockchain.chain_selection().name();
+                B(chain_selection::compare_against(
+                    blockchain.storage(),
+                    blockchain.chain_selection(),
+                    &tip_ref,
+                    &candidate,
+                    max_rollback_depth,
+                )
+                .map_err(|e| Error::with_chain(e, "Cannot compare the candidate with the tip"))
+                .map(move |(comparison, rationale)| match comparison {
                     ComparisonResult::PreferCurrent => {
-                        info!(logger, "create new branch");
-                        A(B(future::ok(false)))
+                        info!(
+                            logger,
+                            "create new branch";
+                            "rule" => rule,
+                            "rationale" => rationale,
+                        );
+                        TipUpdate::Unchanged
+                    }
+                    ComparisonResult::RollbackTooDeep => {
+                        let max_rollback_depth = max_rollback_depth
+                            .expect("the rollback depth is only checked when limited");
+                        error!(
+                            logger,
+                            "refusing to switch to a preferred branch rolling back too many blocks, \
+                             the node may be following a malicious peer" ;
+                            "tip" => %tip_ref.hash(),
+                            "candidate" => %candidate_hash,
+                            "max_rollback_depth" => max_rollback_depth,
+                            "rule" => rule,
+                            "rationale" => rationale,
+                        );
+                        TipUpdate::RefuseDeepReorg(DeepReorg {
+                            tip: tip_ref.hash(),
+                            candidate: candidate_hash,
+                            max_rollback_depth,
+                        })
                     }
                     ComparisonResult::PreferCandidate => {
-                        info!(logger, "switching to new candidate branch");
-                        B(blockchain
-                            .branches_mut()
-                            .apply_or_create(candidate)
-                            .and_then(move |branch| tip.swap(branch))
-                            .map(|()| true))
+                        info!(
+                            logger,
+                            "switching to new candidate branch";
+                            "tip" => %tip_ref.hash(),
+                            "candidate" => %candidate_hash,
+                            "rule" => rule,
+                            "rationale" => rationale,
+                        );
+                        TipUpdate::Switch { old_tip: tip_ref }
                     }
+                }))
+            };
+            tip_update.map(move |tip_update| (tip_update, candidate))
+        })
+        .and_then(move |(tip_update, candidate)| {
+            let old_tip = match &tip_update {
+                TipUpdate::Unchanged => return B(future::ok(NewRefOutcome::Processed(None))),
+                TipUpdate::RefuseDeepReorg(deep_reorg) => {
+                    return B(future::ok(NewRefOutcome::DeepReorgRefused(
+                        deep_reorg.clone(),
+                    )))
                 }
+                TipUpdate::Extend => None,
+                TipUpdate::Switch { old_tip } => Some(old_tip.hash()),
+            };
+            let mut storage = blockchain.storage().clone();
+            let fork_storage = storage.clone();
+            let index_blockchain = blockchain.clone();
+            let mut blockchain = blockchain;
+            A(match old_tip {
+                Some(old_tip) => A(
+                    load_reorg(fork_storage, old_tip, candidate.header().clone())
+                        .map(|reorg| Some(Arc::new(reorg))),
+                ),
+                None => B(future::ok(None)),
             }
-        })
-        .map_err(|_: std::convert::Infallible| unreachable!())
-        .and_then(move |tip_updated| {
-            if tip_updated {
-                A(storage
+            .and_then(move |reorg| {
+                update_index(&index_logger, &index_blockchain, candidate_hash, reorg)
+            })
+            .and_then(move |reorg| {
+                match tip_update {
+                    TipUpdate::Switch { .. } => A(blockchain
+                        .branches_mut()
+                        .apply_or_create(candidate)
+                        .and_then(move |branch| tip.swap(branch))),
+                    _ => B(tip.update_ref(candidate).map(|_| ())),
+                }
+                .map_err(|_: std::convert::Infallible| unreachable!())
+                .map(move |()| reorg)
+            })
+            .and_then(move |reorg| {
+                storage
                     .put_tag(MAIN_BRANCH_TAG.to_owned(), candidate_hash)
-                    .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip")))
-            } else {
-                B(future::ok(()))
-            }
+                    .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip"))
+                    .map(move |()| NewRefOutcome::Processed(reorg))
+            }))
+        })
+}
+
+/// the blocks of the tip switching from `old_tip` to the branch of
+/// `new_tip`, read one at a time from the storage. Only the first blocks of
+/// the new branch are read, see `ReorgEvent`.
+fn load_reorg(
+    storage: Storage,
+    old_tip: HeaderHash,
+    new_tip: Header,
+) -> impl Future<Item = ReorgEvent, Error = Error> {
+    let dropped_storage = storage.clone();
+    let applied_storage = storage.clone();
+    storage
+        .find_fork(old_tip, new_tip.hash())
+        .map_err(|e| Error::with_chain(e, "Cannot find the blocks of the reorg"))
+        .and_then(move |fork| {
+            let common_ancestor = fork.common_ancestor;
+            let dropped = stream::iter_ok(fork.dropped)
+                .and_then(move |hash| {
+                    dropped_storage
+                        .get(hash)
+                        .map_err(|e| Error::with_chain(e, "Cannot load a dropped block"))
+                        .and_then(move |block| {
+                            block.ok_or_else(|| {
+                                format!("the dropped block {} is not in the storage", hash).into()
+                            })
+                        })
+                })
+                .collect();
+            let applied = applied_storage
+                .stream_from_to(common_ancestor, new_tip.hash())
+                .and_then(|blocks| blocks.take(REORG_APPLIED_BLOCKS).collect())
+                .map_err(|e| Error::with_chain(e, "Cannot load the blocks of the new branch"));
+            dropped
+                .join(applied)
+                .map(move |(dropped, applied)| ReorgEvent {
+                    common_ancestor,
+                    dropped,
+                    applied,
+                    new_tip,
+                })
         })
 }
 
+/// bring the chain index, if enabled, to the new tip, recording the events
+/// of the watched addresses along. On a reorg, the blocks of the new branch
+/// are read one at a time from the storage.
+fn update_index(
+    logger: &Logger,
+    blockchain: &Blockchain,
+    new_tip: HeaderHash,
+    reorg: Option<Arc<ReorgEvent>>,
+) -> impl Future<Item = Option<Arc<ReorgEvent>>, Error = Error> {
+    let index = match blockchain.index() {
+        Some(index) => index.clone(),
+        None => return Either::A(future::ok(reorg)),
+    };
+    let subscriptions = blockchain.subscriptions().clone();
+    let logger = logger.clone();
+    // the subscriptions miss the events, the index is still updated
+    let recorded = move |result: Result<(), IndexError>| {
+        if let Err(e) = result {
+            warn!(logger, "cannot record the events of the subscriptions"; "reason" => %e);
+        }
+    };
+    match reorg {
+        Some(reorg) => {
+            let storage = blockchain.storage().clone();
+            let apply_index = index.clone();
+            let apply_subscriptions = subscriptions.clone();
+            let apply_recorded = recorded.clone();
+            let rolled_back = index
+                .follow_tip(|| {
+                    recorded(subscriptions.record_rolled_back(&index, &reorg.dropped));
+                    index.rollback_reorg(&reorg)
+                })
+                .map_err(|e| Error::with_chain(e, "Cannot update the chain index"));
+            let future = future::result(rolled_back)
+                .and_then(move |()| {
+                    storage
+                        .stream_from_to(reorg.common_ancestor, new_tip)
+                        .map_err(|e| Error::with_chain(e, "Cannot iterate the new branch"))
+                        .map(move |blocks| (blocks, reorg))
+                })
+                .and_then(move |(blocks, reorg)| {
+                    let index = apply_index;
+                    let subscriptions = apply_subscriptions;
+                    let recorded = apply_recorded;
+                    blocks
+                        .map_err(|e| Error::with_chain(e, "Cannot load a block of the new branch"))
+                        .for_each(move |block| {
+                            index
+                                .follow_tip(|| {
+                                    index.apply_block(&block)?;
+                                    let blocks = std::slice::from_ref(&block);
+                                    recorded(subscriptions.record_applied(&index, blocks));
+                                    Ok(())
+                                })
+                                .map_err(|e| Error::with_chain(e, "Cannot update the chain index"))
+                        })
+                        .map(move |()| Some(reorg))
+                });
+            Either::B(Either::A(future))
+        }
+        None => Either::B(Either::B(
+            blockchain
+                .storage()
+                .get(new_tip)
+                .map_err(|e| Error::with_chain(e, "Cannot load the new tip from the storage"))
+                .and_then(move |block| {
+                    let block = block.ok_or_else(|| ErrorKind::MissingParentBlock(new_tip))?;
+                    index
+                        .follow_tip(|| {
+                            index.apply_block(&block)?;
+                            let blocks = std::slice::from_ref(&block);
+                            recorded(subscriptions.record_applied(&index, blocks));
+                            Ok(())
+                        })
+                        .map(|()| None)
+                        .map_err(|e| Error::with_chain(e, "Cannot update the chain index"))
+                }),
+        )),
+    }
+}
+
 fn process_and_propagate_new_ref(
     logger: Logger,
     blockchain: Blockchain,
     tip: Tip,
     new_block_ref: Arc<Ref>,
     network_msg_box: MessageBox<NetworkMsg>,
+    tx_msg_box: MessageBox<TransactionMsg>,
+    explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+    notifier_msg_box: Option<MessageBox<NotifierMsg>>,
+    stats_counter: StatsCounter,
 ) -> impl Future<Item = (), Error = Error> {
+    let notify_logger = logger.clone();
+    let refused_logger = logger.clone();
+    let hooks_logger = logger.clone();
+    let epoch_hooks = blockchain.epoch_hooks().clone();
+    let hooks_tip = tip.clone();
+    let mut refused_msg_box = notifier_msg_box.clone();
     let process_new_ref = process_new_ref(logger, blockchain, tip, new_block_ref.clone());
 
-    process_new_ref.and_then(move |()| {
-        let header = new_block_ref.header().clone();
-        network_msg_box
-            .send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
-            .map_err(|_| "Cannot propagate block to network".into())
-            .map(|_| ())
-    })
+    process_new_ref
+        .and_then(move |outcome| match outcome {
+            NewRefOutcome::Processed(Some(reorg)) => {
+                Either::A(notify_reorg(reorg, tx_msg_box, explorer_msg_box))
+            }
+            NewRefOutcome::Processed(None) => Either::B(future::ok(())),
+            NewRefOutcome::DeepReorgRefused(deep_reorg) => {
+                stats_counter.add_deep_reorg_refused();
+                if let Some(msg_box) = refused_msg_box.as_mut() {
+                    if let Err(e) = msg_box.try_send(NotifierMsg::DeepReorgRefused(deep_reorg)) {
+                        debug!(
+                            refused_logger,
+                            "cannot notify the notifier of the refused reorg";
+                            "reason" => %e,
+                        );
+                    }
+                }
+                Either::B(future::ok(()))
+            }
+        })
+        .and_then(move |()| {
+            hooks_tip
+                .get_ref()
+                .map_err(|e| match e {})
+                .map(move |tip_ref| epoch_hooks.tip_updated(&tip_ref, &hooks_logger))
+        })
+        .and_then(move |()| {
+            let header = new_block_ref.header().clone();
+            network_msg_box
+                .send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
+                .map_err(|_| "Cannot propagate block to network".into())
+                .map(|_| ())
+        })
+        .map(move |()| {
+            // the notifier catches up with the tip on the next message if
+            // its queue is full
+            if let Some(mut msg_box) = notifier_msg_box {
+                if let Err(e) = msg_box.try_send(NotifierMsg::TipUpdated) {
+                    debug!(notify_logger, "cannot notify the notifier of the tip"; "reason" => %e);
+                }
+            }
+        })
+}
+
+fn notify_reorg(
+    reorg: Arc<ReorgEvent>,
+    tx_msg_box: MessageBox<TransactionMsg>,
+    explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+) -> impl Future<Item = (), Error = Error> {
+    let notify_explorer = match explorer_msg_box {
+        Some(msg_box) => Either::A(
+            msg_box
+                .send(ExplorerMsg::Reorg(Arc::clone(&reorg)))
+                .map_err(|_| "Cannot notify the explorer of the reorg".into())
+                .map(|_| ()),
+        ),
+        None => Either::B(future::ok(())),
+    };
+    tx_msg_box
+        .send(TransactionMsg::Reorg(reorg))
+        .map_err(|_| "Cannot notify the fragment pool of the reorg".into())
+        .and_then(move |_| notify_explorer)
 }
 
 pub fn process_leadership_block(
@@ -434,14 +831,65 @@ pub fn process_block_announcement(
         .map_err(|err| Error::with_chain(err, "cannot process block announcement"))
 }
 
+/// verify the header of a block against the leadership of the epoch of the
+/// tip, in parallel with the application of the blocks received before it.
+/// The verification is done again when the block is applied if the block is
+/// not in the epoch of the tip or if its parent is in another epoch.
+fn verify_header_ahead(
+    block: Block,
+    epoch: Epoch,
+    leadership: &Arc<Leadership>,
+    executor: &TaskExecutor,
+) -> impl Future<Item = (Block, HeaderVerification), Error = Error> {
+    if block.header.block_date().epoch != epoch {
+        return Either::A(future::ok((block, HeaderVerification::Unverified)));
+    }
+    let leadership = Arc::clone(leadership);
+    let check = future::lazy(move || {
+        let verification = match chain::verify_header_leadership(&leadership, &block.header) {
+            Ok(()) => HeaderVerification::VerifiedWith(leadership),
+            Err(_) => HeaderVerification::Unverified,
+        };
+        Ok((block, verification))
+    });
+    Either::B(oneshot::spawn(check, executor))
+}
+
+/// store the applied blocks in the order they are applied, adding the `Ref`
+/// of a block to the cache only once the block is stored. The first block
+/// which cannot be stored stops the commits, its descendants are dropped.
+fn commit_blocks<S, R, B, F, C, FF, CF>(
+    commits: S,
+    mut store: F,
+    mut cache: C,
+) -> impl Future<Item = (), Error = Error>
+where
+    S: Stream<Item = (R, B), Error = ()>,
+    F: FnMut(B) -> FF,
+    FF: IntoFuture<Item = (), Error = Error>,
+    C: FnMut(R) -> CF,
+    CF: IntoFuture<Item = (), Error = Error>,
+{
+    commits
+        .map_err(|()| unreachable!())
+        .and_then(move |(block_ref, block)| store(block).into_future().map(|()| block_ref))
+        .for_each(move |block_ref| cache(block_ref))
+}
+
+/// validate the block and apply it, returning the applied block to be
+/// stored. The block is not applied if it is already present or if its
+/// parent is missing. `pending` is the last block applied before, which
+/// may not be stored yet.
 pub fn process_network_block(
     mut blockchain: Blockchain,
     candidate_forest: CandidateForest,
     block: Block,
+    pending: Option<Arc<Ref>>,
+    verification: HeaderVerification,
     mut tx_msg_box: MessageBox<TransactionMsg>,
     mut explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
     logger: Logger,
-) -> impl Future<Item = Option<Arc<Ref>>, Error = chain::Error> {
+) -> impl Future<Item = Option<(Arc<Ref>, Block)>, Error = chain::Error> {
     use futures::future::Either::{A, B};
 
     let logger = logger.new(o!(
@@ -452,9 +900,10 @@ pub fn process_network_block(
     let end_logger = logger.clone();
     let mut end_blockchain = blockchain.clone();
     let explorer_enabled = explorer_msg_box.is_some();
+    let received_headers = blockchain.received_headers().clone();
     let header = block.header();
     blockchain
-        .pre_check_header(header, false)
+        .pre_check_header_after(header, pending)
         .and_then(move |pre_checked| match pre_checked {
             PreCheckedHeader::AlreadyPresent { .. } => {
                 debug!(logger, "block is already present");
@@ -469,7 +918,7 @@ pub fn process_network_block(
             }
             PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
                 let post_check_and_apply = blockchain
-                    .post_check_header(header, parent_ref)
+                    .post_check_verified_header(header, parent_ref, verification)
                     .and_then(move |post_checked| {
                         let mut block_for_explorer = if explorer_enabled {
                             Some(block.clone())
@@ -478,7 +927,7 @@ pub fn process_network_block(
                         };
                         let fragment_ids = block.fragments().map(|f| f.id()).collect::<Vec<_>>();
                         end_blockchain
-                            .apply_and_store_block(post_checked, block)
+                            .apply_block(post_checked, &block)
                             .and_then(move |block_ref| {
                                 try_request_fragment_removal(&mut tx_msg_box, fragment_ids, block_ref.header()).unwrap_or_else(|err| {
                                     error!(logger, "cannot remove fragments from pool" ; "reason" => %err)
@@ -490,17 +939,18 @@ pub fn process_network_block(
                                             error!(logger, "cannot add block to explorer: {}", err)
                                         });
                                 }
-                                Ok(block_ref)
+                                Ok((block_ref, block))
                             })
                     })
-                    .and_then(move |block_ref| {
+                    .and_then(move |(block_ref, block)| {
                         candidate_forest
                             .on_applied_block(block_ref.hash())
                             .map_err(|never| match never {})
-                            .map(|more_blocks| (block_ref, more_blocks))
+                            .map(|more_blocks| (block_ref, block, more_blocks))
                     })
-                    .map(move |(block_ref, more_blocks)| {
+                    .map(move |(block_ref, block, more_blocks)| {
                         info!(end_logger, "block successfully applied");
+                        received_headers.push(block.header.clone());
                         if !more_blocks.is_empty() {
                             warn!(
                                 end_logger,
@@ -509,7 +959,7 @@ pub fn process_network_block(
                                 more_blocks.len(),
                             );
                         }
-                        Some(block_ref)
+                        Some((block_ref, block))
                     });
                 B(post_check_and_apply)
             }
@@ -542,3 +992,55 @@ fn chain_header_error_into_reply(err: candidate::Error) -> intercom::Error {
         _ => intercom::Error::failed(err.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::sync::Mutex;
+
+    /// commit the blocks numbered `blocks`, the storage failing on `failing`,
+    /// returning the outcome and the log of the stores and caches
+    fn commit(blocks: Vec<u32>, failing: u32) -> (Result<(), Error>, Vec<String>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let store_log = Arc::clone(&log);
+        let cache_log = Arc::clone(&log);
+        let commits = stream::iter_ok::<_, ()>(blocks.into_iter().map(|n| (n, n)));
+        let res = commit_blocks(
+            commits,
+            move |block| -> Result<(), Error> {
+                if block == failing {
+                    return Err("cannot store the block".into());
+                }
+                store_log.lock().unwrap().push(format!("store {}", block));
+                Ok(())
+            },
+            move |block_ref| -> Result<(), Error> {
+                cache_log
+                    .lock()
+                    .unwrap()
+                    .push(format!("cache {}", block_ref));
+                Ok(())
+            },
+        )
+        .wait();
+        let log = log.lock().unwrap().clone();
+        (res, log)
+    }
+
+    #[test]
+    fn refs_are_cached_once_their_block_is_stored() {
+        let (res, log) = commit(vec![1, 2], 0);
+
+        assert!(res.is_ok());
+        assert_eq!(log, vec!["store 1", "cache 1", "store 2", "cache 2"]);
+    }
+
+    #[test]
+    fn ref_of_a_block_not_stored_is_not_cached() {
+        let (res, log) = commit(vec![1, 2, 3], 2);
+
+        assert!(res.is_err());
+        assert_eq!(log, vec!["store 1", "cache 1"]);
+    }
+}
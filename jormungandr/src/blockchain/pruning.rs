@@ -0,0 +1,95 @@
+//! removal of the blocks of abandoned forks
+//!
+//! Periodically, the blocks deeper than the pruning depth below the tip are
+//! checked and the ones that are neither ancestors of the tip nor of one of
+//! the branches still followed are removed from the storage.
+
+use crate::{
+    blockchain::{Blockchain, Tip},
+    utils::task::TokioServiceInfo,
+};
+use std::time::Duration;
+use tokio::{prelude::*, timer::Interval};
+
+/// forks recent enough to still be selected as the tip are never pruned
+pub const MIN_PRUNING_DEPTH: u64 = 2160;
+
+pub struct Pruning {
+    blockchain: Blockchain,
+    tip: Tip,
+    depth: u64,
+    interval: Duration,
+}
+
+impl Pruning {
+    pub fn new(blockchain: Blockchain, tip: Tip, depth: u64, interval: Duration) -> Self {
+        Pruning {
+            blockchain,
+            tip,
+            depth,
+            interval,
+        }
+    }
+
+    pub fn run(self, info: TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let error_logger = logger.clone();
+
+        // the blocks the tip may still be rolled back to must be kept
+        let min_depth = self
+            .blockchain
+            .max_rollback_depth()
+            .map_or(MIN_PRUNING_DEPTH, |depth| depth.max(MIN_PRUNING_DEPTH));
+        let depth = if self.depth < min_depth {
+            warn!(
+                logger,
+                "pruning depth is too low, using the minimum instead" ;
+                "depth" => self.depth,
+                "minimum" => min_depth,
+            );
+            min_depth
+        } else {
+            self.depth
+        };
+        let blockchain = self.blockchain;
+        let tip = self.tip;
+
+        Interval::new_interval(self.interval)
+            .map_err(move |e| error!(error_logger, "pruning timer error"; "reason" => %e))
+            // the depth up to which the storage was already pruned
+            .fold(0, move |pruned_depth, _| {
+                let storage = blockchain.storage().clone();
+                let logger = logger.clone();
+
+                tip.get_ref()
+                    .join(blockchain.branches().heads())
+                    .map(|(tip_ref, mut heads)| {
+                        heads.retain(|head| *head != tip_ref.hash());
+                        heads.insert(0, tip_ref.hash());
+                        heads
+                    })
+                    .map_err(|never| match never {})
+                    .and_then(move |heads| storage.prune(heads, pruned_depth, depth))
+                    .then(move |result| match result {
+                        Ok((removed, pruned_depth)) => {
+                            if removed > 0 {
+                                info!(
+                                    logger,
+                                    "removed blocks of abandoned forks" ;
+                                    "removed" => removed,
+                                    "pruned_depth" => pruned_depth,
+                                );
+                            }
+                            Ok(pruned_depth)
+                        }
+                        Err(e) => {
+                            // start over from the beginning on the next run so
+                            // nothing is left behind
+                            error!(logger, "pruning the storage failed"; "reason" => %e);
+                            Ok(0)
+                        }
+                    })
+            })
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,34 @@
+//! the headers of the blocks received from the network
+//!
+//! The block task records the header of each block it applies from the
+//! network. The blocks created by the node itself are applied before they are
+//! propagated and never show up here. The standby leadership reads them to
+//! notice the blocks of the primary node, including the ones which are not
+//! on the branch of the tip.
+
+use crate::blockcfg::Header;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// the headers kept until they are read, the oldest being dropped first
+const CAPACITY: usize = 64;
+
+#[derive(Clone, Default)]
+pub struct ReceivedHeaders(Arc<Mutex<VecDeque<Header>>>);
+
+impl ReceivedHeaders {
+    pub fn push(&self, header: Header) {
+        let mut headers = self.0.lock().unwrap();
+        if headers.len() == CAPACITY {
+            headers.pop_front();
+        }
+        headers.push_back(header);
+    }
+
+    /// the headers received since the last call, the oldest first
+    pub fn take(&self) -> Vec<Header> {
+        self.0.lock().unwrap().drain(..).collect()
+    }
+}
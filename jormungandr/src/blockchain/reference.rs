@@ -1,7 +1,8 @@
 use crate::blockcfg::{
     BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
 };
-use chain_impl_mockchain::multiverse::GCRoot;
+use crate::blockchain::Governance;
+use chain_impl_mockchain::{ledger::EpochRewardsInfo, multiverse::GCRoot};
 use chain_time::TimeFrame;
 use std::sync::Arc;
 
@@ -35,6 +36,14 @@ pub struct Ref {
     /// the parent `Ref`. Otherwise it will be copied from `Ref` to `Ref`.
     ///
     previous_epoch_state: Option<Arc<Ref>>,
+
+    /// the rewards distributed at the start of the current epoch, in the
+    /// ledger of its first block. Shared between the `Ref` of the same epoch.
+    epoch_rewards_info: Option<Arc<EpochRewardsInfo>>,
+
+    /// the update proposals of the BFT leaders and their votes, shared
+    /// between the `Ref` of the blocks without proposals or votes
+    governance: Arc<Governance>,
 }
 
 impl Ref {
@@ -47,6 +56,8 @@ impl Ref {
         epoch_ledger_parameters: Arc<LedgerParameters>,
         header: Header,
         previous_epoch_state: Option<Arc<Ref>>,
+        epoch_rewards_info: Option<Arc<EpochRewardsInfo>>,
+        governance: Arc<Governance>,
     ) -> Self {
         debug_assert!(
             (*ledger_pointer) == header.hash(),
@@ -61,6 +72,8 @@ impl Ref {
             epoch_ledger_parameters,
             header,
             previous_epoch_state,
+            epoch_rewards_info,
+            governance,
         }
     }
 
@@ -111,4 +124,16 @@ impl Ref {
     pub fn last_ref_previous_epoch(&self) -> Option<&Arc<Ref>> {
         self.previous_epoch_state.as_ref()
     }
+
+    /// the rewards distributed at the start of the epoch of the `Ref`, none
+    /// in the epochs of the BFT consensus
+    pub fn epoch_rewards_info(&self) -> Option<&Arc<EpochRewardsInfo>> {
+        self.epoch_rewards_info.as_ref()
+    }
+
+    /// the update proposals open to the votes of the BFT leaders after
+    /// this block
+    pub fn governance(&self) -> &Arc<Governance> {
+        &self.governance
+    }
 }
@@ -1,5 +1,13 @@
 use crate::{blockcfg::HeaderHash, blockchain::Ref};
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::{
     prelude::*,
     sync::lock::Lock,
@@ -13,27 +21,69 @@ use tokio::{
 /// The cache expired [`Ref`] will be removed only if the [`Ref`]'s
 /// TTL has expired and [`purge`] has been called and has completed.
 ///
+/// If the cache has a capacity, the least recently accessed [`Ref`]
+/// is removed as soon as a new one would exceed it. The [`Ref`] of
+/// the block0 is never removed this way.
+///
 /// [`Ref`]: ./struct.Ref.html
 /// [`purge`]: ./struct.Ref.html#method.purge
 #[derive(Clone)]
 pub struct RefCache {
     inner: Lock<RefCacheData>,
+    stats: Arc<RefCacheCounters>,
+}
+
+/// snapshot of the usage of the [`RefCache`](./struct.RefCache.html)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefCacheStats {
+    /// number of lookups that found the `Ref` in the cache
+    pub hits: u64,
+    /// number of lookups that did not find the `Ref` in the cache
+    pub misses: u64,
+    /// number of `Ref` removed to stay within the capacity
+    pub evictions: u64,
+    /// number of `Ref` currently in the cache
+    pub entries: usize,
+}
+
+#[derive(Default)]
+struct RefCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    entries: AtomicUsize,
 }
 
 /// cache of already loaded in-memory block `Ref`
 struct RefCacheData {
-    entries: HashMap<HeaderHash, (Arc<Ref>, delay_queue::Key)>,
+    entries: LinkedHashMap<HeaderHash, (Arc<Ref>, delay_queue::Key)>,
     expirations: DelayQueue<HeaderHash>,
 
     ttl: Duration,
+    capacity: Option<usize>,
+
+    stats: Arc<RefCacheCounters>,
 }
 
 impl RefCache {
-    /// create a new `RefCache` with the given expiration `Duration`.
+    /// create a new `RefCache` with the given expiration `Duration`
+    /// and, optionally, the maximum number of `Ref` to hold.
     ///
-    pub fn new(ttl: Duration) -> Self {
+    pub fn new(ttl: Duration, capacity: Option<usize>) -> Self {
+        let stats = Arc::new(RefCacheCounters::default());
         RefCache {
-            inner: Lock::new(RefCacheData::new(ttl)),
+            inner: Lock::new(RefCacheData::new(ttl, capacity, Arc::clone(&stats))),
+            stats,
+        }
+    }
+
+    /// the usage of the cache since the node started
+    pub fn stats(&self) -> RefCacheStats {
+        RefCacheStats {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            entries: self.stats.entries.load(Ordering::Relaxed),
         }
     }
 
@@ -91,26 +141,34 @@ impl RefCache {
 }
 
 impl RefCacheData {
-    fn new(ttl: Duration) -> Self {
+    fn new(ttl: Duration, capacity: Option<usize>, stats: Arc<RefCacheCounters>) -> Self {
         RefCacheData {
-            entries: HashMap::new(),
+            entries: LinkedHashMap::new(),
             expirations: DelayQueue::new(),
             ttl,
+            capacity,
+            stats,
         }
     }
 
     fn insert(&mut self, key: HeaderHash, value: Arc<Ref>) {
         let delay = self.expirations.insert(key.clone(), self.ttl);
 
-        self.entries.insert(key, (value, delay));
+        if let Some((_, old_delay)) = self.entries.insert(key, (value, delay)) {
+            self.expirations.remove(&old_delay);
+        }
+        self.evict_over_capacity();
+        self.update_entries();
     }
 
     fn get(&mut self, key: &HeaderHash) -> Option<&Arc<Ref>> {
-        if let Some((v, k)) = self.entries.get(key) {
+        if let Some((v, k)) = self.entries.get_refresh(key) {
             self.expirations.reset(k, self.ttl);
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
 
             Some(v)
         } else {
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
             None
         }
     }
@@ -119,6 +177,38 @@ impl RefCacheData {
         if let Some((_, cache_key)) = self.entries.remove(key) {
             self.expirations.remove(&cache_key);
         }
+        self.update_entries();
+    }
+
+    /// remove the least recently accessed entries beyond the capacity,
+    /// keeping the block0 as every other `Ref` can be rebuilt from it
+    fn evict_over_capacity(&mut self) {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        let mut block0 = None;
+        while self.entries.len() + block0.is_some() as usize > capacity {
+            let (key, (value, delay)) = match self.entries.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if is_block0(&value) {
+                block0 = Some((key, (value, delay)));
+                continue;
+            }
+            self.expirations.remove(&delay);
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some((key, entry)) = block0 {
+            self.entries.insert(key, entry);
+        }
+    }
+
+    fn update_entries(&self) {
+        self.stats
+            .entries
+            .store(self.entries.len(), Ordering::Relaxed);
     }
 
     pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
@@ -127,9 +217,26 @@ impl RefCacheData {
                 Async::NotReady => return Ok(Async::Ready(())),
                 Async::Ready(None) => return Ok(Async::Ready(())),
                 Async::Ready(Some(entry)) => {
-                    self.entries.remove(entry.get_ref());
+                    let key = entry.into_inner();
+                    let keep = self
+                        .entries
+                        .get(&key)
+                        .map_or(false, |(value, _)| is_block0(value));
+                    if keep {
+                        let delay = self.expirations.insert(key.clone(), self.ttl);
+                        if let Some(entry) = self.entries.get_mut(&key) {
+                            entry.1 = delay;
+                        }
+                    } else {
+                        self.entries.remove(&key);
+                        self.update_entries();
+                    }
                 }
             }
         }
     }
 }
+
+fn is_block0(value: &Ref) -> bool {
+    u32::from(value.chain_length()) == 0
+}
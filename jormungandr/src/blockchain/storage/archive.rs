@@ -0,0 +1,146 @@
+//! portable archive of a chain
+//!
+//! An archive starts with the magic bytes, the version of the format and
+//! the hash of the block0 of the chain. It is followed by the blocks, from
+//! the block0 up to the tip, each one prefixed by its size as a big endian
+//! u32. The blocks are written and read one at a time so an archive is
+//! never fully held in memory.
+
+use crate::blockcfg::{Block, HeaderHash};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"JORMCHN\0";
+pub const ARCHIVE_VERSION: u16 = 1;
+
+/// sanity limit preventing a corrupted size from exhausting the memory
+const MAX_BLOCK_SIZE: u32 = 64 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("I/O error on the chain archive")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+    #[error("not a chain archive")]
+    InvalidMagic,
+    #[error("unsupported chain archive version {0}")]
+    UnsupportedVersion(u16),
+    #[error("block of {0} bytes in the chain archive is too large")]
+    BlockTooLarge(u32),
+    #[error("malformed block in the chain archive")]
+    MalformedBlock { source: io::Error },
+    #[error("the chain archive is truncated")]
+    Truncated,
+}
+
+pub struct ArchiveWriter<W> {
+    writer: W,
+    blocks: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// write the header of an archive of the chain starting at `block0_hash`
+    pub fn new(mut writer: W, block0_hash: &HeaderHash) -> Result<Self, ArchiveError> {
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&ARCHIVE_VERSION.to_be_bytes())?;
+        block0_hash.serialize(&mut writer)?;
+        Ok(ArchiveWriter { writer, blocks: 0 })
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<(), ArchiveError> {
+        let bytes = block.serialize_as_vec()?;
+        if bytes.len() > MAX_BLOCK_SIZE as usize {
+            return Err(ArchiveError::BlockTooLarge(bytes.len() as u32));
+        }
+        self.writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.blocks += 1;
+        Ok(())
+    }
+
+    /// flush the archive, returning the number of blocks written
+    pub fn finish(mut self) -> Result<u64, ArchiveError> {
+        self.writer.flush()?;
+        Ok(self.blocks)
+    }
+}
+
+pub struct ArchiveReader<R> {
+    reader: R,
+    block0_hash: HeaderHash,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// read and check the header of the archive
+    pub fn new(mut reader: R) -> Result<Self, ArchiveError> {
+        let mut magic = [0; 8];
+        read_header(&mut reader, &mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(ArchiveError::InvalidMagic);
+        }
+        let mut version = [0; 2];
+        read_header(&mut reader, &mut version)?;
+        let version = u16::from_be_bytes(version);
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        let block0_hash = HeaderHash::deserialize(&mut reader).map_err(truncated)?;
+        Ok(ArchiveReader {
+            reader,
+            block0_hash,
+        })
+    }
+
+    pub fn block0_hash(&self) -> &HeaderHash {
+        &self.block0_hash
+    }
+
+    fn read_block(&mut self) -> Result<Option<Block>, ArchiveError> {
+        let mut size = [0; 4];
+        // the end of the file is only expected between two blocks
+        let mut read = 0;
+        while read < size.len() {
+            match self.reader.read(&mut size[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(ArchiveError::Truncated),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let size = u32::from_be_bytes(size);
+        if size > MAX_BLOCK_SIZE {
+            return Err(ArchiveError::BlockTooLarge(size));
+        }
+        let mut bytes = vec![0; size as usize];
+        self.reader.read_exact(&mut bytes).map_err(truncated)?;
+        Block::deserialize(bytes.as_slice())
+            .map(Some)
+            .map_err(|source| ArchiveError::MalformedBlock { source })
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<Block, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), ArchiveError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ArchiveError::InvalidMagic,
+        _ => e.into(),
+    })
+}
+
+fn truncated(error: io::Error) -> ArchiveError {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => ArchiveError::Truncated,
+        _ => error.into(),
+    }
+}
@@ -0,0 +1,213 @@
+//! the latencies of the operations of the block store
+//!
+//! Only the time spent in the backend is measured, not the time waiting for
+//! the lock of the store, so the latencies tell how fast the disk serves the
+//! node. An operation slower than the threshold is logged as a warning.
+
+use slog::Logger;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// the upper bounds of the buckets of the latency histograms, in milliseconds
+const LATENCY_BUCKETS_MS: [u64; 11] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoKind {
+    Read,
+    Write,
+}
+
+impl IoKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IoKind::Read => "read",
+            IoKind::Write => "write",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IoStats {
+    inner: Arc<IoStatsImpl>,
+}
+
+struct IoStatsImpl {
+    reads: Histogram,
+    writes: Histogram,
+    slow_threshold: Option<Duration>,
+    logger: Logger,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// the operations within each bucket, the last one counting the
+    /// operations slower than all the bounds
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    sum_us: AtomicU64,
+}
+
+/// snapshot of the latencies of one kind of operations
+#[derive(Debug, Clone, Default)]
+pub struct IoLatency {
+    /// the number of operations within each bound of `LATENCY_BUCKETS_MS`,
+    /// cumulative as in the Prometheus histograms
+    pub buckets: Vec<(u64, u64)>,
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl IoStats {
+    /// the operations slower than `slow_threshold`, if set, are logged
+    pub fn new(slow_threshold: Option<Duration>, logger: Logger) -> Self {
+        IoStats {
+            inner: Arc::new(IoStatsImpl {
+                reads: Histogram::default(),
+                writes: Histogram::default(),
+                slow_threshold,
+                logger,
+            }),
+        }
+    }
+
+    /// run the operation on the store, recording its latency
+    pub fn time<T, F>(&self, kind: IoKind, operation: &'static str, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let start = Instant::now();
+        let res = f();
+        let elapsed = start.elapsed();
+        self.histogram(kind).record(elapsed);
+        match self.inner.slow_threshold {
+            Some(threshold) if elapsed >= threshold => warn!(
+                self.inner.logger,
+                "slow storage operation";
+                "operation" => operation,
+                "kind" => kind.as_str(),
+                "duration" => ?elapsed,
+            ),
+            _ => {}
+        }
+        res
+    }
+
+    /// the latencies of the operations of the given kind since the node
+    /// started
+    pub fn latency(&self, kind: IoKind) -> IoLatency {
+        self.histogram(kind).snapshot()
+    }
+
+    fn histogram(&self, kind: IoKind) -> &Histogram {
+        match kind {
+            IoKind::Read => &self.inner.reads,
+            IoKind::Write => &self.inner.writes,
+        }
+    }
+}
+
+impl IoLatency {
+    /// the sum of the latencies in the unit of the bounds, the fractions of
+    /// milliseconds of the fast operations being kept
+    pub fn sum_millis(&self) -> f64 {
+        self.sum.as_micros() as f64 / 1000.0
+    }
+}
+
+impl Histogram {
+    fn record(&self, elapsed: Duration) {
+        // a bucket counts the operations up to its bound included, compared
+        // on the whole duration as the milliseconds are rounded down
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed <= Duration::from_millis(*bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> IoLatency {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS_MS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        let count = cumulative + self.buckets[LATENCY_BUCKETS_MS.len()].load(Ordering::Relaxed);
+        IoLatency {
+            buckets,
+            count,
+            sum: Duration::from_micros(self.sum_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket_counts(latencies: &[Duration]) -> Vec<(u64, u64)> {
+        let histogram = Histogram::default();
+        for latency in latencies {
+            histogram.record(*latency);
+        }
+        histogram.snapshot().buckets
+    }
+
+    fn count_within(buckets: &[(u64, u64)], bound: u64) -> u64 {
+        buckets
+            .iter()
+            .find(|(le, _)| *le == bound)
+            .map(|(_, count)| *count)
+            .unwrap()
+    }
+
+    #[test]
+    fn latency_on_a_bound_is_within_its_bucket() {
+        let buckets = bucket_counts(&[Duration::from_millis(1), Duration::from_millis(5)]);
+        assert_eq!(count_within(&buckets, 1), 1);
+        assert_eq!(count_within(&buckets, 5), 2);
+    }
+
+    #[test]
+    fn latency_past_a_bound_is_in_the_next_bucket() {
+        let buckets = bucket_counts(&[Duration::from_micros(1_001), Duration::from_micros(5_999)]);
+        assert_eq!(count_within(&buckets, 1), 0);
+        assert_eq!(count_within(&buckets, 5), 1);
+        assert_eq!(count_within(&buckets, 10), 2);
+    }
+
+    #[test]
+    fn latency_past_the_last_bound_is_only_counted() {
+        let histogram = Histogram::default();
+        histogram.record(Duration::from_millis(5_000));
+        histogram.record(Duration::from_micros(5_000_001));
+        let latency = histogram.snapshot();
+        assert_eq!(count_within(&latency.buckets, 5_000), 1);
+        assert_eq!(latency.count, 2);
+        assert_eq!(latency.sum, Duration::from_micros(10_000_001));
+    }
+
+    #[test]
+    fn sum_keeps_the_fractions_of_milliseconds() {
+        let histogram = Histogram::default();
+        for _ in 0..3 {
+            histogram.record(Duration::from_micros(250));
+        }
+        assert_eq!(histogram.snapshot().sum_millis().to_string(), "0.75");
+    }
+
+    #[test]
+    fn no_latency_is_within_the_first_bucket() {
+        let buckets = bucket_counts(&[Duration::from_secs(0)]);
+        assert_eq!(count_within(&buckets, 1), 1);
+    }
+}
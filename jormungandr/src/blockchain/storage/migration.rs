@@ -0,0 +1,180 @@
+//! versioning of the layout of the storage directory
+//!
+//! The version of the layout is kept in the `VERSION` file of the storage
+//! directory. On start up, a storage written with an older layout is
+//! upgraded by running the migrations from its version up to
+//! `STORAGE_VERSION`, one version at a time, after the directory was copied
+//! next to it so it can be restored if a migration fails.
+
+use slog::Logger;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// the version of the layout of the storage written by this node
+pub const STORAGE_VERSION: u32 = 1;
+
+/// the version of the storages written before the `VERSION` file existed
+const UNVERSIONED_STORAGE_VERSION: u32 = 1;
+
+const VERSION_FILE: &str = "VERSION";
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("I/O error on the storage directory {path}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("invalid storage version file {path}")]
+    InvalidVersion { path: PathBuf },
+    #[error("the storage has the version {found}, this node only supports up to {supported}")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("no migration from the storage version {0}")]
+    MissingMigration(u32),
+    #[error(
+        "a backup of the storage already exists at {0}, move it away before starting the node"
+    )]
+    BackupExists(PathBuf),
+    #[error(
+        "the migration of the storage from the version {from} failed, the backup is in {backup}"
+    )]
+    MigrationFailed {
+        from: u32,
+        backup: PathBuf,
+        source: io::Error,
+    },
+}
+
+/// an upgrade of the layout of the storage directory from the version
+/// `from` to the next one
+struct Migration {
+    from: u32,
+    description: &'static str,
+    run: fn(&Path, &Logger) -> io::Result<()>,
+}
+
+/// the migrations, in the order of the versions they upgrade from
+const MIGRATIONS: &[Migration] = &[];
+
+/// bring the storage directory to the current layout, creating it if needed
+pub fn migrate_storage(dir: &Path, logger: &Logger) -> Result<(), MigrationError> {
+    let io_error = |path: &Path| {
+        let path = path.to_owned();
+        move |source| MigrationError::Io { path, source }
+    };
+
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+
+    let version = match read_version(dir)? {
+        Some(version) => version,
+        None if is_empty(dir).map_err(io_error(dir))? => {
+            return write_version(dir, STORAGE_VERSION);
+        }
+        None => UNVERSIONED_STORAGE_VERSION,
+    };
+
+    if version > STORAGE_VERSION {
+        return Err(MigrationError::UnsupportedVersion {
+            found: version,
+            supported: STORAGE_VERSION,
+        });
+    }
+    if version == STORAGE_VERSION {
+        // the storages predating the version file get one
+        return write_version(dir, STORAGE_VERSION);
+    }
+
+    let migrations = (version..STORAGE_VERSION)
+        .map(|from| {
+            MIGRATIONS
+                .iter()
+                .find(|migration| migration.from == from)
+                .ok_or(MigrationError::MissingMigration(from))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let backup = backup_path(dir, version);
+    if backup.exists() {
+        return Err(MigrationError::BackupExists(backup));
+    }
+    info!(
+        logger,
+        "backing up the storage before upgrading it" ;
+        "path" => %backup.display(),
+        "version" => version,
+    );
+    copy_dir(dir, &backup).map_err(io_error(&backup))?;
+
+    for migration in migrations {
+        info!(
+            logger,
+            "upgrading the storage" ;
+            "from" => migration.from,
+            "to" => migration.from + 1,
+            "migration" => migration.description,
+        );
+        (migration.run)(dir, logger).map_err(|source| MigrationError::MigrationFailed {
+            from: migration.from,
+            backup: backup.clone(),
+            source,
+        })?;
+        // an interrupted upgrade resumes from the last completed migration
+        write_version(dir, migration.from + 1)?;
+    }
+
+    info!(
+        logger,
+        "storage upgraded, the backup can be removed" ;
+        "version" => STORAGE_VERSION,
+        "backup" => %backup.display(),
+    );
+    Ok(())
+}
+
+fn read_version(dir: &Path) -> Result<Option<u32>, MigrationError> {
+    let path = dir.join(VERSION_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| MigrationError::InvalidVersion { path }),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(MigrationError::Io { path, source }),
+    }
+}
+
+/// write the version in a temporary file first, so the version file is
+/// never left truncated
+fn write_version(dir: &Path, version: u32) -> Result<(), MigrationError> {
+    let path = dir.join(VERSION_FILE);
+    let tmp_path = dir.join(format!("{}.tmp", VERSION_FILE));
+    fs::write(&tmp_path, format!("{}\n", version))
+        .and_then(|()| fs::rename(&tmp_path, &path))
+        .map_err(|source| MigrationError::Io { path, source })
+}
+
+fn is_empty(dir: &Path) -> io::Result<bool> {
+    Ok(fs::read_dir(dir)?.next().is_none())
+}
+
+fn backup_path(dir: &Path, version: u32) -> PathBuf {
+    let name = dir
+        .file_name()
+        .map_or_else(|| "storage".into(), |name| name.to_string_lossy());
+    dir.with_file_name(format!("{}.v{}.backup", name, version))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
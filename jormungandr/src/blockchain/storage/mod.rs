@@ -0,0 +1,752 @@
+mod archive;
+mod io_stats;
+mod migration;
+mod sled_store;
+mod sqlite_store;
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    start_up::NodeStorage,
+    utils::task,
+};
+use chain_storage::{
+    memory::MemoryBlockStore,
+    store::{self, for_path_to_nth_ancestor, BlockInfo},
+};
+use jormungandr_lib::interfaces::EpochRewards;
+use std::{
+    ops::{Deref as _, RangeInclusive},
+    sync::Arc,
+};
+use tokio::prelude::*;
+use tokio::sync::lock::{Lock, LockGuard};
+
+pub use self::archive::{ArchiveError, ArchiveReader, ArchiveWriter};
+pub use self::io_stats::{IoKind, IoStats};
+pub use self::migration::{migrate_storage, MigrationError};
+pub use self::sled_store::SledBlockStore;
+pub use self::sqlite_store::SqliteBlockStore;
+pub use chain_storage::error::Error as StorageError;
+
+/// the block stores the node can keep the blockchain in
+pub trait BlockStore: store::BlockStore<Block = Block> + Send + Sync {
+    /// the blocks whose depth is in the given range
+    fn blocks_in_depth_range(
+        &self,
+        _depths: RangeInclusive<u64>,
+    ) -> Result<Vec<HeaderHash>, StorageError> {
+        Err(removal_not_supported())
+    }
+
+    /// remove the block from the store, whatever its descendants
+    fn remove_block(&mut self, _block_hash: &HeaderHash) -> Result<(), StorageError> {
+        Err(removal_not_supported())
+    }
+
+    /// reclaim the space freed by the removed blocks
+    fn compact(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// write the buffered writes to the disk
+    fn flush(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// record the rewards distributed at the start of an epoch, in place of
+    /// the previous record of the epoch
+    fn put_epoch_rewards(&mut self, _rewards: &EpochRewards) -> Result<(), StorageError> {
+        Err(rewards_not_supported())
+    }
+
+    /// the rewards records of the epochs in the given range, by epoch
+    fn epoch_rewards(
+        &self,
+        _epochs: RangeInclusive<u32>,
+    ) -> Result<Vec<EpochRewards>, StorageError> {
+        Err(rewards_not_supported())
+    }
+}
+
+fn removal_not_supported() -> StorageError {
+    StorageError::BackendError("the storage backend does not support removing blocks".into())
+}
+
+fn rewards_not_supported() -> StorageError {
+    StorageError::BackendError("the storage backend does not keep the rewards history".into())
+}
+
+impl BlockStore for MemoryBlockStore<Block> {}
+
+#[derive(Clone)]
+pub struct Storage {
+    inner: Lock<NodeStorage>,
+    io: IoStats,
+}
+
+/// the fork between the old and the new tip, see `Storage::find_fork`
+#[derive(Debug)]
+pub struct Fork {
+    pub common_ancestor: HeaderHash,
+    /// the blocks from the common ancestor (excluded) to the old tip,
+    /// ordered from the common ancestor
+    pub dropped: Vec<HeaderHash>,
+}
+
+pub struct BlockStream {
+    lock: Lock<NodeStorage>,
+    io: IoStats,
+    to_depth: u64,
+    cur_depth: u64,
+    pending_infos: Vec<BlockInfo<HeaderHash>>,
+}
+
+impl Storage {
+    pub fn new(storage: NodeStorage, io: IoStats) -> Self {
+        Storage {
+            inner: Lock::new(storage),
+            io,
+        }
+    }
+
+    /// the latencies of the operations on the store
+    pub fn io_stats(&self) -> &IoStats {
+        &self.io
+    }
+
+    #[deprecated(since = "new blockchain API", note = "use the stream iterator instead")]
+    pub fn get_inner(&self) -> impl Future<Item = LockGuard<NodeStorage>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+    }
+
+    pub fn get_tag(
+        &self,
+        tag: String,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match io.time(IoKind::Read, "get_tag", || guard.get_tag(&tag)) {
+                Err(error) => future::err(error),
+                Ok(res) => future::ok(res),
+            }
+        })
+    }
+
+    pub fn put_tag(
+        &mut self,
+        tag: String,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = (), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut guard| {
+            match io.time(IoKind::Write, "put_tag", || {
+                guard.put_tag(&tag, &header_hash)
+            }) {
+                Err(error) => future::err(error),
+                Ok(res) => future::ok(res),
+            }
+        })
+    }
+
+    pub fn get(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Option<Block>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match io.time(IoKind::Read, "get_block", || guard.get_block(&header_hash)) {
+                Err(StorageError::BlockNotFound) => future::ok(None),
+                Err(error) => future::err(error),
+                Ok((block, _block_info)) => future::ok(Some(block)),
+            }
+        })
+    }
+
+    pub fn get_with_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Option<(Block, BlockInfo<HeaderHash>)>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match io.time(IoKind::Read, "get_block", || guard.get_block(&header_hash)) {
+                Err(StorageError::BlockNotFound) => future::ok(None),
+                Err(error) => future::err(error),
+                Ok(v) => future::ok(Some(v)),
+            }
+        })
+    }
+
+    pub fn get_block_info(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Option<BlockInfo<HeaderHash>>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match io.time(IoKind::Read, "get_block_info", || {
+                guard.get_block_info(&header_hash)
+            }) {
+                Err(StorageError::BlockNotFound) => future::ok(None),
+                Err(error) => future::err(error),
+                Ok(block_info) => future::ok(Some(block_info)),
+            }
+        })
+    }
+
+    pub fn block_exists(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = bool, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            match io.time(IoKind::Read, "block_exists", || {
+                guard.block_exists(&header_hash)
+            }) {
+                Err(StorageError::BlockNotFound) => future::ok(false),
+                Err(error) => future::err(error),
+                Ok(existence) => future::ok(existence),
+            }
+        })
+    }
+
+    /// the distance from `ancestor` to `descendant`, if it is one of its
+    /// ancestors
+    pub fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> impl Future<Item = Option<u64>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+            io.time(IoKind::Read, "is_ancestor", || {
+                guard.is_ancestor(&ancestor, &descendant)
+            })
+        })
+    }
+
+    pub fn put_block(&mut self, block: Block) -> impl Future<Item = (), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut guard| {
+            match io.time(IoKind::Write, "put_block", || guard.put_block(&block)) {
+                Err(StorageError::BlockNotFound) => unreachable!(),
+                Err(error) => future::err(error),
+                Ok(()) => future::ok(()),
+            }
+        })
+    }
+
+    /// Return values:
+    /// - `Ok(stream)` - `from` is ancestor of `to`, returns blocks between them
+    /// - `Err(CannotIterate)` - `from` is not ancestor of `to`
+    /// - `Err(BlockNotFound)` - `from` or `to` was not found
+    /// - `Err(_)` - some other storage error
+    pub fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future<Item = BlockStream, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let inner_2 = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            let lookup: Result<Option<(u64, BlockInfo<HeaderHash>)>, StorageError> =
+                io.time(IoKind::Read, "stream_from_to", || {
+                    match store.is_ancestor(&from, &to)? {
+                        None => Ok(None),
+                        Some(distance) => Ok(Some((distance, store.get_block_info(&to)?))),
+                    }
+                });
+            match lookup {
+                Err(error) => future::err(error),
+                Ok(None) => future::err(StorageError::CannotIterate),
+                Ok(Some((distance, to_info))) => future::ok(BlockStream {
+                    lock: inner_2,
+                    io,
+                    to_depth: to_info.depth,
+                    cur_depth: to_info.depth - distance,
+                    pending_infos: vec![to_info],
+                }),
+            }
+        })
+    }
+
+    /// remove the blocks that are not ancestors of any of the given `heads`,
+    /// from above `from_depth` up to `keep_depth` blocks below the first
+    /// head. Returns the number of removed blocks and the depth up to which
+    /// the store has been pruned.
+    pub fn prune(
+        &self,
+        heads: Vec<HeaderHash>,
+        from_depth: u64,
+        keep_depth: u64,
+    ) -> impl Future<Item = (usize, u64), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let lock = self.inner.clone();
+        let io = self.io.clone();
+        let heads = Arc::new(heads);
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then({
+                let io = io.clone();
+                move |store| {
+                    io.time(IoKind::Read, "prune_candidates", || {
+                        let tip_info = store.get_block_info(&heads[0])?;
+                        let to_depth = tip_info.depth.saturating_sub(keep_depth);
+                        let candidates = if to_depth > from_depth {
+                            store.blocks_in_depth_range(from_depth + 1..=to_depth)?
+                        } else {
+                            Vec::new()
+                        };
+                        Ok((candidates, to_depth.max(from_depth), heads))
+                    })
+                }
+            })
+            .and_then(move |(candidates, to_depth, heads)| {
+                // the lock is taken for each candidate so the blockchain
+                // task is not blocked for the whole pruning
+                let mut flush_lock = lock.clone();
+                let flush_io = io.clone();
+                stream::iter_ok(candidates)
+                    .fold(0, move |removed, candidate| {
+                        let mut lock = lock.clone();
+                        let io = io.clone();
+                        let heads = Arc::clone(&heads);
+                        future::poll_fn(move || Ok(lock.poll_lock())).and_then(move |mut store| {
+                            for head in heads.iter() {
+                                match io.time(IoKind::Read, "is_ancestor", || {
+                                    store.is_ancestor(&candidate, head)
+                                }) {
+                                    Ok(Some(_)) => return Ok(removed),
+                                    Ok(None) => {}
+                                    // cannot tell, keep the block
+                                    Err(StorageError::BlockNotFound) => return Ok(removed),
+                                    Err(error) => return Err(error),
+                                }
+                            }
+                            io.time(IoKind::Write, "remove_block", || {
+                                store.remove_block(&candidate)
+                            })?;
+                            Ok(removed + 1)
+                        })
+                    })
+                    .and_then(move |removed| {
+                        // the space of the removed blocks is reclaimed by
+                        // the compaction of the maintenance, rewriting the
+                        // whole store
+                        future::poll_fn(move || Ok(flush_lock.poll_lock())).and_then(
+                            move |mut store| {
+                                if removed > 0 {
+                                    flush_io.time(IoKind::Write, "flush", || store.flush())?;
+                                }
+                                Ok((removed, to_depth))
+                            },
+                        )
+                    })
+            })
+    }
+
+    /// write the buffered writes of the store to the disk
+    pub fn flush(&self) -> impl Future<Item = (), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then(move |mut store| io.time(IoKind::Write, "flush", || store.flush()))
+    }
+
+    /// reclaim the space freed by the removed blocks. The store is
+    /// rewritten in a blocking section, locked until it is done.
+    pub fn compact(&self) -> impl Future<Item = (), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut store| {
+            task::blocking(move || io.time(IoKind::Write, "compact", || store.compact()))
+        })
+    }
+
+    pub fn put_epoch_rewards(
+        &self,
+        rewards: EpochRewards,
+    ) -> impl Future<Item = (), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut store| {
+            io.time(IoKind::Write, "put_epoch_rewards", || {
+                store.put_epoch_rewards(&rewards)
+            })
+        })
+    }
+
+    pub fn get_epoch_rewards(
+        &self,
+        epochs: RangeInclusive<u32>,
+    ) -> impl Future<Item = Vec<EpochRewards>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            io.time(IoKind::Read, "epoch_rewards", || {
+                store.epoch_rewards(epochs)
+            })
+        })
+    }
+
+    pub fn get_checkpoints(
+        &self,
+        tip: HeaderHash,
+    ) -> impl Future<Item = Vec<HeaderHash>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            io.time(IoKind::Read, "get_checkpoints", || {
+                let tip_info = store.get_block_info(&tip)?;
+                let mut checkpoints = Vec::new();
+                assert!(tip_info.depth > 0);
+                for_path_to_nth_ancestor(&*store, &tip, tip_info.depth - 1, |block_info| {
+                    checkpoints.push(block_info.block_hash.clone());
+                })?;
+                Ok(checkpoints)
+            })
+        })
+    }
+
+    /// checks if switching the tip from `current` to `candidate` rolls back
+    /// at most `max_depth` blocks, i.e. the `max_depth`th ancestor of
+    /// `current` is also an ancestor of `candidate`
+    pub fn is_within_rollback_depth(
+        &self,
+        current: HeaderHash,
+        candidate: HeaderHash,
+        max_depth: u64,
+    ) -> impl Future<Item = bool, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            io.time(IoKind::Read, "is_within_rollback_depth", || {
+                let current_info = store.get_block_info(&current)?;
+                if current_info.depth <= max_depth {
+                    return Ok(true);
+                }
+                let oldest_kept = for_path_to_nth_ancestor(&*store, &current, max_depth, |_| {})?;
+                Ok(store
+                    .is_ancestor(&oldest_kept.block_hash, &candidate)?
+                    .is_some())
+            })
+        })
+    }
+
+    /// find the common ancestor of the two given blocks, along with the
+    /// blocks from it (excluded) to `old_tip`. Only the infos of the blocks
+    /// are read, the branch of `new_tip` being skipped over with the fast
+    /// links down to the depth of `old_tip`.
+    pub fn find_fork(
+        &self,
+        old_tip: HeaderHash,
+        new_tip: HeaderHash,
+    ) -> impl Future<Item = Fork, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            io.time(IoKind::Read, "find_fork", || {
+                let mut old_info = store.get_block_info(&old_tip)?;
+                let mut new_info = store.get_block_info(&new_tip)?;
+                if new_info.depth > old_info.depth {
+                    let distance = new_info.depth - old_info.depth;
+                    new_info = for_path_to_nth_ancestor(&*store, &new_tip, distance, |_| {})?;
+                }
+                let mut dropped = Vec::new();
+                while old_info.block_hash != new_info.block_hash {
+                    if old_info.depth >= new_info.depth {
+                        let parent = old_info.parent_id();
+                        dropped.push(old_info.block_hash);
+                        old_info = store.get_block_info(&parent)?;
+                    } else {
+                        let parent = new_info.parent_id();
+                        new_info = store.get_block_info(&parent)?;
+                    }
+                }
+                dropped.reverse();
+                Ok(Fork {
+                    common_ancestor: old_info.block_hash,
+                    dropped,
+                })
+            })
+        })
+    }
+
+    pub fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let io = self.io.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            io.time(IoKind::Read, "find_closest_ancestor", || {
+                let mut ancestor = None;
+                let mut closest_found = std::u64::MAX;
+                for checkpoint in checkpoints {
+                    // Checkpoints sent by a peer may not
+                    // be present locally, so we need to ignore certain errors
+                    match store.is_ancestor(&checkpoint, &descendant) {
+                        Ok(None) => {}
+                        Ok(Some(distance)) => {
+                            if closest_found > distance {
+                                ancestor = Some(checkpoint);
+                                closest_found = distance;
+                            }
+                        }
+                        Err(e) => {
+                            // Checkpoints sent by a peer may not
+                            // be present locally, so we need to ignore certain errors
+                            match e {
+                                StorageError::BlockNotFound => {
+                                    // FIXME: add block hash into the error so we
+                                    // can see which of the two it is.
+                                    // For now, just ignore either.
+                                }
+                                _ => return Err(e),
+                            }
+                        }
+                    }
+                }
+                // Could return the distance alongside in a struct?
+                Ok(ancestor)
+            })
+        })
+    }
+}
+
+impl Stream for BlockStream {
+    type Item = Block;
+    type Error = StorageError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.cur_depth >= self.to_depth {
+            return Ok(Async::Ready(None));
+        }
+
+        let guard = try_ready!(Ok(self.lock.poll_lock()));
+
+        self.cur_depth += 1;
+
+        let io = self.io.clone();
+        let (block, _block_info) = io.time(IoKind::Read, "get_block", || {
+            let block_info = self.pending_infos.pop().unwrap();
+
+            if block_info.depth == self.cur_depth {
+                // We've seen this block on a previous ancestor traversal.
+                guard.get_block(&block_info.block_hash)
+            } else {
+                // We don't have this block yet, so search back from
+                // the furthest block that we do have.
+                assert!(self.cur_depth < block_info.depth);
+                let depth = block_info.depth;
+                let parent = block_info.parent_id();
+                self.pending_infos.push(block_info);
+                let block_info = for_path_to_nth_ancestor(
+                    guard.deref().deref(),
+                    &parent,
+                    depth - self.cur_depth - 1,
+                    |new_info| {
+                        self.pending_infos.push(new_info.clone());
+                    },
+                )?;
+
+                guard.get_block(&block_info.block_hash)
+            }
+        })?;
+        Ok(Async::Ready(Some(block)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, ContentsBuilder, Header};
+    use chain_core::property::Block as _;
+    use chain_impl_mockchain::block;
+    use slog::Logger;
+    use std::convert::Infallible;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn block(parent: Option<&Block>, slot_id: u32) -> Block {
+        block::builder(
+            BlockVersion::Genesis,
+            ContentsBuilder::new().into(),
+            |hdr| {
+                let hdr = match parent {
+                    None => hdr.set_genesis(),
+                    Some(parent) => hdr.set_parent(&parent.id(), parent.chain_length().increase()),
+                };
+                let header: Result<Header, Infallible> = Ok(hdr
+                    .set_date(BlockDate { epoch: 0, slot_id })
+                    .to_unsigned_header()
+                    .unwrap()
+                    .generalize());
+                header
+            },
+        )
+        .unwrap()
+    }
+
+    fn storage(runtime: &mut Runtime, blocks: &[&Block]) -> Storage {
+        let logger = Logger::root(slog::Discard, o!());
+        let mut storage = Storage::new(
+            Box::new(MemoryBlockStore::new()),
+            IoStats::new(None, logger),
+        );
+        for block in blocks {
+            runtime
+                .block_on(storage.put_block((*block).clone()))
+                .unwrap();
+        }
+        storage
+    }
+
+    #[test]
+    fn fork_has_the_dropped_blocks_from_the_common_ancestor() {
+        let mut runtime = Runtime::new().unwrap();
+        let block0 = block(None, 0);
+        let old1 = block(Some(&block0), 1);
+        let old2 = block(Some(&old1), 2);
+        let new1 = block(Some(&block0), 3);
+        let new2 = block(Some(&new1), 4);
+        let new3 = block(Some(&new2), 5);
+        let new4 = block(Some(&new3), 6);
+        let storage = storage(
+            &mut runtime,
+            &[&block0, &old1, &old2, &new1, &new2, &new3, &new4],
+        );
+
+        let fork = runtime
+            .block_on(storage.find_fork(old2.id(), new4.id()))
+            .unwrap();
+        assert_eq!(fork.common_ancestor, block0.id());
+        assert_eq!(fork.dropped, vec![old1.id(), old2.id()]);
+
+        let fork = runtime
+            .block_on(storage.find_fork(new4.id(), old1.id()))
+            .unwrap();
+        assert_eq!(fork.common_ancestor, block0.id());
+        assert_eq!(
+            fork.dropped,
+            vec![new1.id(), new2.id(), new3.id(), new4.id()]
+        );
+    }
+
+    #[test]
+    fn fork_of_a_descendant_of_the_tip_drops_no_block() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut blocks = vec![block(None, 0)];
+        for slot_id in 1..100 {
+            let next = block(blocks.last(), slot_id);
+            blocks.push(next);
+        }
+        let storage = storage(&mut runtime, &blocks.iter().collect::<Vec<_>>());
+
+        let fork = runtime
+            .block_on(storage.find_fork(blocks[10].id(), blocks[99].id()))
+            .unwrap();
+        assert_eq!(fork.common_ancestor, blocks[10].id());
+        assert!(fork.dropped.is_empty());
+    }
+
+    /// `old2` forks off two blocks below its tip from the longer branch of
+    /// `new4`, which forks off four blocks below its tip
+    fn forked_storage(runtime: &mut Runtime) -> (Storage, Block, Block) {
+        let block0 = block(None, 0);
+        let old1 = block(Some(&block0), 1);
+        let old2 = block(Some(&old1), 2);
+        let new1 = block(Some(&block0), 3);
+        let new2 = block(Some(&new1), 4);
+        let new3 = block(Some(&new2), 5);
+        let new4 = block(Some(&new3), 6);
+        let storage = storage(
+            runtime,
+            &[&block0, &old1, &old2, &new1, &new2, &new3, &new4],
+        );
+        (storage, old2, new4)
+    }
+
+    fn within_depth(
+        runtime: &mut Runtime,
+        storage: &Storage,
+        current: &Block,
+        candidate: &Block,
+        max_depth: u64,
+    ) -> bool {
+        runtime
+            .block_on(storage.is_within_rollback_depth(current.id(), candidate.id(), max_depth))
+            .unwrap()
+    }
+
+    #[test]
+    fn fork_within_the_rollback_depth_is_allowed() {
+        let mut runtime = Runtime::new().unwrap();
+        let (storage, old2, new4) = forked_storage(&mut runtime);
+        assert!(within_depth(&mut runtime, &storage, &old2, &new4, 2));
+        assert!(within_depth(&mut runtime, &storage, &new4, &old2, 4));
+    }
+
+    #[test]
+    fn fork_beyond_the_rollback_depth_is_refused() {
+        let mut runtime = Runtime::new().unwrap();
+        let (storage, old2, new4) = forked_storage(&mut runtime);
+        assert!(!within_depth(&mut runtime, &storage, &old2, &new4, 1));
+        assert!(!within_depth(&mut runtime, &storage, &new4, &old2, 3));
+    }
+
+    #[test]
+    fn zero_rollback_depth_only_allows_the_descendants_of_the_tip() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut blocks = vec![block(None, 0)];
+        for slot_id in 1..6 {
+            let next = block(blocks.last(), slot_id);
+            blocks.push(next);
+        }
+        let fork = block(Some(&blocks[3]), 6);
+        let mut all: Vec<&Block> = blocks.iter().collect();
+        all.push(&fork);
+        let storage = storage(&mut runtime, &all);
+
+        assert!(within_depth(
+            &mut runtime,
+            &storage,
+            &blocks[3],
+            &blocks[5],
+            0
+        ));
+        assert!(within_depth(&mut runtime, &storage, &blocks[3], &fork, 0));
+        assert!(!within_depth(&mut runtime, &storage, &blocks[4], &fork, 0));
+    }
+
+    #[test]
+    fn tip_shallower_than_the_rollback_depth_switches_to_any_branch() {
+        let mut runtime = Runtime::new().unwrap();
+        let (storage, old2, new4) = forked_storage(&mut runtime);
+        // the ancestors of the tip run out before the rollback depth
+        assert!(within_depth(&mut runtime, &storage, &old2, &new4, 100));
+        assert!(within_depth(&mut runtime, &storage, &new4, &old2, 100));
+    }
+}
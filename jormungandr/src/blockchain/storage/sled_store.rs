@@ -0,0 +1,561 @@
+//! block store backed by a [sled](https://docs.rs/sled) database
+//!
+//! The blocks, their information and the tags are kept in separate trees.
+//! A block's information is written after the block itself so a block is
+//! only visible once fully written. The rewards history is kept in its own
+//! tree too, the records in JSON by epoch.
+//!
+//! The blocks are also indexed by depth, so the pruning reads the blocks of
+//! the depths it checks instead of every block information. The index of a
+//! database written before it existed is built when the database is opened.
+//!
+//! sled reclaims the space of the removed entries only when a segment of
+//! its log is mostly unused, the compaction rewrites the live entries to a
+//! new database next to the current one and then puts it in place of the
+//! current one. An interrupted compaction is finished or rolled back when
+//! the database is opened again.
+
+use super::{BlockStore, StorageError};
+use crate::blockcfg::{Block, HeaderHash};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::store::{self, BackLink, BlockInfo};
+use jormungandr_lib::interfaces::EpochRewards;
+use std::{
+    fs,
+    io::{self, Read as _},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const BLOCKS_TREE: &str = "blocks";
+const BLOCK_INFOS_TREE: &str = "block_infos";
+const TAGS_TREE: &str = "tags";
+const REWARDS_TREE: &str = "rewards";
+const DEPTHS_TREE: &str = "depths";
+/// the value of the entries of the index of the depths
+const NO_VALUE: &[u8] = &[];
+
+/// the extensions of the directories of the compaction, next to the
+/// database: the new database being written, and the previous one while
+/// the new one is put in its place
+const COMPACTING_EXTENSION: &str = "compacting";
+const PREVIOUS_EXTENSION: &str = "previous";
+
+pub struct SledBlockStore {
+    db: sled::Db,
+    blocks: sled::Tree,
+    block_infos: sled::Tree,
+    tags: sled::Tree,
+    rewards: sled::Tree,
+    /// the keys of the blocks by depth: the depth in big endian followed by
+    /// the block hash, the values being empty
+    depths: sled::Tree,
+    path: PathBuf,
+    cache_capacity: Option<u64>,
+    flush_interval: Option<Duration>,
+}
+
+impl SledBlockStore {
+    /// open the database in the given directory, creating it if needed
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        cache_capacity: Option<u64>,
+        flush_interval: Option<Duration>,
+    ) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        recover_compaction(path).map_err(backend_error)?;
+        let mut config = sled::Config::default().path(path);
+        if let Some(cache_capacity) = cache_capacity {
+            config = config.cache_capacity(cache_capacity);
+        }
+        if let Some(flush_interval) = flush_interval {
+            config = config.flush_every_ms(Some(flush_interval.as_millis() as u64));
+        }
+        let db = config.open().map_err(backend_error)?;
+        Self::with_db(db, path.to_owned(), cache_capacity, flush_interval)
+    }
+
+    /// an empty database in memory, taking the place of the database while
+    /// it is closed
+    fn temporary() -> Result<Self, StorageError> {
+        let db = sled::Config::default()
+            .temporary(true)
+            .open()
+            .map_err(backend_error)?;
+        Self::with_db(db, PathBuf::new(), None, None)
+    }
+
+    fn with_db(
+        db: sled::Db,
+        path: PathBuf,
+        cache_capacity: Option<u64>,
+        flush_interval: Option<Duration>,
+    ) -> Result<Self, StorageError> {
+        let store = SledBlockStore {
+            blocks: db.open_tree(BLOCKS_TREE).map_err(backend_error)?,
+            block_infos: db.open_tree(BLOCK_INFOS_TREE).map_err(backend_error)?,
+            tags: db.open_tree(TAGS_TREE).map_err(backend_error)?,
+            rewards: db.open_tree(REWARDS_TREE).map_err(backend_error)?,
+            depths: db.open_tree(DEPTHS_TREE).map_err(backend_error)?,
+            db,
+            path,
+            cache_capacity,
+            flush_interval,
+        };
+        if store.depths.is_empty() {
+            store.index_depths()?;
+        }
+        Ok(store)
+    }
+
+    /// index the blocks of a database written before the index existed
+    fn index_depths(&self) -> Result<(), StorageError> {
+        for entry in self.block_infos.iter() {
+            let (key, value) = entry.map_err(backend_error)?;
+            let depth = read_u64(&mut value.as_ref()).map_err(backend_error)?;
+            self.depths
+                .insert(depth_key(depth, key.as_ref()), NO_VALUE)
+                .map_err(backend_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// the directory next to the database with the given extension
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn remove_dir_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// put the compacted database in place of the current one, the current one
+/// being put back if the compacted one cannot be moved
+fn replace_dir(path: &Path, compacting: &Path) -> io::Result<()> {
+    let previous = sibling(path, PREVIOUS_EXTENSION);
+    remove_dir_if_exists(&previous)?;
+    fs::rename(path, &previous)?;
+    if let Err(e) = fs::rename(compacting, path) {
+        fs::rename(&previous, path)?;
+        return Err(e);
+    }
+    fs::remove_dir_all(&previous)
+}
+
+/// finish the compaction interrupted once the current database was moved
+/// away, or drop the compacted database if the current one is still in
+/// place
+fn recover_compaction(path: &Path) -> io::Result<()> {
+    let previous = sibling(path, PREVIOUS_EXTENSION);
+    let compacting = sibling(path, COMPACTING_EXTENSION);
+    if !path.exists() && previous.exists() {
+        if compacting.exists() {
+            fs::rename(&compacting, path)?;
+        } else {
+            fs::rename(&previous, path)?;
+        }
+    }
+    remove_dir_if_exists(&compacting)?;
+    remove_dir_if_exists(&previous)
+}
+
+impl Drop for SledBlockStore {
+    fn drop(&mut self) {
+        // nothing to do with a failure at this point: the writes that were
+        // not flushed are lost but the database stays consistent
+        let _ = self.db.flush();
+    }
+}
+
+impl store::BlockStore for SledBlockStore {
+    type Block = Block;
+
+    fn put_block_internal(
+        &mut self,
+        block: &Block,
+        block_info: BlockInfo<HeaderHash>,
+    ) -> Result<(), StorageError> {
+        let key = serialize_id(&block_info.block_hash)?;
+        let block = block.serialize_as_vec().map_err(backend_error)?;
+        self.blocks
+            .insert(key.as_slice(), block)
+            .map_err(backend_error)?;
+        self.depths
+            .insert(depth_key(block_info.depth, &key), NO_VALUE)
+            .map_err(backend_error)?;
+        self.block_infos
+            .insert(key.as_slice(), serialize_block_info(&block_info)?)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        let block_info = self.get_block_info(block_hash)?;
+        let key = serialize_id(block_hash)?;
+        let bytes = self
+            .blocks
+            .get(key)
+            .map_err(backend_error)?
+            .ok_or(StorageError::BlockNotFound)?;
+        let block = Block::deserialize(bytes.as_ref()).map_err(backend_error)?;
+        Ok((block, block_info))
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<BlockInfo<HeaderHash>, StorageError> {
+        let key = serialize_id(block_hash)?;
+        let bytes = self
+            .block_infos
+            .get(key)
+            .map_err(backend_error)?
+            .ok_or(StorageError::BlockNotFound)?;
+        deserialize_block_info(block_hash.clone(), bytes.as_ref()).map_err(backend_error)
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &HeaderHash) -> Result<(), StorageError> {
+        self.tags
+            .insert(tag_name.as_bytes(), serialize_id(block_hash)?)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<HeaderHash>, StorageError> {
+        match self.tags.get(tag_name.as_bytes()).map_err(backend_error)? {
+            None => Ok(None),
+            Some(bytes) => HeaderHash::deserialize(bytes.as_ref())
+                .map(Some)
+                .map_err(backend_error),
+        }
+    }
+}
+
+impl BlockStore for SledBlockStore {
+    fn blocks_in_depth_range(
+        &self,
+        depths: RangeInclusive<u64>,
+    ) -> Result<Vec<HeaderHash>, StorageError> {
+        // the keys of the depths past the end start with the next depth
+        let start = depths.start().to_be_bytes();
+        let entries = match depths.end().checked_add(1) {
+            Some(next) => self.depths.range(start..next.to_be_bytes()),
+            None => self.depths.range(start..),
+        };
+        entries
+            .map(|entry| {
+                let (key, _) = entry.map_err(backend_error)?;
+                HeaderHash::deserialize(&key[8..]).map_err(backend_error)
+            })
+            .collect()
+    }
+
+    fn remove_block(&mut self, block_hash: &HeaderHash) -> Result<(), StorageError> {
+        let key = serialize_id(block_hash)?;
+        let depth = match self
+            .block_infos
+            .get(key.as_slice())
+            .map_err(backend_error)?
+        {
+            Some(value) => Some(read_u64(&mut value.as_ref()).map_err(backend_error)?),
+            None => None,
+        };
+        // the information first, so the block stops being visible before
+        // it is removed
+        self.block_infos
+            .remove(key.as_slice())
+            .map_err(backend_error)?;
+        self.blocks.remove(key.as_slice()).map_err(backend_error)?;
+        if let Some(depth) = depth {
+            self.depths
+                .remove(depth_key(depth, &key))
+                .map_err(backend_error)?;
+        }
+        Ok(())
+    }
+
+    /// the live entries are exported to a new database, which takes the
+    /// place of the current one once written to the disk
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let path = self.path.clone();
+        let (cache_capacity, flush_interval) = (self.cache_capacity, self.flush_interval);
+        let compacting = sibling(&path, COMPACTING_EXTENSION);
+        remove_dir_if_exists(&compacting).map_err(backend_error)?;
+        {
+            let db = sled::Config::default()
+                .path(&compacting)
+                .open()
+                .map_err(backend_error)?;
+            db.import(self.db.export());
+            db.flush().map_err(backend_error)?;
+        }
+
+        // the database is closed while its directory is replaced, and opened
+        // again whether it was replaced or not
+        *self = Self::temporary()?;
+        let replaced = replace_dir(&path, &compacting);
+        *self = Self::open(&path, cache_capacity, flush_interval)?;
+        replaced.map_err(backend_error)
+    }
+
+    fn flush(&mut self) -> Result<(), StorageError> {
+        self.db.flush().map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn put_epoch_rewards(&mut self, rewards: &EpochRewards) -> Result<(), StorageError> {
+        let record = serde_json::to_vec(rewards).map_err(backend_error)?;
+        self.rewards
+            .insert(rewards.epoch.to_be_bytes(), record)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn epoch_rewards(
+        &self,
+        epochs: RangeInclusive<u32>,
+    ) -> Result<Vec<EpochRewards>, StorageError> {
+        // the keys are big endian so the records come in the order of the
+        // epochs
+        let range = epochs.start().to_be_bytes()..=epochs.end().to_be_bytes();
+        self.rewards
+            .range(range)
+            .map(|entry| {
+                let (_, record) = entry.map_err(backend_error)?;
+                serde_json::from_slice(record.as_ref()).map_err(backend_error)
+            })
+            .collect()
+    }
+}
+
+pub(super) fn backend_error<E>(error: E) -> StorageError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    StorageError::BackendError(Box::new(error))
+}
+
+fn serialize_id(id: &HeaderHash) -> Result<Vec<u8>, StorageError> {
+    id.serialize_as_vec().map_err(backend_error)
+}
+
+fn depth_key(depth: u64, id: &[u8]) -> Vec<u8> {
+    let mut key = depth.to_be_bytes().to_vec();
+    key.extend_from_slice(id);
+    key
+}
+
+/// the block hash is the key of the record so it is not repeated: the
+/// depth is followed by the back links, each one being its distance
+/// followed by the hash of the block it points to.
+fn serialize_block_info(block_info: &BlockInfo<HeaderHash>) -> Result<Vec<u8>, StorageError> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&block_info.depth.to_be_bytes());
+    for back_link in block_info.back_links.iter() {
+        bytes.extend_from_slice(&back_link.distance.to_be_bytes());
+        bytes.extend(serialize_id(&back_link.block_hash)?);
+    }
+    Ok(bytes)
+}
+
+fn deserialize_block_info(
+    block_hash: HeaderHash,
+    mut bytes: &[u8],
+) -> Result<BlockInfo<HeaderHash>, io::Error> {
+    let depth = read_u64(&mut bytes)?;
+    let mut back_links = Vec::new();
+    while !bytes.is_empty() {
+        let distance = read_u64(&mut bytes)?;
+        let block_hash = HeaderHash::deserialize(&mut bytes)?;
+        back_links.push(BackLink {
+            distance,
+            block_hash,
+        });
+    }
+    Ok(BlockInfo {
+        block_hash,
+        depth,
+        back_links,
+    })
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Result<u64, io::Error> {
+    let mut buf = [0; 8];
+    bytes.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::crypto::hash::Hash;
+    use std::collections::BTreeMap;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-sled-store-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn rewards(epoch: u32, account_reward: u64) -> EpochRewards {
+        let mut accounts = BTreeMap::new();
+        accounts.insert("account".to_owned(), account_reward.into());
+        EpochRewards {
+            epoch,
+            drawn: 100.into(),
+            fees: 10.into(),
+            treasury: 1.into(),
+            stake_pools: BTreeMap::new(),
+            accounts,
+        }
+    }
+
+    #[test]
+    fn rewards_are_read_back_by_epoch() {
+        let path = temp_dir("rewards");
+        {
+            let mut store = SledBlockStore::open(&path, None, None).unwrap();
+            for epoch in &[300, 2, 1] {
+                store
+                    .put_epoch_rewards(&rewards(*epoch, *epoch as u64))
+                    .unwrap();
+            }
+            let epochs = |range| {
+                store
+                    .epoch_rewards(range)
+                    .unwrap()
+                    .iter()
+                    .map(|record| record.epoch)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(epochs(0..=std::u32::MAX), vec![1, 2, 300]);
+            assert_eq!(epochs(2..=299), vec![2]);
+            assert!(epochs(3..=299).is_empty());
+        }
+        {
+            let store = SledBlockStore::open(&path, None, None).unwrap();
+            let records = store.epoch_rewards(300..=300).unwrap();
+            assert_eq!(records, vec![rewards(300, 300)]);
+            assert_eq!(
+                records[0]
+                    .account_reward("account")
+                    .map(|reward| reward.epoch),
+                Some(300)
+            );
+            assert!(records[0].account_reward("other").is_none());
+        }
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    fn block_info(id: u8, depth: u64) -> BlockInfo<HeaderHash> {
+        BlockInfo {
+            block_hash: Hash::from([id; 32]).into_hash(),
+            depth,
+            back_links: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn blocks_are_read_by_depth() {
+        let path = temp_dir("depths");
+        let infos = vec![
+            block_info(1, 1),
+            block_info(2, 2),
+            block_info(3, 2),
+            block_info(4, 3),
+        ];
+        {
+            // a database written before the index of the depths
+            let store = SledBlockStore::open(&path, None, None).unwrap();
+            store.depths.clear().unwrap();
+            for info in infos.iter() {
+                store
+                    .block_infos
+                    .insert(
+                        serialize_id(&info.block_hash).unwrap(),
+                        serialize_block_info(info).unwrap(),
+                    )
+                    .unwrap();
+            }
+        }
+        let mut store = SledBlockStore::open(&path, None, None).unwrap();
+        let hash = |index: usize| infos[index].block_hash.clone();
+
+        assert_eq!(
+            store.blocks_in_depth_range(2..=2).unwrap(),
+            vec![hash(1), hash(2)]
+        );
+        assert_eq!(
+            store.blocks_in_depth_range(2..=std::u64::MAX).unwrap(),
+            vec![hash(1), hash(2), hash(3)]
+        );
+        assert!(store.blocks_in_depth_range(4..=10).unwrap().is_empty());
+
+        store.remove_block(&hash(1)).unwrap();
+        assert_eq!(
+            store.blocks_in_depth_range(0..=3).unwrap(),
+            vec![hash(0), hash(2), hash(3)]
+        );
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn compaction_keeps_the_entries() {
+        let path = temp_dir("compaction");
+        let tip = Hash::from([1; 32]).into_hash();
+        {
+            let mut store = SledBlockStore::open(&path, None, None).unwrap();
+            store.put_tag("tip", &tip).unwrap();
+            store.put_epoch_rewards(&rewards(1, 1)).unwrap();
+            store.compact().unwrap();
+            assert_eq!(store.get_tag("tip").unwrap(), Some(tip));
+            store.put_epoch_rewards(&rewards(2, 2)).unwrap();
+        }
+        assert!(!sibling(&path, COMPACTING_EXTENSION).exists());
+        assert!(!sibling(&path, PREVIOUS_EXTENSION).exists());
+        let store = SledBlockStore::open(&path, None, None).unwrap();
+        assert_eq!(store.get_tag("tip").unwrap(), Some(tip));
+        assert_eq!(
+            store.epoch_rewards(0..=2).unwrap(),
+            vec![rewards(1, 1), rewards(2, 2)]
+        );
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn interrupted_compaction_is_finished_on_open() {
+        let path = temp_dir("interrupted-compaction");
+        let previous = sibling(&path, PREVIOUS_EXTENSION);
+        let compacting = sibling(&path, COMPACTING_EXTENSION);
+        let _ = std::fs::remove_dir_all(&previous);
+        let _ = std::fs::remove_dir_all(&compacting);
+        let tip = Hash::from([1; 32]).into_hash();
+        {
+            let mut store = SledBlockStore::open(&compacting, None, None).unwrap();
+            store.put_tag("tip", &tip).unwrap();
+        }
+        SledBlockStore::open(&previous, None, None).unwrap();
+
+        let store = SledBlockStore::open(&path, None, None).unwrap();
+
+        assert_eq!(store.get_tag("tip").unwrap(), Some(tip));
+        assert!(!previous.exists());
+        assert!(!compacting.exists());
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
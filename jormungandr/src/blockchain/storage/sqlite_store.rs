@@ -0,0 +1,100 @@
+//! block store backed by the SQLite database of `chain-storage-sqlite`
+//!
+//! The blocks are kept by `SQLiteBlockStore`, this store adds the
+//! compaction: SQLite keeps the pages freed by the removed rows for its
+//! next writes, `VACUUM` rewrites the database without them so the file
+//! shrinks.
+
+use super::{sled_store::backend_error, BlockStore, StorageError};
+use crate::blockcfg::{Block, HeaderHash};
+use chain_storage::store::{self, BlockInfo};
+use chain_storage_sqlite::SQLiteBlockStore;
+use std::path::{Path, PathBuf};
+
+pub struct SqliteBlockStore {
+    inner: SQLiteBlockStore<Block>,
+    path: PathBuf,
+}
+
+impl SqliteBlockStore {
+    /// open the database in the given file, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_owned();
+        SqliteBlockStore {
+            inner: SQLiteBlockStore::new(path.clone()),
+            path,
+        }
+    }
+}
+
+impl store::BlockStore for SqliteBlockStore {
+    type Block = Block;
+
+    fn put_block_internal(
+        &mut self,
+        block: &Block,
+        block_info: BlockInfo<HeaderHash>,
+    ) -> Result<(), StorageError> {
+        self.inner.put_block_internal(block, block_info)
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        self.inner.get_block(block_hash)
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<BlockInfo<HeaderHash>, StorageError> {
+        self.inner.get_block_info(block_hash)
+    }
+
+    fn block_exists(&self, block_hash: &HeaderHash) -> Result<bool, StorageError> {
+        self.inner.block_exists(block_hash)
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &HeaderHash) -> Result<(), StorageError> {
+        self.inner.put_tag(tag_name, block_hash)
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<HeaderHash>, StorageError> {
+        self.inner.get_tag(tag_name)
+    }
+}
+
+impl BlockStore for SqliteBlockStore {
+    /// the store is locked while the database is rewritten, so no
+    /// transaction of its connections is running
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let connection = rusqlite::Connection::open(&self.path).map_err(backend_error)?;
+        connection.execute_batch("VACUUM").map_err(backend_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::crypto::hash::Hash;
+
+    #[test]
+    fn compaction_keeps_the_tags() {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-sqlite-store-{}-compaction.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let tip = Hash::from([1; 32]).into_hash();
+        {
+            let mut store = SqliteBlockStore::open(&path);
+            store.put_tag("tip", &tip).unwrap();
+            store.compact().unwrap();
+            assert_eq!(store.get_tag("tip").unwrap(), Some(tip));
+        }
+        let store = SqliteBlockStore::open(&path);
+        assert_eq!(store.get_tag("tip").unwrap(), Some(tip));
+        let _ = std::fs::remove_file(&path);
+    }
+}
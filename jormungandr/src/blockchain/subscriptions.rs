@@ -0,0 +1,360 @@
+//! the addresses watched by the clients of the REST API
+//!
+//! A client subscribes to a set of addresses and polls the events of the
+//! fragments transferring value from or to them: `applied` when the block of
+//! the fragment joins the main chain, `rolled_back` when a reorg drops it.
+//! The subscriptions are identified by random ids, only known to the client
+//! which opened them as they give access to the events of its addresses.
+//! The events are numbered from 1 in each subscription, the client passes the
+//! number of the last event it read to get the next ones. A subscription
+//! watches up to `MAX_ADDRESSES` addresses, only keeps its last `MAX_EVENTS`
+//! events, and is removed once it was not polled for `SUBSCRIPTION_TTL`.
+//!
+//! The addresses of the fragments are found by the chain index, the events are
+//! recorded by the block task as it updates the index, before the tip moves,
+//! or by the backfill of the index while it catches up with the tip.
+//! A failure to record the events is logged, the blocks are still processed.
+
+use super::{ChainIndex, IndexError};
+use crate::blockcfg::Block;
+use chain_addr::Address;
+use chain_core::property::{Block as _, Fragment as _};
+use jormungandr_lib::interfaces;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// the events kept by a subscription not polled
+const MAX_EVENTS: usize = 1000;
+
+/// the subscriptions open at the same time
+const MAX_SUBSCRIPTIONS: usize = 1000;
+
+/// the addresses watched by a subscription
+pub const MAX_ADDRESSES: usize = 100;
+
+/// how long a subscription is kept without being polled
+const SUBSCRIPTION_TTL: Duration = Duration::from_secs(3600);
+
+/// 128 random bits in hexadecimal
+pub type SubscriptionId = String;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SubscribeError {
+    #[error("too many subscriptions are open")]
+    TooManySubscriptions,
+    #[error(
+        "too many addresses to watch, at most {} are watched by a subscription",
+        MAX_ADDRESSES
+    )]
+    TooManyAddresses,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionEventKind {
+    Applied,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionEvent {
+    pub seq: u64,
+    pub event: SubscriptionEventKind,
+    pub fragment_id: String,
+    pub block: String,
+    pub date: String,
+    pub addresses: Vec<interfaces::Address>,
+}
+
+/// the events read by a poll
+#[derive(Debug, Serialize)]
+pub struct SubscriptionEvents {
+    /// the events dropped since the last poll, the subscription holding too
+    /// many events
+    pub missed: u64,
+    pub events: Vec<SubscriptionEvent>,
+}
+
+#[derive(Clone, Default)]
+pub struct Subscriptions(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    subscriptions: HashMap<SubscriptionId, Subscription>,
+}
+
+struct Subscription {
+    /// the bytes of the addresses
+    addresses: HashSet<Vec<u8>>,
+    events: VecDeque<SubscriptionEvent>,
+    next_seq: u64,
+    last_poll: Instant,
+}
+
+impl Subscriptions {
+    /// watch the given addresses
+    pub fn subscribe(&self, addresses: Vec<Address>) -> Result<SubscriptionId, SubscribeError> {
+        let addresses: HashSet<Vec<u8>> = addresses.iter().map(Address::to_bytes).collect();
+        if addresses.len() > MAX_ADDRESSES {
+            return Err(SubscribeError::TooManyAddresses);
+        }
+        let mut inner = self.0.lock().unwrap();
+        inner.purge();
+        if inner.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(SubscribeError::TooManySubscriptions);
+        }
+        let id = format!("{:032x}", rand::random::<u128>());
+        inner.subscriptions.insert(
+            id.clone(),
+            Subscription {
+                addresses,
+                events: VecDeque::new(),
+                next_seq: 1,
+                last_poll: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// returns false if there was no such subscription
+    pub fn unsubscribe(&self, id: &str) -> bool {
+        self.0.lock().unwrap().subscriptions.remove(id).is_some()
+    }
+
+    /// the events following the event numbered `after`, `None` if there is
+    /// no such subscription
+    pub fn poll(&self, id: &str, after: u64) -> Option<SubscriptionEvents> {
+        let mut inner = self.0.lock().unwrap();
+        inner.purge();
+        let subscription = inner.subscriptions.get_mut(id)?;
+        subscription.last_poll = Instant::now();
+        // the events up to `after` are read, they are not kept any longer
+        while subscription
+            .events
+            .front()
+            .map_or(false, |event| event.seq <= after)
+        {
+            subscription.events.pop_front();
+        }
+        let first_seq = subscription
+            .events
+            .front()
+            .map_or(subscription.next_seq, |event| event.seq);
+        Some(SubscriptionEvents {
+            missed: first_seq.saturating_sub(after + 1),
+            events: subscription.events.iter().cloned().collect(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().subscriptions.is_empty()
+    }
+
+    /// record the events of the blocks leaving the main chain, to be called
+    /// before they are removed from the index
+    pub fn record_rolled_back(
+        &self,
+        index: &ChainIndex,
+        blocks: &[Block],
+    ) -> Result<(), IndexError> {
+        self.record(
+            index,
+            SubscriptionEventKind::RolledBack,
+            blocks.iter().rev(),
+        )
+    }
+
+    /// record the events of the blocks joining the main chain, to be called
+    /// once they are added to the index
+    pub fn record_applied(&self, index: &ChainIndex, blocks: &[Block]) -> Result<(), IndexError> {
+        self.record(index, SubscriptionEventKind::Applied, blocks.iter())
+    }
+
+    fn record<'a>(
+        &self,
+        index: &ChainIndex,
+        kind: SubscriptionEventKind,
+        blocks: impl Iterator<Item = &'a Block>,
+    ) -> Result<(), IndexError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        for block in blocks {
+            let block_id = block.id();
+            for fragment in block.contents.iter() {
+                let addresses = keyed(index.addresses_of(fragment)?);
+                if addresses.is_empty() {
+                    continue;
+                }
+                let mut inner = self.0.lock().unwrap();
+                for subscription in inner.subscriptions.values_mut() {
+                    let watched = subscription.watched(&addresses);
+                    if watched.is_empty() {
+                        continue;
+                    }
+                    subscription.push(SubscriptionEvent {
+                        seq: 0,
+                        event: kind,
+                        fragment_id: fragment.id().to_string(),
+                        block: block_id.to_string(),
+                        date: block.header.block_date().to_string(),
+                        addresses: watched,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn purge(&mut self) {
+        self.subscriptions
+            .retain(|_, subscription| subscription.last_poll.elapsed() < SUBSCRIPTION_TTL);
+    }
+}
+
+/// the addresses with their bytes, each address once
+fn keyed(addresses: Vec<Address>) -> Vec<(Vec<u8>, Address)> {
+    let mut seen = HashSet::new();
+    addresses
+        .into_iter()
+        .map(|address| (address.to_bytes(), address))
+        .filter(|(bytes, _)| seen.insert(bytes.clone()))
+        .collect()
+}
+
+impl Subscription {
+    /// the addresses of the fragment watched by the subscription
+    fn watched(&self, addresses: &[(Vec<u8>, Address)]) -> Vec<interfaces::Address> {
+        addresses
+            .iter()
+            .filter(|(bytes, _)| self.addresses.contains(bytes))
+            .map(|(_, address)| address.clone().into())
+            .collect()
+    }
+
+    fn push(&mut self, mut event: SubscriptionEvent) {
+        event.seq = self.next_seq;
+        self.next_seq += 1;
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_addr::{Discrimination, Kind};
+    use chain_crypto::{Ed25519, SecretKey};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn address(seed: u8) -> Address {
+        let secret_key = SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([seed; 32]));
+        Address(Discrimination::Test, Kind::Single(secret_key.to_public()))
+    }
+
+    fn event(kind: SubscriptionEventKind) -> SubscriptionEvent {
+        SubscriptionEvent {
+            seq: 0,
+            event: kind,
+            fragment_id: String::new(),
+            block: String::new(),
+            date: String::new(),
+            addresses: Vec::new(),
+        }
+    }
+
+    fn push_events(subscriptions: &Subscriptions, id: &str, count: usize) {
+        let mut inner = subscriptions.0.lock().unwrap();
+        let subscription = inner.subscriptions.get_mut(id).unwrap();
+        for _ in 0..count {
+            subscription.push(event(SubscriptionEventKind::Applied));
+        }
+    }
+
+    #[test]
+    fn subscription_watches_a_limited_number_of_addresses() {
+        let subscriptions = Subscriptions::default();
+        let too_many = (0..=MAX_ADDRESSES as u8).map(address).collect();
+        assert_eq!(
+            subscriptions.subscribe(too_many),
+            Err(SubscribeError::TooManyAddresses)
+        );
+
+        // an address given twice is watched once
+        let mut addresses: Vec<_> = (0..MAX_ADDRESSES as u8).map(address).collect();
+        addresses.push(address(0));
+        assert!(subscriptions.subscribe(addresses).is_ok());
+    }
+
+    #[test]
+    fn limited_number_of_subscriptions_are_open() {
+        let subscriptions = Subscriptions::default();
+        for _ in 0..MAX_SUBSCRIPTIONS {
+            subscriptions.subscribe(vec![address(1)]).unwrap();
+        }
+        assert_eq!(
+            subscriptions.subscribe(vec![address(1)]),
+            Err(SubscribeError::TooManySubscriptions)
+        );
+    }
+
+    #[test]
+    fn watched_addresses_of_a_fragment_are_reported_once() {
+        let subscriptions = Subscriptions::default();
+        let id = subscriptions
+            .subscribe(vec![address(1), address(2)])
+            .unwrap();
+        let fragment_addresses = keyed(vec![address(3), address(2), address(1), address(2)]);
+
+        let inner = subscriptions.0.lock().unwrap();
+        let watched = inner.subscriptions[&id].watched(&fragment_addresses);
+        let expected: Vec<interfaces::Address> = vec![address(2).into(), address(1).into()];
+        assert_eq!(watched, expected);
+    }
+
+    #[test]
+    fn poll_returns_the_events_after_the_last_read() {
+        let subscriptions = Subscriptions::default();
+        let id = subscriptions.subscribe(vec![address(1)]).unwrap();
+        push_events(&subscriptions, &id, 3);
+
+        let polled = subscriptions.poll(&id, 0).unwrap();
+        assert_eq!(polled.missed, 0);
+        let seqs: Vec<_> = polled.events.iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+
+        let polled = subscriptions.poll(&id, 2).unwrap();
+        assert_eq!(polled.events.len(), 1);
+        assert_eq!(polled.events[0].seq, 3);
+        assert!(subscriptions.poll(&id, 3).unwrap().events.is_empty());
+    }
+
+    #[test]
+    fn events_over_the_limit_are_missed() {
+        let subscriptions = Subscriptions::default();
+        let id = subscriptions.subscribe(vec![address(1)]).unwrap();
+        push_events(&subscriptions, &id, MAX_EVENTS + 5);
+
+        let polled = subscriptions.poll(&id, 0).unwrap();
+        assert_eq!(polled.missed, 5);
+        assert_eq!(polled.events.len(), MAX_EVENTS);
+        assert_eq!(polled.events[0].seq, 6);
+    }
+
+    #[test]
+    fn unsubscribed_subscription_is_not_polled() {
+        let subscriptions = Subscriptions::default();
+        let id = subscriptions.subscribe(vec![address(1)]).unwrap();
+        assert!(subscriptions.unsubscribe(&id));
+        assert!(!subscriptions.unsubscribe(&id));
+        assert!(subscriptions.poll(&id, 0).is_none());
+    }
+}
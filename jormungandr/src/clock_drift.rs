@@ -0,0 +1,199 @@
+//! the monitoring of the drift of the local clock
+//!
+//! The slots, and so the leadership of the node, follow the local clock. The
+//! node samples its time servers periodically and keeps the median of their
+//! offsets: above the threshold a warning is logged and, if configured, the
+//! leaders do not create blocks until the clock is back within the threshold.
+//! A sampling most servers did not answer leaves the previous state
+//! unchanged, so a lone server does not decide the drift.
+
+use crate::{
+    stats_counter::StatsCounter,
+    utils::{sntp, task::TokioServiceInfo},
+};
+use futures::{future, prelude::*};
+use slog::Logger;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::timer::Interval;
+
+/// how long a time server is waited for
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// whether the leaders are paused for the drift of the clock, shared with
+/// the leadership task
+#[derive(Clone, Default)]
+pub struct ClockGuard {
+    paused: Arc<AtomicBool>,
+}
+
+impl ClockGuard {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// returns whether the leaders were paused before
+    fn set_paused(&self, paused: bool) -> bool {
+        self.paused.swap(paused, Ordering::SeqCst)
+    }
+}
+
+/// sample the time servers every `interval`. The future does not complete,
+/// as the node shuts down when one of its tasks ends.
+pub fn monitor(
+    info: TokioServiceInfo,
+    servers: Vec<String>,
+    interval: Duration,
+    threshold: Duration,
+    pause_leadership: bool,
+    stats_counter: StatsCounter,
+    guard: ClockGuard,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = info.logger().clone();
+    let err_logger = logger.clone();
+    let sample_logger = logger.clone();
+    let threshold_ms = threshold.as_millis() as i64;
+    let mut drifted = false;
+
+    Interval::new(Instant::now(), interval)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .and_then(move |_| sample(&servers, &sample_logger))
+        .for_each(move |drift_ms| {
+            let drift_ms = match drift_ms {
+                Some(drift_ms) => drift_ms,
+                None => {
+                    warn!(
+                        logger,
+                        "most time servers did not answer, the drift of the clock is unknown"
+                    );
+                    return Ok(());
+                }
+            };
+            stats_counter.set_clock_drift_ms(drift_ms);
+
+            if drift_ms.abs() > threshold_ms {
+                warn!(
+                    logger,
+                    "the clock of the node drifted from the time servers, check its time synchronization";
+                    "drift_ms" => drift_ms,
+                    "threshold_ms" => threshold_ms,
+                );
+                if pause_leadership && !guard.set_paused(true) {
+                    warn!(logger, "pausing the leaders until the clock is back within the threshold");
+                }
+                drifted = true;
+            } else {
+                if drifted {
+                    info!(
+                        logger,
+                        "the clock of the node is back within the threshold";
+                        "drift_ms" => drift_ms,
+                    );
+                }
+                if guard.set_paused(false) {
+                    info!(logger, "resuming the leaders");
+                }
+                drifted = false;
+            }
+            Ok(())
+        })
+}
+
+/// the median of the offsets of the servers which answered, `None` unless
+/// most of them did
+fn sample(servers: &[String], logger: &Logger) -> impl Future<Item = Option<i64>, Error = ()> {
+    let queries: Vec<_> = servers
+        .iter()
+        .map(|server| {
+            let server = server.clone();
+            let logger = logger.clone();
+            sntp::query(&server, QUERY_TIMEOUT).then(move |res| {
+                Ok::<_, ()>(match res {
+                    Ok(offset) => {
+                        debug!(
+                            logger,
+                            "time server sampled";
+                            "server" => &server,
+                            "offset_ms" => offset.offset_ms,
+                            "round_trip_ms" => offset.round_trip_ms,
+                        );
+                        Some(offset.offset_ms)
+                    }
+                    Err(e) => {
+                        warn!(
+                            logger,
+                            "cannot query the time server";
+                            "server" => &server,
+                            "reason" => %e,
+                        );
+                        None
+                    }
+                })
+            })
+        })
+        .collect();
+    let count = servers.len();
+    future::join_all(queries).map(move |offsets| {
+        let offsets: Vec<_> = offsets.into_iter().flatten().collect();
+        if has_quorum(offsets.len(), count) {
+            median(offsets)
+        } else {
+            None
+        }
+    })
+}
+
+/// whether the servers which answered are a majority of the servers
+fn has_quorum(answered: usize, servers: usize) -> bool {
+    answered * 2 > servers
+}
+
+fn median(mut offsets: Vec<i64>) -> Option<i64> {
+    if offsets.is_empty() {
+        return None;
+    }
+    offsets.sort();
+    let middle = offsets.len() / 2;
+    if offsets.len() % 2 == 0 {
+        Some((offsets[middle - 1] + offsets[middle]) / 2)
+    } else {
+        Some(offsets[middle])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_of_an_odd_number_of_offsets() {
+        assert_eq!(median(vec![30, -10, 1000]), Some(30));
+        assert_eq!(median(vec![7]), Some(7));
+    }
+
+    #[test]
+    fn median_of_an_even_number_of_offsets_is_their_middle() {
+        assert_eq!(median(vec![40, -20, 10, 0]), Some(5));
+        assert_eq!(median(vec![-30, -11]), Some(-20));
+    }
+
+    #[test]
+    fn median_of_no_offsets_is_unknown() {
+        assert_eq!(median(Vec::new()), None);
+    }
+
+    #[test]
+    fn most_servers_must_answer() {
+        assert!(has_quorum(1, 1));
+        assert!(has_quorum(2, 3));
+        assert!(!has_quorum(1, 3));
+        assert!(!has_quorum(2, 4));
+        assert!(has_quorum(3, 4));
+        assert!(!has_quorum(0, 0));
+    }
+}
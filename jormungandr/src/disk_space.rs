@@ -0,0 +1,251 @@
+//! the monitoring of the free space of the storage volume
+//!
+//! A store whose disk fills up in the middle of a write may be left
+//! corrupted. The node checks the space left on the volume of its storage
+//! periodically: below the soft threshold a warning is logged, and below the
+//! hard threshold the node refuses the new fragments and pauses the writes
+//! it can do without, the export of the chain, the compaction of the
+//! storage, the fragment logs on disk, the stake snapshots and the history
+//! of the rewards, until the space is back above the threshold. The blocks
+//! of the chain are still stored.
+
+use crate::stats_counter::StatsCounter;
+use crate::utils::task::TokioServiceInfo;
+use futures::prelude::*;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::timer::Interval;
+
+/// whether the space left is below the hard threshold, shared with the
+/// tasks writing to the disk
+#[derive(Clone, Default)]
+pub struct DiskGuard {
+    low: Arc<AtomicBool>,
+}
+
+impl DiskGuard {
+    pub fn is_low(&self) -> bool {
+        self.low.load(Ordering::SeqCst)
+    }
+
+    /// returns whether the space was low before
+    pub(crate) fn set_low(&self, low: bool) -> bool {
+        self.low.swap(low, Ordering::SeqCst)
+    }
+}
+
+/// the space left relative to the thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Level {
+    below_soft: bool,
+    below_hard: bool,
+}
+
+impl Level {
+    fn of(available: u64, soft_threshold: u64, hard_threshold: u64) -> Self {
+        Level {
+            below_soft: available < soft_threshold,
+            below_hard: available < hard_threshold,
+        }
+    }
+
+    /// the crossings of the thresholds since the previous check, each one
+    /// logged once
+    fn crossed_from(self, previous: Level) -> Crossings {
+        Crossings {
+            below_hard: self.below_hard && !previous.below_hard,
+            above_hard: !self.below_hard && previous.below_hard,
+            above_soft: !self.below_soft && previous.below_soft,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Crossings {
+    below_hard: bool,
+    above_hard: bool,
+    above_soft: bool,
+}
+
+/// the level before the first check, so a volume already below the
+/// thresholds is logged at the first check
+const ABOVE: Level = Level {
+    below_soft: false,
+    below_hard: false,
+};
+
+/// check the space left on the volume of the path every `interval`. The
+/// future does not complete, as the node shuts down when one of its tasks
+/// ends.
+pub fn monitor(
+    info: TokioServiceInfo,
+    path: PathBuf,
+    interval: Duration,
+    soft_threshold: u64,
+    hard_threshold: u64,
+    stats_counter: StatsCounter,
+    guard: DiskGuard,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = info.logger().clone();
+    let err_logger = logger.clone();
+    let mut previous = ABOVE;
+
+    Interval::new(Instant::now(), interval)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .for_each(move |_| {
+            let available = match fs2::available_space(&path) {
+                Ok(available) => available,
+                Err(e) => {
+                    warn!(
+                        logger,
+                        "cannot get the space left on the storage volume";
+                        "path" => %path.display(),
+                        "reason" => %e,
+                    );
+                    return Ok(());
+                }
+            };
+            stats_counter.set_disk_space_available(available);
+            let level = Level::of(available, soft_threshold, hard_threshold);
+            let crossings = level.crossed_from(previous);
+            previous = level;
+            guard.set_low(level.below_hard);
+
+            if crossings.below_hard {
+                error!(
+                    logger,
+                    "the storage volume is almost full, refusing the new fragments and pausing the writes the node can do without";
+                    "available" => available,
+                    "hard_threshold" => hard_threshold,
+                );
+            }
+            if crossings.above_hard {
+                info!(
+                    logger,
+                    "the space left on the storage volume is back above the hard threshold, resuming";
+                    "available" => available,
+                );
+            }
+            if level.below_soft {
+                warn!(
+                    logger,
+                    "the storage volume is running out of space";
+                    "available" => available,
+                    "soft_threshold" => soft_threshold,
+                );
+            }
+            if crossings.above_soft {
+                info!(
+                    logger,
+                    "the space left on the storage volume is back above the soft threshold";
+                    "available" => available,
+                );
+            }
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_against_the_thresholds() {
+        let level = |available| Level::of(available, 2048, 512);
+        assert_eq!(
+            level(4096),
+            Level {
+                below_soft: false,
+                below_hard: false,
+            }
+        );
+        assert_eq!(
+            level(2048),
+            Level {
+                below_soft: false,
+                below_hard: false,
+            }
+        );
+        assert_eq!(
+            level(2047),
+            Level {
+                below_soft: true,
+                below_hard: false,
+            }
+        );
+        assert_eq!(
+            level(512),
+            Level {
+                below_soft: true,
+                below_hard: false,
+            }
+        );
+        assert_eq!(
+            level(511),
+            Level {
+                below_soft: true,
+                below_hard: true,
+            }
+        );
+        assert_eq!(
+            level(0),
+            Level {
+                below_soft: true,
+                below_hard: true,
+            }
+        );
+    }
+
+    #[test]
+    fn each_crossing_of_a_threshold_is_reported_once() {
+        let level = |available| Level::of(available, 2048, 512);
+        let mut previous = ABOVE;
+        let mut checks = Vec::new();
+        for available in &[4096, 256, 128, 1024, 1024, 4096, 4096] {
+            let current = level(*available);
+            checks.push(current.crossed_from(previous));
+            previous = current;
+        }
+        let crossed = |below_hard, above_hard, above_soft| Crossings {
+            below_hard,
+            above_hard,
+            above_soft,
+        };
+        assert_eq!(
+            checks,
+            vec![
+                Crossings::default(),
+                crossed(true, false, false),
+                Crossings::default(),
+                crossed(false, true, false),
+                Crossings::default(),
+                crossed(false, false, true),
+                Crossings::default(),
+            ]
+        );
+    }
+
+    #[test]
+    fn volume_already_low_is_reported_at_the_first_check() {
+        let crossings = Level::of(0, 2048, 512).crossed_from(ABOVE);
+        assert!(crossings.below_hard);
+    }
+
+    #[test]
+    fn guard_is_shared_and_reports_the_previous_state() {
+        let guard = DiskGuard::default();
+        let shared = guard.clone();
+        assert!(!shared.is_low());
+        assert!(!guard.set_low(true));
+        assert!(shared.is_low());
+        assert!(guard.set_low(true));
+        assert!(guard.set_low(false));
+        assert!(!shared.is_low());
+    }
+}
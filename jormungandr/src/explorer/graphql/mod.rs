@@ -945,9 +945,24 @@ impl Epoch {
         self.id.into()
     }
 
-    /// Not yet implemented
-    pub fn stake_distribution(&self) -> FieldResult<StakeDistribution> {
-        Err(ErrorKind::Unimplemented.into())
+    /// The stake distribution the leaders of the epoch are elected with,
+    /// null for the epochs the node did not see the main chain enter and for
+    /// the BFT consensus
+    pub fn stake_distribution(&self, context: &Context) -> Option<StakeDistribution> {
+        context
+            .db
+            .get_stake_distribution(self.id)
+            .wait()
+            .expect("Infallible to not happen")
+            .map(|pools| StakeDistribution {
+                pools: pools
+                    .into_iter()
+                    .map(|(pool_id, stake)| PoolStakeDistribution {
+                        pool: Pool::from_valid_id(pool_id),
+                        delegated_stake: Value::from(&stake),
+                    })
+                    .collect(),
+            })
     }
 
     /// Get a paginated view of all the blocks in this epoch
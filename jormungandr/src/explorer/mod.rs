@@ -14,15 +14,19 @@ use self::persistent_sequence::PersistentSequence;
 use self::future::Either;
 use crate::blockcfg::{
     Block, ChainLength, ConfigParam, ConfigParams, ConsensusVersion, Epoch, Fragment, FragmentId,
-    HeaderHash,
+    Header, HeaderHash,
 };
-use crate::blockchain::{Blockchain, Multiverse, MAIN_BRANCH_TAG};
+use crate::blockchain::{Blockchain, EpochTransition, Multiverse, MAIN_BRANCH_TAG};
 use crate::intercom::ExplorerMsg;
+use crate::utils::async_msg::MessageQueue;
 use crate::utils::task::{Input, TokioServiceInfo};
 use chain_addr::Discrimination;
 use chain_core::property::Block as _;
 use chain_impl_mockchain::certificate::{Certificate, PoolId};
+use chain_impl_mockchain::leadership::LeadershipConsensus;
 use chain_impl_mockchain::multiverse::GCRoot;
+use chain_impl_mockchain::value::Value;
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::sync::Arc;
 use tokio::prelude::*;
@@ -42,6 +46,9 @@ struct Branch {
 #[derive(Clone)]
 struct Tip(Lock<Branch>);
 
+/// the stake delegated to each pool, by epoch
+type StakeDistributions = BTreeMap<Epoch, Vec<(PoolId, Value)>>;
+
 #[derive(Clone)]
 pub struct ExplorerDB {
     /// Structure that keeps all the known states to allow easy branch management
@@ -52,6 +59,9 @@ pub struct ExplorerDB {
     /// performed using the state of this branch, the HeaderHash is used as key for the
     /// multiverse, and the ChainLength is used in the updating process.
     longest_chain_tip: Tip,
+    /// the stake distributions the leaders of the epochs entered by the main
+    /// chain while the node runs are elected with
+    stake_distributions: Lock<StakeDistributions>,
     pub blockchain_config: BlockchainConfig,
 }
 
@@ -127,6 +137,9 @@ impl Explorer {
                     Err(err) => Err(error!(logger, "Explorer error: {}", err)),
                 },
             )),
+            ExplorerMsg::Reorg(reorg) => {
+                info.spawn(explorer_db.follow_reorg(&reorg.new_tip));
+            }
         }
         future::ok::<(), ()>(())
     }
@@ -188,6 +201,7 @@ impl ExplorerDB {
                 id: block0.id(),
                 length: block0.header.chain_length(),
             }),
+            stake_distributions: Lock::new(BTreeMap::new()),
             blockchain_config,
         };
 
@@ -299,6 +313,17 @@ impl ExplorerDB {
             })
     }
 
+    /// the node switched its tip to another branch: the longest chain is
+    /// the one selected by the node, even if a longer one was seen
+    pub fn follow_reorg(&mut self, new_tip: &Header) -> impl Future<Item = (), Error = ()> {
+        self.longest_chain_tip
+            .replace(Branch {
+                id: new_tip.hash(),
+                length: new_tip.chain_length(),
+            })
+            .map_err(|_: Infallible| unreachable!())
+    }
+
     pub fn get_latest_block_hash(&self) -> impl Future<Item = HeaderHash, Error = Infallible> {
         self.longest_chain_tip.blockid()
     }
@@ -319,6 +344,43 @@ impl ExplorerDB {
         self.with_latest_state(move |state| state.epochs.lookup(&epoch).map(|e| (*e).clone()))
     }
 
+    /// the stake delegated to each pool in the epoch, none if the node did not
+    /// see the main chain enter the epoch or for the BFT consensus
+    pub fn get_stake_distribution(
+        &self,
+        epoch: Epoch,
+    ) -> impl Future<Item = Option<Vec<(PoolId, Value)>>, Error = Infallible> {
+        get_lock(&self.stake_distributions)
+            .map(move |distributions| distributions.get(&epoch).cloned())
+    }
+
+    /// record the stake distribution of each epoch entered by the main chain,
+    /// the one of an epoch entered again on another branch replacing it
+    pub fn record_stake_distributions(
+        &self,
+        transitions: MessageQueue<EpochTransition>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let stake_distributions = self.stake_distributions.clone();
+        transitions.for_each(move |transition| {
+            let pools = match transition.tip.epoch_leadership_schedule().consensus() {
+                LeadershipConsensus::GenesisPraos(genesis_praos) => genesis_praos
+                    .distribution()
+                    .to_pools
+                    .iter()
+                    .map(|(pool_id, pool)| (pool_id.clone(), pool.total.total_stake))
+                    .collect(),
+                _ => return Either::A(future::ok(())),
+            };
+            Either::B(
+                get_lock(&stake_distributions)
+                    .map(move |mut distributions| {
+                        distributions.insert(transition.epoch, pools);
+                    })
+                    .map_err(|e| match e {}),
+            )
+        })
+    }
+
     pub fn find_block_by_chain_length(
         &self,
         chain_length: ChainLength,
@@ -596,6 +658,10 @@ impl Tip {
         })
     }
 
+    fn replace(&self, branch: Branch) -> impl Future<Item = (), Error = Infallible> {
+        get_lock(&self.0).map(move |mut current| *current = branch)
+    }
+
     fn blockid(&self) -> impl Future<Item = HeaderHash, Error = Infallible> {
         get_lock(&self.0).map(|guard| (*guard).id)
     }
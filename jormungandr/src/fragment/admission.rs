@@ -0,0 +1,282 @@
+//! the checks of the fragments received from the network or the REST API,
+//! done before they are handed to the fragment task
+//!
+//! Only the properties of a fragment not depending on the state of the ledger
+//! are checked: its size, the number of inputs and outputs of a transaction,
+//! the kind of the fragment and the structure of a transaction. A fragment
+//! passing the checks is still validated against the ledger when applied: the
+//! proposer of an update proposal and the voter of an update vote have to be
+//! BFT leaders, and the vote has to be for an open proposal.
+
+use crate::fragment::Fragment;
+use chain_impl_mockchain::transaction::Transaction;
+use jormungandr_lib::interfaces::FragmentLimits;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Rejection {
+    #[error("the fragment of {size} bytes is larger than the maximum of {max} bytes")]
+    TooLarge { size: usize, max: u32 },
+    #[error("the transaction has {count} inputs, more than the maximum of {max}")]
+    TooManyInputs { count: usize, max: u8 },
+    #[error("the transaction has {count} outputs, more than the maximum of {max}")]
+    TooManyOutputs { count: usize, max: u8 },
+    #[error("the transaction has {witnesses} witnesses for {inputs} inputs")]
+    WitnessCountMismatch { inputs: usize, witnesses: usize },
+    #[error("the transaction does not balance")]
+    Unbalanced,
+    #[error("{0} fragments are not accepted in the pool")]
+    NotAccepted(&'static str),
+}
+
+impl Rejection {
+    /// the code of the rejection, labelling it in the REST responses and in
+    /// the metrics
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::TooLarge { .. } => "too_large",
+            Rejection::TooManyInputs { .. } => "too_many_inputs",
+            Rejection::TooManyOutputs { .. } => "too_many_outputs",
+            Rejection::WitnessCountMismatch { .. } => "witness_count_mismatch",
+            Rejection::Unbalanced => "unbalanced",
+            Rejection::NotAccepted(_) => "not_accepted",
+        }
+    }
+}
+
+/// check the fragment against the limits, then as `check_content` does
+pub fn check(fragment: &Fragment, limits: &FragmentLimits) -> Result<(), Rejection> {
+    let size = fragment.to_raw().size_bytes_plus_size();
+    if size > limits.max_size as usize {
+        return Err(Rejection::TooLarge {
+            size,
+            max: limits.max_size,
+        });
+    }
+    if let Some(counts) = transaction_counts(fragment) {
+        if counts.inputs > limits.max_inputs as usize {
+            return Err(Rejection::TooManyInputs {
+                count: counts.inputs,
+                max: limits.max_inputs,
+            });
+        }
+        if counts.outputs > limits.max_outputs as usize {
+            return Err(Rejection::TooManyOutputs {
+                count: counts.outputs,
+                max: limits.max_outputs,
+            });
+        }
+    }
+    check_content(fragment)
+}
+
+/// check that the fragment is of a kind accepted in the pool and, if it is a
+/// transaction, that it is well formed
+pub fn check_content(fragment: &Fragment) -> Result<(), Rejection> {
+    match fragment {
+        // never valid in the pool, only acceptable in genesis
+        Fragment::Initial(_) => Err(Rejection::NotAccepted("initial")),
+        Fragment::OldUtxoDeclaration(_) => Err(Rejection::NotAccepted("old UTxO declaration")),
+        // general transactions stuff
+        Fragment::Transaction(ref tx) => check_transaction(tx),
+        Fragment::StakeDelegation(ref tx) => check_transaction(tx),
+        Fragment::OwnerStakeDelegation(ref tx) => check_transaction(tx),
+        Fragment::PoolRegistration(ref tx) => check_transaction(tx),
+        Fragment::PoolRetirement(ref tx) => check_transaction(tx),
+        Fragment::PoolUpdate(ref tx) => check_transaction(tx),
+        // governance, the BFT leaders are only known to the ledger
+        Fragment::UpdateProposal(_) => Ok(()),
+        Fragment::UpdateVote(_) => Ok(()),
+    }
+}
+
+struct TransactionCounts {
+    inputs: usize,
+    outputs: usize,
+    witnesses: usize,
+}
+
+fn counts<P>(tx: &Transaction<P>) -> TransactionCounts {
+    let tx = tx.as_slice();
+    TransactionCounts {
+        inputs: tx.inputs().iter().count(),
+        outputs: tx.outputs().iter().count(),
+        witnesses: tx.witnesses().iter().count(),
+    }
+}
+
+fn transaction_counts(fragment: &Fragment) -> Option<TransactionCounts> {
+    match fragment {
+        Fragment::Transaction(ref tx) => Some(counts(tx)),
+        Fragment::StakeDelegation(ref tx) => Some(counts(tx)),
+        Fragment::OwnerStakeDelegation(ref tx) => Some(counts(tx)),
+        Fragment::PoolRegistration(ref tx) => Some(counts(tx)),
+        Fragment::PoolRetirement(ref tx) => Some(counts(tx)),
+        Fragment::PoolUpdate(ref tx) => Some(counts(tx)),
+        _ => None,
+    }
+}
+
+fn check_transaction<P>(tx: &Transaction<P>) -> Result<(), Rejection> {
+    let counts = counts(tx);
+    if counts.witnesses != counts.inputs {
+        return Err(Rejection::WitnessCountMismatch {
+            inputs: counts.inputs,
+            witnesses: counts.witnesses,
+        });
+    }
+    tx.verify_possibly_balanced()
+        .map_err(|_| Rejection::Unbalanced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_addr::{Address, Discrimination, Kind};
+    use chain_core::property::Fragment as _;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::{
+        config::ConfigParam,
+        fragment::config::ConfigParams,
+        key::EitherEd25519SecretKey,
+        leadership::bft::LeaderId,
+        legacy::UtxoDeclaration,
+        transaction::{TxBuilder, Witness},
+        update::{
+            SignedUpdateProposal, SignedUpdateVote, UpdateProposal, UpdateProposalWithProposer,
+            UpdateVote,
+        },
+        value::Value,
+    };
+    use jormungandr_lib::{crypto::hash::Hash, transaction::TransactionBuilder};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn secret_key() -> SecretKey<Ed25519> {
+        SecretKey::generate(&mut StdRng::from_seed([1; 32]))
+    }
+
+    /// a transaction spending the `inputs` to the `outputs`, with the given
+    /// number of witnesses
+    fn transaction(inputs: &[u64], outputs: &[u64], witnesses: usize) -> Fragment {
+        let address = Address(Discrimination::Test, Kind::Single(secret_key().to_public()));
+        let mut builder = TransactionBuilder::new();
+        for (index, input) in inputs.iter().enumerate() {
+            builder.add_utxo_input(Hash::from([1; 32]), index as u8, Value(*input));
+        }
+        for output in outputs {
+            builder.add_output(address.clone(), Value(*output));
+        }
+        let block0_hash = Hash::from([0; 32]).into_hash();
+        let secret_key = EitherEd25519SecretKey::Normal(secret_key());
+        let witnesses: Vec<_> = (0..witnesses)
+            .map(|_| Witness::new_utxo(&block0_hash, &builder.sign_data_hash(), &secret_key))
+            .collect();
+        let tx = TxBuilder::new()
+            .set_nopayload()
+            .set_ios(builder.inputs(), builder.outputs())
+            .set_witnesses(&witnesses)
+            .set_payload_auth(&());
+        Fragment::Transaction(tx)
+    }
+
+    #[test]
+    fn well_formed_transaction_is_accepted() {
+        let fragment = transaction(&[100, 10], &[50, 50], 2);
+        assert!(check(&fragment, &FragmentLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn size_is_limited() {
+        let fragment = transaction(&[100], &[50], 1);
+        let size = fragment.to_raw().size_bytes_plus_size();
+        let mut limits = FragmentLimits::default();
+        limits.max_size = size as u32;
+        assert!(check(&fragment, &limits).is_ok());
+        limits.max_size = size as u32 - 1;
+        match check(&fragment, &limits) {
+            Err(Rejection::TooLarge { size: rejected, .. }) => assert_eq!(rejected, size),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn inputs_are_limited() {
+        let fragment = transaction(&[100, 10], &[50], 2);
+        let mut limits = FragmentLimits::default();
+        limits.max_inputs = 2;
+        assert!(check(&fragment, &limits).is_ok());
+        limits.max_inputs = 1;
+        match check(&fragment, &limits) {
+            Err(Rejection::TooManyInputs { count: 2, max: 1 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn outputs_are_limited() {
+        let fragment = transaction(&[100], &[10, 10, 10], 1);
+        let mut limits = FragmentLimits::default();
+        limits.max_outputs = 3;
+        assert!(check(&fragment, &limits).is_ok());
+        limits.max_outputs = 2;
+        match check(&fragment, &limits) {
+            Err(Rejection::TooManyOutputs { count: 3, max: 2 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn one_witness_per_input() {
+        for witnesses in &[0, 1, 3] {
+            match check_content(&transaction(&[100, 10], &[50], *witnesses)) {
+                Err(Rejection::WitnessCountMismatch {
+                    inputs: 2,
+                    witnesses: rejected,
+                }) => assert_eq!(rejected, *witnesses),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn outputs_are_covered_by_the_inputs() {
+        assert!(check_content(&transaction(&[100], &[100], 1)).is_ok());
+        match check_content(&transaction(&[100], &[101], 1)) {
+            Err(Rejection::Unbalanced) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn genesis_fragments_are_not_accepted() {
+        let initial = Fragment::Initial(ConfigParams::new());
+        let declaration = Fragment::OldUtxoDeclaration(UtxoDeclaration { addrs: Vec::new() });
+        for fragment in &[initial, declaration] {
+            match check_content(fragment) {
+                Err(Rejection::NotAccepted(_)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn update_proposals_and_votes_are_accepted() {
+        let leader_id = LeaderId::from(secret_key().to_public());
+        let mut changes = ConfigParams::new();
+        changes.push(ConfigParam::ProposalExpiration(10));
+        let proposal = Fragment::UpdateProposal(SignedUpdateProposal {
+            proposal: UpdateProposalWithProposer {
+                proposal: UpdateProposal { changes },
+                proposer_id: leader_id.clone(),
+            },
+        });
+        let vote = Fragment::UpdateVote(SignedUpdateVote {
+            vote: UpdateVote {
+                proposal_id: proposal.id(),
+                voter_id: leader_id,
+            },
+        });
+        assert!(check(&proposal, &FragmentLimits::default()).is_ok());
+        assert!(check(&vote, &FragmentLimits::default()).is_ok());
+    }
+}
@@ -1,3 +1,4 @@
+use super::persistent_logs::PersistentLogs;
 use crate::fragment::FragmentId;
 use jormungandr_lib::interfaces::{FragmentLog, FragmentStatus};
 use std::time::Duration;
@@ -11,8 +12,10 @@ use tokio::{
 pub struct Logs(Lock<internal::Logs>);
 
 impl Logs {
-    pub fn new(ttl: Duration) -> Self {
-        Logs(Lock::new(internal::Logs::new(ttl)))
+    /// the logs are also appended to the `persistent` file, if any, from
+    /// which the logs within its retention window are loaded
+    pub fn new(ttl: Duration, persistent: Option<PersistentLogs>) -> Self {
+        Logs(Lock::new(internal::Logs::new(ttl, persistent)))
     }
 
     /// Returns true if fragment was registered
@@ -74,6 +77,16 @@ impl Logs {
         self.run_on_inner(move |inner| inner.logs().cloned().collect())
     }
 
+    /// write the logs kept on disk, if any, to the disk
+    pub fn flush(&self) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(|mut guard| guard.flush())
+    }
+
+    /// set the time to live of the logs registered from now on
+    pub fn set_ttl(&mut self, ttl: Duration) -> impl Future<Item = (), Error = ()> {
+        self.run_on_inner(move |inner| inner.set_ttl(ttl))
+    }
+
     fn run_on_inner<O>(
         &self,
         run: impl FnOnce(&mut internal::Logs) -> O,
@@ -89,6 +102,7 @@ impl Logs {
 }
 
 pub(super) mod internal {
+    use super::PersistentLogs;
     use jormungandr_lib::{
         crypto::hash::Hash,
         interfaces::{FragmentLog, FragmentOrigin, FragmentStatus},
@@ -98,7 +112,7 @@ pub(super) mod internal {
         time::{Duration, Instant},
     };
     use tokio::{
-        prelude::*,
+        prelude::{future::Either, *},
         timer::{self, delay_queue, DelayQueue},
     };
 
@@ -106,17 +120,36 @@ pub(super) mod internal {
         entries: HashMap<Hash, (FragmentLog, delay_queue::Key)>,
         expirations: DelayQueue<Hash>,
         ttl: Duration,
+        persistent: Option<PersistentLogs>,
     }
 
     impl Logs {
-        pub fn new(ttl: Duration) -> Self {
+        pub fn new(ttl: Duration, mut persistent: Option<PersistentLogs>) -> Self {
+            let mut entries = HashMap::new();
+            let mut expirations = DelayQueue::new();
+            // the logs submitted before a restart are kept for the rest of
+            // their time to live
+            let loaded = persistent.as_mut().map(PersistentLogs::load);
+            for (log, age) in loaded.into_iter().flatten() {
+                let left = match ttl.checked_sub(age) {
+                    Some(left) => left,
+                    None => continue,
+                };
+                let delay = expirations.insert(*log.fragment_id(), left);
+                entries.insert(*log.fragment_id(), (log, delay));
+            }
             Logs {
-                entries: HashMap::new(),
-                expirations: DelayQueue::new(),
+                entries,
+                expirations,
                 ttl,
+                persistent,
             }
         }
 
+        pub fn set_ttl(&mut self, ttl: Duration) {
+            self.ttl = ttl;
+        }
+
         pub fn exists(&self, fragment_id: &Hash) -> bool {
             self.entries.contains_key(fragment_id)
         }
@@ -135,6 +168,9 @@ pub(super) mod internal {
                 Entry::Occupied(_) => return false,
                 Entry::Vacant(entry) => entry,
             };
+            if let Some(persistent) = self.persistent.as_mut() {
+                persistent.append(&log);
+            }
             let delay = self.expirations.insert(fragment_id, self.ttl);
             entry.insert((log, delay));
             true
@@ -149,12 +185,13 @@ pub(super) mod internal {
         }
 
         pub fn modify(&mut self, fragment_id: &Hash, status: FragmentStatus) {
-            match self.entries.entry(fragment_id.clone()) {
+            let log = match self.entries.entry(fragment_id.clone()) {
                 Entry::Occupied(mut entry) => {
                     entry.get_mut().0.modify(status);
 
                     self.expirations
                         .reset_at(&entry.get().1, Instant::now() + self.ttl);
+                    &entry.into_mut().0
                 }
                 Entry::Vacant(entry) => {
                     // while a log modification, if the log was not already present in the
@@ -163,11 +200,19 @@ pub(super) mod internal {
                     // be stored.
 
                     let delay = self.expirations.insert(*fragment_id, self.ttl);
-                    entry.insert((
-                        FragmentLog::new(fragment_id.clone().into_hash(), FragmentOrigin::Network),
-                        delay,
-                    ));
+                    &entry
+                        .insert((
+                            FragmentLog::new(
+                                fragment_id.clone().into_hash(),
+                                FragmentOrigin::Network,
+                            ),
+                            delay,
+                        ))
+                        .0
                 }
+            };
+            if let Some(persistent) = self.persistent.as_mut() {
+                persistent.append(log);
             }
         }
 
@@ -180,8 +225,12 @@ pub(super) mod internal {
         pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
             loop {
                 match self.expirations.poll()? {
-                    Async::NotReady => return Ok(Async::Ready(())),
-                    Async::Ready(None) => return Ok(Async::Ready(())),
+                    Async::NotReady | Async::Ready(None) => {
+                        if let Some(persistent) = self.persistent.as_mut() {
+                            persistent.compact();
+                        }
+                        return Ok(Async::Ready(()));
+                    }
                     Async::Ready(Some(entry)) => {
                         self.entries.remove(entry.get_ref());
                     }
@@ -189,8 +238,46 @@ pub(super) mod internal {
             }
         }
 
+        pub fn flush(&mut self) -> impl Future<Item = (), Error = ()> {
+            match self.persistent.as_mut() {
+                Some(persistent) => Either::A(persistent.flush()),
+                None => Either::B(future::ok(())),
+            }
+        }
+
         pub fn logs<'a>(&'a self) -> impl Iterator<Item = &'a FragmentLog> {
             self.entries.values().map(|(v, _)| v)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{internal, PersistentLogs};
+    use crate::disk_space::DiskGuard;
+    use crate::fragment::persistent_logs::tests::{line_updated_ago, log, temp_path};
+    use slog::Logger;
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn loaded_logs_expire_on_their_ttl() {
+        let path = temp_path("ttl");
+        let lines = [
+            line_updated_ago(&log(1), Duration::from_secs(1200)),
+            line_updated_ago(&log(2), Duration::from_secs(300)),
+        ];
+        fs::write(&path, lines.concat()).unwrap();
+        let persistent = PersistentLogs::open(
+            &path,
+            Duration::from_secs(3600),
+            DiskGuard::default(),
+            Logger::root(slog::Discard, o!()),
+        )
+        .unwrap();
+
+        let logs = internal::Logs::new(Duration::from_secs(600), Some(persistent));
+        assert!(!logs.exists(log(1).fragment_id()));
+        assert!(logs.exists(log(2).fragment_id()));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,11 +1,14 @@
+pub mod admission;
 mod entry;
 mod logs;
+mod persistent_logs;
 mod pool;
 mod process;
 pub mod selection;
 
 pub use self::entry::PoolEntry;
 pub use self::logs::Logs;
+pub use self::persistent_logs::PersistentLogs;
 pub use self::pool::Pool;
 pub use self::process::Process;
 
@@ -0,0 +1,396 @@
+//! the fragment logs kept on disk
+//!
+//! Every new or modified fragment log is appended to the file as a line of
+//! JSON, the last line of a fragment being its current log. The file is a
+//! ring buffer over the retention window: at the garbage collection of the
+//! logs it is compacted once it grew to twice the logs it held, dropping the
+//! superseded lines and the logs not modified within the retention window.
+//!
+//! On startup, the logs still within the retention window are loaded back so
+//! the status of the fragments submitted before a restart can be queried.
+//! The file is then written by a thread of its own, the writes and the
+//! compactions being queued so they never block the tasks of the node.
+//!
+//! While the storage volume is almost full the logs are not written, so the
+//! file may tell an older status of the fragments updated in the meantime.
+
+use crate::disk_space::DiskGuard;
+use futures::sync::oneshot;
+use jormungandr_lib::{crypto::hash::Hash, interfaces::FragmentLog, time::SystemTime};
+use slog::Logger;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead as _, BufReader, BufWriter, Write as _},
+    mem,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use tokio::prelude::*;
+
+/// the number of lines below which the file is not compacted
+const MIN_COMPACTION_LINES: usize = 1024;
+
+enum Request {
+    Append(FragmentLog),
+    Compact,
+    Flush(oneshot::Sender<()>),
+}
+
+pub struct PersistentLogs {
+    requests: mpsc::Sender<Request>,
+    /// the logs read when opening the file, until they are loaded
+    loaded: Vec<(FragmentLog, Duration)>,
+    disk_guard: DiskGuard,
+    /// whether the logs were not written at the last append
+    paused: bool,
+    logger: Logger,
+}
+
+impl PersistentLogs {
+    /// open the file to append to, created if missing, read the logs still
+    /// within the retention window and start the thread writing the file
+    pub fn open(
+        path: &Path,
+        retention: Duration,
+        disk_guard: DiskGuard,
+        logger: Logger,
+    ) -> io::Result<Self> {
+        let mut writer = Writer::open(path, retention, logger.clone())?;
+        let loaded = writer.load();
+        let (requests, queue) = mpsc::channel();
+        thread::Builder::new()
+            .name("fragment-logs".to_owned())
+            .spawn(move || writer.serve(queue))?;
+        Ok(PersistentLogs {
+            requests,
+            loaded,
+            disk_guard,
+            paused: false,
+            logger,
+        })
+    }
+
+    /// the logs of the file still within the retention window when it was
+    /// opened, with the time since they were last updated
+    pub fn load(&mut self) -> Vec<(FragmentLog, Duration)> {
+        mem::replace(&mut self.loaded, Vec::new())
+    }
+
+    pub fn append(&mut self, log: &FragmentLog) {
+        let low = self.disk_guard.is_low();
+        if low != self.paused {
+            self.paused = low;
+            if low {
+                warn!(
+                    self.logger,
+                    "the storage volume is almost full, not writing the fragment logs"
+                );
+            } else {
+                info!(self.logger, "writing the fragment logs again");
+            }
+        }
+        if !low {
+            self.send(Request::Append(log.clone()))
+        }
+    }
+
+    /// compact the file if it grew to twice the logs it held
+    pub fn compact(&mut self) {
+        if !self.disk_guard.is_low() {
+            self.send(Request::Compact)
+        }
+    }
+
+    /// wait for the logs appended to reach the disk
+    pub fn flush(&mut self) -> impl Future<Item = (), Error = ()> {
+        let (reply, flushed) = oneshot::channel();
+        self.send(Request::Flush(reply));
+        flushed.map_err(|_| ())
+    }
+
+    fn send(&self, request: Request) {
+        if self.requests.send(request).is_err() {
+            warn!(self.logger, "the fragment logs are not written anymore");
+        }
+    }
+}
+
+/// the file, written by the thread of the logs
+struct Writer {
+    path: PathBuf,
+    file: File,
+    retention: Duration,
+    /// the number of lines in the file
+    lines: usize,
+    /// the number of logs in the file at the last compaction
+    compacted_lines: usize,
+    logger: Logger,
+}
+
+impl Writer {
+    fn open(path: &Path, retention: Duration, logger: Logger) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Writer {
+            path: path.to_path_buf(),
+            file,
+            retention,
+            lines: 0,
+            compacted_lines: 0,
+            logger,
+        })
+    }
+
+    fn load(&mut self) -> Vec<(FragmentLog, Duration)> {
+        let (logs, lines) = match self.read() {
+            Ok(read) => read,
+            Err(e) => {
+                warn!(self.logger, "cannot read the fragment logs"; "reason" => %e);
+                return Vec::new();
+            }
+        };
+        self.lines = lines;
+        self.compacted_lines = logs.len();
+        info!(self.logger, "fragment logs loaded"; "logs" => logs.len());
+        logs
+    }
+
+    fn serve(mut self, queue: mpsc::Receiver<Request>) {
+        for request in queue {
+            match request {
+                Request::Append(log) => self.append(&log),
+                Request::Compact => self.compact(),
+                Request::Flush(reply) => {
+                    self.flush();
+                    let _ = reply.send(());
+                }
+            }
+        }
+        self.flush();
+    }
+
+    fn append(&mut self, log: &FragmentLog) {
+        let mut line = match serde_json::to_vec(log) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(self.logger, "cannot serialize the fragment log"; "reason" => %e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        match self.file.write_all(&line) {
+            Ok(()) => self.lines += 1,
+            Err(e) => warn!(self.logger, "cannot write the fragment log"; "reason" => %e),
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.file.sync_data() {
+            warn!(self.logger, "cannot flush the fragment logs"; "reason" => %e);
+        }
+    }
+
+    fn compact(&mut self) {
+        if self.lines < MIN_COMPACTION_LINES || self.lines < 2 * self.compacted_lines {
+            return;
+        }
+        match self.rewrite() {
+            Ok(logs) => {
+                debug!(self.logger, "fragment logs compacted"; "logs" => logs);
+                self.lines = logs;
+                self.compacted_lines = logs;
+            }
+            Err(e) => warn!(self.logger, "cannot compact the fragment logs"; "reason" => %e),
+        }
+    }
+
+    /// the current logs of the file within the retention window with their
+    /// age, and the number of lines in the file
+    fn read(&self) -> io::Result<(Vec<(FragmentLog, Duration)>, usize)> {
+        let mut logs: HashMap<Hash, FragmentLog> = HashMap::new();
+        let mut lines = 0;
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            let line = line?;
+            lines += 1;
+            // the last line may have been cut short by a crash of the node
+            match serde_json::from_str::<FragmentLog>(&line) {
+                Ok(log) => {
+                    logs.insert(*log.fragment_id(), log);
+                }
+                Err(e) => warn!(
+                    self.logger,
+                    "skipping a malformed fragment log";
+                    "line" => lines,
+                    "reason" => %e,
+                ),
+            }
+        }
+        let now = SystemTime::now();
+        let logs = logs
+            .into_iter()
+            .filter_map(|(_, log)| {
+                let age: Duration = now
+                    .duration_since(*log.last_updated_at())
+                    .map(Into::into)
+                    .unwrap_or_default();
+                if age < self.retention {
+                    Some((log, age))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok((logs, lines))
+    }
+
+    /// replace the file with the current logs within the retention window,
+    /// returning their number
+    fn rewrite(&mut self) -> io::Result<usize> {
+        let (logs, _) = self.read()?;
+        let mut temp_path = OsString::from(&self.path);
+        temp_path.push(".compacting");
+        let temp_path = PathBuf::from(temp_path);
+        {
+            let mut temp = BufWriter::new(File::create(&temp_path)?);
+            for (log, _) in logs.iter() {
+                serde_json::to_writer(&mut temp, log)?;
+                temp.write_all(b"\n")?;
+            }
+            temp.into_inner()?.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(logs.len())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use jormungandr_lib::interfaces::{FragmentOrigin, FragmentStatus};
+
+    pub fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-fragment-logs-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    pub fn log(id: u8) -> FragmentLog {
+        FragmentLog::new(Hash::from([id; 32]).into_hash(), FragmentOrigin::Rest)
+    }
+
+    /// the JSON line of the log, last updated `age` ago
+    pub fn line_updated_ago(log: &FragmentLog, age: Duration) -> String {
+        let mut value = serde_json::to_value(log).unwrap();
+        let updated_at = SystemTime::from(std::time::SystemTime::now() - age);
+        value["last_updated_at"] = serde_json::to_value(updated_at).unwrap();
+        format!("{}\n", value)
+    }
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    fn open(path: &Path, retention: Duration) -> PersistentLogs {
+        PersistentLogs::open(path, retention, DiskGuard::default(), logger()).unwrap()
+    }
+
+    fn lines(path: &Path) -> usize {
+        BufReader::new(File::open(path).unwrap()).lines().count()
+    }
+
+    #[test]
+    fn logs_are_loaded_back() {
+        let path = temp_path("loaded-back");
+        let retention = Duration::from_secs(3600);
+        let mut logs = open(&path, retention);
+        assert!(logs.load().is_empty());
+        let mut modified = log(2);
+        logs.append(&log(1));
+        logs.append(&modified);
+        modified.modify(FragmentStatus::Rejected {
+            reason: "rejected".to_owned(),
+        });
+        logs.append(&modified);
+        logs.flush().wait().unwrap();
+
+        let mut loaded = open(&path, retention).load();
+        loaded.sort_by_key(|(log, _)| *log.fragment_id());
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].0, log(1));
+        assert_eq!(loaded[1].0, modified);
+        assert!(loaded.iter().all(|(_, age)| *age < retention));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn logs_out_of_the_retention_window_are_not_loaded() {
+        let path = temp_path("retention");
+        let retention = Duration::from_secs(3600);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(line_updated_ago(&log(1), Duration::from_secs(7200)).as_bytes())
+            .unwrap();
+        file.write_all(line_updated_ago(&log(2), Duration::from_secs(1800)).as_bytes())
+            .unwrap();
+        // a line cut short by a crash
+        file.write_all(b"{\"fragment_id\":").unwrap();
+
+        let loaded = open(&path, retention).load();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].0.fragment_id(), log(2).fragment_id());
+        assert!(loaded[0].1 >= Duration::from_secs(1800));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_drops_the_superseded_lines() {
+        let path = temp_path("compaction");
+        let mut logs = open(&path, Duration::from_secs(3600));
+        logs.append(&log(1));
+        logs.compact();
+        logs.flush().wait().unwrap();
+        assert_eq!(lines(&path), 1);
+
+        for _ in 0..MIN_COMPACTION_LINES {
+            logs.append(&log(2));
+        }
+        logs.compact();
+        logs.flush().wait().unwrap();
+        assert_eq!(lines(&path), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn logs_are_not_written_while_the_space_is_low() {
+        let path = temp_path("space-low");
+        let disk_guard = DiskGuard::default();
+        let mut logs = PersistentLogs::open(
+            &path,
+            Duration::from_secs(3600),
+            disk_guard.clone(),
+            logger(),
+        )
+        .unwrap();
+        logs.append(&log(1));
+        disk_guard.set_low(true);
+        logs.append(&log(2));
+        for _ in 0..MIN_COMPACTION_LINES {
+            logs.append(&log(3));
+        }
+        logs.flush().wait().unwrap();
+        assert_eq!(lines(&path), 1);
+
+        disk_guard.set_low(false);
+        logs.append(&log(4));
+        logs.flush().wait().unwrap();
+        assert_eq!(lines(&path), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
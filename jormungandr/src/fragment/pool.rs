@@ -1,14 +1,13 @@
 use crate::{
     blockcfg::{HeaderContentEvalContext, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
-    intercom::{NetworkMsg, PropagateMsg},
+    fragment::{admission, selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
+    intercom::{NetworkMsg, PropagateMsg, ReorgEvent},
     utils::async_msg::MessageBox,
 };
 use chain_core::property::Fragment as _;
-use chain_impl_mockchain::transaction::Transaction;
 use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin, FragmentStatus};
 use slog::Logger;
-use std::time::Duration;
+use std::{collections::HashSet, time::Duration};
 use tokio::{
     prelude::{
         future::{
@@ -101,6 +100,65 @@ impl Pool {
             .and_then(move |fragment_ids| logs.modify_all(fragment_ids, status))
     }
 
+    /// set the time to live of the fragments inserted from now on, along
+    /// with the one of their logs
+    pub fn set_ttl(
+        &mut self,
+        ttl: Duration,
+        logs_ttl: Duration,
+    ) -> impl Future<Item = (), Error = ()> {
+        let mut pool_lock = self.pool.clone();
+        let mut logs = self.logs.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock()))
+            .map(move |mut pool| pool.set_ttl(ttl))
+            .and_then(move |()| logs.set_ttl(logs_ttl))
+    }
+
+    /// put back in the pool the fragments of the blocks dropped from the
+    /// main chain that are not in the blocks that replaced them. Returns
+    /// the number of fragments put back.
+    pub fn reinject_dropped(
+        &mut self,
+        reorg: &ReorgEvent,
+    ) -> impl Future<Item = usize, Error = ()> {
+        let applied = reorg
+            .applied
+            .iter()
+            .flat_map(|block| block.fragments().map(Fragment::id))
+            .collect::<HashSet<_>>();
+        let fragments = reorg
+            .dropped
+            .iter()
+            .flat_map(|block| block.fragments())
+            .filter(|fragment| !applied.contains(&fragment.id()) && is_fragment_valid(fragment))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut pool_lock = self.pool.clone();
+        let mut logs = self.logs.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).and_then(move |mut pool| {
+            let fragment_ids = pool
+                .insert_all(fragments)
+                .iter()
+                .map(Fragment::id)
+                .collect::<Vec<_>>();
+            let count = fragment_ids.len();
+            logs.modify_all(fragment_ids, FragmentStatus::Pending)
+                .map(move |()| count)
+        })
+    }
+
+    /// the fragments waiting to be included in a block, the oldest first
+    pub fn pending(&self) -> impl Future<Item = Vec<Fragment>, Error = ()> {
+        let mut pool_lock = self.pool.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).map(|pool| pool.fragments())
+    }
+
+    /// the number of the fragments waiting to be included in a block
+    pub fn count_pending(&self) -> impl Future<Item = usize, Error = ()> {
+        let mut pool_lock = self.pool.clone();
+        future::poll_fn(move || Ok(pool_lock.poll_lock())).map(|pool| pool.len())
+    }
+
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         let mut lock = self.pool.clone();
         let purge_logs = self.logs.poll_purge();
@@ -133,25 +191,7 @@ impl Pool {
 }
 
 fn is_fragment_valid(fragment: &Fragment) -> bool {
-    match fragment {
-        // never valid in the pool, only acceptable in genesis
-        Fragment::Initial(_) => false,
-        Fragment::OldUtxoDeclaration(_) => false,
-        // general transactions stuff
-        Fragment::Transaction(ref tx) => is_transaction_valid(tx),
-        Fragment::StakeDelegation(ref tx) => is_transaction_valid(tx),
-        Fragment::OwnerStakeDelegation(ref tx) => is_transaction_valid(tx),
-        Fragment::PoolRegistration(ref tx) => is_transaction_valid(tx),
-        Fragment::PoolRetirement(ref tx) => is_transaction_valid(tx),
-        // disabled for now
-        Fragment::PoolUpdate(_) => false,
-        Fragment::UpdateProposal(_) => false,
-        Fragment::UpdateVote(_) => false,
-    }
-}
-
-fn is_transaction_valid<E>(tx: &Transaction<E>) -> bool {
-    tx.verify_possibly_balanced().is_ok()
+    admission::check_content(fragment).is_ok()
 }
 
 pub(super) mod internal {
@@ -220,6 +260,10 @@ pub(super) mod internal {
             }
         }
 
+        pub fn set_ttl(&mut self, ttl: Duration) {
+            self.ttl = ttl;
+        }
+
         pub fn remove_all(&mut self, fragment_ids: impl IntoIterator<Item = FragmentId>) {
             // TODO fix terrible performance, entries_by_time are linear searched N times
             for fragment_id in fragment_ids {
@@ -227,6 +271,19 @@ pub(super) mod internal {
             }
         }
 
+        /// clones of the fragments, the oldest first
+        pub fn fragments(&self) -> Vec<Fragment> {
+            self.entries_by_time
+                .iter()
+                .filter_map(|id| self.entries.get(id))
+                .map(|(_, fragment, _)| fragment.clone())
+                .collect()
+        }
+
+        pub fn len(&self) -> usize {
+            self.entries_by_time.len()
+        }
+
         pub fn remove_oldest(&mut self) -> Option<Fragment> {
             let fragment_id = self.entries_by_time.pop_front()?;
             let (_, fragment, cache_key) = self
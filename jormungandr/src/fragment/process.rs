@@ -1,40 +1,47 @@
 use crate::{
-    fragment::{Logs, Pool},
+    disk_space::DiskGuard,
+    fragment::{Logs, PersistentLogs, Pool},
     intercom::{NetworkMsg, TransactionMsg},
+    log,
     stats_counter::StatsCounter,
-    utils::{
-        async_msg::{MessageBox, MessageQueue},
-        task::TokioServiceInfo,
-    },
+    utils::{async_msg::MessageBox, task::TokioServiceInfo},
 };
 use slog::Logger;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::{
     prelude::{
-        future::Either::{A, B},
+        future::{
+            self,
+            Either::{A, B},
+        },
         Future, Stream,
     },
     timer::Interval,
 };
 
+#[derive(Clone)]
 pub struct Process {
     pool: Pool,
     logs: Logs,
     garbage_collection_interval: Duration,
+    disk_guard: DiskGuard,
 }
 
 impl Process {
     pub fn new(
         pool_ttl: Duration,
         logs_ttl: Duration,
+        persistent_logs: Option<PersistentLogs>,
         garbage_collection_interval: Duration,
         network_msg_box: MessageBox<NetworkMsg>,
+        disk_guard: DiskGuard,
     ) -> Self {
-        let logs = Logs::new(logs_ttl);
+        let logs = Logs::new(logs_ttl, persistent_logs);
         Process {
             pool: Pool::new(pool_ttl, logs.clone(), network_msg_box),
             logs,
             garbage_collection_interval,
+            disk_guard,
         }
     }
 
@@ -45,16 +52,24 @@ impl Process {
         &self.pool
     }
 
-    pub fn start(
+    pub fn start<S>(
         self,
         service_info: TokioServiceInfo,
         stats_counter: StatsCounter,
-        input: MessageQueue<TransactionMsg>,
-    ) -> impl Future<Item = (), Error = ()> {
-        service_info.spawn(self.start_pool_garbage_collector(service_info.logger().clone()));
-        input.for_each(move |input| {
-            match input {
-                TransactionMsg::SendTransaction(origin, txs) => {
+        input: S,
+    ) -> impl Future<Item = (), Error = ()>
+    where
+        S: Stream<Item = TransactionMsg, Error = ()>,
+    {
+        // the garbage collector stops with the task, a restarted task runs
+        // its own
+        let garbage_collector = self.start_pool_garbage_collector(service_info.logger().clone());
+        let process_input = input.for_each(move |input| {
+            let variant = input.variant_name();
+            let started = Instant::now();
+            let task_stats_counter = stats_counter.clone();
+            let processed = match input {
+                TransactionMsg::SendTransaction(trace, origin, txs) => {
                     // Note that we cannot use apply_block here, since we don't have a valid context to which to apply
                     // those blocks. one valid tx in a given context, could be invalid in another. for example
                     // fee calculations, existence utxo / account solvency.
@@ -66,19 +81,61 @@ impl Process {
                     // for other message we don't want to receive them through this interface, and possibly
                     // put them in another pool.
 
-                    let stats_counter = stats_counter.clone();
-                    A(self
-                        .pool
-                        .clone()
-                        .insert_and_propagate_all(origin, txs, service_info.logger().clone())
-                        .map(move |count| stats_counter.add_tx_recv_cnt(count)))
+                    let logger = service_info
+                        .logger()
+                        .new(o!(log::KEY_TRACE => trace.to_string()));
+                    if drop_while_low(&self.disk_guard, &stats_counter, txs.len(), &logger) {
+                        A(A(A(future::ok(()))))
+                    } else {
+                        let stats_counter = stats_counter.clone();
+                        A(A(B(self
+                            .pool
+                            .clone()
+                            .insert_and_propagate_all(origin, txs, logger)
+                            .map(move |count| stats_counter.add_tx_recv_cnt(count)))))
+                    }
                 }
-                TransactionMsg::RemoveTransactions(fragment_ids, status) => B(self
+                TransactionMsg::RemoveTransactions(fragment_ids, status) => A(B(self
                     .pool
                     .clone()
-                    .remove_added_to_block(fragment_ids, status)),
-            }
-        })
+                    .remove_added_to_block(fragment_ids, status))),
+                TransactionMsg::SetTtl {
+                    fragment_ttl,
+                    log_ttl,
+                } => B(A(self.pool.clone().set_ttl(fragment_ttl, log_ttl))),
+                TransactionMsg::Reorg(reorg) => {
+                    let logger = service_info.logger().clone();
+                    B(B(A(self.pool.clone().reinject_dropped(&reorg).map(
+                        move |count| {
+                            if count > 0 {
+                                info!(
+                                    logger,
+                                    "fragments of the dropped blocks put back in the pool" ;
+                                    "count" => count,
+                                    "common_ancestor" => %reorg.common_ancestor,
+                                );
+                            }
+                        },
+                    ))))
+                }
+                TransactionMsg::GetPending(reply) => B(B(B(A(self
+                    .pool
+                    .pending()
+                    .map(move |fragments| reply.reply_ok(fragments)))))),
+                TransactionMsg::CountPending(reply) => B(B(B(B(self
+                    .pool
+                    .count_pending()
+                    .map(move |count| reply.reply_ok(count)))))),
+            };
+            processed.then(move |res| {
+                task_stats_counter.add_message_processed("fragment", variant, started.elapsed());
+                res
+            })
+        });
+        process_input
+            .select(garbage_collector)
+            .map(|_| ())
+            .map_err(|_| ())
     }
 
     fn start_pool_garbage_collector(&self, logger: Logger) -> impl Future<Item = (), Error = ()> {
@@ -95,3 +152,45 @@ impl Process {
             })
     }
 }
+
+/// drop the fragments coming from the network while the storage volume is
+/// almost full, the fragments of the REST API being refused before reaching
+/// the task. Returns whether the fragments were dropped.
+fn drop_while_low(
+    disk_guard: &DiskGuard,
+    stats_counter: &StatsCounter,
+    count: usize,
+    logger: &Logger,
+) -> bool {
+    if !disk_guard.is_low() {
+        return false;
+    }
+    debug!(
+        logger,
+        "the storage volume is almost full, dropping the fragments";
+        "count" => count,
+    );
+    for _ in 0..count {
+        stats_counter.add_fragment_rejected("disk_space");
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_fragments_are_dropped_while_the_space_is_low() {
+        let logger = Logger::root(slog::Discard, o!());
+        let disk_guard = DiskGuard::default();
+        let stats_counter = StatsCounter::default();
+        assert!(!drop_while_low(&disk_guard, &stats_counter, 3, &logger));
+        assert!(stats_counter.fragments_rejected().is_empty());
+
+        disk_guard.set_low(true);
+
+        assert!(drop_while_low(&disk_guard, &stats_counter, 3, &logger));
+        assert_eq!(stats_counter.fragments_rejected(), vec![("disk_space", 3)]);
+    }
+}
@@ -7,6 +7,9 @@ use crate::{
 use chain_core::property::Fragment as _;
 use jormungandr_lib::interfaces::FragmentStatus;
 
+/// the most fragments put in a block by the leader
+pub const MAX_PER_BLOCK: usize = 250;
+
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
     RequestSmallerFee,
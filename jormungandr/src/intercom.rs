@@ -1,18 +1,26 @@
-use crate::blockcfg::{Block, Fragment, FragmentId, Header, HeaderHash};
+use crate::blockcfg::{Block, Fragment, FragmentId, Header, HeaderHash, Leader};
 use crate::blockchain::Checkpoints;
+use crate::log::TraceId;
 use crate::network::p2p::comm::PeerStats;
 use crate::network::p2p::Id as NodeId;
+use crate::reload::ReloadReport;
 use crate::utils::async_msg::{self, MessageBox, MessageQueue};
+use chain_impl_mockchain::account::Identifier;
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
-use jormungandr_lib::interfaces::{FragmentOrigin, FragmentStatus};
+use jormungandr_lib::interfaces::{
+    AccountReward, EnclaveLeaderId, EpochRewards, FragmentOrigin, FragmentStatus,
+};
 use network_core::error as core_error;
 use slog::Logger;
 use std::{
     error,
     fmt::{self, Debug, Display},
     marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use tokio::timer::Delay;
 
 /// The error values passed via intercom messages.
 #[derive(Debug)]
@@ -72,6 +80,16 @@ impl Error {
         }
     }
 
+    pub fn deadline_exceeded<T>(cause: T) -> Self
+    where
+        T: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Error {
+            code: core_error::Code::DeadlineExceeded,
+            cause: cause.into(),
+        }
+    }
+
     pub fn not_found<T>(cause: T) -> Self
     where
         T: Into<Box<dyn error::Error + Send + Sync>>,
@@ -163,10 +181,38 @@ impl<T> ReplyHandle<T> {
 
 pub struct ReplyFuture<T, E> {
     receiver: oneshot::Receiver<Result<T, Error>>,
+    deadline: Option<(Delay, Duration)>,
     logger: Logger,
     _phantom_error: PhantomData<E>,
 }
 
+impl<T, E> ReplyFuture<T, E> {
+    /// Fails the request with a `DeadlineExceeded` error if the reply
+    /// does not arrive within `timeout`, so a stuck processing task does
+    /// not leave the requester waiting forever.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some((Delay::new(Instant::now() + timeout), timeout));
+        self
+    }
+}
+
+fn poll_deadline(deadline: &mut Option<(Delay, Duration)>, logger: &Logger) -> Result<(), Error> {
+    match deadline.as_mut() {
+        None => Ok(()),
+        Some((delay, timeout)) => match delay.poll() {
+            Ok(Async::NotReady) => Ok(()),
+            Ok(Async::Ready(())) => {
+                warn!(logger, "request timed out"; "timeout" => ?timeout);
+                Err(Error::deadline_exceeded(format!(
+                    "no reply within {:?}",
+                    timeout
+                )))
+            }
+            Err(e) => Err(Error::failed(e)),
+        },
+    }
+}
+
 impl<T, E> Future for ReplyFuture<T, E>
 where
     E: From<Error>,
@@ -176,7 +222,10 @@ where
 
     fn poll(&mut self) -> Poll<T, E> {
         match self.receiver.poll() {
-            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::NotReady) => {
+                poll_deadline(&mut self.deadline, &self.logger)?;
+                Ok(Async::NotReady)
+            }
             Ok(Async::Ready(Ok(item))) => {
                 debug!(self.logger, "request processed");
                 Ok(Async::Ready(item))
@@ -197,6 +246,7 @@ pub fn unary_reply<T, E>(logger: Logger) -> (ReplyHandle<T>, ReplyFuture<T, E>)
     let (sender, receiver) = oneshot::channel();
     let future = ReplyFuture {
         receiver,
+        deadline: None,
         logger,
         _phantom_error: PhantomData,
     };
@@ -240,10 +290,22 @@ impl<T> ReplyStreamHandle<T> {
 
 pub struct ReplyStream<T, E> {
     receiver: mpsc::UnboundedReceiver<Result<T, Error>>,
+    deadline: Option<(Delay, Duration)>,
     logger: Logger,
     _phantom_error: PhantomData<E>,
 }
 
+impl<T, E> ReplyStream<T, E> {
+    /// Fails the stream with a `DeadlineExceeded` error if an item, or the
+    /// end of the stream, does not arrive within `timeout` of the previous
+    /// one, so a long stream is not cut while the processing task is busy
+    /// sending it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some((Delay::new(Instant::now() + timeout), timeout));
+        self
+    }
+}
+
 impl<T, E> Stream for ReplyStream<T, E>
 where
     E: From<Error>,
@@ -254,9 +316,17 @@ where
     fn poll(&mut self) -> Poll<Option<T>, E> {
         match self.receiver.poll() {
             Err(()) => panic!("receiver returned an error"),
-            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::NotReady) => {
+                poll_deadline(&mut self.deadline, &self.logger)?;
+                Ok(Async::NotReady)
+            }
             Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
-            Ok(Async::Ready(Some(Ok(item)))) => Ok(Async::Ready(Some(item))),
+            Ok(Async::Ready(Some(Ok(item)))) => {
+                if let Some((delay, timeout)) = self.deadline.as_mut() {
+                    delay.reset(Instant::now() + *timeout);
+                }
+                Ok(Async::Ready(Some(item)))
+            }
             Ok(Async::Ready(Some(Err(e)))) => {
                 info!(
                     self.logger,
@@ -273,6 +343,7 @@ pub fn stream_reply<T, E>(logger: Logger) -> (ReplyStreamHandle<T>, ReplyStream<
     let (sender, receiver) = mpsc::unbounded();
     let stream = ReplyStream {
         receiver,
+        deadline: None,
         logger,
         _phantom_error: PhantomData,
     };
@@ -390,10 +461,63 @@ pub fn stream_request<T, R, E>(
 /// ...
 #[derive(Debug)]
 pub enum TransactionMsg {
-    SendTransaction(FragmentOrigin, Vec<Fragment>),
+    SendTransaction(TraceId, FragmentOrigin, Vec<Fragment>),
     RemoveTransactions(Vec<FragmentId>, FragmentStatus),
+    /// the fragments of the blocks dropped from the main chain which are
+    /// not in the applied blocks are put back in the pool
+    Reorg(Arc<ReorgEvent>),
+    /// the reloaded configuration changed the time to live of the fragments
+    /// and of their logs, applied to the ones registered from now on
+    SetTtl {
+        fragment_ttl: Duration,
+        log_ttl: Duration,
+    },
+    /// replied with the fragments of the pool, not in a block yet
+    GetPending(ReplyHandle<Vec<Fragment>>),
+    /// replied with the number of the fragments of the pool
+    CountPending(ReplyHandle<usize>),
+}
+
+impl TransactionMsg {
+    /// the name of the variant, labelling the message in the metrics
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            TransactionMsg::SendTransaction(..) => "send_transaction",
+            TransactionMsg::RemoveTransactions(..) => "remove_transactions",
+            TransactionMsg::Reorg(_) => "reorg",
+            TransactionMsg::SetTtl { .. } => "set_ttl",
+            TransactionMsg::GetPending(_) => "get_pending",
+            TransactionMsg::CountPending(_) => "count_pending",
+        }
+    }
 }
 
+/// The tip switched to another branch. The blocks are ordered from the
+/// common ancestor to the old and the new tip respectively.
+///
+/// Only the first blocks of the new branch are in `applied`, at most
+/// `REORG_APPLIED_BLOCKS`, the branch joined being as long as the chain
+/// pulled on a bootstrap.
+#[derive(Debug)]
+pub struct ReorgEvent {
+    pub common_ancestor: HeaderHash,
+    pub dropped: Vec<Block>,
+    pub applied: Vec<Block>,
+    pub new_tip: Header,
+}
+
+/// The chain selection preferred the branch of `candidate`, but switching
+/// to it would roll back more than `max_rollback_depth` blocks of the tip.
+#[derive(Debug, Clone)]
+pub struct DeepReorg {
+    pub tip: HeaderHash,
+    pub candidate: HeaderHash,
+    pub max_rollback_depth: u64,
+}
+
+/// the maximum number of the blocks of the new branch in a `ReorgEvent`
+pub const REORG_APPLIED_BLOCKS: u64 = 1024;
+
 /// Client messages, mainly requests from connected peers to our node.
 /// Fetching the block headers, the block, the tip
 pub enum ClientMsg {
@@ -447,17 +571,29 @@ impl Debug for ClientMsg {
 #[derive(Debug)]
 pub enum BlockMsg {
     /// A trusted Block has been received from the leadership task
-    LeadershipBlock(Block),
+    LeadershipBlock(TraceId, Block),
     /// A untrusted block Header has been received from the network task
     AnnouncedBlock(Header, NodeId),
     /// A stream of untrusted blocks has been received from the network task.
-    NetworkBlocks(RequestStreamHandle<Block, ()>),
+    NetworkBlocks(TraceId, RequestStreamHandle<Block, ()>),
     /// The stream of headers for missing chain blocks has been received
     /// from the network in response to a PullHeaders request or a Missing
     /// solicitation event.
     ChainHeaders(RequestStreamHandle<Header, ()>),
 }
 
+impl BlockMsg {
+    /// the name of the variant, labelling the message in the metrics
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            BlockMsg::LeadershipBlock(..) => "leadership_block",
+            BlockMsg::AnnouncedBlock(..) => "announced_block",
+            BlockMsg::NetworkBlocks(..) => "network_blocks",
+            BlockMsg::ChainHeaders(_) => "chain_headers",
+        }
+    }
+}
+
 /// Propagation requests for the network task.
 #[derive(Clone, Debug)]
 pub enum PropagateMsg {
@@ -469,7 +605,8 @@ pub enum PropagateMsg {
 #[derive(Debug)]
 pub enum NetworkMsg {
     Propagate(PropagateMsg),
-    GetBlocks(Vec<HeaderHash>),
+    /// replied with the blocks which could not be fetched from any peer
+    GetBlocks(Vec<HeaderHash>, ReplyHandle<Vec<HeaderHash>>),
     GetNextBlock(NodeId, HeaderHash),
     PullHeaders {
         node_id: NodeId,
@@ -477,12 +614,144 @@ pub enum NetworkMsg {
         to: HeaderHash,
     },
     PeerStats(ReplyHandle<Vec<(NodeId, PeerStats)>>),
+    /// the reloaded configuration changed the interval between two gossips
+    SetGossipInterval(Duration),
+    /// the private ID of the node was rotated, announce the new node ID
+    SetNodeId(NodeId),
+    /// bootstrap again from the trusted peers, replied once the bootstrap is
+    /// started
+    Bootstrap(ReplyHandle<()>),
+    /// the node is shutting down, save the topology before replying
+    Shutdown(ReplyHandle<()>),
+}
+
+impl NetworkMsg {
+    /// the name of the variant, labelling the message in the metrics
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            NetworkMsg::Propagate(PropagateMsg::Block(_)) => "propagate_block",
+            NetworkMsg::Propagate(PropagateMsg::Fragment(_)) => "propagate_fragment",
+            NetworkMsg::GetBlocks(..) => "get_blocks",
+            NetworkMsg::GetNextBlock(..) => "get_next_block",
+            NetworkMsg::PullHeaders { .. } => "pull_headers",
+            NetworkMsg::PeerStats(_) => "peer_stats",
+            NetworkMsg::SetGossipInterval(_) => "set_gossip_interval",
+            NetworkMsg::SetNodeId(_) => "set_node_id",
+            NetworkMsg::Bootstrap(_) => "bootstrap",
+            NetworkMsg::Shutdown(_) => "shutdown",
+        }
+    }
+}
+
+/// Messages to the notifier task.
+#[derive(Debug)]
+pub enum NotifierMsg {
+    /// a block was processed, the tip may have changed
+    TipUpdated,
+    /// the tip was kept on a branch the chain selection did not prefer
+    DeepReorgRefused(DeepReorg),
+}
+
+/// Request to reload the configuration file of the node, replied with the
+/// changes applied or the reason they were rejected.
+pub struct ReloadMsg(pub ReplyHandle<ReloadReport>);
+
+/// Messages to the leadership task, updating the leaders held by the
+/// enclave while the node is running.
+pub enum LeadershipMsg {
+    AddLeader(Leader, ReplyHandle<EnclaveLeaderId>),
+    RemoveLeader(EnclaveLeaderId, ReplyHandle<()>),
+}
+
+impl Debug for LeadershipMsg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the leader secret keys are never printed out
+        match self {
+            LeadershipMsg::AddLeader(_, _) => f
+                .debug_tuple("AddLeader")
+                .field(&format_args!("_"))
+                .field(&format_args!("_"))
+                .finish(),
+            LeadershipMsg::RemoveLeader(id, _) => f
+                .debug_tuple("RemoveLeader")
+                .field(id)
+                .field(&format_args!("_"))
+                .finish(),
+        }
+    }
+}
+
+/// Queries of the rewards history kept by the rewards task.
+pub enum RewardsMsg {
+    /// the rewards distributed at the start of the epoch
+    GetEpoch(u32, ReplyHandle<EpochRewards>),
+    /// the rewards received by the account, from the oldest epoch up to the
+    /// given one, the epoch of the tip
+    GetAccount(Identifier, u32, ReplyHandle<Vec<AccountReward>>),
 }
 
 /// Messages to the explorer task
 pub enum ExplorerMsg {
     NewBlock(Block),
+    Reorg(Arc<ReorgEvent>),
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use tokio::runtime::current_thread;
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    fn timeout() -> Duration {
+        Duration::from_millis(50)
+    }
+
+    #[test]
+    fn unanswered_request_times_out() {
+        let (_handle, future) = unary_reply::<(), Error>(logger());
+        match current_thread::block_on_all(future.with_timeout(timeout())) {
+            Err(e) => assert_eq!(e.code(), core_error::Code::DeadlineExceeded),
+            Ok(()) => panic!("unanswered request succeeded"),
+        }
+    }
+
+    #[test]
+    fn answered_request_does_not_time_out() {
+        let (handle, future) = unary_reply::<u32, Error>(logger());
+        handle.reply_ok(7);
+        assert_eq!(
+            current_thread::block_on_all(future.with_timeout(timeout())).unwrap(),
+            7
+        );
+    }
+
+    #[test]
+    fn stalled_stream_times_out_after_its_items() {
+        let (mut handle, stream) = stream_reply::<u32, Error>(logger());
+        handle.send(1).unwrap();
+        handle.send(2).unwrap();
+        let mut items = Vec::new();
+        let res = current_thread::block_on_all(stream.with_timeout(timeout()).for_each(|item| {
+            items.push(item);
+            Ok(())
+        }));
+        assert_eq!(items, vec![1, 2]);
+        match res {
+            Err(e) => assert_eq!(e.code(), core_error::Code::DeadlineExceeded),
+            Ok(()) => panic!("stalled stream ended"),
+        }
+        handle.close();
+    }
+
+    #[test]
+    fn closed_stream_ends_before_its_deadline() {
+        let (mut handle, stream) = stream_reply::<u32, Error>(logger());
+        handle.send(1).unwrap();
+        handle.close();
+        let items = current_thread::block_on_all(stream.with_timeout(timeout()).collect());
+        assert_eq!(items.unwrap(), vec![1]);
+    }
+}
@@ -0,0 +1,514 @@
+//! block signatures by a hardware security module, through PKCS#11
+//!
+//! The BFT leaders' Ed25519 keys are kept in the token and used with the
+//! `CKM_EDDSA` mechanism of PKCS#11 3.0. The node only knows the public key
+//! of each leader, configured with the label of its private key object in
+//! the token, and evaluates their schedules from the BFT leaders of the
+//! epoch.
+//!
+//! The genesis praos leaders are not supported: their KES signing key
+//! evolves every period, which a token cannot do on a key it holds, and the
+//! VRF of the schedule is not a mechanism of PKCS#11.
+//!
+//! The PKCS#11 calls are blocking and a token may take tens of milliseconds
+//! to sign, so they run on a thread of their own with one session logged in
+//! for the lifetime of the node: the runtime is never blocked and no login
+//! is paid per block. The requests still queued past their timeout are
+//! dropped without reaching the token, so a slow signature misses its own
+//! slot and not the next ones.
+
+use crate::{
+    blockcfg::{BlockDate, LeaderOutput, Leadership},
+    leadership::enclave::LeaderEvent,
+};
+use chain_crypto::{Ed25519, PublicKey, Signature, Verification};
+use chain_impl_mockchain::leadership::{bft, LeadershipConsensus};
+use futures::sync::oneshot;
+use jormungandr_lib::{crypto::passphrase::Passphrase, interfaces::EnclaveLeaderId as LeaderId};
+use pkcs11::{
+    errors::Error as Pkcs11Error,
+    types::{
+        CKA_CLASS, CKA_LABEL, CKF_SERIAL_SESSION, CKO_PRIVATE_KEY, CKR_DEVICE_REMOVED,
+        CKR_KEY_HANDLE_INVALID, CKR_OBJECT_HANDLE_INVALID, CKR_SESSION_CLOSED,
+        CKR_SESSION_HANDLE_INVALID, CKR_TOKEN_NOT_PRESENT, CKR_USER_NOT_LOGGED_IN, CKU_USER,
+        CK_ATTRIBUTE, CK_MECHANISM, CK_MECHANISM_TYPE, CK_OBJECT_HANDLE, CK_RV, CK_SESSION_HANDLE,
+        CK_SLOT_ID,
+    },
+    Ctx,
+};
+use slog::Logger;
+use std::{
+    path::PathBuf,
+    ptr,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{prelude::*, timer::Timeout};
+
+/// `CKM_EDDSA`, only defined since PKCS#11 3.0
+const CKM_EDDSA: CK_MECHANISM_TYPE = 0x0000_1057;
+
+/// the data signed at startup to check the keys and the latency of the token
+const PROBE: &[u8] = b"jormungandr hsm probe";
+
+/// the errors of a signature telling that the session or its login is
+/// lost, e.g. after the token was reset: the signature is tried again in a
+/// new session
+const SESSION_LOST: &[CK_RV] = &[
+    CKR_SESSION_HANDLE_INVALID,
+    CKR_SESSION_CLOSED,
+    CKR_USER_NOT_LOGGED_IN,
+    CKR_KEY_HANDLE_INVALID,
+    CKR_OBJECT_HANDLE_INVALID,
+    CKR_DEVICE_REMOVED,
+    CKR_TOKEN_NOT_PRESENT,
+];
+
+#[derive(Debug, Clone, Error)]
+pub enum HsmError {
+    #[error("PKCS#11 error: {reason}")]
+    Pkcs11 { reason: String },
+    #[error("the PIN of the token is not valid UTF-8")]
+    InvalidPin,
+    #[error("invalid public key for the key {label}")]
+    InvalidPublicKey { label: String },
+    #[error("no private key labelled {label} in the token")]
+    KeyNotFound { label: String },
+    #[error("the key {label} of the token does not match its public key")]
+    KeyMismatch { label: String },
+    #[error("the token did not sign within {timeout:?}")]
+    Timeout { timeout: Duration },
+    #[error("the signing thread of the token stopped")]
+    Stopped,
+}
+
+/// the settings of the token, see `leadership.hsm` in the node configuration
+pub struct HsmSettings {
+    pub module: PathBuf,
+    pub slot: u64,
+    pub pin: Option<Passphrase>,
+    /// the label of the private key and the bech32 public key of each leader
+    pub keys: Vec<(String, String)>,
+    pub timeout: Duration,
+    pub latency_warning: Duration,
+}
+
+struct Key {
+    id: LeaderId,
+    label: String,
+    leader: bft::LeaderId,
+}
+
+struct Request {
+    key: usize,
+    data: Vec<u8>,
+    deadline: Instant,
+    reply: oneshot::Sender<Result<Vec<u8>, HsmError>>,
+}
+
+#[derive(Clone)]
+pub struct HsmEnclave {
+    keys: Arc<Vec<Key>>,
+    requests: mpsc::Sender<Request>,
+    timeout: Duration,
+}
+
+impl HsmEnclave {
+    /// open a session with the token and check that every key signs as its
+    /// public key verifies, before the node starts
+    pub fn open(settings: HsmSettings, logger: Logger) -> Result<Self, HsmError> {
+        let mut public_keys = Vec::new();
+        let mut keys = Vec::new();
+        let mut id = LeaderId::new();
+        for (label, public_key) in &settings.keys {
+            let public_key = PublicKey::<Ed25519>::from_bech32_str(public_key).map_err(|_| {
+                HsmError::InvalidPublicKey {
+                    label: label.clone(),
+                }
+            })?;
+            id = id.next();
+            keys.push(Key {
+                id,
+                label: label.clone(),
+                leader: public_key.clone().into(),
+            });
+            public_keys.push(public_key);
+        }
+
+        let (requests, queue) = mpsc::channel();
+        let (opened, open_result) = mpsc::channel();
+        let labels: Vec<_> = keys.iter().map(|key| key.label.clone()).collect();
+        let latency_warning = settings.latency_warning;
+        thread::Builder::new()
+            .name("hsm".to_owned())
+            .spawn(move || {
+                let mut token = match Token::open(&settings, labels) {
+                    Ok(token) => token,
+                    Err(e) => return opened.send(Err(e)).unwrap_or(()),
+                };
+                if let Err(e) = token.probe(&public_keys, &logger) {
+                    return opened.send(Err(e)).unwrap_or(());
+                }
+                if opened.send(Ok(())).is_err() {
+                    return;
+                }
+                token.serve(queue, latency_warning, &logger)
+            })
+            .map_err(|e| HsmError::Pkcs11 {
+                reason: e.to_string(),
+            })?;
+        open_result.recv().map_err(|_| HsmError::Stopped)??;
+
+        Ok(HsmEnclave {
+            keys: Arc::new(keys),
+            requests,
+            timeout: settings.timeout,
+        })
+    }
+
+    /// the slots the leaders of the token are elected for, from the BFT
+    /// leaders of the epoch
+    pub fn query_schedules(
+        &self,
+        leadership: &Leadership,
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> Vec<LeaderEvent> {
+        let bft = match leadership.consensus() {
+            LeadershipConsensus::Bft(bft) => bft,
+            _ => return Vec::new(),
+        };
+        let mut output = Vec::new();
+        for slot_idx in slot_start..slot_start + nb_slots {
+            let date = leadership.date_at_slot(slot_idx);
+            if let Ok(leader) = bft.get_leader_at(date) {
+                output.extend(events_of(&self.keys, date, &leader));
+            }
+        }
+        output
+    }
+
+    pub fn has_leader(&self, id: LeaderId) -> bool {
+        self.keys.iter().any(|key| key.id == id)
+    }
+
+    /// sign the header data with the key of the given leader, failing if
+    /// the token does not answer within the timeout
+    pub fn sign_bft(
+        &self,
+        id: LeaderId,
+        data: &[u8],
+    ) -> impl Future<Item = Signature<(), Ed25519>, Error = HsmError> {
+        let timeout = self.timeout;
+        let deadline = Instant::now() + timeout;
+        let (reply, signature) = oneshot::channel();
+        let key = self
+            .keys
+            .iter()
+            .position(|key| key.id == id)
+            .expect("signing with a leader not in the token");
+        let sent = self.requests.send(Request {
+            key,
+            data: data.to_vec(),
+            deadline,
+            reply,
+        });
+        if sent.is_err() {
+            return future::Either::A(future::err(HsmError::Stopped));
+        }
+
+        let signature = signature
+            .map_err(|_canceled| HsmError::Stopped)
+            .and_then(|result| result)
+            .and_then(|bytes| Signature::from_binary(&bytes).map_err(pkcs11_error));
+        future::Either::B(
+            Timeout::new_at(signature, deadline)
+                .map_err(move |e| e.into_inner().unwrap_or(HsmError::Timeout { timeout })),
+        )
+    }
+}
+
+/// the session with the token, owned by the signing thread
+struct Token {
+    ctx: Ctx,
+    slot: CK_SLOT_ID,
+    pin: Option<Passphrase>,
+    session: CK_SESSION_HANDLE,
+    labels: Vec<String>,
+    handles: Vec<CK_OBJECT_HANDLE>,
+}
+
+impl Token {
+    fn open(settings: &HsmSettings, labels: Vec<String>) -> Result<Self, HsmError> {
+        let mut token = Token {
+            ctx: Ctx::new_and_initialize(&settings.module).map_err(pkcs11_error)?,
+            slot: settings.slot as CK_SLOT_ID,
+            pin: settings.pin.clone(),
+            session: 0,
+            labels,
+            handles: Vec::new(),
+        };
+        token.login()?;
+        Ok(token)
+    }
+
+    /// open a new session and find the keys in it, the object handles are
+    /// only valid within the session
+    fn login(&mut self) -> Result<(), HsmError> {
+        let pin = match &self.pin {
+            Some(pin) => {
+                Some(std::str::from_utf8(pin.as_bytes()).map_err(|_| HsmError::InvalidPin)?)
+            }
+            None => None,
+        };
+        let session = self
+            .ctx
+            .open_session(self.slot, CKF_SERIAL_SESSION, None, None)
+            .map_err(pkcs11_error)?;
+        self.session = session;
+        self.ctx
+            .login(session, CKU_USER, pin)
+            .map_err(pkcs11_error)?;
+
+        let mut handles = Vec::with_capacity(self.labels.len());
+        for label in &self.labels {
+            let template = vec![
+                CK_ATTRIBUTE::new(CKA_CLASS).with_ck_ulong(&CKO_PRIVATE_KEY),
+                CK_ATTRIBUTE::new(CKA_LABEL).with_string(label),
+            ];
+            self.ctx
+                .find_objects_init(session, &template)
+                .map_err(pkcs11_error)?;
+            let found = self.ctx.find_objects(session, 1).map_err(pkcs11_error);
+            self.ctx.find_objects_final(session).map_err(pkcs11_error)?;
+            match found?.first() {
+                Some(handle) => handles.push(*handle),
+                None => {
+                    return Err(HsmError::KeyNotFound {
+                        label: label.clone(),
+                    })
+                }
+            }
+        }
+        self.handles = handles;
+        Ok(())
+    }
+
+    fn sign(&self, key: usize, data: &[u8]) -> Result<Vec<u8>, Pkcs11Error> {
+        let mechanism = CK_MECHANISM {
+            mechanism: CKM_EDDSA,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        self.ctx
+            .sign_init(self.session, &mechanism, self.handles[key])?;
+        self.ctx.sign(self.session, data)
+    }
+
+    /// sign with a session opened again if the session or its login is
+    /// lost, the other errors fail the signature
+    fn sign_or_login(&mut self, key: usize, data: &[u8]) -> Result<Vec<u8>, HsmError> {
+        match self.sign(key, data) {
+            Err(ref e) if session_lost(e) => {
+                let _ = self.ctx.close_session(self.session);
+                self.login()?;
+                self.sign(key, data).map_err(pkcs11_error)
+            }
+            result => result.map_err(pkcs11_error),
+        }
+    }
+
+    fn probe(
+        &mut self,
+        public_keys: &[PublicKey<Ed25519>],
+        logger: &Logger,
+    ) -> Result<(), HsmError> {
+        for (key, public_key) in public_keys.iter().enumerate() {
+            let started = Instant::now();
+            let signature = self.sign(key, PROBE).map_err(pkcs11_error)?;
+            let latency = started.elapsed();
+            let verified = Signature::<&[u8], Ed25519>::from_binary(&signature)
+                .map(|signature| signature.verify(public_key, &PROBE));
+            if let Ok(Verification::Success) = verified {
+                info!(
+                    logger,
+                    "leader key found in the token";
+                    "label" => &self.labels[key],
+                    "latency" => ?latency,
+                );
+            } else {
+                return Err(HsmError::KeyMismatch {
+                    label: self.labels[key].clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn serve(
+        &mut self,
+        queue: mpsc::Receiver<Request>,
+        latency_warning: Duration,
+        logger: &Logger,
+    ) {
+        serve_requests(queue, |key, data| {
+            let started = Instant::now();
+            let result = self.sign_or_login(key, data);
+            let latency = started.elapsed();
+            if latency > latency_warning {
+                warn!(
+                    logger,
+                    "the token is slow to sign";
+                    "label" => &self.labels[key],
+                    "latency" => ?latency,
+                );
+            }
+            result
+        });
+        let _ = self.ctx.close_session(self.session);
+    }
+}
+
+/// the events of the keys of the leader of the slot at `date`
+fn events_of<'a>(
+    keys: &'a [Key],
+    date: BlockDate,
+    leader: &'a bft::LeaderId,
+) -> impl Iterator<Item = LeaderEvent> + 'a {
+    keys.iter()
+        .filter(move |key| key.leader == *leader)
+        .map(move |key| LeaderEvent {
+            id: key.id,
+            date,
+            output: LeaderOutput::Bft(key.leader.clone()),
+        })
+}
+
+/// sign the requests of the queue until it is closed, dropping the ones
+/// past their deadline without signing them
+fn serve_requests<F>(queue: mpsc::Receiver<Request>, mut sign: F)
+where
+    F: FnMut(usize, &[u8]) -> Result<Vec<u8>, HsmError>,
+{
+    for request in queue {
+        if Instant::now() >= request.deadline {
+            // the slot is missed already, the caller timed out
+            continue;
+        }
+        let result = sign(request.key, &request.data);
+        let _ = request.reply.send(result);
+    }
+}
+
+fn session_lost(e: &Pkcs11Error) -> bool {
+    match e {
+        Pkcs11Error::Pkcs11(rv) => SESSION_LOST.contains(rv),
+        _ => false,
+    }
+}
+
+fn pkcs11_error<E: ToString>(e: E) -> HsmError {
+    HsmError::Pkcs11 {
+        reason: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_crypto::SecretKey;
+    use rand::{rngs::StdRng, SeedableRng as _};
+
+    fn keys() -> Vec<Key> {
+        let mut id = LeaderId::new();
+        (1..=2)
+            .map(|seed| {
+                id = id.next();
+                let public_key =
+                    SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([seed; 32])).to_public();
+                Key {
+                    id,
+                    label: format!("leader{}", seed),
+                    leader: public_key.into(),
+                }
+            })
+            .collect()
+    }
+
+    fn date() -> BlockDate {
+        BlockDate {
+            epoch: 1,
+            slot_id: 2,
+        }
+    }
+
+    #[test]
+    fn leader_of_the_slot_is_scheduled() {
+        let keys = keys();
+        let events: Vec<_> = events_of(&keys, date(), &keys[1].leader).collect();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, keys[1].id);
+        assert_eq!(events[0].date, date());
+    }
+
+    #[test]
+    fn other_leaders_are_not_scheduled() {
+        let keys = keys();
+        let leader = SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([3; 32]))
+            .to_public()
+            .into();
+
+        assert_eq!(events_of(&keys, date(), &leader).count(), 0);
+    }
+
+    fn request(
+        key: usize,
+        deadline: Instant,
+    ) -> (Request, oneshot::Receiver<Result<Vec<u8>, HsmError>>) {
+        let (reply, signature) = oneshot::channel();
+        let request = Request {
+            key,
+            data: vec![key as u8],
+            deadline,
+            reply,
+        };
+        (request, signature)
+    }
+
+    #[test]
+    fn requests_past_their_deadline_do_not_reach_the_token() {
+        let (requests, queue) = mpsc::channel();
+        let (expired, expired_signature) = request(0, Instant::now());
+        let (pending, pending_signature) = request(1, Instant::now() + Duration::from_secs(60));
+        requests.send(expired).unwrap();
+        requests.send(pending).unwrap();
+        drop(requests);
+
+        let mut signed = Vec::new();
+        serve_requests(queue, |key, data| {
+            signed.push(key);
+            Ok(data.to_vec())
+        });
+
+        assert_eq!(signed, vec![1]);
+        assert!(expired_signature.wait().is_err());
+        assert_eq!(pending_signature.wait().unwrap().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn only_the_session_errors_log_in_again() {
+        assert!(session_lost(&Pkcs11Error::Pkcs11(
+            CKR_SESSION_HANDLE_INVALID
+        )));
+        assert!(session_lost(&Pkcs11Error::Pkcs11(CKR_USER_NOT_LOGGED_IN)));
+        assert!(!session_lost(&Pkcs11Error::Pkcs11(
+            pkcs11::types::CKR_DATA_LEN_RANGE
+        )));
+        assert!(!session_lost(&Pkcs11Error::Pkcs11(
+            pkcs11::types::CKR_MECHANISM_INVALID
+        )));
+    }
+}
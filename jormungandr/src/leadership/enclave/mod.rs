@@ -0,0 +1,311 @@
+pub use crate::secure::enclave::LeaderEvent;
+use crate::{
+    blockcfg::{
+        HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
+        HeaderSetConsensusSignature, Leader, Leadership,
+    },
+    intercom::{self, LeadershipMsg},
+    secure::enclave::Enclave as SecureEnclave,
+    utils::task::{Input, TokioServiceInfo},
+};
+use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::{
+    prelude::{future::Either, *},
+    sync::lock::Lock,
+};
+
+mod hsm;
+mod remote;
+
+pub use self::hsm::{HsmEnclave, HsmError, HsmSettings};
+pub use self::remote::{RemoteEnclave, RemoteEnclaveError};
+
+#[derive(Debug, Clone, Error)]
+pub enum EnclaveError {
+    #[error("This leader {id} is not in the enclave")]
+    NotInEnclave { id: LeaderId },
+    #[error("The leader has neither a BFT nor a Genesis Praos key")]
+    NoLeaderKeys,
+    #[error("The leaders are managed by the remote signer")]
+    ManagedRemotely,
+    #[error("The leaders are managed by the hardware security module")]
+    ManagedByHsm,
+    #[error("Remote enclave failure: {source}")]
+    Remote {
+        #[from]
+        source: RemoteEnclaveError,
+    },
+    #[error("Hardware security module failure: {source}")]
+    Hsm {
+        #[from]
+        source: HsmError,
+    },
+}
+
+/// represent the client side of an enclave. From there we will query the
+/// actual enclave about schedules and signing blocks
+///
+#[derive(Clone)]
+pub struct Enclave {
+    backend: Backend,
+}
+
+#[derive(Clone)]
+enum Backend {
+    /// the secrets are held in the node's memory
+    Local(Lock<SecureEnclave>),
+    /// the secrets are held by a separate signer process
+    Remote(RemoteEnclave),
+    /// the BFT secrets are held by a hardware security module
+    Hsm(HsmEnclave),
+}
+
+impl Enclave {
+    /// create a new enclave structure holding the secrets in the node process
+    pub fn new(secure_enclave: SecureEnclave) -> Self {
+        Enclave {
+            backend: Backend::Local(Lock::new(secure_enclave)),
+        }
+    }
+
+    /// create a new enclave structure delegating the schedules and the
+    /// signatures to a separate signer process
+    pub fn remote(remote: RemoteEnclave) -> Self {
+        Enclave {
+            backend: Backend::Remote(remote),
+        }
+    }
+
+    /// create a new enclave structure signing the blocks with the BFT keys
+    /// of a hardware security module
+    pub fn hsm(hsm: HsmEnclave) -> Self {
+        Enclave {
+            backend: Backend::Hsm(hsm),
+        }
+    }
+
+    /// ask the enclave to attempt computing some leadership schedule for the
+    /// given settings
+    ///
+    /// TODO: for now we are utilizing the Leadership object fully but on the long
+    ///       run this might be limited to only the required data.
+    pub fn query_schedules(
+        &self,
+        leadership: Arc<Leadership>,
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> impl Future<Item = Vec<LeaderEvent>, Error = EnclaveError> {
+        match &self.backend {
+            Backend::Local(inner) => {
+                let mut inner = inner.clone();
+                Either::A(
+                    future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+                        guard.leadership_evaluate(&leadership, slot_start, nb_slots)
+                    }),
+                )
+            }
+            Backend::Remote(remote) => {
+                let epoch = leadership.date_at_slot(slot_start).epoch;
+                Either::B(Either::A(
+                    remote
+                        .query_schedules(epoch, slot_start, nb_slots)
+                        .map_err(EnclaveError::from),
+                ))
+            }
+            Backend::Hsm(hsm) => Either::B(Either::B(future::ok(hsm.query_schedules(
+                &leadership,
+                slot_start,
+                nb_slots,
+            )))),
+        }
+    }
+
+    /// ask the leader associated to the `LeaderEvent` to finalize the given
+    /// block by providing the proof.
+    ///
+    /// TODO: for now we are querying the whole with the block builder but on the long
+    ///       run we will only need the block signing data.
+    pub fn query_header_bft_finalize(
+        &self,
+        block_builder: HeaderBftBuilder<HeaderSetConsensusSignature>,
+        id: LeaderId,
+    ) -> impl Future<Item = HeaderBft, Error = EnclaveError> {
+        match &self.backend {
+            Backend::Local(inner) => {
+                let mut inner = inner.clone();
+                Either::A(
+                    future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+                        if let Some(block) = guard.create_header_bft(block_builder, id) {
+                            future::ok(block)
+                        } else {
+                            future::err(EnclaveError::NotInEnclave { id })
+                        }
+                    }),
+                )
+            }
+            Backend::Remote(remote) => {
+                let data = block_builder.get_authenticated_data().to_vec();
+                Either::B(Either::A(
+                    remote
+                        .sign_bft(id, &data)
+                        .map(move |signature| {
+                            block_builder.set_signature(signature.coerce().into())
+                        })
+                        .map_err(EnclaveError::from),
+                ))
+            }
+            Backend::Hsm(hsm) if hsm.has_leader(id) => {
+                let data = block_builder.get_authenticated_data().to_vec();
+                Either::B(Either::B(Either::A(
+                    hsm.sign_bft(id, &data)
+                        .map(move |signature| {
+                            block_builder.set_signature(signature.coerce().into())
+                        })
+                        .map_err(EnclaveError::from),
+                )))
+            }
+            Backend::Hsm(_) => Either::B(Either::B(Either::B(future::err(
+                EnclaveError::NotInEnclave { id },
+            )))),
+        }
+    }
+
+    /// ask the leader associated to the `LeaderEvent` to finalize the given
+    /// block by providing the proof.
+    ///
+    /// TODO: for now we are querying the whole with the block builder but on the long
+    ///       run we will only need the block signing data.
+    pub fn query_header_genesis_praos_finalize(
+        &self,
+        block_builder: HeaderGenesisPraosBuilder<HeaderSetConsensusSignature>,
+        id: LeaderId,
+    ) -> impl Future<Item = HeaderGenesisPraos, Error = EnclaveError> {
+        match &self.backend {
+            Backend::Local(inner) => {
+                let mut inner = inner.clone();
+                Either::A(
+                    future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+                        if let Some(block) = guard.create_header_genesis_praos(block_builder, id) {
+                            future::ok(block)
+                        } else {
+                            future::err(EnclaveError::NotInEnclave { id })
+                        }
+                    }),
+                )
+            }
+            Backend::Remote(remote) => {
+                let data = block_builder.get_authenticated_data().to_vec();
+                Either::B(Either::A(
+                    remote
+                        .sign_genesis_praos(id, &data)
+                        .map(move |signature| {
+                            block_builder.set_signature(signature.coerce().into())
+                        })
+                        .map_err(EnclaveError::from),
+                ))
+            }
+            // the token only holds BFT keys
+            Backend::Hsm(_) => Either::B(Either::B(future::err(EnclaveError::NotInEnclave { id }))),
+        }
+    }
+
+    /// register a new leader in the enclave. The leader is only visible
+    /// to the schedule queries once it has been fully inserted.
+    pub fn add_leader(&self, leader: Leader) -> impl Future<Item = LeaderId, Error = EnclaveError> {
+        let mut inner = match &self.backend {
+            Backend::Local(inner) => inner.clone(),
+            Backend::Remote(_) => return Either::B(future::err(EnclaveError::ManagedRemotely)),
+            Backend::Hsm(_) => return Either::B(future::err(EnclaveError::ManagedByHsm)),
+        };
+
+        Either::A(
+            future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+                if leader.bft_leader.is_none() && leader.genesis_leader.is_none() {
+                    future::err(EnclaveError::NoLeaderKeys)
+                } else {
+                    future::ok(guard.add_leader(leader))
+                }
+            }),
+        )
+    }
+
+    /// retire a leader from the enclave. Schedules already computed for this
+    /// leader will fail to be finalized once it has been removed.
+    pub fn remove_leader(&self, id: LeaderId) -> impl Future<Item = (), Error = EnclaveError> {
+        let mut inner = match &self.backend {
+            Backend::Local(inner) => inner.clone(),
+            Backend::Remote(_) => return Either::B(future::err(EnclaveError::ManagedRemotely)),
+            Backend::Hsm(_) => return Either::B(future::err(EnclaveError::ManagedByHsm)),
+        };
+
+        Either::A(
+            future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |guard| {
+                if guard.remove_leader(id) {
+                    future::ok(())
+                } else {
+                    future::err(EnclaveError::NotInEnclave { id })
+                }
+            }),
+        )
+    }
+
+    /// process the leader updates sent to the leadership task. The messages
+    /// are processed one at a time so the enclave is never observed with a
+    /// partially applied update.
+    pub fn handle_input(
+        &self,
+        info: &TokioServiceInfo,
+        input: Input<LeadershipMsg>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let msg = match input {
+            Input::Shutdown => return Either::A(future::ok(())),
+            Input::Input(msg) => msg,
+        };
+
+        let future = match msg {
+            LeadershipMsg::AddLeader(leader, handle) => {
+                Either::A(self.add_leader(leader).then(move |res| {
+                    match res {
+                        Ok(id) => {
+                            info!(logger, "leader added to the enclave"; "id" => %id);
+                            handle.reply_ok(id);
+                        }
+                        Err(e) => handle.reply_error(e.into()),
+                    }
+                    Ok(())
+                }))
+            }
+            LeadershipMsg::RemoveLeader(id, handle) => {
+                Either::B(self.remove_leader(id).then(move |res| {
+                    match res {
+                        Ok(()) => {
+                            info!(logger, "leader removed from the enclave"; "id" => %id);
+                            handle.reply_ok(());
+                        }
+                        Err(e) => handle.reply_error(e.into()),
+                    }
+                    Ok(())
+                }))
+            }
+        };
+        Either::B(future)
+    }
+}
+
+impl From<EnclaveError> for intercom::Error {
+    fn from(error: EnclaveError) -> Self {
+        match error {
+            EnclaveError::NotInEnclave { .. } => intercom::Error::not_found(error),
+            EnclaveError::NoLeaderKeys => intercom::Error::invalid_argument(error),
+            EnclaveError::ManagedRemotely | EnclaveError::ManagedByHsm => {
+                intercom::Error::failed_precondition(error)
+            }
+            EnclaveError::Remote { .. } | EnclaveError::Hsm { .. } => {
+                intercom::Error::failed(error)
+            }
+        }
+    }
+}
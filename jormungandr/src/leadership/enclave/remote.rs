@@ -0,0 +1,442 @@
+//! client of an enclave running as a separate signer process
+//!
+//! The node and the signer talk over a unix socket, so the remote signer is
+//! only available on unix platforms. For every query the
+//! node opens a new connection, writes one JSON encoded request followed by
+//! a new line and reads back one JSON encoded response, also terminated by
+//! a new line. Opening a connection per request keeps the node oblivious of
+//! the signer restarting. A response longer than `MAX_RESPONSE_LEN` is
+//! refused.
+//!
+//! The signer holds the leaders' secret keys and follows the blockchain on
+//! its own to evaluate the leadership schedules: the node only provides the
+//! epoch and slots it wants the schedule of, and the data to sign.
+
+use crate::{
+    blockcfg::{BlockDate, LeaderOutput},
+    leadership::enclave::LeaderEvent,
+};
+use chain_crypto::{vrf::ProvenOutputSeed, Ed25519, Signature, SumEd25519_12};
+use jormungandr_lib::{
+    crypto::{hash::Hash, key::Identifier},
+    interfaces::EnclaveLeaderId as LeaderId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{prelude::*, timer::Timeout};
+
+/// the longest response read from the signer, new line included
+const MAX_RESPONSE_LEN: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Error)]
+pub enum RemoteEnclaveError {
+    #[error("the signer at {path} is unreachable: {reason}")]
+    Unreachable { path: String, reason: String },
+    #[error("the signer did not answer within {timeout:?}")]
+    Timeout { timeout: Duration },
+    #[error("invalid response from the signer: {reason}")]
+    InvalidResponse { reason: String },
+    #[error("the signer rejected the request: {reason}")]
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Schedules {
+        epoch: u32,
+        slot_start: u32,
+        nb_slots: u32,
+    },
+    Sign {
+        leader_id: LeaderId,
+        kind: SignatureKind,
+        /// hexadecimal encoding of the header data to sign
+        data: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SignatureKind {
+    Bft,
+    GenesisPraos,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Schedules(Vec<Event>),
+    /// hexadecimal encoding of the signature
+    Signature(String),
+    Error(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    leader_id: LeaderId,
+    slot_id: u32,
+    output: Output,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Output {
+    Bft { leader_id: String },
+    GenesisPraos { node_id: String, vrf_proof: String },
+}
+
+#[derive(Clone)]
+pub struct RemoteEnclave {
+    socket_path: Arc<PathBuf>,
+    timeout: Duration,
+}
+
+impl RemoteEnclave {
+    pub fn new(socket_path: PathBuf, timeout: Duration) -> Self {
+        RemoteEnclave {
+            socket_path: Arc::new(socket_path),
+            timeout,
+        }
+    }
+
+    pub fn query_schedules(
+        &self,
+        epoch: u32,
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> impl Future<Item = Vec<LeaderEvent>, Error = RemoteEnclaveError> {
+        let request = Request::Schedules {
+            epoch,
+            slot_start,
+            nb_slots,
+        };
+        self.query(request)
+            .and_then(move |response| match response {
+                Response::Schedules(events) => events
+                    .into_iter()
+                    .map(|event| event.into_leader_event(epoch))
+                    .collect(),
+                other => Err(unexpected_response(other)),
+            })
+    }
+
+    pub fn sign_bft(
+        &self,
+        leader_id: LeaderId,
+        data: &[u8],
+    ) -> impl Future<Item = Signature<(), Ed25519>, Error = RemoteEnclaveError> {
+        self.sign(leader_id, SignatureKind::Bft, data)
+            .and_then(|signature| parse_signature(&signature))
+    }
+
+    pub fn sign_genesis_praos(
+        &self,
+        leader_id: LeaderId,
+        data: &[u8],
+    ) -> impl Future<Item = Signature<(), SumEd25519_12>, Error = RemoteEnclaveError> {
+        self.sign(leader_id, SignatureKind::GenesisPraos, data)
+            .and_then(|signature| parse_signature(&signature))
+    }
+
+    fn sign(
+        &self,
+        leader_id: LeaderId,
+        kind: SignatureKind,
+        data: &[u8],
+    ) -> impl Future<Item = String, Error = RemoteEnclaveError> {
+        let request = Request::Sign {
+            leader_id,
+            kind,
+            data: hex::encode(data),
+        };
+        self.query(request).and_then(|response| match response {
+            Response::Signature(signature) => Ok(signature),
+            other => Err(unexpected_response(other)),
+        })
+    }
+
+    fn query(&self, request: Request) -> impl Future<Item = Response, Error = RemoteEnclaveError> {
+        let path = self.socket_path.clone();
+        let timeout = self.timeout;
+        let mut line = serde_json::to_vec(&request).expect("enclave requests are serializable");
+        line.push(b'\n');
+
+        let exchange_error = move |e: io::Error| match e.kind() {
+            io::ErrorKind::InvalidData => invalid_response(e),
+            _ => RemoteEnclaveError::Unreachable {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            },
+        };
+        let exchange = exchange_line(self.socket_path.as_ref(), line)
+            .map_err(exchange_error)
+            .and_then(|line| {
+                serde_json::from_slice(&line).map_err(|e| RemoteEnclaveError::InvalidResponse {
+                    reason: e.to_string(),
+                })
+            })
+            .and_then(|response| match response {
+                Response::Error(reason) => Err(RemoteEnclaveError::Rejected { reason }),
+                response => Ok(response),
+            });
+
+        Timeout::new_at(exchange, Instant::now() + timeout).map_err(move |e| {
+            e.into_inner()
+                .unwrap_or(RemoteEnclaveError::Timeout { timeout })
+        })
+    }
+}
+
+impl Event {
+    fn into_leader_event(self, epoch: u32) -> Result<LeaderEvent, RemoteEnclaveError> {
+        let output = match self.output {
+            Output::Bft { leader_id } => {
+                let public_key = Identifier::<Ed25519>::from_hex(&leader_id)
+                    .map_err(invalid_response)?
+                    .into_public_key();
+                LeaderOutput::Bft(public_key.into())
+            }
+            Output::GenesisPraos { node_id, vrf_proof } => {
+                let node_id = Hash::from_hex(&node_id).map_err(invalid_response)?;
+                let vrf_proof = hex::decode(&vrf_proof)
+                    .ok()
+                    .and_then(|bytes| ProvenOutputSeed::from_bytes_unverified(&bytes))
+                    .ok_or_else(|| invalid_response("malformed VRF proof"))?;
+                LeaderOutput::GenesisPraos(node_id.into_digest_of(), vrf_proof)
+            }
+        };
+        Ok(LeaderEvent {
+            id: self.leader_id,
+            date: BlockDate {
+                epoch,
+                slot_id: self.slot_id,
+            },
+            output,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn exchange_line(path: &Path, line: Vec<u8>) -> impl Future<Item = Vec<u8>, Error = io::Error> {
+    use std::io::{BufReader, Read as _};
+    use tokio::net::UnixStream;
+
+    UnixStream::connect(path)
+        .and_then(move |stream| tokio::io::write_all(stream, line))
+        .and_then(|(stream, _)| {
+            let reader = BufReader::new(stream.take(MAX_RESPONSE_LEN));
+            tokio::io::read_until(reader, b'\n', Vec::new())
+        })
+        .and_then(|(_, line)| {
+            if line.len() as u64 >= MAX_RESPONSE_LEN && line.last() != Some(&b'\n') {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("the response exceeds {} bytes", MAX_RESPONSE_LEN),
+                ))
+            } else {
+                Ok(line)
+            }
+        })
+}
+
+#[cfg(not(unix))]
+fn exchange_line(_path: &Path, _line: Vec<u8>) -> impl Future<Item = Vec<u8>, Error = io::Error> {
+    future::err(io::Error::new(
+        io::ErrorKind::Other,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+fn parse_signature<A: chain_crypto::VerificationAlgorithm>(
+    signature: &str,
+) -> Result<Signature<(), A>, RemoteEnclaveError> {
+    signature.parse().map_err(invalid_response)
+}
+
+fn unexpected_response(response: Response) -> RemoteEnclaveError {
+    invalid_response(format!("unexpected response {:?}", response))
+}
+
+fn invalid_response<E: ToString>(reason: E) -> RemoteEnclaveError {
+    RemoteEnclaveError::InvalidResponse {
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_request_carries_hex_data() {
+        let request = Request::Sign {
+            leader_id: serde_json::from_str("3").unwrap(),
+            kind: SignatureKind::GenesisPraos,
+            data: hex::encode(&[0x01, 0xab]),
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "sign": { "leader_id": 3, "kind": "genesis_praos", "data": "01ab" }
+            })
+        );
+    }
+
+    #[test]
+    fn responses_are_parsed() {
+        match serde_json::from_str(r#"{"signature":"abcd"}"#).unwrap() {
+            Response::Signature(signature) => assert_eq!(signature, "abcd"),
+            other => panic!("unexpected response {:?}", other),
+        }
+        match serde_json::from_str(r#"{"error":"unknown leader"}"#).unwrap() {
+            Response::Error(reason) => assert_eq!(reason, "unknown leader"),
+            other => panic!("unexpected response {:?}", other),
+        }
+        match serde_json::from_str(r#"{"schedules":[]}"#).unwrap() {
+            Response::Schedules(events) => assert!(events.is_empty()),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_vrf_proof_is_an_invalid_response() {
+        for vrf_proof in &["0", "zz", "00"] {
+            let event = Event {
+                leader_id: serde_json::from_str("0").unwrap(),
+                slot_id: 1,
+                output: Output::GenesisPraos {
+                    node_id: "0".repeat(64),
+                    vrf_proof: vrf_proof.to_string(),
+                },
+            };
+            match event.into_leader_event(0) {
+                Err(RemoteEnclaveError::InvalidResponse { .. }) => {}
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("{} accepted as a VRF proof", vrf_proof),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn serve_once(name: &str, response: &str) -> PathBuf {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-remote-enclave-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let response = response.to_owned();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut request)
+                .unwrap();
+            if !response.is_empty() {
+                // the node may stop reading before the end of the response
+                let _ = stream.write_all(response.as_bytes());
+            } else {
+                // keep the connection open and never answer
+                std::thread::sleep(Duration::from_secs(5));
+            }
+        });
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejected_request_is_reported() {
+        let path = serve_once("rejected", "{\"error\":\"unknown leader\"}\n");
+        let enclave = RemoteEnclave::new(path.clone(), Duration::from_secs(5));
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result = runtime.block_on(enclave.query_schedules(1, 0, 10));
+        let _ = std::fs::remove_file(&path);
+        match result {
+            Err(RemoteEnclaveError::Rejected { reason }) => assert_eq!(reason, "unknown leader"),
+            other => panic!("unexpected result {:?}", other.map(|events| events.len())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn schedules_of_the_signer_are_read() {
+        let response = format!(
+            "{{\"schedules\":[{{\"leader_id\":1,\"slot_id\":5,\"output\":{{\"bft\":{{\"leader_id\":\"{}\"}}}}}}]}}\n",
+            "11".repeat(32)
+        );
+        let path = serve_once("schedules", &response);
+        let enclave = RemoteEnclave::new(path.clone(), Duration::from_secs(5));
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result = runtime.block_on(enclave.query_schedules(2, 0, 10));
+        let _ = std::fs::remove_file(&path);
+        let events = result.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].date,
+            BlockDate {
+                epoch: 2,
+                slot_id: 5
+            }
+        );
+        match events[0].output {
+            LeaderOutput::Bft(_) => {}
+            _ => panic!("the BFT output of the signer was not read"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_signer_times_out() {
+        let path = serve_once("silent", "");
+        let timeout = Duration::from_millis(100);
+        let enclave = RemoteEnclave::new(path.clone(), timeout);
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result = runtime.block_on(enclave.sign_bft(serde_json::from_str("0").unwrap(), &[]));
+        let _ = std::fs::remove_file(&path);
+        match result {
+            Err(RemoteEnclaveError::Timeout { timeout: t }) => assert_eq!(t, timeout),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn oversized_response_is_invalid() {
+        // a signer never sending the new line
+        let response = "x".repeat(MAX_RESPONSE_LEN as usize + 1);
+        let path = serve_once("oversized", &response);
+        let enclave = RemoteEnclave::new(path.clone(), Duration::from_secs(5));
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result = runtime.block_on(enclave.sign_bft(serde_json::from_str("0").unwrap(), &[]));
+        let _ = std::fs::remove_file(&path);
+        match result {
+            Err(RemoteEnclaveError::InvalidResponse { reason }) => {
+                assert!(reason.contains("exceeds"), "unexpected reason {}", reason)
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn missing_signer_is_unreachable() {
+        let path = std::env::temp_dir().join("jormungandr-remote-enclave-missing.sock");
+        let enclave = RemoteEnclave::new(path, Duration::from_secs(1));
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        match runtime.block_on(enclave.query_schedules(0, 0, 1)) {
+            Err(RemoteEnclaveError::Unreachable { .. }) => {}
+            other => panic!("unexpected result {:?}", other.map(|events| events.len())),
+        }
+    }
+}
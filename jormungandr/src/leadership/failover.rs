@@ -0,0 +1,204 @@
+//! coordination of redundant nodes running the same leaders
+//!
+//! Pool operators may run a standby node with the same keys as the primary
+//! one. To not double-sign, a standby node gives the primary node some time
+//! into each slot before creating a block: if the primary's block shows up
+//! in the meantime the standby abstains. The blocks of the primary node are
+//! its heartbeat: once none was seen for the promotion timeout, the standby
+//! node promotes itself and stops waiting. It steps back as soon as a block
+//! of the primary node shows up again.
+//!
+//! A promoted node creates its blocks without waiting, so the block of the
+//! primary node for the same slot is not its tip: the blocks received from
+//! the network for the slots of the shared leaders are the primary's, the
+//! blocks of the node itself never coming back from the network.
+
+use crate::{
+    blockcfg::{BlockDate, Header, LeaderOutput},
+    blockchain::ReceivedHeaders,
+};
+use chain_impl_mockchain::{block::Proof, certificate::PoolId};
+use slog::Logger;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// the slots of the shared leaders kept to recognise the blocks of the
+/// primary node received late
+const SLOTS_CAPACITY: usize = 64;
+
+/// the creator of the block of a slot of the shared leaders
+#[derive(PartialEq)]
+enum Producer {
+    /// there is only one BFT leader per slot
+    Bft,
+    Pool(PoolId),
+}
+
+pub struct Standby {
+    slot_grace: Duration,
+    promotion_timeout: Duration,
+    last_primary_block: Instant,
+    promoted: bool,
+    received: ReceivedHeaders,
+    slots: VecDeque<(BlockDate, Producer)>,
+}
+
+impl Standby {
+    pub fn new(
+        slot_grace: Duration,
+        promotion_timeout: Duration,
+        received: ReceivedHeaders,
+    ) -> Self {
+        Standby {
+            slot_grace,
+            promotion_timeout,
+            last_primary_block: Instant::now(),
+            promoted: false,
+            received,
+            slots: VecDeque::new(),
+        }
+    }
+
+    /// one of the shared leaders is elected for the slot of `date`. The
+    /// blocks received since the last event are checked for the primary's
+    /// first, to step back before the slot if the primary node is back
+    pub fn leader_event(&mut self, date: BlockDate, output: &LeaderOutput, logger: &Logger) {
+        let producer = match output {
+            LeaderOutput::Bft(_) => Producer::Bft,
+            LeaderOutput::GenesisPraos(node_id, _) => Producer::Pool(node_id.clone()),
+            _ => return,
+        };
+        if self.slots.len() == SLOTS_CAPACITY {
+            self.slots.pop_front();
+        }
+        self.slots.push_back((date, producer));
+
+        let primary_produced = self.received.take().iter().any(|header| {
+            self.slots.iter().any(|(date, producer)| {
+                header.block_date() == *date && produced_by(header, producer)
+            })
+        });
+        if primary_produced {
+            self.primary_produced(logger);
+        }
+    }
+
+    /// how long to wait for the primary node's block before creating ours
+    pub fn grace(&self) -> Duration {
+        if self.promoted {
+            Duration::from_secs(0)
+        } else {
+            self.slot_grace
+        }
+    }
+
+    /// the primary node created the block of one of the shared leaders
+    pub fn primary_produced(&mut self, logger: &Logger) {
+        self.primary_produced_at(Instant::now(), logger)
+    }
+
+    fn primary_produced_at(&mut self, now: Instant, logger: &Logger) {
+        if self.promoted {
+            warn!(
+                logger,
+                "the primary node is creating blocks again, stepping back as standby"
+            );
+            self.promoted = false;
+        }
+        self.last_primary_block = now;
+    }
+
+    /// the primary node did not create the block in time, we are creating it
+    pub fn took_over(&mut self, logger: &Logger) {
+        self.took_over_at(Instant::now(), logger)
+    }
+
+    fn took_over_at(&mut self, now: Instant, logger: &Logger) {
+        if !self.promoted && now.duration_since(self.last_primary_block) >= self.promotion_timeout {
+            warn!(
+                logger,
+                "no block from the primary node for too long, promoting this node" ;
+                "promotion_timeout" => ?self.promotion_timeout,
+            );
+            self.promoted = true;
+        }
+    }
+}
+
+/// checks if the block of the given header was created by the leader
+/// elected with `output`
+pub fn created_by(header: &Header, output: &LeaderOutput) -> bool {
+    match output {
+        LeaderOutput::Bft(_) => produced_by(header, &Producer::Bft),
+        LeaderOutput::GenesisPraos(node_id, _) => {
+            produced_by(header, &Producer::Pool(node_id.clone()))
+        }
+        _ => false,
+    }
+}
+
+fn produced_by(header: &Header, producer: &Producer) -> bool {
+    match (header.proof(), producer) {
+        (Proof::Bft(_), Producer::Bft) => true,
+        (Proof::GenesisPraos(_), Producer::Pool(pool_id)) => {
+            header.get_stakepool_id() == Some(pool_id)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(2);
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn standby(now: Instant) -> Standby {
+        let mut standby = Standby::new(GRACE, TIMEOUT, ReceivedHeaders::default());
+        standby.last_primary_block = now;
+        standby
+    }
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    #[test]
+    fn standby_waits_for_the_primary_within_the_timeout() {
+        let start = Instant::now();
+        let mut standby = standby(start);
+
+        standby.took_over_at(start + TIMEOUT / 2, &logger());
+        assert!(!standby.promoted);
+        assert_eq!(standby.grace(), GRACE);
+
+        // each block of the primary node restarts the timeout
+        standby.primary_produced_at(start + TIMEOUT / 2, &logger());
+        standby.took_over_at(start + TIMEOUT, &logger());
+        assert!(!standby.promoted);
+    }
+
+    #[test]
+    fn promoted_standby_steps_back_when_the_primary_is_back() {
+        let start = Instant::now();
+        let mut standby = standby(start);
+
+        standby.took_over_at(start + TIMEOUT, &logger());
+        assert!(standby.promoted);
+        assert_eq!(standby.grace(), Duration::from_secs(0));
+
+        let primary_back = start + TIMEOUT + Duration::from_secs(10);
+        standby.primary_produced_at(primary_back, &logger());
+        assert!(!standby.promoted);
+        assert_eq!(standby.grace(), GRACE);
+
+        // promoted again only once the primary is gone for the timeout
+        standby.took_over_at(primary_back + TIMEOUT / 2, &logger());
+        assert!(!standby.promoted);
+        standby.took_over_at(primary_back + TIMEOUT, &logger());
+        assert!(standby.promoted);
+    }
+}
@@ -58,9 +58,13 @@
 //!
 
 mod enclave;
+mod failover;
 mod logs;
 mod process;
 
-pub use self::enclave::{Enclave, EnclaveError, LeaderEvent};
+pub use self::enclave::{
+    Enclave, EnclaveError, HsmEnclave, HsmError, HsmSettings, LeaderEvent, RemoteEnclave,
+};
+pub use self::failover::Standby;
 pub use self::logs::{LeadershipLogHandle, Logs};
 pub use self::process::Module;
@@ -1,20 +1,25 @@
 use crate::{
     blockcfg::{
-        Block, BlockVersion, Contents, HeaderBuilderNew, HeaderContentEvalContext, LeaderOutput,
-        Leadership, Ledger, LedgerParameters,
+        Block, BlockDate, BlockVersion, Contents, HeaderBuilderNew, HeaderContentEvalContext,
+        HeaderHash, LeaderOutput, Leadership, Ledger, LedgerParameters,
     },
-    blockchain::{new_epoch_leadership_from, Ref, Tip},
+    blockchain::{new_epoch_leadership_from, verify_header_leadership, Ref, Tip},
+    clock_drift::ClockGuard,
     fragment,
     intercom::BlockMsg,
     leadership::{
-        enclave::{Enclave, EnclaveError, LeaderEvent},
+        enclave::{Enclave, LeaderEvent},
+        failover::{self, Standby},
         LeadershipLogHandle, Logs,
     },
+    lifecycle::Lifecycle,
+    log::TraceId,
+    stats_counter::{MissedSlotReason, StatsCounter},
     utils::{async_msg::MessageBox, task::TokioServiceInfo},
 };
 use chain_time::{
     era::{EpochPosition, EpochSlotOffset},
-    Epoch, Slot,
+    Epoch, Slot, TimeEra,
 };
 use jormungandr_lib::{
     interfaces::{LeadershipLog, LeadershipLogStatus},
@@ -32,6 +37,9 @@ use tokio::{
     timer::{self, Delay, Interval, Timeout},
 };
 
+/// delay before querying the enclave again when it failed to provide a schedule
+const SCHEDULE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 #[derive(Error, Debug)]
 pub enum LeadershipError {
     #[error("Error while awaiting for next leader event to process")]
@@ -48,12 +56,6 @@ pub enum LeadershipError {
         //       of the time frame to report appropriate error
     },
 
-    #[error("Cannot query enclave for leader schedules")]
-    CannotScheduleWithEnclave {
-        #[source]
-        source: EnclaveError,
-    },
-
     #[error("fragment selection failed")]
     FragmentSelectionFailed,
 
@@ -83,6 +85,20 @@ pub struct Module {
     pool: fragment::Pool,
     enclave: Enclave,
     block_message: MessageBox<BlockMsg>,
+    stats_counter: StatsCounter,
+    /// set if this node is the standby of another node running the same leaders
+    standby: Option<Standby>,
+    /// when to query the enclave again after it failed to provide a schedule
+    schedule_retry: Option<Instant>,
+    /// no block is created until the node is synced
+    lifecycle: Lifecycle,
+    /// no block is created while the clock drifted from the time servers
+    clock_guard: ClockGuard,
+    /// no block is created while the tip is more slots behind the slot
+    max_slots_behind: Option<u32>,
+    /// the tip and the slot of the first block not created since because of
+    /// the stale tip
+    stale_since: Option<(HeaderHash, u64)>,
 }
 
 impl Module {
@@ -94,6 +110,11 @@ impl Module {
         pool: fragment::Pool,
         enclave: Enclave,
         block_message: MessageBox<BlockMsg>,
+        stats_counter: StatsCounter,
+        standby: Option<Standby>,
+        lifecycle: Lifecycle,
+        clock_guard: ClockGuard,
+        max_slots_behind: Option<u32>,
     ) -> impl Future<Item = Self, Error = LeadershipError> {
         let mut logs_to_purge = logs.clone();
         let garbage_collection_interval = garbage_collection_interval;
@@ -121,6 +142,13 @@ impl Module {
             pool,
             enclave,
             block_message,
+            stats_counter,
+            standby,
+            schedule_retry: None,
+            lifecycle,
+            clock_guard,
+            max_slots_behind,
+            stale_since: None,
         })
     }
 
@@ -217,6 +245,36 @@ impl Module {
         self.slot_time(epoch, slot)
     }
 
+    /// the number of slots from the date of the tip to the date of the
+    /// event, if more than the configured maximum and the block is not
+    /// created
+    fn stale_tip(&mut self, event: &LeaderEvent, logger: &Logger) -> Option<u64> {
+        let max_slots_behind = u64::from(self.max_slots_behind?);
+        let era = self.tip_ref.epoch_leadership_schedule().era();
+        let tip_slot = slot_of(era, self.tip_ref.block_date());
+        let event_slot = slot_of(era, event.date);
+        let slots_behind = event_slot.saturating_sub(tip_slot);
+        match abstain(
+            &mut self.stale_since,
+            self.tip_ref.hash(),
+            event_slot,
+            slots_behind,
+            max_slots_behind,
+        ) {
+            Abstain::No => None,
+            Abstain::Yes => Some(slots_behind),
+            Abstain::Stalled => {
+                warn!(
+                    logger,
+                    "the tip did not move for more than the maximum slots behind, taking the network as stalled and creating the block";
+                    "tip_date" => %self.tip_ref.block_date(),
+                    "slots_behind" => slots_behind,
+                );
+                None
+            }
+        }
+    }
+
     // gives the slot time of the following slot, the slot that follow directly
     // the given event, being exactly the strict upper bound
     //
@@ -271,11 +329,20 @@ impl Module {
                 // there is no other schedule to have for the current epoch. Better
                 // wait for the next epoch
 
+                let next_epoch = self.next_epoch_instant()?;
+                if let Some(retry) = self.schedule_retry {
+                    debug!(
+                        self.service_info.logger(),
+                        "no item scheduled, waiting to query the enclave again"
+                    );
+                    return Ok(retry.min(next_epoch));
+                }
+
                 debug!(
                     self.service_info.logger(),
                     "no item scheduled, waiting for next epoch"
                 );
-                self.next_epoch_instant()
+                Ok(next_epoch)
             }
             Some(entry) => {
                 let logger = self.service_info.logger().new(o!(
@@ -312,7 +379,10 @@ impl Module {
             .and_then(move |module| end_log.mark_finished().map(|()| module))
     }
 
-    fn action_run_entry(self, entry: Entry) -> impl Future<Item = Self, Error = LeadershipError> {
+    fn action_run_entry(
+        mut self,
+        entry: Entry,
+    ) -> impl Future<Item = Self, Error = LeadershipError> {
         let now = SystemTime::now();
         let event_start = self.event_slot_time(&entry.event);
         let event_end = self.event_following_slot_time(&entry.event);
@@ -334,8 +404,48 @@ impl Module {
             let tell_user_about_failure = entry.log.set_status(LeadershipLogStatus::Rejected {
                 reason: "Missed the deadline to compute the schedule".to_owned(),
             });
+            self.stats_counter
+                .add_slot_missed(MissedSlotReason::TooLate);
+
+            Either::B(Either::A(tell_user_about_failure.map(|()| self)))
+        } else if !self.lifecycle.is_ready() || self.clock_guard.is_paused() {
+            let (reason, missed) = if !self.lifecycle.is_ready() {
+                warn!(logger, "the node is not synced yet, not creating the block");
+                ("The node was still syncing", MissedSlotReason::Syncing)
+            } else {
+                warn!(
+                    logger,
+                    "the clock of the node drifted, not creating the block"
+                );
+                (
+                    "The clock of the node drifted from the time servers",
+                    MissedSlotReason::ClockDrift,
+                )
+            };
+
+            let tell_user_about_failure = entry.log.set_status(LeadershipLogStatus::Rejected {
+                reason: reason.to_owned(),
+            });
+            self.stats_counter.add_slot_missed(missed);
 
-            Either::B(tell_user_about_failure.map(|()| self))
+            Either::B(Either::B(Either::A(tell_user_about_failure.map(|()| self))))
+        } else if let Some(slots_behind) = self.stale_tip(&entry.event, &logger) {
+            // after a downtime, the blocks created on the tip before the
+            // node catches up would only be discarded by the network
+            warn!(
+                logger,
+                "the tip is too far behind the slot, not creating the block";
+                "tip_date" => %self.tip_ref.block_date(),
+                "slots_behind" => slots_behind,
+            );
+
+            let tell_user_about_failure = entry.log.set_status(LeadershipLogStatus::Rejected {
+                reason: format!("The tip was {} slots behind the slot", slots_behind),
+            });
+            self.stats_counter
+                .add_slot_missed(MissedSlotReason::StaleTip);
+
+            Either::B(Either::B(Either::B(tell_user_about_failure.map(|()| self))))
         } else {
             let right_time = future::result(entry.instant(&self));
 
@@ -351,7 +461,7 @@ impl Module {
                         Delay::new(right_time)
                             .map_err(|source| LeadershipError::AwaitError { source })
                             .and_then(move |()| {
-                                self.action_run_entry_in_bound(entry, logger, event_end)
+                                self.action_run_entry_standby(entry, logger, event_end)
                             }),
                     )
                 } else {
@@ -359,12 +469,71 @@ impl Module {
                     // time, if we cannot compute the _right_time_ it means the time
                     // is just starting now to be correct. So it's okay to start
                     // running it now still
-                    Either::B(self.action_run_entry_in_bound(entry, logger, event_end))
+                    Either::B(self.action_run_entry_standby(entry, logger, event_end))
                 }
             }))
         }
     }
 
+    /// if this node is a standby, give the primary node some time to create
+    /// the block before creating it ourselves.
+    fn action_run_entry_standby(
+        mut self,
+        entry: Entry,
+        logger: Logger,
+        event_end: SystemTime,
+    ) -> impl Future<Item = Self, Error = LeadershipError> {
+        let grace = match self.standby.as_mut() {
+            None => None,
+            Some(standby) => {
+                standby.leader_event(entry.event.date, &entry.event.output, &logger);
+                Some(standby.grace())
+            }
+        };
+        let grace = match grace {
+            None => return Either::A(self.action_run_entry_in_bound(entry, logger, event_end)),
+            Some(grace) => grace,
+        };
+        // keep at least half of what remains of the slot to create the block
+        let remaining_time: Duration = event_end
+            .duration_since(SystemTime::now())
+            .map(Into::into)
+            .unwrap_or_default();
+        let grace = grace.min(remaining_time / 2);
+
+        let tip = self.tip.clone();
+        let abstained_log = entry.log.clone();
+
+        Either::B(
+            Delay::new(Instant::now() + grace)
+                .map_err(|source| LeadershipError::AwaitError { source })
+                .and_then(move |()| tip.get_ref())
+                .and_then(move |tip_ref| {
+                    let mut module = self;
+                    module.tip_ref = tip_ref;
+                    let primary_produced = module.tip_ref.block_date() == entry.event.date
+                        && failover::created_by(module.tip_ref.header(), &entry.event.output);
+                    let standby = module.standby.as_mut().expect("node is a standby");
+
+                    if primary_produced {
+                        info!(logger, "the primary node created the block, abstaining");
+                        standby.primary_produced(&logger);
+                        Either::A(
+                            abstained_log
+                                .set_status(LeadershipLogStatus::Rejected {
+                                    reason: "Abstained, the primary node created the block"
+                                        .to_owned(),
+                                })
+                                .map(|()| module),
+                        )
+                    } else {
+                        standby.took_over(&logger);
+                        Either::B(module.action_run_entry_in_bound(entry, logger, event_end))
+                    }
+                }),
+        )
+    }
+
     fn action_run_entry_in_bound(
         self,
         entry: Entry,
@@ -392,9 +561,11 @@ impl Module {
         info!(logger, "Leader event started");
 
         let timed_out_log = logger.clone();
+        let stats_counter = self.stats_counter.clone();
         Timeout::new_at(self.action_run_entry_build_block(entry, logger), deadline)
             .or_else(move |timeout_error| {
                 error!(timed_out_log, "Eek... took too long to process the event..." ; "reason" => %timeout_error);
+                stats_counter.add_slot_missed(MissedSlotReason::Timeout);
                 event_logs.set_status(LeadershipLogStatus::Rejected {
                     reason: "Failed to compute the schedule within time boundaries".to_owned()
                 })
@@ -413,15 +584,36 @@ impl Module {
         let enclave = self.enclave.clone();
         let sender = self.block_message.clone();
         let pool = self.pool.clone();
+        let stats_counter = self.stats_counter.clone();
 
-        let (parent_id, chain_length, ledger, ledger_parameters) = if self.tip_ref.block_date()
+        let (parent_id, chain_length, leadership, ledger, ledger_parameters) = if self
+            .tip_ref
+            .block_date()
             < event.date
         {
+            // the block of a new epoch applies to the ledger with the
+            // rewards of the previous epoch distributed
+            let (leadership, ledger_parameters, ledger, _, _, _) = match new_epoch_leadership_from(
+                event.date.epoch,
+                Arc::clone(&self.tip_ref),
+            ) {
+                Ok(epoch) => epoch,
+                Err(error) => {
+                    error!(logger, "Cannot distribute the rewards of the previous epoch" ; "reason" => error.to_string());
+                    return Either::B(event_logs.set_status(LeadershipLogStatus::Rejected {
+                        reason: format!(
+                            "Cannot distribute the rewards of the previous epoch: {}",
+                            error
+                        ),
+                    }));
+                }
+            };
             (
                 self.tip_ref.hash(),
                 self.tip_ref.chain_length().increase(),
-                Arc::clone(self.tip_ref.ledger()),
-                Arc::clone(self.tip_ref.epoch_ledger_parameters()),
+                leadership,
+                ledger,
+                ledger_parameters,
             )
         } else {
             // it appears we are either competing against another stake pool for the same
@@ -438,6 +630,9 @@ impl Module {
                 "It appears the node is running a bit behind schedule, system time might be off?"
             );
 
+            self.stats_counter
+                .add_slot_missed(MissedSlotReason::TipBehind);
+
             let tell_user_about_failure = event_logs.set_status(
                     LeadershipLogStatus::Rejected {
                         reason: "Not computing this schedule because of invalid state against the network blockchain".to_owned()
@@ -456,6 +651,7 @@ impl Module {
         let preparation = prepare_block(pool, eval_context, &ledger, ledger_parameters);
 
         let event_logs_error = event_logs.clone();
+        let signing_stats = stats_counter.clone();
         let signing = preparation.and_then(move |contents| {
             let ver = match event.output {
                 LeaderOutput::None => BlockVersion::Genesis,
@@ -488,6 +684,7 @@ impl Module {
                                 })
                             })
                             .or_else(move |e| {
+                                signing_stats.add_slot_missed(MissedSlotReason::EnclaveError);
                                 event_logs_error
                                     .set_status(LeadershipLogStatus::Rejected {
                                         reason: format!("Cannot sign the block: {}", e),
@@ -511,6 +708,7 @@ impl Module {
                                 })
                             })
                             .or_else(move |e| {
+                                signing_stats.add_slot_missed(MissedSlotReason::EnclaveError);
                                 event_logs_error
                                     .set_status(LeadershipLogStatus::Rejected {
                                         reason: format!("Cannot sign the block: {}", e),
@@ -522,16 +720,40 @@ impl Module {
             }
         });
 
+        // the signature of a remote signer or of a token is not trusted: the
+        // block is verified against the leadership of its epoch, as the
+        // peers will, before it is sent
+        let event_logs_invalid = event_logs.clone();
+        let invalid_stats = stats_counter.clone();
+        let verified = signing.and_then(move |block| match block {
+            Some(block) => match verify_header_leadership(&leadership, &block.header) {
+                Ok(()) => Either::A(future::ok(Some(block))),
+                Err(e) => {
+                    invalid_stats.add_slot_missed(MissedSlotReason::EnclaveError);
+                    Either::B(
+                        event_logs_invalid
+                            .set_status(LeadershipLogStatus::Rejected {
+                                reason: format!("The signed block is invalid: {}", e),
+                            })
+                            .map(|()| None),
+                    )
+                }
+            },
+            None => Either::A(future::ok(None)),
+        });
+
         let event_logs_success = event_logs.clone();
-        let send_block = signing.and_then(|block| {
+        let send_block = verified.and_then(|block| {
             if let Some(block) = block {
                 let id = block.header.hash();
+                let epoch = block.header.block_date().epoch;
                 let chain_length: u32 = block.header.chain_length().into();
                 Either::A(
                     sender
-                        .send(BlockMsg::LeadershipBlock(block))
+                        .send(BlockMsg::LeadershipBlock(TraceId::new(), block))
                         .map_err(|_send_error| LeadershipError::CannotSendLeadershipBlock)
                         .and_then(move |_| {
+                            stats_counter.add_block_produced(epoch, id);
                             event_logs_success.set_status(LeadershipLogStatus::Block {
                                 block: id.into(),
                                 chain_length,
@@ -558,8 +780,16 @@ impl Module {
         ));
 
         if epoch_tip < current_slot_position.epoch {
-            let (leadership, _, _, _) =
-                new_epoch_leadership_from(current_slot_position.epoch.0, Arc::clone(&self.tip_ref));
+            let leadership = match new_epoch_leadership_from(
+                current_slot_position.epoch.0,
+                Arc::clone(&self.tip_ref),
+            ) {
+                Ok((leadership, _, _, _, _, _)) => leadership,
+                Err(error) => {
+                    error!(logger, "Cannot distribute the rewards of the previous epoch" ; "reason" => error.to_string());
+                    return Either::B(future::ok(self));
+                }
+            };
 
             let slot_start = current_slot_position.slot.0 + 1;
             let nb_slots = leadership.era().slots_per_epoch() - slot_start;
@@ -601,40 +831,58 @@ impl Module {
     }
 
     fn action_run_schedule(
-        self,
+        mut self,
         leadership: Arc<Leadership>,
         slot_start: u32,
         nb_slots: u32,
     ) -> impl Future<Item = Self, Error = LeadershipError> {
+        self.schedule_retry = None;
         self.enclave
             .query_schedules(leadership, slot_start, nb_slots)
-            .map_err(|e| LeadershipError::CannotScheduleWithEnclave { source: e })
-            .and_then(move |schedules| {
-                stream::iter_ok::<_, LeadershipError>(schedules).fold(
-                    self,
-                    |mut module, schedule| {
-                        let epoch = Epoch(schedule.date.epoch);
-                        let slot = EpochSlotOffset(schedule.date.slot_id);
-                        let scheduled_at_time = module.slot_time(epoch, slot);
-                        let log = LeadershipLog::new(
-                            schedule.id,
-                            schedule.date.into(),
-                            scheduled_at_time,
-                        );
-
-                        module
-                            .logs
-                            .insert(log)
-                            .map_err(|()| LeadershipError::CannotUpdateLogs)
-                            .map(move |log| {
-                                module.schedule.push(Entry {
-                                    event: schedule,
-                                    log,
-                                });
-                                module
-                            })
-                    },
-                )
+            .then(move |result| match result {
+                Ok(schedules) => {
+                    if let Some(first) = schedules.first() {
+                        self.stats_counter
+                            .add_slots_scheduled(first.date.epoch, schedules.len() as u64);
+                    }
+                    Either::A(stream::iter_ok::<_, LeadershipError>(schedules).fold(
+                        self,
+                        |mut module, schedule| {
+                            let epoch = Epoch(schedule.date.epoch);
+                            let slot = EpochSlotOffset(schedule.date.slot_id);
+                            let scheduled_at_time = module.slot_time(epoch, slot);
+                            let log = LeadershipLog::new(
+                                schedule.id,
+                                schedule.date.into(),
+                                scheduled_at_time,
+                            );
+
+                            module
+                                .logs
+                                .insert(log)
+                                .map_err(|()| LeadershipError::CannotUpdateLogs)
+                                .map(move |log| {
+                                    module.schedule.push(Entry {
+                                        event: schedule,
+                                        log,
+                                    });
+                                    module
+                                })
+                        },
+                    ))
+                }
+                Err(source) => {
+                    // the enclave may be a separate signer process that is
+                    // temporarily down: do not give up on the epoch
+                    error!(
+                        self.service_info.logger(),
+                        "cannot query enclave for leader schedules, retrying later" ;
+                        "reason" => %source,
+                        "retry_in" => ?SCHEDULE_RETRY_DELAY,
+                    );
+                    self.schedule_retry = Some(Instant::now() + SCHEDULE_RETRY_DELAY);
+                    Either::B(future::ok(self))
+                }
             })
     }
 }
@@ -667,9 +915,9 @@ fn prepare_block(
     ledger: &Arc<Ledger>,
     epoch_parameters: Arc<LedgerParameters>,
 ) -> impl Future<Item = Contents, Error = LeadershipError> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
+    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst, MAX_PER_BLOCK};
 
-    let selection_algorithm = OldestFirst::new(250 /* TODO!! */);
+    let selection_algorithm = OldestFirst::new(MAX_PER_BLOCK);
     fragment_pool
         .select(
             ledger.as_ref().clone(),
@@ -684,3 +932,123 @@ fn prepare_block(
 fn too_late(now: SystemTime, event_end: SystemTime) -> bool {
     event_end <= now
 }
+
+/// the slot of the date since the genesis block, the era of the date
+/// counting the slots of the eras before it
+fn slot_of(era: &TimeEra, date: BlockDate) -> u64 {
+    let slot = era.from_era_to_slot(EpochPosition {
+        epoch: Epoch(date.epoch),
+        slot: EpochSlotOffset(date.slot_id),
+    });
+    u64::from(slot)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Abstain {
+    No,
+    Yes,
+    /// the tip is stale but did not move for longer than the leaders
+    /// abstain: all the leaders abstaining, the network is stalled
+    Stalled,
+}
+
+/// whether the block of the slot is not created, the tip being
+/// `slots_behind` the slot. The leaders abstain on the same tip for at most
+/// `max_slots_behind` slots: when all the leaders of the network abstain,
+/// the tip moves only once one of them creates a block again.
+fn abstain(
+    stale_since: &mut Option<(HeaderHash, u64)>,
+    tip: HeaderHash,
+    slot: u64,
+    slots_behind: u64,
+    max_slots_behind: u64,
+) -> Abstain {
+    if slots_behind <= max_slots_behind {
+        *stale_since = None;
+        return Abstain::No;
+    }
+    match stale_since {
+        Some((stale_tip, since)) if *stale_tip == tip => {
+            if slot.saturating_sub(*since) > max_slots_behind {
+                Abstain::Stalled
+            } else {
+                Abstain::Yes
+            }
+        }
+        _ => {
+            *stale_since = Some((tip, slot));
+            Abstain::Yes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::crypto::hash::Hash;
+
+    fn hash(seed: u8) -> HeaderHash {
+        Hash::from([seed; 32]).into_hash()
+    }
+
+    fn date(epoch: u32, slot_id: u32) -> BlockDate {
+        BlockDate { epoch, slot_id }
+    }
+
+    #[test]
+    fn slots_of_the_previous_eras_are_counted() {
+        // ten epochs of 10 slots, then epochs of 50 slots
+        let era = TimeEra::new(Slot::from(100), Epoch(10), 50);
+        assert_eq!(slot_of(&era, date(10, 0)), 100);
+        assert_eq!(slot_of(&era, date(11, 5)), 155);
+        assert_eq!(slot_of(&era, date(12, 0)) - slot_of(&era, date(11, 49)), 1);
+    }
+
+    #[test]
+    fn blocks_are_created_on_a_recent_tip() {
+        let mut stale_since = Some((hash(1), 10));
+        assert_eq!(abstain(&mut stale_since, hash(1), 20, 5, 10), Abstain::No);
+        assert_eq!(stale_since, None);
+        assert_eq!(abstain(&mut stale_since, hash(1), 20, 10, 10), Abstain::No);
+    }
+
+    #[test]
+    fn leaders_abstain_while_the_stale_tip_moves() {
+        let mut stale_since = None;
+        assert_eq!(
+            abstain(&mut stale_since, hash(1), 100, 50, 10),
+            Abstain::Yes
+        );
+        assert_eq!(stale_since, Some((hash(1), 100)));
+        // catching up, the tip moves and the leaders keep abstaining
+        assert_eq!(
+            abstain(&mut stale_since, hash(2), 115, 30, 10),
+            Abstain::Yes
+        );
+        assert_eq!(
+            abstain(&mut stale_since, hash(3), 130, 20, 10),
+            Abstain::Yes
+        );
+        assert_eq!(stale_since, Some((hash(3), 130)));
+    }
+
+    #[test]
+    fn blocks_are_created_again_when_the_network_stalled() {
+        let mut stale_since = None;
+        assert_eq!(
+            abstain(&mut stale_since, hash(1), 100, 20, 10),
+            Abstain::Yes
+        );
+        assert_eq!(
+            abstain(&mut stale_since, hash(1), 110, 30, 10),
+            Abstain::Yes
+        );
+        assert_eq!(
+            abstain(&mut stale_since, hash(1), 111, 31, 10),
+            Abstain::Stalled
+        );
+        // the block created moves the tip
+        assert_eq!(abstain(&mut stale_since, hash(4), 112, 1, 10), Abstain::No);
+        assert_eq!(stale_since, None);
+    }
+}
@@ -0,0 +1,168 @@
+//! the states the node goes through from its start until it is ready
+//!
+//! The node starts the REST server, prepares the storage and the genesis
+//! block, bootstraps from its trusted peers and starts its tasks. It is then
+//! syncing until its tip is recent, or until no more blocks come from the
+//! network, and stays ready from then on. The REST API only serves the
+//! state of the blockchain, and the leaders only create blocks, once the
+//! node is ready.
+
+use crate::{blockchain::Tip, service_manager, utils::task::TokioServiceInfo};
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset},
+    Epoch,
+};
+use futures::{future, prelude::*, sync::mpsc};
+use jormungandr_lib::interfaces::NodeState;
+use slog::Logger;
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::timer::Interval;
+
+const SYNC_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the current state of the node, shared by all the tasks
+#[derive(Clone)]
+pub struct Lifecycle {
+    state: Arc<RwLock<NodeState>>,
+    /// the watchers of the changes of the state, dropped with their receiver
+    watchers: Arc<Mutex<Vec<mpsc::UnboundedSender<NodeState>>>>,
+    logger: Logger,
+}
+
+impl Lifecycle {
+    pub fn new(logger: Logger) -> Self {
+        Lifecycle {
+            state: Arc::new(RwLock::new(NodeState::StartingRestServer)),
+            watchers: Arc::new(Mutex::new(Vec::new())),
+            logger,
+        }
+    }
+
+    pub fn set(&self, state: NodeState) {
+        let mut current = self.state.write().expect("node state poisoned");
+        if *current != state {
+            info!(self.logger, "node state changed"; "from" => ?*current, "to" => ?state);
+            service_manager::notify_status(&format!("{:?}", state));
+            self.watchers
+                .lock()
+                .expect("node state watchers poisoned")
+                .retain(|watcher| watcher.unbounded_send(state.clone()).is_ok());
+            *current = state;
+        }
+    }
+
+    /// the current state, then each change of the state
+    pub fn watch(&self) -> mpsc::UnboundedReceiver<NodeState> {
+        // the state does not change before the watcher is registered
+        let current = self.state.read().expect("node state poisoned");
+        let (watcher, changes) = mpsc::unbounded();
+        watcher
+            .unbounded_send(current.clone())
+            .expect("the receiver is held");
+        self.watchers
+            .lock()
+            .expect("node state watchers poisoned")
+            .push(watcher);
+        changes
+    }
+
+    pub fn state(&self) -> NodeState {
+        self.state.read().expect("node state poisoned").clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state() == NodeState::Running
+    }
+}
+
+/// move the node from syncing to ready once its tip is less than
+/// `max_tip_age` old, or did not change for `sync_timeout` which means the
+/// peers have no more recent blocks. The future does not complete, as the
+/// node shuts down when one of its tasks ends.
+pub fn track_sync(
+    info: TokioServiceInfo,
+    tip: Tip,
+    lifecycle: Lifecycle,
+    max_tip_age: Duration,
+    sync_timeout: Duration,
+) -> impl Future<Item = (), Error = ()> {
+    let logger = info.logger().clone();
+    let err_logger = logger.clone();
+    let mut last_tip = None;
+    let mut last_change = Instant::now();
+
+    lifecycle.set(NodeState::Syncing);
+    Interval::new_interval(SYNC_CHECK_INTERVAL)
+        .map_err(move |e| error!(err_logger, "timer error: {}", e))
+        .and_then(move |_| tip.get_ref())
+        .take_while(move |tip| {
+            let hash = tip.hash();
+            if last_tip != Some(hash) {
+                last_tip = Some(hash);
+                last_change = Instant::now();
+            }
+
+            let tip_date = tip.block_date();
+            let slot = tip
+                .epoch_leadership_schedule()
+                .era()
+                .from_era_to_slot(EpochPosition {
+                    epoch: Epoch(tip_date.epoch),
+                    slot: EpochSlotOffset(tip_date.slot_id),
+                });
+            let tip_age = tip
+                .time_frame()
+                .slot_to_systemtime(slot)
+                .and_then(|tip_time| SystemTime::now().duration_since(tip_time).ok())
+                .unwrap_or_default();
+
+            let synced = if tip_age <= max_tip_age {
+                info!(logger, "the node is synced"; "tip_age" => ?tip_age);
+                true
+            } else if last_change.elapsed() >= sync_timeout {
+                warn!(
+                    logger,
+                    "no more blocks received, the node is ready with an old tip";
+                    "tip_age" => ?tip_age,
+                );
+                true
+            } else {
+                false
+            };
+            if synced {
+                lifecycle.set(NodeState::Running);
+            }
+            Ok(!synced)
+        })
+        .for_each(|_| Ok(()))
+        .and_then(|()| future::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_sends_the_current_state_then_the_changes() {
+        let lifecycle = Lifecycle::new(Logger::root(slog::Discard, o!()));
+        lifecycle.set(NodeState::Syncing);
+        let changes = lifecycle.watch();
+        lifecycle.set(NodeState::Syncing);
+        lifecycle.set(NodeState::Running);
+        lifecycle.set(NodeState::ShuttingDown);
+        drop(lifecycle);
+
+        let states: Vec<NodeState> = changes.wait().map(Result::unwrap).collect();
+        assert_eq!(
+            states,
+            vec![
+                NodeState::Syncing,
+                NodeState::Running,
+                NodeState::ShuttingDown
+            ]
+        );
+    }
+}
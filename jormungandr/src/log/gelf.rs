@@ -0,0 +1,203 @@
+use serde_json::{Map, Value};
+use slog::{Drain, Level, Never, OwnedKVList, Record, KV};
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// the time given to the server to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// the time given to the server to take a message
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// the delay before the first attempt to connect again to the server
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// the delay between the attempts to connect again, doubled on every failed
+/// attempt up to this one
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// a drain sending the records to a Graylog server over TCP, one GELF
+/// message terminated by a null byte per record
+///
+/// The connection is opened again on a record once the retry delay is over
+/// when it fails, the delay being doubled on every failed attempt. The
+/// records which cannot be sent are dropped.
+pub struct GelfTcp {
+    server: String,
+    source: String,
+    connection: Mutex<Connection>,
+}
+
+struct Connection {
+    stream: Option<TcpStream>,
+    backoff: Backoff,
+}
+
+impl GelfTcp {
+    pub fn new(source: &str, server: &str) -> io::Result<Self> {
+        let stream = connect(server)?;
+        Ok(GelfTcp {
+            server: server.to_owned(),
+            source: source.to_owned(),
+            connection: Mutex::new(Connection {
+                stream: Some(stream),
+                backoff: Backoff::new(),
+            }),
+        })
+    }
+
+    fn message(&self, record: &Record, values: &OwnedKVList) -> Vec<u8> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as f64 / 1000.0)
+            .unwrap_or(0.0);
+        let mut fields = FieldSerializer(Map::new());
+        let _ = record.kv().serialize(record, &mut fields);
+        let _ = values.serialize(record, &mut fields);
+        let mut message = fields.0;
+        message.insert("version".to_owned(), "1.1".into());
+        message.insert("host".to_owned(), self.source.clone().into());
+        message.insert("short_message".to_owned(), record.msg().to_string().into());
+        message.insert("timestamp".to_owned(), timestamp.into());
+        message.insert("level".to_owned(), syslog_severity(record.level()).into());
+        message.insert("_file".to_owned(), record.file().into());
+        message.insert("_line".to_owned(), record.line().into());
+        message.insert("_module".to_owned(), record.module().into());
+
+        let mut bytes = serde_json::to_vec(&Value::Object(message)).unwrap_or_default();
+        bytes.push(0);
+        bytes
+    }
+}
+
+impl Drain for GelfTcp {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        let message = self.message(record, values);
+        let mut connection = self.connection.lock().unwrap();
+        let now = Instant::now();
+        if connection.stream.is_none() && connection.backoff.may_retry(now) {
+            match connect(&self.server) {
+                Ok(stream) => {
+                    connection.stream = Some(stream);
+                    connection.backoff.reset();
+                }
+                Err(_) => connection.backoff.failed(now),
+            }
+        }
+        let sent = match connection.stream.as_mut() {
+            Some(stream) => stream.write_all(&message).is_ok(),
+            None => return Ok(()),
+        };
+        if !sent {
+            // a message partially written leaves the stream unusable
+            connection.stream = None;
+            connection.backoff.failed(now);
+        }
+        Ok(())
+    }
+}
+
+/// connect to the first address of the server accepting the connection in
+/// time
+fn connect(server: &str) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in server.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "no address for the server")
+    }))
+}
+
+/// the delay before connecting again to the server
+struct Backoff {
+    delay: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Backoff {
+            delay: MIN_RETRY_DELAY,
+            retry_at: None,
+        }
+    }
+
+    fn may_retry(&self, now: Instant) -> bool {
+        self.retry_at.map_or(true, |retry_at| now >= retry_at)
+    }
+
+    fn failed(&mut self, now: Instant) {
+        if self.retry_at.is_some() {
+            self.delay = (self.delay * 2).min(MAX_RETRY_DELAY);
+        }
+        self.retry_at = Some(now + self.delay);
+    }
+
+    fn reset(&mut self) {
+        *self = Backoff::new();
+    }
+}
+
+/// the fields of the records as GELF additional fields. The first value of
+/// a key is kept, the ones of the record come before the ones of the loggers.
+struct FieldSerializer(Map<String, Value>);
+
+impl FieldSerializer {
+    fn insert(&mut self, key: slog::Key, value: Value) {
+        // `_id` is reserved by GELF
+        let key = match key {
+            "id" => "_id_".to_owned(),
+            key => format!("_{}", key),
+        };
+        self.0.entry(key).or_insert(value);
+    }
+}
+
+impl slog::Serializer for FieldSerializer {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        self.insert(key, val.to_string().into());
+        Ok(())
+    }
+
+    fn emit_bool(&mut self, key: slog::Key, val: bool) -> slog::Result {
+        self.insert(key, val.into());
+        Ok(())
+    }
+
+    fn emit_u64(&mut self, key: slog::Key, val: u64) -> slog::Result {
+        self.insert(key, val.into());
+        Ok(())
+    }
+
+    fn emit_i64(&mut self, key: slog::Key, val: i64) -> slog::Result {
+        self.insert(key, val.into());
+        Ok(())
+    }
+
+    fn emit_f64(&mut self, key: slog::Key, val: f64) -> slog::Result {
+        self.insert(key, val.into());
+        Ok(())
+    }
+}
+
+fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Critical => 2,
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
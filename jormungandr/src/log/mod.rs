@@ -1,8 +1,17 @@
 mod asyncable_drain;
+#[cfg(feature = "gelf")]
+mod gelf;
+mod rotation;
 pub mod stream;
+mod trace;
 
 pub use self::asyncable_drain::AsyncableDrain;
+#[cfg(feature = "gelf")]
+pub use self::gelf::GelfTcp;
+pub use self::rotation::RotatingFile;
+pub use self::trace::TraceId;
 
 pub const KEY_TASK: &str = "task";
 pub const KEY_SUB_TASK: &str = "sub_task";
 pub const KEY_SCOPE: &str = "scope";
+pub const KEY_TRACE: &str = "trace_id";
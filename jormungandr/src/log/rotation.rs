@@ -0,0 +1,120 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// a log file replaced by a new one when it grows over `max_size` bytes or
+/// gets older than `interval`. The replaced files are kept as `<path>.1`
+/// (the most recent) up to `<path>.<max_files>`, the older ones are removed.
+///
+/// The file is only replaced between two lines, so the records written by
+/// the drains are never split.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: Instant,
+    max_size: Option<u64>,
+    interval: Option<Duration>,
+    max_files: usize,
+    at_line_start: bool,
+}
+
+impl RotatingFile {
+    pub fn open<P: Into<PathBuf>>(
+        path: P,
+        max_size: Option<u64>,
+        interval: Option<Duration>,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            // the age of a file written by a previous run is not known, it is
+            // counted from the start of the node
+            opened: Instant::now(),
+            max_size,
+            interval,
+            max_files,
+            at_line_start: true,
+        })
+    }
+
+    fn needs_rotation(&self) -> bool {
+        self.size > 0
+            && (self
+                .max_size
+                .map_or(false, |max_size| self.size >= max_size)
+                || self
+                    .interval
+                    .map_or(false, |interval| self.opened.elapsed() >= interval))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            remove_if_exists(&rotated_path(&self.path, self.max_files))?;
+            for index in (1..self.max_files).rev() {
+                rename_if_exists(
+                    &rotated_path(&self.path, index),
+                    &rotated_path(&self.path, index + 1),
+                )?;
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_line_start && self.needs_rotation() {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        if written > 0 {
+            self.at_line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    rotated.into()
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::{self, Display};
+
+/// the identifier of the processing of a fragment or a block across the
+/// tasks of the node, logged under the `trace_id` key so the logs of the
+/// tasks can be correlated. It has the format of the trace ids of the
+/// [W3C Trace Context](https://www.w3.org/TR/trace-context/).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId([u8; 16]);
+
+impl TraceId {
+    pub fn new() -> Self {
+        TraceId(rand::random())
+    }
+
+    /// the trace id of a W3C `traceparent` header, e.g.
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`, so the
+    /// processing of a request continues the trace of the client
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        if version.len() != 2 || version == "ff" || trace_id.len() != 32 {
+            return None;
+        }
+        let mut bytes = [0; 16];
+        for (byte, i) in bytes.iter_mut().zip((0..32).step_by(2)) {
+            *byte = u8::from_str_radix(trace_id.get(i..i + 2)?, 16).ok()?;
+        }
+        // the all zero trace id is invalid
+        if bytes == [0; 16] {
+            None
+        } else {
+            Some(TraceId(bytes))
+        }
+    }
+}
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
@@ -21,11 +21,14 @@ extern crate imhamt;
 extern crate custom_error;
 #[macro_use]
 extern crate error_chain;
+extern crate flate2;
 #[macro_use(try_ready)]
 extern crate futures;
+extern crate hex;
 extern crate http;
 extern crate humantime;
 extern crate hyper;
+extern crate hyper_tls;
 extern crate jormungandr_lib;
 #[macro_use]
 extern crate lazy_static;
@@ -57,33 +60,48 @@ extern crate structopt;
 extern crate thiserror;
 extern crate tk_listen;
 extern crate tokio;
+#[cfg(unix)]
+extern crate tokio_signal;
+#[cfg(windows)]
+extern crate windows_service;
 
 use crate::{
     blockcfg::{HeaderHash, Leader},
     blockchain::{Blockchain, CandidateForest},
+    lifecycle::Lifecycle,
     secure::enclave::Enclave,
-    settings::start::Settings,
-    utils::{async_msg, task::Services},
+    settings::start::{LedgerDump, Settings, StorageBackend},
+    utils::{async_msg, task::Services, watchdog::Watchdog},
 };
 use futures::Future;
 use jormungandr_lib::interfaces::NodeState;
 use settings::{start::RawSettings, CommandLine};
 use slog::Logger;
-use std::time::Duration;
+use std::path::Path;
 
 pub mod blockcfg;
 pub mod blockchain;
 pub mod blockchain_stuck_notifier;
 pub mod client;
+pub mod clock_drift;
+pub mod disk_space;
 pub mod explorer;
 pub mod fragment;
 pub mod intercom;
 pub mod leadership;
+pub mod lifecycle;
 pub mod log;
+pub mod maintenance;
 pub mod network;
+pub mod notifier;
+pub mod reload;
 pub mod rest;
+pub mod rewards;
 pub mod secure;
+pub mod service_manager;
 pub mod settings;
+pub mod shutdown;
+pub mod stake_snapshot;
 pub mod start_up;
 pub mod state;
 mod stats_counter;
@@ -91,9 +109,22 @@ pub mod utils;
 
 use stats_counter::StatsCounter;
 
-fn start() -> Result<(), start_up::Error> {
+/// run the node, returns the exit code of the node once its tasks stopped
+fn start() -> Result<i32, start_up::Error> {
     let initialized_node = initialize_node()?;
 
+    if let Some(path) = initialized_node.settings.export_chain.clone() {
+        return export_chain(initialized_node, &path).map(|()| 0);
+    }
+
+    if initialized_node.settings.storage_check {
+        return check_storage(initialized_node).map(|()| 0);
+    }
+
+    if let Some(dump) = initialized_node.settings.dump_ledger.clone() {
+        return dump_ledger(initialized_node, dump).map(|()| 0);
+    }
+
     let bootstrapped_node = bootstrap(initialized_node)?;
 
     start_services(bootstrapped_node)
@@ -104,25 +135,36 @@ pub struct BootstrappedNode {
     blockchain: Blockchain,
     blockchain_tip: blockchain::Tip,
     block0_hash: HeaderHash,
+    kes_schedule: Option<secure::evolution::KesSchedule>,
     logger: Logger,
     explorer_db: Option<explorer::ExplorerDB>,
     rest_context: Option<rest::Context>,
+    lifecycle: Lifecycle,
     services: Services,
+    running_config: reload::RunningConfig,
 }
 
-const FRAGMENT_TASK_QUEUE_LEN: usize = 1024;
-const NETWORK_TASK_QUEUE_LEN: usize = 32;
+const RELOAD_TASK_QUEUE_LEN: usize = 4;
+const REWARDS_TASK_QUEUE_LEN: usize = 16;
+/// the epoch transitions waiting to be recorded by the explorer or the stats
+const EPOCH_TRANSITIONS_QUEUE_LEN: usize = 4;
 
-fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::Error> {
-    if let Some(context) = bootstrapped_node.rest_context.as_ref() {
-        context.set_node_state(NodeState::StartingWorkers)
-    }
+fn start_services(bootstrapped_node: BootstrappedNode) -> Result<i32, start_up::Error> {
+    service_manager::notify_ready();
+    bootstrapped_node.lifecycle.set(NodeState::StartingWorkers);
 
     let mut services = bootstrapped_node.services;
+    let intercom = bootstrapped_node.settings.intercom.clone();
+    let stats_counter = StatsCounter::default();
 
     // initialize the network propagation channel
-    let (network_msgbox, network_queue) = async_msg::channel(NETWORK_TASK_QUEUE_LEN);
-    let (fragment_msgbox, fragment_queue) = async_msg::channel(FRAGMENT_TASK_QUEUE_LEN);
+    let (network_msgbox, network_queue) = async_msg::channel(intercom.network);
+    let (fragment_msgbox, fragment_queue) = async_msg::channel(intercom.fragment);
+    let (reload_msgbox, reload_queue) = async_msg::channel(RELOAD_TASK_QUEUE_LEN);
+    stats_counter.add_queue("network", network_msgbox.monitor().clone());
+    stats_counter.add_queue("fragment", fragment_msgbox.monitor().clone());
+    services.watch_queue("network", network_msgbox.monitor().clone());
+    services.watch_queue("fragment", fragment_msgbox.monitor().clone());
     let blockchain_tip = bootstrapped_node.blockchain_tip;
     let blockchain = bootstrapped_node.blockchain;
     let leadership_logs =
@@ -130,30 +172,90 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     let leadership_garbage_collection_interval =
         bootstrapped_node.settings.leadership.log_ttl.into();
 
-    let stats_counter = StatsCounter::default();
+    let disk_guard = disk_space::DiskGuard::default();
+    if let Some(storage) = &bootstrapped_node.settings.storage {
+        let path = storage.path.clone();
+        let disk_space = &bootstrapped_node.settings.disk_space;
+        let interval = disk_space.interval.into();
+        let soft_threshold = disk_space.soft_threshold;
+        let hard_threshold = disk_space.hard_threshold;
+        let stats_counter = stats_counter.clone();
+        let disk_guard = disk_guard.clone();
+        services.spawn_future("disk_space", move |info| {
+            disk_space::monitor(
+                info,
+                path,
+                interval,
+                soft_threshold,
+                hard_threshold,
+                stats_counter,
+                disk_guard,
+            )
+        });
+    }
 
     let (fragment_pool, pool_logs) = {
         let stats_counter = stats_counter.clone();
+        let persistent_logs = bootstrapped_node
+            .settings
+            .mempool
+            .persistent_log
+            .as_ref()
+            .and_then(|config| {
+                let logger = bootstrapped_node
+                    .logger
+                    .new(o!(log::KEY_TASK => "fragment"));
+                fragment::PersistentLogs::open(
+                    &config.path,
+                    config.retention.into(),
+                    disk_guard.clone(),
+                    logger,
+                )
+                .map_err(|e| {
+                    error!(
+                        bootstrapped_node.logger,
+                        "cannot open the fragment logs file, the logs are kept in memory only";
+                        "path" => %config.path.display(),
+                        "reason" => %e,
+                    )
+                })
+                .ok()
+            });
         let process = fragment::Process::new(
             bootstrapped_node.settings.mempool.fragment_ttl.into(),
             bootstrapped_node.settings.mempool.log_ttl.into(),
+            persistent_logs,
             bootstrapped_node
                 .settings
                 .mempool
                 .garbage_collection_interval
                 .into(),
             network_msgbox.clone(),
+            disk_guard.clone(),
         );
 
         let pool = process.pool().clone();
         let logs = process.logs().clone();
 
-        services.spawn_future("fragment", move |info| {
-            process.start(info, stats_counter, fragment_queue)
+        let fragment_queue = fragment_queue.into_shared();
+        services.spawn_future_supervised("fragment", move |info| {
+            process
+                .clone()
+                .start(info, stats_counter.clone(), fragment_queue.clone())
         });
         (pool, logs)
     };
 
+    let shutdown = shutdown::Shutdown {
+        lifecycle: bootstrapped_node.lifecycle.clone(),
+        network_task: network_msgbox.clone(),
+        fragment_logs: pool_logs.clone(),
+        blockchain: blockchain.clone(),
+        logger: bootstrapped_node
+            .logger
+            .new(o!(log::KEY_TASK => "shutdown")),
+    };
+
     let explorer = {
         if bootstrapped_node.settings.explorer {
             let explorer_db = bootstrapped_node
@@ -166,8 +268,19 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             // Context to give to the rest api
             let context = explorer.clone();
 
-            let task_msg_box = services.spawn_future_with_inputs("explorer", move |info, input| {
-                explorer.handle_input(info, input)
+            let task_msg_box = services.spawn_future_with_inputs(
+                "explorer",
+                intercom.explorer,
+                move |info, input| explorer.handle_input(info, input),
+            );
+            stats_counter.add_queue("explorer", task_msg_box.monitor().clone());
+
+            let (transitions, monitor) = blockchain
+                .epoch_hooks()
+                .subscribe("explorer_stake", EPOCH_TRANSITIONS_QUEUE_LEN);
+            stats_counter.add_queue("explorer_stake", monitor);
+            services.spawn_future("explorer_stake", move |_info| {
+                explorer_db.record_stake_distributions(transitions)
             });
             Some((task_msg_box, context))
         } else {
@@ -175,35 +288,205 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         }
     };
 
+    let notifier = {
+        let notifications = bootstrapped_node.settings.notifications.clone();
+        if notifications.webhooks.is_empty() {
+            None
+        } else {
+            if blockchain.index().is_none()
+                && notifications
+                    .webhooks
+                    .iter()
+                    .any(|webhook| !webhook.addresses.is_empty())
+            {
+                warn!(
+                    bootstrapped_node.logger,
+                    "the chain index is not enabled, no `fragment_included` events will be sent"
+                );
+            }
+            match notifier::Notifier::new(
+                notifications,
+                blockchain.clone(),
+                blockchain_tip.clone(),
+                bootstrapped_node.lifecycle.clone(),
+            ) {
+                Ok(mut notifier) => {
+                    let task_msg_box = services.spawn_future_with_inputs(
+                        "notifier",
+                        intercom.notifier,
+                        move |info, input| notifier.handle_input(info, input),
+                    );
+                    stats_counter.add_queue("notifier", task_msg_box.monitor().clone());
+                    Some(task_msg_box)
+                }
+                Err(e) => {
+                    error!(
+                        bootstrapped_node.logger,
+                        "cannot start the notifications";
+                        "reason" => %e,
+                    );
+                    None
+                }
+            }
+        }
+    };
+
     let block_task = {
         let mut blockchain = blockchain.clone();
         let mut blockchain_tip = blockchain_tip.clone();
         let mut network_msgbox = network_msgbox.clone();
         let mut fragment_msgbox = fragment_msgbox.clone();
         let mut explorer_msg_box = explorer.as_ref().map(|(msg_box, _context)| msg_box.clone());
-        // TODO: we should get this value from the configuration
-        let block_cache_ttl: Duration = Duration::from_secs(3600);
-        let stats_counter = stats_counter.clone();
-        services.spawn_future_with_inputs("block", move |info, input| {
-            let candidate_repo = CandidateForest::new(
+        let mut notifier_msg_box = notifier;
+        let candidate_repo = CandidateForest::new(
+            blockchain.clone(),
+            bootstrapped_node.settings.cache.header_chains_ttl.into(),
+            bootstrapped_node
+                .logger
+                .new(o!(log::KEY_TASK => "block", log::KEY_SUB_TASK => "chain_pull")),
+        );
+        let cache_purge = blockchain::CachePurge::new(
+            blockchain.clone(),
+            candidate_repo.clone(),
+            bootstrapped_node.settings.cache.purge_interval.into(),
+        );
+        services.spawn_future("cache_purge", move |info| cache_purge.run(info));
+        let task_stats_counter = stats_counter.clone();
+        let block_task =
+            services.spawn_future_with_inputs("block", intercom.block, move |info, input| {
+                blockchain::handle_input(
+                    info,
+                    &mut blockchain,
+                    &mut blockchain_tip,
+                    &candidate_repo,
+                    &task_stats_counter,
+                    &mut network_msgbox,
+                    &mut fragment_msgbox,
+                    explorer_msg_box.as_mut(),
+                    notifier_msg_box.as_mut(),
+                    input,
+                )
+            });
+        stats_counter.add_queue("block", block_task.monitor().clone());
+        block_task
+    };
+
+    if let Some(storage) = bootstrapped_node.settings.storage.as_ref() {
+        if let Some(pruning) = storage.pruning.as_ref() {
+            if storage.backend == StorageBackend::Sled {
+                let pruning = blockchain::Pruning::new(
+                    blockchain.clone(),
+                    blockchain_tip.clone(),
+                    pruning.depth,
+                    pruning.interval,
+                );
+                services.spawn_future("pruning", move |info| pruning.run(info));
+            } else {
+                warn!(
+                    bootstrapped_node.logger,
+                    "storage pruning is only supported by the sled backend, not pruning"
+                );
+            }
+        }
+    }
+
+    if blockchain.index().is_some() {
+        let backfill = blockchain::Backfill::new(
+            blockchain.clone(),
+            blockchain_tip.clone(),
+            bootstrapped_node.block0_hash,
+            bootstrapped_node.settings.check_index,
+        );
+        services.spawn_future("index_backfill", move |info| backfill.run(info));
+    }
+
+    {
+        let config = bootstrapped_node.settings.maintenance.clone();
+        if maintenance::Maintenance::has_actions(&config) {
+            if config.compact_storage && bootstrapped_node.settings.storage.is_none() {
+                warn!(
+                    bootstrapped_node.logger,
+                    "the blockchain is kept in memory, there is no storage to compact"
+                );
+            }
+            let scheduled = config.schedule.is_some();
+            let maintenance = maintenance::Maintenance::new(
                 blockchain.clone(),
-                block_cache_ttl,
-                info.logger().new(o!(log::KEY_SUB_TASK => "chain_pull")),
+                blockchain_tip.clone(),
+                bootstrapped_node.block0_hash,
+                bootstrapped_node
+                    .settings
+                    .storage
+                    .as_ref()
+                    .map(|storage| storage.path.clone()),
+                stats_counter.clone(),
+                disk_guard.clone(),
+                config,
             );
-            blockchain::handle_input(
-                info,
-                &mut blockchain,
-                &mut blockchain_tip,
-                &candidate_repo,
-                &stats_counter,
-                &mut network_msgbox,
-                &mut fragment_msgbox,
-                explorer_msg_box.as_mut(),
-                input,
-            )
-        })
+            if scheduled {
+                services.spawn_future("maintenance", move |info| maintenance.run_scheduled(info));
+            } else {
+                let (transitions, monitor) = blockchain
+                    .epoch_hooks()
+                    .subscribe("maintenance", maintenance::TRANSITIONS_CAPACITY);
+                stats_counter.add_queue("maintenance", monitor);
+                services.spawn_future("maintenance", move |info| {
+                    maintenance.run(info, transitions)
+                });
+            }
+        }
+    }
+
+    // the rewards history is kept in the sled storage
+    let rewards_msgbox = if bootstrapped_node
+        .settings
+        .storage
+        .as_ref()
+        .map_or(false, |storage| storage.backend == StorageBackend::Sled)
+    {
+        let (rewards_msgbox, rewards_queue) = async_msg::channel(REWARDS_TASK_QUEUE_LEN);
+        let (transitions, monitor) = blockchain
+            .epoch_hooks()
+            .subscribe("rewards", rewards::TRANSITIONS_CAPACITY);
+        stats_counter.add_queue("rewards", monitor);
+        let rewards = rewards::Rewards::new(blockchain.storage().clone(), disk_guard.clone());
+        services.spawn_future("rewards", move |info| {
+            rewards.run(info, transitions, rewards_queue)
+        });
+        Some(rewards_msgbox)
+    } else {
+        info!(
+            bootstrapped_node.logger,
+            "the rewards history is only kept by the sled storage backend, not keeping it"
+        );
+        None
     };
 
+    {
+        let (transitions, monitor) = blockchain
+            .epoch_hooks()
+            .subscribe("epoch_stats", EPOCH_TRANSITIONS_QUEUE_LEN);
+        stats_counter.add_queue("epoch_stats", monitor);
+        let stats_counter = stats_counter.clone();
+        services.spawn_future("epoch_stats", move |_info| {
+            transitions.for_each(move |transition| {
+                stats_counter.add_epoch_transition(transition.epoch, transition.reentered);
+                Ok(())
+            })
+        });
+    }
+
+    if let Some(config) = bootstrapped_node.settings.stake_snapshots.clone() {
+        let (transitions, monitor) = blockchain
+            .epoch_hooks()
+            .subscribe("stake_snapshot", stake_snapshot::TRANSITIONS_CAPACITY);
+        stats_counter.add_queue("stake_snapshot", monitor);
+        let snapshots = stake_snapshot::Snapshots::new(config, disk_guard.clone());
+        services.spawn_future("stake_snapshot", move |info| {
+            snapshots.run(info, transitions)
+        });
+    }
+
     let client_task = {
         let mut task_data = client::TaskData {
             storage: blockchain.storage().clone(),
@@ -211,9 +494,12 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             blockchain_tip: blockchain_tip.clone(),
         };
 
-        services.spawn_with_inputs("client-query", move |info, input| {
-            client::handle_input(info, &mut task_data, input)
-        })
+        let client_task =
+            services.spawn_with_inputs("client-query", intercom.client, move |info, input| {
+                client::handle_input(info, &mut task_data, input)
+            });
+        stats_counter.add_queue("client-query", client_task.monitor().clone());
+        client_task
     };
 
     {
@@ -222,11 +508,26 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         let block_msgbox = block_task.clone();
         let block0_hash = bootstrapped_node.block0_hash;
         let config = bootstrapped_node.settings.network.clone();
+        let stats_counter = stats_counter.clone();
+        let lifecycle = bootstrapped_node.lifecycle.clone();
+        let blockchain_tip = blockchain_tip.clone();
         let channels = network::Channels {
             client_box: client_msgbox,
             transaction_box: fragment_msgbox,
             block_box: block_msgbox,
         };
+        let audit_log = match config.audit_log.as_ref() {
+            Some(path) => {
+                let logger = bootstrapped_node.logger.new(o!(log::KEY_TASK => "network"));
+                let audit_log =
+                    network::AuditLog::open(path, logger).map_err(|e| start_up::Error::IO {
+                        source: e,
+                        reason: start_up::ErrorKind::AuditLog,
+                    })?;
+                Some(audit_log)
+            }
+            None => None,
+        };
 
         services.spawn_future("network", move |info| {
             let params = network::TaskParams {
@@ -234,6 +535,10 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 block0_hash,
                 input: network_queue,
                 channels,
+                stats_counter,
+                lifecycle,
+                blockchain_tip,
+                audit_log,
             };
             network::start(info, params)
                 // FIXME: more graceful error reporting
@@ -241,12 +546,24 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    {
+        let reload = reload::Reload::new(
+            bootstrapped_node.running_config,
+            network_msgbox.clone(),
+            fragment_msgbox.clone(),
+        );
+        services.spawn_future("reload", move |info| reload.run(info, reload_queue));
+    }
+
     let leader_secrets: Result<Vec<Leader>, start_up::Error> = bootstrapped_node
         .settings
         .secrets
         .iter()
         .map(|secret_path| {
-            let secret = secure::NodeSecret::load_from_file(secret_path.as_path())?;
+            let secret = secure::NodeSecret::load_from_file(
+                secret_path.as_path(),
+                bootstrapped_node.settings.secrets_passphrase.as_ref(),
+            )?;
             Ok(Leader {
                 bft_leader: secret.bft(),
                 genesis_leader: secret.genesis(),
@@ -254,14 +571,121 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         })
         .collect();
     let leader_secrets = leader_secrets?;
-    let enclave = Enclave::from_vec(leader_secrets);
+    let enclave = Enclave::new();
+    let secret_files: Vec<_> = leader_secrets
+        .into_iter()
+        .zip(bootstrapped_node.settings.secrets.iter().cloned())
+        .map(|(leader, secret_path)| (enclave.add_leader(leader), secret_path))
+        .collect();
+
+    if let Some(kes_schedule) = bootstrapped_node.kes_schedule {
+        let key_evolution = secure::evolution::KeyEvolution::new(
+            enclave.clone(),
+            kes_schedule,
+            secret_files,
+            bootstrapped_node.settings.secrets_passphrase.clone(),
+        );
+        services.spawn_future("kes_evolution", move |info| key_evolution.run(info));
+    }
+
+    // the same client enclave is shared between the leadership task and the
+    // task applying the leader updates so both are serialized on its lock
+    let leadership_settings = &bootstrapped_node.settings.leadership;
+    let leadership_enclave = match (
+        &leadership_settings.remote_enclave,
+        &leadership_settings.hsm,
+    ) {
+        (Some(remote), _) => {
+            if !bootstrapped_node.settings.secrets.is_empty() {
+                warn!(
+                    bootstrapped_node.logger,
+                    "a remote enclave is configured, the secret files will not be used to create blocks"
+                );
+            }
+            leadership::Enclave::remote(leadership::RemoteEnclave::new(
+                remote.socket_path.clone(),
+                remote.timeout.into(),
+            ))
+        }
+        (None, Some(hsm)) => {
+            if !bootstrapped_node.settings.secrets.is_empty() {
+                warn!(
+                    bootstrapped_node.logger,
+                    "a hardware security module is configured, the secret files will not be used to create blocks"
+                );
+            }
+            let settings = leadership::HsmSettings {
+                module: hsm.module.clone(),
+                slot: hsm.slot,
+                pin: bootstrapped_node.settings.hsm_pin.clone(),
+                keys: hsm
+                    .keys
+                    .iter()
+                    .map(|key| (key.label.clone(), key.public_key.clone()))
+                    .collect(),
+                timeout: hsm.timeout.into(),
+                latency_warning: hsm.latency_warning.into(),
+            };
+            let logger = bootstrapped_node.logger.new(o!(log::KEY_TASK => "hsm"));
+            leadership::Enclave::hsm(leadership::HsmEnclave::open(settings, logger)?)
+        }
+        (None, None) => leadership::Enclave::new(enclave.clone()),
+    };
+
+    let leadership_task = {
+        let enclave = leadership_enclave.clone();
+        let leadership_task =
+            services.spawn_future_with_inputs("leaders", intercom.leaders, move |info, input| {
+                enclave.handle_input(info, input)
+            });
+        stats_counter.add_queue("leaders", leadership_task.monitor().clone());
+        leadership_task
+    };
+
+    let clock_guard = clock_drift::ClockGuard::default();
+    let clock_drift = &bootstrapped_node.settings.clock_drift;
+    if !clock_drift.servers.is_empty() {
+        let servers = clock_drift.servers.clone();
+        let interval = clock_drift.interval.into();
+        let threshold = clock_drift.threshold.into();
+        let pause_leadership = clock_drift.pause_leadership;
+        let stats_counter = stats_counter.clone();
+        let clock_guard = clock_guard.clone();
+        services.spawn_future("clock_drift", move |info| {
+            clock_drift::monitor(
+                info,
+                servers,
+                interval,
+                threshold,
+                pause_leadership,
+                stats_counter,
+                clock_guard,
+            )
+        });
+    }
 
     {
         let leadership_logs = leadership_logs.clone();
         let fragment_pool = fragment_pool.clone();
         let block_task = block_task.clone();
         let blockchain_tip = blockchain_tip.clone();
-        let enclave = leadership::Enclave::new(enclave.clone());
+        let enclave = leadership_enclave;
+        let stats_counter = stats_counter.clone();
+        let lifecycle = bootstrapped_node.lifecycle.clone();
+        let clock_guard = clock_guard.clone();
+        let max_slots_behind = bootstrapped_node.settings.leadership.max_slots_behind;
+        let standby = bootstrapped_node
+            .settings
+            .leadership
+            .standby
+            .as_ref()
+            .map(|standby| {
+                leadership::Standby::new(
+                    standby.slot_grace.into(),
+                    standby.promotion_timeout.into(),
+                    blockchain.received_headers().clone(),
+                )
+            });
 
         services.spawn_future("leadership", move |info| {
             leadership::Module::new(
@@ -272,6 +696,11 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 fragment_pool,
                 enclave,
                 block_task,
+                stats_counter,
+                standby,
+                lifecycle,
+                clock_guard,
+                max_slots_behind,
             )
             .and_then(|module| module.run())
             .map_err(|e| unimplemented!("error in leadership {}", e))
@@ -285,15 +714,34 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             blockchain_tip: blockchain_tip.clone(),
             network_task: network_msgbox,
             transaction_task: fragment_msgbox,
+            leadership_task,
+            reload_task: reload_msgbox,
+            rewards_task: rewards_msgbox,
             logs: pool_logs,
+            fragment_limits: bootstrapped_node.settings.mempool.fragment_limits.clone(),
             leadership_logs,
             enclave,
             explorer: explorer.as_ref().map(|(_msg_box, context)| context.clone()),
+            disk_guard,
         };
         rest_context.set_full(full_context);
-        rest_context.set_node_state(NodeState::Running);
     };
 
+    {
+        let blockchain_tip = blockchain_tip.clone();
+        let lifecycle = bootstrapped_node.lifecycle.clone();
+        let readiness = bootstrapped_node.settings.readiness.clone();
+        services.spawn_future("sync", move |info| {
+            lifecycle::track_sync(
+                info,
+                blockchain_tip,
+                lifecycle,
+                readiness.max_tip_age.into(),
+                readiness.sync_timeout.into(),
+            )
+        });
+    }
+
     {
         let blockchain_tip = blockchain_tip.clone();
         let no_blockchain_updates_warning_interval = bootstrapped_node
@@ -310,9 +758,20 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    let termination = shutdown::Termination::default();
+    #[cfg(any(unix, windows))]
+    {
+        let termination = termination.clone();
+        services.spawn_future("signals", move |info| {
+            shutdown::wait_for_signal(info.into_logger(), termination)
+        });
+    }
+
     services.wait_any_finished();
+    service_manager::notify_stopping();
     info!(bootstrapped_node.logger, "Shutting down node");
-    Ok(())
+    shutdown.run(&mut services);
+    Ok(termination.exit_code())
 }
 
 /// # Bootstrap phase
@@ -334,23 +793,44 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
         storage,
         logger,
         rest_context,
+        lifecycle,
         services,
+        running_config,
     } = initialized_node;
 
-    if let Some(context) = rest_context.as_ref() {
-        context.set_node_state(NodeState::Bootstrapping)
-    }
+    lifecycle.set(NodeState::Bootstrapping);
 
     let bootstrap_logger = logger.new(o!(log::KEY_TASK => "bootstrap"));
 
     let block0_hash = block0.header.hash();
 
+    // block0 without the KES parameters have no genesis leaders keys to evolve
+    let kes_schedule = secure::evolution::KesSchedule::from_block0(&block0).ok();
+
     let block0_explorer = block0.clone();
 
-    // TODO: we should get this value from the configuration
-    let block_cache_ttl: Duration = Duration::from_secs(5 * 24 * 3600);
+    let index = start_up::prepare_index(&settings, &block0, &bootstrap_logger)?;
 
-    let (blockchain, blockchain_tip) = start_up::load_blockchain(block0, storage, block_cache_ttl)?;
+    let (blockchain, blockchain_tip) = start_up::load_blockchain(
+        block0,
+        storage,
+        start_up::storage_io_stats(&settings, &logger),
+        settings.cache.ledger_states_ttl.into(),
+        settings.cache.ledger_states_capacity,
+        settings.max_rollback_depth,
+        settings.chain_selection.clone(),
+        index,
+    )?;
+
+    if let Some(path) = settings.import_chain.as_ref() {
+        start_up::import_chain(
+            blockchain.clone(),
+            blockchain_tip.clone(),
+            block0_hash,
+            path,
+            &bootstrap_logger,
+        )?;
+    }
 
     let bootstrapped = network::bootstrap(
         &settings.network,
@@ -378,22 +858,115 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     Ok(BootstrappedNode {
         settings,
         block0_hash,
+        kes_schedule,
         blockchain,
         blockchain_tip,
         logger,
         explorer_db,
         rest_context,
+        lifecycle,
         services,
+        running_config,
     })
 }
 
+/// write the chain held in the storage to an archive instead of starting
+/// the node
+fn export_chain(initialized_node: InitializedNode, path: &Path) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        settings,
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+
+    let export_logger = logger.new(o!(log::KEY_TASK => "export"));
+    let block0_hash = block0.header.hash();
+    let (blockchain, blockchain_tip) = start_up::load_blockchain(
+        block0,
+        storage,
+        start_up::storage_io_stats(&settings, &logger),
+        settings.cache.ledger_states_ttl.into(),
+        settings.cache.ledger_states_capacity,
+        settings.max_rollback_depth,
+        settings.chain_selection.clone(),
+        None,
+    )?;
+    start_up::export_chain(
+        blockchain,
+        blockchain_tip,
+        block0_hash,
+        path,
+        &export_logger,
+    )?;
+    Ok(())
+}
+
+/// verify the chain held in the storage instead of starting the node
+fn check_storage(initialized_node: InitializedNode) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        settings,
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+
+    let check_logger = logger.new(o!(log::KEY_TASK => "storage_check"));
+    start_up::check_storage(
+        block0,
+        storage,
+        start_up::storage_io_stats(&settings, &logger),
+        settings.cache.ledger_states_ttl.into(),
+        settings.cache.ledger_states_capacity,
+        &check_logger,
+    )?;
+    Ok(())
+}
+
+/// write the ledger state at a block of the storage to files instead of
+/// starting the node
+fn dump_ledger(initialized_node: InitializedNode, dump: LedgerDump) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        settings,
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+
+    let dump_logger = logger.new(o!(log::KEY_TASK => "ledger_dump"));
+    let (blockchain, blockchain_tip) = start_up::load_blockchain(
+        block0,
+        storage,
+        start_up::storage_io_stats(&settings, &logger),
+        settings.cache.ledger_states_ttl.into(),
+        settings.cache.ledger_states_capacity,
+        settings.max_rollback_depth,
+        settings.chain_selection.clone(),
+        None,
+    )?;
+    start_up::dump_ledger(
+        blockchain,
+        blockchain_tip,
+        dump.at,
+        &dump.dir,
+        dump.format,
+        &dump_logger,
+    )?;
+    Ok(())
+}
+
 pub struct InitializedNode {
     pub settings: Settings,
     pub block0: blockcfg::Block,
     pub storage: start_up::NodeStorage,
     pub logger: Logger,
     pub rest_context: Option<rest::Context>,
+    pub lifecycle: Lifecycle,
     pub services: Services,
+    pub running_config: reload::RunningConfig,
 }
 
 fn initialize_node() -> Result<InitializedNode, start_up::Error> {
@@ -405,12 +978,16 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
     } else if command_line.source_version {
         println!("{}", env!("SOURCE_VERSION"));
         std::process::exit(0);
+    } else if command_line.check_config {
+        let report = settings::start::check_config(command_line);
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        std::process::exit(if report.valid { 0 } else { 2 });
     }
 
     let raw_settings = RawSettings::load(command_line)?;
 
     let log_settings = raw_settings.log_settings();
-    let logger = log_settings.to_logger()?;
+    let (logger, log_levels) = log_settings.to_logger()?;
 
     // The log crate is used by some libraries, e.g. tower-grpc.
     // Set up forwarding from log to slog, but only when trace log level is
@@ -418,7 +995,8 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
     if log_settings
         .0
         .iter()
-        .any(|entry| entry.level >= slog::FilterLevel::Trace)
+        .flat_map(|entry| std::iter::once(&entry.level).chain(entry.modules.values()))
+        .any(|level| *level >= slog::FilterLevel::Trace)
     {
         slog_scope::set_global_logger(logger.new(o!(log::KEY_SCOPE => "global"))).cancel_reset();
         slog_stdlog::init().unwrap();
@@ -426,12 +1004,27 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
 
     let init_logger = logger.new(o!(log::KEY_TASK => "init"));
     info!(init_logger, "Starting {}", env!("FULL_VERSION"),);
-    let settings = raw_settings.try_into_settings(&init_logger)?;
+    let running_config =
+        reload::RunningConfig::new(&raw_settings, &log_settings, log_levels.clone())?;
+    let mut settings = raw_settings.try_into_settings(&init_logger)?;
+    network::peer_list::extend_trusted_peers(&mut settings.network, &init_logger)?;
+    let lifecycle = Lifecycle::new(logger.new(o!(log::KEY_TASK => "lifecycle")));
     let mut services = Services::new(logger.clone());
+    services.set_restart_policy(settings.restart_policy);
+    services.set_runtime_threads(
+        settings.runtime_threads,
+        settings.task_runtime_threads.clone(),
+    );
+    services.set_watchdog(Watchdog::start(
+        logger.new(o!(log::KEY_TASK => "watchdog")),
+        settings.watchdog.timeout.into(),
+        settings.watchdog.abort,
+    ));
 
     let rest_context = match settings.rest.clone() {
         Some(rest) => {
-            let context = rest::Context::new();
+            let context = rest::Context::new(lifecycle.clone());
+            context.set_log_levels(log_levels);
             let explorer = settings.explorer;
             let server_context = context.clone();
             services.spawn("rest", move |info| {
@@ -444,21 +1037,18 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         None => None,
     };
 
-    if let Some(context) = rest_context.as_ref() {
-        context.set_node_state(NodeState::PreparingStorage)
-    }
+    lifecycle.set(NodeState::PreparingStorage);
     let storage = start_up::prepare_storage(&settings, &init_logger)?;
 
     // TODO: load network module here too (if needed)
 
-    if let Some(context) = rest_context.as_ref() {
-        context.set_node_state(NodeState::PreparingBlock0)
-    }
+    lifecycle.set(NodeState::PreparingBlock0);
     let block0 = start_up::prepare_block_0(
         &settings,
         &storage,
         &init_logger, /* add network to fetch block0 */
     )?;
+    let storage = start_up::check_block0(&settings, storage, &block0, &init_logger)?;
 
     Ok(InitializedNode {
         settings,
@@ -466,26 +1056,46 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         storage,
         logger,
         rest_context,
+        lifecycle,
         services,
+        running_config,
     })
 }
 
-fn main() {
+fn print_error(error: &start_up::Error) {
     use std::error::Error;
 
-    if let Err(error) = start() {
-        eprintln!("{}", error);
-        let mut source = error.source();
-        while let Some(err) = source {
-            eprintln!(" |-> {}", err);
-            source = err.source();
+    eprintln!("{}", error);
+    let mut source = error.source();
+    while let Some(err) = source {
+        eprintln!(" |-> {}", err);
+        source = err.source();
+    }
+}
+
+fn main() {
+    #[cfg(windows)]
+    {
+        if CommandLine::load().windows_service {
+            if let Err(error) = service_manager::windows::run() {
+                eprintln!("cannot run as a Windows service: {}", error);
+                std::process::exit(1);
+            }
+            return;
         }
+    }
 
-        // TODO: https://github.com/rust-lang/rust/issues/43301
-        //
-        // as soon as #43301 is stabilized it would be nice to no use
-        // `exit` but the more appropriate:
-        // https://doc.rust-lang.org/stable/std/process/trait.Termination.html
-        std::process::exit(error.code());
+    // TODO: https://github.com/rust-lang/rust/issues/43301
+    //
+    // as soon as #43301 is stabilized it would be nice to no use
+    // `exit` but the more appropriate:
+    // https://doc.rust-lang.org/stable/std/process/trait.Termination.html
+    match start() {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(error) => {
+            print_error(&error);
+            std::process::exit(error.code());
+        }
     }
 }
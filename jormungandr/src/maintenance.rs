@@ -0,0 +1,410 @@
+//! the maintenance actions, run at the epoch transitions or on a schedule
+//!
+//! The load of the node is predictable at the start of an epoch: the leaders
+//! of the epoch are known and its first slots are not theirs yet. The actions
+//! configured by the operator run then, at the transitions to the epochs
+//! multiple of `every_epochs` and once `delay` has passed, one transition
+//! after the other, and not again when the main chain enters the epoch on
+//! another branch. With a `schedule`, they run at its times instead, in the
+//! quiet hours of the node. A failed action is logged and tried again at the
+//! next run. The export of the chain and the compaction of the storage are
+//! skipped while the storage volume is almost full, both writing a copy of
+//! the chain before the previous one is removed.
+//!
+//! The runs of each action, the one running and for how long, and the space
+//! the storage takes on the disk once the actions ran, are in the stats of
+//! the node.
+
+use crate::{
+    blockcfg::HeaderHash,
+    blockchain::{Blockchain, EpochTransition, Storage, Tip},
+    disk_space::DiskGuard,
+    settings::start::config,
+    start_up,
+    stats_counter::StatsCounter,
+    utils::{
+        async_msg::MessageQueue,
+        task::{self, TokioServiceInfo},
+    },
+};
+use futures::future::{self, Either, Loop};
+use jormungandr_lib::time;
+use slog::Logger;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{prelude::*, timer::Delay};
+
+/// the wait before looking again for the next time of the schedule, when
+/// there is none
+const RETRY_SCHEDULE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// the transitions waiting while the actions of a previous one run
+pub const TRANSITIONS_CAPACITY: usize = 4;
+
+pub struct Maintenance {
+    blockchain: Blockchain,
+    tip: Tip,
+    block0_hash: HeaderHash,
+    /// the path of the storage, to measure the space it takes
+    storage_path: Option<PathBuf>,
+    stats_counter: StatsCounter,
+    disk_guard: DiskGuard,
+    config: config::Maintenance,
+}
+
+/// the actions of a run
+#[derive(Clone)]
+struct Actions {
+    storage: Storage,
+    block0_hash: HeaderHash,
+    storage_path: Option<PathBuf>,
+    stats_counter: StatsCounter,
+    disk_guard: DiskGuard,
+    compact_storage: bool,
+    export_chain: Option<PathBuf>,
+}
+
+impl Maintenance {
+    pub fn new(
+        blockchain: Blockchain,
+        tip: Tip,
+        block0_hash: HeaderHash,
+        storage_path: Option<PathBuf>,
+        stats_counter: StatsCounter,
+        disk_guard: DiskGuard,
+        config: config::Maintenance,
+    ) -> Self {
+        Maintenance {
+            blockchain,
+            tip,
+            block0_hash,
+            storage_path,
+            stats_counter,
+            disk_guard,
+            config,
+        }
+    }
+
+    /// whether there are actions to run
+    pub fn has_actions(config: &config::Maintenance) -> bool {
+        config.compact_storage || config.export_chain.is_some()
+    }
+
+    fn actions(&self) -> Actions {
+        Actions {
+            storage: self.blockchain.storage().clone(),
+            block0_hash: self.block0_hash,
+            storage_path: self.storage_path.clone(),
+            stats_counter: self.stats_counter.clone(),
+            disk_guard: self.disk_guard.clone(),
+            compact_storage: self.config.compact_storage,
+            export_chain: self.config.export_chain.clone(),
+        }
+    }
+
+    /// run the actions at the epoch transitions
+    pub fn run(
+        self,
+        info: TokioServiceInfo,
+        transitions: MessageQueue<EpochTransition>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let every_epochs = self.config.every_epochs.get();
+        let delay: Duration = self.config.delay.into();
+        let actions = self.actions();
+
+        actions.measure_storage(logger.clone()).and_then(move |()| {
+            transitions
+                .filter(move |transition| {
+                    !transition.reentered && transition.epoch % every_epochs == 0
+                })
+                .for_each(move |transition| {
+                    let logger = logger.new(o!("epoch" => transition.epoch));
+                    let timer_logger = logger.clone();
+                    let actions = actions.clone();
+                    let tip = transition.tip.hash();
+
+                    Delay::new(Instant::now() + delay)
+                        .map_err(move |e| error!(timer_logger, "timer error"; "reason" => %e))
+                        .and_then(move |()| actions.run(tip, logger))
+                })
+        })
+    }
+
+    /// run the actions at the times of the schedule, from the tip at the
+    /// time
+    pub fn run_scheduled(self, info: TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let schedule = self
+            .config
+            .schedule
+            .clone()
+            .expect("scheduled maintenance without a schedule");
+        let actions = self.actions();
+        let tip = self.tip;
+        let measure = actions.measure_storage(logger.clone());
+
+        let run = future::loop_fn((), move |()| {
+            let now = SystemTime::now();
+            let timer_logger = logger.clone();
+            // the schedules matching no day are refused with the
+            // configuration, so there is no next time only when the clock is
+            // before the UNIX epoch: the schedule is looked at again later
+            let wait = match schedule.next_after(now) {
+                Some(next) => {
+                    debug!(logger, "next maintenance scheduled"; "at" => %time::SystemTime::from(next));
+                    next.duration_since(now).unwrap_or_default()
+                }
+                None => {
+                    warn!(
+                        logger,
+                        "no next time of the maintenance schedule, looking again in a day";
+                        "schedule" => %schedule,
+                    );
+                    return Either::A(
+                        Delay::new(Instant::now() + RETRY_SCHEDULE)
+                            .map_err(move |e| error!(timer_logger, "timer error"; "reason" => %e))
+                            .map(|()| Loop::Continue(())),
+                    );
+                }
+            };
+            let run_logger = logger.clone();
+            let actions = actions.clone();
+            Either::B(
+                Delay::new(Instant::now() + wait)
+                    .map_err(move |e| error!(timer_logger, "timer error"; "reason" => %e))
+                    .and_then(move |()| tip.get_ref())
+                    .and_then(move |tip_ref| actions.run(tip_ref.hash(), run_logger))
+                    .map(|()| Loop::Continue(())),
+            )
+        });
+        measure.and_then(move |()| run)
+    }
+}
+
+impl Actions {
+    fn run(self, tip: HeaderHash, logger: Logger) -> impl Future<Item = (), Error = ()> {
+        info!(logger, "running the maintenance actions");
+        let compact_logger = logger.clone();
+        let export_storage = self.storage.clone();
+        let block0_hash = self.block0_hash;
+        let export_chain = self.export_chain.clone();
+        let stats_counter = self.stats_counter.clone();
+        let export_stats_counter = stats_counter.clone();
+        let disk_guard = self.disk_guard.clone();
+
+        // compacting writes a new copy of the storage, needing as much space
+        // as the blocks kept
+        let compact = if self.compact_storage && disk_guard.is_low() {
+            warn!(
+                logger,
+                "the storage volume is almost full, not compacting the storage"
+            );
+            Either::B(future::ok(logger))
+        } else if self.compact_storage {
+            let storage = self.storage.clone();
+            Either::A(
+                record(stats_counter, "compact_storage", move || storage.compact()).then(
+                    move |result| {
+                        match result {
+                            Ok(()) => info!(compact_logger, "storage compacted"),
+                            Err(e) => error!(
+                                compact_logger,
+                                "cannot compact the storage";
+                                "reason" => %e,
+                            ),
+                        }
+                        Ok(logger)
+                    },
+                ),
+            )
+        } else {
+            Either::B(future::ok(logger))
+        };
+
+        compact
+            .and_then(move |logger| match export_chain {
+                Some(_) if disk_guard.is_low() => {
+                    warn!(
+                        logger,
+                        "the storage volume is almost full, not exporting the chain"
+                    );
+                    Either::B(future::ok(logger))
+                }
+                Some(path) => Either::A(
+                    export(
+                        export_storage,
+                        export_stats_counter,
+                        block0_hash,
+                        tip,
+                        path,
+                        logger.clone(),
+                    )
+                    .map(move |()| logger),
+                ),
+                None => Either::B(future::ok(logger)),
+            })
+            .and_then(move |logger| self.measure_storage(logger))
+    }
+
+    /// record the space the storage takes on the disk, walking the
+    /// directory of the storage in a blocking section
+    fn measure_storage(&self, logger: Logger) -> impl Future<Item = (), Error = ()> {
+        let path = match &self.storage_path {
+            Some(path) => path.clone(),
+            None => return Either::B(future::ok(())),
+        };
+        let stats_counter = self.stats_counter.clone();
+        Either::A(task::blocking(move || {
+            match disk_usage(&path) {
+                Ok(bytes) => {
+                    debug!(logger, "storage size measured"; "bytes" => bytes);
+                    stats_counter.set_storage_size(bytes)
+                }
+                Err(e) => warn!(
+                    logger,
+                    "cannot measure the size of the storage";
+                    "path" => %path.display(),
+                    "reason" => %e,
+                ),
+            }
+            Ok(())
+        }))
+    }
+}
+
+/// run the action, recording its progress and its duration in the stats
+fn record<F, R>(
+    stats_counter: StatsCounter,
+    action: &'static str,
+    run: R,
+) -> impl Future<Item = F::Item, Error = F::Error>
+where
+    R: FnOnce() -> F,
+    F: Future,
+{
+    future::lazy(move || {
+        stats_counter.maintenance_started(action);
+        let started = Instant::now();
+        run().then(move |result| {
+            stats_counter.maintenance_completed(action, started.elapsed(), result.is_ok());
+            result
+        })
+    })
+}
+
+fn export(
+    storage: Storage,
+    stats_counter: StatsCounter,
+    block0_hash: HeaderHash,
+    tip: HeaderHash,
+    path: PathBuf,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let archive_path = path.clone();
+    let progress_stats_counter = stats_counter.clone();
+    record(stats_counter, "export_chain", move || {
+        start_up::write_archive(
+            storage,
+            block0_hash,
+            tip,
+            archive_path,
+            move |done, total| {
+                progress_stats_counter.maintenance_progress("export_chain", done, total)
+            },
+        )
+    })
+    .then(move |result| {
+        match result {
+            Ok(blocks) => info!(
+                logger,
+                "chain exported";
+                "path" => %path.display(),
+                "blocks" => blocks,
+            ),
+            Err(e) => error!(
+                logger,
+                "cannot export the chain";
+                "path" => %path.display(),
+                "reason" => %e,
+            ),
+        }
+        Ok(())
+    })
+}
+
+/// the size of the files under the path
+fn disk_usage(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut bytes = 0;
+    for entry in fs::read_dir(path)? {
+        bytes += disk_usage(&entry?.path())?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "jormungandr-maintenance-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn maintenance_without_actions_is_not_run() {
+        let mut config = config::Maintenance::default();
+        assert!(!Maintenance::has_actions(&config));
+        config.export_chain = Some(PathBuf::from("/var/lib/jormungandr/chain.archive"));
+        assert!(Maintenance::has_actions(&config));
+        config.export_chain = None;
+        config.compact_storage = true;
+        assert!(Maintenance::has_actions(&config));
+    }
+
+    #[test]
+    fn disk_usage_counts_the_files_of_the_subdirectories() {
+        let dir = temp_dir("disk-usage");
+        fs::create_dir_all(dir.join("blocks")).unwrap();
+        fs::write(dir.join("conf"), vec![0; 10]).unwrap();
+        fs::write(dir.join("blocks").join("db"), vec![0; 32]).unwrap();
+
+        assert_eq!(disk_usage(&dir).unwrap(), 42);
+        assert_eq!(disk_usage(&dir.join("conf")).unwrap(), 10);
+        assert!(disk_usage(&dir.join("missing")).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn runs_of_an_action_are_recorded() {
+        let stats_counter = StatsCounter::default();
+        let succeeded = record(stats_counter.clone(), "compact_storage", || {
+            future::ok::<_, ()>(())
+        });
+        assert_eq!(succeeded.wait(), Ok(()));
+        let failed = record(stats_counter.clone(), "compact_storage", || {
+            future::err::<(), _>(())
+        });
+        assert_eq!(failed.wait(), Err(()));
+
+        let stats = stats_counter.maintenance_stats();
+        assert_eq!(stats.len(), 1);
+        let (action, stats) = stats[0];
+        assert_eq!(action, "compact_storage");
+        assert_eq!((stats.succeeded, stats.failed), (1, 1));
+        assert!(stats.running_since.is_none());
+        assert!(stats.last_completed.is_some());
+    }
+}
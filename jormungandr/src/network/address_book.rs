@@ -0,0 +1,225 @@
+//! the address book of the nodes the node connected to
+//!
+//! The nodes are recorded once the node connected to them, with the last time
+//! they were connected to and the last time they were seen in the gossips of
+//! the peers. The gossips are not authenticated, so they do not add nodes nor
+//! change their addresses. The book is saved periodically and when the node
+//! shuts down, and loaded on the next start: the node gossips with its nodes
+//! in addition to the trusted peers, and bootstraps from the addresses of
+//! the trusted peers it recorded when none of the configured ones can be
+//! reached. Only the nodes most recently connected to are kept.
+//!
+//! The file is a JSON array of the nodes. It is written to a temporary file
+//! first and then renamed, so an interrupted write leaves the previous file
+//! intact.
+
+use super::{p2p::Id, topology_file};
+use crate::settings::start::{config, network::TrustedPeer};
+use crate::utils::fs::temp_path;
+use jormungandr_lib::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// the number of nodes kept in the book
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    id: Id,
+    address: SocketAddr,
+    last_seen: SystemTime,
+    /// absent from the nodes only seen in the gossips, which the earlier
+    /// versions recorded too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_dialed: Option<SystemTime>,
+}
+
+pub struct AddressBook {
+    path: PathBuf,
+    entries: Mutex<HashMap<Id, Entry>>,
+}
+
+impl AddressBook {
+    /// load the book from the file, an absent file has no nodes
+    pub fn open(path: PathBuf, logger: &Logger) -> Self {
+        let entries = read(&path, logger)
+            .into_iter()
+            .map(|entry| (entry.id, entry))
+            .collect();
+        AddressBook {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the nodes of the book, the ones last connected to first
+    pub fn peers(&self) -> Vec<TrustedPeer> {
+        let entries = self.entries.lock().unwrap();
+        to_peers(entries.values().cloned().collect())
+    }
+
+    /// a node of the book gossiped at the address it was connected to
+    pub fn record_seen(&self, id: Id, address: SocketAddr) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&id) {
+            if entry.address == address {
+                entry.last_seen = SystemTime::now();
+            }
+        }
+    }
+
+    /// the node connected to the node at the address
+    pub fn record_dialed(&self, id: Id, address: SocketAddr) {
+        let mut entries = self.entries.lock().unwrap();
+        let now = SystemTime::now();
+        if !entries.contains_key(&id) && entries.len() >= CAPACITY {
+            let oldest = entries
+                .values()
+                .min_by_key(|entry| (entry.last_dialed, entry.last_seen))
+                .map(|entry| entry.id);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            id,
+            Entry {
+                id,
+                address,
+                last_seen: now,
+                last_dialed: Some(now),
+            },
+        );
+    }
+
+    /// returns how many nodes were saved
+    pub fn save(&self) -> io::Result<usize> {
+        let entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        let tmp_path = temp_path(&self.path);
+        {
+            let file = fs::File::create(&tmp_path)?;
+            serde_json::to_writer(&file, &entries)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(entries.len())
+    }
+}
+
+/// the nodes saved in the file, the ones last connected to first
+pub fn load(path: &Path, logger: &Logger) -> Vec<TrustedPeer> {
+    to_peers(read(path, logger))
+}
+
+fn read(path: &Path, logger: &Logger) -> Vec<Entry> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!(
+                logger,
+                "cannot read the address book";
+                "path" => %path.display(),
+                "reason" => %e,
+            );
+            return Vec::new();
+        }
+    };
+    let entries: Vec<Entry> =
+        serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+            warn!(
+                logger,
+                "invalid address book, starting with an empty one";
+                "path" => %path.display(),
+                "reason" => %e,
+            );
+            Vec::new()
+        });
+    entries
+        .into_iter()
+        .filter(|entry| entry.last_dialed.is_some())
+        .collect()
+}
+
+fn to_peers(mut entries: Vec<Entry>) -> Vec<TrustedPeer> {
+    entries.sort_by_key(|entry| Reverse((entry.last_dialed, entry.last_seen)));
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let address = topology_file::multiaddr(entry.address).parse().ok()?;
+            Some(TrustedPeer::from(config::TrustedPeer {
+                address: config::Address(address),
+                id: entry.id,
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> AddressBook {
+        let logger = Logger::root(slog::Discard, o!());
+        AddressBook::open(
+            std::env::temp_dir().join("jormungandr-address-book-absent.json"),
+            &logger,
+        )
+    }
+
+    fn id() -> Id {
+        poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into()
+    }
+
+    fn address(port: u16) -> SocketAddr {
+        ([192, 0, 2, 1], port).into()
+    }
+
+    fn addresses(book: &AddressBook) -> Vec<(Id, SocketAddr)> {
+        let entries = book.entries.lock().unwrap();
+        entries
+            .values()
+            .map(|entry| (entry.id, entry.address))
+            .collect()
+    }
+
+    #[test]
+    fn gossiped_nodes_are_not_recorded() {
+        let book = book();
+        book.record_seen(id(), address(3000));
+        assert!(addresses(&book).is_empty());
+    }
+
+    #[test]
+    fn nodes_connected_to_are_recorded() {
+        let book = book();
+        let node = id();
+        book.record_dialed(node, address(3000));
+        assert_eq!(addresses(&book), vec![(node, address(3000))]);
+        assert_eq!(book.peers().len(), 1);
+    }
+
+    #[test]
+    fn gossips_do_not_change_the_address() {
+        let book = book();
+        let node = id();
+        book.record_dialed(node, address(3000));
+        book.record_seen(node, address(3001));
+        assert_eq!(addresses(&book), vec![(node, address(3000))]);
+        book.record_dialed(node, address(3001));
+        assert_eq!(addresses(&book), vec![(node, address(3001))]);
+    }
+}
@@ -0,0 +1,248 @@
+//! the audit log of the P2P connections
+//!
+//! Every connection attempt to or from the node, and every close of a
+//! connection, is appended to the file as a line of JSON:
+//!
+//! ```json
+//! {"time":"2019-11-05T09:45:02.791829+00:00","direction":"outbound","peer_id":"...","address":"10.0.0.2:3000","outcome":"connected"}
+//! ```
+//!
+//! The ID of the peer of an inbound connection is not known when the
+//! connection is accepted, it is in the entry of the close of the
+//! connection if the peer subscribed.
+//!
+//! The lines are written by a thread of their own, the connections do not
+//! wait on the file. Once the file is over `MAX_FILE_SIZE`, it is renamed
+//! with a `.1` suffix, replacing the previous one, and a new file is
+//! started.
+
+use super::p2p::Id;
+use jormungandr_lib::time::SystemTime;
+use serde::Serialize;
+use slog::Logger;
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// the size of the file over which it is rotated
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// the number of lines waiting to be written, the lines recorded while the
+/// writer lags behind are dropped
+const QUEUE_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// an inbound connection is accepted
+    Accepted,
+    /// an outbound connection is established with the peer
+    Connected,
+    /// the connection could not be established
+    Failed,
+    /// the outbound connection was canceled before it was established
+    Canceled,
+    /// an established connection is closed
+    Closed,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: SystemTime,
+    direction: Direction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_id: Option<String>,
+    address: SocketAddr,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+pub struct AuditLog {
+    lines: Mutex<mpsc::SyncSender<Vec<u8>>>,
+    logger: Logger,
+}
+
+impl AuditLog {
+    /// open the file to append to, created if missing, and start the thread
+    /// writing to it
+    pub fn open(path: &Path, logger: Logger) -> io::Result<Self> {
+        let writer = Writer::open(path, MAX_FILE_SIZE)?;
+        let (lines, queue) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let writer_logger = logger.clone();
+        thread::Builder::new()
+            .name("audit_log".to_owned())
+            .spawn(move || {
+                let mut writer = writer;
+                for line in queue {
+                    if let Err(e) = writer.write(&line) {
+                        warn!(writer_logger, "cannot write to the audit log"; "reason" => %e);
+                    }
+                }
+            })?;
+        Ok(AuditLog {
+            lines: Mutex::new(lines),
+            logger,
+        })
+    }
+
+    pub fn record(
+        &self,
+        direction: Direction,
+        peer_id: Option<Id>,
+        address: SocketAddr,
+        outcome: Outcome,
+        reason: Option<&str>,
+    ) {
+        let entry = Entry {
+            time: SystemTime::now(),
+            direction,
+            peer_id: peer_id.map(|id| id.to_string()),
+            address,
+            outcome,
+            reason,
+        };
+        let line = match entry.to_line() {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(self.logger, "cannot serialize the audit log entry"; "reason" => %e);
+                return;
+            }
+        };
+        let res = self.lines.lock().unwrap().try_send(line);
+        if let Err(mpsc::TrySendError::Full(_)) = res {
+            warn!(
+                self.logger,
+                "the audit log lags behind, an entry is dropped"
+            );
+        }
+    }
+}
+
+impl<'a> Entry<'a> {
+    fn to_line(&self) -> serde_json::Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// the file of the audit log, rotated once over its maximum size
+struct Writer {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl Writer {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Writer {
+            path: path.to_owned(),
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    /// append the line at once, so a line is never split between two files
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, rotated_path(&self.path))?;
+        *self = Writer::open(&self.path, self.max_size)?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(".1");
+    rotated.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-audit-log-{}-{}.log",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated_path(&path));
+        path
+    }
+
+    #[test]
+    fn file_is_rotated_once_over_its_size() {
+        let path = temp_path("rotation");
+        let line = [b'x'; 39].iter().chain(b"\n").cloned().collect::<Vec<_>>();
+        let mut writer = Writer::open(&path, 100).unwrap();
+        for _ in 0..3 {
+            writer.write(&line).unwrap();
+        }
+
+        assert_eq!(fs::read(rotated_path(&path)).unwrap().len(), 80);
+        assert_eq!(fs::read(&path).unwrap().len(), 40);
+
+        // the size of the file is known when it is opened again
+        let mut writer = Writer::open(&path, 100).unwrap();
+        writer.write(&line).unwrap();
+        writer.write(&line).unwrap();
+        assert_eq!(fs::read(rotated_path(&path)).unwrap().len(), 80);
+        assert_eq!(fs::read(&path).unwrap().len(), 40);
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated_path(&path));
+    }
+
+    #[test]
+    fn log_in_a_missing_directory_cannot_be_opened() {
+        let path = temp_path("missing").join("audit.log");
+        let logger = Logger::root(slog::Discard, o!());
+        assert!(AuditLog::open(&path, logger).is_err());
+    }
+
+    #[test]
+    fn closed_entry_has_the_peer_and_the_reason() {
+        let entry = Entry {
+            time: SystemTime::now(),
+            direction: Direction::Inbound,
+            peer_id: Some("peer".to_owned()),
+            address: "10.0.0.7:40112".parse().unwrap(),
+            outcome: Outcome::Closed,
+            reason: Some("connection reset by peer"),
+        };
+        let line = entry.to_line().unwrap();
+        assert_eq!(line.last(), Some(&b'\n'));
+        let json: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(json["direction"], "inbound");
+        assert_eq!(json["peer_id"], "peer");
+        assert_eq!(json["outcome"], "closed");
+        assert_eq!(json["reason"], "connection reset by peer");
+    }
+}
@@ -0,0 +1,118 @@
+//! the recent blocks served to the peers
+//!
+//! The syncing peers fetch the same recent blocks from the node, each request
+//! being answered from the storage by the client task. The blocks streamed to
+//! the peers are kept in an LRU cache, and the requests of blocks or headers
+//! all found in the cache are answered without the client task.
+
+use crate::blockcfg::{Block, Header, HeaderHash};
+use crate::intercom::{self, ReplyStream};
+use crate::stats_counter::StatsCounter;
+use chain_core::property::Block as _;
+use futures::prelude::*;
+use linked_hash_map::LinkedHashMap;
+use network_core::error as core_error;
+use slog::Logger;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub struct BlockCache {
+    entries: Arc<Mutex<LinkedHashMap<HeaderHash, Block>>>,
+    capacity: usize,
+    stats_counter: StatsCounter,
+}
+
+impl BlockCache {
+    /// the cache is disabled with a capacity of 0
+    pub fn new(capacity: usize, stats_counter: StatsCounter) -> Self {
+        BlockCache {
+            entries: Arc::new(Mutex::new(LinkedHashMap::new())),
+            capacity,
+            stats_counter,
+        }
+    }
+
+    /// the blocks, in the order of the IDs, if they are all in the cache
+    pub fn get_all(&self, ids: &[HeaderHash]) -> Option<Vec<Block>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let blocks: Vec<Option<Block>> = ids
+            .iter()
+            .map(|id| entries.get_refresh(id).map(|block| block.clone()))
+            .collect();
+        let hits = blocks.iter().filter(|block| block.is_some()).count() as u64;
+        self.stats_counter.add_block_cache_hits(hits);
+        self.stats_counter
+            .add_block_cache_misses(ids.len() as u64 - hits);
+        blocks.into_iter().collect()
+    }
+
+    /// the headers of the blocks, in the order of the IDs, if the blocks are
+    /// all in the cache
+    pub fn get_all_headers(&self, ids: &[HeaderHash]) -> Option<Vec<Header>> {
+        self.get_all(ids)
+            .map(|blocks| blocks.into_iter().map(|block| block.header).collect())
+    }
+
+    fn insert(&self, block: &Block) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get_refresh(&block.id()).is_some() {
+            return;
+        }
+        entries.insert(block.id(), block.clone());
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+}
+
+/// reply to a peer with items found in the cache
+pub fn cached_reply<T>(items: Vec<T>, logger: Logger) -> ReplyStream<T, core_error::Error> {
+    let (mut handle, stream) = intercom::stream_reply(logger);
+    for item in items {
+        // the stream is returned below, it cannot be dropped yet
+        let _ = handle.send(item);
+    }
+    handle.close();
+    stream
+}
+
+/// the blocks fetched by the client task for a peer, cached as they are
+/// streamed to it
+#[must_use = "streams do nothing unless polled"]
+pub struct CachingStream {
+    inner: ReplyStream<Block, core_error::Error>,
+    cache: Option<BlockCache>,
+}
+
+impl CachingStream {
+    pub fn new(inner: ReplyStream<Block, core_error::Error>, cache: BlockCache) -> Self {
+        CachingStream {
+            inner,
+            cache: Some(cache),
+        }
+    }
+
+    /// the blocks all found in the cache, not to be cached again
+    pub fn cached(inner: ReplyStream<Block, core_error::Error>) -> Self {
+        CachingStream { inner, cache: None }
+    }
+}
+
+impl Stream for CachingStream {
+    type Item = Block;
+    type Error = core_error::Error;
+
+    fn poll(&mut self) -> Poll<Option<Block>, core_error::Error> {
+        let block = try_ready!(self.inner.poll());
+        if let (Some(cache), Some(block)) = (self.cache.as_ref(), block.as_ref()) {
+            cache.insert(block);
+        }
+        Ok(Async::Ready(block))
+    }
+}
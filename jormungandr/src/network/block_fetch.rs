@@ -0,0 +1,370 @@
+//! the distribution of the block fetches over the peers
+//!
+//! The blocks requested by the block task are split across the peers with the
+//! highest throughput, each chunk sized for the peer it is solicited from. The
+//! blocks a peer did not deliver, or not within the pull inactivity timeout,
+//! are solicited again from the peers not tried yet. Once none of the blocks
+//! of a request is pending anymore, the block task is replied the ones which
+//! could not be fetched from any peer. A block requested again while it is
+//! pending is not solicited twice: the requests wait for it together.
+//!
+//! The chunks are only solicited from the peers subscribed to the
+//! solicitations. The chunks a peer cannot queue are solicited from the other
+//! peers, and abandoned when no peer is left.
+
+use super::{p2p::Id, GlobalState, GlobalStateR};
+use crate::blockcfg::{Block, HeaderHash};
+use crate::intercom::ReplyHandle;
+use chain_core::property::Block as _;
+use futures::prelude::*;
+use network_core::server::request_stream::{MapResponse, ProcessingError};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// the blocks being fetched from the peers
+#[derive(Default)]
+pub struct BlockFetches {
+    pending: Mutex<HashMap<HeaderHash, Pending>>,
+}
+
+struct Pending {
+    /// the requests waiting for the block
+    fetches: Vec<Arc<Fetch>>,
+    /// the peers the block was solicited from, the last one first
+    tried: Vec<Id>,
+    solicited_at: Instant,
+}
+
+/// a request of the block task, replied when it is dropped with the last of
+/// its pending blocks
+struct Fetch {
+    not_fetched: Mutex<Vec<HeaderHash>>,
+    reply: Option<ReplyHandle<Vec<HeaderHash>>>,
+}
+
+impl Drop for Fetch {
+    fn drop(&mut self) {
+        if let Some(reply) = self.reply.take() {
+            let not_fetched = mem::replace(self.not_fetched.get_mut().unwrap(), Vec::new());
+            reply.reply_ok(not_fetched);
+        }
+    }
+}
+
+impl BlockFetches {
+    /// start the request, returning the blocks to solicit, the ones not
+    /// pending for an earlier request
+    fn start(&self, ids: &[HeaderHash], reply: ReplyHandle<Vec<HeaderHash>>) -> Vec<HeaderHash> {
+        let fetch = Arc::new(Fetch {
+            not_fetched: Mutex::new(Vec::new()),
+            reply: Some(reply),
+        });
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        let mut to_solicit = Vec::new();
+        for id in ids {
+            match pending.entry(*id) {
+                Entry::Occupied(mut entry) => entry.get_mut().fetches.push(fetch.clone()),
+                Entry::Vacant(entry) => {
+                    entry.insert(Pending {
+                        fetches: vec![fetch.clone()],
+                        tried: Vec::new(),
+                        solicited_at: now,
+                    });
+                    to_solicit.push(*id);
+                }
+            }
+        }
+        to_solicit
+    }
+
+    fn solicited(&self, node_id: Id, ids: &[HeaderHash]) {
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        for id in ids {
+            if let Some(block) = pending.get_mut(id) {
+                block.tried.insert(0, node_id);
+                block.solicited_at = now;
+            }
+        }
+    }
+
+    pub fn received(&self, id: &HeaderHash) {
+        self.pending.lock().unwrap().remove(id);
+    }
+
+    /// the blocks last solicited from the peer still pending, and the peers
+    /// they were already solicited from
+    fn undelivered(&self, node_id: Id, ids: &[HeaderHash]) -> (Vec<HeaderHash>, HashSet<Id>) {
+        let pending = self.pending.lock().unwrap();
+        let mut tried = HashSet::new();
+        let undelivered = ids
+            .iter()
+            .filter(|id| match pending.get(id) {
+                Some(block) if block.tried.first() == Some(&node_id) => {
+                    tried.extend(block.tried.iter().cloned());
+                    true
+                }
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        (undelivered, tried)
+    }
+
+    /// the blocks solicited longer than `timeout` ago, by the peer they were
+    /// last solicited from
+    fn expired(&self, timeout: Duration) -> HashMap<Id, Vec<HeaderHash>> {
+        let pending = self.pending.lock().unwrap();
+        let mut expired = HashMap::new();
+        for (id, block) in pending.iter() {
+            if let Some(node_id) = block.tried.first() {
+                if block.solicited_at.elapsed() > timeout {
+                    expired.entry(*node_id).or_insert_with(Vec::new).push(*id);
+                }
+            }
+        }
+        expired
+    }
+
+    fn abandon(&self, ids: &[HeaderHash]) {
+        let mut pending = self.pending.lock().unwrap();
+        for id in ids {
+            if let Some(block) = pending.remove(id) {
+                for fetch in block.fetches {
+                    fetch.not_fetched.lock().unwrap().push(*id);
+                }
+            }
+        }
+    }
+}
+
+/// fetch the blocks requested by the block task
+pub fn fetch_blocks(
+    state: &GlobalState,
+    ids: Vec<HeaderHash>,
+    reply: ReplyHandle<Vec<HeaderHash>>,
+) {
+    let ids = state.block_fetches.start(&ids, reply);
+    distribute(state, ids, &HashSet::new());
+}
+
+/// solicit again from other peers the blocks a peer did not deliver, once
+/// the fetch from this peer is completed or failed
+pub fn retry(state: &GlobalState, node_id: Id, ids: &[HeaderHash]) {
+    let (undelivered, tried) = state.block_fetches.undelivered(node_id, ids);
+    if !undelivered.is_empty() {
+        debug!(
+            state.logger(),
+            "soliciting the blocks not delivered by the peer from other peers";
+            "node_id" => %node_id,
+            "count" => undelivered.len(),
+        );
+        distribute(state, undelivered, &tried);
+    }
+}
+
+/// retry the blocks not delivered within the pull inactivity timeout, the
+/// ones solicited from the peers no fetch completion is known of
+pub fn retry_expired(state: &GlobalState) {
+    let expired = state
+        .block_fetches
+        .expired(state.config.pull_inactivity_timeout);
+    for (node_id, ids) in expired {
+        retry(state, node_id, &ids);
+    }
+}
+
+fn distribute(state: &GlobalState, mut ids: Vec<HeaderHash>, except: &HashSet<Id>) {
+    let mut except = except.clone();
+    while !ids.is_empty() {
+        let peers = state
+            .peers
+            .peers_for_block_fetch(state.config.block_fetch_peers, &except);
+        if peers.is_empty() {
+            warn!(
+                state.logger(),
+                "no peers to fetch the blocks from";
+                "count" => ids.len(),
+            );
+            state.block_fetches.abandon(&ids);
+            return;
+        }
+        let mut unsent = Vec::new();
+        for (node_id, chunk) in assign(&peers, &ids) {
+            state.block_fetches.solicited(node_id, &chunk);
+            if let Err(chunk) = state.peers.solicit_block_fetch(node_id, chunk) {
+                except.insert(node_id);
+                unsent.extend(chunk);
+            }
+        }
+        ids = unsent;
+    }
+}
+
+/// split the blocks in chunks over the peers, in turn, each chunk of the
+/// size of the peer it is assigned to
+fn assign<P: Copy, T: Clone>(peers: &[(P, usize)], ids: &[T]) -> Vec<(P, Vec<T>)> {
+    let mut chunks = Vec::new();
+    let mut remaining = ids;
+    for &(peer, chunk_size) in peers.iter().cycle() {
+        if remaining.is_empty() {
+            break;
+        }
+        let (chunk, rest) = remaining.split_at(chunk_size.max(1).min(remaining.len()));
+        remaining = rest;
+        chunks.push((peer, chunk.to_vec()));
+    }
+    chunks
+}
+
+/// a sink of the blocks received from a peer, recording them as fetched
+#[must_use = "sinks do nothing unless polled"]
+pub struct FetchedSink<S> {
+    inner: S,
+    global_state: GlobalStateR,
+}
+
+impl<S> FetchedSink<S> {
+    pub fn new(inner: S, global_state: GlobalStateR) -> Self {
+        FetchedSink {
+            inner,
+            global_state,
+        }
+    }
+}
+
+impl<S> Sink for FetchedSink<S>
+where
+    S: Sink<SinkItem = Block>,
+{
+    type SinkItem = Block;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, block: Block) -> StartSend<Block, S::SinkError> {
+        let id = block.id();
+        let res = self.inner.start_send(block)?;
+        if res.is_ready() {
+            self.global_state.block_fetches.received(&id);
+        }
+        Ok(res)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.inner.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.inner.close()
+    }
+}
+
+impl<S> MapResponse for FetchedSink<S>
+where
+    S: MapResponse,
+{
+    type Response = S::Response;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn on_stream_termination(&mut self, res: Result<(), ProcessingError>) -> Self::ResponseFuture {
+        self.inner.on_stream_termination(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intercom::{self, ReplyFuture};
+    use futures::future;
+    use jormungandr_lib::crypto::hash::Hash;
+    use slog::Logger;
+
+    fn hash(seed: u8) -> HeaderHash {
+        Hash::from([seed; 32]).into_hash()
+    }
+
+    fn start(
+        fetches: &BlockFetches,
+        ids: &[HeaderHash],
+    ) -> (
+        Vec<HeaderHash>,
+        ReplyFuture<Vec<HeaderHash>, intercom::Error>,
+    ) {
+        let (reply, future) = intercom::unary_reply(Logger::root(slog::Discard, o!()));
+        (fetches.start(ids, reply), future)
+    }
+
+    #[test]
+    fn block_pending_for_an_earlier_request_is_not_solicited_again() {
+        let fetches = BlockFetches::default();
+        let (to_solicit, _first) = start(&fetches, &[hash(1), hash(2)]);
+        assert_eq!(to_solicit, vec![hash(1), hash(2)]);
+        let (to_solicit, _second) = start(&fetches, &[hash(2), hash(3)]);
+        assert_eq!(to_solicit, vec![hash(3)]);
+    }
+
+    #[test]
+    fn overlapping_requests_are_replied_once_their_blocks_are_received() {
+        let fetches = BlockFetches::default();
+        let (_, mut first) = start(&fetches, &[hash(1), hash(2)]);
+        let (_, mut second) = start(&fetches, &[hash(2), hash(3)]);
+
+        fetches.received(&hash(1));
+        fetches.received(&hash(2));
+        // polled in a task, to be notified of the replies
+        future::lazy(|| {
+            assert!(first.poll().unwrap().is_ready());
+            assert!(second.poll().unwrap().is_not_ready());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        fetches.abandon(&[hash(3)]);
+        assert_eq!(second.wait().unwrap(), vec![hash(3)]);
+    }
+
+    #[test]
+    fn abandoned_block_is_not_fetched_for_every_request() {
+        let fetches = BlockFetches::default();
+        let (_, first) = start(&fetches, &[hash(1), hash(2)]);
+        let (_, second) = start(&fetches, &[hash(2)]);
+
+        fetches.received(&hash(1));
+        fetches.abandon(&[hash(2)]);
+
+        assert_eq!(first.wait().unwrap(), vec![hash(2)]);
+        assert_eq!(second.wait().unwrap(), vec![hash(2)]);
+    }
+
+    #[test]
+    fn chunks_are_sized_for_their_peer() {
+        let ids: Vec<u32> = (0..10).collect();
+        let chunks = assign(&[('a', 4), ('b', 2)], &ids);
+        assert_eq!(
+            chunks,
+            vec![
+                ('a', vec![0, 1, 2, 3]),
+                ('b', vec![4, 5]),
+                ('a', vec![6, 7, 8, 9]),
+            ]
+        );
+    }
+
+    #[test]
+    fn fewer_blocks_than_peers() {
+        let chunks = assign(&[('a', 4), ('b', 4), ('c', 4)], &[0, 1]);
+        assert_eq!(chunks, vec![('a', vec![0, 1])]);
+        assert!(assign::<char, u32>(&[('a', 4)], &[]).is_empty());
+    }
+
+    #[test]
+    fn every_block_is_assigned_once() {
+        let ids: Vec<u32> = (0..100).collect();
+        let chunks = assign(&[('a', 7), ('b', 0), ('c', 16)], &ids);
+        let assigned: Vec<u32> = chunks.into_iter().flat_map(|(_, chunk)| chunk).collect();
+        assert_eq!(assigned, ids);
+    }
+}
@@ -1,19 +1,23 @@
 use super::{grpc, BlockConfig};
 use crate::blockcfg::{Block, HeaderHash};
-use crate::blockchain::{self, Blockchain, Error as BlockchainError, PreCheckedHeader, Ref, Tip};
+use crate::blockchain::{
+    self, Blockchain, Error as BlockchainError, HeaderVerification, PreCheckedHeader, Ref, Tip,
+};
 use crate::settings::start::network::Peer;
 use chain_core::property::HasHeader;
+use futures::sync::oneshot;
 use network_core::client::{BlockService, Client as _};
 use network_core::error::Error as NetworkError;
 use network_grpc::client::Connection;
 use slog::Logger;
 use thiserror::Error;
-use tokio::prelude::*;
-use tokio::runtime::Runtime;
+use tokio::prelude::{future::Either, *};
+use tokio::runtime::{Runtime, TaskExecutor};
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -35,14 +39,28 @@ pub enum Error {
     BlockMissingParent(HeaderHash),
     #[error("failed to apply block to the blockchain")]
     ApplyBlockFailed { source: BlockchainError },
+    #[error("failed to store the block")]
+    StoreBlockFailed { source: BlockchainError },
     #[error("failed to select the new tip")]
     ChainSelectionFailed { source: BlockchainError },
+    #[error("received block {received} does not match the trusted checkpoint {expected} at chain length {chain_length}")]
+    CheckpointMismatch {
+        chain_length: u32,
+        expected: HeaderHash,
+        received: HeaderHash,
+    },
+    #[error("the bootstrap stopped before reaching the trusted checkpoint at chain length {0}")]
+    CheckpointNotReached(u32),
 }
 
+/// the hashes of the trusted checkpoints, by chain length
+pub type TrustedCheckpoints = BTreeMap<u32, HeaderHash>;
+
 pub fn bootstrap_from_peer(
     peer: Peer,
     blockchain: Blockchain,
     branch: Tip,
+    checkpoints: Arc<TrustedCheckpoints>,
     logger: Logger,
 ) -> Result<Arc<Ref>, Error> {
     info!(logger, "connecting to bootstrap peer {}", peer.connection);
@@ -51,8 +69,9 @@ pub fn bootstrap_from_peer(
 
     let blockchain2 = blockchain.clone();
     let logger2 = logger.clone();
+    let executor = runtime.executor();
 
-    let bootstrap = grpc::connect(peer.address(), None, runtime.executor())
+    let bootstrap = grpc::connect(peer.address(), None, None, runtime.executor())
         .map_err(|e| Error::Connect { source: e })
         .and_then(|client: Connection<BlockConfig>| {
             client
@@ -66,21 +85,44 @@ pub fn bootstrap_from_peer(
             client
                 .pull_blocks_to_tip(&[tip_hash])
                 .map_err(|e| Error::PullRequestFailed { source: e })
-                .and_then(move |stream| bootstrap_from_stream(blockchain, tip, stream, logger))
+                .and_then(move |stream| {
+                    bootstrap_from_stream(blockchain, tip, stream, checkpoints, executor, logger)
+                })
         })
         .and_then(move |tip| {
             blockchain::process_new_ref(logger2, blockchain2, branch, tip.clone())
                 .map_err(|e| Error::ChainSelectionFailed { source: e })
-                .map(|()| tip)
+                .map(|_| tip)
         });
 
     runtime.block_on_all(bootstrap)
 }
 
+/// number of blocks whose headers are verified together
+const VERIFICATION_BATCH: usize = 64;
+/// number of batches verified ahead of the batch applied on the ledger
+const VERIFICATION_PIPELINE_DEPTH: usize = 2;
+
+/// The headers are verified in parallel on the runtime's thread pool, one
+/// batch of blocks ahead of their sequential application on the ledger. A
+/// header is verified with the leadership of the last block applied when its
+/// batch is picked, which is the leadership of its epoch unless an epoch
+/// transition happens in between: such headers and the ones whose
+/// verification failed are verified again when applied.
+///
+/// The headers of the blocks up to the last trusted checkpoint are not
+/// verified, only their linkage: the blocks at the chain length of a
+/// checkpoint must have its hash and the bootstrap fails if the peer
+/// stops before the last one. Such blocks are only stored once the next
+/// checkpoint is received, so an interrupted bootstrap does not leave
+/// blocks in the storage that were neither verified nor linked to a
+/// checkpoint.
 fn bootstrap_from_stream<S>(
     blockchain: Blockchain,
     tip: Arc<Ref>,
     stream: S,
+    checkpoints: Arc<TrustedCheckpoints>,
+    executor: TaskExecutor,
     logger: Logger,
 ) -> impl Future<Item = Arc<Ref>, Error = Error>
 where
@@ -88,16 +130,201 @@ where
     S::Error: Debug,
 {
     let fold_logger = logger.clone();
+    let last_applied = Arc::new(Mutex::new(tip.clone()));
+    let last_applied_2 = Arc::clone(&last_applied);
+    let start_hash = tip.hash();
+    let last_checkpoint = checkpoints.keys().next_back().cloned();
+    let fold_checkpoints = Arc::clone(&checkpoints);
+    let progress = Progress {
+        tip,
+        pending: Vec::new(),
+    };
+
     stream
         .map_err(|e| Error::PullStreamFailed { source: e })
-        .fold(tip, move |_, block| {
-            handle_block(blockchain.clone(), block, fold_logger.clone())
+        .chunks(VERIFICATION_BATCH)
+        .map(move |blocks| {
+            let parent = last_applied.lock().unwrap().clone();
+            verify_headers(blocks, parent, &checkpoints, &executor)
+        })
+        .buffered(VERIFICATION_PIPELINE_DEPTH)
+        .fold(progress, move |progress, verified_blocks| {
+            let blockchain = blockchain.clone();
+            let logger = fold_logger.clone();
+            let checkpoints = Arc::clone(&fold_checkpoints);
+            let last_applied = Arc::clone(&last_applied_2);
+            stream::iter_ok(verified_blocks)
+                .fold(progress, move |progress, (block, verification)| {
+                    let blockchain = blockchain.clone();
+                    match verification {
+                        HeaderVerification::Trusted => {
+                            let chain_length = u32::from(block.header.chain_length());
+                            let checkpoint = checkpoints.contains_key(&chain_length);
+                            Either::A(apply_trusted_block(blockchain, progress, block, checkpoint))
+                        }
+                        // the blocks past the last checkpoint follow the
+                        // stored block of the checkpoint, none is pending
+                        verification => Either::B(
+                            handle_verified_block(blockchain, block, verification, logger.clone())
+                                .map(|tip| Progress {
+                                    tip,
+                                    pending: Vec::new(),
+                                }),
+                        ),
+                    }
+                })
+                .inspect(move |progress| *last_applied.lock().unwrap() = progress.tip.clone())
+        })
+        .map(|progress| progress.tip)
+        .and_then(move |tip| match last_checkpoint {
+            Some(last_checkpoint)
+                if u32::from(tip.chain_length()) < last_checkpoint && tip.hash() != start_hash =>
+            {
+                Err(Error::CheckpointNotReached(last_checkpoint))
+            }
+            _ => Ok(tip),
+        })
+}
+
+/// verify the headers of the blocks in the epoch of `parent` in parallel,
+/// returning along each block the verification done
+fn verify_headers(
+    blocks: Vec<Block>,
+    parent: Arc<Ref>,
+    checkpoints: &TrustedCheckpoints,
+    executor: &TaskExecutor,
+) -> impl Future<Item = Vec<(Block, HeaderVerification)>, Error = Error> {
+    let epoch = parent.block_date().epoch;
+    let leadership = parent.epoch_leadership_schedule();
+    let last_checkpoint = checkpoints.keys().next_back().cloned();
+
+    let checks = blocks.into_iter().map(|block| {
+        let chain_length = u32::from(block.header.chain_length());
+        if let Some(expected) = checkpoints.get(&chain_length) {
+            let received = block.header.hash();
+            if *expected != received {
+                return Either::A(future::err(Error::CheckpointMismatch {
+                    chain_length,
+                    expected: *expected,
+                    received,
+                }));
+            }
+        }
+        if last_checkpoint.map_or(false, |last| chain_length <= last) {
+            return Either::A(future::ok((block, HeaderVerification::Trusted)));
+        }
+        if block.header.block_date().epoch != epoch {
+            return Either::A(future::ok((block, HeaderVerification::Unverified)));
+        }
+        let leadership = Arc::clone(leadership);
+        let check = future::lazy(move || {
+            let verification =
+                match blockchain::verify_header_leadership(&leadership, &block.header) {
+                    Ok(()) => HeaderVerification::VerifiedWith(leadership),
+                    Err(_) => HeaderVerification::Unverified,
+                };
+            Ok::<_, Error>((block, verification))
+        });
+        Either::B(oneshot::spawn(check, executor))
+    });
+
+    future::join_all(checks)
+}
+
+/// the blocks applied past the last checkpoint received, not stored yet
+struct Progress {
+    tip: Arc<Ref>,
+    pending: Vec<(Block, Arc<Ref>)>,
+}
+
+impl Progress {
+    /// store the pending blocks in order and cache their `Ref`s, the last
+    /// one being the block of a checkpoint
+    fn store_pending(self, blockchain: Blockchain) -> impl Future<Item = Self, Error = Error> {
+        let Progress { tip, pending } = self;
+        stream::iter_ok(pending)
+            .for_each(move |(block, block_ref)| {
+                let cache_blockchain = blockchain.clone();
+                blockchain
+                    .store_block(block)
+                    .map_err(|e| Error::StoreBlockFailed { source: e })
+                    .and_then(move |()| {
+                        cache_blockchain
+                            .cache_ref(block_ref)
+                            .map_err(|_: std::convert::Infallible| unreachable!())
+                    })
+            })
+            .map(move |()| Progress {
+                tip,
+                pending: Vec::new(),
+            })
+    }
+}
+
+/// apply the block of a trusted chain on top of its parent, the last
+/// pending block if any, storing the pending blocks once the block of a
+/// `checkpoint` is applied
+fn apply_trusted_block(
+    mut blockchain: Blockchain,
+    mut progress: Progress,
+    block: Block,
+    checkpoint: bool,
+) -> impl Future<Item = Progress, Error = Error> {
+    let header = block.header();
+    let pre_check = match progress.pending.last() {
+        Some((_, parent_ref)) => {
+            Either::A(blockchain.pre_check_header_after(header, Some(Arc::clone(parent_ref))))
+        }
+        None => Either::B(blockchain.pre_check_header(header, true)),
+    };
+    let mut post_check_blockchain = blockchain.clone();
+    let mut apply_blockchain = blockchain.clone();
+    pre_check
+        .map_err(|e| Error::HeaderCheckFailed { source: e })
+        .and_then(|pre_checked| match pre_checked {
+            PreCheckedHeader::AlreadyPresent { header, .. } => {
+                Err(Error::BlockAlreadyPresent(header.hash()))
+            }
+            PreCheckedHeader::MissingParent { header, .. } => {
+                Err(Error::BlockMissingParent(header.hash()))
+            }
+            PreCheckedHeader::HeaderWithCache { header, parent_ref } => Ok((header, parent_ref)),
+        })
+        .and_then(move |(header, parent_ref)| {
+            post_check_blockchain
+                .post_check_verified_header(header, parent_ref, HeaderVerification::Trusted)
+                .map_err(|e| Error::HeaderCheckFailed { source: e })
         })
+        .and_then(move |post_checked| {
+            apply_blockchain
+                .apply_block(post_checked, &block)
+                .map_err(|e| Error::ApplyBlockFailed { source: e })
+                .map(move |block_ref| (block, block_ref))
+        })
+        .and_then(move |(block, block_ref)| {
+            progress.tip = Arc::clone(&block_ref);
+            progress.pending.push((block, block_ref));
+            if checkpoint {
+                Either::A(progress.store_pending(blockchain))
+            } else {
+                Either::B(future::ok(progress))
+            }
+        })
+}
+
+/// validate the block and apply it on top of its parent
+pub fn handle_block(
+    blockchain: Blockchain,
+    block: Block,
+    logger: Logger,
+) -> impl Future<Item = Arc<Ref>, Error = Error> {
+    handle_verified_block(blockchain, block, HeaderVerification::Unverified, logger)
 }
 
-fn handle_block(
+fn handle_verified_block(
     mut blockchain: Blockchain,
     block: Block,
+    verification: HeaderVerification,
     logger: Logger,
 ) -> impl Future<Item = Arc<Ref>, Error = Error> {
     let header = block.header();
@@ -121,7 +348,7 @@ fn handle_block(
         })
         .and_then(move |(header, parent_ref)| {
             blockchain
-                .post_check_header(header, parent_ref)
+                .post_check_verified_header(header, parent_ref, verification)
                 .map_err(|e| Error::HeaderCheckFailed { source: e })
         })
         .and_then(move |post_checked| {
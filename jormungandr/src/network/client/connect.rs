@@ -1,6 +1,9 @@
 use super::super::{
     grpc,
-    p2p::{comm::PeerComms, Gossip as NodeData, Id},
+    p2p::{
+        comm::{BlockLane, PeerComms},
+        Gossip as NodeData, Id,
+    },
     Channels, ConnectionState,
 };
 use super::{Client, ClientBuilder, GlobalStateR, InboundSubscriptions};
@@ -12,9 +15,11 @@ use network_core::error as core_error;
 use futures::prelude::*;
 use futures::sync::oneshot;
 use thiserror::Error;
+use tokio::timer::Delay;
 
 use std::error;
 use std::mem;
+use std::time::Instant;
 
 /// Initiates a client connection, returning a connection handle and
 /// the connection future that must be polled to complete the connection.
@@ -23,9 +28,12 @@ use std::mem;
 /// gRPC protocol, all other code is generic in terms of network-core traits.
 /// This is intentional, to facilitate extension to different protocols
 /// in the future.
+///
+/// The subscriptions of the connection share the `block_lane` of the peer.
 pub fn connect(
     state: ConnectionState,
     channels: Channels,
+    block_lane: BlockLane,
 ) -> (ConnectHandle, ConnectFuture<grpc::ConnectFuture>) {
     let (sender, receiver) = oneshot::channel();
     let addr = state.connection;
@@ -33,13 +41,27 @@ pub fn connect(
     let builder = Some(ClientBuilder {
         channels,
         logger: state.logger,
+        request_timeout: state.request_timeout,
     });
-    let cf = grpc::connect(addr, Some(node_id), state.global.executor.clone());
+    let compression = if state.global.config.compression {
+        Some(state.global.stats_counter.clone())
+    } else {
+        None
+    };
+    let (cf, negotiated) = grpc::connect_negotiated(
+        addr,
+        Some(node_id),
+        compression,
+        state.global.executor.clone(),
+    );
     let handle = ConnectHandle { receiver };
     let future = ConnectFuture {
         sender: Some(sender),
         builder,
+        deadline: Delay::new(Instant::now() + state.connect_timeout),
         global: state.global.clone(),
+        block_lane,
+        negotiated,
         state: State::Connecting(cf),
         client: None,
     };
@@ -79,7 +101,12 @@ where
 {
     sender: Option<oneshot::Sender<PeerComms>>,
     builder: Option<ClientBuilder>,
+    /// the connection is abandoned if not established by then
+    deadline: Delay,
     global: GlobalStateR,
+    block_lane: BlockLane,
+    /// the protocol version negotiated in the preface of the connection
+    negotiated: grpc::Negotiated,
     client: Option<F::Item>,
     state: State<F>,
 }
@@ -91,12 +118,16 @@ where
 {
     #[error("connection has been canceled")]
     Canceled,
+    #[error("connection not established within the connect timeout")]
+    Timeout,
     #[error("connection failed")]
     Connect(#[source] E),
     #[error("client connection unable to send requests")]
     ClientNotReady(#[source] core_error::Error),
     #[error("protocol handshake failed: {0}")]
     Handshake(#[source] core_client::HandshakeError),
+    #[error("the peer speaks the unsupported protocol versions {version}")]
+    UnsupportedVersion { version: String },
     #[error(
         "genesis block hash {peer_responded} reported by the peer is not the expected {expected}"
     )]
@@ -181,9 +212,28 @@ where
                 return Err(ConnectError::Canceled);
             }
 
+            // a timer failure only loses the timeout of this connection
+            if let Ok(Async::Ready(())) = self.deadline.poll() {
+                return Err(ConnectError::Timeout);
+            }
+
             let new_state = match self.state {
                 State::Connecting(ref mut future) => {
-                    let client = try_ready!(future.poll().map_err(ConnectError::Connect));
+                    let client = match future.poll() {
+                        Ok(Async::Ready(client)) => client,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => return Err(self.connect_error(e)),
+                    };
+                    if let Some(grpc::Negotiation::Version(version)) = self.negotiated.get() {
+                        self.global.stats_counter.add_peer_version(version);
+                        if let Some(builder) = &self.builder {
+                            debug!(
+                                builder.logger,
+                                "protocol version negotiated";
+                                "peer_version" => version,
+                            );
+                        }
+                    }
                     self.client = Some(client);
                     State::BeforeHandshake
                 }
@@ -193,9 +243,25 @@ where
                     State::Handshake(client.handshake())
                 }
                 State::Handshake(ref mut future) => {
-                    let block0 = try_ready!(future.poll().map_err(ConnectError::Handshake));
+                    // the version negotiated in the preface is the version of
+                    // the node protocol, the handshake of `network-grpc`
+                    // checks the version of its wire format
+                    let block0 = match future.poll() {
+                        Ok(Async::Ready(block0)) => block0,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(core_client::HandshakeError::UnsupportedVersion(version)) => {
+                            self.global.stats_counter.add_unsupported_version(&version);
+                            return Err(ConnectError::UnsupportedVersion {
+                                version: version.into(),
+                            });
+                        }
+                        Err(e) => return Err(ConnectError::Handshake(e)),
+                    };
                     self.match_block0(block0)?;
-                    State::Subscribing(SubscriptionStaging::new())
+                    State::Subscribing(SubscriptionStaging::new(
+                        self.global.config.block_priority,
+                        self.block_lane.clone(),
+                    ))
                 }
                 State::Subscribing(ref mut staging) => {
                     let client = self.client.as_mut().expect("client must be connected");
@@ -240,6 +306,19 @@ where
     F::Error: error::Error + 'static,
     F::Item: BlockService + FragmentService + GossipService,
 {
+    /// the connection failed, on the versions of the peer if it speaks none
+    /// of the versions of this node
+    fn connect_error(&self, e: F::Error) -> ConnectError<F::Error> {
+        match self.negotiated.get() {
+            Some(grpc::Negotiation::Unsupported(versions)) => {
+                let version = versions.to_string();
+                self.global.stats_counter.add_unsupported_version(&version);
+                ConnectError::UnsupportedVersion { version }
+            }
+            _ => ConnectError::Connect(e),
+        }
+    }
+
     fn match_block0(&self, peer_responded: HeaderHash) -> Result<(), ConnectError<F::Error>> {
         let expected = self.global.block0_hash;
         if expected == peer_responded {
@@ -263,20 +342,22 @@ where
     pub gossip: Option<<T as GossipService>::GossipSubscription>,
     pub req: SubscriptionRequests<T>,
     pub comms: PeerComms,
+    block_priority: bool,
 }
 
 impl<T> SubscriptionStaging<T>
 where
     T: BlockService + FragmentService + GossipService,
 {
-    fn new() -> Self {
+    fn new(block_priority: bool, block_lane: BlockLane) -> Self {
         SubscriptionStaging {
             node_id: None,
             block_events: None,
             fragments: None,
             gossip: None,
             req: SubscriptionRequests::new(),
-            comms: PeerComms::new(),
+            comms: PeerComms::with_block_lane(block_lane),
+            block_priority,
         }
     }
 
@@ -346,7 +427,7 @@ where
         if !self.comms.fragments_subscribed() {
             try_ready!(poll_client_ready(client));
             ready = Async::Ready(());
-            let outbound = self.comms.subscribe_to_fragments();
+            let outbound = self.comms.subscribe_to_fragments(self.block_priority);
             self.req.fragments = Some(client.fragment_subscription(outbound));
         }
         if !self.comms.gossip_subscribed() {
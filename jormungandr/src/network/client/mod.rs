@@ -1,9 +1,12 @@
 mod connect;
+mod pull;
 
 use super::{
+    block_fetch::{self, FetchedSink},
     buffer_sizes,
+    message_limits::{self, LimitedSink},
     p2p::{
-        comm::{OutboundSubscription, PeerComms},
+        comm::{FetchMeasure, OutboundSubscription, PeerComms},
         Gossip as NodeData, Id,
     },
     subscription::{BlockAnnouncementProcessor, FragmentProcessor, GossipProcessor},
@@ -12,6 +15,7 @@ use super::{
 use crate::{
     blockcfg::{Block, Fragment, Header, HeaderHash},
     intercom::{self, BlockMsg, ClientMsg},
+    log::{self, TraceId},
     utils::task::TaskMessageBox,
 };
 use network_core::client as core_client;
@@ -21,9 +25,28 @@ use network_core::subscription::{BlockEvent, ChainPullRequest};
 
 use futures::prelude::*;
 use slog::Logger;
+use thiserror::Error;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use self::pull::{Cancellation, InactivityTimeout};
 
 pub use self::connect::{connect, ConnectError, ConnectFuture, ConnectHandle};
 
+/// why the connection with the peer was broken off
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("connection broke down: {source}")]
+    Connection { source: core_error::Error },
+    #[error("block event subscription failed: {source}")]
+    BlockEvents { source: core_error::Error },
+    #[error("fragment subscription failed: {source}")]
+    Fragments { source: core_error::Error },
+    #[error("gossip subscription failed: {source}")]
+    Gossip { source: core_error::Error },
+}
+
 #[must_use = "Client must be polled"]
 pub struct Client<S>
 where
@@ -40,6 +63,10 @@ where
     gossip_processor: GossipProcessor,
     incoming_block_announcement: Option<Header>,
     incoming_fragment: Option<Fragment>,
+    /// cancels the pulls in flight when the client is dropped
+    pulls: Cancellation,
+    /// how long the peer may take to respond to a request
+    request_timeout: Duration,
     // FIXME: kill it with fire
     client_box: TaskMessageBox<ClientMsg>,
 }
@@ -47,6 +74,7 @@ where
 struct ClientBuilder {
     pub logger: Logger,
     pub channels: Channels,
+    pub request_timeout: Duration,
 }
 
 impl<S> Client<S>
@@ -60,6 +88,23 @@ where
     pub fn logger(&self) -> &Logger {
         &self.logger
     }
+
+    /// queue a request of the peer for the client task, without blocking
+    /// the connection. When the queue is full the request is dropped and
+    /// `false` is returned
+    fn query_client(&self, msg: ClientMsg) -> bool {
+        match self.client_box.try_send_to(msg) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    self.logger,
+                    "cannot process the request of the peer";
+                    "reason" => %e,
+                );
+                false
+            }
+        }
+    }
 }
 
 impl<S> Client<S>
@@ -113,6 +158,8 @@ where
             client_box: builder.channels.client_box,
             incoming_block_announcement: None,
             incoming_fragment: None,
+            pulls: Cancellation::new(),
+            request_timeout: builder.request_timeout,
         }
     }
 }
@@ -156,14 +203,18 @@ where
     S::PushHeadersFuture: Send + 'static,
     S::UploadBlocksFuture: Send + 'static,
 {
-    fn process_block_event(&mut self) -> Poll<ProcessingOutcome, ()> {
+    fn process_block_event(&mut self) -> Poll<ProcessingOutcome, Error> {
         use self::ProcessingOutcome::*;
 
         // Drive sending of a message to block task to completion
         // before polling more events from the block subscription
         // stream.
         if let Some(header) = self.incoming_block_announcement.take() {
-            match self.block_sink.start_send(header).map_err(|_| ())? {
+            match self
+                .block_sink
+                .start_send(header)
+                .map_err(|e| Error::BlockEvents { source: e })?
+            {
                 AsyncSink::Ready => {}
                 AsyncSink::NotReady(header) => {
                     self.incoming_block_announcement = Some(header);
@@ -174,7 +225,9 @@ where
             // Ignoring possible NotReady return here: due to the following
             // try_ready!() invocation, this function cannot return Continue
             // while no progress has been made.
-            self.block_sink.poll_complete().map_err(|_| ())?;
+            self.block_sink
+                .poll_complete()
+                .map_err(|e| Error::BlockEvents { source: e })?;
         }
         let maybe_event = try_ready!(self.inbound.block_events.poll().map_err(|e| {
             debug!(
@@ -182,6 +235,7 @@ where
                 "block subscription stream failure";
                 "error" => ?e,
             );
+            Error::BlockEvents { source: e }
         }));
         let event = match maybe_event {
             Some(event) => event,
@@ -208,8 +262,10 @@ where
                     Block,
                     network_core::error::Error,
                 >(self.logger.clone());
-                self.client_box
-                    .send_to(ClientMsg::GetBlocks(block_ids, reply_handle));
+                let stream = stream.with_timeout(self.global_state.config.request_timeout);
+                if !self.query_client(ClientMsg::GetBlocks(block_ids, reply_handle)) {
+                    return Ok(Continue.into());
+                }
                 let done_logger = self.logger.clone();
                 let err_logger = self.logger.clone();
                 self.global_state.spawn(
@@ -242,8 +298,10 @@ where
     fn push_missing_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let (reply_handle, stream) =
             intercom::stream_reply::<Header, network_core::error::Error>(self.logger.clone());
-        self.client_box
-            .send_to(ClientMsg::GetHeadersRange(req.from, req.to, reply_handle));
+        let stream = stream.with_timeout(self.global_state.config.request_timeout);
+        if !self.query_client(ClientMsg::GetHeadersRange(req.from, req.to, reply_handle)) {
+            return;
+        }
         let done_logger = self.logger.clone();
         let err_logger = self.logger.clone();
         self.global_state.spawn(
@@ -275,10 +333,23 @@ where
         let logger = self.logger.new(o!("request" => "PullHeaders"));
         let req_err_logger = logger.clone();
         let res_logger = logger.clone();
+        let err_logger = logger.clone();
+        let cancel_logger = logger.clone();
+        let node_id = self.remote_node_id();
+        let err_state = self.global_state.clone();
+        let timeout = self.global_state.config.pull_inactivity_timeout;
+        let request_timeout = self.request_timeout;
         let (handle, sink) = intercom::stream_request::<Header, (), core_error::Error>(
             buffer_sizes::CHAIN_PULL,
             logger.clone(),
         );
+        let sink = LimitedSink::new(
+            sink,
+            message_limits::check_header,
+            Some(node_id),
+            self.global_state.clone(),
+            logger.clone(),
+        );
         // TODO: make sure that back pressure on the number of requests
         // in flight, imposed through self.service.poll_ready(),
         // prevents unlimited spawning of these tasks.
@@ -295,28 +366,41 @@ where
                 })
                 .map(|_mbox| ()),
         );
-        self.global_state.spawn(
-            self.service
-                .pull_headers(&req.from, &req.to)
-                .map_err(move |e| {
+        let request = self.service.pull_headers(&req.from, &req.to);
+        let pull = pull::response(request, request_timeout)
+            .map_err(move |e| {
+                info!(
+                    req_err_logger,
+                    "request failed";
+                    "reason" => %e,
+                );
+                e
+            })
+            .and_then(move |stream| {
+                sink.send_all(InactivityTimeout::new(stream, timeout))
+                    .map_err(move |e| {
+                        info!(
+                            res_logger,
+                            "response stream failed";
+                            "reason" => %e,
+                        );
+                        e
+                    })
+                    .map(|_| ())
+            })
+            .or_else(move |e| {
+                if pull::is_timeout(&e) {
                     info!(
-                        req_err_logger,
-                        "request failed";
-                        "reason" => %e,
+                        err_logger,
+                        "the peer stalled, pulling the headers from another peer"
                     );
-                })
-                .and_then(move |stream| {
-                    sink.send_all(stream)
-                        .map_err(move |e| {
-                            info!(
-                                res_logger,
-                                "response stream failed";
-                                "reason" => %e,
-                            );
-                        })
-                        .map(|_| ())
-                }),
-        );
+                    pull::report_stall(&err_state, node_id, &err_logger);
+                    err_state.peers.repull_headers(node_id, req.from, req.to);
+                }
+                Ok(())
+            });
+        self.global_state
+            .spawn(self.pulls.cancelable(pull, cancel_logger));
     }
 }
 
@@ -329,20 +413,42 @@ where
 {
     fn solicit_blocks(&mut self, block_ids: &[HeaderHash]) {
         let block_box = self.block_sink.message_box();
-        let logger = self.logger.new(o!("request" => "GetBlocks"));
+        let trace = TraceId::new();
+        let logger = self.logger.new(o!(
+            "request" => "GetBlocks",
+            log::KEY_TRACE => trace.to_string(),
+        ));
         let req_err_logger = logger.clone();
         let res_logger = logger.clone();
+        let err_logger = logger.clone();
+        let cancel_logger = logger.clone();
+        let node_id = self.remote_node_id();
+        let res_state = self.global_state.clone();
+        let err_state = self.global_state.clone();
+        let retry_state = self.global_state.clone();
+        let timeout = self.global_state.config.pull_inactivity_timeout;
+        let request_timeout = self.request_timeout;
+        let block_ids = block_ids.to_vec();
+        let measure = Arc::new(Mutex::new(FetchMeasure::start()));
         let (handle, sink) = intercom::stream_request::<Block, (), core_error::Error>(
             buffer_sizes::BLOCKS,
             logger.clone(),
         );
+        let sink = LimitedSink::new(
+            sink,
+            message_limits::check_block,
+            Some(node_id),
+            self.global_state.clone(),
+            logger.clone(),
+        );
+        let sink = FetchedSink::new(sink, self.global_state.clone());
         // TODO: make sure that back pressure on the number of requests
         // in flight, imposed through self.service.poll_ready(),
         // prevents unlimited spawning of these tasks.
         // https://github.com/input-output-hk/jormungandr/issues/1034
         self.global_state.spawn(
             block_box
-                .send(BlockMsg::NetworkBlocks(handle))
+                .send(BlockMsg::NetworkBlocks(trace, handle))
                 .map_err(move |e| {
                     error!(
                         logger,
@@ -352,28 +458,49 @@ where
                 })
                 .map(|_mbox| ()),
         );
-        self.global_state.spawn(
-            self.service
-                .get_blocks(block_ids)
-                .map_err(move |e| {
+        let pull = pull::response(self.service.get_blocks(&block_ids), request_timeout)
+            .map_err(move |e| {
+                info!(
+                    req_err_logger,
+                    "request failed";
+                    "reason" => %e,
+                );
+                e
+            })
+            .and_then(move |stream| {
+                let block_measure = measure.clone();
+                let stream = stream.inspect(move |block| {
+                    block_measure.lock().unwrap().add_block(block);
+                });
+                sink.send_all(InactivityTimeout::new(stream, timeout))
+                    .map_err(move |e| {
+                        info!(
+                            res_logger,
+                            "response stream failed";
+                            "reason" => %e,
+                        );
+                        e
+                    })
+                    .map(move |_| {
+                        if let Some(fetch) = measure.lock().unwrap().finish() {
+                            res_state.peers.record_block_fetch(node_id, fetch);
+                        }
+                    })
+            })
+            .or_else(move |e| {
+                err_state.peers.record_block_fetch_failure(node_id);
+                if pull::is_timeout(&e) {
                     info!(
-                        req_err_logger,
-                        "request failed";
-                        "reason" => %e,
+                        err_logger,
+                        "the peer stalled, fetching the blocks from other peers"
                     );
-                })
-                .and_then(move |stream| {
-                    sink.send_all(stream)
-                        .map_err(move |e| {
-                            info!(
-                                res_logger,
-                                "response stream failed";
-                                "reason" => %e,
-                            );
-                        })
-                        .map(|_| ())
-                }),
-        );
+                    pull::report_stall(&err_state, node_id, &err_logger);
+                }
+                Ok(())
+            })
+            .map(move |()| block_fetch::retry(&retry_state, node_id, &block_ids));
+        self.global_state
+            .spawn(self.pulls.cancelable(pull, cancel_logger));
     }
 }
 
@@ -382,14 +509,18 @@ where
     S: FragmentService<Fragment = Fragment>,
     S: BlockService + GossipService,
 {
-    fn process_fragments(&mut self) -> Poll<ProcessingOutcome, ()> {
+    fn process_fragments(&mut self) -> Poll<ProcessingOutcome, Error> {
         use self::ProcessingOutcome::*;
 
         // Drive sending of a message to fragment task to completion
         // before polling more events from the fragment subscription
         // stream.
         if let Some(fragment) = self.incoming_fragment.take() {
-            match self.fragment_sink.start_send(fragment).map_err(|_| ())? {
+            match self
+                .fragment_sink
+                .start_send(fragment)
+                .map_err(|e| Error::Fragments { source: e })?
+            {
                 AsyncSink::Ready => {}
                 AsyncSink::NotReady(fragment) => {
                     self.incoming_fragment = Some(fragment);
@@ -400,7 +531,9 @@ where
             // Ignoring possible NotReady return here: due to the following
             // try_ready!() invocation, this function cannot return Continue
             // while no progress has been made.
-            self.fragment_sink.poll_complete().map_err(|_| ())?;
+            self.fragment_sink
+                .poll_complete()
+                .map_err(|e| Error::Fragments { source: e })?;
         }
 
         let maybe_fragment = try_ready!(self.inbound.fragments.poll().map_err(|e| {
@@ -409,6 +542,7 @@ where
                 "fragment stream failure";
                 "error" => %e,
             );
+            Error::Fragments { source: e }
         }));
         match maybe_fragment {
             Some(fragment) => {
@@ -437,15 +571,21 @@ where
     S: GossipService<Node = NodeData>,
     S: BlockService + FragmentService,
 {
-    fn process_gossip(&mut self) -> Poll<ProcessingOutcome, ()> {
+    fn process_gossip(&mut self) -> Poll<ProcessingOutcome, Error> {
         use self::ProcessingOutcome::*;
 
+        // the gossips over the rate limit are left in the stream
+        try_ready!(self
+            .gossip_processor
+            .poll_admit()
+            .map_err(|e| Error::Gossip { source: e }));
         let maybe_gossip = try_ready!(self.inbound.gossip.poll().map_err(|e| {
             debug!(
                 self.logger,
                 "gossip stream failure";
                 "error" => %e,
             );
+            Error::Gossip { source: e }
         }));
         match maybe_gossip {
             Some(gossip) => {
@@ -456,7 +596,9 @@ where
                     "direction" => "in",
                     "item" => ?gossip,
                 );
-                self.gossip_processor.process_item(gossip);
+                self.gossip_processor
+                    .process_item(gossip)
+                    .map_err(|e| Error::Gossip { source: e })?;
                 Ok(Continue.into())
             }
             None => {
@@ -484,8 +626,8 @@ where
     S::UploadBlocksFuture: Send + 'static,
 {
     type Item = ();
-    type Error = ();
-    fn poll(&mut self) -> Poll<(), ()> {
+    type Error = Error;
+    fn poll(&mut self) -> Poll<(), Error> {
         use self::ProcessingOutcome::*;
 
         loop {
@@ -496,6 +638,7 @@ where
                     self.logger,
                     "client connection broke down";
                     "error" => ?e);
+                Error::Connection { source: e }
             }));
 
             let mut progress = Progress(None);
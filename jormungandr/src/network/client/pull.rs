@@ -0,0 +1,217 @@
+//! the timeouts and the cancellation of the pulls from a peer
+//!
+//! A peer may start streaming the blocks or headers requested from it and
+//! then stall. A pull fails when the peer sends no response within the
+//! request timeout or no item within the inactivity timeout, the client then
+//! requests the same blocks or headers from another peer. The stalls are
+//! counted in the stats of the peer, which is struck in the topology once it
+//! stalled `MAX_STALLS` times within `STALLS_WINDOW`: a peer slowed down by
+//! its load once in a while is not quarantined for it. The pulls still in
+//! flight are canceled when the client connection to the peer ends.
+
+use crate::network::{p2p::Id, GlobalState};
+use futures::future::{Either, Shared};
+use futures::prelude::*;
+use futures::sync::oneshot;
+use network_core::error as core_error;
+use poldercast::StrikeReason;
+use slog::Logger;
+use std::time::{Duration, Instant};
+use tokio::prelude::FutureExt as _;
+use tokio::timer::Delay;
+
+/// the stalls of a peer within `STALLS_WINDOW` after which it is struck
+const MAX_STALLS: u32 = 3;
+/// the period over which the stalls of a peer are counted
+const STALLS_WINDOW: Duration = Duration::from_secs(600);
+
+/// the error of a pull over the request or the inactivity timeout
+pub fn is_timeout(e: &core_error::Error) -> bool {
+    match e.code() {
+        core_error::Code::DeadlineExceeded => true,
+        _ => false,
+    }
+}
+
+fn inactivity_timeout() -> core_error::Error {
+    core_error::Error::new(
+        core_error::Code::DeadlineExceeded,
+        "the peer sent nothing within the inactivity timeout",
+    )
+}
+
+fn request_timeout() -> core_error::Error {
+    core_error::Error::new(
+        core_error::Code::DeadlineExceeded,
+        "the peer did not respond within the request timeout",
+    )
+}
+
+/// record a stalled pull from the peer, striking it once it stalled too many
+/// times. The topology has no reason for a stall, the peer is struck as an
+/// unreachable one.
+pub fn report_stall(state: &GlobalState, node_id: Id, logger: &Logger) {
+    let stalls = state
+        .peers
+        .record_stall(node_id, STALLS_WINDOW)
+        .unwrap_or(1);
+    if stalls < MAX_STALLS {
+        return;
+    }
+    warn!(
+        logger,
+        "the peer stalled too many pulls, striking it";
+        "stalls" => stalls,
+    );
+    state
+        .topology
+        .report_node(node_id, StrikeReason::CannotConnect);
+}
+
+/// the response to a pull request, failing after the request timeout
+pub fn response<F>(request: F, timeout: Duration) -> impl Future<Item = F::Item, Error = F::Error>
+where
+    F: Future<Error = core_error::Error>,
+{
+    request
+        .timeout(timeout)
+        .map_err(|e| e.into_inner().unwrap_or_else(request_timeout))
+}
+
+/// the items of a pull, failing when none is received within the timeout
+#[must_use = "streams do nothing unless polled"]
+pub struct InactivityTimeout<S> {
+    inner: S,
+    timeout: Duration,
+    delay: Delay,
+    /// the timeout starts when the next item is polled: the time the
+    /// receiver of the items holds back the stream does not count
+    waiting: bool,
+}
+
+impl<S> InactivityTimeout<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        InactivityTimeout {
+            inner,
+            timeout,
+            delay: Delay::new(Instant::now() + timeout),
+            waiting: false,
+        }
+    }
+}
+
+impl<S> Stream for InactivityTimeout<S>
+where
+    S: Stream<Error = core_error::Error>,
+{
+    type Item = S::Item;
+    type Error = core_error::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, core_error::Error> {
+        if !self.waiting {
+            self.delay.reset(Instant::now() + self.timeout);
+            self.waiting = true;
+        }
+        match self.inner.poll()? {
+            Async::Ready(item) => {
+                self.waiting = false;
+                Ok(Async::Ready(item))
+            }
+            Async::NotReady => match self.delay.poll() {
+                Ok(Async::Ready(())) => Err(inactivity_timeout()),
+                // a timer failure only loses the timeout of this pull
+                Ok(Async::NotReady) | Err(_) => Ok(Async::NotReady),
+            },
+        }
+    }
+}
+
+/// held by the client, the pulls it started are canceled once it is dropped
+pub struct Cancellation {
+    _sender: oneshot::Sender<()>,
+    canceled: Shared<oneshot::Receiver<()>>,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        Cancellation {
+            _sender: sender,
+            canceled: receiver.shared(),
+        }
+    }
+
+    /// run the pull until it completes or the client is dropped
+    pub fn cancelable<F>(&self, pull: F, logger: Logger) -> impl Future<Item = (), Error = ()>
+    where
+        F: Future<Item = (), Error = ()>,
+    {
+        pull.select2(self.canceled.clone()).then(move |res| {
+            if let Ok(Either::B(_)) | Err(Either::B(_)) = res {
+                debug!(logger, "pull canceled, the client connection ended");
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, stream};
+    use tokio::runtime::current_thread::Runtime;
+
+    type Items = Box<dyn Stream<Item = u32, Error = core_error::Error>>;
+
+    fn stalled() -> Items {
+        Box::new(stream::poll_fn(|| Ok(Async::NotReady)))
+    }
+
+    #[test]
+    fn items_received_in_time_go_through() {
+        let mut runtime = Runtime::new().unwrap();
+        let items = stream::iter_ok(vec![1, 2, 3]).chain(stream::iter_ok(vec![4]));
+        let timeout = InactivityTimeout::new(items, Duration::from_secs(1));
+
+        assert_eq!(
+            runtime.block_on(timeout.collect()).unwrap(),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn stalled_pull_fails_after_the_inactivity_timeout() {
+        let mut runtime = Runtime::new().unwrap();
+        let items = stream::iter_ok(vec![1]).chain(stalled());
+        let timeout = InactivityTimeout::new(items, Duration::from_millis(20));
+
+        let (first, rest) = runtime.block_on(timeout.into_future()).ok().unwrap();
+        assert_eq!(first, Some(1));
+        match runtime.block_on(rest.collect()) {
+            Err(e) => assert!(is_timeout(&e)),
+            Ok(items) => panic!("stalled pull completed with {:?}", items),
+        }
+    }
+
+    #[test]
+    fn request_not_responded_fails_after_the_request_timeout() {
+        let mut runtime = Runtime::new().unwrap();
+        let request = future::empty::<(), core_error::Error>();
+
+        match runtime.block_on(response(request, Duration::from_millis(20))) {
+            Err(e) => assert!(is_timeout(&e)),
+            Ok(()) => panic!("request without response completed"),
+        }
+    }
+
+    #[test]
+    fn pulls_are_canceled_with_the_client() {
+        let mut runtime = Runtime::new().unwrap();
+        let cancellation = Cancellation::new();
+        let logger = Logger::root(slog::Discard, o!());
+        let pull = cancellation.cancelable(future::empty(), logger);
+        drop(cancellation);
+
+        assert!(runtime.block_on(pull).is_ok());
+    }
+}
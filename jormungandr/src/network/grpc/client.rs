@@ -1,7 +1,9 @@
+use super::compression::{Connector, Negotiated};
 use crate::{
     blockcfg::{Block, HeaderHash},
     network::{p2p::Id, BlockConfig},
     settings::start::network::Peer,
+    stats_counter::StatsCounter,
 };
 use futures::prelude::*;
 use http::{HttpTryFrom, Uri};
@@ -34,19 +36,39 @@ pub enum FetchBlockError {
 }
 
 pub type Connection = network_grpc::client::Connection<BlockConfig>;
-pub type ConnectFuture =
-    network_grpc::client::ConnectFuture<BlockConfig, HttpConnector, TaskExecutor>;
+pub type ConnectFuture = network_grpc::client::ConnectFuture<BlockConfig, Connector, TaskExecutor>;
 pub type ConnectError = network_grpc::client::ConnectError<io::Error>;
 
-pub fn connect(addr: SocketAddr, node_id: Option<Id>, executor: TaskExecutor) -> ConnectFuture {
+/// connect to the peer, the connection being compressed if the peer
+/// supports it when `compression` is set with the stats to count it in
+pub fn connect(
+    addr: SocketAddr,
+    node_id: Option<Id>,
+    compression: Option<StatsCounter>,
+    executor: TaskExecutor,
+) -> ConnectFuture {
+    connect_negotiated(addr, node_id, compression, executor).0
+}
+
+/// connect to the peer as `connect`, with the outcome of the negotiation
+/// of the protocol version, set once the peer answered it
+pub fn connect_negotiated(
+    addr: SocketAddr,
+    node_id: Option<Id>,
+    compression: Option<StatsCounter>,
+    executor: TaskExecutor,
+) -> (ConnectFuture, Negotiated) {
     let uri = destination_uri(addr);
-    let mut connector = HttpConnector::new(2);
-    connector.set_nodelay(true);
+    let mut http = HttpConnector::new(2);
+    http.set_nodelay(true);
+    let connector = Connector::new(http, compression);
+    let negotiated = connector.negotiated();
     let mut builder = Connect::new(connector, executor);
     if let Some(id) = node_id {
         builder.node_id(id);
     }
-    builder.connect(Destination::try_from_uri(uri).unwrap())
+    let future = builder.connect(Destination::try_from_uri(uri).unwrap());
+    (future, negotiated)
 }
 
 fn destination_uri(addr: SocketAddr) -> Uri {
@@ -67,7 +89,7 @@ pub fn fetch_block(
 ) -> Result<Block, FetchBlockError> {
     info!(logger, "fetching block {}", hash);
     let runtime = Runtime::new().map_err(|e| FetchBlockError::RuntimeInit { source: e })?;
-    let fetch = connect(peer.address(), None, runtime.executor())
+    let fetch = connect(peer.address(), None, None, runtime.executor())
         .map_err(|err| FetchBlockError::Connect { source: err })
         .and_then(move |client: Connection| {
             client
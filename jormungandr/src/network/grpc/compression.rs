@@ -0,0 +1,1016 @@
+//! The preface and the compression of the P2P connections.
+//!
+//! The clients open every connection with the preface negotiating the
+//! version of the node protocol and the compression (see `preface`). The
+//! gRPC codec of `network-grpc` neither sends nor accepts compressed
+//! messages, so the compression is not negotiated per stream with the gRPC
+//! message encoding: the whole HTTP/2 connection is compressed instead, both
+//! sides switching to a deflate stream once the listening node agreed to it
+//! in its answer. The incompressible bytes, such as the hashes and the
+//! signatures of the blocks, cost the compression time but barely grow on
+//! the wire, deflate storing them as they are.
+//! A node released before the preface fails the HTTP/2 handshake on it or
+//! does not answer it, the client then connects again without the preface,
+//! and connects to it without the preface for `LEGACY_RETRY` so that its
+//! connections do not all wait for the answer. The listening node detects
+//! the preface, the connections of the peers not sending it are served
+//! uncompressed; a peer which does not complete the preface within
+//! `PREFACE_TIMEOUT` is disconnected.
+
+use super::preface::{Answer, Hello, Versions, ANSWER_LEN, HELLO_LEN, LEGACY_VERSION};
+use crate::stats_counter::{CompressedBytes, StatsCounter};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use futures::future::Either;
+use hyper::client::connect::{Connect, Connected, Destination, HttpConnector};
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::prelude::*;
+use tokio::timer::{Delay, Timeout};
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// how long the client waits for the answer of the peer to the preface
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long the listening node waits for the first bytes of a connection
+/// and for the preface to be answered
+const PREFACE_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long a peer which did not answer the preface is connected to without
+/// it, before the preface is sent again in case the peer was upgraded
+const LEGACY_RETRY: Duration = Duration::from_secs(3600);
+/// the most peers remembered as not answering the preface
+const LEGACY_PEERS_CAPACITY: usize = 1024;
+
+lazy_static! {
+    static ref LEGACY_PEERS: LegacyPeers = LegacyPeers::default();
+}
+
+/// the peers which did not answer the preface, by address, with the time
+/// they were found not to answer
+#[derive(Default)]
+struct LegacyPeers(Mutex<HashMap<String, Instant>>);
+
+impl LegacyPeers {
+    fn insert(&self, peer: String, now: Instant) {
+        let mut peers = self.0.lock().unwrap();
+        if peers.len() >= LEGACY_PEERS_CAPACITY {
+            peers.retain(|_, since| now.duration_since(*since) < LEGACY_RETRY);
+            if peers.len() >= LEGACY_PEERS_CAPACITY {
+                peers.clear();
+            }
+        }
+        peers.insert(peer, now);
+    }
+
+    fn contains(&self, peer: &str, now: Instant) -> bool {
+        let mut peers = self.0.lock().unwrap();
+        match peers.get(peer) {
+            Some(since) if now.duration_since(*since) < LEGACY_RETRY => true,
+            Some(_) => {
+                peers.remove(peer);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+fn peer_key(dst: &Destination) -> String {
+    format!("{}:{}", dst.host(), dst.port().unwrap_or(0))
+}
+
+/// the outcome of the preface of a connection of the client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Negotiation {
+    /// the version of the protocol spoken on the connection
+    Version(u16),
+    /// the peer speaks none of the versions of this node
+    Unsupported(Versions),
+}
+
+/// the outcome of the preface of the connection made by a connector, set
+/// once the preface is answered
+#[derive(Clone, Default)]
+pub struct Negotiated(Arc<Mutex<Option<Negotiation>>>);
+
+impl Negotiated {
+    fn set(&self, negotiation: Negotiation) {
+        *self.0.lock().unwrap() = Some(negotiation);
+    }
+
+    pub fn get(&self) -> Option<Negotiation> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// the connector of the client, negotiating the version and compressing
+/// the connections if enabled
+#[derive(Clone)]
+pub struct Connector {
+    http: HttpConnector,
+    compression: Option<StatsCounter>,
+    negotiated: Negotiated,
+}
+
+impl Connector {
+    /// the connections are compressed with the peers supporting it when
+    /// `compression` is set, the compressed bytes being counted in it
+    pub fn new(http: HttpConnector, compression: Option<StatsCounter>) -> Self {
+        Connector {
+            http,
+            compression,
+            negotiated: Negotiated::default(),
+        }
+    }
+
+    /// the outcome of the preface of the connections of this connector
+    pub fn negotiated(&self) -> Negotiated {
+        self.negotiated.clone()
+    }
+}
+
+impl Connect for Connector {
+    type Transport = Transport<TcpStream>;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = (Self::Transport, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let hello = Hello {
+            versions: Versions::SUPPORTED,
+            compression: self.compression.is_some(),
+        };
+        let stats = self.compression.clone();
+        let negotiated = self.negotiated.clone();
+        let http = self.http.clone();
+        let peer = peer_key(&dst);
+        if LEGACY_PEERS.contains(&peer, Instant::now()) {
+            let connecting = future::result(negotiated_legacy(&negotiated))
+                .and_then(move |()| connect_plain(&http, dst));
+            return Box::new(connecting);
+        }
+        let fallback = dst.clone();
+        let connecting = self
+            .http
+            .connect(dst)
+            .and_then(move |(stream, connected)| {
+                negotiate(stream, hello).map(move |answered| (answered, connected))
+            })
+            .and_then(move |(answered, connected)| match answered {
+                Some((stream, answer)) => Either::A(future::result(
+                    answered_transport(stream, answer, stats, &negotiated)
+                        .map(|transport| (transport, connected)),
+                )),
+                // a node released before the preface
+                None => {
+                    LEGACY_PEERS.insert(peer, Instant::now());
+                    Either::B(
+                        future::result(negotiated_legacy(&negotiated))
+                            .and_then(move |()| connect_plain(&http, fallback)),
+                    )
+                }
+            });
+        Box::new(connecting)
+    }
+}
+
+fn connect_plain(
+    http: &HttpConnector,
+    dst: Destination,
+) -> impl Future<Item = (Transport<TcpStream>, Connected), Error = io::Error> {
+    http.connect(dst)
+        .map(|(stream, connected)| (Transport::plain(stream), connected))
+}
+
+/// send the preface and wait for the answer, the stream being dropped if
+/// the peer does not answer it
+fn negotiate(
+    stream: TcpStream,
+    hello: Hello,
+) -> impl Future<Item = Option<(TcpStream, Answer)>, Error = io::Error> {
+    let negotiation = tokio::io::write_all(stream, hello.encode())
+        .and_then(|(stream, _)| tokio::io::read_exact(stream, [0; ANSWER_LEN]))
+        .map(|(stream, answer)| Answer::decode(&answer).map(|answer| (stream, answer)));
+    Timeout::new(negotiation, ANSWER_TIMEOUT).then(|result| Ok(result.unwrap_or(None)))
+}
+
+/// the connection once the peer answered the preface
+fn answered_transport<S>(
+    stream: S,
+    answer: Answer,
+    compression: Option<StatsCounter>,
+    negotiated: &Negotiated,
+) -> io::Result<Transport<S>>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let version = match answer.version {
+        Some(version) => version,
+        None => {
+            negotiated.set(Negotiation::Unsupported(answer.versions));
+            return Err(unsupported_versions(answer.versions));
+        }
+    };
+    negotiated.set(Negotiation::Version(version));
+    match (answer.compression, compression) {
+        (false, _) => Ok(Transport::plain(stream)),
+        (true, Some(stats)) => Ok(Transport::Compressed(Compressed::new(stream, stats))),
+        (true, None) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the peer compresses the connection though it was not asked to",
+        )),
+    }
+}
+
+/// the version spoken with a node not answering the preface
+fn negotiated_legacy(negotiated: &Negotiated) -> io::Result<()> {
+    if Versions::SUPPORTED.negotiate(Versions::LEGACY).is_some() {
+        negotiated.set(Negotiation::Version(LEGACY_VERSION));
+        Ok(())
+    } else {
+        negotiated.set(Negotiation::Unsupported(Versions::LEGACY));
+        Err(unsupported_versions(Versions::LEGACY))
+    }
+}
+
+fn unsupported_versions(versions: Versions) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!(
+            "the peer speaks the protocol versions {}, this node {}",
+            versions,
+            Versions::SUPPORTED
+        ),
+    )
+}
+
+/// a connection, compressed or not
+pub enum Transport<S> {
+    /// the preface of the client being looked for in the first bytes
+    Accepting(Accepting<S>),
+    Plain(Rewind<S>),
+    Compressed(Compressed<S>),
+    /// an error occurred while accepting the connection
+    Failed,
+}
+
+impl<S> Transport<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    /// a connection not compressed
+    pub fn plain(stream: S) -> Self {
+        Transport::Plain(Rewind::new(stream, Vec::new()))
+    }
+
+    /// an incoming connection, its version negotiated if it starts with
+    /// the preface and compressed if both sides ask for it with
+    /// `compression` set. The negotiated versions are counted in `stats`.
+    pub fn accept(stream: S, stats: StatsCounter, compression: bool) -> Self {
+        Self::accept_within(stream, stats, compression, PREFACE_TIMEOUT)
+    }
+
+    fn accept_within(stream: S, stats: StatsCounter, compression: bool, timeout: Duration) -> Self {
+        Transport::Accepting(Accepting {
+            stream,
+            received: Vec::with_capacity(HELLO_LEN),
+            answer: None,
+            compression,
+            stats,
+            deadline: Delay::new(Instant::now() + timeout),
+        })
+    }
+
+    fn poll_accepted(&mut self) -> Poll<(), io::Error> {
+        let accepting = match self {
+            Transport::Accepting(accepting) => accepting,
+            Transport::Failed => return Err(io::ErrorKind::NotConnected.into()),
+            _ => return Ok(Async::Ready(())),
+        };
+        let accepted = match accepting.poll() {
+            Ok(Async::Ready(accepted)) => accepted,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                *self = Transport::Failed;
+                return Err(e);
+            }
+        };
+        let accepting = match std::mem::replace(self, Transport::Failed) {
+            Transport::Accepting(accepting) => accepting,
+            _ => unreachable!(),
+        };
+        *self = match accepted {
+            Accepted::Legacy => Transport::Plain(Rewind::new(accepting.stream, accepting.received)),
+            Accepted::Plain => Transport::plain(accepting.stream),
+            Accepted::Compressed => {
+                Transport::Compressed(Compressed::new(accepting.stream, accepting.stats))
+            }
+        };
+        Ok(Async::Ready(()))
+    }
+}
+
+fn would_block() -> io::Error {
+    io::ErrorKind::WouldBlock.into()
+}
+
+fn poll_io<T>(result: io::Result<T>) -> Poll<T, io::Error> {
+    match result {
+        Ok(value) => Ok(Async::Ready(value)),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+        Err(e) => Err(e),
+    }
+}
+
+impl<S> Read for Transport<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Async::NotReady = self.poll_accepted()? {
+            return Err(would_block());
+        }
+        match self {
+            Transport::Plain(stream) => stream.read(buf),
+            Transport::Compressed(stream) => stream.read(buf),
+            Transport::Accepting(_) | Transport::Failed => unreachable!(),
+        }
+    }
+}
+
+impl<S> Write for Transport<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the server speaks first in HTTP/2, its writes wait for the
+        // preface to be looked for and answered
+        if let Async::NotReady = self.poll_accepted()? {
+            return Err(would_block());
+        }
+        match self {
+            Transport::Plain(stream) => stream.inner.write(buf),
+            Transport::Compressed(stream) => stream.write(buf),
+            Transport::Accepting(_) | Transport::Failed => unreachable!(),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Async::NotReady = self.poll_accepted()? {
+            return Err(would_block());
+        }
+        match self {
+            Transport::Plain(stream) => stream.inner.flush(),
+            Transport::Compressed(stream) => stream.flush(),
+            Transport::Accepting(_) | Transport::Failed => unreachable!(),
+        }
+    }
+}
+
+impl<S> AsyncRead for Transport<S> where S: AsyncRead + AsyncWrite {}
+
+impl<S> AsyncWrite for Transport<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            Transport::Accepting(accepting) => accepting.stream.shutdown(),
+            Transport::Plain(stream) => stream.inner.shutdown(),
+            Transport::Compressed(stream) => stream.writer.shutdown(),
+            Transport::Failed => Ok(Async::Ready(())),
+        }
+    }
+}
+
+/// the first bytes of an incoming connection, compared to the preface
+pub struct Accepting<S> {
+    stream: S,
+    received: Vec<u8>,
+    answer: Option<Answering>,
+    compression: bool,
+    stats: StatsCounter,
+    /// the connection fails if the preface is not looked for and answered
+    /// by then
+    deadline: Delay,
+}
+
+/// the answer to the preface of a client, being written
+struct Answering {
+    hello: Hello,
+    answer: Answer,
+    encoded: Vec<u8>,
+    written: usize,
+}
+
+/// how an incoming connection goes on after its first bytes
+enum Accepted {
+    /// the peer did not send the preface, its first bytes are replayed
+    Legacy,
+    Plain,
+    Compressed,
+}
+
+impl<S> Accepting<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    /// how the connection goes on, once the preface is received and
+    /// answered or the first bytes differ from it. The connection fails if
+    /// the peer speaks none of the versions of this node, or if it does not
+    /// get that far by the deadline.
+    fn poll(&mut self) -> Poll<Accepted, io::Error> {
+        match self.poll_preface()? {
+            Async::Ready(accepted) => Ok(Async::Ready(accepted)),
+            Async::NotReady => match self.deadline.poll() {
+                Ok(Async::Ready(())) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the peer did not complete the preface in time",
+                )),
+                // a timer failure only loses the deadline of this connection
+                Ok(Async::NotReady) | Err(_) => Ok(Async::NotReady),
+            },
+        }
+    }
+
+    fn poll_preface(&mut self) -> Poll<Accepted, io::Error> {
+        while self.answer.is_none() {
+            if !Hello::may_start(&self.received) {
+                return self.accepted_legacy();
+            }
+            if self.received.len() == HELLO_LEN {
+                let hello = Hello::decode(&self.received).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "malformed preface")
+                })?;
+                let answer = hello.answer(self.compression);
+                self.answer = Some(Answering {
+                    hello,
+                    answer,
+                    encoded: answer.encode(),
+                    written: 0,
+                });
+                break;
+            }
+            let mut byte = [0; 1];
+            match try_ready!(poll_io(self.stream.read(&mut byte))) {
+                0 => return self.accepted_legacy(),
+                _ => self.received.push(byte[0]),
+            }
+        }
+        let answering = self.answer.as_mut().unwrap();
+        while answering.written < answering.encoded.len() {
+            let unwritten = &answering.encoded[answering.written..];
+            match try_ready!(poll_io(self.stream.write(unwritten))) {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                len => answering.written += len,
+            }
+        }
+        try_ready!(poll_io(self.stream.flush()));
+        match answering.answer.version {
+            Some(version) => {
+                self.stats.add_peer_version(version);
+                Ok(Async::Ready(if answering.answer.compression {
+                    Accepted::Compressed
+                } else {
+                    Accepted::Plain
+                }))
+            }
+            None => {
+                let versions = answering.hello.versions;
+                self.stats.add_unsupported_version(&versions.to_string());
+                Err(unsupported_versions(versions))
+            }
+        }
+    }
+
+    fn accepted_legacy(&self) -> Poll<Accepted, io::Error> {
+        if Versions::SUPPORTED.negotiate(Versions::LEGACY).is_some() {
+            self.stats.add_peer_version(LEGACY_VERSION);
+            Ok(Async::Ready(Accepted::Legacy))
+        } else {
+            self.stats
+                .add_unsupported_version(&Versions::LEGACY.to_string());
+            Err(unsupported_versions(Versions::LEGACY))
+        }
+    }
+}
+
+/// a stream whose first bytes were already read
+pub struct Rewind<S> {
+    prefix: Vec<u8>,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    fn new(inner: S, prefix: Vec<u8>) -> Self {
+        Rewind { prefix, inner }
+    }
+}
+
+impl<S: Read> Read for Rewind<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.prefix.is_empty() {
+            return self.inner.read(buf);
+        }
+        let len = self.prefix.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.prefix[..len]);
+        self.prefix.drain(..len);
+        Ok(len)
+    }
+}
+
+/// a deflate stream over a connection, the bytes before and after the
+/// compression being counted in the stats
+pub struct Compressed<S> {
+    reader: DeflateDecoder<Counting<ReadHalf<S>>>,
+    writer: DeflateEncoder<Counting<WriteHalf<S>>>,
+    stats: StatsCounter,
+}
+
+impl<S> Compressed<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn new(stream: S, stats: StatsCounter) -> Self {
+        let (reader, writer) = stream.split();
+        Compressed {
+            reader: DeflateDecoder::new(Counting {
+                inner: reader,
+                stats: stats.clone(),
+                counted: CompressedBytes::ReceivedWire,
+            }),
+            writer: DeflateEncoder::new(
+                Counting {
+                    inner: writer,
+                    stats: stats.clone(),
+                    counted: CompressedBytes::SentWire,
+                },
+                Compression::fast(),
+            ),
+            stats,
+        }
+    }
+}
+
+impl<S: AsyncRead> Read for Compressed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.stats
+            .add_compressed_bytes(CompressedBytes::ReceivedRaw, len as u64);
+        Ok(len)
+    }
+}
+
+impl<S: AsyncWrite> Write for Compressed<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.writer.write(buf)?;
+        self.stats
+            .add_compressed_bytes(CompressedBytes::SentRaw, len as u64);
+        Ok(len)
+    }
+
+    /// a sync flush of the deflate stream, so the peer can decode every
+    /// byte written so far
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// one side of a connection, its bytes counted in the stats
+struct Counting<S> {
+    inner: S,
+    stats: StatsCounter,
+    counted: CompressedBytes,
+}
+
+impl<S: Read> Read for Counting<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.stats.add_compressed_bytes(self.counted, len as u64);
+        Ok(len)
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Counting<S> {}
+
+impl<S: Write> Write for Counting<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.stats.add_compressed_bytes(self.counted, len as u64);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Counting<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::grpc::preface::MAX_VERSION;
+    use std::cmp;
+
+    /// an in-memory connection: the bytes to read, and the bytes written
+    struct Pipe {
+        input: io::Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+        /// the largest read, to split the input
+        chunk: usize,
+    }
+
+    impl Pipe {
+        fn new(input: Vec<u8>, chunk: usize) -> (Self, Arc<Mutex<Vec<u8>>>) {
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let pipe = Pipe {
+                input: io::Cursor::new(input),
+                output: output.clone(),
+                chunk,
+            };
+            (pipe, output)
+        }
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = cmp::min(buf.len(), self.chunk);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    impl AsyncRead for Pipe {}
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for Pipe {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    /// a peer which connected and sends nothing
+    struct Silent;
+
+    impl Read for Silent {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(would_block())
+        }
+    }
+
+    impl AsyncRead for Silent {}
+
+    impl Write for Silent {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for Silent {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    fn read_all<S: Read>(stream: &mut S) -> Vec<u8> {
+        let mut read = Vec::new();
+        stream.read_to_end(&mut read).unwrap();
+        read
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn plain_connection_is_replayed() {
+        let http2_preface = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        let (pipe, output) = Pipe::new(http2_preface.clone(), 1);
+        let stats = StatsCounter::default();
+        let mut transport = Transport::accept(pipe, stats.clone(), true);
+        future::lazy(move || {
+            transport.write_all(b"settings").unwrap();
+            assert_eq!(read_all(&mut transport), http2_preface);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+        assert_eq!(&output.lock().unwrap()[..], b"settings");
+        assert_eq!(stats.peer_versions(), vec![(LEGACY_VERSION, 1)]);
+    }
+
+    #[test]
+    fn short_plain_connection_is_replayed() {
+        let (pipe, _) = Pipe::new(b"JORM".to_vec(), 3);
+        let mut transport = Transport::accept(pipe, StatsCounter::default(), true);
+        future::lazy(move || {
+            assert_eq!(read_all(&mut transport), b"JORM");
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    fn hello(compression: bool) -> Vec<u8> {
+        Hello {
+            versions: Versions::SUPPORTED,
+            compression,
+        }
+        .encode()
+    }
+
+    fn answer(output: &[u8]) -> Answer {
+        let mut answer = [0; ANSWER_LEN];
+        answer.copy_from_slice(&output[..ANSWER_LEN]);
+        Answer::decode(&answer).unwrap()
+    }
+
+    #[test]
+    fn preface_is_answered_uncompressed() {
+        let data = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        let mut input = hello(true);
+        input.extend_from_slice(&data);
+        let (pipe, output) = Pipe::new(input, 4);
+        let stats = StatsCounter::default();
+        let mut transport = Transport::accept(pipe, stats.clone(), false);
+        future::lazy(move || {
+            transport.write_all(b"settings").unwrap();
+            assert_eq!(read_all(&mut transport), data);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        let output = output.lock().unwrap();
+        let answer = answer(&output);
+        assert_eq!(answer.version, Some(MAX_VERSION));
+        assert!(!answer.compression);
+        assert_eq!(&output[ANSWER_LEN..], b"settings");
+        assert_eq!(stats.peer_versions(), vec![(MAX_VERSION, 1)]);
+    }
+
+    #[test]
+    fn peer_without_a_common_version_is_refused() {
+        let input = Hello {
+            versions: Versions {
+                min: MAX_VERSION + 1,
+                max: MAX_VERSION + 2,
+            },
+            compression: false,
+        }
+        .encode();
+        let (pipe, output) = Pipe::new(input, 16);
+        let stats = StatsCounter::default();
+        let mut transport = Transport::accept(pipe, stats.clone(), true);
+        future::lazy(move || {
+            assert!(transport.read(&mut [0; 16]).is_err());
+            assert!(transport.write(b"settings").is_err());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        let output = output.lock().unwrap();
+        assert_eq!(output.len(), ANSWER_LEN);
+        assert_eq!(answer(&output).version, None);
+        let expected = format!("{}-{}", MAX_VERSION + 1, MAX_VERSION + 2);
+        assert_eq!(stats.unsupported_versions(), vec![(expected, 1)]);
+        assert!(stats.peer_versions().is_empty());
+    }
+
+    #[test]
+    fn client_sets_the_negotiated_version() {
+        let negotiated = Negotiated::default();
+        let answer = Answer {
+            version: None,
+            versions: Versions {
+                min: MAX_VERSION + 1,
+                max: MAX_VERSION + 1,
+            },
+            compression: false,
+        };
+        let (pipe, _) = Pipe::new(Vec::new(), 1);
+        assert!(answered_transport(pipe, answer, None, &negotiated).is_err());
+        assert_eq!(
+            negotiated.get(),
+            Some(Negotiation::Unsupported(answer.versions))
+        );
+
+        assert!(negotiated_legacy(&negotiated).is_ok());
+        assert_eq!(negotiated.get(), Some(Negotiation::Version(LEGACY_VERSION)));
+    }
+
+    #[test]
+    fn compressed_connection_is_acknowledged() {
+        let data = b"the blocks of the chain, the blocks of the chain".to_vec();
+        let mut input = hello(true);
+        input.extend(deflate(&data));
+        let (pipe, output) = Pipe::new(input, 5);
+        let stats = StatsCounter::default();
+        let mut transport = Transport::accept(pipe, stats.clone(), true);
+        future::lazy(move || {
+            transport.write_all(&data).unwrap();
+            transport.flush().unwrap();
+            assert_eq!(read_all(&mut transport), data);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        let output = output.lock().unwrap();
+        assert!(answer(&output).compression);
+        let mut decoder = DeflateDecoder::new(&output[ANSWER_LEN..]);
+        let mut written = vec![0; 48];
+        decoder.read_exact(&mut written).unwrap();
+        assert_eq!(
+            &written[..],
+            &b"the blocks of the chain, the blocks of the chain"[..]
+        );
+
+        let bytes: std::collections::HashMap<_, _> = stats
+            .compressed_bytes()
+            .into_iter()
+            .map(|(direction, layer, count)| ((direction, layer), count))
+            .collect();
+        assert_eq!(bytes[&("received", "raw")], 48);
+        assert_eq!(bytes[&("sent", "raw")], 48);
+        assert_eq!(bytes[&("sent", "wire")], (output.len() - ANSWER_LEN) as u64);
+    }
+
+    #[test]
+    fn silent_peer_is_disconnected_after_the_preface_timeout() {
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let timeout = Duration::from_millis(20);
+        let mut transport =
+            Transport::accept_within(Silent, StatsCounter::default(), true, timeout);
+        let read = future::poll_fn(move || transport.poll_read(&mut [0; 16]));
+
+        match runtime.block_on(read) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            Ok(read) => panic!("read {} bytes from a silent peer", read),
+        }
+    }
+
+    #[test]
+    fn peers_not_answering_the_preface_are_remembered_for_a_while() {
+        let peers = LegacyPeers::default();
+        let now = Instant::now();
+        peers.insert("192.0.2.1:3000".to_owned(), now);
+
+        assert!(peers.contains("192.0.2.1:3000", now + Duration::from_secs(60)));
+        assert!(!peers.contains("192.0.2.1:3001", now));
+        assert!(!peers.contains("192.0.2.1:3000", now + LEGACY_RETRY));
+        assert!(!peers.contains("192.0.2.1:3000", now));
+    }
+
+    #[test]
+    fn legacy_peers_are_bounded() {
+        let peers = LegacyPeers::default();
+        let now = Instant::now();
+        for port in 0..LEGACY_PEERS_CAPACITY {
+            peers.insert(format!("192.0.2.1:{}", port), now);
+        }
+        let later = now + LEGACY_RETRY;
+        peers.insert("192.0.2.2:3000".to_owned(), later);
+
+        assert_eq!(peers.0.lock().unwrap().len(), 1);
+        assert!(peers.contains("192.0.2.2:3000", later));
+    }
+
+    mod interop {
+        //! HTTP/2 exchanges over TCP between a node compressing its
+        //! connections and peers not sending nor answering the preface
+
+        use super::*;
+        use hyper::{server::conn::Http, service::service_fn_ok, Body, Client, Response};
+        use std::net::SocketAddr;
+        use tokio::net::TcpListener;
+        use tokio::runtime::Runtime;
+
+        const BODY: &str = "the blocks of the chain, the blocks of the chain";
+
+        fn compressed_bytes(stats: &StatsCounter) -> u64 {
+            stats
+                .compressed_bytes()
+                .into_iter()
+                .map(|(_, _, bytes)| bytes)
+                .sum()
+        }
+
+        fn serve_http2<S>(stream: S) -> impl Future<Item = (), Error = ()>
+        where
+            S: AsyncRead + AsyncWrite + Send + 'static,
+        {
+            Http::new()
+                .http2_only(true)
+                .serve_connection(stream, service_fn_ok(|_| Response::new(Body::from(BODY))))
+                .map_err(|_| ())
+        }
+
+        /// a server of the HTTP/2 connections, each accepted with `accept`
+        fn listen<F, S>(runtime: &mut Runtime, accept: F) -> SocketAddr
+        where
+            F: Fn(TcpStream) -> S + Send + 'static,
+            S: AsyncRead + AsyncWrite + Send + 'static,
+        {
+            let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+            let address = listener.local_addr().unwrap();
+            runtime.spawn(listener.incoming().map_err(|_| ()).for_each(move |stream| {
+                tokio::spawn(serve_http2(accept(stream)));
+                Ok(())
+            }));
+            address
+        }
+
+        fn get<C>(runtime: &mut Runtime, connector: C, address: SocketAddr) -> String
+        where
+            C: Connect + Sync + 'static,
+            C::Transport: 'static,
+            C::Future: 'static,
+        {
+            let client = Client::builder()
+                .http2_only(true)
+                .build::<_, Body>(connector);
+            let uri = format!("http://{}/", address).parse().unwrap();
+            let body = runtime
+                .block_on(client.get(uri).and_then(|res| res.into_body().concat2()))
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        }
+
+        #[test]
+        fn compressing_node_serves_a_peer_without_the_preface() {
+            let mut runtime = Runtime::new().unwrap();
+            let stats = StatsCounter::default();
+            let server_stats = stats.clone();
+            let address = listen(&mut runtime, move |stream| {
+                Transport::accept(stream, server_stats.clone(), true)
+            });
+
+            // the connector of a node released before the preface
+            let body = get(&mut runtime, HttpConnector::new(1), address);
+
+            assert_eq!(body, BODY);
+            assert_eq!(stats.peer_versions(), vec![(LEGACY_VERSION, 1)]);
+            assert_eq!(compressed_bytes(&stats), 0);
+        }
+
+        #[test]
+        fn compressing_node_connects_to_a_peer_without_the_preface() {
+            let mut runtime = Runtime::new().unwrap();
+            let address = listen(&mut runtime, |stream| stream);
+            let stats = StatsCounter::default();
+            let connector = Connector::new(HttpConnector::new(1), Some(stats.clone()));
+            let negotiated = connector.negotiated();
+
+            let body = get(&mut runtime, connector, address);
+
+            assert_eq!(body, BODY);
+            assert_eq!(negotiated.get(), Some(Negotiation::Version(LEGACY_VERSION)));
+            assert_eq!(compressed_bytes(&stats), 0);
+        }
+
+        #[test]
+        fn compressing_nodes_compress_their_connection() {
+            let mut runtime = Runtime::new().unwrap();
+            let server_stats = StatsCounter::default();
+            let accept_stats = server_stats.clone();
+            let address = listen(&mut runtime, move |stream| {
+                Transport::accept(stream, accept_stats.clone(), true)
+            });
+            let client_stats = StatsCounter::default();
+            let connector = Connector::new(HttpConnector::new(1), Some(client_stats.clone()));
+            let negotiated = connector.negotiated();
+
+            let body = get(&mut runtime, connector, address);
+
+            assert_eq!(body, BODY);
+            assert_eq!(negotiated.get(), Some(Negotiation::Version(MAX_VERSION)));
+            assert!(compressed_bytes(&client_stats) > 0);
+            assert!(compressed_bytes(&server_stats) > 0);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! The limits on the messages of the peers, checked on the HTTP/2 bodies
+//! of their requests before the gRPC codec of the node service buffers and
+//! decodes them.
+//!
+//! The bodies of the requests are wrapped by the service of the incoming
+//! connections on their way to the node service (see `standard`). A message
+//! over the limits fails the body, which the codec answers with an error
+//! status, and the whole connection.
+
+use crate::network::message_limits::{Framing, Limit, Violation};
+use futures::sync::mpsc;
+use http::header::HeaderMap;
+use hyper::{body::Payload, Body, Chunk};
+use thiserror::Error;
+use tokio::prelude::*;
+
+#[derive(Debug, Error)]
+pub enum BodyError {
+    #[error("HTTP/2 stream error")]
+    Http(#[source] hyper::Error),
+    #[error("message over the limits")]
+    OverLimits,
+}
+
+/// a body sent by a peer, failed on a message over the limit
+pub struct LimitedBody {
+    body: Body,
+    framing: Framing,
+    tripwire: Tripwire,
+}
+
+impl LimitedBody {
+    pub fn new(body: Body, limit: Limit, tripwire: Tripwire) -> Self {
+        LimitedBody {
+            body,
+            framing: Framing::new(limit),
+            tripwire,
+        }
+    }
+}
+
+impl http_body::Body for LimitedBody {
+    type Data = Chunk;
+    type Error = BodyError;
+
+    fn poll_data(&mut self) -> Poll<Option<Chunk>, BodyError> {
+        let chunk = try_ready!(self.body.poll_data().map_err(BodyError::Http));
+        if let Some(chunk) = &chunk {
+            if let Err(violation) = self.framing.check(chunk) {
+                self.tripwire.trip(violation);
+                return Err(BodyError::OverLimits);
+            }
+        }
+        Ok(Async::Ready(chunk))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, BodyError> {
+        self.body.poll_trailers().map_err(BodyError::Http)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+}
+
+/// the violations of the limits on the streams of a connection
+#[derive(Clone)]
+pub struct Tripwire(mpsc::UnboundedSender<Violation>);
+
+/// the first violation of the limits on the streams of a connection
+pub struct Tripped(mpsc::UnboundedReceiver<Violation>);
+
+pub fn tripwire() -> (Tripwire, Tripped) {
+    let (sender, receiver) = mpsc::unbounded();
+    (Tripwire(sender), Tripped(receiver))
+}
+
+impl Tripwire {
+    fn trip(&self, violation: Violation) {
+        let _ = self.0.unbounded_send(violation);
+    }
+}
+
+impl Tripped {
+    /// drive the connection `future`, failed on the first violation of the
+    /// limits on its streams
+    pub fn guard<F>(self, future: F) -> impl Future<Item = F::Item, Error = F::Error>
+    where
+        F: Future,
+        F::Error: From<Violation>,
+    {
+        future.select2(self).then(|result| match result {
+            Ok(future::Either::A((item, _))) => Ok(item),
+            Ok(future::Either::B((violation, _))) => Err(violation.into()),
+            Err(future::Either::A((e, _))) => Err(e),
+            Err(future::Either::B(((), _))) => unreachable!(),
+        })
+    }
+}
+
+impl Future for Tripped {
+    type Item = Violation;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Violation, ()> {
+        match self.0.poll() {
+            Ok(Async::Ready(Some(violation))) => Ok(Async::Ready(violation)),
+            // the connection does not check its streams anymore
+            _ => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+
+    fn frame(len: usize) -> Vec<u8> {
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(len as u32).to_be_bytes());
+        frame.extend(std::iter::repeat(0xff).take(len));
+        frame
+    }
+
+    fn limited(chunks: Vec<Vec<u8>>, tripwire: Tripwire) -> LimitedBody {
+        let body = Body::wrap_stream(stream::iter_ok::<_, hyper::Error>(chunks));
+        let limit = Limit::request("/iohk.chain.node.Node/PushHeaders");
+        LimitedBody::new(body, limit, tripwire)
+    }
+
+    /// read the body to its end or its first error
+    fn read(mut body: LimitedBody) -> Result<usize, BodyError> {
+        future::poll_fn(move || {
+            let mut read = 0;
+            while let Some(chunk) = try_ready!(body.poll_data()) {
+                read += chunk.len();
+            }
+            Ok(Async::Ready(read))
+        })
+        .wait()
+    }
+
+    #[test]
+    fn messages_within_the_limits_go_through() {
+        let (tripwire, tripped) = tripwire();
+        let stream = frame(100);
+        let (head, tail) = stream.split_at(3);
+        let body = limited(vec![head.to_vec(), tail.to_vec()], tripwire);
+
+        assert_eq!(read(body).unwrap(), stream.len());
+        let connection = tripped.guard(future::ok::<_, Violation>(()));
+        assert!(connection.wait().is_ok());
+    }
+
+    #[test]
+    fn message_over_the_limits_fails_the_connection() {
+        let (tripwire, tripped) = tripwire();
+        let body = limited(vec![frame(8192)], tripwire);
+
+        match read(body) {
+            Err(BodyError::OverLimits) => {}
+            res => panic!("unexpected outcome {:?}", res.map(|_| ())),
+        }
+        let connection = tripped.guard(future::empty::<(), Violation>());
+        match connection.wait() {
+            Err(Violation::TooLarge { message, .. }) => assert_eq!(message, "header"),
+            res => panic!("unexpected outcome {:?}", res),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 mod client;
+mod compression;
+mod limits;
+mod preface;
 mod server;
+mod standard;
 
 use crate::{
     blockcfg::{Block, BlockDate, Fragment, FragmentId, Header, HeaderHash},
@@ -10,8 +14,10 @@ use crate::{
 };
 
 pub use self::client::{
-    connect, fetch_block, ConnectError, ConnectFuture, Connection, FetchBlockError,
+    connect, connect_negotiated, fetch_block, ConnectError, ConnectFuture, Connection,
+    FetchBlockError,
 };
+pub use self::compression::{Negotiated, Negotiation};
 pub use self::server::run_listen_socket;
 
 impl network_grpc::client::ProtocolConfig for BlockConfig {
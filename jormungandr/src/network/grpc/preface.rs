@@ -0,0 +1,268 @@
+//! The preface of the P2P connections, negotiating the version of the node
+//! protocol and the compression before HTTP/2.
+//!
+//! A client opens the connection with a `Hello`: the range of the versions
+//! of the protocol it speaks and whether it asks for the compression. The
+//! listening node answers with its own range and the version chosen, the
+//! highest version in both ranges, or no version if the ranges do not
+//! overlap, in which case the connection is closed. A node released before
+//! the preface does not send nor answer it: it speaks `LEGACY_VERSION`.
+//!
+//! The wire changes are rolled out by raising `MAX_VERSION` first, the
+//! nodes speaking the new version with each other and the previous one
+//! with the other nodes, and raising `MIN_VERSION` once the nodes speaking
+//! only the previous version are gone.
+
+use std::fmt::{self, Display};
+
+/// the oldest version of the node protocol spoken by this node
+pub const MIN_VERSION: u16 = 1;
+/// the newest version of the node protocol spoken by this node
+pub const MAX_VERSION: u16 = 1;
+/// the version of the nodes not sending nor answering the preface
+pub const LEGACY_VERSION: u16 = 1;
+
+/// the first bytes of a `Hello`, which an HTTP/2 server does not accept as
+/// its preface
+const MAGIC: &[u8] = b"JORMP/1\n";
+/// the first byte of an `Answer`, an HTTP/2 server starting its connection
+/// with the length of a frame
+const ANSWER_MARK: u8 = b'J';
+/// the version of an `Answer` not choosing any
+const NO_VERSION: u16 = 0;
+const COMPRESSION: u8 = 0x01;
+
+pub const HELLO_LEN: usize = MAGIC.len() + 5;
+pub const ANSWER_LEN: usize = 8;
+
+/// a range of versions of the node protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Versions {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Versions {
+    /// the versions spoken by this node
+    pub const SUPPORTED: Versions = Versions {
+        min: MIN_VERSION,
+        max: MAX_VERSION,
+    };
+
+    /// the only version spoken by the nodes released before the preface
+    pub const LEGACY: Versions = Versions {
+        min: LEGACY_VERSION,
+        max: LEGACY_VERSION,
+    };
+
+    /// the highest version in both ranges
+    pub fn negotiate(self, other: Versions) -> Option<u16> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        if min <= max {
+            Some(max)
+        } else {
+            None
+        }
+    }
+
+    fn from_be_bytes(bytes: &[u8]) -> Option<Self> {
+        let versions = Versions {
+            min: u16::from_be_bytes([bytes[0], bytes[1]]),
+            max: u16::from_be_bytes([bytes[2], bytes[3]]),
+        };
+        if versions.min == NO_VERSION || versions.min > versions.max {
+            None
+        } else {
+            Some(versions)
+        }
+    }
+
+    fn put_be_bytes(self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.min.to_be_bytes());
+        buffer.extend_from_slice(&self.max.to_be_bytes());
+    }
+}
+
+impl Display for Versions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// the preface of a client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub versions: Versions,
+    pub compression: bool,
+}
+
+impl Hello {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut hello = Vec::with_capacity(HELLO_LEN);
+        hello.extend_from_slice(MAGIC);
+        self.versions.put_be_bytes(&mut hello);
+        hello.push(if self.compression { COMPRESSION } else { 0 });
+        hello
+    }
+
+    /// whether the first bytes received may still be a `Hello`
+    pub fn may_start(received: &[u8]) -> bool {
+        let len = received.len().min(MAGIC.len());
+        MAGIC[..len] == received[..len]
+    }
+
+    /// the `Hello` of `HELLO_LEN` bytes, `None` if they are not one
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != HELLO_LEN || !bytes.starts_with(MAGIC) {
+            return None;
+        }
+        let bytes = &bytes[MAGIC.len()..];
+        Some(Hello {
+            versions: Versions::from_be_bytes(&bytes[..4])?,
+            compression: bytes[4] & COMPRESSION != 0,
+        })
+    }
+
+    /// the answer of the listening node to the hello, the node compressing
+    /// the connection if both ask for it
+    pub fn answer(&self, compression: bool) -> Answer {
+        let version = Versions::SUPPORTED.negotiate(self.versions);
+        Answer {
+            version,
+            versions: Versions::SUPPORTED,
+            compression: version.is_some() && compression && self.compression,
+        }
+    }
+}
+
+/// the answer of the listening node to a `Hello`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Answer {
+    /// the version chosen, `None` if the ranges do not overlap
+    pub version: Option<u16>,
+    /// the versions spoken by the listening node
+    pub versions: Versions,
+    pub compression: bool,
+}
+
+impl Answer {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut answer = Vec::with_capacity(ANSWER_LEN);
+        answer.push(ANSWER_MARK);
+        answer.extend_from_slice(&self.version.unwrap_or(NO_VERSION).to_be_bytes());
+        self.versions.put_be_bytes(&mut answer);
+        answer.push(if self.compression { COMPRESSION } else { 0 });
+        answer
+    }
+
+    /// the answer in the first `ANSWER_LEN` bytes received, `None` if the
+    /// peer did not answer the preface
+    pub fn decode(bytes: &[u8; ANSWER_LEN]) -> Option<Self> {
+        if bytes[0] != ANSWER_MARK {
+            return None;
+        }
+        let version = match u16::from_be_bytes([bytes[1], bytes[2]]) {
+            NO_VERSION => None,
+            version => Some(version),
+        };
+        Some(Answer {
+            version,
+            versions: Versions::from_be_bytes(&bytes[3..7])?,
+            compression: bytes[7] & COMPRESSION != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(min: u16, max: u16) -> Versions {
+        Versions { min, max }
+    }
+
+    #[test]
+    fn highest_common_version_is_chosen() {
+        assert_eq!(versions(1, 3).negotiate(versions(2, 5)), Some(3));
+        assert_eq!(versions(2, 5).negotiate(versions(1, 3)), Some(3));
+        assert_eq!(versions(1, 1).negotiate(versions(1, 4)), Some(1));
+        assert_eq!(versions(1, 2).negotiate(versions(3, 4)), None);
+    }
+
+    #[test]
+    fn hello_round_trip() {
+        let hello = Hello {
+            versions: versions(1, 3),
+            compression: true,
+        };
+        let encoded = hello.encode();
+        assert_eq!(encoded.len(), HELLO_LEN);
+        assert_eq!(Hello::decode(&encoded), Some(hello));
+        assert!(Hello::may_start(&encoded[..3]));
+    }
+
+    #[test]
+    fn http2_preface_is_not_a_hello() {
+        let http2_preface = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+        assert!(!Hello::may_start(&http2_preface[..1]));
+        assert_eq!(Hello::decode(&http2_preface[..HELLO_LEN]), None);
+    }
+
+    #[test]
+    fn hello_with_an_empty_range_is_refused() {
+        let mut encoded = Hello {
+            versions: versions(1, 1),
+            compression: false,
+        }
+        .encode();
+        encoded[MAGIC.len() + 1] = 2;
+        assert_eq!(Hello::decode(&encoded), None);
+    }
+
+    #[test]
+    fn answer_round_trip() {
+        let hello = Hello {
+            versions: Versions::SUPPORTED,
+            compression: true,
+        };
+        for compression in [false, true].iter() {
+            let answer = hello.answer(*compression);
+            assert_eq!(answer.version, Some(MAX_VERSION));
+            assert_eq!(answer.compression, *compression);
+            let mut encoded = [0; ANSWER_LEN];
+            encoded.copy_from_slice(&answer.encode());
+            assert_eq!(Answer::decode(&encoded), Some(answer));
+        }
+    }
+
+    #[test]
+    fn answer_to_a_newer_client_without_a_common_version() {
+        let hello = Hello {
+            versions: versions(MAX_VERSION + 1, MAX_VERSION + 2),
+            compression: true,
+        };
+        let answer = hello.answer(true);
+        assert_eq!(answer.version, None);
+        assert!(!answer.compression);
+        let mut encoded = [0; ANSWER_LEN];
+        encoded.copy_from_slice(&answer.encode());
+        assert_eq!(Answer::decode(&encoded), Some(answer));
+    }
+
+    #[test]
+    fn http2_settings_frame_is_not_an_answer() {
+        let settings = [0, 0, 18, 4, 0, 0, 0, 0];
+        assert_eq!(Answer::decode(&settings), None);
+    }
+
+    #[test]
+    fn versions_are_displayed_as_ranges() {
+        assert_eq!(versions(1, 1).to_string(), "1");
+        assert_eq!(versions(1, 3).to_string(), "1-3");
+    }
+}
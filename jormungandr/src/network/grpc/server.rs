@@ -1,6 +1,12 @@
-use super::super::{service::NodeService, Channels, GlobalStateR, ListenError};
+use super::super::{
+    audit_log::{Direction, Outcome},
+    message_limits::PeerLimits,
+    service::NodeService,
+    Channels, GlobalStateR, ListenError,
+};
+use super::{compression::Transport, standard};
 use crate::settings::start::network::Listen;
-use network_grpc::server::{self, Server};
+use network_grpc::server::{self, NodeServer};
 
 use tk_listen::ListenExt;
 use tokio::prelude::*;
@@ -23,8 +29,13 @@ pub fn run_listen_socket(
             let max_connections = state.config.max_connections;
             let fold_logger = state.logger().clone();
             let err_logger = state.logger().clone();
-            let node_server = NodeService::new(channels, state);
-            let mut server = Server::new(node_server);
+            let audit_state = state.clone();
+            let relays =
+                super::super::inbound_relays(state.config.role, &state.config.trusted_peers);
+            let compression = state.config.compression;
+            let stats_counter = state.stats_counter.clone();
+            let lifecycle = state.lifecycle.clone();
+            let node_service = NodeService::new(channels, state);
 
             let future = listener_stream
                 .map_err(move |err| {
@@ -36,8 +47,8 @@ pub fn run_listen_socket(
                 })
                 .filter_map(move |stream| {
                     // received incoming connection
-                    let conn_logger = match stream.peer_addr() {
-                        Ok(addr) => fold_logger.new(o!("peer_addr" => addr)),
+                    let (peer_addr, conn_logger) = match stream.peer_addr() {
+                        Ok(addr) => (addr, fold_logger.new(o!("peer_addr" => addr))),
                         Err(e) => {
                             debug!(
                                 fold_logger,
@@ -46,34 +57,61 @@ pub fn run_listen_socket(
                             return None;
                         }
                     };
+                    if !super::super::admits_inbound(&relays, peer_addr) {
+                        info!(
+                            conn_logger,
+                            "connection rejected, the peer is not a relay of the core node"
+                        );
+                        audit_state.audit(
+                            Direction::Inbound,
+                            None,
+                            peer_addr,
+                            Outcome::Failed,
+                            Some("not a relay of the core node"),
+                        );
+                        return None;
+                    }
                     info!(
                         conn_logger,
                         "incoming connection on {}",
                         stream.local_addr().unwrap(),
                     );
+                    audit_state.audit(Direction::Inbound, None, peer_addr, Outcome::Accepted, None);
 
-                    let conn = server.serve(stream).then(move |res| {
-                        use network_grpc::server::Error;
-
-                        match res {
+                    let conn_state = audit_state.clone();
+                    let stream = Transport::accept(stream, stats_counter.clone(), compression);
+                    // the peer is rejected on a request over the limits with
+                    // the node ID of its subscriptions
+                    let limits = PeerLimits::new(audit_state.clone(), conn_logger.clone());
+                    let node = NodeServer::new(node_service.for_connection(limits.clone()));
+                    let conn = standard::serve(stream, node, lifecycle.clone());
+                    let conn = conn.then(move |res| {
+                        let reason = match res {
                             Ok(()) => {
                                 info!(conn_logger, "incoming connection closed");
+                                None
                             }
-                            Err(Error::Protocol(e)) => {
+                            Err(standard::Error::Http(e)) => {
                                 info!(
                                     conn_logger,
                                     "incoming HTTP/2 connection error";
                                     "reason" => %e,
                                 );
+                                Some(e.to_string())
                             }
-                            Err(e) => {
-                                warn!(
-                                    conn_logger,
-                                    "incoming connection failed";
-                                    "error" => ?e,
-                                );
+                            Err(standard::Error::Limit(violation)) => {
+                                let reason = violation.to_string();
+                                limits.reject(violation);
+                                Some(reason)
                             }
-                        }
+                        };
+                        conn_state.audit(
+                            Direction::Inbound,
+                            limits.node_id(),
+                            peer_addr,
+                            Outcome::Closed,
+                            reason.as_ref().map(String::as_str),
+                        );
                         Ok(())
                     });
                     Some(conn)
@@ -0,0 +1,639 @@
+//! The standard gRPC services served on the P2P port next to the node
+//! service: the health checking (`grpc.health.v1.Health`) and the
+//! reflection (`grpc.reflection.v1alpha.ServerReflection`).
+//!
+//! The servers of the standard services are generated from their protocol
+//! files by the build script, which also writes the descriptors of all the
+//! services served: the reflection lists the services and describes them,
+//! the node protocol included.
+//!
+//! The incoming connections are served by a HTTP/2 server routing the
+//! requests of the standard services to their servers and handing the
+//! other requests to the gRPC service of the node protocol. The messages of
+//! all the requests are checked against the limits of their methods before
+//! the codecs read them (see `limits`).
+
+use super::limits::{self, LimitedBody, Tripwire};
+use crate::{
+    lifecycle::Lifecycle,
+    network::message_limits::{Limit, Violation},
+};
+use bytes::{Buf, Bytes};
+use http::header::HeaderMap;
+use http_body::Body as HttpBody;
+use hyper::{body::Payload, server::conn::Http, service::Service, Body, Chunk, Request, Response};
+use jormungandr_lib::interfaces::NodeState;
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use thiserror::Error;
+use tokio::prelude::*;
+use tower_grpc::{BoxBody, Code, Status, Streaming};
+
+use std::error;
+
+mod gen {
+    pub mod health {
+        include!(concat!(env!("OUT_DIR"), "/grpc.health.v1.rs"));
+    }
+    pub mod reflection {
+        include!(concat!(env!("OUT_DIR"), "/grpc.reflection.v1alpha.rs"));
+    }
+}
+
+use self::gen::health::{
+    health_check_response::ServingStatus,
+    server::{health, Health, HealthServer},
+    HealthCheckRequest, HealthCheckResponse,
+};
+use self::gen::reflection::{
+    server::{server_reflection, ServerReflection, ServerReflectionServer},
+    server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse,
+    ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest,
+    ServerReflectionResponse, ServiceResponse,
+};
+
+type BoxError = Box<dyn error::Error + Send + Sync>;
+
+const NODE_SERVICE: &str = "iohk.chain.node.Node";
+const HEALTH_SERVICE: &str = "grpc.health.v1.Health";
+const REFLECTION_SERVICE: &str = "grpc.reflection.v1alpha.ServerReflection";
+const SERVICES: [&str; 3] = [NODE_SERVICE, HEALTH_SERVICE, REFLECTION_SERVICE];
+
+const HEALTH_PATH: &str = "/grpc.health.v1.Health/";
+const REFLECTION_PATH: &str = "/grpc.reflection.v1alpha.ServerReflection/";
+
+lazy_static! {
+    /// the descriptors of the files of the services served, written by the
+    /// build script
+    static ref DESCRIPTORS: Descriptors =
+        Descriptors::decode(include_bytes!(concat!(env!("OUT_DIR"), "/services.bin")));
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP/2 connection error")]
+    Http(#[source] hyper::Error),
+    #[error("{0}")]
+    Limit(#[from] Violation),
+}
+
+/// Serves an incoming connection with the standard services and the gRPC
+/// service `node` of the node protocol.
+pub fn serve<S, N, B>(
+    stream: S,
+    node: N,
+    lifecycle: Lifecycle,
+) -> impl Future<Item = (), Error = Error>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+    N: tower_service::Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    N::Error: Into<BoxError>,
+    N::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (tripwire, tripped) = limits::tripwire();
+    let front = Front {
+        node,
+        health: HealthServer::new(HealthService { lifecycle }),
+        reflection: ServerReflectionServer::new(ReflectionService),
+        tripwire,
+    };
+    let connection = Http::new()
+        .http2_only(true)
+        .serve_connection(stream, front)
+        .map_err(Error::Http);
+    tripped.guard(connection)
+}
+
+/// the HTTP/2 service of an incoming connection, routing the requests to
+/// the servers of the standard services and to the node service
+struct Front<N> {
+    node: N,
+    health: HealthServer<HealthService>,
+    reflection: ServerReflectionServer<ReflectionService>,
+    tripwire: Tripwire,
+}
+
+impl<N, B> Service for Front<N>
+where
+    N: tower_service::Service<Request<BoxBody>, Response = Response<B>>,
+    N::Error: Into<BoxError>,
+    N::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type ReqBody = Body;
+    type ResBody = ResponseBody<B>;
+    type Error = BoxError;
+    type Future = Box<dyn Future<Item = Response<ResponseBody<B>>, Error = BoxError> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), BoxError> {
+        self.node.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let path = request.uri().path().to_owned();
+        let limit = Limit::request(&path);
+        let tripwire = self.tripwire.clone();
+        let request =
+            request.map(|body| BoxBody::map_from(LimitedBody::new(body, limit, tripwire)));
+        if path.starts_with(HEALTH_PATH) {
+            Box::new(
+                tower_service::Service::call(&mut self.health, request)
+                    .map(|response| response.map(ResponseBody::Health))
+                    .map_err(|never| match never {}),
+            )
+        } else if path.starts_with(REFLECTION_PATH) {
+            Box::new(
+                tower_service::Service::call(&mut self.reflection, request)
+                    .map(|response| response.map(ResponseBody::Reflection))
+                    .map_err(|never| match never {}),
+            )
+        } else {
+            Box::new(
+                self.node
+                    .call(request)
+                    .map(|response| response.map(ResponseBody::Node))
+                    .map_err(Into::into),
+            )
+        }
+    }
+}
+
+pub enum ResponseBody<B> {
+    Node(B),
+    Health(health::ResponseBody<HealthService>),
+    Reflection(server_reflection::ResponseBody<ReflectionService>),
+}
+
+impl<B> Payload for ResponseBody<B>
+where
+    B: HttpBody + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Data = Chunk;
+    type Error = BoxError;
+
+    fn poll_data(&mut self) -> Poll<Option<Chunk>, BoxError> {
+        match self {
+            ResponseBody::Node(body) => poll_chunk(body),
+            ResponseBody::Health(body) => poll_chunk(body),
+            ResponseBody::Reflection(body) => poll_chunk(body),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, BoxError> {
+        match self {
+            ResponseBody::Node(body) => body.poll_trailers().map_err(Into::into),
+            ResponseBody::Health(body) => body.poll_trailers().map_err(Into::into),
+            ResponseBody::Reflection(body) => body.poll_trailers().map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ResponseBody::Node(body) => body.is_end_stream(),
+            ResponseBody::Health(body) => body.is_end_stream(),
+            ResponseBody::Reflection(body) => body.is_end_stream(),
+        }
+    }
+}
+
+fn poll_chunk<B>(body: &mut B) -> Poll<Option<Chunk>, BoxError>
+where
+    B: HttpBody,
+    B::Error: Into<BoxError>,
+{
+    let data = try_ready!(body.poll_data().map_err(Into::into));
+    Ok(Async::Ready(
+        data.map(|data| Chunk::from(data.collect::<Bytes>())),
+    ))
+}
+
+/// the health checking: the node serves all its services once it is synced,
+/// and until it shuts down
+#[derive(Clone)]
+pub struct HealthService {
+    lifecycle: Lifecycle,
+}
+
+fn is_known_service(service: &str) -> bool {
+    service.is_empty() || SERVICES.contains(&service)
+}
+
+fn serving_status(state: &NodeState) -> ServingStatus {
+    match state {
+        NodeState::Running => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    }
+}
+
+fn health_response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+impl Health for HealthService {
+    type CheckFuture = future::FutureResult<tower_grpc::Response<HealthCheckResponse>, Status>;
+    type WatchStream = Box<dyn Stream<Item = HealthCheckResponse, Error = Status> + Send>;
+    type WatchFuture = future::FutureResult<tower_grpc::Response<Self::WatchStream>, Status>;
+
+    fn check(&mut self, request: tower_grpc::Request<HealthCheckRequest>) -> Self::CheckFuture {
+        let service = &request.get_ref().service;
+        if is_known_service(service) {
+            let status = serving_status(&self.lifecycle.state());
+            future::ok(tower_grpc::Response::new(health_response(status)))
+        } else {
+            future::err(Status::new(
+                Code::NotFound,
+                format!("unknown service {}", service),
+            ))
+        }
+    }
+
+    /// sends the current status, then each change of the status. An
+    /// unknown service is answered with `SERVICE_UNKNOWN` and the stream
+    /// kept open, as the protocol asks
+    fn watch(&mut self, request: tower_grpc::Request<HealthCheckRequest>) -> Self::WatchFuture {
+        let statuses: Self::WatchStream = if is_known_service(&request.get_ref().service) {
+            let mut last = None;
+            Box::new(
+                self.lifecycle
+                    .watch()
+                    .map(|state| serving_status(&state))
+                    .filter(move |status| last.replace(*status) != Some(*status))
+                    .map(health_response)
+                    .map_err(|()| Status::new(Code::Unavailable, "the node is shutting down")),
+            )
+        } else {
+            Box::new(
+                stream::once(Ok(health_response(ServingStatus::ServiceUnknown)))
+                    .chain(future::empty().into_stream()),
+            )
+        };
+        future::ok(tower_grpc::Response::new(statuses))
+    }
+}
+
+/// the reflection, answering from the descriptors of the services served
+#[derive(Clone)]
+pub struct ReflectionService;
+
+impl ServerReflection for ReflectionService {
+    type ServerReflectionInfoStream =
+        Box<dyn Stream<Item = ServerReflectionResponse, Error = Status> + Send>;
+    type ServerReflectionInfoFuture =
+        future::FutureResult<tower_grpc::Response<Self::ServerReflectionInfoStream>, Status>;
+
+    fn server_reflection_info(
+        &mut self,
+        request: tower_grpc::Request<Streaming<ServerReflectionRequest>>,
+    ) -> Self::ServerReflectionInfoFuture {
+        let replies = request
+            .into_inner()
+            .map(|request| DESCRIPTORS.reply(request));
+        future::ok(tower_grpc::Response::new(Box::new(replies)))
+    }
+}
+
+/// the descriptors of the files of the services, with their encoding sent
+/// to the clients of the reflection
+struct Descriptors {
+    files: Vec<(FileDescriptorProto, Vec<u8>)>,
+}
+
+impl Descriptors {
+    fn decode(bytes: &[u8]) -> Self {
+        let set = FileDescriptorSet::decode(bytes)
+            .expect("the build script writes a valid set of descriptors");
+        let files = set
+            .file
+            .into_iter()
+            .map(|file| {
+                let mut encoded = Vec::with_capacity(file.encoded_len());
+                file.encode(&mut encoded)
+                    .expect("the buffer grows to the encoded length");
+                (file, encoded)
+            })
+            .collect();
+        Descriptors { files }
+    }
+
+    fn reply(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let message_response = match &request.message_request {
+            Some(MessageRequest::ListServices(_)) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: SERVICES
+                        .iter()
+                        .map(|name| ServiceResponse {
+                            name: name.to_string(),
+                        })
+                        .collect(),
+                })
+            }
+            Some(MessageRequest::FileByFilename(name)) => {
+                self.files_response(self.by_name(name), || format!("unknown file {}", name))
+            }
+            Some(MessageRequest::FileContainingSymbol(symbol)) => self.files_response(
+                self.files
+                    .iter()
+                    .position(|(file, _)| declares(file, symbol)),
+                || format!("unknown symbol {}", symbol),
+            ),
+            Some(MessageRequest::FileContainingExtension(_))
+            | Some(MessageRequest::AllExtensionNumbersOfType(_)) => {
+                error_response(Code::NotFound, "the services do not have extensions")
+            }
+            None => error_response(Code::InvalidArgument, "the request is empty"),
+        };
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(message_response),
+        }
+    }
+
+    fn by_name(&self, name: &str) -> Option<usize> {
+        self.files
+            .iter()
+            .position(|(file, _)| optional(&file.name) == name)
+    }
+
+    /// the file found, followed by the files it depends on, transitively
+    fn files_response<F>(&self, found: Option<usize>, not_found: F) -> MessageResponse
+    where
+        F: FnOnce() -> String,
+    {
+        let found = match found {
+            Some(found) => found,
+            None => return error_response(Code::NotFound, not_found()),
+        };
+        let mut included = vec![found];
+        let mut next = 0;
+        while next < included.len() {
+            let (file, _) = &self.files[included[next]];
+            for dependency in &file.dependency {
+                if let Some(dependency) = self.by_name(dependency) {
+                    if !included.contains(&dependency) {
+                        included.push(dependency);
+                    }
+                }
+            }
+            next += 1;
+        }
+        MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+            file_descriptor_proto: included
+                .into_iter()
+                .map(|index| self.files[index].1.clone())
+                .collect(),
+        })
+    }
+}
+
+fn error_response(code: Code, message: impl Into<String>) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse {
+        error_code: code as i32,
+        error_message: message.into(),
+    })
+}
+
+fn optional(value: &Option<String>) -> &str {
+    value.as_ref().map_or("", String::as_str)
+}
+
+/// whether the file declares the fully qualified `symbol`: a service or
+/// one of its methods, a message, possibly nested, or an enumeration
+fn declares(file: &FileDescriptorProto, symbol: &str) -> bool {
+    let name = match unqualified(optional(&file.package), symbol) {
+        Some(name) => name,
+        None => return false,
+    };
+    file.service.iter().any(|service| {
+        let service_name = optional(&service.name);
+        name == service_name
+            || unqualified(service_name, name).map_or(false, |method| {
+                service
+                    .method
+                    .iter()
+                    .any(|candidate| optional(&candidate.name) == method)
+            })
+    }) || file
+        .message_type
+        .iter()
+        .any(|message| declares_type(message, name))
+        || file
+            .enum_type
+            .iter()
+            .any(|enumeration| optional(&enumeration.name) == name)
+}
+
+fn declares_type(message: &DescriptorProto, name: &str) -> bool {
+    let message_name = optional(&message.name);
+    if name == message_name {
+        return true;
+    }
+    match unqualified(message_name, name) {
+        Some(nested) => {
+            message
+                .nested_type
+                .iter()
+                .any(|message| declares_type(message, nested))
+                || message
+                    .enum_type
+                    .iter()
+                    .any(|enumeration| optional(&enumeration.name) == nested)
+        }
+        None => false,
+    }
+}
+
+/// the `name` within `scope`, if it is in the scope
+fn unqualified<'a>(scope: &str, name: &'a str) -> Option<&'a str> {
+    if scope.is_empty() {
+        Some(name)
+    } else if name.len() > scope.len()
+        && name.starts_with(scope)
+        && name.as_bytes()[scope.len()] == b'.'
+    {
+        Some(&name[scope.len() + 1..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflect(message_request: MessageRequest) -> MessageResponse {
+        let request = ServerReflectionRequest {
+            host: "localhost".to_owned(),
+            message_request: Some(message_request),
+        };
+        let reply = DESCRIPTORS.reply(request.clone());
+        assert_eq!(reply.valid_host, "localhost");
+        assert_eq!(reply.original_request, Some(request));
+        reply.message_response.unwrap()
+    }
+
+    /// the names of the files of a reply of descriptors
+    fn file_names(response: MessageResponse) -> Vec<String> {
+        match response {
+            MessageResponse::FileDescriptorResponse(response) => response
+                .file_descriptor_proto
+                .iter()
+                .map(|file| {
+                    FileDescriptorProto::decode(&file[..])
+                        .unwrap()
+                        .name
+                        .unwrap()
+                })
+                .collect(),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    fn health_service(state: NodeState) -> HealthService {
+        let lifecycle = Lifecycle::new(slog::Logger::root(slog::Discard, o!()));
+        lifecycle.set(state);
+        HealthService { lifecycle }
+    }
+
+    fn health_request(service: &str) -> tower_grpc::Request<HealthCheckRequest> {
+        tower_grpc::Request::new(HealthCheckRequest {
+            service: service.to_owned(),
+        })
+    }
+
+    #[test]
+    fn health_check_of_the_services() {
+        let mut syncing = health_service(NodeState::Syncing);
+        let mut running = health_service(NodeState::Running);
+        for service in ["", NODE_SERVICE, HEALTH_SERVICE].iter() {
+            let response = syncing.check(health_request(service)).wait().unwrap();
+            assert_eq!(response.get_ref().status, ServingStatus::NotServing as i32);
+            let response = running.check(health_request(service)).wait().unwrap();
+            assert_eq!(response.get_ref().status, ServingStatus::Serving as i32);
+        }
+    }
+
+    #[test]
+    fn health_check_of_an_unknown_service() {
+        let mut health = health_service(NodeState::Running);
+        let status = health
+            .check(health_request("iohk.chain.node.Unknown"))
+            .wait()
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[test]
+    fn health_watch_sends_the_changes_of_the_status() {
+        let mut health = health_service(NodeState::StartingWorkers);
+        let lifecycle = health.lifecycle.clone();
+        let statuses = health
+            .watch(health_request(NODE_SERVICE))
+            .wait()
+            .unwrap()
+            .into_inner();
+        lifecycle.set(NodeState::Syncing);
+        lifecycle.set(NodeState::Running);
+        lifecycle.set(NodeState::ShuttingDown);
+        drop(lifecycle);
+        drop(health);
+
+        let statuses: Vec<i32> = statuses
+            .wait()
+            .map(|response| response.unwrap().status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ServingStatus::NotServing as i32,
+                ServingStatus::Serving as i32,
+                ServingStatus::NotServing as i32,
+            ]
+        );
+    }
+
+    #[test]
+    fn health_watch_of_an_unknown_service() {
+        let mut health = health_service(NodeState::Running);
+        let statuses = health
+            .watch(health_request("iohk.chain.node.Unknown"))
+            .wait()
+            .unwrap()
+            .into_inner();
+        let (first, _) = statuses.into_future().wait().ok().unwrap();
+        assert_eq!(
+            first.unwrap().status,
+            ServingStatus::ServiceUnknown as i32
+        );
+    }
+
+    #[test]
+    fn reflection_lists_the_services() {
+        match reflect(MessageRequest::ListServices(String::new())) {
+            MessageResponse::ListServicesResponse(response) => {
+                let names: Vec<_> = response.service.into_iter().map(|s| s.name).collect();
+                assert_eq!(names, SERVICES);
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reflection_describes_the_files() {
+        let response = reflect(MessageRequest::FileByFilename("health.proto".to_owned()));
+        assert_eq!(file_names(response), vec!["health.proto"]);
+    }
+
+    #[test]
+    fn reflection_finds_the_files_of_the_symbols() {
+        let symbols = [
+            (NODE_SERVICE, "node.proto"),
+            ("grpc.health.v1.Health.Check", "health.proto"),
+            (
+                "grpc.health.v1.HealthCheckResponse.ServingStatus",
+                "health.proto",
+            ),
+            (
+                "grpc.reflection.v1alpha.ServerReflectionRequest",
+                "reflection.proto",
+            ),
+        ];
+        for (symbol, file) in symbols.iter() {
+            let response = reflect(MessageRequest::FileContainingSymbol(symbol.to_string()));
+            assert_eq!(file_names(response)[0], *file, "file of {}", symbol);
+        }
+    }
+
+    #[test]
+    fn reflection_of_unknown_symbols() {
+        for symbol in [
+            "grpc.health.v1.Unknown",
+            "grpc.health.v1.Health.Unknown",
+            "grpc",
+        ]
+        .iter()
+        {
+            match reflect(MessageRequest::FileContainingSymbol(symbol.to_string())) {
+                MessageResponse::ErrorResponse(error) => {
+                    assert_eq!(error.error_code, Code::NotFound as i32)
+                }
+                other => panic!("unexpected response {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn unqualified_names() {
+        assert_eq!(unqualified("", "Node"), Some("Node"));
+        assert_eq!(unqualified("iohk.chain", "iohk.chain.Node"), Some("Node"));
+        assert_eq!(unqualified("iohk.chain", "iohk.chainNode"), None);
+        assert_eq!(unqualified("iohk.chain", "iohk.chain"), None);
+    }
+}
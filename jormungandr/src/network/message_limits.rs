@@ -0,0 +1,399 @@
+//! the maximum sizes of the messages received from the peers
+//!
+//! The gRPC codec of `network-grpc` buffers and decodes messages of any
+//! size, so the requests of the peers are checked on the length of their
+//! gRPC framing by the service of the incoming connections, before the
+//! codec reads them (see `grpc::limits`). The decoded messages are checked
+//! again on their content as soon as they reach the services and the
+//! client of the node, before they are buffered or handed to the tasks: the
+//! responses of the peers are only checked there. A peer sending a message
+//! over the limits is disconnected and, when its node ID is known,
+//! quarantined by the policy of the topology.
+
+use super::{
+    p2p::{limits, Id},
+    GlobalState, GlobalStateR,
+};
+use crate::blockcfg::{Block, Fragment, Header};
+use chain_core::property::Serialize as _;
+use network_core::error as core_error;
+use network_core::server::request_stream::{MapResponse, ProcessingError};
+use poldercast::StrikeReason;
+
+use futures::prelude::*;
+use slog::Logger;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+const NODE_SERVICE_PATH: &str = "/iohk.chain.node.Node/";
+
+/// the length of the prefix of a gRPC message: the compression flag and
+/// the length of the message
+const PREFIX_LEN: usize = 5;
+
+/// the bytes wrapping the content of a message of the node protocol: the
+/// key and the length of its only field
+const CONTENT_OVERHEAD: usize = 6;
+
+#[derive(Debug, Error)]
+pub enum Violation {
+    #[error("the {message} of {size} bytes is larger than the maximum of {max} bytes")]
+    TooLarge {
+        message: &'static str,
+        size: usize,
+        max: usize,
+    },
+    #[error("the gossip of {count} nodes has more than the maximum of {max} nodes")]
+    TooManyNodes { count: usize, max: usize },
+}
+
+impl Violation {
+    /// the kind of the message, labelling the violations in the metrics
+    pub fn message(&self) -> &'static str {
+        match self {
+            Violation::TooLarge { message, .. } => *message,
+            Violation::TooManyNodes { .. } => "gossip",
+        }
+    }
+}
+
+/// the maximum length of the gRPC messages of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    message: &'static str,
+    max: usize,
+}
+
+impl Limit {
+    /// the limit on the messages wrapping a content of at most `max` bytes
+    fn content(message: &'static str, max: usize) -> Self {
+        Limit {
+            message,
+            max: max + CONTENT_OVERHEAD,
+        }
+    }
+
+    fn header() -> Self {
+        Limit::content("header", limits::MAX_HEADER_SIZE)
+    }
+
+    fn block() -> Self {
+        Limit::content("block", limits::MAX_BLOCK_SIZE)
+    }
+
+    fn fragment() -> Self {
+        Limit::content("fragment", limits::MAX_FRAGMENT_SIZE)
+    }
+
+    /// the gossips, their number of nodes being checked once decoded
+    fn gossip() -> Self {
+        Limit {
+            message: "gossip",
+            max: limits::MAX_GOSSIP_NODES * (limits::MAX_GOSSIP_SIZE as usize + CONTENT_OVERHEAD),
+        }
+    }
+
+    fn other(message: &'static str) -> Self {
+        Limit {
+            message,
+            max: limits::MAX_REQUEST_SIZE,
+        }
+    }
+
+    /// the limit on the messages sent by a peer in its request on `path`
+    pub fn request(path: &str) -> Self {
+        match method(path) {
+            Some("PushHeaders") | Some("BlockSubscription") => Limit::header(),
+            Some("UploadBlocks") => Limit::block(),
+            Some("ContentSubscription") => Limit::fragment(),
+            Some("GossipSubscription") => Limit::gossip(),
+            _ => Limit::other("request"),
+        }
+    }
+}
+
+fn method(path: &str) -> Option<&str> {
+    if path.starts_with(NODE_SERVICE_PATH) {
+        Some(&path[NODE_SERVICE_PATH.len()..])
+    } else {
+        None
+    }
+}
+
+/// the gRPC framing of a stream, checking the length of every message
+/// from its prefix
+#[derive(Debug)]
+pub struct Framing {
+    limit: Limit,
+    prefix: [u8; PREFIX_LEN],
+    prefix_read: usize,
+    /// the bytes left of the current message
+    remaining: usize,
+}
+
+impl Framing {
+    pub fn new(limit: Limit) -> Self {
+        Framing {
+            limit,
+            prefix: [0; PREFIX_LEN],
+            prefix_read: 0,
+            remaining: 0,
+        }
+    }
+
+    /// check the messages starting in the next bytes of the stream
+    pub fn check(&mut self, mut data: &[u8]) -> Result<(), Violation> {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let skipped = self.remaining.min(data.len());
+                self.remaining -= skipped;
+                data = &data[skipped..];
+                continue;
+            }
+            let read = (PREFIX_LEN - self.prefix_read).min(data.len());
+            self.prefix[self.prefix_read..self.prefix_read + read].copy_from_slice(&data[..read]);
+            self.prefix_read += read;
+            data = &data[read..];
+            if self.prefix_read < PREFIX_LEN {
+                break;
+            }
+            self.prefix_read = 0;
+            let mut len = [0; 4];
+            len.copy_from_slice(&self.prefix[1..]);
+            let size = u32::from_be_bytes(len) as usize;
+            if size > self.limit.max {
+                return Err(Violation::TooLarge {
+                    message: self.limit.message,
+                    size,
+                    max: self.limit.max,
+                });
+            }
+            self.remaining = size;
+        }
+        Ok(())
+    }
+}
+
+fn check_size(message: &'static str, size: usize, max: usize) -> Result<(), Violation> {
+    if size > max {
+        Err(Violation::TooLarge { message, size, max })
+    } else {
+        Ok(())
+    }
+}
+
+pub fn check_header(header: &Header) -> Result<(), Violation> {
+    let size = header.serialize_as_vec().map_or(0, |bytes| bytes.len());
+    check_size("header", size, limits::MAX_HEADER_SIZE)
+}
+
+/// the block is measured by the content size of its header, its contents
+/// being decoded to that size
+pub fn check_block(block: &Block) -> Result<(), Violation> {
+    check_header(&block.header)?;
+    let size = block.header.block_content_size() as usize;
+    check_size("block", size, limits::MAX_BLOCK_SIZE)
+}
+
+pub fn check_fragment(fragment: &Fragment) -> Result<(), Violation> {
+    let size = fragment.to_raw().size_bytes_plus_size();
+    check_size("fragment", size, limits::MAX_FRAGMENT_SIZE)
+}
+
+pub fn check_gossip_nodes(count: usize) -> Result<(), Violation> {
+    if count > limits::MAX_GOSSIP_NODES {
+        Err(Violation::TooManyNodes {
+            count,
+            max: limits::MAX_GOSSIP_NODES,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// disconnect the peer which sent a message over the limits and quarantine
+/// it if its node ID is known, returning the error terminating the stream
+pub fn reject_peer(
+    state: &GlobalState,
+    node_id: Option<Id>,
+    violation: Violation,
+    logger: &Logger,
+) -> core_error::Error {
+    warn!(
+        logger,
+        "message over the limits, disconnecting the peer";
+        "reason" => %violation,
+    );
+    state.stats_counter.add_limit_violation(violation.message());
+    if let Some(node_id) = node_id {
+        state.peers.remove_peer(node_id);
+        state
+            .topology
+            .report_node(node_id, StrikeReason::InvalidData);
+    }
+    core_error::Error::new(core_error::Code::InvalidArgument, violation.to_string())
+}
+
+/// a sink of a request stream of a peer, checking the items it is sent
+#[must_use = "sinks do nothing unless polled"]
+pub struct LimitedSink<S, T> {
+    inner: S,
+    check: fn(&T) -> Result<(), Violation>,
+    node_id: Option<Id>,
+    global_state: GlobalStateR,
+    logger: Logger,
+}
+
+impl<S, T> LimitedSink<S, T> {
+    pub fn new(
+        inner: S,
+        check: fn(&T) -> Result<(), Violation>,
+        node_id: Option<Id>,
+        global_state: GlobalStateR,
+        logger: Logger,
+    ) -> Self {
+        LimitedSink {
+            inner,
+            check,
+            node_id,
+            global_state,
+            logger,
+        }
+    }
+}
+
+impl<S, T> Sink for LimitedSink<S, T>
+where
+    S: Sink<SinkItem = T, SinkError = core_error::Error>,
+{
+    type SinkItem = T;
+    type SinkError = core_error::Error;
+
+    fn start_send(&mut self, item: T) -> StartSend<T, core_error::Error> {
+        if let Err(violation) = (self.check)(&item) {
+            return Err(reject_peer(
+                &self.global_state,
+                self.node_id,
+                violation,
+                &self.logger,
+            ));
+        }
+        self.inner.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), core_error::Error> {
+        self.inner.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), core_error::Error> {
+        self.inner.close()
+    }
+}
+
+impl<S, T> MapResponse for LimitedSink<S, T>
+where
+    S: MapResponse,
+{
+    type Response = S::Response;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn on_stream_termination(&mut self, res: Result<(), ProcessingError>) -> Self::ResponseFuture {
+        self.inner.on_stream_termination(res)
+    }
+}
+
+/// the peer of a connection, rejected on a message over the limits. Its
+/// node ID is known once the peer subscribed or was subscribed to.
+#[derive(Clone)]
+pub struct PeerLimits {
+    node_id: Arc<Mutex<Option<Id>>>,
+    global_state: GlobalStateR,
+    logger: Logger,
+}
+
+impl PeerLimits {
+    pub fn new(global_state: GlobalStateR, logger: Logger) -> Self {
+        PeerLimits {
+            node_id: Arc::new(Mutex::new(None)),
+            global_state,
+            logger,
+        }
+    }
+
+    pub fn set_node_id(&self, node_id: Id) {
+        *self.node_id.lock().unwrap() = Some(node_id);
+    }
+
+    pub fn node_id(&self) -> Option<Id> {
+        *self.node_id.lock().unwrap()
+    }
+
+    pub fn reject(&self, violation: Violation) {
+        reject_peer(&self.global_state, self.node_id(), violation, &self.logger);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(len: usize) -> Vec<u8> {
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(len as u32).to_be_bytes());
+        frame.extend(std::iter::repeat(0xff).take(len));
+        frame
+    }
+
+    #[test]
+    fn messages_within_the_limit_pass() {
+        let limit = Limit::request("/iohk.chain.node.Node/PushHeaders");
+        let mut framing = Framing::new(limit);
+        let mut stream = frame(limits::MAX_HEADER_SIZE + CONTENT_OVERHEAD);
+        stream.extend(frame(0));
+        stream.extend(frame(100));
+        // the messages split at every length across the chunks
+        for chunk in stream.chunks(3) {
+            framing.check(chunk).unwrap();
+        }
+        assert_eq!(framing.remaining, 0);
+        assert_eq!(framing.prefix_read, 0);
+    }
+
+    #[test]
+    fn message_over_the_limit_is_rejected_on_its_prefix() {
+        let limit = Limit::request("/iohk.chain.node.Node/PushHeaders");
+        let mut framing = Framing::new(limit);
+        framing.check(&frame(100)).unwrap();
+        let prefix = &frame(limits::MAX_HEADER_SIZE + CONTENT_OVERHEAD + 1)[..PREFIX_LEN];
+        framing.check(&prefix[..2]).unwrap();
+        match framing.check(&prefix[2..]) {
+            Err(Violation::TooLarge { message, size, .. }) => {
+                assert_eq!(message, "header");
+                assert_eq!(size, limits::MAX_HEADER_SIZE + CONTENT_OVERHEAD + 1);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn limits_follow_the_method() {
+        let block = Limit::request("/iohk.chain.node.Node/UploadBlocks");
+        assert_eq!(block, Limit::block());
+        assert_eq!(
+            Limit::request("/iohk.chain.node.Node/GetBlocks").message,
+            "request"
+        );
+        assert_eq!(
+            Limit::request("/iohk.chain.node.Node/ContentSubscription"),
+            Limit::fragment()
+        );
+        assert_eq!(
+            Limit::request("/other.Service/UploadBlocks").message,
+            "request"
+        );
+        let mut framing = Framing::new(Limit::request("/iohk.chain.node.Node/UploadBlocks"));
+        framing
+            .check(&frame(limits::MAX_HEADER_SIZE * 2)[..PREFIX_LEN])
+            .unwrap();
+    }
+}
@@ -5,13 +5,23 @@
 //! transactions...);
 //!
 
+mod address_book;
+mod audit_log;
+mod block_cache;
+mod block_fetch;
 pub mod bootstrap;
 mod client;
 mod grpc;
 mod inbound;
+mod message_limits;
 pub mod p2p;
+pub mod peer_list;
+mod rate_limit;
+mod rebootstrap;
 mod service;
 mod subscription;
+mod topology_file;
+mod workers;
 
 // Constants
 
@@ -30,15 +40,24 @@ mod buffer_sizes {
     pub const FRAGMENTS: usize = 128;
 }
 
+use self::address_book::AddressBook;
+pub use self::audit_log::AuditLog;
+use self::audit_log::{Direction, Outcome};
+use self::block_cache::BlockCache;
+use self::block_fetch::BlockFetches;
 use self::client::ConnectError;
 use self::p2p::{
     comm::{PeerComms, Peers},
     P2pTopology,
 };
+use self::rebootstrap::Rebootstrap;
+use self::workers::Workers;
 use crate::blockcfg::{Block, HeaderHash};
 use crate::blockchain::{Blockchain as NewBlockchain, Tip};
 use crate::intercom::{BlockMsg, ClientMsg, NetworkMsg, PropagateMsg, TransactionMsg};
-use crate::settings::start::network::{Configuration, Peer, Protocol};
+use crate::lifecycle::Lifecycle;
+use crate::settings::start::network::{Configuration, Peer, Protocol, TrustedPeer};
+use crate::stats_counter::StatsCounter;
 use crate::utils::{
     async_msg::{MessageBox, MessageQueue},
     task::{TaskMessageBox, TokioServiceInfo},
@@ -50,18 +69,22 @@ use poldercast::StrikeReason;
 use rand::seq::SliceRandom;
 use slog::Logger;
 use tokio::runtime::TaskExecutor;
-use tokio::timer::Interval;
+use tokio::timer::{Delay, Interval};
 
+use std::collections::HashSet;
 use std::error;
 use std::fmt;
 use std::io;
 use std::iter;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 pub use self::bootstrap::Error as BootstrapError;
 
+/// how often the address book is saved, in addition to the shutdown
+const ADDRESS_BOOK_SAVE_INTERVAL: Duration = Duration::from_secs(600);
+
 #[derive(Debug)]
 pub struct ListenError {
     cause: io::Error,
@@ -108,7 +131,18 @@ pub struct GlobalState {
     pub topology: P2pTopology,
     pub peers: Peers,
     pub executor: TaskExecutor,
+    pub stats_counter: StatsCounter,
+    /// the state of the node, served by the health checking
+    pub lifecycle: Lifecycle,
+    /// the recent blocks served to the peers
+    pub block_cache: BlockCache,
+    /// the blocks requested by the block task being fetched from the peers
+    pub block_fetches: BlockFetches,
     pub logger: Logger,
+    gossip_interval: RwLock<Duration>,
+    audit_log: Option<AuditLog>,
+    address_book: Option<AddressBook>,
+    rebootstrap: Rebootstrap,
 }
 
 type GlobalStateR = Arc<GlobalState>;
@@ -119,21 +153,48 @@ impl GlobalState {
         block0_hash: HeaderHash,
         config: Configuration,
         executor: TaskExecutor,
+        stats_counter: StatsCounter,
+        lifecycle: Lifecycle,
+        rebootstrap: Rebootstrap,
+        audit_log: Option<AuditLog>,
         logger: Logger,
     ) -> Self {
         let mut topology = P2pTopology::new(config.profile.clone(), logger.clone());
-        topology.set_poldercast_modules();
+        topology.set_poldercast_modules(&config.layers);
         topology.set_custom_modules(&config);
         topology.set_policy(config.policy.clone());
-
-        // inject the trusted peers as initial gossips, this will make the node
-        // gossip with them at least at the beginning
+        if let p2p::Role::Core = config.role {
+            topology.set_core(config.trusted_peers.iter().map(|peer| peer.id));
+        }
+        if config.client_only {
+            topology.set_client_only();
+        }
+        stats_counter.set_client_only(config.client_only);
+
+        // the nodes of the topology saved on the last shutdown
+        let saved_peers = match config.topology_file.as_ref() {
+            Some(path) => topology_file::load(path, &logger),
+            None => Vec::new(),
+        };
+        let address_book = config
+            .address_book
+            .clone()
+            .map(|path| AddressBook::open(path, &logger));
+        let book_peers = address_book
+            .as_ref()
+            .map_or_else(Vec::new, AddressBook::peers);
+
+        // inject the trusted peers, the saved nodes and the nodes of the
+        // address book as initial gossips,
+        // this will make the node gossip with them at least at the beginning
         topology.accept_gossips(
             (*config.profile.id()).into(),
             config
                 .trusted_peers
                 .clone()
                 .into_iter()
+                .chain(saved_peers)
+                .chain(book_peers)
                 .map(|tp| {
                     let mut builder = poldercast::NodeProfileBuilder::new();
                     builder.id(tp.id.into());
@@ -145,7 +206,14 @@ impl GlobalState {
                 .into(),
         );
 
-        let peers = Peers::new(config.max_connections, logger.clone());
+        let peers = Peers::new(
+            config.max_connections,
+            config.block_priority,
+            logger.clone(),
+        );
+        let gossip_interval = RwLock::new(config.gossip_interval);
+
+        let block_cache = BlockCache::new(config.block_cache_capacity, stats_counter.clone());
 
         GlobalState {
             block0_hash,
@@ -153,7 +221,41 @@ impl GlobalState {
             topology,
             peers,
             executor,
+            stats_counter,
+            lifecycle,
+            block_cache,
+            block_fetches: BlockFetches::default(),
             logger,
+            gossip_interval,
+            audit_log,
+            address_book,
+            rebootstrap,
+        }
+    }
+
+    /// record the connection in the audit log, if the node keeps one
+    fn audit(
+        &self,
+        direction: Direction,
+        peer_id: Option<p2p::Id>,
+        address: SocketAddr,
+        outcome: Outcome,
+        reason: Option<&str>,
+    ) {
+        if let Some(audit_log) = self.audit_log.as_ref() {
+            audit_log.record(direction, peer_id, address, outcome, reason);
+        }
+    }
+
+    /// record the nodes of the address book gossiped by a peer, if the node
+    /// keeps one
+    pub fn record_gossiped(&self, nodes: &[p2p::Gossip]) {
+        if let Some(address_book) = self.address_book.as_ref() {
+            for node in nodes {
+                if let Some(address) = node.address() {
+                    address_book.record_seen(node.id(), address);
+                }
+            }
         }
     }
 
@@ -161,6 +263,12 @@ impl GlobalState {
         &self.logger
     }
 
+    /// the interval between two gossips, `config.gossip_interval` unless
+    /// changed by a reload of the configuration
+    pub fn gossip_interval(&self) -> Duration {
+        *self.gossip_interval.read().unwrap()
+    }
+
     pub fn spawn<F>(&self, f: F)
     where
         F: Future<Item = (), Error = ()> + Send + 'static,
@@ -173,8 +281,11 @@ pub struct ConnectionState {
     /// The global state shared between all connections
     pub global: GlobalStateR,
 
-    /// the timeout to wait for unbefore the connection replies
-    pub timeout: Duration,
+    /// how long the connection may take to be established
+    pub connect_timeout: Duration,
+
+    /// how long the peer may take to respond to a request
+    pub request_timeout: Duration,
 
     /// the local (to the task) connection details
     pub connection: Connection,
@@ -185,7 +296,8 @@ pub struct ConnectionState {
 impl ConnectionState {
     fn new(global: GlobalStateR, peer: &Peer) -> Self {
         ConnectionState {
-            timeout: peer.timeout,
+            connect_timeout: peer.connect_timeout,
+            request_timeout: peer.request_timeout,
             connection: peer.connection.clone(),
             logger: global.logger().new(o!("peer_addr" => peer.connection)),
             global,
@@ -202,6 +314,11 @@ pub struct TaskParams {
     pub block0_hash: HeaderHash,
     pub input: MessageQueue<NetworkMsg>,
     pub channels: Channels,
+    pub stats_counter: StatsCounter,
+    pub lifecycle: Lifecycle,
+    pub blockchain_tip: Tip,
+    /// the audit log of the connections, opened on the start of the node
+    pub audit_log: Option<AuditLog>,
 }
 
 pub fn start(
@@ -213,10 +330,15 @@ pub fn start(
     // * the ID needs to be consistent between restart;
     let input = params.input;
     let channels = params.channels;
+    let rebootstrap = Rebootstrap::new(params.blockchain_tip);
     let global_state = Arc::new(GlobalState::new(
         params.block0_hash,
         params.config,
         service_info.executor().clone(),
+        params.stats_counter,
+        params.lifecycle,
+        rebootstrap,
+        params.audit_log,
         service_info.logger().clone(),
     ));
 
@@ -244,11 +366,13 @@ pub fn start(
     };
 
     let initial_nodes = global_state.topology.view();
-    let self_node = global_state.topology.node();
+    let self_node = global_state.topology.advertised_node();
     for node in initial_nodes {
         connect_and_propagate_with(node, global_state.clone(), channels.clone(), |comms| {
-            let gossip = Gossip::from_nodes(iter::once(self_node.clone().into()));
-            comms.set_pending_gossip(gossip);
+            if let Some(self_node) = self_node.as_ref() {
+                let gossip = Gossip::from_nodes(iter::once(self_node.clone().into()));
+                comms.set_pending_gossip(gossip);
+            }
         });
     }
 
@@ -256,7 +380,11 @@ pub fn start(
 
     let gossip_err_logger = global_state.logger.clone();
     let reset_err_logger = global_state.logger.clone();
+    let fetch_err_logger = global_state.logger.clone();
     let tp2p = global_state.topology.clone();
+    let fetch_state = global_state.clone();
+    let book_err_logger = global_state.logger.clone();
+    let book_state = global_state.clone();
 
     if let Some(interval) = global_state.config.topology_force_reset_interval.clone() {
         global_state.spawn(
@@ -268,14 +396,37 @@ pub fn start(
         );
     }
 
-    let gossip = Interval::new_interval(global_state.config.gossip_interval.clone())
-        .map_err(move |e| {
-            error!(gossip_err_logger, "interval timer error: {:?}", e);
-        })
-        .for_each(move |_| {
-            send_gossip(global_state.clone(), channels.clone());
-            Ok(())
-        });
+    if global_state.address_book.is_some() {
+        global_state.spawn(
+            Interval::new_interval(ADDRESS_BOOK_SAVE_INTERVAL)
+                .map_err(move |e| {
+                    error!(book_err_logger, "interval timer error: {:?}", e);
+                })
+                .for_each(move |_| Ok(save_address_book(&book_state))),
+        );
+    }
+
+    global_state.spawn(
+        Interval::new_interval(global_state.config.pull_inactivity_timeout)
+            .map_err(move |e| {
+                error!(fetch_err_logger, "interval timer error: {:?}", e);
+            })
+            .for_each(move |_| Ok(block_fetch::retry_expired(&fetch_state))),
+    );
+
+    // the interval is read again after every gossip as it can be reloaded
+    let gossip = future::loop_fn(global_state, move |global_state| {
+        let gossip_err_logger = gossip_err_logger.clone();
+        let channels = channels.clone();
+        Delay::new(Instant::now() + global_state.gossip_interval())
+            .map_err(move |e| {
+                error!(gossip_err_logger, "interval timer error: {:?}", e);
+            })
+            .map(move |()| {
+                send_gossip(global_state.clone(), channels);
+                future::Loop::<(), _>::Continue(global_state)
+            })
+    });
 
     listener.join3(handle_cmds, gossip).map(|_| ())
 }
@@ -285,29 +436,130 @@ fn handle_network_input(
     state: GlobalStateR,
     channels: Channels,
 ) -> impl Future<Item = (), Error = ()> {
-    input.for_each(move |msg| match msg {
+    let workers = Workers::start(&state, &channels);
+    let logger = state.logger().clone();
+    input
+        .forward(workers.sink_map_err(move |e| {
+            error!(logger, "cannot send to a network worker"; "reason" => %e);
+        }))
+        .map(|_| ())
+}
+
+fn handle_network_msg(msg: NetworkMsg, state: &GlobalStateR, channels: &Channels) {
+    match msg {
         NetworkMsg::Propagate(msg) => {
             handle_propagation_msg(msg, state.clone(), channels.clone());
-            Ok(())
         }
-        NetworkMsg::GetBlocks(block_ids) => {
-            state.peers.fetch_blocks(block_ids);
-            Ok(())
+        NetworkMsg::GetBlocks(block_ids, reply) => {
+            block_fetch::fetch_blocks(state, block_ids, reply);
         }
         NetworkMsg::GetNextBlock(node_id, block_id) => {
-            state.peers.solicit_blocks(node_id, vec![block_id]);
-            Ok(())
+            if let Err(hashes) = state.peers.solicit_blocks(node_id, vec![block_id]) {
+                connect_on_demand(node_id, state, channels, |comms| {
+                    comms.set_pending_block_solicitation(hashes)
+                });
+            }
         }
         NetworkMsg::PullHeaders { node_id, from, to } => {
-            state.peers.pull_headers(node_id, from.into(), to);
-            Ok(())
+            if let Err(request) = state.peers.pull_headers(node_id, from.into(), to) {
+                connect_on_demand(node_id, state, channels, |comms| {
+                    comms.set_pending_chain_pull(request)
+                });
+            }
         }
         NetworkMsg::PeerStats(reply) => {
             let stats = state.peers.stats();
             reply.reply_ok(stats);
-            Ok(())
         }
-    })
+        NetworkMsg::SetGossipInterval(interval) => {
+            info!(state.logger(), "gossip interval changed"; "interval" => ?interval);
+            *state.gossip_interval.write().unwrap() = interval;
+        }
+        NetworkMsg::SetNodeId(id) => {
+            announce_node_id(id, state, channels);
+        }
+        NetworkMsg::Bootstrap(reply) => {
+            rebootstrap::start(state, channels, reply);
+        }
+        NetworkMsg::Shutdown(reply) => {
+            save_topology(state);
+            save_address_book(state);
+            reply.reply_ok(());
+        }
+    }
+}
+
+/// the peers know the node by the ID it sent on connecting: the connections
+/// are dropped, and the nodes of the view connected to again with the new
+/// profile of the node as their first gossip. The old ID ages out of the
+/// topologies of the peers.
+fn announce_node_id(id: p2p::Id, state: &GlobalStateR, channels: &Channels) {
+    let current_id: p2p::Id = (*state.topology.node().id()).into();
+    if id == current_id {
+        return;
+    }
+    info!(
+        state.logger(),
+        "node ID rotated, announcing the new node ID";
+        "old" => %current_id,
+        "new" => %id,
+    );
+    state.topology.set_node_id(id, &state.config);
+    for (peer, _) in state.peers.stats() {
+        state.peers.remove_peer(peer);
+    }
+    let self_node = state.topology.advertised_node();
+    for node in state.topology.view() {
+        let self_node = self_node.clone();
+        connect_and_propagate_with(node, state.clone(), channels.clone(), move |comms| {
+            if let Some(self_node) = self_node {
+                let gossip = Gossip::from_nodes(iter::once(self_node.into()));
+                comms.set_pending_gossip(gossip);
+            }
+        });
+    }
+}
+
+fn save_address_book(state: &GlobalState) {
+    let address_book = match state.address_book.as_ref() {
+        Some(address_book) => address_book,
+        None => return,
+    };
+    match address_book.save() {
+        Ok(saved) => debug!(
+            state.logger(),
+            "address book saved";
+            "path" => %address_book.path().display(),
+            "nodes" => saved,
+        ),
+        Err(e) => error!(
+            state.logger(),
+            "cannot save the address book";
+            "path" => %address_book.path().display(),
+            "reason" => %e,
+        ),
+    }
+}
+
+fn save_topology(state: &GlobalState) {
+    let path = match state.config.topology_file.as_ref() {
+        Some(path) => path,
+        None => return,
+    };
+    match topology_file::save(path, &state.topology.view()) {
+        Ok(saved) => info!(
+            state.logger(),
+            "topology saved";
+            "path" => %path.display(),
+            "nodes" => saved,
+        ),
+        Err(e) => error!(
+            state.logger(),
+            "cannot save the topology";
+            "path" => %path.display(),
+            "reason" => %e,
+        ),
+    }
 }
 
 fn handle_propagation_msg(msg: PropagateMsg, state: GlobalStateR, channels: Channels) {
@@ -316,6 +568,20 @@ fn handle_propagation_msg(msg: PropagateMsg, state: GlobalStateR, channels: Chan
     let res = match msg {
         PropagateMsg::Block(ref header) => state.peers.propagate_block(nodes, header.clone()),
         PropagateMsg::Fragment(ref fragment) => {
+            // the peers linked to the node for the blocks only do not get
+            // the fragments
+            let count = nodes.len();
+            let nodes: Vec<_> = nodes
+                .into_iter()
+                .filter(|node| !node.declined_topic(p2p::topic::MESSAGES))
+                .collect();
+            if nodes.len() < count {
+                trace!(
+                    state.logger(),
+                    "{} of the peers not interested in the fragments left out of the propagation",
+                    count - nodes.len(),
+                );
+            }
             state.peers.propagate_fragment(nodes, fragment.clone())
         }
     };
@@ -338,7 +604,33 @@ fn handle_propagation_msg(msg: PropagateMsg, state: GlobalStateR, channels: Chan
     }
 }
 
+/// connect to a peer the client task solicits and which is not connected
+/// anymore, the request is sent once the connection is established
+fn connect_on_demand<F>(
+    node_id: p2p::Id,
+    state: &GlobalStateR,
+    channels: &Channels,
+    modify_comms: F,
+) where
+    F: FnOnce(&mut PeerComms),
+{
+    match state.topology.find(node_id) {
+        Some(node) => {
+            debug!(state.logger(), "connecting to peer on demand"; "node_id" => %node_id);
+            connect_and_propagate_with(node, state.clone(), channels.clone(), modify_comms);
+        }
+        None => {
+            info!(
+                state.logger(),
+                "peer {} not available to solicit from", node_id
+            );
+        }
+    }
+}
+
 fn send_gossip(state: GlobalStateR, channels: Channels) {
+    let connections = state.peers.connection_stats();
+    state.stats_counter.set_peer_connections(connections);
     for node in state.topology.view() {
         let gossip = Gossip::from(state.topology.initiate_gossips(node.id()));
         let res = state.peers.propagate_gossip_to(node.id(), gossip);
@@ -375,18 +667,20 @@ fn connect_and_propagate_with<F>(
         (*state.topology.node().id()).into(),
         "topology tells the node to connect to itself"
     );
-    let peer = Peer::new(addr, Protocol::Grpc);
+    let peer = state.config.peer(addr);
     let conn_state = ConnectionState::new(state.clone(), &peer);
     let conn_logger = conn_state
         .logger()
         .new(o!("node_id" => node_id.to_string()));
     info!(conn_logger, "connecting to peer");
-    let (handle, connecting) = client::connect(conn_state, channels.clone());
+    let block_lane = state.peers.block_lane(node_id);
+    let (handle, connecting) = client::connect(conn_state, channels.clone(), block_lane);
     state.peers.connecting_with(node_id, handle, modify_comms);
     let spawn_state = state.clone();
     let conn_err_state = state.clone();
     let cf = connecting
         .map_err(move |e| {
+            let reason = e.to_string();
             let benign = match e {
                 ConnectError::Connect(e) => {
                     if let Some(e) = e.connect_error() {
@@ -402,12 +696,35 @@ fn connect_and_propagate_with<F>(
                     debug!(conn_logger, "connection to peer has been canceled");
                     true
                 }
+                ConnectError::UnsupportedVersion { ref version } => {
+                    info!(
+                        conn_logger,
+                        "the peer speaks an unsupported protocol version";
+                        "peer_version" => version,
+                    );
+                    false
+                }
                 _ => {
                     info!(conn_logger, "connection to peer failed"; "reason" => %e);
                     false
                 }
             };
-            if !benign {
+            if benign {
+                conn_err_state.audit(
+                    Direction::Outbound,
+                    Some(node_id),
+                    addr,
+                    Outcome::Canceled,
+                    None,
+                );
+            } else {
+                conn_err_state.audit(
+                    Direction::Outbound,
+                    Some(node_id),
+                    addr,
+                    Outcome::Failed,
+                    Some(&reason),
+                );
                 conn_err_state.peers.remove_peer(node_id);
                 conn_err_state.topology.report_node(node_id, StrikeReason::CannotConnect);
             }
@@ -425,6 +742,13 @@ fn connect_and_propagate_with<F>(
                         client.logger(),
                         "expected node {} but connected to self", node_id
                     );
+                    state.audit(
+                        Direction::Outbound,
+                        Some(node_id),
+                        addr,
+                        Outcome::Failed,
+                        Some("connected to self"),
+                    );
                     state.peers.remove_peer(node_id);
                     return Err(());
                 }
@@ -434,12 +758,80 @@ fn connect_and_propagate_with<F>(
                     warn!(client.logger(), "peer no longer in map after connecting");
                 }
             }
-            Ok(client)
+            state.audit(
+                Direction::Outbound,
+                Some(connected_node_id),
+                addr,
+                Outcome::Connected,
+                None,
+            );
+            if let Some(address_book) = state.address_book.as_ref() {
+                address_book.record_dialed(connected_node_id, addr);
+            }
+            Ok((client, state, connected_node_id))
         })
-        .and_then(|client| client);
+        .and_then(move |(client, state, connected_node_id)| {
+            client.then(move |res| {
+                let reason = res.as_ref().err().map(ToString::to_string);
+                state.audit(
+                    Direction::Outbound,
+                    Some(connected_node_id),
+                    addr,
+                    Outcome::Closed,
+                    reason.as_ref().map(String::as_str),
+                );
+                res.map_err(|_| ())
+            })
+        });
     spawn_state.spawn(cf);
 }
 
+/// the peers of the address book which are trusted peers
+fn trusted_book_peers(
+    trusted_peers: &[TrustedPeer],
+    book_peers: Vec<TrustedPeer>,
+) -> Vec<TrustedPeer> {
+    book_peers
+        .into_iter()
+        .filter(|peer| trusted_peers.iter().any(|trusted| trusted.id == peer.id))
+        .collect()
+}
+
+/// the IP addresses a node accepts the inbound connections from: a core
+/// node only accepts its relays, the trusted peers, a relay node accepts
+/// any address
+fn inbound_relays(role: p2p::Role, trusted_peers: &[TrustedPeer]) -> Option<HashSet<IpAddr>> {
+    match role {
+        p2p::Role::Relay => None,
+        p2p::Role::Core => Some(
+            trusted_peers
+                .iter()
+                .filter_map(|peer| peer.address.to_socketaddr())
+                .map(|addr| canonical_ip(addr.ip()))
+                .collect(),
+        ),
+    }
+}
+
+fn admits_inbound(relays: &Option<HashSet<IpAddr>>, addr: SocketAddr) -> bool {
+    match relays {
+        None => true,
+        Some(relays) => relays.contains(&canonical_ip(addr.ip())),
+    }
+}
+
+/// the IPv4 address of an IPv4-mapped IPv6 address, as a dual-stack socket
+/// reports the IPv4 peers
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => IpAddr::V4(v6.to_ipv4().unwrap()),
+            _ => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
 fn trusted_peers_shuffled(config: &Configuration) -> Vec<SocketAddr> {
     let mut peers = config
         .trusted_peers
@@ -461,19 +853,43 @@ pub fn bootstrap(
         unimplemented!()
     }
 
-    if config.trusted_peers.is_empty() {
+    // the addresses of the trusted peers recorded in the address book are
+    // tried once none of the configured ones could be bootstrapped from, the
+    // ones last connected to first: the other nodes of the book are only
+    // known from the unauthenticated gossips, not trusted with the chain
+    let book_peers = match config.address_book.as_ref() {
+        Some(path) => trusted_book_peers(&config.trusted_peers, address_book::load(path, logger)),
+        None => Vec::new(),
+    };
+
+    if config.trusted_peers.is_empty() && book_peers.is_empty() {
         warn!(logger, "No trusted peers joinable to bootstrap the network");
     }
 
+    let checkpoints: Arc<bootstrap::TrustedCheckpoints> = Arc::new(
+        config
+            .trusted_checkpoints
+            .iter()
+            .map(|checkpoint| (checkpoint.chain_length, checkpoint.hash))
+            .collect(),
+    );
+
     let mut bootstrapped = false;
 
-    for address in trusted_peers_shuffled(&config) {
+    let addresses = trusted_peers_shuffled(&config).into_iter().chain(
+        book_peers
+            .iter()
+            .filter_map(|peer| peer.address.to_socketaddr()),
+    );
+
+    for address in addresses {
         let logger = logger.new(o!("peer_addr" => address.to_string()));
         let peer = Peer::new(address, Protocol::Grpc);
         let res = bootstrap::bootstrap_from_peer(
             peer,
             blockchain.clone(),
             branch.clone(),
+            Arc::clone(&checkpoints),
             logger.clone(),
         );
 
@@ -548,3 +964,56 @@ custom_error! {
         NoTrustedPeers = "no trusted peers specified",
         CouldNotDownloadBlock { block: HeaderHash } = "could not download block hash {block}",
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::start::config;
+
+    fn peer(id: p2p::Id, port: u16) -> TrustedPeer {
+        TrustedPeer::from(config::TrustedPeer {
+            address: config::Address(format!("/ip4/192.0.2.1/tcp/{}", port).parse().unwrap()),
+            id,
+        })
+    }
+
+    fn id() -> p2p::Id {
+        poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into()
+    }
+
+    #[test]
+    fn only_the_trusted_peers_of_the_address_book_are_bootstrapped_from() {
+        let trusted = id();
+        let gossiped = id();
+        let book_peers = vec![peer(gossiped, 3000), peer(trusted, 3001)];
+
+        let peers = trusted_book_peers(&[peer(trusted, 3002)], book_peers);
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, trusted);
+        assert_eq!(
+            peers[0].address.to_socketaddr(),
+            Some(([192, 0, 2, 1], 3001).into())
+        );
+    }
+
+    #[test]
+    fn core_node_accepts_only_its_relays() {
+        let relays = inbound_relays(p2p::Role::Core, &[peer(id(), 3000)]);
+
+        assert!(admits_inbound(&relays, ([192, 0, 2, 1], 41200).into()));
+        let mapped: IpAddr = "::ffff:192.0.2.1".parse().unwrap();
+        assert!(admits_inbound(&relays, (mapped, 41200).into()));
+        assert!(!admits_inbound(&relays, ([192, 0, 2, 7], 3000).into()));
+        let other: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(!admits_inbound(&relays, (other, 3000).into()));
+    }
+
+    #[test]
+    fn relay_node_accepts_any_address() {
+        let relays = inbound_relays(p2p::Role::Relay, &[peer(id(), 3000)]);
+
+        assert!(relays.is_none());
+        assert!(admits_inbound(&relays, ([192, 0, 2, 7], 3000).into()));
+    }
+}
@@ -1,6 +1,16 @@
+mod block_lane;
+mod chunk_size;
+mod fragment_routes;
 mod peer_map;
 
-use crate::blockcfg::{Block, Fragment, Header, HeaderHash};
+use self::block_lane::Announcements;
+use self::fragment_routes::FragmentRoutes;
+
+pub use self::block_lane::BlockLane;
+pub use self::chunk_size::{FetchMeasure, FetchSample};
+pub use self::peer_map::ConnectionStats;
+
+use crate::blockcfg::{Block, Fragment, FragmentId, Header, HeaderHash};
 use crate::network::{
     client::ConnectHandle,
     p2p::{Gossip as NodeData, Id, Node as NodeRef},
@@ -13,16 +23,22 @@ use network_core::gossip::{Gossip, Node};
 use network_core::subscription::{BlockEvent, ChainPullRequest};
 use slog::Logger;
 
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt;
 use std::mem;
 use std::sync::Mutex;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 // Buffer size determines the number of stream items pending processing that
 // can be buffered before back pressure is applied to the inbound half of
 // a gRPC subscription stream.
 const BUFFER_LEN: usize = 8;
 
+// The number of recent fragments whose senders and propagation targets
+// are remembered, to not propagate a fragment to the same peer twice.
+const FRAGMENT_ROUTES_LEN: usize = 4096;
+
 #[derive(Debug)]
 pub struct PropagateError<T> {
     kind: ErrorKind,
@@ -63,6 +79,16 @@ impl fmt::Display for ErrorKind {
 /// Stream used as the outbound half of a subscription stream.
 pub struct OutboundSubscription<T> {
     inner: mpsc::Receiver<T>,
+    lane: Lane,
+}
+
+// The part of a subscription stream in the priority of the block
+// announcements over the fragments. Dropping the announcements releases
+// the fragments held back.
+enum Lane {
+    Normal,
+    Blocks(Announcements),
+    HeldBack(BlockLane),
 }
 
 impl<T> Stream for OutboundSubscription<T> {
@@ -70,7 +96,16 @@ impl<T> Stream for OutboundSubscription<T> {
     type Error = core_error::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        Ok(self.inner.poll().unwrap())
+        if let Lane::HeldBack(ref lane) = self.lane {
+            if let Async::NotReady = lane.poll_clear() {
+                return Ok(Async::NotReady);
+            }
+        }
+        let item = self.inner.poll().unwrap();
+        if let (Lane::Blocks(ref announcements), Async::Ready(Some(_))) = (&self.lane, &item) {
+            announcements.sent();
+        }
+        Ok(item)
     }
 }
 
@@ -119,6 +154,13 @@ impl<T> CommHandle<T> {
         }
     }
 
+    pub fn is_pending(&self) -> bool {
+        match self.state {
+            SubscriptionState::Pending(_) => true,
+            _ => false,
+        }
+    }
+
     /// Updates this handle with the subscription state from another
     /// handle. This happens when another connection is established
     /// to the same peer. This method is used instead of replacing
@@ -148,7 +190,10 @@ impl<T> CommHandle<T> {
             tx.try_send(item).unwrap();
         }
         self.state = Subscribed(tx);
-        OutboundSubscription { inner: rx }
+        OutboundSubscription {
+            inner: rx,
+            lane: Lane::Normal,
+        }
     }
 
     pub fn is_subscribed(&self) -> bool {
@@ -217,6 +262,7 @@ pub struct PeerComms {
     chain_pulls: CommHandle<ChainPullRequest<HeaderHash>>,
     fragments: CommHandle<Fragment>,
     gossip: CommHandle<Gossip<NodeData>>,
+    block_lane: BlockLane,
 }
 
 impl PeerComms {
@@ -224,10 +270,25 @@ impl PeerComms {
         Default::default()
     }
 
+    /// the handles of a new connection to a peer, sharing the lane of the
+    /// block announcements to the peer
+    pub fn with_block_lane(block_lane: BlockLane) -> PeerComms {
+        PeerComms {
+            block_lane,
+            ..Default::default()
+        }
+    }
+
+    pub fn block_lane(&self) -> &BlockLane {
+        &self.block_lane
+    }
+
     pub fn update(&mut self, newer: PeerComms) {
         // If there would be a need to tell the old connection that
         // it is replaced in any better way than just dropping all its
         // communiction handles, this is the place to do it.
+        // The block lane of the peer is kept: the newer connection has been
+        // set up with it.
         self.block_announcements.update(newer.block_announcements);
         self.fragments.update(newer.fragments);
         self.gossip.update(newer.gossip);
@@ -255,11 +316,24 @@ impl PeerComms {
         self.gossip = CommHandle::pending(gossip);
     }
 
+    pub fn set_pending_block_solicitation(&mut self, hashes: Vec<HeaderHash>) {
+        self.block_solicitations = CommHandle::pending(hashes);
+    }
+
+    pub fn set_pending_chain_pull(&mut self, request: ChainPullRequest<HeaderHash>) {
+        self.chain_pulls = CommHandle::pending(request);
+    }
+
     pub fn try_send_block_announcement(
         &mut self,
         header: Header,
     ) -> Result<(), PropagateError<Header>> {
-        self.block_announcements.try_send(header)
+        let subscribed = self.block_announcements.is_subscribed();
+        self.block_announcements.try_send(header)?;
+        if subscribed {
+            self.block_lane.queued();
+        }
+        Ok(())
     }
 
     pub fn try_send_fragment(
@@ -277,7 +351,14 @@ impl PeerComms {
     }
 
     pub fn subscribe_to_block_announcements(&mut self) -> OutboundSubscription<Header> {
-        self.block_announcements.subscribe()
+        let announcements = self.block_lane.subscribe();
+        let pending = self.block_announcements.is_pending();
+        let mut subscription = self.block_announcements.subscribe();
+        if pending {
+            self.block_lane.queued();
+        }
+        subscription.lane = Lane::Blocks(announcements);
+        subscription
     }
 
     pub fn subscribe_to_block_solicitations(&mut self) -> OutboundSubscription<Vec<HeaderHash>> {
@@ -290,8 +371,17 @@ impl PeerComms {
         self.chain_pulls.subscribe()
     }
 
-    pub fn subscribe_to_fragments(&mut self) -> OutboundSubscription<Fragment> {
-        self.fragments.subscribe()
+    /// with `block_priority`, the fragments are held back while block
+    /// announcements are queued for the peer
+    pub fn subscribe_to_fragments(
+        &mut self,
+        block_priority: bool,
+    ) -> OutboundSubscription<Fragment> {
+        let mut subscription = self.fragments.subscribe();
+        if block_priority {
+            subscription.lane = Lane::HeldBack(self.block_lane.clone());
+        }
+        subscription
     }
 
     pub fn subscribe_to_gossip(&mut self) -> OutboundSubscription<Gossip<NodeData>> {
@@ -311,12 +401,50 @@ impl PeerComms {
     }
 }
 
+/// a count of the misbehaviours of a peer, started over once a window has
+/// passed since the first one counted, so an honest peer misbehaving once in
+/// a while is not eventually punished
+#[derive(Clone, Debug)]
+pub struct WindowedCount {
+    count: u32,
+    since: Instant,
+}
+
+impl Default for WindowedCount {
+    fn default() -> Self {
+        WindowedCount {
+            count: 0,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl WindowedCount {
+    pub fn get(&self) -> u32 {
+        self.count
+    }
+
+    /// count one more at `now`, returning the count in the current window
+    pub fn record(&mut self, now: Instant, window: Duration) -> u32 {
+        if self.count == 0 || now.duration_since(self.since) >= window {
+            self.count = 0;
+            self.since = now;
+        }
+        self.count += 1;
+        self.count
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PeerStats {
     created: SystemTime,
     last_block_received: Option<SystemTime>,
     last_fragment_received: Option<SystemTime>,
     last_gossip_received: Option<SystemTime>,
+    malformed_gossips: u32,
+    throttled: WindowedCount,
+    stalled_pulls: WindowedCount,
+    block_fetch_chunk_size: chunk_size::ChunkSize,
 }
 
 impl Default for PeerStats {
@@ -326,6 +454,10 @@ impl Default for PeerStats {
             last_block_received: None,
             last_fragment_received: None,
             last_gossip_received: None,
+            malformed_gossips: 0,
+            throttled: WindowedCount::default(),
+            stalled_pulls: WindowedCount::default(),
+            block_fetch_chunk_size: Default::default(),
         }
     }
 }
@@ -343,6 +475,34 @@ impl PeerStats {
         self.last_gossip_received.clone()
     }
 
+    /// the number of gossips received from the peer with malformed nodes
+    pub fn malformed_gossips(&self) -> u32 {
+        self.malformed_gossips
+    }
+
+    /// the number of times the peer was throttled for sending items over the
+    /// rate limits of its subscriptions, in the current window
+    pub fn throttled(&self) -> u32 {
+        self.throttled.get()
+    }
+
+    /// the number of the pulls from the peer which stalled, in the current
+    /// window
+    pub fn stalled_pulls(&self) -> u32 {
+        self.stalled_pulls.get()
+    }
+
+    /// the number of blocks to solicit from the peer at once
+    pub fn block_fetch_chunk_size(&self) -> usize {
+        self.block_fetch_chunk_size.get()
+    }
+
+    /// the rate the peer streams the block data at, in bytes per second,
+    /// 0 until a block fetch from the peer is measured
+    pub fn block_fetch_throughput(&self) -> f64 {
+        self.block_fetch_chunk_size.throughput()
+    }
+
     pub fn connection_established(&self) -> SystemTime {
         self.created
     }
@@ -367,13 +527,17 @@ impl PeerStats {
 /// all network connection tasks.
 pub struct Peers {
     mutex: Mutex<peer_map::PeerMap>,
+    fragment_routes: Mutex<FragmentRoutes>,
+    block_priority: bool,
     logger: Logger,
 }
 
 impl Peers {
-    pub fn new(capacity: usize, logger: Logger) -> Self {
+    pub fn new(capacity: usize, block_priority: bool, logger: Logger) -> Self {
         Peers {
             mutex: Mutex::new(peer_map::PeerMap::new(capacity)),
+            fragment_routes: Mutex::new(FragmentRoutes::new(FRAGMENT_ROUTES_LEN)),
+            block_priority,
             logger,
         }
     }
@@ -383,6 +547,13 @@ impl Peers {
         map.insert_peer(id, comms)
     }
 
+    /// the lane of the block announcements to the peer, shared by its
+    /// connections
+    pub fn block_lane(&self, id: Id) -> BlockLane {
+        let mut map = self.mutex.lock().unwrap();
+        map.block_lane(id).clone()
+    }
+
     pub fn connecting_with<F>(&self, id: Id, handle: ConnectHandle, modify_comms: F)
     where
         F: FnOnce(&mut PeerComms),
@@ -401,8 +572,7 @@ impl Peers {
         let mut map = self.mutex.lock().unwrap();
         let handles = map.server_comms(id);
         let announce_events: BlockEventAnnounceStream = handles
-            .block_announcements
-            .subscribe()
+            .subscribe_to_block_announcements()
             .map(BlockEvent::Announce);
         let solicit_events: BlockEventSolicitStream = handles
             .block_solicitations
@@ -418,7 +588,7 @@ impl Peers {
     pub fn serve_fragments(&self, id: Id) -> OutboundSubscription<Fragment> {
         let mut map = self.mutex.lock().unwrap();
         let handles = map.server_comms(id);
-        handles.fragments.subscribe()
+        handles.subscribe_to_fragments(self.block_priority)
     }
 
     pub fn serve_gossip(&self, id: Id) -> OutboundSubscription<Gossip<NodeData>> {
@@ -429,7 +599,7 @@ impl Peers {
 
     fn propagate_with<T, F>(&self, nodes: Vec<NodeRef>, f: F) -> Result<(), Vec<NodeRef>>
     where
-        F: Fn(&mut PeerComms) -> Result<(), PropagateError<T>>,
+        F: Fn(Id, &mut PeerComms) -> Result<(), PropagateError<T>>,
     {
         let mut map = self.mutex.lock().unwrap();
         let unreached_nodes = nodes
@@ -437,7 +607,7 @@ impl Peers {
             .filter(|node| {
                 let id = node.id();
                 if let Some(mut entry) = map.entry(id) {
-                    match f(entry.updated_comms()) {
+                    match f(id, entry.updated_comms()) {
                         Ok(()) => false,
                         Err(e) => {
                             debug!(
@@ -468,7 +638,7 @@ impl Peers {
             "propagating block";
             "hash" => %header.hash(),
         );
-        self.propagate_with(nodes, |handles| {
+        self.propagate_with(nodes, |_, handles| {
             handles.try_send_block_announcement(header.clone())
         })
     }
@@ -478,11 +648,47 @@ impl Peers {
         nodes: Vec<NodeRef>,
         fragment: Fragment,
     ) -> Result<(), Vec<NodeRef>> {
+        // Skip the peers the fragment was received from or already
+        // propagated to.
+        let fragment_id = fragment.id();
+        let nodes = {
+            let routes = self.fragment_routes.lock().unwrap();
+            routes.new_targets(fragment_id, nodes, |node| node.id())
+        };
         debug!(
             self.logger,
             "propagating fragment";
+            "targets" => nodes.len(),
         );
-        self.propagate_with(nodes, |handles| handles.try_send_fragment(fragment.clone()))
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        // A fragment stream held back behind the block announcements can
+        // fill up: the fragment is then dropped for the peer, which is not
+        // unsubscribed for it. Only the peers the fragment was sent to are
+        // recorded, it is propagated again to the others.
+        let block_priority = self.block_priority;
+        let receivers = RefCell::new(Vec::new());
+        let result = self.propagate_with(nodes, |id, handles| {
+            match handles.try_send_fragment(fragment.clone()) {
+                Ok(()) => {
+                    receivers.borrow_mut().push(id);
+                    Ok(())
+                }
+                Err(ref e) if block_priority && e.kind() == ErrorKind::StreamOverflow => Ok(()),
+                Err(e) => Err(e),
+            }
+        });
+        let mut routes = self.fragment_routes.lock().unwrap();
+        routes.add_receivers(fragment_id, receivers.into_inner());
+        result
+    }
+
+    /// the fragment was received from the peer, it will not be propagated
+    /// back to it
+    pub fn record_fragment_sender(&self, node_id: Id, fragment_id: FragmentId) {
+        let mut routes = self.fragment_routes.lock().unwrap();
+        routes.add_sender(fragment_id, node_id);
     }
 
     pub fn propagate_gossip_to(
@@ -549,24 +755,69 @@ impl Peers {
         }
     }
 
-    pub fn fetch_blocks(&self, hashes: Vec<HeaderHash>) {
+    /// count a gossip with malformed nodes received from the peer in its
+    /// stats, `false` if the peer is not in the map
+    pub fn record_malformed_gossip(&self, node_id: Id) -> bool {
         let mut map = self.mutex.lock().unwrap();
-        if let Some((node_id, comms)) = map.next_peer_for_block_fetch() {
-            debug!(self.logger, "fetching blocks from {}", node_id);
-            comms
-                .block_solicitations
-                .try_send(hashes)
-                .unwrap_or_else(|e| {
-                    debug!(self.logger, "block fetch from {} failed: {:?}", node_id, e);
-                    debug!(self.logger, "unsubscribing peer {}", node_id);
-                    map.remove_peer(node_id);
-                });
-        } else {
-            warn!(self.logger, "no peers to fetch blocks from");
+        match map.refresh_peer(node_id) {
+            Some(stats) => {
+                stats.malformed_gossips += 1;
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn solicit_blocks(&self, node_id: Id, hashes: Vec<HeaderHash>) {
+    /// count a throttling of the peer over the rate limits of its
+    /// subscriptions, returning the number of times it was throttled in the
+    /// current `window`
+    pub fn record_throttled(&self, node_id: Id, window: Duration) -> Option<u32> {
+        let mut map = self.mutex.lock().unwrap();
+        map.refresh_peer(node_id)
+            .map(|stats| stats.throttled.record(Instant::now(), window))
+    }
+
+    /// count a pull from the peer which stalled, returning the number of
+    /// stalled pulls in the current `window`
+    pub fn record_stall(&self, node_id: Id, window: Duration) -> Option<u32> {
+        let mut map = self.mutex.lock().unwrap();
+        map.refresh_peer(node_id)
+            .map(|stats| stats.stalled_pulls.record(Instant::now(), window))
+    }
+
+    /// the peers to fetch blocks from, the ones with the highest throughput
+    /// first, with the number of blocks to solicit from each at once
+    pub fn peers_for_block_fetch(&self, count: usize, except: &HashSet<Id>) -> Vec<(Id, usize)> {
+        let mut map = self.mutex.lock().unwrap();
+        map.peers_for_block_fetch(count, except)
+    }
+
+    /// adapt the chunk size of the block fetches from the peer to the
+    /// measures of a completed fetch
+    pub fn record_block_fetch(&self, node_id: Id, fetch: FetchSample) {
+        let mut map = self.mutex.lock().unwrap();
+        if let Some(stats) = map.refresh_peer(node_id) {
+            stats.block_fetch_chunk_size.record(&fetch);
+            debug!(self.logger, "block fetch measured";
+                   "node_id" => %node_id,
+                   "chunk_size" => stats.block_fetch_chunk_size.get());
+        }
+    }
+
+    pub fn record_block_fetch_failure(&self, node_id: Id) {
+        let mut map = self.mutex.lock().unwrap();
+        if let Some(mut entry) = map.entry(node_id) {
+            entry.stats().block_fetch_chunk_size.record_failure();
+        }
+    }
+
+    /// solicit the blocks over the connection of the peer, the hashes are
+    /// returned if the peer is not connected
+    pub fn solicit_blocks(
+        &self,
+        node_id: Id,
+        hashes: Vec<HeaderHash>,
+    ) -> Result<(), Vec<HeaderHash>> {
         let mut map = self.mutex.lock().unwrap();
         match map.peer_comms(node_id) {
             Some(comms) => {
@@ -583,18 +834,48 @@ impl Peers {
                         debug!(self.logger, "unsubscribing peer {}", node_id);
                         map.remove_peer(node_id);
                     });
+                Ok(())
             }
-            None => {
-                // TODO: connect and request on demand, or select another peer?
-                info!(
-                    self.logger,
-                    "peer {} not available to solicit blocks from", node_id
-                );
-            }
+            None => Err(hashes),
         }
     }
 
-    pub fn pull_headers(&self, node_id: Id, from: Vec<HeaderHash>, to: HeaderHash) {
+    /// solicit a chunk of a block fetch from the peer, returning the chunk if
+    /// the peer is not subscribed to the solicitations or cannot queue it:
+    /// a handle not subscribed yet only keeps the last chunk pending
+    pub fn solicit_block_fetch(
+        &self,
+        node_id: Id,
+        hashes: Vec<HeaderHash>,
+    ) -> Result<(), Vec<HeaderHash>> {
+        let mut map = self.mutex.lock().unwrap();
+        let comms = match map.peer_comms(node_id) {
+            Some(comms) if comms.block_solicitations.is_subscribed() => comms,
+            _ => return Err(hashes),
+        };
+        debug!(self.logger, "sending block solicitation to {}", node_id;
+               "hashes" => ?hashes);
+        comms.block_solicitations.try_send(hashes).map_err(|e| {
+            debug!(
+                self.logger,
+                "block solicitation from {} failed: {:?}", node_id, e
+            );
+            if e.kind() != ErrorKind::StreamOverflow {
+                debug!(self.logger, "unsubscribing peer {}", node_id);
+                map.remove_peer(node_id);
+            }
+            e.into_item()
+        })
+    }
+
+    /// pull the headers over the connection of the peer, the request is
+    /// returned if the peer is not connected
+    pub fn pull_headers(
+        &self,
+        node_id: Id,
+        from: Vec<HeaderHash>,
+        to: HeaderHash,
+    ) -> Result<(), ChainPullRequest<HeaderHash>> {
         let mut map = self.mutex.lock().unwrap();
         match map.peer_comms(node_id) {
             Some(comms) => {
@@ -613,14 +894,29 @@ impl Peers {
                         debug!(self.logger, "unsubscribing peer {}", node_id);
                         map.remove_peer(node_id);
                     });
+                Ok(())
             }
-            None => {
-                // TODO: connect and request on demand, or select another peer?
-                info!(
+            None => Err(ChainPullRequest { from, to }),
+        }
+    }
+
+    /// pull the headers a peer stalled on from another peer
+    pub fn repull_headers(&self, stalled: Id, from: Vec<HeaderHash>, to: HeaderHash) {
+        let mut map = self.mutex.lock().unwrap();
+        if let Some((node_id, comms, _)) = map.next_peer_for_block_fetch(Some(stalled)) {
+            debug!(self.logger, "pulling headers from another peer";
+                   "node_id" => %node_id,
+                   "stalled" => %stalled);
+            if let Err(e) = comms.chain_pulls.try_send(ChainPullRequest { from, to }) {
+                debug!(
                     self.logger,
-                    "peer {} not available to pull headers from", node_id
+                    "sending header pull solicitation to {} failed: {:?}", node_id, e
                 );
+                debug!(self.logger, "unsubscribing peer {}", node_id);
+                map.remove_peer(node_id);
             }
+        } else {
+            warn!(self.logger, "no other peers to pull headers from");
         }
     }
 
@@ -628,4 +924,49 @@ impl Peers {
         let map = self.mutex.lock().unwrap();
         map.stats()
     }
+
+    pub fn connection_stats(&self) -> ConnectionStats {
+        let mut map = self.mutex.lock().unwrap();
+        map.connection_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> Id {
+        poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into()
+    }
+
+    #[test]
+    fn malformed_gossips_are_counted_in_the_stats_of_known_peers() {
+        let peers = Peers::new(10, true, Logger::root(slog::Discard, o!()));
+        let known = id();
+        peers.insert_peer(known, PeerComms::new());
+
+        assert!(peers.record_malformed_gossip(known));
+        assert!(peers.record_malformed_gossip(known));
+        assert!(!peers.record_malformed_gossip(id()));
+
+        let stats = peers.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].0, known);
+        assert_eq!(stats[0].1.malformed_gossips(), 2);
+    }
+
+    #[test]
+    fn windowed_count_starts_over_after_the_window() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut count = WindowedCount::default();
+
+        assert_eq!(count.record(start, window), 1);
+        assert_eq!(count.record(start + Duration::from_secs(30), window), 2);
+        assert_eq!(count.record(start + Duration::from_secs(59), window), 3);
+        assert_eq!(count.get(), 3);
+        assert_eq!(count.record(start + Duration::from_secs(60), window), 1);
+        assert_eq!(count.record(start + Duration::from_secs(100), window), 2);
+        assert_eq!(count.record(start + Duration::from_secs(200), window), 1);
+    }
 }
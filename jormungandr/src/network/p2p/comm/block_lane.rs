@@ -0,0 +1,173 @@
+//! the priority of the block announcements over the fragments sent to a peer
+//!
+//! The outbound streams to a peer share its connection. While block
+//! announcements are queued for the peer, its fragment stream is held back,
+//! so the new tips are not delayed behind heavy transaction traffic. A peer
+//! has one lane, shared by the subscriptions of all its connections. The
+//! count of the queued announcements is best effort: announcements sent
+//! before the subscription of the lane are not counted, and a new
+//! subscription resets the count.
+
+use futures::{task::AtomicTask, Async};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+#[derive(Clone, Default)]
+pub struct BlockLane {
+    inner: Arc<LaneState>,
+}
+
+#[derive(Default)]
+struct LaneState {
+    queued: AtomicUsize,
+    /// the number of the subscriptions of announcements to the lane, only
+    /// the latest one counting its announcements sent
+    generation: AtomicUsize,
+    held_back: AtomicTask,
+}
+
+/// the subscription of the announcements to a lane, releasing the held back
+/// fragments when dropped
+pub struct Announcements {
+    lane: BlockLane,
+    generation: usize,
+}
+
+impl BlockLane {
+    /// subscribe the announcements to the lane, replacing the previous
+    /// subscription and forgetting its queued announcements
+    pub fn subscribe(&self) -> Announcements {
+        let generation = self.inner.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.reset();
+        Announcements {
+            lane: self.clone(),
+            generation,
+        }
+    }
+
+    /// an announcement has been queued for the peer
+    pub fn queued(&self) {
+        self.inner.queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn sent(&self) {
+        let mut current = self.inner.queued.load(Ordering::SeqCst);
+        while current > 0 {
+            match self.inner.queued.compare_exchange(
+                current,
+                current - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    if current == 1 {
+                        self.inner.held_back.notify();
+                    }
+                    return;
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn reset(&self) {
+        self.inner.queued.store(0, Ordering::SeqCst);
+        self.inner.held_back.notify();
+    }
+
+    /// ready when no announcement is queued, otherwise the current task is
+    /// notified once the queue is empty
+    pub fn poll_clear(&self) -> Async<()> {
+        if self.inner.queued.load(Ordering::SeqCst) == 0 {
+            return Async::Ready(());
+        }
+        self.inner.held_back.register();
+        if self.inner.queued.load(Ordering::SeqCst) == 0 {
+            Async::Ready(())
+        } else {
+            Async::NotReady
+        }
+    }
+}
+
+impl Announcements {
+    fn is_current(&self) -> bool {
+        self.lane.inner.generation.load(Ordering::SeqCst) == self.generation
+    }
+
+    /// an announcement has been taken from the queue to be sent; the
+    /// announcements of a replaced subscription are not counted
+    pub fn sent(&self) {
+        if self.is_current() {
+            self.lane.sent();
+        }
+    }
+}
+
+impl Drop for Announcements {
+    fn drop(&mut self) {
+        // the announcements still queued will never be sent
+        if self.is_current() {
+            self.lane.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, Future};
+
+    fn is_clear(lane: &BlockLane) -> bool {
+        // poll_clear registers the current task when not clear
+        future::lazy(|| Ok::<_, ()>(lane.poll_clear().is_ready()))
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn clear_once_the_queued_announcements_are_sent() {
+        let lane = BlockLane::default();
+        let announcements = lane.subscribe();
+        assert!(is_clear(&lane));
+        lane.queued();
+        lane.queued();
+        assert!(!is_clear(&lane));
+        announcements.sent();
+        assert!(!is_clear(&lane));
+        announcements.sent();
+        assert!(is_clear(&lane));
+        // the announcements sent before the subscription are not counted
+        announcements.sent();
+        lane.queued();
+        assert!(!is_clear(&lane));
+    }
+
+    #[test]
+    fn dropped_subscription_clears_the_lane() {
+        let lane = BlockLane::default();
+        let announcements = lane.subscribe();
+        lane.queued();
+        assert!(!is_clear(&lane));
+        drop(announcements);
+        assert!(is_clear(&lane));
+    }
+
+    #[test]
+    fn replaced_subscription_does_not_count() {
+        let lane = BlockLane::default();
+        let replaced = lane.subscribe();
+        lane.queued();
+        let announcements = lane.subscribe();
+        assert!(is_clear(&lane));
+        lane.queued();
+        replaced.sent();
+        assert!(!is_clear(&lane));
+        drop(replaced);
+        assert!(!is_clear(&lane));
+        announcements.sent();
+        assert!(is_clear(&lane));
+    }
+}
@@ -0,0 +1,229 @@
+//! the number of blocks to solicit from a peer at once
+//!
+//! The size of the chunks is adapted to each peer from its block fetches:
+//! the round-trip time until the first block arrives, the rate at which the
+//! peer streams the block data and the size of the blocks. A chunk is as
+//! large as the peer is expected to deliver within `TARGET_DURATION`, so
+//! fast peers are solicited whole requests of the block task while slow
+//! peers are not asked for more than they can deliver before the request
+//! times out.
+//!
+//! The size of a block is the size of its contents given by its header: the
+//! headers are left out, being of about the same size for every block.
+
+use crate::blockcfg::Block;
+use std::time::{Duration, Instant};
+
+/// the chunk size of a peer no block was fetched from yet
+pub const INITIAL: usize = 32;
+pub const MIN: usize = 4;
+/// the number of blocks the block task requests at once
+pub const MAX: usize = 32;
+
+/// the time a chunk is expected to be delivered in
+const TARGET_DURATION: Duration = Duration::from_secs(5);
+
+/// the weight of the last fetch in the moving averages of the measures
+const WEIGHT: f64 = 0.25;
+
+/// the shortest transfer time taken into account, so a fetch of blocks
+/// arriving all at once does not yield an infinite throughput
+const MIN_TRANSFER_SECS: f64 = 0.001;
+
+#[derive(Clone, Debug)]
+pub struct ChunkSize {
+    /// the round-trip time, in seconds
+    rtt: f64,
+    /// the throughput of the block data, in bytes per second
+    throughput: f64,
+    /// the size of a block, in bytes
+    block_size: f64,
+    measured: bool,
+    size: usize,
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        ChunkSize {
+            rtt: 0.0,
+            throughput: 0.0,
+            block_size: 0.0,
+            measured: false,
+            size: INITIAL,
+        }
+    }
+}
+
+impl ChunkSize {
+    pub fn get(&self) -> usize {
+        self.size
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.throughput
+    }
+
+    /// update the measures of the peer with a completed block fetch
+    pub fn record(&mut self, fetch: &FetchSample) {
+        let rtt = secs(fetch.rtt);
+        let transfer = (secs(fetch.elapsed) - rtt).max(MIN_TRANSFER_SECS);
+        let throughput = fetch.bytes as f64 / transfer;
+        let block_size = fetch.bytes as f64 / fetch.blocks as f64;
+        if self.measured {
+            self.rtt = average(self.rtt, rtt);
+            self.throughput = average(self.throughput, throughput);
+            self.block_size = average(self.block_size, block_size);
+        } else {
+            self.rtt = rtt;
+            self.throughput = throughput;
+            self.block_size = block_size;
+            self.measured = true;
+        }
+        self.size = self.estimate();
+    }
+
+    /// halve the chunk size after a failed block fetch
+    pub fn record_failure(&mut self) {
+        self.size = (self.size / 2).max(MIN);
+    }
+
+    fn estimate(&self) -> usize {
+        let available = secs(TARGET_DURATION) - self.rtt;
+        if available <= 0.0 {
+            return MIN;
+        }
+        if self.block_size <= 0.0 {
+            return MAX;
+        }
+        let blocks = available * self.throughput / self.block_size;
+        if blocks >= MAX as f64 {
+            MAX
+        } else {
+            (blocks as usize).max(MIN)
+        }
+    }
+}
+
+/// the measures of a completed block fetch
+#[derive(Debug, Clone)]
+pub struct FetchSample {
+    blocks: usize,
+    bytes: u64,
+    rtt: Duration,
+    elapsed: Duration,
+}
+
+/// the measures of a block fetch in progress
+pub struct FetchMeasure {
+    started: Instant,
+    first_block: Option<Instant>,
+    blocks: usize,
+    bytes: u64,
+}
+
+impl FetchMeasure {
+    /// start measuring a block fetch sent to the peer now
+    pub fn start() -> Self {
+        FetchMeasure {
+            started: Instant::now(),
+            first_block: None,
+            blocks: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn add_block(&mut self, block: &Block) {
+        if self.first_block.is_none() {
+            self.first_block = Some(Instant::now());
+        }
+        self.blocks += 1;
+        self.bytes += u64::from(block.header.block_content_size());
+    }
+
+    /// the measures of the fetch, none if the peer sent no block
+    pub fn finish(&self) -> Option<FetchSample> {
+        let first_block = self.first_block?;
+        Some(FetchSample {
+            blocks: self.blocks,
+            bytes: self.bytes,
+            rtt: first_block.duration_since(self.started),
+            elapsed: self.started.elapsed(),
+        })
+    }
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
+fn average(previous: f64, last: f64) -> f64 {
+    previous * (1.0 - WEIGHT) + last * WEIGHT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(blocks: usize, bytes: u64, rtt_millis: u64, elapsed_millis: u64) -> FetchSample {
+        FetchSample {
+            blocks,
+            bytes,
+            rtt: Duration::from_millis(rtt_millis),
+            elapsed: Duration::from_millis(elapsed_millis),
+        }
+    }
+
+    #[test]
+    fn peers_not_measured_are_solicited_the_initial_size() {
+        assert_eq!(ChunkSize::default().get(), INITIAL);
+    }
+
+    #[test]
+    fn fast_peers_are_solicited_whole_requests() {
+        let mut chunk_size = ChunkSize::default();
+        chunk_size.record(&sample(32, 32 * 1024, 50, 100));
+        assert_eq!(chunk_size.get(), MAX);
+    }
+
+    #[test]
+    fn slow_peers_are_solicited_what_they_deliver_in_time() {
+        let mut chunk_size = ChunkSize::default();
+        // 1 KiB blocks at 2 KiB/s after a second of round trip: 8 blocks
+        // in the 4 seconds left
+        chunk_size.record(&sample(4, 4 * 1024, 1000, 3000));
+        assert_eq!(chunk_size.get(), 8);
+    }
+
+    #[test]
+    fn peers_answering_after_the_target_are_solicited_the_minimum() {
+        let mut chunk_size = ChunkSize::default();
+        chunk_size.record(&sample(1, 1024, 6000, 6100));
+        assert_eq!(chunk_size.get(), MIN);
+    }
+
+    #[test]
+    fn measures_are_averaged() {
+        let mut chunk_size = ChunkSize::default();
+        chunk_size.record(&sample(4, 4 * 1024, 1000, 3000));
+        chunk_size.record(&sample(4, 4 * 1024, 1000, 2000));
+        // 2 KiB/s then 4 KiB/s, weighted to 2.5 KiB/s
+        assert!((chunk_size.throughput() - 2.5 * 1024.0).abs() < 1e-6);
+        assert_eq!(chunk_size.get(), 10);
+    }
+
+    #[test]
+    fn failures_halve_the_size_down_to_the_minimum() {
+        let mut chunk_size = ChunkSize::default();
+        chunk_size.record_failure();
+        assert_eq!(chunk_size.get(), INITIAL / 2);
+        for _ in 0..8 {
+            chunk_size.record_failure();
+        }
+        assert_eq!(chunk_size.get(), MIN);
+    }
+
+    #[test]
+    fn fetches_without_blocks_are_not_measured() {
+        assert!(FetchMeasure::start().finish().is_none());
+    }
+}
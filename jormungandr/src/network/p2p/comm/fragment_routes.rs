@@ -0,0 +1,131 @@
+//! the peers each recent fragment was received from or propagated to
+//!
+//! A fragment is not propagated back to a peer that sent it to the node,
+//! nor again to a peer it was already sent to. A peer the fragment could not
+//! be sent to is not recorded, the fragment is propagated to it again. Only
+//! the routes of the `capacity` most recent fragments are kept.
+
+use crate::blockcfg::FragmentId;
+use crate::network::p2p::Id;
+use linked_hash_map::LinkedHashMap;
+use std::collections::HashSet;
+
+pub struct FragmentRoutes {
+    map: LinkedHashMap<FragmentId, HashSet<Id>>,
+    capacity: usize,
+}
+
+impl FragmentRoutes {
+    pub fn new(capacity: usize) -> Self {
+        FragmentRoutes {
+            map: LinkedHashMap::new(),
+            capacity,
+        }
+    }
+
+    /// the fragment was received from the peer
+    pub fn add_sender(&mut self, fragment_id: FragmentId, node_id: Id) {
+        self.peers_mut(fragment_id).insert(node_id);
+    }
+
+    /// the fragment was sent to the peers
+    pub fn add_receivers<I>(&mut self, fragment_id: FragmentId, node_ids: I)
+    where
+        I: IntoIterator<Item = Id>,
+    {
+        let mut node_ids = node_ids.into_iter().peekable();
+        if node_ids.peek().is_some() {
+            self.peers_mut(fragment_id).extend(node_ids);
+        }
+    }
+
+    /// keep the peers the fragment was not exchanged with yet
+    pub fn new_targets<T, F>(&self, fragment_id: FragmentId, nodes: Vec<T>, id: F) -> Vec<T>
+    where
+        F: Fn(&T) -> Id,
+    {
+        match self.map.get(&fragment_id) {
+            None => nodes,
+            Some(peers) => nodes
+                .into_iter()
+                .filter(|node| !peers.contains(&id(node)))
+                .collect(),
+        }
+    }
+
+    fn peers_mut(&mut self, fragment_id: FragmentId) -> &mut HashSet<Id> {
+        if !self.map.contains_key(&fragment_id) && self.map.len() >= self.capacity {
+            self.map.pop_front();
+        }
+        self.map.entry(fragment_id).or_insert_with(HashSet::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> Id {
+        poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into()
+    }
+
+    fn fragment_id(seed: u8) -> FragmentId {
+        jormungandr_lib::crypto::hash::Hash::from([seed; 32]).into_hash()
+    }
+
+    fn targets(routes: &FragmentRoutes, fragment_id: FragmentId, nodes: &[Id]) -> Vec<Id> {
+        routes.new_targets(fragment_id, nodes.to_vec(), |node| *node)
+    }
+
+    #[test]
+    fn fragment_is_not_sent_back_nor_twice() {
+        let (sender, first, second) = (id(), id(), id());
+        let nodes = [sender, first, second];
+        let mut routes = FragmentRoutes::new(16);
+
+        routes.add_sender(fragment_id(1), sender);
+        assert_eq!(targets(&routes, fragment_id(1), &nodes), vec![first, second]);
+
+        routes.add_receivers(fragment_id(1), vec![first, second]);
+        assert!(targets(&routes, fragment_id(1), &nodes).is_empty());
+
+        // the routes of the other fragments are not affected
+        assert_eq!(targets(&routes, fragment_id(2), &nodes), nodes.to_vec());
+    }
+
+    #[test]
+    fn peers_the_fragment_was_not_sent_to_are_targeted_again() {
+        let (reached, failed) = (id(), id());
+        let nodes = [reached, failed];
+        let mut routes = FragmentRoutes::new(16);
+
+        // selecting the targets does not record them
+        assert_eq!(targets(&routes, fragment_id(1), &nodes), nodes.to_vec());
+        assert_eq!(targets(&routes, fragment_id(1), &nodes), nodes.to_vec());
+
+        // the send to `failed` did not succeed
+        routes.add_receivers(fragment_id(1), vec![reached]);
+        assert_eq!(targets(&routes, fragment_id(1), &nodes), vec![failed]);
+
+        // no send succeeded: nothing is recorded
+        routes.add_receivers(fragment_id(2), Vec::new());
+        assert!(!routes.map.contains_key(&fragment_id(2)));
+    }
+
+    #[test]
+    fn routes_of_the_oldest_fragments_expire() {
+        let node = id();
+        let mut routes = FragmentRoutes::new(2);
+
+        routes.add_receivers(fragment_id(1), vec![node]);
+        routes.add_sender(fragment_id(2), node);
+        assert!(targets(&routes, fragment_id(1), &[node]).is_empty());
+        assert!(targets(&routes, fragment_id(2), &[node]).is_empty());
+
+        routes.add_receivers(fragment_id(3), vec![node]);
+        assert_eq!(targets(&routes, fragment_id(1), &[node]), vec![node]);
+        assert!(targets(&routes, fragment_id(2), &[node]).is_empty());
+        assert!(targets(&routes, fragment_id(3), &[node]).is_empty());
+        assert_eq!(routes.map.len(), 2);
+    }
+}
@@ -1,12 +1,24 @@
 use crate::network::{
     client::ConnectHandle,
     p2p::{
-        comm::{PeerComms, PeerStats},
+        comm::{BlockLane, PeerComms, PeerStats},
         Id,
     },
 };
 
 use linked_hash_map::LinkedHashMap;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// the connections of the peers in the map
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionStats {
+    pub established: usize,
+    /// the client connections being established, the peer is not connected
+    /// otherwise
+    pub connecting: usize,
+    pub capacity: usize,
+}
 
 pub struct PeerMap {
     map: LinkedHashMap<Id, PeerData>,
@@ -91,6 +103,10 @@ impl PeerMap {
         self.ensure_peer(id).server_comms()
     }
 
+    pub fn block_lane(&mut self, id: Id) -> &BlockLane {
+        self.ensure_peer(id).comms.block_lane()
+    }
+
     pub fn insert_peer(&mut self, id: Id, comms: PeerComms) {
         self.evict_if_full();
         let data = PeerData::with_comms(comms);
@@ -112,11 +128,52 @@ impl PeerMap {
         })
     }
 
-    pub fn next_peer_for_block_fetch(&mut self) -> Option<(Id, &mut PeerComms)> {
+    /// the most recently active peer, other than `except`
+    pub fn next_peer_for_block_fetch(
+        &mut self,
+        except: Option<Id>,
+    ) -> Option<(Id, &mut PeerComms, usize)> {
         self.map
             .iter_mut()
-            .next_back()
-            .map(|(&id, data)| (id, data.updated_comms()))
+            .rev()
+            .find(|(id, _)| Some(**id) != except)
+            .map(|(&id, data)| {
+                let chunk_size = data.stats.block_fetch_chunk_size();
+                (id, data.updated_comms(), chunk_size)
+            })
+    }
+
+    /// the subscribed peers not in `except` with the highest throughput, the
+    /// most recently active first among the ones not measured yet
+    pub fn peers_for_block_fetch(
+        &mut self,
+        count: usize,
+        except: &HashSet<Id>,
+    ) -> Vec<(Id, usize)> {
+        let mut peers: Vec<_> = self
+            .map
+            .iter_mut()
+            .rev()
+            .filter(|(id, _)| !except.contains(*id))
+            .filter_map(|(&id, data)| {
+                // a handle not subscribed yet only keeps the last chunk
+                if data.updated_comms().block_solicitations.is_subscribed() {
+                    Some((
+                        id,
+                        data.stats.block_fetch_throughput(),
+                        data.stats.block_fetch_chunk_size(),
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        peers.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        peers
+            .into_iter()
+            .take(count)
+            .map(|(id, _, chunk_size)| (id, chunk_size))
+            .collect()
     }
 
     pub fn stats(&self) -> Vec<(Id, PeerStats)> {
@@ -126,6 +183,23 @@ impl PeerMap {
             .collect()
     }
 
+    pub fn connection_stats(&mut self) -> ConnectionStats {
+        let mut stats = ConnectionStats {
+            capacity: self.capacity,
+            ..Default::default()
+        };
+        for data in self.map.values_mut() {
+            // completes the connections established since the last access
+            data.updated_comms();
+            if data.connecting.is_some() {
+                stats.connecting += 1;
+            } else {
+                stats.established += 1;
+            }
+        }
+        stats
+    }
+
     fn evict_if_full(&mut self) {
         if self.map.len() >= self.capacity {
             self.map.pop_front();
@@ -11,66 +11,106 @@ pub struct Gossip(poldercast::NodeProfile);
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Gossips(poldercast::Gossips);
 
+/// the reasons the address of a gossiped node is not accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressRejection {
+    Missing,
+    Unparsable,
+    Unspecified,
+    Broadcast,
+    Multicast,
+    Documentation,
+    Loopback,
+    Private,
+    LinkLocal,
+}
+
+impl AddressRejection {
+    /// the label of the rejection in the metrics
+    pub fn as_str(self) -> &'static str {
+        use self::AddressRejection::*;
+        match self {
+            Missing => "missing",
+            Unparsable => "unparsable",
+            Unspecified => "unspecified",
+            Broadcast => "broadcast",
+            Multicast => "multicast",
+            Documentation => "documentation",
+            Loopback => "loopback",
+            Private => "private",
+            LinkLocal => "link_local",
+        }
+    }
+
+    /// whether the address is garbage no honest peer would gossip. The nodes
+    /// without address and the private addresses may be relayed by peers of
+    /// networks allowing them.
+    pub fn is_malformed(self) -> bool {
+        use self::AddressRejection::*;
+        match self {
+            Missing | Private | LinkLocal => false,
+            Unparsable | Unspecified | Broadcast | Multicast | Documentation | Loopback => true,
+        }
+    }
+}
+
 impl Gossip {
-    pub fn has_valid_address(&self) -> bool {
-        let addr = match self.address() {
-            None => return false,
-            Some(addr) => addr,
+    /// why the address of the node is not accepted, if it is not. The
+    /// loopback, private and link-local addresses are only accepted with
+    /// `allow_private_addresses`.
+    pub fn address_rejection(&self, allow_private_addresses: bool) -> Option<AddressRejection> {
+        let addr = match self.0.address() {
+            None => return Some(AddressRejection::Missing),
+            Some(address) => match address.to_socketaddr() {
+                None => return Some(AddressRejection::Unparsable),
+                Some(addr) => addr,
+            },
         };
 
         match addr.ip() {
             IpAddr::V4(ip) => {
                 if ip.is_unspecified() {
-                    return false;
+                    return Some(AddressRejection::Unspecified);
                 }
                 if ip.is_broadcast() {
-                    return false;
+                    return Some(AddressRejection::Broadcast);
                 }
                 if ip.is_multicast() {
-                    return false;
+                    return Some(AddressRejection::Multicast);
                 }
                 if ip.is_documentation() {
-                    return false;
+                    return Some(AddressRejection::Documentation);
                 }
             }
             IpAddr::V6(ip) => {
                 if ip.is_unspecified() {
-                    return false;
+                    return Some(AddressRejection::Unspecified);
                 }
                 if ip.is_multicast() {
-                    return false;
+                    return Some(AddressRejection::Multicast);
                 }
             }
         }
 
-        true
-    }
-
-    pub fn is_global(&self) -> bool {
-        if !self.has_valid_address() {
-            return false;
+        if allow_private_addresses {
+            return None;
         }
 
-        let addr = match self.address() {
-            None => return false,
-            Some(addr) => addr,
-        };
-
         match addr.ip() {
             IpAddr::V4(ip) => {
                 if ip.is_private() {
-                    return false;
+                    return Some(AddressRejection::Private);
                 }
                 if ip.is_loopback() {
-                    return false;
+                    return Some(AddressRejection::Loopback);
                 }
                 if ip.is_link_local() {
-                    return false;
+                    return Some(AddressRejection::LinkLocal);
                 }
             }
             IpAddr::V6(ip) => {
                 if ip.is_loopback() {
-                    return false;
+                    return Some(AddressRejection::Loopback);
                 }
                 // FIXME: add more tests when Ipv6Addr convenience methods
                 // get stabilized:
@@ -78,7 +118,7 @@ impl Gossip {
             }
         }
 
-        true
+        None
     }
 }
 
@@ -5,11 +5,11 @@ mod node;
 mod policy;
 mod topology;
 
-pub use self::gossip::{Gossip, Gossips};
+pub use self::gossip::{AddressRejection, Gossip, Gossips};
 pub use self::id::Id;
 pub use self::node::Node;
 pub use self::policy::{Policy, PolicyConfig};
-pub use self::topology::P2pTopology;
+pub use self::topology::{LayersConfig, P2pTopology, RingsConfig, Role, ViewConfig};
 
 /**
 # topics definition for p2p interest subscriptions
@@ -34,4 +34,24 @@ pub mod limits {
     /// limit the ID size to 32 bytes. Right now the Node ID are 24 bytes but
     /// for backward compatibility keep the value to 32bytes.
     pub const MAX_ID_SIZE: u64 = 32;
+
+    /// the maximum size of an encoded header received from a peer. The
+    /// largest headers, signed by a stake pool, are below 1KiB.
+    pub const MAX_HEADER_SIZE: usize = 4096;
+
+    /// the maximum size of an encoded block received from a peer, well above
+    /// the block content sizes of the blockchain settings.
+    pub const MAX_BLOCK_SIZE: usize = 8 * 1024 * 1024;
+
+    /// the maximum size of an encoded fragment received from a peer, the
+    /// size of a fragment being encoded on 16 bits.
+    pub const MAX_FRAGMENT_SIZE: usize = 65_537;
+
+    /// the maximum number of nodes in a gossip message received from a peer.
+    pub const MAX_GOSSIP_NODES: usize = 256;
+
+    /// the maximum size of the other messages received from a peer: the
+    /// requests, their responses and the block events, listing block IDs
+    /// at most.
+    pub const MAX_REQUEST_SIZE: usize = 1024 * 1024;
 }
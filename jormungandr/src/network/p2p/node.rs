@@ -2,6 +2,7 @@ use crate::network::p2p::Id;
 use bincode;
 use chain_core::property;
 use network_core::gossip;
+use poldercast::Topic;
 use std::net::SocketAddr;
 
 pub struct Node {
@@ -12,6 +13,20 @@ impl Node {
     pub fn new(info: poldercast::NodeInfo) -> Self {
         Self { info }
     }
+
+    /// the node gossiped its subscriptions and none is to the topic. The
+    /// nodes known only by their address, as the trusted peers before their
+    /// first gossip, declared no subscriptions and are not left out.
+    pub fn declined_topic(&self, topic: Topic) -> bool {
+        let mut declared = false;
+        for subscription in self.info.profile().subscriptions().iter() {
+            if subscription.topic == topic {
+                return false;
+            }
+            declared = true;
+        }
+        declared
+    }
 }
 
 impl gossip::Node for Node {
@@ -10,13 +10,64 @@ use poldercast::{
     poldercast::{Cyclon, Rings, Vicinity},
     Layer, NodeProfile, PolicyReport, StrikeReason, Topology,
 };
+use serde::{Deserialize, Serialize};
 use slog::Logger;
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+/// the role of the node in the P2P network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// a block producing node hidden behind its relays: it connects only to
+    /// its trusted peers and never advertises itself in the gossips
+    Core,
+    /// a node accepting the public traffic
+    Relay,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Relay
+    }
+}
+
+/// the parameters of the poldercast layers, the layers not set keep the
+/// poldercast defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayersConfig {
+    pub rings: Option<RingsConfig>,
+    pub vicinity: Option<ViewConfig>,
+    pub cyclon: Option<ViewConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RingsConfig {
+    /// the number of neighbours kept in the ring of each topic
+    pub view_length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewConfig {
+    /// the number of nodes kept in the view of the layer
+    pub view_length: usize,
+    /// the number of nodes sent in each gossip of the layer
+    pub gossip_length: usize,
+}
 
 /// object holding the P2pTopology of the Node
 #[derive(Clone)]
 pub struct P2pTopology {
     lock: Arc<RwLock<Topology>>,
+    /// the only nodes known to a core node, none for a relay node
+    relays: Option<Arc<HashSet<Id>>>,
+    /// the node is left out of its gossips, as a core node or a client only
+    unadvertised: bool,
     logger: Logger,
 }
 
@@ -27,10 +78,65 @@ impl P2pTopology {
     pub fn new(node: poldercast::NodeProfile, logger: Logger) -> Self {
         P2pTopology {
             lock: Arc::new(RwLock::new(Topology::new(node))),
+            relays: None,
+            unadvertised: false,
             logger,
         }
     }
 
+    /// make the node a core node: the topology only keeps the given relays,
+    /// and the gossips of the node leave the node out
+    pub fn set_core<I: IntoIterator<Item = Id>>(&mut self, relays: I) {
+        info!(
+            self.logger,
+            "the node is a core node, only its relays are gossiped with"
+        );
+        self.relays = Some(Arc::new(relays.into_iter().collect()));
+        self.unadvertised = true;
+    }
+
+    /// make the node a client only: the peers cannot connect to it, so the
+    /// gossips of the node leave the node out
+    pub fn set_client_only(&mut self) {
+        info!(
+            self.logger,
+            "the node is a client only, it does not listen for the connections of the peers"
+        );
+        self.unadvertised = true;
+    }
+
+    fn is_known(&self, id: Id) -> bool {
+        self.relays
+            .as_ref()
+            .map_or(true, |relays| relays.contains(&id))
+    }
+
+    /// the gossips about the nodes the topology keeps
+    fn known_gossips(&self, gossips: Gossips) -> Gossips {
+        if self.relays.is_none() {
+            return gossips;
+        }
+        let gossips: poldercast::Gossips = gossips.into();
+        let known: Vec<NodeProfile> = gossips
+            .into_iter()
+            .filter(|profile| self.is_known((*profile.id()).into()))
+            .collect();
+        poldercast::Gossips::from(known).into()
+    }
+
+    /// the gossips to send, without the gossip about the node itself for a
+    /// core node or a client only
+    fn advertised_gossips(&self, gossips: poldercast::Gossips, node: &NodeProfile) -> Gossips {
+        if !self.unadvertised {
+            return gossips.into();
+        }
+        let advertised: Vec<NodeProfile> = gossips
+            .into_iter()
+            .filter(|profile| profile.id() != node.id())
+            .collect();
+        poldercast::Gossips::from(advertised).into()
+    }
+
     /// set a P2P Topology Module. Each module will work independently from
     /// each other and will help improve the node connectivity
     pub fn add_module<M: Layer + Send + Sync + 'static>(&self, module: M) {
@@ -48,21 +154,35 @@ impl P2pTopology {
         topology.set_policy(Policy::new(policy, self.logger.new(o!("task" => "policy"))));
     }
 
-    /// set all the default poldercast modules (Rings, Vicinity and Cyclon)
-    pub fn set_poldercast_modules(&mut self) {
+    /// set all the poldercast modules (Rings, Vicinity and Cyclon)
+    pub fn set_poldercast_modules(&mut self, config: &LayersConfig) {
         let mut topology = self.lock.write().unwrap();
-        topology.add_layer(Rings::default());
-        topology.add_layer(Vicinity::default());
-        topology.add_layer(Cyclon::default());
+        add_poldercast_layers(&mut topology, config);
     }
 
     pub fn set_custom_modules(&mut self, config: &Configuration) {
         let mut topology = self.lock.write().unwrap();
-        if let Some(size) = config.max_unreachable_nodes_to_connect_per_event {
-            topology.add_layer(custom_layers::RandomDirectConnections::with_max_view_length(size))
-        } else {
-            topology.add_layer(custom_layers::RandomDirectConnections::default());
-        }
+        add_custom_layers(&mut topology, config);
+    }
+
+    /// give the node a new ID: the topology is built again around the new
+    /// profile of the node, from the nodes of the current view
+    pub fn set_node_id(&self, id: Id, config: &Configuration) {
+        let mut topology = self.lock.write().unwrap();
+        let known: Vec<NodeProfile> = topology
+            .view(None, poldercast::Selection::Any)
+            .into_iter()
+            .map(|info| info.profile().clone())
+            .collect();
+        let mut renewed = Topology::new(profile_with_id(topology.profile(), id));
+        add_poldercast_layers(&mut renewed, &config.layers);
+        add_custom_layers(&mut renewed, config);
+        renewed.set_policy(Policy::new(
+            config.policy.clone(),
+            self.logger.new(o!("task" => "policy")),
+        ));
+        renewed.accept_gossips(id.into(), poldercast::Gossips::from(known));
+        *topology = renewed;
     }
 
     /// Returns a list of neighbors selected in this turn
@@ -72,31 +192,49 @@ impl P2pTopology {
         topology
             .view(None, poldercast::Selection::Any)
             .into_iter()
+            .filter(|info| self.is_known((*info.id()).into()))
             .map(Node::new)
             .collect()
     }
 
+    /// the node with the given id, if it is in the view
+    pub fn find(&self, id: Id) -> Option<Node> {
+        self.view().into_iter().find(|node| node.id() == id)
+    }
+
     pub fn initiate_gossips(&self, with: Id) -> Gossips {
         let mut topology = self.lock.write().unwrap();
-        topology.initiate_gossips(with.into()).into()
+        let gossips = topology.initiate_gossips(with.into());
+        self.advertised_gossips(gossips, topology.profile())
     }
 
     pub fn accept_gossips(&self, from: Id, gossips: Gossips) {
+        let gossips = self.known_gossips(gossips);
         let mut topology = self.lock.write().unwrap();
         topology.accept_gossips(from.into(), gossips.into())
     }
 
     pub fn exchange_gossips(&mut self, with: Id, gossips: Gossips) -> Gossips {
+        let gossips = self.known_gossips(gossips);
         let mut topology = self.lock.write().unwrap();
-        topology
-            .exchange_gossips(with.into(), gossips.into())
-            .into()
+        let gossips = topology.exchange_gossips(with.into(), gossips.into());
+        self.advertised_gossips(gossips, topology.profile())
     }
 
     pub fn node(&self) -> NodeProfile {
         self.lock.read().unwrap().profile().clone()
     }
 
+    /// the profile of the node to gossip to the peers it connects to, none
+    /// for a core node or a client only
+    pub fn advertised_node(&self) -> Option<NodeProfile> {
+        if self.unadvertised {
+            None
+        } else {
+            Some(self.node())
+        }
+    }
+
     pub fn force_reset_layers(&self) {
         self.lock.write().unwrap().force_reset_layers()
     }
@@ -112,3 +250,73 @@ impl P2pTopology {
         })
     }
 }
+
+fn add_poldercast_layers(topology: &mut Topology, config: &LayersConfig) {
+    if let Some(rings) = config.rings.as_ref() {
+        topology.add_layer(Rings::new(rings.view_length));
+    } else {
+        topology.add_layer(Rings::default());
+    }
+    if let Some(vicinity) = config.vicinity.as_ref() {
+        topology.add_layer(Vicinity::new(vicinity.view_length, vicinity.gossip_length));
+    } else {
+        topology.add_layer(Vicinity::default());
+    }
+    if let Some(cyclon) = config.cyclon.as_ref() {
+        topology.add_layer(Cyclon::new(cyclon.view_length, cyclon.gossip_length));
+    } else {
+        topology.add_layer(Cyclon::default());
+    }
+}
+
+fn add_custom_layers(topology: &mut Topology, config: &Configuration) {
+    if let Some(size) = config.max_unreachable_nodes_to_connect_per_event {
+        topology.add_layer(custom_layers::RandomDirectConnections::with_max_view_length(size))
+    } else {
+        topology.add_layer(custom_layers::RandomDirectConnections::default());
+    }
+}
+
+/// the profile of the node with another ID, the same address and topics
+fn profile_with_id(profile: &NodeProfile, id: Id) -> NodeProfile {
+    let mut builder = poldercast::NodeProfileBuilder::new();
+    builder.id(id.into());
+    if let Some(address) = profile.address() {
+        builder.address(address.clone());
+    }
+    for subscription in profile.subscriptions().iter() {
+        builder.add_subscription(poldercast::Subscription {
+            topic: subscription.topic,
+            interest: subscription.interest,
+        });
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::p2p::topic;
+
+    #[test]
+    fn profile_with_a_new_id_keeps_the_address_and_topics() {
+        let mut builder = poldercast::NodeProfileBuilder::new();
+        builder.address("/ip4/192.0.2.1/tcp/3000".parse().unwrap());
+        builder.add_subscription(poldercast::Subscription {
+            topic: topic::BLOCKS,
+            interest: poldercast::InterestLevel::Normal,
+        });
+        let profile = builder.build();
+        let id: Id = poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into();
+
+        let renewed = profile_with_id(&profile, id);
+
+        assert_eq!(Id::from(*renewed.id()), id);
+        assert_ne!(renewed.id(), profile.id());
+        assert_eq!(
+            renewed.address().map(ToString::to_string),
+            profile.address().map(ToString::to_string)
+        );
+        assert_eq!(renewed.subscriptions().iter().count(), 1);
+    }
+}
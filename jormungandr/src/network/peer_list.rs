@@ -0,0 +1,213 @@
+//! the trusted peers fetched from a signed list at startup
+//!
+//! The list is served at an HTTPS URL, in YAML or JSON: its `peers` in the
+//! format of the `p2p.trusted_peers` setting and the time it `expires` at.
+//! It is signed with an Ed25519 key of the operator of the network: the
+//! bech32 signature of the list, as written by `jcli key sign`, is served at
+//! the path of the list with the `.sig` suffix. A list with a signature not
+//! verified by the configured public key, or expired, is rejected so an old
+//! signed list cannot be replayed. The node then starts with its static
+//! trusted peers only, or fails to start if it is a `core` node without them.
+
+use crate::{
+    network::p2p::Role,
+    settings::start::{
+        config,
+        network::{Configuration, TrustedPeer, TrustedPeersUrl},
+    },
+};
+use chain_crypto::{bech32::Bech32 as _, Ed25519, Signature, Verification};
+use hyper::{client::HttpConnector, Body, Client, Uri};
+use hyper_tls::HttpsConnector;
+use jormungandr_lib::time::SystemTime;
+use serde::Deserialize;
+use slog::Logger;
+use thiserror::Error;
+use tokio::prelude::*;
+use tokio::runtime::Runtime;
+use tokio::timer::Timeout;
+
+use std::io;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// the largest peer list accepted
+const MAX_LIST_SIZE: usize = 1024 * 1024;
+/// the largest signature accepted, a bech32 Ed25519 signature is about 120
+/// characters
+const MAX_SIGNATURE_SIZE: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("runtime initialization failed")]
+    RuntimeInit { source: io::Error },
+    #[error("cannot initialize the TLS connector")]
+    Tls {
+        #[from]
+        source: hyper_tls::Error,
+    },
+    #[error("invalid URL of the signature {0}")]
+    SignatureUrl(String),
+    #[error("fetching {uri} failed: {reason}")]
+    Fetch { uri: Uri, reason: String },
+    #[error("the signature is not a bech32 Ed25519 signature")]
+    SignatureFormat,
+    #[error("the signature of the peer list is not verified by the public key")]
+    SignatureVerification,
+    #[error("the peer list is malformed")]
+    Format {
+        #[from]
+        source: serde_yaml::Error,
+    },
+    #[error("the peer list expired at {0}")]
+    Expired(SystemTime),
+    #[error("the peer list is empty and the `core` node has no static trusted peers")]
+    NoPeers,
+}
+
+/// the signed document
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignedList {
+    expires: SystemTime,
+    peers: Vec<config::TrustedPeer>,
+}
+
+/// add the peers of the list at `config.trusted_peers_url`, if set, to the
+/// trusted peers. A `core` node without static trusted peers has no relay to
+/// connect to if the list is not fetched, this is an error.
+pub fn extend_trusted_peers(config: &mut Configuration, logger: &Logger) -> Result<(), Error> {
+    let source = match config.trusted_peers_url.as_ref() {
+        Some(source) => source,
+        None => return Ok(()),
+    };
+    let needs_peers = config.role == Role::Core && config.trusted_peers.is_empty();
+    let logger = logger.new(o!("url" => source.uri.to_string()));
+    match fetch(source) {
+        Ok(ref peers) if peers.is_empty() && needs_peers => Err(Error::NoPeers),
+        Ok(peers) => {
+            info!(logger, "trusted peers fetched"; "peers" => peers.len());
+            config.trusted_peers.extend(peers);
+            Ok(())
+        }
+        Err(e) if needs_peers => Err(e),
+        Err(e) => {
+            warn!(logger, "cannot fetch the trusted peers"; "reason" => %e);
+            Ok(())
+        }
+    }
+}
+
+fn fetch(source: &TrustedPeersUrl) -> Result<Vec<TrustedPeer>, Error> {
+    let signature_uri = signature_uri(&source.uri)?;
+
+    let mut runtime = Runtime::new().map_err(|e| Error::RuntimeInit { source: e })?;
+    let client = Client::builder().build(HttpsConnector::new(1)?);
+    let list = runtime.block_on(get(&client, source.uri.clone(), MAX_LIST_SIZE))?;
+    let signature = runtime.block_on(get(&client, signature_uri, MAX_SIGNATURE_SIZE))?;
+
+    let signature = std::str::from_utf8(&signature).map_err(|_| Error::SignatureFormat)?;
+    let signature = Signature::<[u8], Ed25519>::try_from_bech32_str(signature.trim())
+        .map_err(|_| Error::SignatureFormat)?;
+    match signature.verify_slice(&source.public_key, &list) {
+        Verification::Success => {}
+        Verification::Failed => return Err(Error::SignatureVerification),
+    }
+
+    let list: SignedList = serde_yaml::from_slice(&list)?;
+    if list.expires <= SystemTime::now() {
+        return Err(Error::Expired(list.expires));
+    }
+    Ok(list.peers.into_iter().map(Into::into).collect())
+}
+
+/// the URL of the signature: the path of the list with the `.sig` suffix,
+/// keeping the query
+fn signature_uri(uri: &Uri) -> Result<Uri, Error> {
+    let mut parts = uri.clone().into_parts();
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}{}?{}", uri.path(), SIGNATURE_SUFFIX, query),
+        None => format!("{}{}", uri.path(), SIGNATURE_SUFFIX),
+    };
+    parts.path_and_query = Some(
+        path_and_query
+            .parse()
+            .map_err(|_| Error::SignatureUrl(path_and_query.clone()))?,
+    );
+    Uri::from_parts(parts).map_err(|_| Error::SignatureUrl(path_and_query))
+}
+
+fn get(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    uri: Uri,
+    max_size: usize,
+) -> impl Future<Item = Vec<u8>, Error = Error> {
+    let err_uri = uri.clone();
+    let fetch = client
+        .get(uri)
+        .map_err(|e| e.to_string())
+        .and_then(|response| {
+            if response.status().is_success() {
+                Ok(response)
+            } else {
+                Err(format!("the server responded {}", response.status()))
+            }
+        })
+        .and_then(move |response| {
+            response.into_body().map_err(|e| e.to_string()).fold(
+                Vec::new(),
+                move |mut body, chunk| {
+                    if body.len() + chunk.len() > max_size {
+                        return Err(format!("the response is larger than {} bytes", max_size));
+                    }
+                    body.extend_from_slice(&chunk);
+                    Ok(body)
+                },
+            )
+        });
+    Timeout::new(fetch, FETCH_TIMEOUT).map_err(move |e| Error::Fetch {
+        uri: err_uri,
+        reason: match e.into_inner() {
+            Some(reason) => reason,
+            None => format!("no response within {:?}", FETCH_TIMEOUT),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature_of(uri: &str) -> String {
+        signature_uri(&uri.parse().unwrap()).unwrap().to_string()
+    }
+
+    #[test]
+    fn signature_uri_appends_to_the_path() {
+        assert_eq!(
+            signature_of("https://example.com/trusted-peers.yaml"),
+            "https://example.com/trusted-peers.yaml.sig"
+        );
+    }
+
+    #[test]
+    fn signature_uri_keeps_the_query() {
+        assert_eq!(
+            signature_of("https://example.com:8443/peers?network=testnet&v=2"),
+            "https://example.com:8443/peers.sig?network=testnet&v=2"
+        );
+    }
+
+    #[test]
+    fn signed_list_needs_the_expiry() {
+        let peers = "peers:\n  - address: /ip4/127.0.0.1/tcp/3000\n    id: 010203040506070809101112131415161718192021222324\n";
+        assert!(serde_yaml::from_str::<SignedList>(peers).is_err());
+        let list = format!("expires: \"2019-10-14T12:00:00+00:00\"\n{}", peers);
+        let list: SignedList = serde_yaml::from_str(&list).unwrap();
+        assert!(list.expires < SystemTime::now());
+        assert_eq!(list.peers.len(), 1);
+    }
+}
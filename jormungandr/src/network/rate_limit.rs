@@ -0,0 +1,174 @@
+//! the rate limits of the items a peer sends on its subscriptions
+//!
+//! Each inbound subscription of a peer draws from a token bucket refilled at
+//! the configured rate, holding at most the configured burst. A peer with an
+//! empty bucket is throttled: the next item of its subscription is not
+//! accepted until a token is available, so the stream of the peer is held
+//! back by the flow control of its connection.
+
+use crate::settings::start::config::RateLimit;
+use futures::{Async, Future};
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+/// the tokens are counted in millionths, so the slow rates refill smoothly
+const TOKEN: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Admitted,
+    /// the current task is notified once a token is available. `started` is
+    /// set for the first item throttled after some were admitted.
+    Throttled {
+        started: bool,
+    },
+}
+
+pub struct TokenBucket {
+    /// the tokens added per second
+    rate: u64,
+    capacity: u64,
+    tokens: u64,
+    last_refill: Instant,
+    /// a token is held for the item the sink could not accept yet
+    reserved: bool,
+    throttled: bool,
+    delay: Option<Delay>,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        let capacity = u64::from(limit.burst.get()) * TOKEN;
+        TokenBucket {
+            rate: u64::from(limit.rate.get()),
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+            reserved: false,
+            throttled: false,
+            delay: None,
+        }
+    }
+
+    /// take a token for the next item. The token is held until `consume`,
+    /// so an item offered again after the sink was not ready does not take
+    /// another one.
+    pub fn poll_admit(&mut self) -> Admission {
+        if self.reserved {
+            return Admission::Admitted;
+        }
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_micros() as u64;
+        self.tokens = self
+            .tokens
+            .saturating_add(elapsed.saturating_mul(self.rate))
+            .min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= TOKEN {
+            self.tokens -= TOKEN;
+            self.reserved = true;
+            self.throttled = false;
+            self.delay = None;
+            return Admission::Admitted;
+        }
+
+        let wait = Duration::from_micros((TOKEN - self.tokens + self.rate - 1) / self.rate);
+        let mut delay = Delay::new(now + wait);
+        // the delay registers the task to notify, a timer failure only
+        // loses the throttling of this item
+        if let Ok(Async::Ready(())) | Err(_) = delay.poll() {
+            self.tokens = TOKEN;
+            return self.poll_admit();
+        }
+        self.delay = Some(delay);
+        let started = !self.throttled;
+        self.throttled = true;
+        Admission::Throttled { started }
+    }
+
+    /// the item holding the token was accepted
+    pub fn consume(&mut self) {
+        self.reserved = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::num::NonZeroU32;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn bucket(rate: u32, burst: u32) -> TokenBucket {
+        TokenBucket::new(RateLimit {
+            rate: NonZeroU32::new(rate).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        })
+    }
+
+    /// run `f` in a task of a runtime with a timer, for the delays
+    fn in_task<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(future::lazy(|| Ok::<_, ()>(f()))).unwrap()
+    }
+
+    fn admit(bucket: &mut TokenBucket) -> Admission {
+        let admission = bucket.poll_admit();
+        if admission == Admission::Admitted {
+            bucket.consume();
+        }
+        admission
+    }
+
+    #[test]
+    fn burst_is_admitted_then_throttled() {
+        in_task(|| {
+            let mut bucket = bucket(1, 3);
+            for _ in 0..3 {
+                assert_eq!(admit(&mut bucket), Admission::Admitted);
+            }
+            assert_eq!(admit(&mut bucket), Admission::Throttled { started: true });
+            assert_eq!(admit(&mut bucket), Admission::Throttled { started: false });
+        })
+    }
+
+    #[test]
+    fn item_offered_again_does_not_take_another_token() {
+        in_task(|| {
+            let mut bucket = bucket(1, 1);
+            assert_eq!(bucket.poll_admit(), Admission::Admitted);
+            // the sink was not ready, the item is offered again
+            assert_eq!(bucket.poll_admit(), Admission::Admitted);
+            bucket.consume();
+            assert_eq!(bucket.poll_admit(), Admission::Throttled { started: true });
+        })
+    }
+
+    #[test]
+    fn bucket_is_refilled_at_the_rate() {
+        in_task(|| {
+            let mut bucket = bucket(100, 1);
+            assert_eq!(admit(&mut bucket), Admission::Admitted);
+            assert_eq!(admit(&mut bucket), Admission::Throttled { started: true });
+            std::thread::sleep(Duration::from_millis(30));
+            assert_eq!(admit(&mut bucket), Admission::Admitted);
+            // the throttling after some items were admitted is a new one
+            assert_eq!(admit(&mut bucket), Admission::Throttled { started: true });
+        })
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        in_task(|| {
+            let mut bucket = bucket(50, 2);
+            std::thread::sleep(Duration::from_millis(100));
+            assert_eq!(admit(&mut bucket), Admission::Admitted);
+            assert_eq!(admit(&mut bucket), Admission::Admitted);
+            assert_eq!(admit(&mut bucket), Admission::Throttled { started: true });
+        })
+    }
+}
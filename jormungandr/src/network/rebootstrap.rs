@@ -0,0 +1,269 @@
+//! the bootstrap run again while the node is live
+//!
+//! On the request of the operator, e.g. when the node follows a dead fork or
+//! lost all its peers, the blocks are pulled again from the trusted peers as
+//! on the start of the node, one at a time. Unlike the bootstrap on start,
+//! the pulled blocks go through the block task, which verifies and applies
+//! them as the blocks solicited from the peers: the tip is moved to the
+//! pulled chain if it is selected over the current one, with the
+//! subscriptions, the notifications and the index kept up to date. The
+//! request is replied once the block task verified the pulled blocks.
+
+use super::{buffer_sizes, grpc, BlockConfig, Channels, GlobalStateR};
+use crate::blockcfg::Block;
+use crate::blockchain::Tip;
+use crate::intercom::{self, BlockMsg, ReplyHandle};
+use crate::log::{self, TraceId};
+use crate::utils::async_msg::MessageBox;
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use network_core::client::{BlockService, Client as _};
+use network_core::error::Error as NetworkError;
+use network_grpc::client::Connection;
+use slog::Logger;
+use thiserror::Error;
+use tokio::runtime::TaskExecutor;
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to connect to bootstrap peer")]
+    Connect { source: grpc::ConnectError },
+    #[error("connection broken")]
+    ClientNotReady { source: NetworkError },
+    #[error("bootstrap pull request failed")]
+    PullRequestFailed { source: NetworkError },
+    #[error("bootstrap pull stream failed")]
+    PullStreamFailed { source: NetworkError },
+    #[error("the block task has stopped")]
+    BlockTaskStopped,
+    #[error("the block task rejected the pulled blocks")]
+    BlocksRejected { source: NetworkError },
+    #[error("none of the trusted peers could be bootstrapped from")]
+    NoPeer,
+}
+
+pub struct Rebootstrap {
+    tip: Tip,
+    running: AtomicBool,
+}
+
+impl Rebootstrap {
+    pub fn new(tip: Tip) -> Self {
+        Rebootstrap {
+            tip,
+            running: AtomicBool::new(false),
+        }
+    }
+}
+
+/// start the bootstrap, replied once the pulled blocks are verified by the
+/// block task or if another bootstrap is still running. The outcome is
+/// logged.
+pub fn start(state: &GlobalStateR, channels: &Channels, reply: ReplyHandle<()>) {
+    if state.rebootstrap.running.swap(true, Ordering::SeqCst) {
+        reply.reply_error(intercom::Error::failed_precondition(
+            "the node is already bootstrapping",
+        ));
+        return;
+    }
+    let logger = state.logger().new(o!(log::KEY_TASK => "bootstrap"));
+    info!(logger, "bootstrapping again from the trusted peers");
+    let end_state = state.clone();
+    let future = bootstrap_from_peers(
+        super::trusted_peers_shuffled(&state.config),
+        state.rebootstrap.tip.clone(),
+        channels.block_box.clone(),
+        state.executor.clone(),
+        logger.clone(),
+    )
+    .then(move |res| {
+        end_state.rebootstrap.running.store(false, Ordering::SeqCst);
+        match res {
+            Ok(()) => {
+                info!(logger, "bootstrap completed");
+                reply.reply_ok(());
+            }
+            Err(e) => {
+                warn!(logger, "bootstrap failed"; "reason" => %e);
+                reply.reply_error(intercom::Error::failed(e));
+            }
+        }
+        Ok(())
+    });
+    state.spawn(future);
+}
+
+/// bootstrap from the first of the peers which can be bootstrapped from
+fn bootstrap_from_peers(
+    peers: Vec<SocketAddr>,
+    tip: Tip,
+    block_box: MessageBox<BlockMsg>,
+    executor: TaskExecutor,
+    logger: Logger,
+) -> impl Future<Item = (), Error = Error> {
+    future::loop_fn(peers.into_iter(), move |mut peers| {
+        let address = match peers.next() {
+            Some(address) => address,
+            None => return Either::A(future::err(Error::NoPeer)),
+        };
+        let logger = logger.new(o!("peer_addr" => address.to_string()));
+        let future = bootstrap_from_peer(
+            address,
+            tip.clone(),
+            block_box.clone(),
+            executor.clone(),
+            logger.clone(),
+        )
+        .then(move |res| match res {
+            Ok(()) => Ok(Loop::Break(())),
+            Err(Error::BlockTaskStopped) => Err(Error::BlockTaskStopped),
+            Err(e) => {
+                warn!(logger, "bootstrap from the peer failed"; "reason" => %e);
+                Ok(Loop::Continue(peers))
+            }
+        });
+        Either::B(future)
+    })
+}
+
+fn bootstrap_from_peer(
+    address: SocketAddr,
+    tip: Tip,
+    block_box: MessageBox<BlockMsg>,
+    executor: TaskExecutor,
+    logger: Logger,
+) -> impl Future<Item = (), Error = Error> {
+    grpc::connect(address, None, None, executor)
+        .map_err(|e| Error::Connect { source: e })
+        .and_then(|client: Connection<BlockConfig>| {
+            client
+                .ready()
+                .map_err(|e| Error::ClientNotReady { source: e })
+        })
+        .join(tip.get_ref())
+        .and_then(move |(mut client, tip)| {
+            let tip_hash = tip.hash();
+            debug!(logger, "pulling blocks starting from {}", tip_hash);
+            client
+                .pull_blocks_to_tip(&[tip_hash])
+                .map_err(|e| Error::PullRequestFailed { source: e })
+                .and_then(move |stream| through_block_task(stream, block_box, logger))
+        })
+}
+
+/// send the pulled blocks to the block task, resolved once the block task
+/// verified and applied them
+fn through_block_task<S>(
+    stream: S,
+    block_box: MessageBox<BlockMsg>,
+    logger: Logger,
+) -> impl Future<Item = (), Error = Error>
+where
+    S: Stream<Item = Block, Error = NetworkError>,
+{
+    let trace = TraceId::new();
+    let logger = logger.new(o!(log::KEY_TRACE => trace.to_string()));
+    let (handle, mut sink) =
+        intercom::stream_request::<Block, (), NetworkError>(buffer_sizes::BLOCKS, logger);
+    let reply = sink.take_reply_future();
+    block_box
+        .send(BlockMsg::NetworkBlocks(trace, handle))
+        .map_err(|_| Error::BlockTaskStopped)
+        .and_then(move |_| {
+            // the block task stops reading the blocks once one is rejected,
+            // its reply has the reason
+            stream
+                .map_err(|e| Error::PullStreamFailed { source: e })
+                .forward(sink.sink_map_err(|e| Error::BlocksRejected { source: e }))
+                .then(move |forwarded| {
+                    reply.then(move |replied| match replied {
+                        Ok(()) => forwarded.map(|_| ()),
+                        Err(e) => Err(Error::BlocksRejected { source: e }),
+                    })
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::async_msg::{self, MessageQueue};
+    use futures::{stream, sync::oneshot};
+    use tokio::runtime::current_thread::Runtime;
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    /// start sending an empty stream of blocks to the block task, returning
+    /// the outcome and the request received by the block task
+    fn send_to_block_task(
+        runtime: &mut Runtime,
+    ) -> (
+        oneshot::Receiver<Result<(), Error>>,
+        intercom::RequestStreamHandle<Block, ()>,
+    ) {
+        let (block_box, block_queue): (_, MessageQueue<BlockMsg>) = async_msg::channel(1);
+        let (outcome, outcome_future) = oneshot::channel();
+        runtime.spawn(
+            through_block_task(stream::empty(), block_box, logger()).then(move |res| {
+                let _ = outcome.send(res);
+                Ok(())
+            }),
+        );
+        let msg = match runtime.block_on(block_queue.into_future()) {
+            Ok((Some(msg), _)) => msg,
+            _ => panic!("the blocks were not sent to the block task"),
+        };
+        match msg {
+            BlockMsg::NetworkBlocks(_, handle) => (outcome_future, handle),
+            other => panic!("unexpected message {}", other.variant_name()),
+        }
+    }
+
+    #[test]
+    fn bootstrap_ends_once_the_block_task_verified_the_blocks() {
+        let mut runtime = Runtime::new().unwrap();
+        let (mut outcome, handle) = send_to_block_task(&mut runtime);
+        let (blocks, reply) = handle.into_stream_and_reply();
+        let blocks = runtime.block_on(blocks.collect()).unwrap();
+
+        assert!(blocks.is_empty());
+        assert!(outcome.try_recv().unwrap().is_none());
+
+        reply.reply_ok(());
+
+        assert!(runtime.block_on(outcome).unwrap().is_ok());
+    }
+
+    #[test]
+    fn bootstrap_fails_when_the_block_task_rejects_the_blocks() {
+        let mut runtime = Runtime::new().unwrap();
+        let (outcome, handle) = send_to_block_task(&mut runtime);
+        let (_blocks, reply) = handle.into_stream_and_reply();
+
+        reply.reply_error(intercom::Error::failed("invalid block"));
+
+        match runtime.block_on(outcome).unwrap() {
+            Err(Error::BlocksRejected { .. }) => {}
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bootstrap_fails_when_the_block_task_stopped() {
+        let mut runtime = Runtime::new().unwrap();
+        let (block_box, block_queue): (_, MessageQueue<BlockMsg>) = async_msg::channel(1);
+        drop(block_queue);
+
+        let res = runtime.block_on(through_block_task(stream::empty(), block_box, logger()));
+
+        match res {
+            Err(Error::BlockTaskStopped) => {}
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+}
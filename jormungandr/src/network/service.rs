@@ -1,12 +1,19 @@
 use super::{
+    block_cache::{self, CachingStream},
+    block_fetch::FetchedSink,
     buffer_sizes,
+    message_limits::{self, LimitedSink, PeerLimits},
     p2p::comm::{BlockEventSubscription, OutboundSubscription},
     p2p::{Gossip as NodeData, Id},
     subscription::{BlockAnnouncementProcessor, FragmentProcessor, GossipProcessor, Subscription},
     Channels, GlobalStateR,
 };
 use crate::blockcfg::{Block, BlockDate, Fragment, FragmentId, Header, HeaderHash};
-use crate::intercom::{self, BlockMsg, ClientMsg, ReplyFuture, ReplyStream, RequestSink};
+use crate::intercom::{
+    self, BlockMsg, ClientMsg, ReplyFuture, ReplyHandle, ReplyStream, ReplyStreamHandle,
+    RequestSink,
+};
+use crate::log::{self, TraceId};
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use network_core::error as core_error;
@@ -18,6 +25,8 @@ use slog::Logger;
 pub struct NodeService {
     channels: Channels,
     global_state: GlobalStateR,
+    /// the peer of the connection served
+    limits: Option<PeerLimits>,
     logger: Logger,
 }
 
@@ -29,12 +38,67 @@ impl NodeService {
                 .logger()
                 .new(o!(crate::log::KEY_SUB_TASK => "server")),
             global_state,
+            limits: None,
+        }
+    }
+
+    /// the service of an incoming connection, whose peer is identified by
+    /// its subscriptions
+    pub fn for_connection(&self, limits: PeerLimits) -> Self {
+        NodeService {
+            limits: Some(limits),
+            ..self.clone()
         }
     }
 
     pub fn logger(&self) -> &Logger {
         &self.logger
     }
+
+    /// the reply of the client task to a request of a peer, failed if the
+    /// task does not answer within the request timeout
+    fn client_reply<T>(&self) -> (ReplyHandle<T>, ReplyFuture<T, core_error::Error>) {
+        let (handle, future) = intercom::unary_reply(self.logger().clone());
+        (
+            handle,
+            future.with_timeout(self.global_state.config.request_timeout),
+        )
+    }
+
+    /// the stream of the client task answering a request of a peer, failed
+    /// if the task does not send an item within the request timeout
+    fn client_stream<T>(&self) -> (ReplyStreamHandle<T>, ReplyStream<T, core_error::Error>) {
+        let (handle, stream) = intercom::stream_reply(self.logger().clone());
+        (
+            handle,
+            stream.with_timeout(self.global_state.config.request_timeout),
+        )
+    }
+
+    /// queue a request of a peer for the client task. When the queue is
+    /// full the request is dropped, so the peer gets an error
+    fn query_client(&self, msg: ClientMsg) {
+        if let Err(e) = self.channels.client_box.try_send_to(msg) {
+            warn!(
+                self.logger,
+                "cannot process the request of the peer";
+                "reason" => %e,
+            );
+        }
+    }
+
+    /// queue the blocks or headers sent by a peer for the block task. When
+    /// the queue is full the request is dropped, so the peer gets an error
+    /// instead of the node accumulating the requests in flight
+    fn process_blocks(&mut self, msg: BlockMsg, logger: &Logger) {
+        if let Err(e) = self.channels.block_box.try_send(msg) {
+            warn!(
+                logger,
+                "failed to enqueue request for processing";
+                "reason" => %e,
+            );
+        }
+    }
 }
 
 impl NodeService
@@ -44,6 +108,19 @@ where
     fn subscription_logger(&self, subscriber: <Self as P2pService>::NodeId) -> Logger {
         self.logger.new(o!("node_id" => subscriber.to_string()))
     }
+
+    /// record the subscriber as the peer of the connection, its requests
+    /// over the limits being reported against it
+    fn peer_subscribed(&self, subscriber: Id) {
+        if let Some(limits) = &self.limits {
+            limits.set_node_id(subscriber);
+        }
+    }
+
+    /// the node ID of the peer of the connection, once it subscribed
+    fn peer_node_id(&self) -> Option<Id> {
+        self.limits.as_ref().and_then(PeerLimits::node_id)
+    }
 }
 
 impl Node for NodeService {
@@ -81,14 +158,15 @@ impl BlockService for NodeService {
     type PullBlocksStream = ReplyStream<Block, core_error::Error>;
     type PullBlocksFuture = FutureResult<Self::PullBlocksStream, core_error::Error>;
     type PullBlocksToTipFuture = FutureResult<Self::PullBlocksStream, core_error::Error>;
-    type GetBlocksStream = ReplyStream<Block, core_error::Error>;
+    type GetBlocksStream = CachingStream;
     type GetBlocksFuture = FutureResult<Self::GetBlocksStream, core_error::Error>;
     type PullHeadersStream = ReplyStream<Header, core_error::Error>;
     type PullHeadersFuture = FutureResult<Self::PullHeadersStream, core_error::Error>;
     type GetHeadersStream = ReplyStream<Header, core_error::Error>;
     type GetHeadersFuture = FutureResult<Self::GetHeadersStream, core_error::Error>;
-    type PushHeadersSink = RequestSink<Header, (), core_error::Error>;
-    type UploadBlocksSink = RequestSink<Block, (), core_error::Error>;
+    type PushHeadersSink = LimitedSink<RequestSink<Header, (), core_error::Error>, Header>;
+    type UploadBlocksSink =
+        FetchedSink<LimitedSink<RequestSink<Block, (), core_error::Error>, Block>>;
     type BlockSubscription = Subscription<BlockAnnouncementProcessor, BlockEventSubscription>;
     type BlockSubscriptionFuture = FutureResult<Self::BlockSubscription, core_error::Error>;
 
@@ -97,34 +175,35 @@ impl BlockService for NodeService {
     }
 
     fn tip(&mut self) -> Self::TipFuture {
-        let (handle, future) = intercom::unary_reply(self.logger().clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::GetBlockTip(handle));
+        let (handle, future) = self.client_reply();
+        self.query_client(ClientMsg::GetBlockTip(handle));
         future
     }
 
     fn pull_blocks_to_tip(&mut self, from: &[Self::BlockId]) -> Self::PullBlocksFuture {
-        let (handle, stream) = intercom::stream_reply(self.logger().clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::PullBlocksToTip(from.into(), handle));
+        let (handle, stream) = self.client_stream();
+        self.query_client(ClientMsg::PullBlocksToTip(from.into(), handle));
         future::ok(stream)
     }
 
     fn get_blocks(&mut self, ids: &[Self::BlockId]) -> Self::GetBlocksFuture {
-        let (handle, stream) = intercom::stream_reply(self.logger().clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::GetBlocks(ids.into(), handle));
-        future::ok(stream)
+        self.global_state.stats_counter.blocks_fetched(ids);
+        let cache = &self.global_state.block_cache;
+        if let Some(blocks) = cache.get_all(ids) {
+            let stream = block_cache::cached_reply(blocks, self.logger().clone());
+            return future::ok(CachingStream::cached(stream));
+        }
+        let (handle, stream) = self.client_stream();
+        self.query_client(ClientMsg::GetBlocks(ids.into(), handle));
+        future::ok(CachingStream::new(stream, cache.clone()))
     }
 
     fn get_headers(&mut self, ids: &[Self::BlockId]) -> Self::GetHeadersFuture {
-        let (handle, stream) = intercom::stream_reply(self.logger().clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::GetHeaders(ids.into(), handle));
+        if let Some(headers) = self.global_state.block_cache.get_all_headers(ids) {
+            return future::ok(block_cache::cached_reply(headers, self.logger().clone()));
+        }
+        let (handle, stream) = self.client_stream();
+        self.query_client(ClientMsg::GetHeaders(ids.into(), handle));
         future::ok(stream)
     }
 
@@ -141,10 +220,8 @@ impl BlockService for NodeService {
         from: &[Self::BlockId],
         to: &Self::BlockId,
     ) -> Self::PullHeadersFuture {
-        let (handle, stream) = intercom::stream_reply(self.logger().clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::GetHeadersRange(from.into(), *to, handle));
+        let (handle, stream) = self.client_stream();
+        self.query_client(ClientMsg::GetHeadersRange(from.into(), *to, handle));
         future::ok(stream)
     }
 
@@ -155,51 +232,39 @@ impl BlockService for NodeService {
     fn push_headers(&mut self) -> Self::PushHeadersSink {
         let logger = self.logger.new(o!("request" => "PushHeaders"));
         let (handle, sink) = intercom::stream_request(buffer_sizes::CHAIN_PULL, logger.clone());
-        let block_box = self.channels.block_box.clone();
-        // TODO: make sure that a limit on the number of requests in flight
-        // per service connection prevents unlimited spawning of these tasks.
-        // https://github.com/input-output-hk/jormungandr/issues/1034
-        self.global_state.spawn(
-            block_box
-                .send(BlockMsg::ChainHeaders(handle))
-                .map_err(move |e| {
-                    error!(
-                        logger,
-                        "failed to enqueue request for processing";
-                        "reason" => %e,
-                    );
-                })
-                .map(|_mbox| ()),
-        );
-        sink
+        self.process_blocks(BlockMsg::ChainHeaders(handle), &logger);
+        LimitedSink::new(
+            sink,
+            message_limits::check_header,
+            self.peer_node_id(),
+            self.global_state.clone(),
+            logger,
+        )
     }
 
     fn upload_blocks(&mut self) -> Self::UploadBlocksSink {
-        let logger = self.logger.new(o!("request" => "UploadBlocks"));
+        let trace = TraceId::new();
+        let logger = self.logger.new(o!(
+            "request" => "UploadBlocks",
+            log::KEY_TRACE => trace.to_string(),
+        ));
         let (handle, sink) = intercom::stream_request(buffer_sizes::BLOCKS, logger.clone());
-        let block_box = self.channels.block_box.clone();
-        // TODO: make sure that a limit on the number of requests in flight
-        // per service connection prevents unlimited spawning of these tasks.
-        // https://github.com/input-output-hk/jormungandr/issues/1034
-        self.global_state.spawn(
-            block_box
-                .send(BlockMsg::NetworkBlocks(handle))
-                .map_err(move |e| {
-                    error!(
-                        logger,
-                        "failed to enqueue request for processing";
-                        "reason" => %e,
-                    );
-                })
-                .map(|_mbox| ()),
+        self.process_blocks(BlockMsg::NetworkBlocks(trace, handle), &logger);
+        let sink = LimitedSink::new(
+            sink,
+            message_limits::check_block,
+            self.peer_node_id(),
+            self.global_state.clone(),
+            logger,
         );
-        sink
+        FetchedSink::new(sink, self.global_state.clone())
     }
 
     fn block_subscription(&mut self, subscriber: Self::NodeId) -> Self::BlockSubscriptionFuture {
         let logger = self
             .subscription_logger(subscriber)
             .new(o!("stream" => "block_events"));
+        self.peer_subscribed(subscriber);
 
         let sink = BlockAnnouncementProcessor::new(
             self.channels.block_box.clone(),
@@ -234,6 +299,7 @@ impl FragmentService for NodeService {
         let logger = self
             .subscription_logger(subscriber)
             .new(o!("stream" => "fragments"));
+        self.peer_subscribed(subscriber);
 
         let sink = FragmentProcessor::new(
             self.channels.transaction_box.clone(),
@@ -258,6 +324,7 @@ impl GossipService for NodeService {
         let logger = self
             .subscription_logger(subscriber)
             .new(o!("stream" => "gossip"));
+        self.peer_subscribed(subscriber);
 
         let sink = GossipProcessor::new(
             subscriber,
@@ -1,24 +1,100 @@
 use super::{
-    buffer_sizes,
-    p2p::{Gossip as NodeData, Id},
-    GlobalStateR,
+    buffer_sizes, message_limits,
+    p2p::{comm::WindowedCount, AddressRejection, Gossip as NodeData, Id},
+    rate_limit::{Admission, TokenBucket},
+    GlobalState, GlobalStateR,
 };
+use crate::settings::start::config::RateLimit;
 use crate::{
     blockcfg::{Fragment, Header},
+    fragment::admission,
     intercom::{BlockMsg, TransactionMsg},
-    settings::start::network::Configuration,
+    log::TraceId,
     utils::async_msg::{self, MessageBox},
 };
 use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::error as core_error;
 use network_core::gossip::{Gossip, Node as _};
 use network_core::server::request_stream::{MapResponse, ProcessingError};
+use poldercast::StrikeReason;
 
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use slog::Logger;
 
 use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// the number of gossips with malformed nodes a peer may send within
+/// `MALFORMED_GOSSIPS_WINDOW` before it is quarantined
+const MAX_MALFORMED_GOSSIPS: u32 = 3;
+/// the period over which the gossips with malformed nodes are counted
+const MALFORMED_GOSSIPS_WINDOW: Duration = Duration::from_secs(600);
+
+/// the rate limit of an inbound subscription of a peer
+struct Throttle {
+    bucket: TokenBucket,
+    subscription: &'static str,
+}
+
+impl Throttle {
+    fn new(limit: RateLimit, subscription: &'static str) -> Self {
+        Throttle {
+            bucket: TokenBucket::new(limit),
+            subscription,
+        }
+    }
+
+    /// ready when the next item of the peer is within the rate limit. The
+    /// peer is quarantined once it has been throttled too many times within
+    /// the window of the limits.
+    fn poll_admit(
+        &mut self,
+        node_id: Id,
+        global_state: &GlobalState,
+        logger: &Logger,
+    ) -> Poll<(), core_error::Error> {
+        match self.bucket.poll_admit() {
+            Admission::Admitted => return Ok(Async::Ready(())),
+            Admission::Throttled { started: false } => return Ok(Async::NotReady),
+            Admission::Throttled { started: true } => {}
+        }
+        global_state
+            .stats_counter
+            .add_subscription_throttle(self.subscription);
+        let limits = &global_state.config.subscription_limits;
+        let count = global_state
+            .peers
+            .record_throttled(node_id, limits.throttled_window.into())
+            .unwrap_or(0);
+        debug!(
+            logger,
+            "peer over the rate limit of the subscription, throttling it";
+            "throttled" => count,
+        );
+        if count < limits.max_throttled {
+            return Ok(Async::NotReady);
+        }
+        warn!(
+            logger,
+            "peer flooding its subscriptions, quarantining it";
+            "throttled" => count,
+        );
+        global_state.peers.remove_peer(node_id);
+        global_state
+            .topology
+            .report_node(node_id, StrikeReason::InvalidData);
+        Err(core_error::Error::new(
+            core_error::Code::InvalidArgument,
+            "too many items over the rate limits of the subscriptions",
+        ))
+    }
+
+    /// the item admitted was accepted
+    fn consume(&mut self) {
+        self.bucket.consume()
+    }
+}
 
 #[must_use = "`Subscription` needs to be plugged into a service trait implementation"]
 pub struct Subscription<In, Out> {
@@ -158,20 +234,13 @@ impl<In, Out> MapResponse for Subscription<In, Out> {
     }
 }
 
-fn filter_gossip_node(node: &NodeData, config: &Configuration) -> bool {
-    if config.allow_private_addresses {
-        node.has_valid_address()
-    } else {
-        node.is_global()
-    }
-}
-
 #[must_use = "sinks do nothing unless polled"]
 pub struct BlockAnnouncementProcessor {
     mbox: MessageBox<BlockMsg>,
     node_id: Id,
     global_state: GlobalStateR,
     logger: Logger,
+    throttle: Throttle,
 }
 
 impl BlockAnnouncementProcessor {
@@ -181,7 +250,9 @@ impl BlockAnnouncementProcessor {
         global_state: GlobalStateR,
         logger: Logger,
     ) -> Self {
+        let limits = &global_state.config.subscription_limits;
         BlockAnnouncementProcessor {
+            throttle: Throttle::new(limits.block_announcements, "block_announcements"),
             mbox,
             node_id,
             global_state,
@@ -213,6 +284,7 @@ pub struct FragmentProcessor {
     global_state: GlobalStateR,
     logger: Logger,
     buffered_fragments: Vec<Fragment>,
+    throttle: Throttle,
 }
 
 impl FragmentProcessor {
@@ -222,7 +294,9 @@ impl FragmentProcessor {
         global_state: GlobalStateR,
         logger: Logger,
     ) -> Self {
+        let limits = &global_state.config.subscription_limits;
         FragmentProcessor {
+            throttle: Throttle::new(limits.fragments, "fragments"),
             mbox,
             node_id,
             global_state,
@@ -236,24 +310,66 @@ pub struct GossipProcessor {
     node_id: Id,
     global_state: GlobalStateR,
     logger: Logger,
+    throttle: Throttle,
+    /// counted by the subscription, the peer may not be in the peer map
+    malformed_gossips: WindowedCount,
 }
 
 impl GossipProcessor {
     pub fn new(node_id: Id, global_state: GlobalStateR, logger: Logger) -> Self {
+        let limits = &global_state.config.subscription_limits;
         GossipProcessor {
+            throttle: Throttle::new(limits.gossips, "gossips"),
+            malformed_gossips: WindowedCount::default(),
             node_id,
             global_state,
             logger,
         }
     }
 
-    pub fn process_item(&self, gossip: Gossip<NodeData>) {
-        let (nodes, filtered_out): (Vec<_>, Vec<_>) = gossip.into_nodes().partition(|node| {
-            filter_gossip_node(node, &self.global_state.config)
-                || (node.id() == self.node_id && node.address().is_none())
-        });
-        if filtered_out.len() > 0 {
-            debug!(self.logger, "nodes dropped from gossip: {:?}", filtered_out);
+    /// ready when the next gossip of the peer is within the rate limit
+    pub fn poll_admit(&mut self) -> Poll<(), core_error::Error> {
+        self.throttle
+            .poll_admit(self.node_id, &self.global_state, &self.logger)
+    }
+
+    /// accept the nodes of the gossip in the topology, failing if the gossip
+    /// is over the limits
+    pub fn process_item(&mut self, gossip: Gossip<NodeData>) -> Result<(), core_error::Error> {
+        self.throttle.consume();
+        let nodes: Vec<_> = gossip.into_nodes().collect();
+        if let Err(violation) = message_limits::check_gossip_nodes(nodes.len()) {
+            return Err(message_limits::reject_peer(
+                &self.global_state,
+                Some(self.node_id),
+                violation,
+                &self.logger,
+            ));
+        }
+        let mut malformed = 0;
+        let nodes: Vec<_> = nodes
+            .into_iter()
+            .filter(|node| match self.address_rejection(node) {
+                None => true,
+                Some(rejection) => {
+                    debug!(
+                        self.logger,
+                        "node dropped from gossip";
+                        "node_id" => %node.id(),
+                        "reason" => rejection.as_str(),
+                    );
+                    self.global_state
+                        .stats_counter
+                        .add_gossip_rejection(rejection.as_str());
+                    if rejection.is_malformed() {
+                        malformed += 1;
+                    }
+                    false
+                }
+            })
+            .collect();
+        if malformed > 0 {
+            self.report_malformed(malformed)?;
         }
         if !self.global_state.peers.refresh_peer_on_gossip(self.node_id) {
             debug!(
@@ -261,9 +377,51 @@ impl GossipProcessor {
                 "received gossip from node that is not in the peer map",
             );
         }
+        self.global_state.record_gossiped(&nodes);
         self.global_state
             .topology
             .accept_gossips(self.node_id, nodes.into());
+        Ok(())
+    }
+
+    fn address_rejection(&self, node: &NodeData) -> Option<AddressRejection> {
+        // the peer may gossip itself without an address
+        if node.id() == self.node_id && node.address().is_none() {
+            return None;
+        }
+        node.address_rejection(self.global_state.config.allow_private_addresses)
+    }
+
+    /// record the gossip with malformed nodes, quarantining the peer once
+    /// it has sent too many of them
+    fn report_malformed(&mut self, malformed: usize) -> Result<(), core_error::Error> {
+        self.global_state
+            .peers
+            .record_malformed_gossip(self.node_id);
+        let count = self
+            .malformed_gossips
+            .record(Instant::now(), MALFORMED_GOSSIPS_WINDOW);
+        warn!(
+            self.logger,
+            "gossip with malformed nodes";
+            "malformed_nodes" => malformed,
+            "malformed_gossips" => count,
+        );
+        if count < MAX_MALFORMED_GOSSIPS {
+            return Ok(());
+        }
+        warn!(
+            self.logger,
+            "too many malformed gossips, quarantining the peer"
+        );
+        self.global_state.peers.remove_peer(self.node_id);
+        self.global_state
+            .topology
+            .report_node(self.node_id, StrikeReason::InvalidData);
+        Err(core_error::Error::new(
+            core_error::Code::InvalidArgument,
+            "too many gossips with malformed nodes",
+        ))
     }
 }
 
@@ -272,6 +430,20 @@ impl Sink for BlockAnnouncementProcessor {
     type SinkError = core_error::Error;
 
     fn start_send(&mut self, header: Header) -> StartSend<Header, core_error::Error> {
+        if let Err(violation) = message_limits::check_header(&header) {
+            return Err(message_limits::reject_peer(
+                &self.global_state,
+                Some(self.node_id),
+                violation,
+                &self.logger,
+            ));
+        }
+        let admitted = self
+            .throttle
+            .poll_admit(self.node_id, &self.global_state, &self.logger)?;
+        if admitted.is_not_ready() {
+            return Ok(AsyncSink::NotReady(header));
+        }
         let polled_ready = self.mbox.poll_ready().map_err(|e| self.mbox_error(e))?;
         if polled_ready.is_not_ready() {
             return Ok(AsyncSink::NotReady(header));
@@ -284,6 +456,7 @@ impl Sink for BlockAnnouncementProcessor {
             .map_err(|e| self.mbox_error(e))?;
         match polled {
             AsyncSink::Ready => {
+                self.throttle.consume();
                 self.global_state.peers.refresh_peer_on_block(self.node_id);
                 Ok(AsyncSink::Ready)
             }
@@ -325,11 +498,43 @@ impl Sink for FragmentProcessor {
         if self.buffered_fragments.len() >= buffer_sizes::FRAGMENTS {
             return Ok(AsyncSink::NotReady(fragment));
         }
+        let admitted = self
+            .throttle
+            .poll_admit(self.node_id, &self.global_state, &self.logger)?;
+        if admitted.is_not_ready() {
+            return Ok(AsyncSink::NotReady(fragment));
+        }
+        self.throttle.consume();
         trace!(
             self.logger,
             "received";
             "item" => ?fragment,
         );
+        if let Err(violation) = message_limits::check_fragment(&fragment) {
+            return Err(message_limits::reject_peer(
+                &self.global_state,
+                Some(self.node_id),
+                violation,
+                &self.logger,
+            ));
+        }
+        if let Err(rejection) =
+            admission::check(&fragment, &self.global_state.config.fragment_limits)
+        {
+            debug!(
+                self.logger,
+                "fragment rejected";
+                "code" => rejection.code(),
+                "reason" => %rejection,
+            );
+            self.global_state
+                .stats_counter
+                .add_fragment_rejected(rejection.code());
+            return Ok(AsyncSink::Ready);
+        }
+        self.global_state
+            .peers
+            .record_fragment_sender(self.node_id, fragment.id());
         self.buffered_fragments.push(fragment);
         let async_send = self.try_send_fragments()?;
         Ok(async_send.map(|()| self.buffered_fragments.pop().unwrap()))
@@ -371,6 +576,7 @@ impl FragmentProcessor {
         let polled = self
             .mbox
             .start_send(TransactionMsg::SendTransaction(
+                TraceId::new(),
                 FragmentOrigin::Network,
                 fragments,
             ))
@@ -389,7 +595,7 @@ impl FragmentProcessor {
                     .refresh_peer_on_fragment(self.node_id);
                 Ok(AsyncSink::Ready)
             }
-            AsyncSink::NotReady(TransactionMsg::SendTransaction(_, fragments)) => {
+            AsyncSink::NotReady(TransactionMsg::SendTransaction(_, _, fragments)) => {
                 self.buffered_fragments = fragments;
                 Ok(AsyncSink::NotReady(()))
             }
@@ -411,7 +617,10 @@ impl Sink for GossipProcessor {
             "received";
             "item" => ?gossip,
         );
-        self.process_item(gossip);
+        if self.poll_admit()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(gossip));
+        }
+        self.process_item(gossip)?;
         Ok(AsyncSink::Ready)
     }
 
@@ -419,3 +628,33 @@ impl Sink for GossipProcessor {
         Ok(Async::Ready(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_sending_malformed_gossips_in_a_row_reaches_the_limit() {
+        let start = Instant::now();
+        let mut count = WindowedCount::default();
+        for i in 1..MAX_MALFORMED_GOSSIPS {
+            let at = start + Duration::from_secs(u64::from(i));
+            assert!(count.record(at, MALFORMED_GOSSIPS_WINDOW) < MAX_MALFORMED_GOSSIPS);
+        }
+        let at = start + Duration::from_secs(60);
+        assert_eq!(
+            count.record(at, MALFORMED_GOSSIPS_WINDOW),
+            MAX_MALFORMED_GOSSIPS
+        );
+    }
+
+    #[test]
+    fn malformed_gossips_far_apart_are_forgiven() {
+        let start = Instant::now();
+        let mut count = WindowedCount::default();
+        for i in 0..(MAX_MALFORMED_GOSSIPS * 3) {
+            let at = start + MALFORMED_GOSSIPS_WINDOW * i;
+            assert_eq!(count.record(at, MALFORMED_GOSSIPS_WINDOW), 1);
+        }
+    }
+}
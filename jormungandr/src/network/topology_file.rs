@@ -0,0 +1,170 @@
+//! the nodes of the topology kept between two runs of the node
+//!
+//! The file lists one node per line, in the `address@id` format of the
+//! `--trusted-peer` option. It is written to a temporary file first and
+//! then renamed, so an interrupted write leaves the previous file intact,
+//! and the temporary file is removed if the write fails.
+
+use super::p2p::Node;
+use crate::settings::start::{config, network::TrustedPeer};
+use crate::utils::fs::temp_path;
+use network_core::gossip::Node as _;
+use slog::Logger;
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Write as _},
+    net::SocketAddr,
+    path::Path,
+};
+
+/// the nodes saved in the file, an absent file has no nodes
+pub fn load(path: &Path, logger: &Logger) -> Vec<TrustedPeer> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!(
+                logger,
+                "cannot read the topology file";
+                "path" => %path.display(),
+                "reason" => %e,
+            );
+            return Vec::new();
+        }
+    };
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match line.parse::<config::TrustedPeer>() {
+            Ok(peer) => Some(peer.into()),
+            Err(e) => {
+                warn!(logger, "invalid node in the topology file"; "node" => line, "reason" => e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// save the nodes having an address, returns how many were saved
+pub fn save(path: &Path, nodes: &[Node]) -> io::Result<usize> {
+    let lines: Vec<String> = nodes
+        .iter()
+        .filter_map(|node| node.address().map(|address| line(address, node.id())))
+        .collect();
+    write(path, &lines)?;
+    Ok(lines.len())
+}
+
+fn line(address: SocketAddr, id: impl Display) -> String {
+    format!("{}@{}", multiaddr(address), id)
+}
+
+fn write(path: &Path, lines: &[String]) -> io::Result<()> {
+    let tmp_path = temp_path(path);
+    let result = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+pub fn multiaddr(address: SocketAddr) -> String {
+    match address {
+        SocketAddr::V4(address) => format!("/ip4/{}/tcp/{}", address.ip(), address.port()),
+        SocketAddr::V6(address) => format!("/ip6/{}/tcp/{}", address.ip(), address.port()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::p2p::Id;
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "jormungandr-topology-file-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn id() -> Id {
+        poldercast::Id::generate(&mut rand::rngs::OsRng::new().unwrap()).into()
+    }
+
+    #[test]
+    fn addresses_are_written_as_multiaddrs() {
+        assert_eq!(
+            multiaddr("10.0.0.2:3000".parse().unwrap()),
+            "/ip4/10.0.0.2/tcp/3000"
+        );
+        assert_eq!(
+            multiaddr("[2001:db8::1]:3000".parse().unwrap()),
+            "/ip6/2001:db8::1/tcp/3000"
+        );
+    }
+
+    #[test]
+    fn saved_nodes_are_loaded_back() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("topology");
+        let ids = vec![id(), id()];
+        let lines = vec![
+            line("10.0.0.2:3000".parse().unwrap(), ids[0]),
+            line("[2001:db8::1]:3100".parse().unwrap(), ids[1]),
+        ];
+
+        write(&path, &lines).unwrap();
+
+        let peers = load(&path, &logger());
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].id, ids[0]);
+        assert_eq!(peers[1].id, ids[1]);
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            1,
+            "the temporary file is left"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_file_or_invalid_lines_have_no_nodes() {
+        let dir = temp_dir("invalid");
+        let path = dir.join("topology");
+        assert!(load(&path, &logger()).is_empty());
+
+        let valid = line("10.0.0.2:3000".parse().unwrap(), id());
+        fs::write(&path, format!("not a node\n\n  {}  \n", valid)).unwrap();
+        assert_eq!(load(&path, &logger()).len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temporary_file() {
+        let dir = temp_dir("failed");
+        let path = dir.join("topology");
+        // a directory cannot be replaced by the file
+        fs::create_dir_all(path.join("taken")).unwrap();
+
+        assert!(write(&path, &[line("10.0.0.2:3000".parse().unwrap(), id())]).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,285 @@
+//! the workers processing the messages of the network task
+//!
+//! The messages are dispatched by kind to the queues of separate workers, so
+//! propagating to slow peers does not delay the solicitations of blocks or
+//! the replies to the stats queries. The propagations all go through one
+//! queue, in the order they are sent. The workers are tasks of the runtime of
+//! the network task, taking turns with each other and with the connections
+//! to the peers.
+//!
+//! No message is dropped: a message for a worker whose queue is full waits
+//! for room in the queue, holding back the following messages of the
+//! network task.
+//!
+//! The processing latency of a message counts from the time it is queued for
+//! its worker, so the time waiting behind the slow messages of its kind is
+//! included. The block fetches and the bootstraps complete after their
+//! dispatch, their latency counts up to their reply.
+
+use super::{handle_network_msg, Channels, GlobalStateR};
+use crate::intercom::{self, NetworkMsg, ReplyHandle};
+use crate::stats_counter::StatsCounter;
+use crate::utils::async_msg::{self, MessageBox, MessageQueue, SendError};
+use futures::prelude::*;
+use slog::Logger;
+use std::time::Instant;
+
+const PROPAGATION_QUEUE: &str = "network_propagation";
+const SOLICITATION_QUEUE: &str = "network_solicitation";
+const CONTROL_QUEUE: &str = "network_control";
+
+const WORKER_QUEUE_CAPACITY: usize = 64;
+
+/// a message in the queue of a worker, with the time it was queued
+type Queued = (Instant, NetworkMsg);
+
+/// the queues of the workers, to which the messages are sent as to a sink
+pub struct Workers {
+    propagation: MessageBox<Queued>,
+    solicitation: MessageBox<Queued>,
+    control: MessageBox<Queued>,
+}
+
+impl Workers {
+    /// spawn the workers, they stop once the `Workers` is dropped and their
+    /// queues are processed
+    pub fn start(state: &GlobalStateR, channels: &Channels) -> Self {
+        Workers {
+            propagation: start_worker(PROPAGATION_QUEUE, state, channels),
+            solicitation: start_worker(SOLICITATION_QUEUE, state, channels),
+            control: start_worker(CONTROL_QUEUE, state, channels),
+        }
+    }
+
+    fn queue_of(&mut self, msg: &NetworkMsg) -> &mut MessageBox<Queued> {
+        match msg {
+            NetworkMsg::Propagate(_) => &mut self.propagation,
+            NetworkMsg::GetBlocks(..)
+            | NetworkMsg::GetNextBlock(..)
+            | NetworkMsg::PullHeaders { .. } => &mut self.solicitation,
+            NetworkMsg::PeerStats(_)
+            | NetworkMsg::SetGossipInterval(_)
+            | NetworkMsg::SetNodeId(_)
+            | NetworkMsg::Bootstrap(_)
+            | NetworkMsg::Shutdown(_) => &mut self.control,
+        }
+    }
+}
+
+impl Sink for Workers {
+    type SinkItem = NetworkMsg;
+    type SinkError = SendError<Queued>;
+
+    fn start_send(&mut self, msg: NetworkMsg) -> StartSend<NetworkMsg, Self::SinkError> {
+        match self.queue_of(&msg).start_send((Instant::now(), msg))? {
+            AsyncSink::Ready => Ok(AsyncSink::Ready),
+            AsyncSink::NotReady((_, msg)) => Ok(AsyncSink::NotReady(msg)),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        let propagation = self.propagation.poll_complete()?;
+        let solicitation = self.solicitation.poll_complete()?;
+        let control = self.control.poll_complete()?;
+        if propagation.is_ready() && solicitation.is_ready() && control.is_ready() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+fn start_worker(
+    name: &'static str,
+    state: &GlobalStateR,
+    channels: &Channels,
+) -> MessageBox<Queued> {
+    let (mbox, queue) = async_msg::channel(WORKER_QUEUE_CAPACITY);
+    state.stats_counter.add_queue(name, mbox.monitor().clone());
+    state.spawn(run_worker(queue, state.clone(), channels.clone()));
+    mbox
+}
+
+fn run_worker(
+    queue: MessageQueue<Queued>,
+    state: GlobalStateR,
+    channels: Channels,
+) -> impl Future<Item = (), Error = ()> {
+    queue.for_each(move |(queued, msg)| {
+        let variant = msg.variant_name();
+        match msg {
+            NetworkMsg::GetBlocks(ids, reply) => {
+                let reply = spawn_measured_reply(reply, &state, variant, queued);
+                handle_network_msg(NetworkMsg::GetBlocks(ids, reply), &state, &channels);
+            }
+            NetworkMsg::Bootstrap(reply) => {
+                let reply = spawn_measured_reply(reply, &state, variant, queued);
+                handle_network_msg(NetworkMsg::Bootstrap(reply), &state, &channels);
+            }
+            msg => {
+                handle_network_msg(msg, &state, &channels);
+                state
+                    .stats_counter
+                    .add_message_processed("network", variant, queued.elapsed());
+            }
+        }
+        Ok(())
+    })
+}
+
+fn spawn_measured_reply<T>(
+    reply: ReplyHandle<T>,
+    state: &GlobalStateR,
+    variant: &'static str,
+    queued: Instant,
+) -> ReplyHandle<T>
+where
+    T: Send + 'static,
+{
+    let (reply, relay) = measured_reply(
+        reply,
+        state.stats_counter.clone(),
+        variant,
+        queued,
+        state.logger().clone(),
+    );
+    state.spawn(relay);
+    reply
+}
+
+/// the handle to reply a message completed after its dispatch with, and the
+/// future relaying the reply to the sender of the message, recording the
+/// processing of the message once replied
+fn measured_reply<T>(
+    reply: ReplyHandle<T>,
+    stats_counter: StatsCounter,
+    variant: &'static str,
+    queued: Instant,
+    logger: Logger,
+) -> (ReplyHandle<T>, impl Future<Item = (), Error = ()>) {
+    let (handle, future) = intercom::unary_reply::<T, intercom::Error>(logger);
+    let relay = future.then(move |res| {
+        stats_counter.add_message_processed("network", variant, queued.elapsed());
+        reply.reply(res);
+        Ok(())
+    });
+    (handle, relay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, stream};
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn workers(capacity: usize) -> (Workers, [MessageQueue<Queued>; 3]) {
+        let (propagation, propagation_queue) = async_msg::channel(capacity);
+        let (solicitation, solicitation_queue) = async_msg::channel(capacity);
+        let (control, control_queue) = async_msg::channel(capacity);
+        let workers = Workers {
+            propagation,
+            solicitation,
+            control,
+        };
+        (
+            workers,
+            [propagation_queue, solicitation_queue, control_queue],
+        )
+    }
+
+    fn interval(msg: NetworkMsg) -> Duration {
+        match msg {
+            NetworkMsg::SetGossipInterval(interval) => interval,
+            other => panic!("unexpected message {}", other.variant_name()),
+        }
+    }
+
+    #[test]
+    fn messages_wait_for_room_in_the_queue_in_order() {
+        let (workers, [_propagation, _solicitation, control]) = workers(1);
+        let intervals: Vec<_> = (1..=100).map(Duration::from_secs).collect();
+        let msgs = intervals
+            .clone()
+            .into_iter()
+            .map(NetworkMsg::SetGossipInterval)
+            .collect::<Vec<_>>();
+        let send = stream::iter_ok::<_, ()>(msgs)
+            .forward(workers.sink_map_err(|_| ()))
+            .map(|_| ());
+        let receive = control.take(100).map(|(_, msg)| interval(msg)).collect();
+
+        let mut runtime = Runtime::new().unwrap();
+        let ((), received) = runtime.block_on(send.join(receive)).unwrap();
+        assert_eq!(received, intervals);
+    }
+
+    #[test]
+    fn messages_go_to_the_queue_of_their_kind() {
+        let (mut workers, [propagation, solicitation, control]) = workers(4);
+        let logger = Logger::root(slog::Discard, o!());
+        let (reply, _) = intercom::unary_reply(logger.clone());
+        let (shutdown, _) = intercom::unary_reply(logger);
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(|| {
+                workers.start_send(NetworkMsg::GetBlocks(Vec::new(), reply))?;
+                workers.start_send(NetworkMsg::Shutdown(shutdown))?;
+                workers.start_send(NetworkMsg::SetGossipInterval(Duration::from_secs(1)))?;
+                workers.poll_complete().map(|_| ())
+            }))
+            .unwrap_or_else(|_| panic!("cannot send to the workers"));
+        drop(workers);
+
+        let names = |queue: MessageQueue<Queued>| {
+            queue
+                .map(|(_, msg)| msg.variant_name())
+                .collect()
+                .wait()
+                .unwrap()
+        };
+        assert!(names(propagation).is_empty());
+        assert_eq!(names(solicitation), vec!["get_blocks"]);
+        assert_eq!(names(control), vec!["shutdown", "set_gossip_interval"]);
+    }
+
+    #[test]
+    fn messages_are_timed_from_their_queueing() {
+        let (mut workers, [_propagation, _solicitation, control]) = workers(1);
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(|| {
+                workers.start_send(NetworkMsg::SetGossipInterval(Duration::from_secs(1)))?;
+                workers.poll_complete().map(|_| ())
+            }))
+            .unwrap_or_else(|_| panic!("cannot send to the workers"));
+        thread::sleep(Duration::from_millis(20));
+
+        let (queued, _) = control.take(1).collect().wait().unwrap().remove(0);
+        assert!(queued.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn reply_of_a_fetch_is_measured_once_replied() {
+        let stats_counter = StatsCounter::default();
+        let logger = Logger::root(slog::Discard, o!());
+        let (reply, future) = intercom::unary_reply::<u32, intercom::Error>(logger.clone());
+        let queued = Instant::now();
+        let (measured, relay) =
+            measured_reply(reply, stats_counter.clone(), "get_blocks", queued, logger);
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(relay);
+        assert!(stats_counter.message_stats().is_empty());
+
+        thread::sleep(Duration::from_millis(20));
+        measured.reply_ok(7);
+        assert_eq!(runtime.block_on(future).unwrap(), 7);
+
+        let stats = stats_counter.message_stats();
+        assert_eq!(stats.len(), 1);
+        let (task, variant, stats) = stats[0];
+        assert_eq!((task, variant, stats.count), ("network", "get_blocks", 1));
+        assert!(stats.latency >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,530 @@
+//! webhook notifications of the chain events
+//!
+//! The block task tells the notifier when it processed a block. The notifier
+//! compares the tip with the last one it notified and posts the events to the
+//! webhooks interested in them, as JSON objects tagged by their `event`:
+//! `new_tip`, `reorg`, `epoch_transition`, and `fragment_included` for the
+//! fragments transferring value from or to the watched addresses. The block
+//! task also tells when it kept the tip on a branch the chain selection did
+//! not prefer, posted as `deep_reorg_refused`.
+//!
+//! No events of the tip are sent while the node is not ready, so syncing
+//! does not post the whole chain. The events of a webhook wait in its queue of
+//! `EVENTS_CAPACITY` events and are posted one after the other, in the order
+//! of the tip changes: the events not fitting in the queue of a webhook
+//! lagging behind are dropped. A failed post is retried with an exponential
+//! backoff.
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{Blockchain, ChainIndex, Ref, Tip},
+    intercom::{DeepReorg, NotifierMsg},
+    lifecycle::Lifecycle,
+    settings::start::config::{self, Notifications},
+    utils::{
+        async_msg::{self, MessageBox, MessageQueue},
+        task::{Input, TokioServiceInfo},
+    },
+};
+use chain_core::property::{Block as _, Fragment as _};
+use futures::future::{self, Either, Loop};
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Request, Uri};
+use hyper_tls::HttpsConnector;
+use jormungandr_lib::interfaces::Address;
+use slog::Logger;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    prelude::*,
+    runtime::TaskExecutor,
+    timer::{Delay, Timeout},
+};
+
+/// threads resolving the host names of the webhooks
+const DNS_THREADS: usize = 1;
+
+/// the events waiting to be posted to a webhook
+const EVENTS_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainEventKind {
+    NewTip,
+    Reorg,
+    EpochTransition,
+    FragmentIncluded,
+    DeepReorgRefused,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ChainEvent {
+    NewTip {
+        hash: String,
+        parent: String,
+        chain_length: u32,
+        date: String,
+    },
+    Reorg {
+        common_ancestor: String,
+        dropped: Vec<String>,
+        applied: Vec<String>,
+    },
+    EpochTransition {
+        epoch: u32,
+        block: String,
+    },
+    FragmentIncluded {
+        fragment_id: String,
+        block: String,
+        date: String,
+        addresses: Vec<Address>,
+    },
+    DeepReorgRefused {
+        tip: String,
+        candidate: String,
+        max_rollback_depth: u64,
+    },
+}
+
+/// a fragment of the new blocks of the main chain, with the addresses it
+/// transfers value from or to
+struct IncludedFragment {
+    fragment_id: String,
+    block: String,
+    date: String,
+    addresses: Vec<chain_addr::Address>,
+}
+
+/// what changed from the last notified tip
+struct TipChange {
+    tip: Arc<Ref>,
+    reorg: Option<(HeaderHash, Vec<HeaderHash>)>,
+    applied: Vec<Block>,
+    previous_epoch: u32,
+}
+
+struct Webhook {
+    uri: Uri,
+    events: Option<Vec<ChainEventKind>>,
+    addresses: Vec<Address>,
+    /// the events waiting to be posted, the task posting them being started
+    /// with the first events
+    queue: Mutex<Option<MessageBox<ChainEvent>>>,
+}
+
+#[derive(Clone)]
+struct Delivery {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    timeout: Duration,
+}
+
+pub struct Notifier {
+    blockchain: Blockchain,
+    tip: Tip,
+    lifecycle: Lifecycle,
+    webhooks: Arc<Vec<Webhook>>,
+    delivery: Delivery,
+    last_tip: Arc<Mutex<Option<Arc<Ref>>>>,
+}
+
+impl Notifier {
+    pub fn new(
+        config: Notifications,
+        blockchain: Blockchain,
+        tip: Tip,
+        lifecycle: Lifecycle,
+    ) -> Result<Self, hyper_tls::Error> {
+        let client = Client::builder().build(HttpsConnector::new(DNS_THREADS)?);
+        let webhooks = config
+            .webhooks
+            .into_iter()
+            .map(|webhook: config::Webhook| Webhook {
+                uri: webhook
+                    .uri()
+                    .expect("webhook URL validated with the settings"),
+                events: webhook.events,
+                addresses: webhook.addresses,
+                queue: Mutex::new(None),
+            })
+            .collect();
+        Ok(Notifier {
+            blockchain,
+            tip,
+            lifecycle,
+            webhooks: Arc::new(webhooks),
+            delivery: Delivery {
+                client,
+                max_retries: config.max_retries,
+                retry_backoff: config.retry_backoff.into(),
+                timeout: config.timeout.into(),
+            },
+            last_tip: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn handle_input(
+        &mut self,
+        info: &TokioServiceInfo,
+        input: Input<NotifierMsg>,
+    ) -> impl Future<Item = (), Error = ()> {
+        match input {
+            Input::Shutdown => Either::A(future::ok(())),
+            Input::Input(NotifierMsg::TipUpdated) => Either::B(self.notify_tip(info)),
+            Input::Input(NotifierMsg::DeepReorgRefused(deep_reorg)) => {
+                self.notify_deep_reorg(info, deep_reorg);
+                Either::A(future::ok(()))
+            }
+        }
+    }
+
+    /// posted while syncing too, the branches refused being as many signs
+    /// of a peer serving a garbage chain
+    fn notify_deep_reorg(&self, info: &TokioServiceInfo, deep_reorg: DeepReorg) {
+        for webhook in self.webhooks.iter() {
+            if !webhook.wants(ChainEventKind::DeepReorgRefused) {
+                continue;
+            }
+            let event = ChainEvent::DeepReorgRefused {
+                tip: deep_reorg.tip.to_string(),
+                candidate: deep_reorg.candidate.to_string(),
+                max_rollback_depth: deep_reorg.max_rollback_depth,
+            };
+            let logger = info
+                .logger()
+                .new(o!("webhook" => webhook.uri.to_string()));
+            webhook.enqueue(vec![event], &self.delivery, info.executor(), logger);
+        }
+    }
+
+    fn notify_tip(&self, info: &TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let err_logger = logger.clone();
+        let executor = info.executor().clone();
+        let storage = self.blockchain.storage().clone();
+        let lifecycle = self.lifecycle.clone();
+        let last_tip = self.last_tip.clone();
+        let webhooks = self.webhooks.clone();
+        let delivery = self.delivery.clone();
+        // the fragments of the blocks not indexed yet are not found in an
+        // index being backfilled
+        let index = self
+            .blockchain
+            .index()
+            .filter(|index| index.backfill_progress().is_none())
+            .cloned();
+
+        self.tip
+            .get_ref()
+            .and_then(move |tip| {
+                let mut last_tip = last_tip.lock().unwrap();
+                let previous = match last_tip.replace(tip.clone()) {
+                    Some(previous) if previous.hash() != tip.hash() => previous,
+                    _ => return Either::A(future::ok(None)),
+                };
+                // the tip is tracked while syncing so the notifications
+                // resume from the tip the node is ready with
+                if !lifecycle.is_ready() {
+                    return Either::A(future::ok(None));
+                }
+                let previous_epoch = previous.block_date().epoch;
+                let applied_storage = storage.clone();
+                Either::B(
+                    storage
+                        .find_fork(previous.hash(), tip.hash())
+                        .and_then(move |fork| {
+                            applied_storage
+                                .stream_from_to(fork.common_ancestor, tip.hash())
+                                .and_then(|blocks| blocks.collect())
+                                .map(move |applied| {
+                                    let reorg = if fork.dropped.is_empty() {
+                                        None
+                                    } else {
+                                        Some((fork.common_ancestor, fork.dropped))
+                                    };
+                                    Some(TipChange {
+                                        tip,
+                                        reorg,
+                                        applied,
+                                        previous_epoch,
+                                    })
+                                })
+                        }),
+                )
+            })
+            .map_err(move |e| error!(err_logger, "cannot find the new blocks of the tip"; "reason" => %e))
+            .map(move |change| {
+                let change = match change {
+                    Some(change) => change,
+                    None => return,
+                };
+                let included = match index {
+                    Some(index) => included_fragments(&index, &change.applied, &logger),
+                    None => Vec::new(),
+                };
+                for webhook in webhooks.iter() {
+                    let events = webhook.events_of(&change, &included);
+                    if events.is_empty() {
+                        continue;
+                    }
+                    let logger = logger.new(o!("webhook" => webhook.uri.to_string()));
+                    webhook.enqueue(events, &delivery, &executor, logger);
+                }
+            })
+    }
+}
+
+fn included_fragments(
+    index: &ChainIndex,
+    blocks: &[Block],
+    logger: &Logger,
+) -> Vec<IncludedFragment> {
+    let mut included = Vec::new();
+    for block in blocks {
+        for fragment in block.contents.iter() {
+            match index.addresses_of(fragment) {
+                Ok(addresses) if !addresses.is_empty() => included.push(IncludedFragment {
+                    fragment_id: fragment.id().to_string(),
+                    block: block.id().to_string(),
+                    date: block.header.block_date().to_string(),
+                    addresses,
+                }),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(logger, "cannot find the addresses of a fragment"; "reason" => %e)
+                }
+            }
+        }
+    }
+    included
+}
+
+impl Webhook {
+    fn enqueue(
+        &self,
+        events: Vec<ChainEvent>,
+        delivery: &Delivery,
+        executor: &TaskExecutor,
+        logger: Logger,
+    ) {
+        let mut queue = self.queue.lock().unwrap();
+        let queue = queue.get_or_insert_with(|| {
+            let (msg_box, queue) = async_msg::channel(EVENTS_CAPACITY);
+            executor.spawn(
+                delivery
+                    .clone()
+                    .post_all(self.uri.clone(), queue, logger.clone()),
+            );
+            msg_box
+        });
+        let dropped = push_events(queue, events);
+        if dropped > 0 {
+            warn!(
+                logger,
+                "the webhook lags behind, dropping the events";
+                "dropped" => dropped,
+            );
+        }
+    }
+
+    fn wants(&self, kind: ChainEventKind) -> bool {
+        self.events
+            .as_ref()
+            .map_or(true, |events| events.contains(&kind))
+    }
+
+    fn events_of(&self, change: &TipChange, included: &[IncludedFragment]) -> Vec<ChainEvent> {
+        let mut events = Vec::new();
+        if let Some((common_ancestor, dropped)) = change.reorg.as_ref() {
+            if self.wants(ChainEventKind::Reorg) {
+                events.push(ChainEvent::Reorg {
+                    common_ancestor: common_ancestor.to_string(),
+                    dropped: dropped.iter().map(HeaderHash::to_string).collect(),
+                    applied: change
+                        .applied
+                        .iter()
+                        .map(|block| block.id().to_string())
+                        .collect(),
+                });
+            }
+        }
+        if self.wants(ChainEventKind::EpochTransition) {
+            let mut epoch = change.previous_epoch;
+            for block in change.applied.iter() {
+                let block_epoch = block.header.block_date().epoch;
+                if block_epoch > epoch {
+                    epoch = block_epoch;
+                    events.push(ChainEvent::EpochTransition {
+                        epoch,
+                        block: block.id().to_string(),
+                    });
+                }
+            }
+        }
+        if self.wants(ChainEventKind::FragmentIncluded) && !self.addresses.is_empty() {
+            for fragment in included {
+                let addresses: Vec<Address> = self
+                    .addresses
+                    .iter()
+                    .filter(|watched| fragment.addresses.contains(watched.as_ref()))
+                    .cloned()
+                    .collect();
+                if !addresses.is_empty() {
+                    events.push(ChainEvent::FragmentIncluded {
+                        fragment_id: fragment.fragment_id.clone(),
+                        block: fragment.block.clone(),
+                        date: fragment.date.clone(),
+                        addresses,
+                    });
+                }
+            }
+        }
+        if self.wants(ChainEventKind::NewTip) {
+            events.push(ChainEvent::NewTip {
+                hash: change.tip.hash().to_string(),
+                parent: change.tip.block_parent_hash().to_string(),
+                chain_length: u32::from(change.tip.chain_length()),
+                date: change.tip.block_date().to_string(),
+            });
+        }
+        events
+    }
+}
+
+/// queue the events in order, returns how many did not fit in the queue
+fn push_events(queue: &mut MessageBox<ChainEvent>, events: Vec<ChainEvent>) -> usize {
+    let mut dropped = 0;
+    for event in events {
+        if queue.try_send(event).is_err() {
+            dropped += 1;
+        }
+    }
+    dropped
+}
+
+impl Delivery {
+    /// post the queued events one after the other, giving up on an event once
+    /// its retries are exhausted
+    fn post_all(
+        self,
+        uri: Uri,
+        events: MessageQueue<ChainEvent>,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        events.for_each(move |event| {
+            let payload = serde_json::to_vec(&event).expect("chain events serialize to JSON");
+            self.post(uri.clone(), payload, logger.clone())
+        })
+    }
+
+    fn post(
+        &self,
+        uri: Uri,
+        payload: Vec<u8>,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        let delivery = self.clone();
+        future::loop_fn(0, move |attempt| {
+            let request = Request::post(uri.clone())
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(payload.clone()))
+                .expect("webhook request is valid");
+            let delivery = delivery.clone();
+            let logger = logger.clone();
+            Timeout::new(delivery.client.request(request), delivery.timeout).then(move |result| {
+                let reason = match result {
+                    Ok(ref response) if response.status().is_success() => {
+                        return Either::A(future::ok(Loop::Break(())));
+                    }
+                    Ok(response) => format!("the webhook responded {}", response.status()),
+                    Err(e) => e.to_string(),
+                };
+                if attempt >= delivery.max_retries {
+                    warn!(
+                        logger,
+                        "cannot notify the webhook, dropping the event";
+                        "reason" => reason,
+                        "attempts" => attempt + 1,
+                    );
+                    return Either::A(future::ok(Loop::Break(())));
+                }
+                let backoff = delivery.retry_backoff * 2u32.pow(attempt.min(16));
+                debug!(
+                    logger,
+                    "cannot notify the webhook, retrying";
+                    "reason" => reason,
+                    "retry_in" => ?backoff,
+                );
+                Either::B(
+                    Delay::new(Instant::now() + backoff)
+                        .map(move |()| Loop::Continue(attempt + 1))
+                        .map_err(move |e| error!(logger, "timer error"; "reason" => %e)),
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_tip(chain_length: u32) -> ChainEvent {
+        ChainEvent::NewTip {
+            hash: String::new(),
+            parent: String::new(),
+            chain_length,
+            date: String::new(),
+        }
+    }
+
+    fn chain_length(event: &ChainEvent) -> u32 {
+        match event {
+            ChainEvent::NewTip { chain_length, .. } => *chain_length,
+            _ => panic!("unexpected event"),
+        }
+    }
+
+    #[test]
+    fn events_are_queued_in_order_up_to_the_capacity() {
+        let (mut msg_box, queue) = async_msg::channel(2);
+
+        let dropped = push_events(&mut msg_box, (1..=3).map(new_tip).collect());
+        let dropped = dropped + push_events(&mut msg_box, (4..=6).map(new_tip).collect());
+        drop(msg_box);
+
+        let queued: Vec<u32> = queue
+            .wait()
+            .map(|event| chain_length(&event.unwrap()))
+            .collect();
+        assert!(dropped > 0);
+        assert_eq!(queued.len() + dropped, 6);
+        // the events fitting in the queue are the first ones, in order
+        let expected: Vec<u32> = (1..=queued.len() as u32).collect();
+        assert_eq!(queued, expected);
+    }
+
+    #[test]
+    fn events_are_tagged_by_their_kind() {
+        let json = serde_json::to_value(&ChainEvent::EpochTransition {
+            epoch: 13,
+            block: "b".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(json["event"], "epoch_transition");
+        assert_eq!(json["epoch"], 13);
+
+        let json = serde_json::to_value(&ChainEvent::DeepReorgRefused {
+            tip: "t".to_owned(),
+            candidate: "c".to_owned(),
+            max_rollback_depth: 2160,
+        })
+        .unwrap();
+        assert_eq!(json["event"], "deep_reorg_refused");
+        assert_eq!(json["max_rollback_depth"], 2160);
+    }
+}
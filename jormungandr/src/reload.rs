@@ -0,0 +1,272 @@
+//! reload of the configuration file while the node runs
+//!
+//! On SIGHUP, or when requested through the REST API, the configuration file
+//! is read again and compared with the one the node runs with. Only the
+//! settings listed in `RELOADABLE` are applied, by the tasks using them. If
+//! any other setting changed, nothing is applied and the changes are
+//! reported as needing a restart.
+//!
+//! The file of `p2p.private_id_file` is read again as well: when the node ID
+//! derived from it changed, the network task announces the new node ID.
+
+use crate::{
+    intercom::{self, NetworkMsg, ReloadMsg, TransactionMsg},
+    network::p2p::Id,
+    settings::{
+        self,
+        logging::{LogFormat, LogLevels, LogOutput, LogRotation, LogSettings, LogSettingsEntry},
+        start::RawSettings,
+        CommandLine,
+    },
+    utils::{
+        async_msg::{MessageBox, MessageQueue},
+        task::TokioServiceInfo,
+    },
+};
+use futures::prelude::*;
+use serde_yaml::Value;
+use slog::Logger;
+use thiserror::Error;
+
+/// the settings that can be changed without restarting the node. Only the
+/// levels of the log outputs can be changed, not the outputs themselves.
+const RELOADABLE: &[&str] = &[
+    "log",
+    "mempool.fragment_ttl",
+    "mempool.log_ttl",
+    "p2p.gossip_interval",
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("the node was started without a configuration file")]
+    NoConfigFile,
+    #[error("invalid configuration file")]
+    Settings {
+        #[from]
+        source: settings::Error,
+    },
+    #[error("cannot compare the configuration files")]
+    Compare {
+        #[from]
+        source: serde_yaml::Error,
+    },
+    #[error("the {0} task is not available to apply the new settings")]
+    TaskUnavailable(&'static str),
+}
+
+/// the outcome of a reload: the settings changed in the configuration file
+/// and applied, or the ones which cannot be without a restart in which case
+/// nothing is applied
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    pub applied: Vec<String>,
+    pub rejected: Vec<String>,
+}
+
+/// the configuration the node was started with, as it is needed to detect
+/// the changes of the configuration file
+pub struct RunningConfig {
+    config: Value,
+    log_outputs: Vec<(LogFormat, LogOutput, Option<LogRotation>)>,
+    log_levels: LogLevels,
+    /// the node ID derived from the file of `p2p.private_id_file`
+    node_id: Option<Id>,
+}
+
+impl RunningConfig {
+    pub fn new(
+        raw_settings: &RawSettings,
+        log_settings: &LogSettings,
+        log_levels: LogLevels,
+    ) -> Result<Self, Error> {
+        Ok(RunningConfig {
+            config: serde_yaml::to_value(raw_settings.config())?,
+            log_outputs: log_outputs(log_settings),
+            log_levels,
+            node_id: private_node_id(raw_settings)?,
+        })
+    }
+}
+
+pub struct Reload {
+    running: RunningConfig,
+    network_task: MessageBox<NetworkMsg>,
+    transaction_task: MessageBox<TransactionMsg>,
+}
+
+impl Reload {
+    pub fn new(
+        running: RunningConfig,
+        network_task: MessageBox<NetworkMsg>,
+        transaction_task: MessageBox<TransactionMsg>,
+    ) -> Self {
+        Reload {
+            running,
+            network_task,
+            transaction_task,
+        }
+    }
+
+    /// reload the configuration on each of the requests of the REST API, and
+    /// on SIGHUP
+    pub fn run(
+        mut self,
+        info: TokioServiceInfo,
+        input: MessageQueue<ReloadMsg>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let requests = input.map(|ReloadMsg(reply)| Some(reply));
+        #[cfg(unix)]
+        let requests = requests.select(sighup(logger.clone()).map(|()| None));
+
+        requests.for_each(move |reply| {
+            info!(logger, "reloading the configuration file");
+            let result = self.reload(&logger);
+            match &result {
+                Ok(report) if !report.rejected.is_empty() => warn!(
+                    logger,
+                    "the configuration changes need a restart of the node, none was applied" ;
+                    "rejected" => ?report.rejected,
+                ),
+                Ok(report) => info!(
+                    logger,
+                    "configuration reloaded" ;
+                    "applied" => ?report.applied,
+                ),
+                Err(e) => error!(logger, "cannot reload the configuration"; "reason" => %e),
+            }
+            if let Some(reply) = reply {
+                reply.reply(result.map_err(intercom::Error::failed_precondition));
+            }
+            Ok(())
+        })
+    }
+
+    fn reload(&mut self, logger: &Logger) -> Result<ReloadReport, Error> {
+        let raw_settings = RawSettings::load(CommandLine::load())?;
+        if raw_settings.config().is_none() {
+            return Err(Error::NoConfigFile);
+        }
+        let config = serde_yaml::to_value(raw_settings.config())?;
+        let log_settings = raw_settings.log_settings();
+        let same_log_outputs = log_outputs(&log_settings) == self.running.log_outputs;
+
+        let mut changes = Vec::new();
+        changed_paths(String::new(), &self.running.config, &config, &mut changes);
+        let (mut applied, rejected): (Vec<_>, Vec<_>) = changes.into_iter().partition(|path| {
+            RELOADABLE.contains(&path.as_str()) && (path != "log" || same_log_outputs)
+        });
+        if !rejected.is_empty() {
+            return Ok(ReloadReport {
+                applied: Vec::new(),
+                rejected,
+            });
+        }
+
+        let node_id = private_node_id(&raw_settings)?;
+        let settings = raw_settings.try_into_settings(logger)?;
+        if applied.iter().any(|path| path.starts_with("mempool.")) {
+            self.transaction_task
+                .try_send(TransactionMsg::SetTtl {
+                    fragment_ttl: settings.mempool.fragment_ttl.into(),
+                    log_ttl: settings.mempool.log_ttl.into(),
+                })
+                .map_err(|_| Error::TaskUnavailable("fragment"))?;
+        }
+        if applied.iter().any(|path| path == "p2p.gossip_interval") {
+            self.network_task
+                .try_send(NetworkMsg::SetGossipInterval(
+                    settings.network.gossip_interval,
+                ))
+                .map_err(|_| Error::TaskUnavailable("network"))?;
+        }
+        if applied.iter().any(|path| path == "log") {
+            let levels = log_settings
+                .0
+                .iter()
+                .map(LogSettingsEntry::levels)
+                .collect::<Vec<_>>();
+            self.running.log_levels.set(&levels);
+        }
+
+        if node_id != self.running.node_id {
+            if let Some(id) = node_id {
+                self.network_task
+                    .try_send(NetworkMsg::SetNodeId(id))
+                    .map_err(|_| Error::TaskUnavailable("network"))?;
+                applied.push("p2p.private_id_file".to_owned());
+            }
+        }
+
+        self.running.config = config;
+        self.running.node_id = node_id;
+        Ok(ReloadReport {
+            applied,
+            rejected: Vec::new(),
+        })
+    }
+}
+
+/// the node ID derived from the private ID file, if the node has one. The
+/// file is read on each reload as rotating the private ID does not change
+/// the configuration file.
+fn private_node_id(raw_settings: &RawSettings) -> Result<Option<Id>, Error> {
+    match raw_settings
+        .config()
+        .and_then(|config| config.p2p.private_id_file.as_ref())
+    {
+        Some(path) => Ok(Some(settings::start::read_private_id(path)?)),
+        None => Ok(None),
+    }
+}
+
+fn log_outputs(log_settings: &LogSettings) -> Vec<(LogFormat, LogOutput, Option<LogRotation>)> {
+    log_settings
+        .0
+        .iter()
+        .map(|entry| (entry.format, entry.output.clone(), entry.rotation.clone()))
+        .collect()
+}
+
+/// collect the dotted paths of the settings that differ, down to the values
+/// which are not mappings (e.g. the log outputs are compared as a whole)
+fn changed_paths(path: String, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Mapping(old), Value::Mapping(new)) => {
+            let keys = old.iter().map(|(key, _)| key).chain(
+                new.iter()
+                    .map(|(key, _)| key)
+                    .filter(|key| !old.contains_key(key)),
+            );
+            for key in keys {
+                let key_path = match key {
+                    Value::String(key) if path.is_empty() => key.clone(),
+                    Value::String(key) => format!("{}.{}", path, key),
+                    _ => path.clone(),
+                };
+                changed_paths(
+                    key_path,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (old, new) => {
+            if old != new {
+                changes.push(path);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn sighup(logger: Logger) -> impl Stream<Item = (), Error = ()> {
+    use tokio_signal::unix::{Signal, SIGHUP};
+
+    Signal::new(SIGHUP)
+        .flatten_stream()
+        .map(|_| ())
+        .map_err(move |e| error!(logger, "cannot receive SIGHUP"; "reason" => %e))
+}
@@ -0,0 +1,1946 @@
+use jormungandr_lib::interfaces::*;
+use jormungandr_lib::time::SystemTime;
+
+use actix_web::error::{
+    ErrorBadRequest, ErrorConflict, ErrorGatewayTimeout, ErrorInternalServerError, ErrorNotFound,
+    ErrorNotImplemented, ErrorServiceUnavailable, ErrorTooManyRequests,
+};
+use actix_web::http::header;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use actix_web::{Json, Path, Query, Responder, State};
+use chain_addr::Kind;
+use chain_core::property::{Block, Deserialize, Serialize as _};
+use chain_crypto::{bech32::Bech32 as _, Blake2b256, Ed25519, PublicKey};
+use chain_impl_mockchain::account::{AccountAlg, Identifier};
+use chain_impl_mockchain::certificate::PoolId;
+use chain_impl_mockchain::fee::FeeAlgorithm;
+use chain_impl_mockchain::fragment::{Fragment, FragmentId};
+use chain_impl_mockchain::key::Hash;
+use chain_impl_mockchain::leadership::{Leader, LeadershipConsensus};
+use chain_impl_mockchain::transaction::{InputEnum, Payload, Transaction};
+use chain_impl_mockchain::value::{Value, ValueError};
+use chain_storage::error::Error as StorageError;
+
+use crate::blockcfg::{self, Ledger};
+use crate::blockchain::{ChainIndex, IoKind, Ref, SubscribeError, SubscriptionId};
+use crate::disk_space::DiskGuard;
+use crate::fragment::{admission, selection::MAX_PER_BLOCK};
+use crate::intercom::{self, LeadershipMsg, NetworkMsg, ReloadMsg, RewardsMsg, TransactionMsg};
+use crate::log::TraceId;
+use crate::secure::NodeSecret;
+use crate::settings::logging::OutputLevels;
+use crate::stake_snapshot;
+use crate::stats_counter::StatsCounter;
+use crate::utils::async_msg::MessageBox;
+use bytes::{Bytes, IntoBuf};
+use futures::{
+    future::{
+        self,
+        Either::{A, B},
+    },
+    Future, IntoFuture, Stream,
+};
+use jormungandr_lib::fragment::{visit_transaction, TransactionVisitor};
+use jormungandr_lib::interfaces::NodeState;
+use network_core::error::Code;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::rest::ApiVersion;
+pub use crate::rest::{Context, FullContext};
+
+/// the W3C Trace Context header continued by the processing of the requests
+const TRACEPARENT: &str = "traceparent";
+
+macro_rules! ActixFuture {
+    () => { impl Future<Item = impl Responder + 'static, Error = impl Into<Error> + 'static> + 'static }
+}
+
+fn chain_tip_fut<'a>(context: &State<Context>) -> impl Future<Item = Arc<Ref>, Error = Error> {
+    context
+        .try_full_fut()
+        .and_then(|context| chain_tip_fut_raw(&*context))
+}
+
+fn chain_tip_fut_raw<'a>(context: &FullContext) -> impl Future<Item = Arc<Ref>, Error = Error> {
+    context.blockchain_tip.get_ref()
+}
+
+pub fn get_account_state(context: State<Context>, account_id_hex: Path<String>) -> ActixFuture!() {
+    parse_account_id(&account_id_hex)
+        .into_future()
+        .and_then(move |account_id| {
+            chain_tip_fut(&context).map(|tip_reference| (tip_reference, account_id))
+        })
+        .and_then(|(tip_reference, account_id)| {
+            let state = tip_reference
+                .ledger()
+                .accounts()
+                .get_state(&account_id)
+                .map_err(|e| ErrorNotFound(e))?;
+
+            Ok(Json(AccountState::from(state)))
+        })
+}
+
+fn parse_account_id(id_hex: &str) -> Result<Identifier, Error> {
+    PublicKey::<AccountAlg>::from_str(id_hex)
+        .map(Into::into)
+        .map_err(ErrorBadRequest)
+}
+
+fn parse_fragment_id(id_hex: &str) -> Result<FragmentId, Error> {
+    FragmentId::from_str(id_hex).map_err(ErrorBadRequest)
+}
+
+pub fn get_message_logs(context: State<Context>) -> ActixFuture!() {
+    context.try_full_fut().and_then(|context| {
+        context
+            .logs
+            .logs()
+            .map_err(|_| ErrorInternalServerError("Failed to get logs"))
+            .map(Json)
+    })
+}
+
+/// refuse the fragments while the storage volume is almost full
+fn check_disk_space(disk_guard: &DiskGuard, stats_counter: &StatsCounter) -> Result<(), Error> {
+    if disk_guard.is_low() {
+        stats_counter.add_fragment_rejected("disk_space");
+        return Err(ErrorServiceUnavailable(
+            "the storage volume of the node is almost full, fragments are refused",
+        ));
+    }
+    Ok(())
+}
+
+pub fn post_message(
+    request: HttpRequest<Context>,
+    context: State<Context>,
+    message: Bytes,
+) -> Result<impl Responder, Error> {
+    let fragment = Fragment::deserialize(message.into_buf()).map_err(ErrorBadRequest)?;
+    let full_context = context.try_full()?;
+    check_disk_space(&full_context.disk_guard, &full_context.stats_counter)?;
+    if let Err(rejection) = admission::check(&fragment, &full_context.fragment_limits) {
+        full_context
+            .stats_counter
+            .add_fragment_rejected(rejection.code());
+        return Err(ErrorBadRequest(format!(
+            "fragment rejected ({}): {}",
+            rejection.code(),
+            rejection
+        )));
+    }
+    let trace = request
+        .headers()
+        .get(TRACEPARENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(TraceId::from_traceparent)
+        .unwrap_or_else(TraceId::new);
+    let msg = TransactionMsg::SendTransaction(trace, FragmentOrigin::Rest, vec![fragment]);
+    full_context
+        .transaction_task
+        .clone()
+        .try_send(msg)
+        .map_err(|e| ErrorInternalServerError(e))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn get_tip(context: State<Context>) -> ActixFuture!() {
+    chain_tip_fut(&context).map(|tip| tip.hash().to_string())
+}
+
+#[derive(Serialize)]
+struct NodeStatsDto {
+    state: NodeState,
+    #[serde(flatten)]
+    stats: Option<serde_json::Value>,
+}
+
+pub fn get_stats_counter(context: State<Context>) -> ActixFuture!() {
+    let api_version = context.api_version();
+    match context.try_full_any_state() {
+        Ok(context) => {
+            let stats_json_fut = chain_tip_fut_raw(&*context)
+                .map(|tip| (context, tip))
+                .and_then(move |(context, tip)| {
+                    let header = tip.header().clone();
+                    context
+                        .blockchain
+                        .storage()
+                        .get(header.hash())
+                        .then(|res| match res {
+                            Ok(Some(block)) => Ok(block.contents),
+                            Ok(None) => {
+                                Err(ErrorInternalServerError("Could not find block for tip"))
+                            }
+                            Err(e) => Err(ErrorInternalServerError(e)),
+                        })
+                        .map(move |contents| (context, contents, header))
+                })
+                .and_then(move |(context, contents, tip_header)| {
+                    let mut block_tx_count = 0;
+                    let mut block_input_sum = Value::zero();
+                    let mut block_fee_sum = Value::zero();
+                    contents
+                        .iter()
+                        .filter_map(|fragment| match fragment {
+                            Fragment::Transaction(tx) => Some(tx),
+                            _ => None,
+                        })
+                        .map(|tx| {
+                            let input_sum = tx.total_input()?;
+                            let output_sum = tx.total_output()?;
+                            //    Value::sum(tx.outputs.iter().map(|input| input.value))?;
+                            // Input < output implies minting, so no fee
+                            let fee = (input_sum - output_sum).unwrap_or(Value::zero());
+                            block_tx_count += 1;
+                            block_input_sum = (block_input_sum + input_sum)?;
+                            block_fee_sum = (block_fee_sum + fee)?;
+                            Ok(())
+                        })
+                        .collect::<Result<(), ValueError>>()
+                        .map_err(|e| {
+                            ErrorInternalServerError(format!(
+                                "Block value calculation error: {}",
+                                e
+                            ))
+                        })?;
+                    let stats = &context.stats_counter;
+                    let chain_length = tip_header.chain_length();
+                    let last_block_height = match api_version {
+                        ApiVersion::V0 => json!(chain_length.to_string()),
+                        ApiVersion::V1 => json!(u32::from(chain_length)),
+                    };
+                    Ok(Some(json!({
+                        "txRecvCnt": stats.tx_recv_cnt(),
+                        "blockRecvCnt": stats.block_recv_cnt(),
+                        "uptime": stats.uptime_sec(),
+                        "lastBlockHash": tip_header.hash().to_string(),
+                        "lastBlockHeight": last_block_height,
+                        "lastBlockDate": tip_header.block_date().to_string(),
+                        "lastBlockTime": stats.slot_start_time().map(SystemTime::from),
+                        "lastBlockTx": block_tx_count,
+                        "lastBlockSum": block_input_sum.0,
+                        "lastBlockFees": block_fee_sum.0,
+                        "poolStats": stats.pool_stats(),
+                        "clockDriftMillis": stats.clock_drift_ms(),
+                        "blockCacheHits": stats.block_cache_hits(),
+                        "blockCacheMisses": stats.block_cache_misses(),
+                        "peerConnectionsEstablished": stats.peer_connections().established,
+                        "peerConnectionsConnecting": stats.peer_connections().connecting,
+                        "clientOnly": stats.client_only(),
+                        "diskSpaceAvailable": stats.disk_space_available(),
+                        "diskSpaceLow": context.disk_guard.is_low(),
+                    })))
+                });
+            A(stats_json_fut)
+        }
+        Err(_) => B(future::ok(None)),
+    }
+    .map(move |stats| {
+        Json(NodeStatsDto {
+            state: context.node_state(),
+            stats,
+        })
+    })
+}
+
+#[derive(Serialize)]
+struct NodeReadyDto {
+    ready: bool,
+    state: NodeState,
+}
+
+/// the readiness probe of the node: `200` once it is synced, `503` before
+pub fn get_node_ready(context: State<Context>) -> Result<impl Responder, Error> {
+    let state = context.node_state();
+    let ready = state == NodeState::Running;
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(NodeReadyDto { ready, state }))
+}
+
+pub fn get_metrics(context: State<Context>) -> Result<impl Responder, Error> {
+    use std::fmt::Write as _;
+
+    let full_context = context.try_full_any_state()?;
+    let stats = &full_context.stats_counter;
+    let pool = stats.pool_stats();
+    let ref_cache = full_context.blockchain.ref_cache_stats();
+    let queues = stats.queue_stats();
+    let messages = stats.message_stats();
+    let fragments_rejected = stats.fragments_rejected();
+    let limit_violations = stats.limit_violations();
+    let gossip_rejections = stats.gossip_rejections();
+    let subscription_throttles = stats.subscription_throttles();
+    let unsupported_versions = stats.unsupported_versions();
+    let peer_versions = stats.peer_versions();
+    let compressed_bytes = stats.compressed_bytes();
+    let connections = stats.peer_connections();
+    let maintenance = stats.maintenance_stats();
+    let io_stats = full_context.blockchain.storage().io_stats();
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, u64)>| {
+        let _ = writeln!(body, "# HELP jormungandr_{} {}", name, help);
+        let _ = writeln!(body, "# TYPE jormungandr_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(body, "jormungandr_{}{} {}", name, labels, value);
+        }
+    };
+
+    metric(
+        "tx_received_total",
+        "counter",
+        "Number of fragments received",
+        vec![(String::new(), stats.tx_recv_cnt())],
+    );
+    metric(
+        "tx_rejected_total",
+        "counter",
+        "Number of fragments rejected before reaching the pool, per reason",
+        fragments_rejected
+            .into_iter()
+            .map(|(code, count)| (format!("{{reason=\"{}\"}}", code), count))
+            .collect(),
+    );
+    metric(
+        "peer_messages_over_limits_total",
+        "counter",
+        "Number of messages of the peers over the size limits, per kind of message",
+        limit_violations
+            .into_iter()
+            .map(|(message, count)| (format!("{{message=\"{}\"}}", message), count))
+            .collect(),
+    );
+    metric(
+        "gossip_nodes_rejected_total",
+        "counter",
+        "Number of nodes dropped from the gossips of the peers, per reason",
+        gossip_rejections
+            .into_iter()
+            .map(|(reason, count)| (format!("{{reason=\"{}\"}}", reason), count))
+            .collect(),
+    );
+    metric(
+        "peer_subscriptions_throttled_total",
+        "counter",
+        "Number of times the peers were throttled over the rate limits, per subscription",
+        subscription_throttles
+            .into_iter()
+            .map(|(subscription, count)| (format!("{{subscription=\"{}\"}}", subscription), count))
+            .collect(),
+    );
+    metric(
+        "peer_handshakes_unsupported_version_total",
+        "counter",
+        "Number of connections to peers failed on their protocol version, per version",
+        unsupported_versions
+            .into_iter()
+            .map(|(version, count)| (format!("{{version=\"{}\"}}", version), count))
+            .collect(),
+    );
+    metric(
+        "peer_connections_by_version_total",
+        "counter",
+        "Number of connections negotiated with peers, per protocol version",
+        peer_versions
+            .into_iter()
+            .map(|(version, count)| (format!("{{version=\"{}\"}}", version), count))
+            .collect(),
+    );
+    metric(
+        "peer_compression_bytes_total",
+        "counter",
+        "Number of bytes of the compressed connections, per direction, on the wire or uncompressed",
+        compressed_bytes
+            .into_iter()
+            .map(|(direction, layer, bytes)| {
+                (
+                    format!("{{direction=\"{}\",layer=\"{}\"}}", direction, layer),
+                    bytes,
+                )
+            })
+            .collect(),
+    );
+    metric(
+        "block_received_total",
+        "counter",
+        "Number of blocks received",
+        vec![(String::new(), stats.block_recv_cnt())],
+    );
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "Time since the node started",
+        vec![(String::new(), stats.uptime_sec())],
+    );
+    metric(
+        "pool_slots_scheduled",
+        "gauge",
+        "Slots the node's leaders are elected for, per epoch",
+        pool.epochs
+            .iter()
+            .map(|e| (format!("{{epoch=\"{}\"}}", e.epoch), e.scheduled))
+            .collect(),
+    );
+    metric(
+        "pool_blocks_produced",
+        "gauge",
+        "Blocks created by the node's leaders, per epoch",
+        pool.epochs
+            .iter()
+            .map(|e| (format!("{{epoch=\"{}\"}}", e.epoch), e.produced))
+            .collect(),
+    );
+    let missed = &pool.missed_slots;
+    metric(
+        "pool_slots_missed_total",
+        "counter",
+        "Slots the node's leaders did not create a block for, per reason",
+        vec![
+            ("tip_behind", missed.tip_behind),
+            ("too_late", missed.too_late),
+            ("enclave_error", missed.enclave_error),
+            ("timeout", missed.timeout),
+            ("syncing", missed.syncing),
+            ("clock_drift", missed.clock_drift),
+            ("stale_tip", missed.stale_tip),
+        ]
+        .into_iter()
+        .map(|(reason, count)| (format!("{{reason=\"{}\"}}", reason), count))
+        .collect(),
+    );
+    metric(
+        "pool_block_propagation_delay_milliseconds",
+        "gauge",
+        "Average time before a peer fetches a block created by the node",
+        pool.avg_propagation_delay_ms
+            .map(|delay| (String::new(), delay))
+            .into_iter()
+            .collect(),
+    );
+    metric(
+        "ref_cache_hits_total",
+        "counter",
+        "Lookups of a block's ledger state found in the cache",
+        vec![(String::new(), ref_cache.hits)],
+    );
+    metric(
+        "ref_cache_misses_total",
+        "counter",
+        "Lookups of a block's ledger state not found in the cache",
+        vec![(String::new(), ref_cache.misses)],
+    );
+    metric(
+        "ref_cache_evictions_total",
+        "counter",
+        "Ledger states removed from the cache to stay within its capacity",
+        vec![(String::new(), ref_cache.evictions)],
+    );
+    metric(
+        "ref_cache_entries",
+        "gauge",
+        "Ledger states currently in the cache",
+        vec![(String::new(), ref_cache.entries as u64)],
+    );
+    metric(
+        "peer_block_cache_hits_total",
+        "counter",
+        "Blocks requested by the peers found in the cache of the recent blocks",
+        vec![(String::new(), stats.block_cache_hits())],
+    );
+    metric(
+        "peer_block_cache_misses_total",
+        "counter",
+        "Blocks requested by the peers not found in the cache of the recent blocks",
+        vec![(String::new(), stats.block_cache_misses())],
+    );
+    metric(
+        "peer_connections",
+        "gauge",
+        "Connections of the peers, per state",
+        vec![
+            ("established", connections.established),
+            ("connecting", connections.connecting),
+        ]
+        .into_iter()
+        .map(|(state, count)| (format!("{{state=\"{}\"}}", state), count as u64))
+        .collect(),
+    );
+    metric(
+        "peer_connections_capacity",
+        "gauge",
+        "Peers the node keeps connections with at most",
+        vec![(String::new(), connections.capacity as u64)],
+    );
+    metric(
+        "client_only",
+        "gauge",
+        "1 if the node does not listen for the connections of the peers",
+        vec![(String::new(), stats.client_only() as u64)],
+    );
+    let queue_label = |queue: &str| format!("{{queue=\"{}\"}}", queue);
+    metric(
+        "intercom_queue_capacity",
+        "gauge",
+        "Messages the queue of a task holds before the senders wait or drop them",
+        queues
+            .iter()
+            .map(|(queue, stats)| (queue_label(queue), stats.capacity as u64))
+            .collect(),
+    );
+    metric(
+        "intercom_queue_depth",
+        "gauge",
+        "Messages sent to a task and not processed yet",
+        queues
+            .iter()
+            .map(|(queue, stats)| (queue_label(queue), stats.depth as u64))
+            .collect(),
+    );
+    metric(
+        "intercom_queue_dropped_total",
+        "counter",
+        "Messages not sent to a task because its queue was full",
+        queues
+            .iter()
+            .map(|(queue, stats)| (queue_label(queue), stats.dropped))
+            .collect(),
+    );
+    let message_label =
+        |queue: &str, variant: &str| format!("{{queue=\"{}\",variant=\"{}\"}}", queue, variant);
+    metric(
+        "intercom_messages_processed_total",
+        "counter",
+        "Messages processed by a task, per variant",
+        messages
+            .iter()
+            .map(|(queue, variant, stats)| (message_label(queue, variant), stats.count))
+            .collect(),
+    );
+    metric(
+        "intercom_message_processing_milliseconds_total",
+        "counter",
+        "Time spent by a task processing the messages, per variant",
+        messages
+            .iter()
+            .map(|(queue, variant, stats)| {
+                (
+                    message_label(queue, variant),
+                    stats.latency.as_millis() as u64,
+                )
+            })
+            .collect(),
+    );
+    let action_label = |action: &str| format!("{{action=\"{}\"}}", action);
+    metric(
+        "maintenance_action_running",
+        "gauge",
+        "1 while the maintenance action runs",
+        maintenance
+            .iter()
+            .map(|(action, stats)| (action_label(action), stats.running_since.is_some() as u64))
+            .collect(),
+    );
+    metric(
+        "maintenance_action_progress_steps",
+        "gauge",
+        "Steps done by the running maintenance action",
+        maintenance
+            .iter()
+            .filter_map(|(action, stats)| Some((action_label(action), stats.progress?.0)))
+            .collect(),
+    );
+    metric(
+        "maintenance_action_total_steps",
+        "gauge",
+        "Steps to do by the running maintenance action",
+        maintenance
+            .iter()
+            .filter_map(|(action, stats)| Some((action_label(action), stats.progress?.1)))
+            .collect(),
+    );
+    metric(
+        "maintenance_action_elapsed_milliseconds",
+        "gauge",
+        "Time since the running maintenance action started",
+        maintenance
+            .iter()
+            .filter_map(|(action, stats)| {
+                let elapsed = stats.running_since?.elapsed().as_millis() as u64;
+                Some((action_label(action), elapsed))
+            })
+            .collect(),
+    );
+    metric(
+        "maintenance_action_duration_milliseconds",
+        "gauge",
+        "Duration of the last completed run of the maintenance action",
+        maintenance
+            .iter()
+            .filter(|(_, stats)| stats.last_completed.is_some())
+            .map(|(action, stats)| (action_label(action), stats.last_duration.as_millis() as u64))
+            .collect(),
+    );
+    metric(
+        "maintenance_action_last_completed_timestamp_seconds",
+        "gauge",
+        "Time the last run of the maintenance action completed",
+        maintenance
+            .iter()
+            .filter_map(|(action, stats)| {
+                Some((action_label(action), stats.last_completed?.to_secs()))
+            })
+            .collect(),
+    );
+    metric(
+        "maintenance_action_runs_total",
+        "counter",
+        "Completed runs of the maintenance action, per outcome",
+        maintenance
+            .iter()
+            .flat_map(|(action, stats)| {
+                vec![("success", stats.succeeded), ("failure", stats.failed)]
+                    .into_iter()
+                    .map(move |(outcome, count)| {
+                        (
+                            format!("{{action=\"{}\",outcome=\"{}\"}}", action, outcome),
+                            count,
+                        )
+                    })
+            })
+            .collect(),
+    );
+    metric(
+        "disk_space_available_bytes",
+        "gauge",
+        "Space left on the storage volume",
+        stats
+            .disk_space_available()
+            .map(|bytes| (String::new(), bytes))
+            .into_iter()
+            .collect(),
+    );
+    metric(
+        "disk_space_low",
+        "gauge",
+        "1 while the space left on the storage volume is below the hard threshold",
+        vec![(String::new(), full_context.disk_guard.is_low() as u64)],
+    );
+    metric(
+        "storage_size_bytes",
+        "gauge",
+        "Space taken by the storage on the disk, measured by the maintenance",
+        stats
+            .storage_size()
+            .map(|bytes| (String::new(), bytes))
+            .into_iter()
+            .collect(),
+    );
+    metric(
+        "epoch_entered",
+        "gauge",
+        "Epoch of the last transition of the main chain seen by the node",
+        stats
+            .epoch_entered()
+            .map(|epoch| (String::new(), u64::from(epoch)))
+            .into_iter()
+            .collect(),
+    );
+    metric(
+        "epoch_reentries_total",
+        "counter",
+        "Transitions to an epoch the main chain was in on another branch",
+        vec![(String::new(), stats.epoch_reentries())],
+    );
+    metric(
+        "deep_reorgs_refused_total",
+        "counter",
+        "Switches to a preferred branch refused for rolling back too many blocks",
+        vec![(String::new(), stats.deep_reorgs_refused())],
+    );
+
+    let name = "jormungandr_storage_operation_duration_milliseconds";
+    let _ = writeln!(
+        body,
+        "# HELP {} Time spent by the storage backend in an operation, per kind of operation",
+        name
+    );
+    let _ = writeln!(body, "# TYPE {} histogram", name);
+    for kind in &[IoKind::Read, IoKind::Write] {
+        let latency = io_stats.latency(*kind);
+        let operation = kind.as_str();
+        for (bound, count) in latency.buckets {
+            let _ = writeln!(
+                body,
+                "{}_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                name, operation, bound, count
+            );
+        }
+        let _ = writeln!(
+            body,
+            "{}_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+            name, operation, latency.count
+        );
+        let _ = writeln!(
+            body,
+            "{}_sum{{operation=\"{}\"}} {}",
+            name,
+            operation,
+            latency.sum_millis()
+        );
+        let _ = writeln!(
+            body,
+            "{}_count{{operation=\"{}\"}} {}",
+            name, operation, latency.count
+        );
+    }
+
+    if let Some(drift_ms) = stats.clock_drift_ms() {
+        let name = "jormungandr_clock_drift_milliseconds";
+        let _ = writeln!(
+            body,
+            "# HELP {} How far the clock of the node is ahead of the time servers",
+            name
+        );
+        let _ = writeln!(body, "# TYPE {} gauge", name);
+        let _ = writeln!(body, "{} {}", name, drift_ms);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+pub fn get_block_id(context: State<Context>, block_id_hex: Path<String>) -> ActixFuture!() {
+    context
+        .try_full()
+        .and_then(|context| parse_block_hash(&block_id_hex).map(|block_id| (context, block_id)))
+        .into_future()
+        .and_then(|(context, block_id)| {
+            context
+                .blockchain
+                .storage()
+                .get(block_id)
+                .map_err(|e| ErrorInternalServerError(e))
+                .and_then(|block_opt| {
+                    block_opt
+                        .ok_or_else(|| ErrorNotFound("Block not found"))?
+                        .serialize_as_vec()
+                        .map_err(ErrorInternalServerError)
+                        .map(Bytes::from)
+                })
+        })
+}
+
+pub fn get_block_header(context: State<Context>, block_id_hex: Path<String>) -> ActixFuture!() {
+    context
+        .try_full()
+        .and_then(|context| parse_block_hash(&block_id_hex).map(|block_id| (context, block_id)))
+        .into_future()
+        .and_then(|(context, block_id)| {
+            context
+                .blockchain
+                .storage()
+                .get(block_id)
+                .map_err(|e| ErrorInternalServerError(e))
+                .and_then(|block_opt| {
+                    block_opt
+                        .ok_or_else(|| ErrorNotFound("Block not found"))?
+                        .header
+                        .serialize_as_vec()
+                        .map_err(ErrorInternalServerError)
+                        .map(Bytes::from)
+                })
+        })
+}
+
+fn parse_block_hash(hex: &str) -> Result<Hash, Error> {
+    Blake2b256::from_str(hex)
+        .map_err(|e| ErrorBadRequest(e))
+        .map(Into::into)
+}
+
+pub fn get_block_next_id(
+    context: State<Context>,
+    block_id_hex: Path<String>,
+    query_params: Query<QueryParams>,
+) -> ActixFuture!() {
+    context
+        .try_full()
+        .and_then(|context| parse_block_hash(&block_id_hex).map(|block_id| (context, block_id)))
+        .into_future()
+        .and_then(|(context, block_id)| {
+            chain_tip_fut_raw(&context).and_then(move |tip| {
+                context
+                    .blockchain
+                    .storage()
+                    .stream_from_to(block_id, tip.hash())
+                    .then(|res| match res {
+                        Ok(stream) => Ok(stream.map_err(ErrorInternalServerError)),
+                        Err(e) => match e {
+                            StorageError::CannotIterate => {
+                                Err(ErrorNotFound("Block is not in chain of the tip"))
+                            }
+                            StorageError::BlockNotFound => Err(ErrorNotFound(e)),
+                            _ => Err(ErrorInternalServerError(e)),
+                        },
+                    })
+            })
+        })
+        .flatten_stream()
+        .take(query_params.get_count())
+        .fold(Bytes::new(), |mut bytes, block| {
+            bytes.extend_from_slice(block.id().as_ref());
+            Result::<Bytes, Error>::Ok(bytes)
+        })
+}
+
+const MAX_COUNT: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    count: Option<u64>,
+}
+
+impl QueryParams {
+    pub fn get_count(&self) -> u64 {
+        self.count.unwrap_or(1).min(MAX_COUNT)
+    }
+}
+
+pub fn get_stake_distribution(context: State<Context>) -> ActixFuture!() {
+    chain_tip_fut(&context).map(|blockchain_tip| {
+        let leadership = blockchain_tip.epoch_leadership_schedule();
+        let last_epoch = blockchain_tip.block_date().epoch;
+        if let LeadershipConsensus::GenesisPraos(gp) = leadership.consensus() {
+            let stake = gp.distribution();
+            let pools: Vec<_> = stake
+                .to_pools
+                .iter()
+                .map(|(h, p)| (format!("{}", h), p.total.total_stake.0))
+                .collect();
+            Json(json!({
+                "epoch": last_epoch,
+                "stake": {
+                    "unassigned": stake.unassigned.0,
+                    "dangling": stake.dangling.0,
+                    "pools": pools,
+                }
+            }))
+        } else {
+            Json(json!({ "epoch": last_epoch }))
+        }
+    })
+}
+
+/// the stake distribution of the current epoch with the stake of each
+/// delegator, as written by the stake snapshots at the transition to it
+pub fn get_stake_snapshot(context: State<Context>) -> ActixFuture!() {
+    chain_tip_fut(&context).and_then(|blockchain_tip| {
+        let epoch = blockchain_tip.block_date().epoch;
+        stake_snapshot::snapshot(epoch, blockchain_tip.epoch_leadership_schedule())
+            .map(Json)
+            .ok_or_else(|| ErrorNotFound("no stake distribution in the BFT consensus"))
+    })
+}
+
+pub fn get_update_proposals(context: State<Context>) -> ActixFuture!() {
+    chain_tip_fut(&context).map(|tip| Json(tip.governance().to_update_proposals()))
+}
+
+pub fn get_clock(context: State<Context>) -> ActixFuture!() {
+    context
+        .try_full_fut()
+        .and_then(|context| chain_tip_fut_raw(&context).map(move |tip| (context, tip)))
+        .map(|(context, tip)| {
+            let now = SystemTime::now();
+            let time_frame = tip.time_frame();
+            let slot_duration = time_frame.slot_duration();
+            // none before the start of the blockchain
+            let current = time_frame.slot_at(now.as_ref()).and_then(|slot| {
+                let position = tip
+                    .epoch_leadership_schedule()
+                    .era()
+                    .from_slot_to_era(slot)?;
+                let start = time_frame.slot_to_systemtime(slot)?;
+                Some((position.epoch.0, position.slot.0, start))
+            });
+            // the drift measured by the monitoring of the clock, the time
+            // servers are not queried on the requests
+            let clock_drift_ms = context.stats_counter.clock_drift_ms();
+            Json(json!({
+                "currentTime": now,
+                "epoch": current.map(|(epoch, _, _)| epoch),
+                "slot": current.map(|(_, slot, _)| slot),
+                "slotStartTime": current.map(|(_, _, start)| SystemTime::from(start)),
+                "slotEndTime": current.map(|(_, _, start)| {
+                    SystemTime::from(start + Duration::from_secs(slot_duration))
+                }),
+                "slotDuration": slot_duration,
+                "clockDriftMillis": clock_drift_ms,
+            }))
+        })
+}
+
+pub fn get_settings(context: State<Context>) -> ActixFuture!() {
+    context
+        .try_full_fut()
+        .and_then(|context| chain_tip_fut_raw(&context).map(move |tip| (context, tip)))
+        .map(|(context, blockchain_tip)| {
+            let ledger = blockchain_tip.ledger();
+            let static_params = ledger.get_static_parameters();
+            let consensus_version = ledger.consensus_version();
+            let current_params = blockchain_tip.epoch_ledger_parameters();
+            let fees = current_params.fees;
+            let slot_duration = blockchain_tip.time_frame().slot_duration();
+            let slots_per_epoch = blockchain_tip
+                .epoch_leadership_schedule()
+                .era()
+                .slots_per_epoch();
+            Json(json!({
+                "block0Hash": static_params.block0_initial_hash.to_string(),
+                "block0Time": SystemTime::from_secs_since_epoch(static_params.block0_start_time.0),
+                "currSlotStartTime": context.stats_counter.slot_start_time().map(SystemTime::from),
+                "consensusVersion": consensus_version.to_string(),
+                "fees":{
+                    "constant": fees.constant,
+                    "coefficient": fees.coefficient,
+                    "certificate": fees.certificate,
+                },
+                "maxTxsPerBlock": 255, // TODO?
+                "slotDuration": slot_duration,
+                "slotsPerEpoch": slots_per_epoch,
+            }))
+        })
+}
+
+#[derive(Deserialize)]
+pub struct FeeEstimateParams {
+    inputs: Option<u8>,
+    outputs: Option<u8>,
+    #[serde(default)]
+    certificate: bool,
+}
+
+/// the fee of a transaction of the given shape, one input and one output by
+/// default, at the tip. The fragments are selected into the blocks from the
+/// oldest, so the fee does not get a fragment in a block sooner: the
+/// congestion of the pool only tells how many blocks it waits for, assuming
+/// the blocks are full.
+pub fn get_fee_estimate(
+    context: State<Context>,
+    params: Query<FeeEstimateParams>,
+) -> ActixFuture!() {
+    let timeout = context.timeouts().query;
+    context
+        .try_full()
+        .and_then(|full_context| Ok((full_context, context.logger()?)))
+        .into_future()
+        .and_then(move |(full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            let pending = full_context
+                .transaction_task
+                .clone()
+                .try_send(TransactionMsg::CountPending(reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(ErrorInternalServerError))
+                });
+            chain_tip_fut_raw(&full_context)
+                .join(pending)
+                .map(move |(tip_reference, pending)| {
+                    let fees = tip_reference.epoch_ledger_parameters().fees;
+                    let inputs = params.inputs.unwrap_or(1);
+                    let outputs = params.outputs.unwrap_or(1);
+                    let mut fee = fees.calculate(None, inputs, outputs).0;
+                    if params.certificate {
+                        fee += fees.certificate;
+                    }
+                    Json(json!({
+                        "fee": fee,
+                        "fees": {
+                            "constant": fees.constant,
+                            "coefficient": fees.coefficient,
+                            "certificate": fees.certificate,
+                        },
+                        "pendingFragments": pending,
+                        "maxFragmentsPerBlock": MAX_PER_BLOCK,
+                        "blocksToInclusion": pending / MAX_PER_BLOCK + 1,
+                    }))
+                })
+        })
+}
+
+pub fn shutdown(
+    request: HttpRequest<Context>,
+    context: State<Context>,
+) -> Result<impl Responder, Error> {
+    context.check_admin_token(authorization_header(&request))?;
+    // Server finishes ongoing tasks before stopping, so user will get response to this request
+    // Node should be shutdown automatically when server stopping is finished
+    context.try_full_any_state()?;
+    context.server()?.stop();
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn get_leaders(context: State<Context>) -> Result<impl Responder, Error> {
+    Ok(Json(json! {
+        context.try_full_any_state()?.enclave.get_leaderids()
+    }))
+}
+
+fn authorization_header(request: &HttpRequest<Context>) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+}
+
+pub fn post_leaders(
+    request: HttpRequest<Context>,
+    secret: Json<NodeSecret>,
+    context: State<Context>,
+) -> ActixFuture!() {
+    let leader = Leader {
+        bft_leader: secret.bft(),
+        genesis_leader: secret.genesis(),
+    };
+    let timeout = context.timeouts().admin;
+    context
+        .check_admin_token(authorization_header(&request))
+        .and_then(|()| context.try_full_any_state())
+        .and_then(|full_context| context.logger().map(|logger| (full_context, logger)))
+        .into_future()
+        .and_then(move |(full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            full_context
+                .leadership_task
+                .clone()
+                .try_send(LeadershipMsg::AddLeader(leader, reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(ErrorBadRequest))
+                })
+                .map(Json)
+        })
+}
+
+pub fn delete_leaders(
+    request: HttpRequest<Context>,
+    context: State<Context>,
+    leader_id: Path<EnclaveLeaderId>,
+) -> ActixFuture!() {
+    let leader_id = *leader_id;
+    let timeout = context.timeouts().admin;
+    context
+        .check_admin_token(authorization_header(&request))
+        .and_then(|()| context.try_full_any_state())
+        .and_then(|full_context| context.logger().map(|logger| (full_context, logger)))
+        .into_future()
+        .and_then(move |(full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            full_context
+                .leadership_task
+                .clone()
+                .try_send(LeadershipMsg::RemoveLeader(leader_id, reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(ErrorNotFound))
+                })
+                .map(|()| HttpResponse::Ok().finish())
+        })
+}
+
+pub fn post_reload(request: HttpRequest<Context>, context: State<Context>) -> ActixFuture!() {
+    let timeout = context.timeouts().admin;
+    context
+        .check_admin_token(authorization_header(&request))
+        .and_then(|()| context.try_full_any_state())
+        .and_then(|full_context| context.logger().map(|logger| (full_context, logger)))
+        .into_future()
+        .and_then(move |(full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            full_context
+                .reload_task
+                .clone()
+                .try_send(ReloadMsg(reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(ErrorBadRequest))
+                })
+                .map(|report| {
+                    if report.rejected.is_empty() {
+                        HttpResponse::Ok().json(report)
+                    } else {
+                        HttpResponse::Conflict().json(report)
+                    }
+                })
+        })
+}
+
+pub fn get_log_levels(context: State<Context>) -> Result<impl Responder, Error> {
+    Ok(Json(context.log_levels()?.get()))
+}
+
+pub fn put_log_levels(
+    request: HttpRequest<Context>,
+    levels: Json<Vec<OutputLevels>>,
+    context: State<Context>,
+) -> Result<impl Responder, Error> {
+    context.check_admin_token(authorization_header(&request))?;
+    let log_levels = context.log_levels()?;
+    if levels.len() != log_levels.outputs() {
+        return Err(ErrorBadRequest(format!(
+            "expected the levels of {} log outputs",
+            log_levels.outputs()
+        )));
+    }
+    log_levels.set(&levels);
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn get_leaders_logs(context: State<Context>) -> ActixFuture!() {
+    context
+        .try_full_any_state()
+        .into_future()
+        .and_then(|context| {
+            context
+                .leadership_logs
+                .logs()
+                .map(Json)
+                .map_err(|_| ErrorInternalServerError("Failed to get leader logs"))
+        })
+}
+
+pub fn get_stake_pools(context: State<Context>) -> ActixFuture!() {
+    chain_tip_fut(&context).map(|blockchain_tip| {
+        let stake_pool_ids = blockchain_tip
+            .ledger()
+            .delegation()
+            .stake_pool_ids()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        Json(stake_pool_ids)
+    })
+}
+
+pub fn get_stake_pool(context: State<Context>, pool_id_hex: Path<String>) -> ActixFuture!() {
+    PoolId::from_str(&pool_id_hex)
+        .map_err(ErrorBadRequest)
+        .into_future()
+        .and_then(move |pool_id| chain_tip_fut(&context).map(|tip| (tip, pool_id)))
+        .and_then(|(blockchain_tip, pool_id)| {
+            // a retired stake pool is not found, an updated one has its new keys
+            let pool = blockchain_tip
+                .ledger()
+                .delegation()
+                .lookup(&pool_id)
+                .cloned()
+                .ok_or_else(|| ErrorNotFound("stake pool not found"))?;
+            let bech32_keys = |keys: &[PublicKey<Ed25519>]| {
+                keys.iter()
+                    .map(|key| key.to_bech32_str())
+                    .collect::<Vec<_>>()
+            };
+            Ok(Json(json!({
+                "owners": bech32_keys(&pool.owners),
+                "operators": bech32_keys(&pool.operators),
+                "kesPublicKey": pool.keys.kes_public_key.to_bech32_str(),
+                "vrfPublicKey": pool.keys.vrf_public_key.to_bech32_str(),
+            })))
+        })
+}
+
+pub fn get_network_stats(context: State<Context>) -> ActixFuture!() {
+    let timeout = context.timeouts().query;
+    context.try_full_any_state().into_future()
+        .and_then(move |full_context| context.logger().map(|logger| (full_context, logger)))
+        .and_then(|(full_context, logger)| {
+        let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+        full_context
+            .network_task
+            .clone()
+            .try_send(NetworkMsg::PeerStats(reply_handle))
+            .map_err(ErrorInternalServerError)
+            .into_future()
+            .and_then(move |_| {
+                reply_future
+                    .with_timeout(timeout)
+                    .map_err(reply_error(ErrorInternalServerError))
+            })
+            .map(|peer_stats| {
+                let network_stats = peer_stats
+                    .into_iter()
+                    .map(|(node_id, stats)| json! ({
+                        "nodeId": node_id.to_string(),
+                        "establishedAt": SystemTime::from(stats.connection_established()),
+                        "lastBlockReceived": stats.last_block_received().map(SystemTime::from),
+                        "lastFragmentReceived": stats.last_fragment_received().map(SystemTime::from),
+                        "lastGossipReceived": stats.last_gossip_received().map(SystemTime::from),
+                        "malformedGossips": stats.malformed_gossips(),
+                        "throttled": stats.throttled(),
+                        "stalledPulls": stats.stalled_pulls(),
+                    }))
+                    .collect::<Vec<_>>();
+                Json(network_stats)
+            })
+    })
+}
+
+pub fn post_network_bootstrap(
+    request: HttpRequest<Context>,
+    context: State<Context>,
+) -> ActixFuture!() {
+    let timeout = context.timeouts().admin;
+    context
+        .check_admin_token(authorization_header(&request))
+        .and_then(|()| context.try_full_any_state())
+        .and_then(|full_context| context.logger().map(|logger| (full_context, logger)))
+        .into_future()
+        .and_then(move |(full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            full_context
+                .network_task
+                .clone()
+                .try_send(NetworkMsg::Bootstrap(reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(bootstrap_error))
+                })
+                .map(|()| HttpResponse::Ok().finish())
+        })
+}
+
+fn bootstrap_error(error: intercom::Error) -> Error {
+    match error.code() {
+        Code::FailedPrecondition => ErrorConflict(error),
+        _ => ErrorInternalServerError(error),
+    }
+}
+
+/// the chain index, once it follows the tip: the answers of an index still
+/// backfilled would miss the blocks not indexed yet
+fn chain_index(context: &FullContext) -> Result<ChainIndex, Error> {
+    let index = context
+        .blockchain
+        .index()
+        .cloned()
+        .ok_or_else(|| ErrorNotImplemented("the chain index is not enabled"))?;
+    match index.backfill_progress() {
+        None => Ok(index),
+        Some(progress) => Err(ErrorServiceUnavailable(format!(
+            "the chain index is being backfilled, {} of {} blocks indexed",
+            progress.indexed, progress.target
+        ))),
+    }
+}
+
+/// the transaction, the block of the main chain containing it and the
+/// number of blocks on top of this block, the proof of a payment
+pub fn get_transaction(context: State<Context>, fragment_id_hex: Path<String>) -> ActixFuture!() {
+    context
+        .try_full()
+        .and_then(|context| {
+            let fragment_id = parse_fragment_id(&fragment_id_hex)?;
+            let block_id = chain_index(&context)?
+                .transaction_block(&fragment_id)
+                .map_err(ErrorInternalServerError)?
+                .ok_or_else(|| ErrorNotFound("Transaction not found in the main chain"))?;
+            Ok((context, fragment_id, block_id))
+        })
+        .into_future()
+        .and_then(|(context, fragment_id, block_id)| {
+            let block = context
+                .blockchain
+                .storage()
+                .get(block_id)
+                .map_err(ErrorInternalServerError)
+                .and_then(|block| {
+                    block.ok_or_else(|| ErrorInternalServerError("Could not find indexed block"))
+                });
+            // the index may not have rolled the block back yet
+            let storage = context.blockchain.storage().clone();
+            let depth = chain_tip_fut_raw(&context).and_then(move |tip| {
+                storage
+                    .is_ancestor(block_id, tip.hash())
+                    .map_err(ErrorInternalServerError)
+            });
+            block
+                .join(depth)
+                .map(move |(block, depth)| (fragment_id, block, depth))
+        })
+        .and_then(|(fragment_id, block, depth)| {
+            transaction_in_block(&fragment_id, &block, depth).map(Json)
+        })
+}
+
+/// the transaction found in its block, `depth` blocks below the tip, or
+/// `None` if the block is not in the main chain
+fn transaction_in_block(
+    fragment_id: &FragmentId,
+    block: &blockcfg::Block,
+    depth: Option<u64>,
+) -> Result<serde_json::Value, Error> {
+    let depth = depth.ok_or_else(|| ErrorNotFound("Transaction not found in the main chain"))?;
+    let fragment = block
+        .contents
+        .iter()
+        .find(|fragment| fragment.id() == *fragment_id)
+        .ok_or_else(|| ErrorInternalServerError("Transaction not found in its block"))?;
+    let fragment_bytes = fragment
+        .serialize_as_vec()
+        .map_err(ErrorInternalServerError)?;
+    Ok(json!({
+        "transaction": hex::encode(&fragment_bytes),
+        "block": block.id().to_string(),
+        "block_date": block.header.block_date().to_string(),
+        "chain_length": u32::from(block.header.chain_length()),
+        "depth": depth,
+    }))
+}
+
+pub fn get_address_transactions(context: State<Context>, address: Path<String>) -> ActixFuture!() {
+    context
+        .try_full()
+        .and_then(|context| {
+            let address = Address::from_str(&address).map_err(ErrorBadRequest)?;
+            let transactions = chain_index(&context)?
+                .address_transactions(address.as_ref())
+                .map_err(ErrorInternalServerError)?
+                .into_iter()
+                .map(|transaction| {
+                    json!({
+                        "fragment_id": transaction.fragment_id.to_string(),
+                        "block": transaction.block.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(Json(transactions))
+        })
+        .into_future()
+}
+
+/// the balance of an address at the tip, with the change the fragments of
+/// the pool would make to it once included in a block
+pub fn get_address_balance(context: State<Context>, address: Path<String>) -> ActixFuture!() {
+    let timeout = context.timeouts().query;
+    Address::from_str(&address)
+        .map_err(ErrorBadRequest)
+        .and_then(|address| match address.as_ref().kind() {
+            Kind::Multisig(_) => Err(ErrorBadRequest("multisig addresses are not supported")),
+            _ => Ok(address),
+        })
+        .and_then(|address| {
+            let full_context = context.try_full()?;
+            // the outputs sent to an account address credit the account
+            let outputs = match address.as_ref().kind() {
+                Kind::Single(_) | Kind::Group(..) => chain_index(&full_context)?
+                    .address_outputs(address.as_ref())
+                    .map_err(ErrorInternalServerError)?,
+                _ => Vec::new(),
+            };
+            Ok((address, outputs, full_context, context.logger()?))
+        })
+        .into_future()
+        .and_then(move |(address, outputs, full_context, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            let pending = full_context
+                .transaction_task
+                .clone()
+                .try_send(TransactionMsg::GetPending(reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(ErrorInternalServerError))
+                });
+            chain_tip_fut_raw(&full_context)
+                .join(pending)
+                .map(move |(tip_reference, pending)| {
+                    let balance = AddressBalance::new(
+                        tip_reference.ledger(),
+                        address.as_ref(),
+                        &outputs,
+                        &pending,
+                    );
+                    Json(json!({
+                        "confirmed": balance.utxo + balance.account,
+                        "utxo": balance.utxo,
+                        "utxoCount": balance.utxo_count,
+                        "account": balance.account,
+                        "pendingDelta": balance.pending_in as i64 - balance.pending_out as i64,
+                    }))
+                })
+        })
+}
+
+/// the value held by an address: its unspent outputs, and the account of an
+/// account address or of the group key of a group address
+#[derive(Default)]
+struct AddressBalance {
+    utxo: u64,
+    utxo_count: usize,
+    account: u64,
+    /// sent to the address by the fragments of the pool
+    pending_in: u64,
+    /// spent from the address by the fragments of the pool
+    pending_out: u64,
+}
+
+impl AddressBalance {
+    /// the balance of the address, its unspent outputs being among the
+    /// `outputs` sent to it found in the chain index
+    fn new(
+        ledger: &Ledger,
+        address: &chain_addr::Address,
+        outputs: &[(FragmentId, u8)],
+        pending: &[Fragment],
+    ) -> Self {
+        let account = match address.kind() {
+            Kind::Account(key) | Kind::Group(_, key) => Some(Identifier::from(key.clone())),
+            Kind::Single(_) | Kind::Multisig(_) => None,
+        };
+        let mut balance = AddressBalance::default();
+
+        for (fragment_id, index) in outputs {
+            if let Some(output) = ledger.utxo_out(*fragment_id, *index) {
+                balance.utxo += output.value.0;
+                balance.utxo_count += 1;
+            }
+        }
+        if let Some(account) = account.as_ref() {
+            balance.account = ledger
+                .accounts()
+                .get_state(account)
+                .map_or(0, |state| state.value().0);
+        }
+
+        let transactions: Vec<_> = pending
+            .iter()
+            .filter_map(|fragment| {
+                visit_transaction(
+                    fragment,
+                    PendingTransactionOf {
+                        fragment_id: fragment.id(),
+                    },
+                )
+            })
+            .collect();
+        balance.add_pending(address, &account, &transactions, |fragment_id, index| {
+            ledger
+                .utxo_out(fragment_id, index)
+                .map(|output| output.address.clone())
+        });
+        balance
+    }
+
+    /// add the change of the transactions of the pool, `ledger_output`
+    /// giving the address of an unspent output of the ledger
+    fn add_pending<F>(
+        &mut self,
+        address: &chain_addr::Address,
+        account: &Option<Identifier>,
+        transactions: &[PendingTransaction],
+        ledger_output: F,
+    ) where
+        F: Fn(FragmentId, u8) -> Option<chain_addr::Address>,
+    {
+        let of_account = |id: &Identifier| account.as_ref() == Some(id);
+        // the outputs of the pool sent to the address, which other
+        // transactions of the pool may already spend
+        let mut pending_outputs = HashSet::new();
+        for tx in transactions {
+            for (index, (output_address, value)) in tx.outputs.iter().enumerate() {
+                match output_address.kind() {
+                    Kind::Account(key) => {
+                        if of_account(&Identifier::from(key.clone())) {
+                            self.pending_in += value;
+                        }
+                    }
+                    _ => {
+                        if output_address == address {
+                            self.pending_in += value;
+                            if let Ok(index) = u8::try_from(index) {
+                                pending_outputs.insert((tx.fragment_id, index));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for tx in transactions {
+            for input in tx.inputs.iter() {
+                match input {
+                    InputEnum::AccountInput(id, value) => {
+                        if id.to_single_account().map_or(false, |id| of_account(&id)) {
+                            self.pending_out += value.0;
+                        }
+                    }
+                    InputEnum::UtxoInput(pointer) => {
+                        let output = (pointer.transaction_id, pointer.output_index);
+                        let spent = pending_outputs.contains(&output)
+                            || ledger_output(output.0, output.1)
+                                .map_or(false, |output_address| output_address == *address);
+                        if spent {
+                            self.pending_out += pointer.value.0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// the inputs and outputs of a transaction of the pool
+struct PendingTransaction {
+    fragment_id: FragmentId,
+    inputs: Vec<InputEnum>,
+    outputs: Vec<(chain_addr::Address, u64)>,
+}
+
+struct PendingTransactionOf {
+    fragment_id: FragmentId,
+}
+
+impl TransactionVisitor for PendingTransactionOf {
+    type Output = PendingTransaction;
+
+    fn visit<P: Payload + Debug>(self, tx: &Transaction<P>) -> PendingTransaction {
+        let tx = tx.as_slice();
+        PendingTransaction {
+            fragment_id: self.fragment_id,
+            inputs: tx.inputs().iter().map(|input| input.to_enum()).collect(),
+            outputs: tx
+                .outputs()
+                .iter()
+                .map(|output| (output.address.clone(), output.value.0))
+                .collect(),
+        }
+    }
+}
+
+fn rewards_task(context: &FullContext) -> Result<MessageBox<RewardsMsg>, Error> {
+    context
+        .rewards_task
+        .clone()
+        .ok_or_else(|| ErrorNotImplemented("the storage does not keep the rewards history"))
+}
+
+/// the error of a reply from a task of the node, with the gateway timeout
+/// status if the task did not reply in time
+fn reply_error<F>(error_fn: F) -> impl Fn(intercom::Error) -> Error
+where
+    F: Fn(intercom::Error) -> Error,
+{
+    move |error| match error.code() {
+        Code::DeadlineExceeded => ErrorGatewayTimeout(error),
+        _ => error_fn(error),
+    }
+}
+
+fn rewards_error(error: intercom::Error) -> Error {
+    match error.code() {
+        Code::NotFound => ErrorNotFound(error),
+        _ => ErrorInternalServerError(error),
+    }
+}
+
+pub fn get_epoch_rewards(context: State<Context>, epoch: Path<u32>) -> ActixFuture!() {
+    let epoch = *epoch;
+    let timeout = context.timeouts().query;
+    context
+        .try_full()
+        .and_then(|full_context| rewards_task(&full_context))
+        .and_then(|rewards_task| context.logger().map(|logger| (rewards_task, logger)))
+        .into_future()
+        .and_then(move |(mut rewards_task, logger)| {
+            let (reply_handle, reply_future) = intercom::unary_reply::<_, intercom::Error>(logger);
+            rewards_task
+                .try_send(RewardsMsg::GetEpoch(epoch, reply_handle))
+                .map_err(ErrorInternalServerError)
+                .into_future()
+                .and_then(move |_| {
+                    reply_future
+                        .with_timeout(timeout)
+                        .map_err(reply_error(rewards_error))
+                })
+                .map(Json)
+        })
+}
+
+pub fn get_account_rewards(
+    context: State<Context>,
+    account_id_hex: Path<String>,
+) -> ActixFuture!() {
+    let timeout = context.timeouts().query;
+    parse_account_id(&account_id_hex)
+        .and_then(|account_id| {
+            let full_context = context.try_full()?;
+            let rewards_task = rewards_task(&full_context)?;
+            let logger = context.logger()?;
+            Ok((account_id, full_context, rewards_task, logger))
+        })
+        .into_future()
+        .and_then(
+            move |(account_id, full_context, mut rewards_task, logger)| {
+                let (reply_handle, reply_future) =
+                    intercom::unary_reply::<_, intercom::Error>(logger);
+                // the history has no records past the epoch of the tip
+                chain_tip_fut_raw(&full_context)
+                    .and_then(move |tip| {
+                        let last_epoch = tip.block_date().epoch;
+                        rewards_task
+                            .try_send(RewardsMsg::GetAccount(account_id, last_epoch, reply_handle))
+                            .map_err(ErrorInternalServerError)
+                    })
+                    .and_then(move |_| {
+                        reply_future
+                            .with_timeout(timeout)
+                            .map_err(reply_error(rewards_error))
+                    })
+                    .map(Json)
+            },
+        )
+}
+
+#[derive(Deserialize)]
+pub struct SubscriptionRequest {
+    addresses: Vec<Address>,
+}
+
+pub fn post_subscription(
+    context: State<Context>,
+    request: Json<SubscriptionRequest>,
+) -> Result<impl Responder, Error> {
+    let context = context.try_full()?;
+    chain_index(&context)?;
+    if request.addresses.is_empty() {
+        return Err(ErrorBadRequest("no addresses to watch"));
+    }
+    let addresses = request
+        .into_inner()
+        .addresses
+        .into_iter()
+        .map(|address| address.as_ref().clone())
+        .collect();
+    let id = context
+        .blockchain
+        .subscriptions()
+        .subscribe(addresses)
+        .map_err(|e| match e {
+            SubscribeError::TooManySubscriptions => ErrorTooManyRequests(e),
+            SubscribeError::TooManyAddresses => ErrorBadRequest(e),
+        })?;
+    Ok(Json(json!({ "id": id })))
+}
+
+#[derive(Deserialize)]
+pub struct SubscriptionEventsParams {
+    after: Option<u64>,
+}
+
+pub fn get_subscription_events(
+    context: State<Context>,
+    id: Path<SubscriptionId>,
+    params: Query<SubscriptionEventsParams>,
+) -> Result<impl Responder, Error> {
+    let events = context
+        .try_full()?
+        .blockchain
+        .subscriptions()
+        .poll(&id, params.after.unwrap_or(0))
+        .ok_or_else(|| ErrorNotFound("no such subscription"))?;
+    Ok(Json(events))
+}
+
+pub fn delete_subscription(
+    context: State<Context>,
+    id: Path<SubscriptionId>,
+) -> Result<impl Responder, Error> {
+    if context
+        .try_full()?
+        .blockchain
+        .subscriptions()
+        .unsubscribe(&id)
+    {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Err(ErrorNotFound("no such subscription"))
+    }
+}
+
+pub fn get_utxo(context: State<Context>, path_params: Path<(String, u8)>) -> ActixFuture!() {
+    let (fragment_id_hex, output_index) = path_params.into_inner();
+    parse_fragment_id(&fragment_id_hex)
+        .into_future()
+        .and_then(move |fragment_id| {
+            chain_tip_fut(&context).and_then(move |tip_reference| {
+                let output = tip_reference
+                    .ledger()
+                    .utxo_out(fragment_id, output_index)
+                    .ok_or_else(|| {
+                        ErrorNotFound(format!(
+                            "no UTxO found for address '{}' on index {}",
+                            fragment_id_hex, output_index
+                        ))
+                    })?;
+                Ok(Json(json!({
+                    "address": Address::from(output.address.clone()),
+                    "value": output.value.0,
+                })))
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, ContentsBuilder, Header};
+    use chain_addr::{Address, Discrimination};
+    use chain_crypto::SecretKey;
+    use chain_impl_mockchain::{
+        block,
+        fragment::config::ConfigParams,
+        transaction::{UnspecifiedAccountIdentifier, UtxoPointer},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::convert::Infallible;
+
+    fn public_key(seed: u8) -> PublicKey<Ed25519> {
+        SecretKey::<Ed25519>::generate(&mut StdRng::from_seed([seed; 32])).to_public()
+    }
+
+    fn single(seed: u8) -> Address {
+        Address(Discrimination::Test, Kind::Single(public_key(seed)))
+    }
+
+    fn account(seed: u8) -> Address {
+        Address(Discrimination::Test, Kind::Account(public_key(seed)))
+    }
+
+    fn fragment_id(seed: u8) -> FragmentId {
+        jormungandr_lib::crypto::hash::Hash::from([seed; 32]).into_hash()
+    }
+
+    fn utxo_input(fragment_id: FragmentId, output_index: u8, value: u64) -> InputEnum {
+        InputEnum::UtxoInput(UtxoPointer {
+            transaction_id: fragment_id,
+            output_index,
+            value: Value(value),
+        })
+    }
+
+    fn pending(
+        address: &Address,
+        transactions: &[PendingTransaction],
+        ledger_output: impl Fn(FragmentId, u8) -> Option<Address>,
+    ) -> AddressBalance {
+        let account = match address.kind() {
+            Kind::Account(key) => Some(Identifier::from(key.clone())),
+            _ => None,
+        };
+        let mut balance = AddressBalance::default();
+        balance.add_pending(address, &account, transactions, ledger_output);
+        balance
+    }
+
+    fn block_of(fragment: Fragment) -> blockcfg::Block {
+        let mut contents = ContentsBuilder::new();
+        contents.push(fragment);
+        block::builder(BlockVersion::Genesis, contents.into(), |hdr| {
+            let header: Result<Header, Infallible> = Ok(hdr
+                .set_genesis()
+                .set_date(BlockDate {
+                    epoch: 0,
+                    slot_id: 0,
+                })
+                .to_unsigned_header()
+                .unwrap()
+                .generalize());
+            header
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn transaction_has_the_depth_of_its_block() {
+        let fragment = Fragment::Initial(ConfigParams::new());
+        let block = block_of(fragment.clone());
+
+        let json = transaction_in_block(&fragment.id(), &block, Some(3)).unwrap();
+
+        assert_eq!(json["block"], block.id().to_string());
+        assert_eq!(json["chain_length"], 0);
+        assert_eq!(json["depth"], 3);
+        assert_eq!(
+            json["transaction"],
+            hex::encode(fragment.serialize_as_vec().unwrap())
+        );
+    }
+
+    #[test]
+    fn transaction_of_a_block_out_of_the_main_chain_is_not_found() {
+        let fragment = Fragment::Initial(ConfigParams::new());
+        let block = block_of(fragment.clone());
+
+        let error = transaction_in_block(&fragment.id(), &block, None).unwrap_err();
+
+        assert_eq!(
+            error.as_response_error().error_response().status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn pending_outputs_and_spent_ledger_outputs_are_counted() {
+        let address = single(1);
+        let confirmed = fragment_id(1);
+        let transactions = [PendingTransaction {
+            fragment_id: fragment_id(2),
+            inputs: vec![utxo_input(confirmed, 0, 100), utxo_input(confirmed, 1, 7)],
+            outputs: vec![(single(2), 60), (address.clone(), 40)],
+        }];
+        let balance = pending(&address, &transactions, |_, index| match index {
+            0 => Some(single(1)),
+            _ => Some(single(2)),
+        });
+        assert_eq!(balance.pending_in, 40);
+        assert_eq!(balance.pending_out, 100);
+    }
+
+    #[test]
+    fn chained_pending_transactions_are_counted() {
+        let address = single(1);
+        let first = fragment_id(2);
+        // the second transaction spends the change of the first one, which
+        // is not in the ledger yet
+        let transactions = [
+            PendingTransaction {
+                fragment_id: fragment_id(3),
+                inputs: vec![utxo_input(first, 1, 40)],
+                outputs: vec![(single(2), 40)],
+            },
+            PendingTransaction {
+                fragment_id: first,
+                inputs: vec![utxo_input(fragment_id(1), 0, 100)],
+                outputs: vec![(single(2), 60), (address.clone(), 40)],
+            },
+        ];
+        let balance = pending(&address, &transactions, |_, _| Some(single(1)));
+        assert_eq!(balance.pending_in, 40);
+        assert_eq!(balance.pending_out, 140);
+    }
+
+    #[test]
+    fn account_change_is_counted() {
+        let address = account(1);
+        let id = Identifier::from(public_key(1));
+        let transactions = [PendingTransaction {
+            fragment_id: fragment_id(2),
+            inputs: vec![InputEnum::AccountInput(
+                UnspecifiedAccountIdentifier::from_single_account(id),
+                Value(30),
+            )],
+            outputs: vec![(account(2), 20), (account(1), 5), (single(1), 5)],
+        }];
+        let balance = pending(&address, &transactions, |_, _| None);
+        assert_eq!(balance.pending_in, 5);
+        assert_eq!(balance.pending_out, 30);
+    }
+
+    #[test]
+    fn fragments_are_refused_while_the_space_is_low() {
+        let disk_guard = DiskGuard::default();
+        let stats_counter = StatsCounter::default();
+        assert!(check_disk_space(&disk_guard, &stats_counter).is_ok());
+        assert!(stats_counter.fragments_rejected().is_empty());
+
+        disk_guard.set_low(true);
+        let error = check_disk_space(&disk_guard, &stats_counter).unwrap_err();
+
+        assert_eq!(
+            error.as_response_error().error_response().status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(stats_counter.fragments_rejected(), vec![("disk_space", 1)]);
+    }
+
+    mod admin_token {
+        use super::*;
+        use crate::lifecycle::Lifecycle;
+        use actix_web::{http::StatusCode, test::TestRequest, FromRequest as _};
+        use slog::Logger;
+
+        fn context(admin_token: Option<&str>) -> Context {
+            let context = Context::new(Lifecycle::new(Logger::root(slog::Discard, o!())));
+            context.set_admin_token(admin_token.map(str::to_owned));
+            context
+        }
+
+        fn shutdown_status(context: Context, authorization: Option<&str>) -> StatusCode {
+            let mut request = TestRequest::with_state(context);
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let request = request.finish();
+            let state = State::<Context>::extract(&request);
+            match shutdown(request, state) {
+                Ok(_) => StatusCode::OK,
+                Err(error) => error.as_response_error().error_response().status(),
+            }
+        }
+
+        #[test]
+        fn admin_endpoints_are_refused_without_a_configured_token() {
+            assert_eq!(shutdown_status(context(None), None), StatusCode::FORBIDDEN);
+            assert_eq!(
+                shutdown_status(context(None), Some("Bearer anything")),
+                StatusCode::FORBIDDEN
+            );
+        }
+
+        #[test]
+        fn admin_endpoints_check_the_configured_token() {
+            let token = Some("secret");
+            assert_eq!(
+                shutdown_status(context(token), None),
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                shutdown_status(context(token), Some("Bearer wrong")),
+                StatusCode::UNAUTHORIZED
+            );
+            // past the token check, the node is not started in the test
+            assert_eq!(
+                shutdown_status(context(token), Some("Bearer secret")),
+                StatusCode::SERVICE_UNAVAILABLE
+            );
+        }
+    }
+}
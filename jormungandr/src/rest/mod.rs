@@ -1,48 +1,122 @@
 //! REST API of the node
+//!
+//! The API is served under one prefix per version, `/api/v0` and `/api/v1`,
+//! the handlers being shared by the versions. The breaking changes only ship
+//! in the latest version, the routes of the older ones stay as they are and
+//! their responses carry a `Deprecation` header linking to the next version.
 
+mod handlers;
 mod server;
 
 pub mod explorer;
 pub mod v0;
+pub mod v1;
 
-pub use self::server::{Error, Server};
+pub use self::server::{load_tls_acceptor, Error, Server};
 
 use actix_web::dev::Resource;
-use actix_web::error::{Error as ActixError, ErrorInternalServerError, ErrorServiceUnavailable};
-use actix_web::middleware::cors::Cors;
+use actix_web::error::{
+    Error as ActixError, ErrorForbidden, ErrorInternalServerError, ErrorServiceUnavailable,
+    ErrorUnauthorized,
+};
+use actix_web::middleware::{cors::Cors, DefaultHeaders};
 use actix_web::App;
 
 use futures::{Future, IntoFuture};
 use slog::Logger;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crate::blockchain::{Blockchain, Tip};
+use crate::disk_space::DiskGuard;
 use crate::fragment::Logs;
 use crate::leadership::Logs as LeadershipLogs;
+use crate::lifecycle::Lifecycle;
 use crate::secure::enclave::Enclave;
+use crate::settings::logging::LogLevels;
 use crate::settings::start::{Cors as CorsConfig, Error as ConfigError, Rest};
 use crate::stats_counter::StatsCounter;
 
-use crate::intercom::{NetworkMsg, TransactionMsg};
+use crate::intercom::{LeadershipMsg, NetworkMsg, ReloadMsg, RewardsMsg, TransactionMsg};
 use crate::utils::async_msg::MessageBox;
 
-use jormungandr_lib::interfaces::NodeState;
+use jormungandr_lib::interfaces::{FragmentLimits, NodeState};
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_ADMIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// the time the handlers wait for the reply of a task of the node
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub query: Duration,
+    pub admin: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            query: DEFAULT_QUERY_TIMEOUT,
+            admin: DEFAULT_ADMIN_TIMEOUT,
+        }
+    }
+}
+
+/// the versions of the REST API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V0,
+    V1,
+}
+
+impl ApiVersion {
+    fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V0 => "/api/v0",
+            ApiVersion::V1 => "/api/v1",
+        }
+    }
+
+    fn resources(self) -> Vec<(&'static str, &'static dyn Fn(&mut Resource<Context>))> {
+        match self {
+            ApiVersion::V0 => v0::resources(),
+            ApiVersion::V1 => v1::resources(),
+        }
+    }
+
+    /// the version replacing this one, none for the latest version
+    fn successor(self) -> Option<ApiVersion> {
+        match self {
+            ApiVersion::V0 => Some(ApiVersion::V1),
+            ApiVersion::V1 => None,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Context {
+    api_version: ApiVersion,
     full: Arc<RwLock<Option<Arc<FullContext>>>>,
     server: Arc<RwLock<Option<Arc<Server>>>>,
-    node_state: Arc<RwLock<NodeState>>,
+    lifecycle: Lifecycle,
     logger: Arc<RwLock<Option<Logger>>>,
+    admin_token: Arc<RwLock<Option<String>>>,
+    timeouts: Arc<RwLock<Timeouts>>,
+    log_levels: Arc<RwLock<Option<LogLevels>>>,
 }
 
 impl Context {
-    pub fn new() -> Self {
+    pub fn new(lifecycle: Lifecycle) -> Self {
         Context {
+            api_version: ApiVersion::V1,
             full: Default::default(),
             server: Default::default(),
-            node_state: Arc::new(RwLock::new(NodeState::StartingRestServer)),
+            lifecycle,
             logger: Default::default(),
+            admin_token: Default::default(),
+            timeouts: Default::default(),
+            log_levels: Default::default(),
         }
     }
 
@@ -50,11 +124,39 @@ impl Context {
         *self.full.write().expect("Context state poisoned") = Some(Arc::new(full_context));
     }
 
+    /// the context of the handlers of the given version of the API
+    fn with_api_version(&self, api_version: ApiVersion) -> Self {
+        Context {
+            api_version,
+            ..self.clone()
+        }
+    }
+
+    /// the version of the API the request is handled for
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
     pub fn try_full_fut(&self) -> impl Future<Item = Arc<FullContext>, Error = ActixError> {
         self.try_full().into_future()
     }
 
+    /// the full context, once the node is ready to serve the state of the
+    /// blockchain
     pub fn try_full(&self) -> Result<Arc<FullContext>, ActixError> {
+        let node_state = self.node_state();
+        if node_state != NodeState::Running {
+            return Err(ErrorServiceUnavailable(format!(
+                "The node is not ready: {:?}",
+                node_state
+            )));
+        }
+        self.try_full_any_state()
+    }
+
+    /// the full context, as soon as the tasks of the node are started, for
+    /// the monitoring and the administration of the node while it is syncing
+    pub fn try_full_any_state(&self) -> Result<Arc<FullContext>, ActixError> {
         self.full
             .read()
             .expect("Context state poisoned")
@@ -74,18 +176,8 @@ impl Context {
             .ok_or_else(|| ErrorInternalServerError("Server not set in  REST context"))
     }
 
-    pub fn set_node_state(&self, node_state: NodeState) {
-        *self
-            .node_state
-            .write()
-            .expect("Context node state poisoned") = node_state;
-    }
-
     pub fn node_state(&self) -> NodeState {
-        self.node_state
-            .read()
-            .expect("Context node state poisoned")
-            .clone()
+        self.lifecycle.state()
     }
 
     pub fn set_logger(&self, logger: Logger) {
@@ -99,6 +191,69 @@ impl Context {
             .clone()
             .ok_or_else(|| ErrorInternalServerError("Logger not set in  REST context"))
     }
+
+    pub fn set_log_levels(&self, log_levels: LogLevels) {
+        *self
+            .log_levels
+            .write()
+            .expect("Context log levels poisoned") = Some(log_levels);
+    }
+
+    pub fn log_levels(&self) -> Result<LogLevels, ActixError> {
+        self.log_levels
+            .read()
+            .expect("Context log levels poisoned")
+            .clone()
+            .ok_or_else(|| ErrorInternalServerError("Log levels not set in REST context"))
+    }
+
+    fn set_admin_token(&self, admin_token: Option<String>) {
+        *self
+            .admin_token
+            .write()
+            .expect("Context admin token poisoned") = admin_token;
+    }
+
+    fn set_timeouts(&self, timeouts: Timeouts) {
+        *self.timeouts.write().expect("Context timeouts poisoned") = timeouts;
+    }
+
+    /// the time to wait for the reply of a task of the node
+    pub fn timeouts(&self) -> Timeouts {
+        *self.timeouts.read().expect("Context timeouts poisoned")
+    }
+
+    /// Checks the bearer token of a request made to an administrative
+    /// endpoint. If no token is configured, all requests are refused.
+    pub fn check_admin_token(&self, authorization: Option<&str>) -> Result<(), ActixError> {
+        let admin_token = self
+            .admin_token
+            .read()
+            .expect("Context admin token poisoned");
+        let expected = match admin_token.as_ref() {
+            None => {
+                return Err(ErrorForbidden(
+                    "The administrative endpoints are disabled, set `rest.admin_token` to enable them",
+                ))
+            }
+            Some(token) => token,
+        };
+        let provided = authorization
+            .map(str::trim)
+            .filter(|value| value.starts_with(BEARER_PREFIX))
+            .map(|value| &value[BEARER_PREFIX.len()..])
+            .ok_or_else(|| ErrorUnauthorized("Missing bearer token"))?;
+        if constant_time_eq(provided.trim().as_bytes(), expected.as_bytes()) {
+            Ok(())
+        } else {
+            Err(ErrorUnauthorized("Invalid bearer token"))
+        }
+    }
+}
+
+// compare the tokens without leaking the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 #[derive(Clone)]
@@ -108,10 +263,20 @@ pub struct FullContext {
     pub blockchain_tip: Tip,
     pub network_task: MessageBox<NetworkMsg>,
     pub transaction_task: MessageBox<TransactionMsg>,
+    pub leadership_task: MessageBox<LeadershipMsg>,
+    pub reload_task: MessageBox<ReloadMsg>,
+    /// none when the storage does not keep the rewards history
+    pub rewards_task: Option<MessageBox<RewardsMsg>>,
     pub logs: Logs,
+    /// the limits of the fragments posted, checked before they are sent to
+    /// the fragment task
+    pub fragment_limits: FragmentLimits,
     pub leadership_logs: LeadershipLogs,
     pub enclave: Enclave,
     pub explorer: Option<crate::explorer::Explorer>,
+    /// the posted fragments are refused while the storage volume is almost
+    /// full
+    pub disk_guard: DiskGuard,
 }
 
 pub fn run_rest_server(
@@ -119,20 +284,28 @@ pub fn run_rest_server(
     explorer_enabled: bool,
     context: Context,
 ) -> Result<(), ConfigError> {
+    context.set_admin_token(config.admin_token);
+    context.set_timeouts(Timeouts {
+        query: config
+            .query_timeout
+            .map(Into::into)
+            .unwrap_or(DEFAULT_QUERY_TIMEOUT),
+        admin: config
+            .admin_timeout
+            .map(Into::into)
+            .unwrap_or(DEFAULT_ADMIN_TIMEOUT),
+    });
     let app_context = context.clone();
     let cors_cfg = config.cors;
     let handlers = move || {
-        let mut apps = vec![build_app(
-            app_context.clone(),
-            "/api/v0",
-            v0::resources(),
-            &cors_cfg,
-        )];
+        let mut apps: Vec<_> = [ApiVersion::V0, ApiVersion::V1]
+            .iter()
+            .map(|&version| build_api_app(&app_context, version, &cors_cfg))
+            .collect();
 
         if explorer_enabled {
             apps.push(build_app(
-                app_context.clone(),
-                "/explorer",
+                App::with_state(app_context.clone()).prefix("/explorer"),
                 explorer::resources(),
                 &cors_cfg,
             ))
@@ -141,16 +314,36 @@ pub fn run_rest_server(
         apps
     };
     let server_receiver = move |server| context.set_server(server);
-    Server::run(config.pkcs12, config.listen, handlers, server_receiver).map_err(Into::into)
+    Server::run(
+        config.pkcs12,
+        config.listen,
+        config.unix_socket,
+        handlers,
+        server_receiver,
+    )
+    .map_err(Into::into)
+}
+
+fn build_api_app(
+    context: &Context,
+    version: ApiVersion,
+    cors_cfg: &Option<CorsConfig>,
+) -> App<Context> {
+    let mut app = App::with_state(context.with_api_version(version)).prefix(version.prefix());
+    if let Some(successor) = version.successor() {
+        app = app.middleware(DefaultHeaders::new().header("Deprecation", "true").header(
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", successor.prefix()),
+        ));
+    }
+    build_app(app, version.resources(), cors_cfg)
 }
 
-fn build_app<S, P, R>(state: S, prefix: P, resources: R, cors_cfg: &Option<CorsConfig>) -> App<S>
+fn build_app<S, R>(app: App<S>, resources: R, cors_cfg: &Option<CorsConfig>) -> App<S>
 where
     S: 'static,
-    P: Into<String>,
     R: IntoIterator<Item = (&'static str, &'static dyn Fn(&mut Resource<S>))>,
 {
-    let app = App::with_state(state).prefix(prefix);
     match cors_cfg {
         Some(cors_cfg) => register_resources_with_cors(app, resources, cors_cfg),
         None => register_resources(app, resources),
@@ -2,12 +2,18 @@ use native_tls::Error as TlsError;
 use std::error::Error as StdError;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::Error as IoError;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum Error {
     Pkcs12LoadFailed(IoError),
     Pkcs12Invalid(TlsError),
     BindFailed(IoError),
+    UnixSocketBindFailed(IoError),
+    UnixSocketPathTaken(PathBuf),
+    UnixSocketInUse(PathBuf),
+    UnixSocketUnsupported,
+    NoListener,
     ServerAlreadyStopped,
     ServerStopTimeout,
     ServerStopFailed,
@@ -19,6 +25,21 @@ impl Display for Error {
             Error::Pkcs12LoadFailed(_) => write!(f, "Failed to load PKCS12 identity file"),
             Error::Pkcs12Invalid(_) => write!(f, "Invalid PKCS12 identity file"),
             Error::BindFailed(_) => write!(f, "Failed to bind the port"),
+            Error::UnixSocketBindFailed(_) => write!(f, "Failed to bind the unix domain socket"),
+            Error::UnixSocketPathTaken(ref path) => write!(
+                f,
+                "The path of the unix domain socket {} is taken by another file",
+                path.display()
+            ),
+            Error::UnixSocketInUse(ref path) => write!(
+                f,
+                "The unix domain socket {} is in use by another process",
+                path.display()
+            ),
+            Error::UnixSocketUnsupported => {
+                write!(f, "Unix domain sockets are not supported on this platform")
+            }
+            Error::NoListener => write!(f, "Neither a listen address nor a unix socket is set"),
             Error::ServerAlreadyStopped => write!(f, "Couldn't stop server, it's already stopped"),
             Error::ServerStopTimeout => write!(f, "Timeout during server stopping"),
             Error::ServerStopFailed => write!(f, "Failed to stop server"),
@@ -32,6 +53,7 @@ impl StdError for Error {
             Error::Pkcs12LoadFailed(ref cause) => Some(cause),
             Error::Pkcs12Invalid(ref cause) => Some(cause),
             Error::BindFailed(ref cause) => Some(cause),
+            Error::UnixSocketBindFailed(ref cause) => Some(cause),
             _ => None,
         }
     }
@@ -1,5 +1,9 @@
 //! Framework for REST API server. It's a wrapper around Actix-web allowing it
 //! to be run as a background service.
+//!
+//! The server listens on a TCP address, on a unix domain socket or on both.
+//! The unix socket is served in plain HTTP: it is only reachable locally and
+//! its access is controlled by the permissions of the socket file.
 
 mod error;
 
@@ -10,24 +14,74 @@ use actix_web::{
     actix::{Addr, System},
     server::{self, IntoHttpHandler, StopServer},
 };
+use futures::{prelude::*, sync::oneshot};
 use native_tls::{Identity, TlsAcceptor};
 use std::{
     fs,
     net::{SocketAddr, ToSocketAddrs},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 pub type ServerResult<T> = Result<T, Error>;
 
 #[derive(Clone)]
 pub struct Server {
-    addr: Addr<ActixServer>,
+    /// none when only the unix socket is served
+    addr: Option<Addr<ActixServer>>,
+    /// none when the unix socket is not served
+    unix: Option<UnixServer>,
+    system: System,
+}
+
+/// the handle stopping the server of the unix socket, which the actix
+/// server does not serve
+#[derive(Clone)]
+struct UnixServer {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+}
+
+impl UnixServer {
+    fn stop(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// the incoming connections of a listener, ended once the server is stopped
+struct Stoppable<S> {
+    incoming: S,
+    stopped: oneshot::Receiver<()>,
+}
+
+impl<S: Stream> Stream for Stoppable<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        match self.stopped.poll() {
+            Ok(Async::NotReady) => self.incoming.poll(),
+            // stopped, or the handle is gone with the server
+            Ok(Async::Ready(())) | Err(oneshot::Canceled) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn stoppable<S: Stream>(incoming: S) -> (Stoppable<S>, UnixServer) {
+    let (stop, stopped) = oneshot::channel();
+    let server = UnixServer {
+        stop: Arc::new(Mutex::new(Some(stop))),
+    };
+    (Stoppable { incoming, stopped }, server)
 }
 
 impl Server {
     pub fn run<F, H>(
         pkcs12: Option<PathBuf>,
-        address: SocketAddr,
+        address: Option<SocketAddr>,
+        unix_socket: Option<PathBuf>,
         handler: F,
         server_receiver: impl FnOnce(Server),
     ) -> ServerResult<()>
@@ -35,17 +89,44 @@ impl Server {
         F: Fn() -> H + Clone + Send + 'static,
         H: IntoHttpHandler + 'static,
     {
+        if address.is_none() && unix_socket.is_none() {
+            return Err(Error::NoListener);
+        }
         let tls = load_tls_acceptor(pkcs12)?;
         let actix_system = System::builder().build();
-        let addr = start_server_curr_actix_system(address, tls, handler)?;
-        let server = Server { addr };
+        let addr = match address {
+            Some(address) => Some(start_server_curr_actix_system(
+                address,
+                tls,
+                handler.clone(),
+            )?),
+            None => None,
+        };
+        let unix = match unix_socket {
+            Some(ref path) => Some(start_unix_server_curr_actix_system(path, handler)?),
+            None => None,
+        };
+        let server = Server {
+            addr,
+            unix,
+            system: System::current(),
+        };
         server_receiver(server);
         actix_system.run();
+        if let Some(path) = unix_socket {
+            let _ = fs::remove_file(path);
+        }
         Ok(())
     }
 
     pub fn stop(&self) {
-        self.addr.do_send(StopServer { graceful: false })
+        if let Some(ref unix) = self.unix {
+            unix.stop();
+        }
+        match self.addr {
+            Some(ref addr) => addr.do_send(StopServer { graceful: false }),
+            None => self.system.stop(),
+        }
     }
 }
 
@@ -80,3 +161,130 @@ where
     .map(|bound_server| bound_server.start())
     .map_err(|err| Error::BindFailed(err))
 }
+
+/// serve the unix socket on the current actix system. The actix server
+/// only listens on TCP sockets, the connections of the unix socket are
+/// handled by the HTTP server on the current thread.
+#[cfg(unix)]
+#[allow(deprecated)]
+fn start_unix_server_curr_actix_system<F, H>(path: &Path, handler: F) -> ServerResult<UnixServer>
+where
+    F: Fn() -> H + Clone + Send + 'static,
+    H: IntoHttpHandler + 'static,
+{
+    use tokio::net::UnixListener;
+
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path).map_err(Error::UnixSocketBindFailed)?;
+    let (incoming, unix) = stoppable(listener.incoming());
+    server::new(handler)
+        .workers(1)
+        .system_exit()
+        .disable_signals()
+        .start_incoming(incoming, false);
+    Ok(unix)
+}
+
+/// remove the socket file left by a node which did not shut down cleanly.
+/// A path taken by another kind of file, or by a socket a process still
+/// listens on, is left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> ServerResult<()> {
+    use std::{
+        io,
+        os::unix::{fs::FileTypeExt, net::UnixStream},
+    };
+
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::UnixSocketBindFailed(e)),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(Error::UnixSocketPathTaken(path.to_owned()));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(Error::UnixSocketInUse(path.to_owned()));
+    }
+    fs::remove_file(path).map_err(Error::UnixSocketBindFailed)
+}
+
+#[cfg(not(unix))]
+fn start_unix_server_curr_actix_system<F, H>(_path: &Path, _handler: F) -> ServerResult<UnixServer>
+where
+    F: Fn() -> H + Clone + Send + 'static,
+    H: IntoHttpHandler + 'static,
+{
+    Err(Error::UnixSocketUnsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopped_server_ends_the_incoming_connections() {
+        let (mut incoming, unix) = stoppable(futures::stream::repeat::<_, ()>(()));
+        let clone = unix.clone();
+        assert_eq!(incoming.poll(), Ok(Async::Ready(Some(()))));
+        clone.stop();
+        assert_eq!(incoming.poll(), Ok(Async::Ready(None)));
+        // stopping again is harmless
+        unix.stop();
+    }
+
+    #[cfg(unix)]
+    mod unix {
+        use super::super::*;
+        use std::os::unix::net::UnixListener;
+
+        fn socket_path(name: &str) -> PathBuf {
+            let path = std::env::temp_dir().join(format!(
+                "jormungandr-rest-{}-{}.sock",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_file(&path);
+            path
+        }
+
+        #[test]
+        fn missing_socket_is_not_an_error() {
+            let path = socket_path("missing");
+            assert!(remove_stale_socket(&path).is_ok());
+        }
+
+        #[test]
+        fn stale_socket_is_removed() {
+            let path = socket_path("stale");
+            drop(UnixListener::bind(&path).unwrap());
+            assert!(path.exists());
+            remove_stale_socket(&path).unwrap();
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn socket_in_use_is_kept() {
+            let path = socket_path("in-use");
+            let _listener = UnixListener::bind(&path).unwrap();
+            match remove_stale_socket(&path) {
+                Err(Error::UnixSocketInUse(_)) => {}
+                res => panic!("unexpected outcome {:?}", res),
+            }
+            assert!(path.exists());
+            let _ = fs::remove_file(&path);
+        }
+
+        #[test]
+        fn other_file_is_kept() {
+            let path = socket_path("file");
+            fs::write(&path, b"not a socket").unwrap();
+            match remove_stale_socket(&path) {
+                Err(Error::UnixSocketPathTaken(_)) => {}
+                res => panic!("unexpected outcome {:?}", res),
+            }
+            assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
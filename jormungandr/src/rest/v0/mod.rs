@@ -1,5 +1,9 @@
-mod handlers;
+//! the first version of the API, kept for the existing clients
+//!
+//! The routes of this version are frozen, the new ones are only added to
+//! `v1`.
 
+use super::handlers;
 use actix_web::dev::Resource;
 
 pub fn resources() -> Vec<(
@@ -10,6 +14,9 @@ pub fn resources() -> Vec<(
         ("/account/{account_id}", &|r| {
             r.get().with_async(handlers::get_account_state)
         }),
+        ("/address/{address}/transactions", &|r| {
+            r.get().with_async(handlers::get_address_transactions)
+        }),
         ("/block/{block_id}", &|r| {
             r.get().with_async(handlers::get_block_id)
         }),
@@ -21,17 +28,22 @@ pub fn resources() -> Vec<(
         }),
         ("/leaders", &|r| {
             r.get().with(handlers::get_leaders);
-            r.post().with(handlers::post_leaders);
+            r.post().with_async(handlers::post_leaders);
         }),
         ("/leaders/logs", &|r| {
             r.get().with_async(handlers::get_leaders_logs);
         }),
         ("/leaders/{leader_id}", &|r| {
-            r.delete().with(handlers::delete_leaders)
+            r.delete().with_async(handlers::delete_leaders)
+        }),
+        ("/log/levels", &|r| {
+            r.get().with(handlers::get_log_levels);
+            r.put().with(handlers::put_log_levels);
         }),
         ("/network/stats", &|r| {
             r.get().with_async(handlers::get_network_stats)
         }),
+        ("/reload", &|r| r.post().with_async(handlers::post_reload)),
         ("/settings", &|r| r.get().with_async(handlers::get_settings)),
         ("/stake", &|r| {
             r.get().with_async(handlers::get_stake_distribution)
@@ -39,14 +51,56 @@ pub fn resources() -> Vec<(
         ("/stake_pools", &|r| {
             r.get().with_async(handlers::get_stake_pools)
         }),
-        ("/shutdown", &|r| r.get().with(handlers::get_shutdown)),
+        ("/shutdown", &|r| r.get().with(handlers::shutdown)),
         ("/message", &|r| r.post().with(handlers::post_message)),
+        ("/node/metrics", &|r| r.get().with(handlers::get_metrics)),
+        ("/node/ready", &|r| r.get().with(handlers::get_node_ready)),
         ("/node/stats", &|r| {
             r.get().with_async(handlers::get_stats_counter)
         }),
         ("/tip", &|r| r.get().with_async(handlers::get_tip)),
+        ("/transaction/{fragment_id}", &|r| {
+            r.get().with_async(handlers::get_transaction)
+        }),
         ("/utxo/{fragment_id}/{output_index}", &|r| {
             r.get().with_async(handlers::get_utxo)
         }),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the routes served by the first version of the API
+    const FROZEN_PATHS: &[&str] = &[
+        "/account/{account_id}",
+        "/address/{address}/transactions",
+        "/block/{block_id}",
+        "/block/{block_id}/next_id",
+        "/fragment/logs",
+        "/leaders",
+        "/leaders/logs",
+        "/leaders/{leader_id}",
+        "/log/levels",
+        "/network/stats",
+        "/reload",
+        "/settings",
+        "/stake",
+        "/stake_pools",
+        "/shutdown",
+        "/message",
+        "/node/metrics",
+        "/node/ready",
+        "/node/stats",
+        "/tip",
+        "/transaction/{fragment_id}",
+        "/utxo/{fragment_id}/{output_index}",
+    ];
+
+    #[test]
+    fn routes_are_frozen() {
+        let paths: Vec<_> = resources().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, FROZEN_PATHS);
+    }
+}
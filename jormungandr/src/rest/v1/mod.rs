@@ -0,0 +1,95 @@
+//! the latest version of the API
+//!
+//! It serves the routes of `v0`, with the breaking changes:
+//! * `/shutdown` is a `POST` request;
+//! * `lastBlockHeight` of `/node/stats` is a number.
+//!
+//! The routes added since `v0` are only served by this version.
+
+use super::{handlers, v0};
+use actix_web::dev::Resource;
+
+type Resources = Vec<(
+    &'static str,
+    &'static dyn Fn(&mut Resource<handlers::Context>),
+)>;
+
+pub fn resources() -> Resources {
+    let changed = changed_resources();
+    let mut resources: Resources = v0::resources()
+        .into_iter()
+        .filter(|(path, _)| changed.iter().all(|(changed, _)| changed != path))
+        .collect();
+    resources.extend(changed);
+    resources.extend(added_resources());
+    resources
+}
+
+/// the routes of `v0` served differently
+fn changed_resources() -> Resources {
+    vec![("/shutdown", &|r| r.post().with(handlers::shutdown))]
+}
+
+/// the routes not served by `v0`
+fn added_resources() -> Resources {
+    vec![
+        ("/address/{address}/balance", &|r| {
+            r.get().with_async(handlers::get_address_balance)
+        }),
+        ("/block/{block_id}/header", &|r| {
+            r.get().with_async(handlers::get_block_header)
+        }),
+        ("/clock", &|r| r.get().with_async(handlers::get_clock)),
+        ("/fees/estimate", &|r| {
+            r.get().with_async(handlers::get_fee_estimate)
+        }),
+        ("/network/bootstrap", &|r| {
+            r.post().with_async(handlers::post_network_bootstrap)
+        }),
+        ("/rewards/account/{account_id}", &|r| {
+            r.get().with_async(handlers::get_account_rewards)
+        }),
+        ("/rewards/epoch/{epoch}", &|r| {
+            r.get().with_async(handlers::get_epoch_rewards)
+        }),
+        ("/stake/snapshot", &|r| {
+            r.get().with_async(handlers::get_stake_snapshot)
+        }),
+        ("/stake_pool/{pool_id}", &|r| {
+            r.get().with_async(handlers::get_stake_pool)
+        }),
+        ("/subscriptions", &|r| {
+            r.post().with(handlers::post_subscription)
+        }),
+        ("/subscriptions/{id}", &|r| {
+            r.delete().with(handlers::delete_subscription)
+        }),
+        ("/subscriptions/{id}/events", &|r| {
+            r.get().with(handlers::get_subscription_events)
+        }),
+        ("/update_proposals", &|r| {
+            r.get().with_async(handlers::get_update_proposals)
+        }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn routes_of_v0_are_served() {
+        let paths: HashSet<_> = resources().into_iter().map(|(path, _)| path).collect();
+        for (path, _) in v0::resources() {
+            assert!(paths.contains(path), "{} is not served", path);
+        }
+    }
+
+    #[test]
+    fn routes_are_served_once() {
+        let resources = resources();
+        let paths: HashSet<_> = resources.iter().map(|(path, _)| path).collect();
+        assert_eq!(paths.len(), resources.len());
+    }
+}
@@ -0,0 +1,183 @@
+//! the history of the rewards distributed at the epoch transitions
+//!
+//! The ledger of the first block of an epoch has the rewards of the previous
+//! epoch distributed, and the blocks of the epoch keep the record of the
+//! distribution. The rewards task saves the record in the storage when the
+//! main chain enters the epoch, and answers the queries of the history from
+//! the storage.
+//!
+//! The record of an epoch is the one of the main chain when it last entered
+//! the epoch. A transition missed by the task while the node was down leaves
+//! a gap in the history, as does one while the storage volume is almost full.
+
+use crate::{
+    blockchain::{EpochTransition, Storage},
+    disk_space::DiskGuard,
+    intercom::{self, RewardsMsg},
+    utils::{async_msg::MessageQueue, task::TokioServiceInfo},
+};
+use chain_crypto::PublicKey;
+use chain_impl_mockchain::account::AccountAlg;
+use futures::future::{self, Either, Loop};
+use jormungandr_lib::interfaces::EpochRewards;
+use slog::Logger;
+use tokio::prelude::*;
+
+/// the transitions waiting while a record is saved
+pub const TRANSITIONS_CAPACITY: usize = 4;
+
+/// the epochs whose records are read at once for the rewards of an account,
+/// the storage being released for the other tasks between the batches
+const ACCOUNT_EPOCHS_BATCH: u32 = 64;
+
+enum Input {
+    Transition(EpochTransition),
+    Query(RewardsMsg),
+}
+
+pub struct Rewards {
+    storage: Storage,
+    disk_guard: DiskGuard,
+}
+
+impl Rewards {
+    pub fn new(storage: Storage, disk_guard: DiskGuard) -> Self {
+        Rewards {
+            storage,
+            disk_guard,
+        }
+    }
+
+    pub fn run(
+        self,
+        info: TokioServiceInfo,
+        transitions: MessageQueue<EpochTransition>,
+        queries: MessageQueue<RewardsMsg>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let storage = self.storage;
+        let disk_guard = self.disk_guard;
+
+        transitions
+            .map(Input::Transition)
+            .select(queries.map(Input::Query))
+            .for_each(move |input| match input {
+                Input::Transition(transition) => {
+                    if disk_guard.is_low() {
+                        warn!(
+                            logger,
+                            "the storage volume is almost full, not saving the rewards";
+                            "epoch" => transition.epoch,
+                        );
+                        return Either::A(Either::A(future::ok(())));
+                    }
+                    Either::A(Either::B(save(&storage, transition, logger.clone())))
+                }
+                Input::Query(query) => Either::B(answer(&storage, query)),
+            })
+    }
+}
+
+fn save(
+    storage: &Storage,
+    transition: EpochTransition,
+    logger: Logger,
+) -> impl Future<Item = (), Error = ()> {
+    let epoch = transition.epoch;
+    match transition.tip.epoch_rewards_info() {
+        // the epochs of the BFT consensus have no rewards
+        None => Either::A(future::ok(())),
+        Some(info) => {
+            let rewards = EpochRewards::new(epoch, info);
+            Either::B(storage.put_epoch_rewards(rewards).then(move |result| {
+                match result {
+                    Ok(()) => debug!(logger, "rewards saved"; "epoch" => epoch),
+                    Err(e) => error!(
+                        logger,
+                        "cannot save the rewards";
+                        "epoch" => epoch,
+                        "reason" => %e,
+                    ),
+                }
+                Ok(())
+            }))
+        }
+    }
+}
+
+fn answer(storage: &Storage, query: RewardsMsg) -> impl Future<Item = (), Error = ()> {
+    match query {
+        RewardsMsg::GetEpoch(epoch, reply) => Either::A(
+            storage
+                .get_epoch_rewards(epoch..=epoch)
+                .then(move |result| {
+                    reply.reply(result.map_err(intercom::Error::from).and_then(|records| {
+                        records.into_iter().next().ok_or_else(|| {
+                            intercom::Error::not_found(format!(
+                                "no rewards recorded for the epoch {}",
+                                epoch
+                            ))
+                        })
+                    }));
+                    Ok(())
+                }),
+        ),
+        RewardsMsg::GetAccount(account, last_epoch, reply) => {
+            let key: PublicKey<AccountAlg> = account.into();
+            let key = key.to_string();
+            let storage = storage.clone();
+            let scan = future::loop_fn((0, Vec::new()), move |(start, mut rewards)| {
+                let end = epochs_batch_end(start, last_epoch);
+                let key = key.clone();
+                storage.get_epoch_rewards(start..=end).map(move |records| {
+                    rewards.extend(
+                        records
+                            .iter()
+                            .filter_map(|record| record.account_reward(&key)),
+                    );
+                    if end >= last_epoch {
+                        Loop::Break(rewards)
+                    } else {
+                        Loop::Continue((end + 1, rewards))
+                    }
+                })
+            });
+            Either::B(scan.then(move |result| {
+                reply.reply(result.map_err(intercom::Error::from));
+                Ok(())
+            }))
+        }
+    }
+}
+
+/// the last epoch of the batch starting at `start`, not past `last_epoch`
+fn epochs_batch_end(start: u32, last_epoch: u32) -> u32 {
+    start
+        .saturating_add(ACCOUNT_EPOCHS_BATCH - 1)
+        .min(last_epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_cover_the_epochs_up_to_the_last() {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = epochs_batch_end(start, 130);
+            batches.push((start, end));
+            if end >= 130 {
+                break;
+            }
+            start = end + 1;
+        }
+        assert_eq!(batches, vec![(0, 63), (64, 127), (128, 130)]);
+        assert_eq!(epochs_batch_end(0, 0), 0);
+        assert_eq!(
+            epochs_batch_end(std::u32::MAX - 1, std::u32::MAX),
+            std::u32::MAX
+        );
+    }
+}
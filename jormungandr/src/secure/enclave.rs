@@ -2,6 +2,7 @@ use crate::blockcfg::{
     BlockDate, HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
     HeaderSetConsensusSignature, SlotId,
 };
+use chain_crypto::{SecretKey, SumEd25519_12};
 use chain_impl_mockchain::leadership::{Leader, LeaderOutput, Leadership};
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::collections::BTreeMap;
@@ -12,6 +13,21 @@ pub struct Enclave {
     leaders: Arc<RwLock<BTreeMap<LeaderId, Leader>>>,
 }
 
+/// the outcome of `Enclave::evolve_genesis_key`
+pub enum GenesisKeyEvolution {
+    /// the leader is unknown, is not a genesis leader, or its key is
+    /// already at (or past) the requested period
+    UpToDate,
+    /// the key is at its last period and cannot evolve anymore
+    Expired { period: u32 },
+    /// the key evolved from `from_period`, up to the requested period or to
+    /// its last one
+    Evolved {
+        from_period: u32,
+        sig_key: SecretKey<SumEd25519_12>,
+    },
+}
+
 pub struct LeaderEvent {
     pub id: LeaderId,
     pub date: BlockDate,
@@ -29,14 +45,6 @@ impl Enclave {
         }
     }
 
-    pub fn from_vec(leaders: Vec<Leader>) -> Self {
-        let e = Self::new();
-        for leader in leaders {
-            e.add_leader(leader);
-        }
-        e
-    }
-
     pub fn get_leaderids(&self) -> Vec<LeaderId> {
         let leaders = self.leaders.read().unwrap();
         leaders.keys().map(|v| v.clone()).collect()
@@ -59,6 +67,67 @@ impl Enclave {
         leaders.remove(&leader_id).is_some()
     }
 
+    /// evolve a copy of the KES signing key of the given genesis leader up
+    /// to the given period. The key in use is left as is, for the caller to
+    /// persist the evolved key without holding the leaders, and to put it
+    /// in use with `replace_genesis_key` once persisted.
+    pub fn evolve_genesis_key(&self, leader_id: LeaderId, period: u32) -> GenesisKeyEvolution {
+        let leaders = self.leaders.read().unwrap();
+        let genesis_leader = match leaders
+            .get(&leader_id)
+            .and_then(|leader| leader.genesis_leader.as_ref())
+        {
+            Some(genesis_leader) => genesis_leader,
+            None => return GenesisKeyEvolution::UpToDate,
+        };
+
+        let from_period = genesis_leader.sig_key.get_period() as u32;
+        if from_period >= period {
+            return GenesisKeyEvolution::UpToDate;
+        }
+
+        let mut sig_key = genesis_leader.sig_key.clone();
+        while (sig_key.get_period() as u32) < period {
+            if !sig_key.update() {
+                // the key cannot evolve past its last period
+                break;
+            }
+        }
+        if sig_key.get_period() as u32 == from_period {
+            return GenesisKeyEvolution::Expired {
+                period: from_period,
+            };
+        }
+        GenesisKeyEvolution::Evolved {
+            from_period,
+            sig_key,
+        }
+    }
+
+    /// put in use the key evolved by `evolve_genesis_key`, unless the key of
+    /// the leader changed in the meantime (the leader was removed, or its
+    /// key evolved already). Returns whether the key was replaced.
+    pub fn replace_genesis_key(
+        &self,
+        leader_id: LeaderId,
+        from_period: u32,
+        sig_key: SecretKey<SumEd25519_12>,
+    ) -> bool {
+        let mut leaders = self.leaders.write().unwrap();
+        match leaders
+            .get_mut(&leader_id)
+            .and_then(|leader| leader.genesis_leader.as_mut())
+        {
+            Some(genesis_leader) if genesis_leader.sig_key.get_period() as u32 == from_period => {
+                // the previous key is dropped here, the secret key types
+                // clear their memory on drop
+                genesis_leader.sig_key = sig_key;
+                true
+            }
+            _ => false,
+        }
+    }
+
     // temporary method
     pub fn leadership_evaluate1(
         &self,
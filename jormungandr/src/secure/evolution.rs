@@ -0,0 +1,181 @@
+//! evolution of the KES signing keys of the genesis praos leaders
+//!
+//! a KES key has to be updated once every `kes_update_speed` seconds (as
+//! set in the block0). The evolved key is written back to the secret file
+//! the leader was loaded from so the node does not restart with a key of a
+//! past period. The file is written before the evolved key is put in use and
+//! without holding the leaders of the enclave, which keep signing with the
+//! current key in the meantime.
+//!
+//! A key at its last period cannot evolve anymore: the leader is reported
+//! once and its file is left as is.
+
+use crate::{
+    blockcfg::{Block, Block0DataSource as _, Block0Error},
+    secure::{
+        enclave::{Enclave, GenesisKeyEvolution},
+        NodeSecret, NodeSecretFromFileError, NodeSecretToFileError,
+    },
+    utils::task::TokioServiceInfo,
+};
+use chain_crypto::{SecretKey, SumEd25519_12};
+use jormungandr_lib::{crypto::passphrase::Passphrase, interfaces::EnclaveLeaderId as LeaderId};
+use slog::Logger;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{prelude::*, timer::Interval};
+
+/// how often the current KES period is checked against the keys'
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+custom_error! {pub KeyEvolutionError
+    Load { source: NodeSecretFromFileError } = "Cannot reload the secret file: {source}",
+    Save { source: NodeSecretToFileError } = "Cannot persist the evolved key: {source}",
+    NotGenesis = "The secret file does not contain a genesis praos setting",
+}
+
+/// the KES periods, as defined in the block0
+#[derive(Debug, Clone, Copy)]
+pub struct KesSchedule {
+    block0_time: SystemTime,
+    update_speed: Duration,
+}
+
+impl KesSchedule {
+    /// read the start time and the KES update speed from the block0
+    pub fn from_block0(block0: &Block) -> Result<Self, Block0Error> {
+        Ok(KesSchedule {
+            block0_time: block0.start_time()?,
+            update_speed: block0.kes_update_speed()?,
+        })
+    }
+
+    /// the KES period at the given time
+    pub fn period_at(&self, time: SystemTime) -> u32 {
+        match time.duration_since(self.block0_time) {
+            Ok(elapsed) => (elapsed.as_secs() / self.update_speed.as_secs().max(1)) as u32,
+            Err(_) => 0,
+        }
+    }
+}
+
+/// evolves the genesis leaders' keys held by the enclave as the KES
+/// periods go by, and persists them in their secret files.
+pub struct KeyEvolution {
+    enclave: Enclave,
+    schedule: KesSchedule,
+    secrets: Vec<(LeaderId, PathBuf)>,
+    passphrase: Option<Passphrase>,
+    /// the leaders whose key expired, reported already
+    expired: BTreeSet<LeaderId>,
+}
+
+impl KeyEvolution {
+    /// `secrets` associates the leaders of the enclave with the secret file
+    /// they were loaded from. Leaders without a file (e.g. added through the
+    /// REST API) are still used but their keys are not evolved. The
+    /// encrypted files are decrypted with `passphrase` and written back
+    /// encrypted.
+    pub fn new(
+        enclave: Enclave,
+        schedule: KesSchedule,
+        secrets: Vec<(LeaderId, PathBuf)>,
+        passphrase: Option<Passphrase>,
+    ) -> Self {
+        KeyEvolution {
+            enclave,
+            schedule,
+            secrets,
+            passphrase,
+            expired: BTreeSet::new(),
+        }
+    }
+
+    pub fn run(self, info: TokioServiceInfo) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let error_logger = logger.clone();
+        let mut evolution = self;
+
+        Interval::new_interval(CHECK_INTERVAL)
+            .map_err(move |e| error!(error_logger, "KES evolution timer error"; "reason" => %e))
+            .for_each(move |_| {
+                evolution.evolve(&logger);
+                Ok(())
+            })
+    }
+
+    fn evolve(&mut self, logger: &Logger) {
+        let period = self.schedule.period_at(SystemTime::now());
+
+        for (leader_id, path) in self.secrets.iter() {
+            let (from_period, sig_key) = match self.enclave.evolve_genesis_key(*leader_id, period) {
+                GenesisKeyEvolution::UpToDate => continue,
+                GenesisKeyEvolution::Expired {
+                    period: last_period,
+                } => {
+                    if self.expired.insert(*leader_id) {
+                        error!(
+                            logger,
+                            "KES key expired, the leader cannot sign the blocks of the next periods";
+                            "leader" => %leader_id,
+                            "last_period" => last_period,
+                            "period" => period,
+                        );
+                    }
+                    continue;
+                }
+                GenesisKeyEvolution::Evolved {
+                    from_period,
+                    sig_key,
+                } => (from_period, sig_key),
+            };
+
+            if let Err(e) = persist(path, &sig_key, self.passphrase.as_ref()) {
+                error!(
+                    logger,
+                    "KES key evolution failed, keeping the current key";
+                    "leader" => %leader_id,
+                    "path" => %path.display(),
+                    "reason" => %e,
+                );
+                continue;
+            }
+
+            let evolved_period = sig_key.get_period();
+            if self
+                .enclave
+                .replace_genesis_key(*leader_id, from_period, sig_key)
+            {
+                info!(
+                    logger,
+                    "KES key evolved";
+                    "leader" => %leader_id,
+                    "period" => evolved_period,
+                );
+            } else {
+                warn!(
+                    logger,
+                    "the key of the leader changed while its evolution was persisted, not replacing it";
+                    "leader" => %leader_id,
+                );
+            }
+        }
+    }
+}
+
+/// write the evolved key to the secret file, in the format it was read in
+fn persist(
+    path: &Path,
+    sig_key: &SecretKey<SumEd25519_12>,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), KeyEvolutionError> {
+    let (mut secret, format) = NodeSecret::load_with_format(path, passphrase)?;
+    if !secret.set_genesis_sig_key(sig_key.clone()) {
+        return Err(KeyEvolutionError::NotGenesis);
+    }
+    secret.save_to_file(path, &format)?;
+    Ok(())
+}
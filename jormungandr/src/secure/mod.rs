@@ -1,23 +1,30 @@
-use chain_crypto::{Blake2b256, Curve25519_2HashDH, Ed25519, PublicKey, SumEd25519_12};
+use crate::utils::fs::temp_path;
+use chain_crypto::{Blake2b256, Curve25519_2HashDH, Ed25519, PublicKey, SecretKey, SumEd25519_12};
 use chain_impl_mockchain::leadership::{BftLeader, GenesisLeader};
 use jormungandr_lib::crypto::{
     hash::Hash,
     key::{Identifier, SigningKey},
+    passphrase::{zeroize, DecryptionError, Encrypted, EncryptedFile, Passphrase},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::Path,
 };
-use serde::Deserialize;
-use std::path::Path;
 
 pub mod enclave;
+pub mod evolution;
 
 /// hold the node's bft secret setting
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Bft {
     signing_key: SigningKey<Ed25519>,
 }
 
 /// the genesis praos setting
 ///
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct GenesisPraos {
     node_id: Hash,
     sig_key: SigningKey<SumEd25519_12>,
@@ -43,12 +50,21 @@ pub struct StakePoolInfo {
 }
 
 /// Node Secret(s)
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct NodeSecret {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub bft: Option<Bft>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub genesis: Option<GenesisPraos>,
 }
 
+/// how a secret file is stored, to write it back the same way
+#[derive(Clone, Debug)]
+pub enum SecretFormat {
+    Plain,
+    Encrypted(Passphrase),
+}
+
 /// Node Secret's Public parts
 #[derive(Clone)]
 pub struct NodePublic {
@@ -58,12 +74,95 @@ pub struct NodePublic {
 custom_error! {pub NodeSecretFromFileError
     Io { source: std::io::Error } = "Cannot read node's secrets: {source}",
     Format { source: serde_yaml::Error } = "Invalid Node secret file: {source}",
+    Permissions = "The secret file is accessible by other users, restrict it to its owner with `chmod 600`",
+    PassphraseMissing = "The secret file is encrypted but no passphrase is given, see `--secrets-passphrase-file`",
+    Decryption { source: DecryptionError } = "Cannot decrypt node's secrets: {source}",
+}
+
+custom_error! {pub NodeSecretToFileError
+    Io { source: std::io::Error } = "Cannot write node's secrets: {source}",
+    Format { source: serde_yaml::Error } = "Cannot serialize node's secrets: {source}",
+    Rand { source: rand::Error } = "Cannot encrypt node's secrets: {source}",
 }
 
 impl NodeSecret {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<NodeSecret, NodeSecretFromFileError> {
-        let file = std::fs::File::open(path)?;
-        Ok(serde_yaml::from_reader(file)?)
+    /// load the secrets from their file, decrypted with the passphrase if
+    /// the file is encrypted. The files other users can access are refused.
+    pub fn load_from_file<P: AsRef<Path>>(
+        path: P,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<NodeSecret, NodeSecretFromFileError> {
+        Self::load_with_format(path, passphrase).map(|(secret, _)| secret)
+    }
+
+    /// as `load_from_file`, also telling how the file is stored
+    pub fn load_with_format<P: AsRef<Path>>(
+        path: P,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<(NodeSecret, SecretFormat), NodeSecretFromFileError> {
+        let metadata = fs::metadata(path.as_ref())?;
+        if !owner_only(&metadata) {
+            return Err(NodeSecretFromFileError::Permissions);
+        }
+        // the buffers holding the secrets are zeroized once parsed, the
+        // secrets then only live in the `NodeSecret` given to the enclave
+        let mut content = fs::read(path)?;
+        let result = Self::parse(&content, passphrase);
+        zeroize(&mut content);
+        result
+    }
+
+    fn parse(
+        content: &[u8],
+        passphrase: Option<&Passphrase>,
+    ) -> Result<(NodeSecret, SecretFormat), NodeSecretFromFileError> {
+        let encrypted = match serde_yaml::from_slice::<EncryptedFile>(content)?.encrypted {
+            None => return Ok((serde_yaml::from_slice(content)?, SecretFormat::Plain)),
+            Some(encrypted) => encrypted,
+        };
+        let passphrase = passphrase.ok_or(NodeSecretFromFileError::PassphraseMissing)?;
+        let mut plaintext = encrypted.decrypt(passphrase)?;
+        let secret = serde_yaml::from_slice(&plaintext);
+        zeroize(&mut plaintext);
+        Ok((secret?, SecretFormat::Encrypted(passphrase.clone())))
+    }
+
+    /// replace the secret file with the content of this `NodeSecret`,
+    /// stored in the given format.
+    ///
+    /// The secrets are first written and synced to a temporary file next to
+    /// `path` which is then renamed over it, so a crash leaves either the
+    /// previous or the new secrets on disk, never a truncated file.
+    pub fn save_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: &SecretFormat,
+    ) -> Result<(), NodeSecretToFileError> {
+        let mut content = serde_yaml::to_string(self)?.into_bytes();
+        if let SecretFormat::Encrypted(passphrase) = format {
+            let encrypted =
+                Encrypted::encrypt(&mut rand::rngs::OsRng::new()?, passphrase, &content);
+            zeroize(&mut content);
+            content = serde_yaml::to_string(&EncryptedFile {
+                encrypted: Some(encrypted),
+            })?
+            .into_bytes();
+        }
+        let result = write_atomically(path.as_ref(), &content);
+        zeroize(&mut content);
+        Ok(result?)
+    }
+
+    /// replace the KES signing key of the genesis praos setting, returns
+    /// `false` if these secrets do not contain a genesis praos setting.
+    pub fn set_genesis_sig_key(&mut self, sig_key: SecretKey<SumEd25519_12>) -> bool {
+        match self.genesis.as_mut() {
+            Some(genesis) => {
+                genesis.sig_key = sig_key.into();
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn bft(&self) -> Option<BftLeader> {
@@ -80,3 +179,209 @@ impl NodeSecret {
         })
     }
 }
+
+/// write the secrets to a temporary file next to `path`, only readable by
+/// its owner, then rename it over `path`. The temporary file is created by
+/// this write (never an existing file reused with its permissions) and is
+/// removed if any step fails.
+fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = temp_path(path);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+
+    let result = options
+        .open(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    // make the rename itself durable
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// whether only the owner of the file can access it, the rule for all the
+/// files holding secrets: the node secrets, the private node ID, the
+/// passphrases and the PINs
+#[cfg(unix)]
+pub fn owner_only(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+    metadata.permissions().mode() & 0o077 == 0
+}
+
+#[cfg(not(unix))]
+pub fn owner_only(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng as _};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "jormungandr-secure-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn bft_secret(seed: u8) -> NodeSecret {
+        NodeSecret {
+            bft: Some(Bft {
+                signing_key: SigningKey::generate(&mut StdRng::from_seed([seed; 32])),
+            }),
+            genesis: None,
+        }
+    }
+
+    fn bft_key(secret: &NodeSecret) -> PublicKey<Ed25519> {
+        secret.bft().unwrap().sig_key.to_public()
+    }
+
+    fn passphrase(passphrase: &str) -> Passphrase {
+        Passphrase::new(passphrase.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn plain_secrets_are_loaded_back() {
+        let path = temp_path("plain.yaml");
+        let secret = bft_secret(1);
+        secret.save_to_file(&path, &SecretFormat::Plain).unwrap();
+
+        let loaded = NodeSecret::load_with_format(&path, None);
+        fs::remove_file(&path).unwrap();
+
+        let (loaded, format) = loaded.unwrap();
+        assert_eq!(bft_key(&loaded), bft_key(&secret));
+        match format {
+            SecretFormat::Plain => {}
+            other => panic!("unexpected format {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encrypted_secrets_are_loaded_back_with_their_passphrase() {
+        let path = temp_path("encrypted.yaml");
+        let secret = bft_secret(2);
+        let format = SecretFormat::Encrypted(passphrase("secret passphrase"));
+        secret.save_to_file(&path, &format).unwrap();
+
+        let loaded = NodeSecret::load_with_format(&path, Some(&passphrase("secret passphrase")));
+        let missing = NodeSecret::load_with_format(&path, None);
+        let wrong = NodeSecret::load_with_format(&path, Some(&passphrase("another passphrase")));
+        fs::remove_file(&path).unwrap();
+
+        let (loaded, format) = loaded.unwrap();
+        assert_eq!(bft_key(&loaded), bft_key(&secret));
+        match format {
+            SecretFormat::Encrypted(passphrase) => {
+                assert_eq!(passphrase.as_bytes(), b"secret passphrase")
+            }
+            other => panic!("unexpected format {:?}", other),
+        }
+        match missing {
+            Err(NodeSecretFromFileError::PassphraseMissing) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("encrypted secrets loaded without a passphrase"),
+        }
+        match wrong {
+            Err(NodeSecretFromFileError::Decryption { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("encrypted secrets loaded with another passphrase"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_other_users_can_access_are_refused() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let path = temp_path("shared.yaml");
+        bft_secret(3)
+            .save_to_file(&path, &SecretFormat::Plain)
+            .unwrap();
+
+        let mut refused = Vec::new();
+        for mode in &[0o640, 0o604, 0o660] {
+            fs::set_permissions(&path, fs::Permissions::from_mode(*mode)).unwrap();
+            refused.push(NodeSecret::load_with_format(&path, None));
+        }
+        fs::remove_file(&path).unwrap();
+
+        for res in refused {
+            match res {
+                Err(NodeSecretFromFileError::Permissions) => {}
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("secrets other users can access loaded"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_temporary_files_are_not_reused() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let path = temp_path("stale.yaml");
+        let mut stale = path.clone().into_os_string();
+        stale.push(".tmp");
+        let stale = PathBuf::from(stale);
+        fs::write(&stale, b"planted").unwrap();
+        fs::set_permissions(&stale, fs::Permissions::from_mode(0o666)).unwrap();
+
+        let secret = bft_secret(4);
+        let saved = secret.save_to_file(&path, &SecretFormat::Plain);
+        let mode = fs::metadata(&path).map(|m| m.permissions().mode() & 0o777);
+        let loaded = NodeSecret::load_from_file(&path, None);
+        let stale_content = fs::read(&stale);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&stale);
+
+        saved.unwrap();
+        assert_eq!(mode.unwrap(), 0o600);
+        assert_eq!(bft_key(&loaded.unwrap()), bft_key(&secret));
+        assert_eq!(stale_content.unwrap(), b"planted");
+    }
+
+    #[test]
+    fn failed_writes_leave_no_temporary_file() {
+        let dir = temp_path("failed-write");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // renaming a file over a non-empty directory fails
+        let path = dir.join("secret.yaml");
+        fs::create_dir_all(path.join("occupied")).unwrap();
+
+        let saved = bft_secret(5).save_to_file(&path, &SecretFormat::Plain);
+        let left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(saved.is_err());
+        assert_eq!(left, vec![std::ffi::OsString::from("secret.yaml")]);
+    }
+}
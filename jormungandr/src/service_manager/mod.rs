@@ -0,0 +1,56 @@
+//! integration with the service manager running the node
+//!
+//! On Linux, the node notifies systemd through `sd_notify` when the service
+//! runs with `Type=notify`: `READY=1` once the bootstrap is over, its state
+//! as `STATUS=`, and `WATCHDOG=1` as long as none of its tasks is stuck when
+//! `WatchdogSec` is set. On Windows, the node runs as a service of the
+//! service control manager when started with `--windows-service`.
+//!
+//! The functions of this module do nothing when the node is not run by a
+//! service manager.
+
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(windows)]
+pub mod windows;
+
+use std::time::Duration;
+
+/// the node is bootstrapped and its tasks are starting
+pub fn notify_ready() {
+    #[cfg(target_os = "linux")]
+    systemd::notify("READY=1");
+    #[cfg(windows)]
+    windows::set_running();
+}
+
+/// a short description of what the node is doing
+pub fn notify_status(status: &str) {
+    #[cfg(target_os = "linux")]
+    systemd::notify(&format!("STATUS={}", status));
+    #[cfg(not(target_os = "linux"))]
+    let _ = status;
+}
+
+/// the node is shutting down
+pub fn notify_stopping() {
+    #[cfg(target_os = "linux")]
+    systemd::notify("STOPPING=1");
+}
+
+/// the interval the service manager expects the node to be alive within
+#[cfg(target_os = "linux")]
+pub fn watchdog_interval() -> Option<Duration> {
+    systemd::watchdog_interval()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watchdog_interval() -> Option<Duration> {
+    None
+}
+
+/// tell the service manager the node is alive
+pub fn feed_watchdog() {
+    #[cfg(target_os = "linux")]
+    systemd::notify("WATCHDOG=1");
+}
@@ -0,0 +1,35 @@
+use std::{env, os::unix::net::UnixDatagram, path::PathBuf, process, time::Duration};
+
+lazy_static! {
+    /// the socket systemd listens to for the notifications of the service,
+    /// not set when the node is not run by systemd. The sockets in the
+    /// abstract namespace (starting with `@`) are not supported.
+    static ref NOTIFY_SOCKET: Option<PathBuf> = env::var_os("NOTIFY_SOCKET")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+}
+
+/// send the notification, as `sd_notify` does the errors are ignored
+pub fn notify(state: &str) {
+    if let Some(path) = NOTIFY_SOCKET.as_ref() {
+        if let Ok(socket) = UnixDatagram::unbound() {
+            let _ = socket.send_to(state.as_bytes(), path);
+        }
+    }
+}
+
+/// the watchdog interval set with `WatchdogSec`, if it applies to this
+/// process, as `sd_watchdog_enabled` does
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+    if usec == 0 {
+        None
+    } else {
+        Some(Duration::from_micros(usec))
+    }
+}
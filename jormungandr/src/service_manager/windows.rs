@@ -0,0 +1,106 @@
+use futures::{sync::oneshot, Future};
+use std::{ffi::OsString, sync::Mutex, time::Duration};
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+    service_dispatcher,
+};
+
+/// the name the service is registered with, e.g. with
+/// `sc.exe create jormungandr binPath= "...\jormungandr.exe --windows-service ..."`
+const SERVICE_NAME: &str = "jormungandr";
+
+/// time the service control manager waits for the node to be bootstrapped,
+/// the bootstrap may have to download the blocks since the last run
+const START_WAIT_HINT: Duration = Duration::from_secs(600);
+
+/// time the service control manager waits for the node to shut down, the
+/// steps of the shutdown having 30 seconds each
+const STOP_WAIT_HINT: Duration = Duration::from_secs(90);
+
+lazy_static! {
+    static ref STATUS_HANDLE: Mutex<Option<ServiceStatusHandle>> = Mutex::new(None);
+    static ref STOP_REQUEST: Mutex<Option<oneshot::Sender<()>>> = Mutex::new(None);
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// run the node as a service of the service control manager, returns once
+/// the service is stopped
+pub fn run() -> Result<(), windows_service::Error> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let status_handle = match service_control_handler::register(SERVICE_NAME, handle_control) {
+        Ok(status_handle) => status_handle,
+        Err(e) => {
+            eprintln!("cannot register the service control handler: {}", e);
+            return;
+        }
+    };
+    *STATUS_HANDLE.lock().unwrap() = Some(status_handle);
+    set_status(ServiceState::StartPending, 0, START_WAIT_HINT);
+
+    let code = match crate::start() {
+        Ok(code) => code,
+        Err(error) => {
+            crate::print_error(&error);
+            error.code()
+        }
+    };
+    set_status(ServiceState::Stopped, code as u32, Duration::default());
+}
+
+fn handle_control(control: ServiceControl) -> ServiceControlHandlerResult {
+    match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            set_status(ServiceState::StopPending, 0, STOP_WAIT_HINT);
+            if let Some(stop_request) = STOP_REQUEST.lock().unwrap().take() {
+                let _ = stop_request.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    }
+}
+
+/// complete when the service control manager asks the node to stop, the
+/// node then shuts down as on SIGTERM and the service is reported stopped
+/// once `crate::start` returns. The future does not complete when the node
+/// is not run as a service.
+pub fn stop_requested() -> impl Future<Item = (), Error = ()> {
+    let (stop_request, stopped) = oneshot::channel();
+    *STOP_REQUEST.lock().unwrap() = Some(stop_request);
+    stopped.map_err(|_canceled| ())
+}
+
+/// the node is bootstrapped
+pub fn set_running() {
+    set_status(ServiceState::Running, 0, Duration::default());
+}
+
+fn set_status(current_state: ServiceState, code: u32, wait_hint: Duration) {
+    let status_handle = match *STATUS_HANDLE.lock().unwrap() {
+        Some(status_handle) => status_handle,
+        // not run as a service
+        None => return,
+    };
+    let controls_accepted = match current_state {
+        ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        _ => ServiceControlAccept::empty(),
+    };
+    let _ = status_handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OwnProcess,
+        current_state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(code),
+        checkpoint: 0,
+        wait_hint,
+    });
+}
@@ -7,6 +7,7 @@ use structopt::StructOpt;
 use crate::{
     blockcfg::HeaderHash,
     settings::logging::{LogFormat, LogOutput},
+    start_up::LedgerDumpFormat,
 };
 
 #[derive(StructOpt, Debug)]
@@ -24,6 +25,12 @@ pub struct StartArguments {
     #[structopt(long = "secret", parse(from_os_str))]
     pub secret: Vec<PathBuf>,
 
+    /// Read the passphrase of the encrypted secret files from the given
+    /// file, only accessible by its owner. Without it, the passphrase is
+    /// taken from the `JORMUNGANDR_SECRETS_PASSPHRASE` environment variable.
+    #[structopt(long = "secrets-passphrase-file", parse(from_os_str))]
+    pub secrets_passphrase_file: Option<PathBuf>,
+
     /// Path to the genesis block (the block0) of the blockchain
     #[structopt(long = "genesis-block", parse(try_from_str))]
     pub block_0_path: Option<PathBuf>,
@@ -44,6 +51,72 @@ pub struct StartArguments {
     /// Start the explorer task and enable associated query endpoints.
     #[structopt(long = "enable-explorer")]
     pub explorer_enabled: bool,
+
+    /// Write the blockchain, from the genesis block up to the tip, to the given
+    /// archive file and exit.
+    #[structopt(long = "export-chain", parse(from_os_str))]
+    pub export_chain: Option<PathBuf>,
+
+    /// Validate and load the blocks of the given archive file (as written by
+    /// `--export-chain') before bootstrapping from the network.
+    #[structopt(
+        long = "import-chain",
+        parse(from_os_str),
+        conflicts_with = "export_chain"
+    )]
+    pub import_chain: Option<PathBuf>,
+
+    /// Verify the blockchain held in the storage, from the genesis block up
+    /// to the tip, report the first corrupted block if any and exit.
+    #[structopt(
+        long = "storage-check",
+        conflicts_with = "export_chain",
+        conflicts_with = "import_chain"
+    )]
+    pub storage_check: bool,
+
+    /// Write the UTxO set, the accounts and the delegation of the accounts
+    /// at the tip of the blockchain held in the storage to files in the given
+    /// directory and exit.
+    #[structopt(
+        long = "dump-ledger",
+        parse(from_os_str),
+        conflicts_with = "export_chain",
+        conflicts_with = "import_chain",
+        conflicts_with = "storage_check"
+    )]
+    pub dump_ledger: Option<PathBuf>,
+
+    /// Export the ledger state at the given block of the storage in place of
+    /// the tip (see `--dump-ledger').
+    #[structopt(long = "dump-ledger-at", parse(try_from_str), requires = "dump_ledger")]
+    pub dump_ledger_at: Option<HeaderHash>,
+
+    /// The format of the files written by `--dump-ledger'.
+    #[structopt(
+        long = "dump-ledger-format",
+        default_value = "json",
+        possible_values = &["json", "csv"],
+        parse(try_from_str)
+    )]
+    pub dump_ledger_format: LedgerDumpFormat,
+
+    /// Empty the storage if it holds the blockchain of another genesis block
+    /// than the configured one, in place of refusing to start, and
+    /// synchronize again from the network.
+    #[structopt(long = "force-resync")]
+    pub force_resync: bool,
+
+    /// Remove the chain index and index the blockchain held in the storage
+    /// again, in the background once the node is started.
+    #[structopt(long = "rebuild-index")]
+    pub rebuild_index: bool,
+
+    /// Compare the chain index with the blockchain held in the storage once
+    /// the node is started, and repair the index if it drifted, e.g. after a
+    /// crash.
+    #[structopt(long = "check-index", conflicts_with = "rebuild_index")]
+    pub check_index: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -94,6 +167,15 @@ pub struct CommandLine {
     /// this option is useful for scripting retrieving the logs of the version of this application.
     #[structopt(long = "source-version")]
     pub source_version: bool,
+
+    /// validate the node configuration, print the report in JSON and exit.
+    /// The exit code is 0 only if the configuration is valid.
+    #[structopt(long = "check-config")]
+    pub check_config: bool,
+
+    /// run as a service of the Windows service control manager (Windows only)
+    #[structopt(long = "windows-service")]
+    pub windows_service: bool,
 }
 
 impl CommandLine {
@@ -1,26 +1,39 @@
-use crate::log::AsyncableDrain;
-use slog::{Drain, FilterLevel, Logger};
+#[cfg(feature = "gelf")]
+use crate::log::GelfTcp;
+use crate::log::{AsyncableDrain, RotatingFile, KEY_TASK};
+use jormungandr_lib::time::Duration;
+use slog::{Drain, FilterLevel, Logger, KV};
 use slog_async::Async;
 #[cfg(feature = "gelf")]
 use slog_gelf::Gelf;
 #[cfg(feature = "systemd")]
 use slog_journald::JournaldDrain;
 #[cfg(unix)]
-use slog_syslog::Facility;
+use slog_syslog::{Facility, SyslogBuilder};
 use slog_term::{PlainDecorator, TermDecorator};
+use std::collections::BTreeMap;
 use std::error;
 use std::fmt::{self, Display};
-use std::fs;
 use std::io;
+#[cfg(unix)]
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock,
+};
 
 pub struct LogSettings(pub Vec<LogSettingsEntry>);
 
 #[derive(Debug)]
 pub struct LogSettingsEntry {
     pub level: FilterLevel,
+    /// the levels of the records of the tasks logging at another level than
+    /// `level`, by the name of the task
+    pub modules: BTreeMap<String, FilterLevel>,
     pub format: LogFormat,
     pub output: LogOutput,
+    pub rotation: Option<LogRotation>,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -49,16 +62,58 @@ pub enum LogOutput {
     Stderr,
     #[cfg(unix)]
     Syslog,
+    /// a syslog server on the network, the messages are in the RFC 3164
+    /// format
+    #[cfg(unix)]
+    #[serde(rename = "remote_syslog")]
+    RemoteSyslog {
+        server: SocketAddr,
+        #[serde(default)]
+        transport: Transport,
+        /// the name of the node host in the messages
+        hostname: String,
+    },
     #[cfg(feature = "systemd")]
     Journald,
     #[cfg(feature = "gelf")]
     Gelf {
         backend: String,
         log_id: String,
+        #[serde(default)]
+        transport: Transport,
     },
     File(String),
 }
 
+/// Rotation of a file output, when it reaches `max_size` bytes or every
+/// `interval`. The last `max_files` files are kept.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LogRotation {
+    pub max_size: Option<u64>,
+    pub interval: Option<Duration>,
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+/// the protocol of the outputs sending the messages over the network
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Udp
+    }
+}
+
 impl FromStr for LogFormat {
     type Err = String;
 
@@ -111,30 +166,163 @@ impl<D: Drain> Drain for DrainMux<D> {
     }
 }
 
+/// the filter levels of an output: `level` applies to the records of the
+/// tasks which have no level of their own in `modules`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputLevels {
+    #[serde(with = "filter_level_serde")]
+    pub level: FilterLevel,
+    #[serde(default, with = "filter_level_map_serde")]
+    pub modules: BTreeMap<String, FilterLevel>,
+}
+
+struct SharedLevels {
+    level: AtomicUsize,
+    modules: RwLock<BTreeMap<String, FilterLevel>>,
+}
+
+impl SharedLevels {
+    fn new(levels: OutputLevels) -> Self {
+        SharedLevels {
+            level: AtomicUsize::new(levels.level.as_usize()),
+            modules: RwLock::new(levels.modules),
+        }
+    }
+
+    fn get(&self) -> OutputLevels {
+        OutputLevels {
+            level: self.level(),
+            modules: self.modules.read().unwrap().clone(),
+        }
+    }
+
+    fn set(&self, levels: &OutputLevels) {
+        self.level.store(levels.level.as_usize(), Ordering::Relaxed);
+        *self.modules.write().unwrap() = levels.modules.clone();
+    }
+
+    fn level(&self) -> FilterLevel {
+        FilterLevel::from_usize(self.level.load(Ordering::Relaxed)).unwrap_or(FilterLevel::Off)
+    }
+
+    /// the level applying to a record logged with the given values
+    fn level_of(&self, record: &slog::Record, values: &slog::OwnedKVList) -> FilterLevel {
+        let modules = self.modules.read().unwrap();
+        if modules.is_empty() {
+            return self.level();
+        }
+        let mut task = FindTask(None);
+        // the values of the loggers are serialized from the most recently
+        // added, whose task is the most specific one
+        let _ = values.serialize(record, &mut task);
+        task.0
+            .and_then(|task| modules.get(&task).cloned())
+            .unwrap_or_else(|| self.level())
+    }
+}
+
+/// the filter levels of the outputs of a logger, in the order of the
+/// entries of its `LogSettings`. They can be changed while the logger runs.
+#[derive(Clone)]
+pub struct LogLevels(Vec<Arc<SharedLevels>>);
+
+impl LogLevels {
+    pub fn get(&self) -> Vec<OutputLevels> {
+        self.0.iter().map(|levels| levels.get()).collect()
+    }
+
+    /// set the levels of the outputs, there must be one for each output
+    pub fn set(&self, levels: &[OutputLevels]) {
+        assert_eq!(levels.len(), self.0.len());
+        for (shared, levels) in self.0.iter().zip(levels) {
+            shared.set(levels);
+        }
+    }
+
+    /// the number of outputs of the logger
+    pub fn outputs(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// the value of the task key of a logger, if any
+struct FindTask(Option<String>);
+
+impl slog::Serializer for FindTask {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        if self.0.is_none() && key == KEY_TASK {
+            self.0 = Some(val.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// drop the records below the level of their task
+struct LevelFilter<D> {
+    drain: D,
+    levels: Arc<SharedLevels>,
+}
+
+impl<D: Drain> Drain for LevelFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if self.levels.level_of(record, values).accepts(record.level()) {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 impl LogSettings {
-    pub fn to_logger(&self) -> Result<Logger, Error> {
+    pub fn to_logger(&self) -> Result<(Logger, LogLevels), Error> {
         let mut drains = Vec::new();
+        let mut levels = Vec::new();
         for config in self.0.iter() {
-            drains.push(config.to_logger()?);
+            let shared = Arc::new(SharedLevels::new(config.levels()));
+            drains.push(config.to_logger(shared.clone())?);
+            levels.push(shared);
         }
         let common_drain = DrainMux::new(drains).fuse();
-        Ok(slog::Logger::root(common_drain, o!()))
+        Ok((slog::Logger::root(common_drain, o!()), LogLevels(levels)))
     }
 }
 
 impl LogSettingsEntry {
-    pub fn to_logger(&self) -> Result<slog::Filter<Async, impl slog::FilterFn>, Error> {
-        let filter_level = self.level;
-        let drain = self
-            .output
-            .to_logger(&self.format)?
-            .filter(move |record| filter_level.accepts(record.level()));
-        Ok(drain)
+    pub fn levels(&self) -> OutputLevels {
+        OutputLevels {
+            level: self.level,
+            modules: self.modules.clone(),
+        }
+    }
+
+    fn to_logger(&self, levels: Arc<SharedLevels>) -> Result<LevelFilter<Async>, Error> {
+        Ok(LevelFilter {
+            drain: self
+                .output
+                .to_logger(&self.format, self.rotation.as_ref())?,
+            levels,
+        })
     }
 }
 
 impl LogOutput {
-    fn to_logger(&self, format: &LogFormat) -> Result<Async, Error> {
+    fn to_logger(
+        &self,
+        format: &LogFormat,
+        rotation: Option<&LogRotation>,
+    ) -> Result<Async, Error> {
+        match (self, rotation) {
+            (LogOutput::File(_), _) | (_, None) => {}
+            _ => return Err(Error::RotationRequiresFile),
+        }
         match self {
             LogOutput::Stdout => Ok(format.decorate_stdout()),
             LogOutput::Stderr => Ok(format.decorate_stderr()),
@@ -146,6 +334,30 @@ impl LogOutput {
                     Err(e) => Err(Error::SyslogAccessFailed(e)),
                 }
             }
+            #[cfg(unix)]
+            LogOutput::RemoteSyslog {
+                server,
+                transport,
+                hostname,
+            } => {
+                format.require_plain()?;
+                let builder = SyslogBuilder::new().facility(Facility::LOG_USER);
+                let builder = match transport {
+                    Transport::Udp => {
+                        let local = if server.is_ipv4() {
+                            "0.0.0.0:0"
+                        } else {
+                            "[::]:0"
+                        };
+                        builder.udp(local.parse().unwrap(), *server, hostname)
+                    }
+                    Transport::Tcp => builder.tcp(*server, hostname),
+                };
+                match builder.start() {
+                    Ok(drain) => Ok(drain.into_async()),
+                    Err(e) => Err(Error::SyslogAccessFailed(e)),
+                }
+            }
             #[cfg(feature = "systemd")]
             LogOutput::Journald => {
                 format.require_plain()?;
@@ -155,6 +367,7 @@ impl LogOutput {
             LogOutput::Gelf {
                 backend: graylog_host_port,
                 log_id: graylog_source,
+                transport,
             } => {
                 // Both currently recognized formats can be understood to apply:
                 // GELF formats payloads in JSON so 'json' is redundant,
@@ -164,17 +377,26 @@ impl LogOutput {
                 match format {
                     LogFormat::Plain | LogFormat::Json => {}
                 };
-                let gelf_drain = Gelf::new(graylog_source, graylog_host_port)
-                    .map_err(Error::GelfConnectionFailed)?;
-                Ok(gelf_drain.into_async())
+                match transport {
+                    Transport::Udp => {
+                        Gelf::new(graylog_source, graylog_host_port).map(AsyncableDrain::into_async)
+                    }
+                    Transport::Tcp => GelfTcp::new(graylog_source, graylog_host_port)
+                        .map(AsyncableDrain::into_async),
+                }
+                .map_err(Error::GelfConnectionFailed)
             }
             LogOutput::File(path) => {
-                let file = fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .append(true)
-                    .open(path)
-                    .map_err(Error::FileError)?;
+                let file = match rotation {
+                    Some(rotation) => RotatingFile::open(
+                        path,
+                        rotation.max_size,
+                        rotation.interval.map(Into::into),
+                        rotation.max_files,
+                    ),
+                    None => RotatingFile::open(path, None, None, 0),
+                }
+                .map_err(Error::FileError)?;
                 Ok(format.decorate_writer(file))
             }
         }
@@ -232,6 +454,7 @@ pub enum Error {
     #[cfg(feature = "gelf")]
     GelfConnectionFailed(io::Error),
     FileError(io::Error),
+    RotationRequiresFile,
 }
 
 impl Display for Error {
@@ -247,6 +470,7 @@ impl Display for Error {
             #[cfg(feature = "gelf")]
             Error::GelfConnectionFailed(_) => write!(f, "GELF connection failed"),
             Error::FileError(e) => write!(f, "failed to open the log file: {}", e),
+            Error::RotationRequiresFile => write!(f, "only the file outputs can be rotated"),
         }
     }
 }
@@ -260,6 +484,57 @@ impl error::Error for Error {
             #[cfg(feature = "gelf")]
             Error::GelfConnectionFailed(err) => Some(err),
             Error::FileError(err) => Some(err),
+            Error::RotationRequiresFile => None,
         }
     }
 }
+
+/// (de)serialize a filter level by its name
+pub(crate) mod filter_level_serde {
+    use crate::settings::LOG_FILTER_LEVEL_POSSIBLE_VALUES;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+    use slog::FilterLevel;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FilterLevel, D::Error> {
+        let variant = String::deserialize(deserializer)?;
+        variant
+            .parse()
+            .map_err(|_| D::Error::unknown_variant(&variant, &**LOG_FILTER_LEVEL_POSSIBLE_VALUES))
+    }
+
+    pub fn serialize<S: Serializer>(data: &FilterLevel, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(data.as_str())
+    }
+}
+
+/// (de)serialize the filter levels of the tasks by their names
+pub(crate) mod filter_level_map_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use slog::FilterLevel;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Level(#[serde(with = "super::filter_level_serde")] FilterLevel);
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, FilterLevel>, D::Error> {
+        let levels = BTreeMap::<String, Level>::deserialize(deserializer)?;
+        Ok(levels
+            .into_iter()
+            .map(|(task, Level(level))| (task, level))
+            .collect())
+    }
+
+    pub fn serialize<S: Serializer>(
+        data: &BTreeMap<String, FilterLevel>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        data.iter()
+            .map(|(task, level)| (task, Level(*level)))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+}
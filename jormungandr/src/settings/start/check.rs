@@ -0,0 +1,243 @@
+//! validation of the settings of the node without starting it
+
+use super::{Error, RawSettings};
+use crate::{
+    rest::load_tls_acceptor,
+    secure::NodeSecret,
+    settings::{logging::LogOutput, Block0Info, CommandLine},
+};
+use slog::{Discard, Logger};
+use std::{error, fs::File, path::Path};
+
+/// the outcome of the validation, printed as JSON by `--check-config`
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigReport {
+    pub valid: bool,
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigIssue {
+    /// the setting the issue is about, e.g. `p2p.public_address`
+    pub setting: String,
+    pub message: String,
+}
+
+impl ConfigReport {
+    fn error<S: Into<String>>(&mut self, setting: &str, message: S) {
+        self.errors.push(ConfigIssue {
+            setting: setting.to_owned(),
+            message: message.into(),
+        })
+    }
+
+    fn warning<S: Into<String>>(&mut self, setting: &str, message: S) {
+        self.warnings.push(ConfigIssue {
+            setting: setting.to_owned(),
+            message: message.into(),
+        })
+    }
+
+    fn finish(mut self) -> Self {
+        self.valid = self.errors.is_empty();
+        self
+    }
+}
+
+/// parse and validate the settings given by the command line and the
+/// configuration file, checking the files they refer to can be read. Nothing
+/// is created or modified.
+pub fn check_config(command_line: CommandLine) -> ConfigReport {
+    let mut report = ConfigReport::default();
+
+    let raw_settings = match RawSettings::load(command_line) {
+        Ok(raw_settings) => raw_settings,
+        Err(e) => {
+            report.error("config", e.to_string());
+            return report.finish();
+        }
+    };
+
+    for entry in raw_settings.log_settings().0 {
+        if let LogOutput::File(path) = &entry.output {
+            let dir = Path::new(path).parent().filter(|dir| dir != &Path::new(""));
+            if dir.map_or(false, |dir| !dir.is_dir()) {
+                report.error("log.output", format!("no directory to write {} in", path));
+            }
+        }
+    }
+
+    if let Some(config) = raw_settings.config() {
+        let p2p = &config.p2p;
+        let public_address = p2p.public_address.as_ref();
+        match (public_address, p2p.listen_address.as_ref()) {
+            (Some(public), None) if public.to_socketaddr().is_none() => report.error(
+                "p2p.public_address",
+                "the node listens on its public address when `p2p.listen_address` is not set, \
+                 it must be a TCP address",
+            ),
+            (Some(public), Some(listen)) => {
+                match (public.to_socketaddr(), listen.to_socketaddr()) {
+                    (Some(public), Some(listen)) if public.port() != listen.port() => report
+                        .warning(
+                            "p2p.listen_address",
+                            format!(
+                                "the node listens on the port {} but advertises the port {}",
+                                listen.port(),
+                                public.port()
+                            ),
+                        ),
+                    _ => {}
+                }
+            }
+            (None, Some(_)) => report.warning(
+                "p2p.public_address",
+                "the node listens without a public address, its peers cannot connect to it",
+            ),
+            _ => {}
+        }
+    }
+
+    // errors are reported, the warnings logged while loading the settings
+    // are not
+    let logger = Logger::root(Discard, o!());
+    let settings = match raw_settings.try_into_settings(&logger) {
+        Ok(settings) => settings,
+        Err(e) => {
+            report.error(setting_of(&e), e.to_string());
+            return report.finish();
+        }
+    };
+
+    if let Block0Info::Path(path) = &settings.block_0 {
+        if let Err(e) = File::open(path) {
+            report.error(
+                "genesis_block",
+                format!("cannot read {}: {}", path.display(), e),
+            );
+        }
+    }
+
+    // the genesis block is then fetched from the network
+    if let (Block0Info::Hash(_), Some(path)) = (&settings.block_0, &settings.block0_path) {
+        if let Err(e) = File::open(path) {
+            report.warning(
+                "block0_path",
+                format!("cannot read {}: {}", path.display(), e),
+            );
+        }
+    }
+
+    for path in settings.secrets.iter() {
+        if let Err(e) = NodeSecret::load_from_file(path, settings.secrets_passphrase.as_ref()) {
+            report.error("secret_files", format!("{}: {}", path.display(), e));
+        }
+    }
+
+    if let Some(storage) = settings.storage.as_ref() {
+        if storage.path.exists() && !storage.path.is_dir() {
+            report.error(
+                "storage",
+                format!("{} is not a directory", storage.path.display()),
+            );
+        }
+    }
+
+    if let Some(rest) = settings.rest.as_ref() {
+        if let Some(pkcs12) = rest.pkcs12.as_ref() {
+            if let Err(e) = load_tls_acceptor(Some(pkcs12.clone())) {
+                report.error("rest.pkcs12", describe(&e));
+            }
+        }
+        if rest.listen.is_none() && rest.unix_socket.is_none() {
+            report.error("rest", "neither `listen` nor `unix_socket` is set");
+        }
+        if let Some(socket) = rest.unix_socket.as_ref() {
+            if rest.pkcs12.is_some() {
+                report.warning("rest.unix_socket", "the unix socket is served without TLS");
+            }
+            let dir_missing = socket
+                .parent()
+                .map_or(false, |dir| !dir.as_os_str().is_empty() && !dir.is_dir());
+            if dir_missing {
+                report.error(
+                    "rest.unix_socket",
+                    format!("the directory of {} does not exist", socket.display()),
+                );
+            }
+        }
+    }
+
+    if let Some(remote) = settings.leadership.remote_enclave.as_ref() {
+        if !remote.socket_path.exists() {
+            report.warning(
+                "leadership.remote_enclave.socket_path",
+                format!(
+                    "{} does not exist, the enclave must be started before the node",
+                    remote.socket_path.display()
+                ),
+            );
+        }
+    }
+
+    let watches_addresses = settings
+        .notifications
+        .webhooks
+        .iter()
+        .any(|webhook| !webhook.addresses.is_empty());
+    if watches_addresses
+        && !settings
+            .storage
+            .as_ref()
+            .map_or(false, |storage| storage.index)
+    {
+        report.warning(
+            "notifications.webhooks.addresses",
+            "the `fragment_included` events need the chain index, enabled with `storage.index`",
+        );
+    }
+
+    report.finish()
+}
+
+fn setting_of(error: &Error) -> &'static str {
+    match error {
+        Error::ConfigIo { .. } | Error::Config { .. } | Error::EnvOverride { .. } => "config",
+        Error::IncludeIo { .. }
+        | Error::Include { .. }
+        | Error::InvalidInclude { .. }
+        | Error::IncludeCycle { .. } => "include",
+        Error::Rest { .. } => "rest",
+        Error::ExpectedBlock0Info | Error::TooMuchBlock0Info => "genesis_block",
+        Error::ListenAddressNotValid => "p2p.listen_address",
+        Error::InvalidWebhookUrl { .. } => "notifications.webhooks",
+        Error::ClientOnlyWithAddress => "p2p.listen",
+        Error::CoreWithoutRelays => "p2p.role",
+        Error::InvalidTrustedPeersUrl { .. } => "p2p.trusted_peers_url",
+        Error::TooMuchPublicId
+        | Error::PrivateIdIo { .. }
+        | Error::PrivateIdInvalid { .. }
+        | Error::PrivateIdPermissions { .. } => "p2p.private_id_file",
+        Error::SecretsPassphraseIo { .. } | Error::SecretsPassphrasePermissions { .. } => {
+            "secret_files"
+        }
+        Error::HsmPinIo { .. } | Error::HsmPinPermissions { .. } => "leadership.hsm",
+        Error::TooMuchEnclave => "leadership",
+        Error::InvalidDiskSpaceThresholds => "disk_space",
+        Error::ZeroDuration { setting } | Error::ZeroLayerLength { setting } => setting,
+        Error::UnknownRuntimeTask { .. } | Error::TooManyRuntimeThreads { .. } => "runtime",
+    }
+}
+
+/// the error followed by its sources
+fn describe(error: &dyn error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
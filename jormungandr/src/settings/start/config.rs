@@ -1,22 +1,44 @@
 use crate::{
-    network::p2p::{topic, Id, PolicyConfig},
-    settings::logging::{LogFormat, LogOutput},
+    network::p2p::{topic, Id, LayersConfig, PolicyConfig, Role},
+    notifier::ChainEventKind,
+    settings::logging::{filter_level_map_serde, LogFormat, LogOutput, LogRotation},
     settings::LOG_FILTER_LEVEL_POSSIBLE_VALUES,
 };
-use jormungandr_lib::{interfaces::Mempool, time::Duration};
+use chain_crypto::{Ed25519, PublicKey};
+use jormungandr_lib::{
+    crypto::{
+        hash::Hash,
+        serde::{deserialize_public, serialize_public},
+    },
+    interfaces::{Address as ChainAddress, Mempool},
+    schedule::Schedule,
+    time::Duration,
+};
 use poldercast;
 use serde::{de::Error as _, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use slog::FilterLevel;
-use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::SocketAddr,
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default)]
     pub secret_files: Vec<PathBuf>,
-    pub storage: Option<PathBuf>,
+    pub storage: Option<Storage>,
     pub log: Option<ConfigLogSettings>,
 
+    /// a local copy of the genesis block, read when the node is started with
+    /// `--genesis-block-hash` and the block is not in the storage, before
+    /// fetching it from the trusted peers
+    #[serde(default)]
+    pub block0_path: Option<PathBuf>,
+
     /// setting of the mempool, fragment logs and related data
     #[serde(default)]
     pub mempool: Mempool,
@@ -24,6 +46,10 @@ pub struct Config {
     #[serde(default)]
     pub leadership: Leadership,
 
+    /// the in-memory caches of the blockchain task
+    #[serde(default)]
+    pub cache: Cache,
+
     pub rest: Option<Rest>,
 
     #[serde(default)]
@@ -34,6 +60,102 @@ pub struct Config {
     /// the time interval with no blockchain updates after which alerts are thrown
     #[serde(default)]
     pub no_blockchain_updates_warning_interval: Option<Duration>,
+
+    /// the maximum number of blocks the tip may be rolled back by when
+    /// switching to a fork, unlimited if not set
+    #[serde(default)]
+    pub max_rollback_depth: Option<u64>,
+
+    /// the rule choosing the tip among the branches, `longest_chain` by
+    /// default. `density` is only meant for the test networks.
+    #[serde(default)]
+    pub chain_selection: ChainSelectionRule,
+
+    /// what the node does when one of its tasks fails
+    #[serde(default)]
+    pub supervision: Supervision,
+
+    /// the capacities of the message queues of the tasks
+    #[serde(default)]
+    pub intercom: Intercom,
+
+    /// the detection of the tasks which stopped responding
+    #[serde(default)]
+    pub watchdog: Watchdog,
+
+    /// the monitoring of the clock of the node against time servers
+    #[serde(default)]
+    pub clock_drift: ClockDrift,
+
+    /// the monitoring of the space left on the storage volume
+    #[serde(default)]
+    pub disk_space: DiskSpace,
+
+    /// when the node is considered synced and ready
+    #[serde(default)]
+    pub readiness: Readiness,
+
+    /// the webhooks notified of the chain events
+    #[serde(default)]
+    pub notifications: Notifications,
+
+    /// the actions run at the epoch transitions
+    #[serde(default)]
+    pub maintenance: Maintenance,
+
+    /// the stake distribution written at the epoch transitions, not written
+    /// if not set
+    pub stake_snapshots: Option<StakeSnapshots>,
+
+    /// the threads of the runtimes the tasks run in
+    #[serde(default)]
+    pub runtime: Runtime,
+}
+
+/// the blockchain storage, given either as the path of the storage directory
+/// or with the settings of the storage backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Storage {
+    Path(PathBuf),
+    Settings(StorageSettings),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageSettings {
+    /// path of the storage directory, it may be given on the command line
+    /// instead
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub backend: StorageBackend,
+    /// size in bytes of the backend's cache (sled only)
+    pub cache_capacity: Option<u64>,
+    /// interval between two flushes of the writes to the disk (sled only)
+    pub flush_interval: Option<Duration>,
+    /// removal of the blocks of abandoned forks (sled only)
+    pub pruning: Option<Pruning>,
+    /// index the transactions and addresses of the main chain
+    #[serde(default)]
+    pub index: bool,
+    /// the duration above which an operation on the storage is logged
+    pub slow_operation_warning: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pruning {
+    /// number of blocks below the tip within which no block is removed
+    pub depth: Option<u64>,
+    /// interval between two pruning runs
+    pub interval: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Sqlite,
+    Sled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -43,6 +165,14 @@ pub struct ConfigLogSettingsEntry {
     pub level: Option<FilterLevel>,
     pub format: Option<LogFormat>,
     pub output: Option<LogOutput>,
+    /// the levels of the tasks logging at another level than `level`
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        with = "filter_level_map_serde"
+    )]
+    pub modules: BTreeMap<String, FilterLevel>,
+    pub rotation: Option<LogRotation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -51,10 +181,28 @@ pub struct ConfigLogSettings(pub Vec<ConfigLogSettingsEntry>);
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct Rest {
-    pub listen: SocketAddr,
+    /// The TCP address to listen on, may be omitted if `unix_socket` is set
+    #[serde(default)]
+    pub listen: Option<SocketAddr>,
+    /// The path of a unix domain socket to listen on, in addition to or
+    /// instead of `listen`
+    #[serde(default)]
+    pub unix_socket: Option<PathBuf>,
     pub pkcs12: Option<PathBuf>,
     /// Enables CORS if provided
     pub cors: Option<Cors>,
+    /// Bearer token required by the administrative endpoints, such as
+    /// enrolling or retiring leaders. These endpoints are refused if not provided
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// The time to wait for a task of the node to reply to a query, such as
+    /// the network stats or the rewards history
+    #[serde(default)]
+    pub query_timeout: Option<Duration>,
+    /// The time to wait for a task of the node to reply to an administrative
+    /// request, such as enrolling a leader or reloading the configuration
+    #[serde(default)]
+    pub admin_timeout: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
@@ -79,11 +227,39 @@ pub struct P2pConfig {
     /// all network interfaces.
     pub listen_address: Option<Address>,
 
+    /// `address` by default. With `none` the node runs as a client only: it
+    /// connects to the peers but does not listen for their connections nor
+    /// advertise itself in the gossips
+    #[serde(default)]
+    pub listen: ListenMode,
+
     pub public_id: Option<Id>,
 
+    /// file containing the private ID the node ID is derived from, in place
+    /// of `public_id`. On unix, the file must only be accessible by its
+    /// owner.
+    #[serde(default)]
+    pub private_id_file: Option<PathBuf>,
+
     /// the rendezvous points for the peer to connect to in order to initiate
     /// the p2p discovery from.
     pub trusted_peers: Option<Vec<TrustedPeer>>,
+
+    /// a signed list of trusted peers fetched at startup, in addition to
+    /// the `trusted_peers`
+    #[serde(default)]
+    pub trusted_peers_url: Option<TrustedPeersUrl>,
+
+    /// `relay` by default. A `core` node connects only to its trusted peers,
+    /// its relays, and never advertises itself in the gossips
+    #[serde(default)]
+    pub role: Role,
+
+    /// blocks of the chain known in advance, the blocks up to the last one
+    /// are not fully verified when bootstrapping from the trusted peers
+    #[serde(default)]
+    pub trusted_checkpoints: Vec<TrustedCheckpoint>,
+
     /// the topic subscriptions
     ///
     /// When connecting to different nodes we will expose these too in order to
@@ -100,10 +276,55 @@ pub struct P2pConfig {
     #[serde(default)]
     pub allow_private_addresses: bool,
 
+    /// Whether the connections to the peers supporting it are compressed.
+    /// Disabled by default.
+    #[serde(default)]
+    pub compression: bool,
+
+    /// Whether the block announcements to a peer are sent before the
+    /// fragments to propagate to it. Enabled by default.
+    #[serde(default)]
+    pub block_priority: Option<bool>,
+
+    /// The number of recent blocks kept in memory to serve the peers,
+    /// 128 by default. The cache is disabled with 0.
+    #[serde(default)]
+    pub block_cache_capacity: Option<usize>,
+
+    /// how long a peer may send nothing while streaming the blocks or the
+    /// headers pulled from it before they are pulled from another peer,
+    /// 30 seconds by default
+    #[serde(default)]
+    pub pull_inactivity_timeout: Option<Duration>,
+
+    /// how long the connection to a peer may take to be established, the
+    /// protocol handshake and the subscriptions included, 30 seconds by
+    /// default
+    #[serde(default)]
+    pub connect_timeout: Option<Duration>,
+
+    /// how long a peer may take to respond to a request of the node, 15
+    /// seconds by default
+    #[serde(default)]
+    pub request_timeout: Option<Duration>,
+
+    /// The number of peers with the highest throughput the blocks of a
+    /// fetch are split across, 3 by default.
+    #[serde(default)]
+    pub block_fetch_peers: Option<usize>,
+
+    /// the rates of the items a peer may send on its subscriptions
+    #[serde(default)]
+    pub subscription_limits: SubscriptionLimits,
+
     /// setting for the policy
     #[serde(default)]
     pub policy: PolicyConfig,
 
+    /// the parameters of the poldercast layers of the topology
+    #[serde(default)]
+    pub layers: LayersConfig,
+
     /// set the maximum number of unreachable nodes to contact at a time for every
     /// new notification. The default value is 20.
     ///
@@ -128,6 +349,23 @@ pub struct P2pConfig {
     ///
     #[serde(default)]
     pub topology_force_reset_interval: Option<Duration>,
+
+    /// file the nodes of the topology are saved to when the node shuts down
+    /// gracefully, and loaded from on the next start to gossip with them in
+    /// addition to the trusted peers
+    #[serde(default)]
+    pub topology_file: Option<PathBuf>,
+
+    /// file the nodes connected to are recorded in, with the last time they
+    /// were connected to and seen in the gossips. The node gossips with them on
+    /// the next start and bootstraps from them when none of the trusted
+    /// peers can be reached.
+    #[serde(default)]
+    pub address_book: Option<PathBuf>,
+
+    /// file every connection attempt and close is appended to
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,6 +375,38 @@ pub struct TrustedPeer {
     pub id: Id,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustedPeersUrl {
+    /// the HTTPS URL of the list, in the format of `trusted_peers`
+    pub url: String,
+    /// the key the list is signed with, the signature is fetched from the
+    /// path of the list with the `.sig` suffix
+    #[serde(
+        serialize_with = "serialize_public",
+        deserialize_with = "deserialize_public"
+    )]
+    pub public_key: PublicKey<Ed25519>,
+}
+
+impl TrustedPeersUrl {
+    /// the URL, if it is a valid HTTPS URL
+    pub fn uri(&self) -> Option<hyper::Uri> {
+        let uri = self.url.parse::<hyper::Uri>().ok()?;
+        match uri.scheme_part().map(|scheme| scheme.as_str()) {
+            Some("https") if uri.host().is_some() => Some(uri),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustedCheckpoint {
+    pub chain_length: u32,
+    pub hash: Hash,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Leadership {
@@ -145,6 +415,335 @@ pub struct Leadership {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// delegate the leaders' schedules and block signatures to a separate
+    /// signer process instead of loading the secret files in the node
+    #[serde(default)]
+    pub remote_enclave: Option<RemoteEnclave>,
+    /// sign the blocks of the BFT leaders with the keys of a hardware
+    /// security module, through PKCS#11
+    #[serde(default)]
+    pub hsm: Option<Hsm>,
+    /// run the node as the standby of another node running the same leaders
+    #[serde(default)]
+    pub standby: Option<Standby>,
+    /// do not create blocks while the tip is more than this number of slots
+    /// behind the slot of the leader, the blocks are created on any tip if
+    /// not set
+    #[serde(default)]
+    pub max_slots_behind: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cache {
+    /// the maximum number of recent blocks whose ledger state is kept in
+    /// memory, unlimited if not set. The states of the blocks evicted are
+    /// computed again from the storage when needed.
+    pub ledger_states_capacity: Option<usize>,
+    /// time to live of a ledger state not accessed
+    pub ledger_states_ttl: Duration,
+    /// time to live of the chains of headers received from the network
+    /// whose blocks are not fetched yet
+    pub header_chains_ttl: Duration,
+    /// interval between 2 removals of the expired entries of the caches
+    pub purge_interval: Duration,
+}
+
+/// the number of messages each task queue holds before the senders wait, or
+/// the messages are dropped for the ones which cannot wait
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Intercom {
+    pub network: usize,
+    pub fragment: usize,
+    pub block: usize,
+    pub explorer: usize,
+    pub leaders: usize,
+    pub client: usize,
+    pub notifier: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Watchdog {
+    /// the time after which a task which did not check in, or did not read
+    /// the messages waiting in its queue, is reported as stuck
+    pub timeout: Duration,
+    /// abort the process when a task is stuck
+    pub abort: bool,
+}
+
+/// the rates of the items a peer may send on its subscriptions, above which
+/// the peer is throttled
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubscriptionLimits {
+    pub block_announcements: RateLimit,
+    pub fragments: RateLimit,
+    pub gossips: RateLimit,
+    /// the number of times a peer is throttled within `throttled_window`
+    /// before it is quarantined
+    pub max_throttled: u32,
+    /// the period over which the throttlings of a peer are counted
+    pub throttled_window: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// the items per second
+    pub rate: NonZeroU32,
+    /// the items a peer may send at once
+    pub burst: NonZeroU32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockDrift {
+    /// the time servers sampled, `pool.ntp.org:123` for instance. The clock
+    /// is not monitored if none is given.
+    pub servers: Vec<String>,
+    /// interval between two samplings of the servers
+    pub interval: Duration,
+    /// the drift of the clock above which a warning is logged
+    pub threshold: Duration,
+    /// do not create blocks while the drift is above the threshold
+    pub pause_leadership: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskSpace {
+    /// interval between two checks of the space left on the storage volume
+    pub interval: Duration,
+    /// the space left, in bytes, below which a warning is logged
+    pub soft_threshold: u64,
+    /// the space left, in bytes, below which the new fragments are refused
+    /// and the writes the node can do without are paused
+    pub hard_threshold: u64,
+}
+
+/// the threads of the tokio runtimes of the tasks, each task running in its
+/// own runtime. The tokio defaults are used for the numbers not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Runtime {
+    /// the worker threads of each runtime, one per CPU core by default
+    pub worker_threads: Option<NonZeroUsize>,
+    /// the threads of the blocking pool of each runtime, 100 by default
+    pub blocking_threads: Option<NonZeroUsize>,
+    /// the threads of the runtimes of the named tasks, overriding the ones
+    /// above
+    pub tasks: BTreeMap<String, RuntimeThreads>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeThreads {
+    pub worker_threads: Option<NonZeroUsize>,
+    pub blocking_threads: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Maintenance {
+    /// run the actions at these times instead of at the epoch transitions,
+    /// in the format of the crontab and in UTC
+    pub schedule: Option<Schedule>,
+    /// run the actions at the transitions to the epochs multiple of this
+    pub every_epochs: NonZeroU32,
+    /// time left after the transition before running the actions
+    pub delay: Duration,
+    /// reclaim the space of the removed blocks
+    pub compact_storage: bool,
+    /// write the chain to an archive at this path
+    pub export_chain: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StakeSnapshots {
+    /// the directory the snapshots are written to, one file per epoch
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub format: SnapshotFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    /// the whole snapshot, as served by the REST API
+    Json,
+    /// the stake of each delegator, one line per pool and account
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {
+    pub webhooks: Vec<Webhook>,
+    /// the number of times a notification is sent again after a failure
+    pub max_retries: u32,
+    /// the delay before the first retry, doubled for each following one
+    pub retry_backoff: Duration,
+    /// the time a webhook has to respond
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    /// the HTTP or HTTPS URL the events are posted to
+    pub url: String,
+    /// the events posted to the webhook, all of them if not set
+    #[serde(default)]
+    pub events: Option<Vec<ChainEventKind>>,
+    /// the addresses the `fragment_included` events are sent for
+    #[serde(default)]
+    pub addresses: Vec<ChainAddress>,
+}
+
+impl Webhook {
+    /// the URL, if it is a valid HTTP or HTTPS URL
+    pub fn uri(&self) -> Option<hyper::Uri> {
+        let uri = self.url.parse::<hyper::Uri>().ok()?;
+        match uri.scheme_part().map(|scheme| scheme.as_str()) {
+            Some("http") | Some("https") if uri.host().is_some() => Some(uri),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Readiness {
+    /// the node is synced once the date of its tip is this close to the
+    /// current time
+    pub max_tip_age: Duration,
+    /// the node is considered synced with an older tip when its tip did not
+    /// change for this long
+    pub sync_timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Supervision {
+    pub on_failure: OnFailure,
+    /// the number of failures of a task within `restart_window` after which
+    /// the node shuts down instead of restarting the task
+    pub max_restarts: u32,
+    pub restart_window: Duration,
+}
+
+/// whether the node accepts the connections of the peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenMode {
+    /// on `listen_address`, or else on `public_address`. The node runs as a
+    /// client only if neither is set.
+    Address,
+    /// never, the node runs as a client only
+    None,
+}
+
+impl Default for ListenMode {
+    fn default() -> Self {
+        ListenMode::Address
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainSelectionRule {
+    LongestChain,
+    Density,
+}
+
+impl Default for ChainSelectionRule {
+    fn default() -> Self {
+        ChainSelectionRule::LongestChain
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    Shutdown,
+    Restart,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteEnclave {
+    /// path to the unix socket the signer listens on
+    pub socket_path: PathBuf,
+    /// how long to wait for the signer to answer a query
+    #[serde(default = "default_remote_enclave_timeout")]
+    pub timeout: Duration,
+}
+
+fn default_remote_enclave_timeout() -> Duration {
+    Duration::new(5, 0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hsm {
+    /// path to the PKCS#11 module of the token
+    pub module: PathBuf,
+    /// the slot of the token
+    pub slot: u64,
+    /// path to the file with the user PIN of the token, only accessible by
+    /// its owner. The PIN is taken from the `JORMUNGANDR_HSM_PIN`
+    /// environment variable if not set
+    #[serde(default)]
+    pub pin_file: Option<PathBuf>,
+    /// the BFT leaders whose keys are in the token
+    pub keys: Vec<HsmKey>,
+    /// how long to wait for the token to sign a block
+    #[serde(default = "default_hsm_timeout")]
+    pub timeout: Duration,
+    /// the signatures taking longer than this are logged
+    #[serde(default = "default_hsm_latency_warning")]
+    pub latency_warning: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HsmKey {
+    /// the label of the private key object in the token
+    pub label: String,
+    /// the public key of the leader, in bech32
+    pub public_key: String,
+}
+
+fn default_hsm_timeout() -> Duration {
+    Duration::new(2, 0)
+}
+
+fn default_hsm_latency_warning() -> Duration {
+    Duration::from_millis(500)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Standby {
+    /// how long into a slot to wait for the block of the primary node
+    /// before creating it
+    #[serde(default = "default_standby_slot_grace")]
+    pub slot_grace: Duration,
+    /// how long without a block from the primary node before this node
+    /// stops waiting for it
+    #[serde(default = "default_standby_promotion_timeout")]
+    pub promotion_timeout: Duration,
+}
+
+fn default_standby_slot_grace() -> Duration {
+    Duration::new(1, 0)
+}
+
+fn default_standby_promotion_timeout() -> Duration {
+    Duration::new(600, 0)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -182,24 +781,179 @@ impl Default for P2pConfig {
         P2pConfig {
             public_address: None,
             listen_address: None,
+            listen: ListenMode::default(),
             public_id: None,
+            private_id_file: None,
             trusted_peers: None,
+            trusted_peers_url: None,
+            role: Role::default(),
+            trusted_checkpoints: Vec::new(),
             topics_of_interest: None,
             max_connections: None,
             allow_private_addresses: false,
+            compression: false,
+            block_priority: None,
+            block_cache_capacity: None,
+            pull_inactivity_timeout: None,
+            connect_timeout: None,
+            request_timeout: None,
+            block_fetch_peers: None,
+            subscription_limits: SubscriptionLimits::default(),
             policy: PolicyConfig::default(),
+            layers: LayersConfig::default(),
             max_unreachable_nodes_to_connect_per_event: None,
             gossip_interval: None,
             topology_force_reset_interval: None,
+            topology_file: None,
+            address_book: None,
+            audit_log: None,
+        }
+    }
+}
+
+impl Storage {
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Storage::Path(path) => Some(path),
+            Storage::Settings(settings) => settings.path.as_ref(),
         }
     }
 }
 
+impl Default for StorageBackend {
+    fn default() -> Self {
+        StorageBackend::Sqlite
+    }
+}
+
 impl Default for Leadership {
     fn default() -> Self {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            remote_enclave: None,
+            hsm: None,
+            standby: None,
+            max_slots_behind: None,
+        }
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            ledger_states_capacity: None,
+            ledger_states_ttl: Duration::new(5 * 24 * 3600, 0),
+            header_chains_ttl: Duration::new(3600, 0),
+            purge_interval: Duration::new(3600 / 4, 0),
+        }
+    }
+}
+
+impl Default for Intercom {
+    fn default() -> Self {
+        Intercom {
+            network: 32,
+            fragment: 1024,
+            block: 1000,
+            explorer: 1000,
+            leaders: 1000,
+            client: 1000,
+            notifier: 32,
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog {
+            timeout: Duration::new(60, 0),
+            abort: false,
+        }
+    }
+}
+
+impl Default for SubscriptionLimits {
+    fn default() -> Self {
+        let limit = |rate, burst| RateLimit {
+            rate: NonZeroU32::new(rate).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        };
+        SubscriptionLimits {
+            block_announcements: limit(10, 100),
+            fragments: limit(500, 2000),
+            gossips: limit(1, 10),
+            max_throttled: 100,
+            throttled_window: Duration::new(600, 0),
+        }
+    }
+}
+
+impl Default for ClockDrift {
+    fn default() -> Self {
+        ClockDrift {
+            servers: Vec::new(),
+            interval: Duration::new(600, 0),
+            threshold: Duration::new(0, 500_000_000),
+            pause_leadership: false,
+        }
+    }
+}
+
+impl Default for DiskSpace {
+    fn default() -> Self {
+        DiskSpace {
+            interval: Duration::new(60, 0),
+            soft_threshold: 2 * 1024 * 1024 * 1024,
+            hard_threshold: 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Maintenance {
+            schedule: None,
+            every_epochs: NonZeroU32::new(1).unwrap(),
+            delay: Duration::new(0, 0),
+            compact_storage: false,
+            export_chain: None,
+        }
+    }
+}
+
+impl Default for SnapshotFormat {
+    fn default() -> Self {
+        SnapshotFormat::Json
+    }
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Notifications {
+            webhooks: Vec::new(),
+            max_retries: 5,
+            retry_backoff: Duration::new(1, 0),
+            timeout: Duration::new(10, 0),
+        }
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness {
+            max_tip_age: Duration::new(600, 0),
+            sync_timeout: Duration::new(120, 0),
+        }
+    }
+}
+
+impl Default for Supervision {
+    fn default() -> Self {
+        Supervision {
+            on_failure: OnFailure::Shutdown,
+            max_restarts: 5,
+            restart_window: Duration::new(600, 0),
         }
     }
 }
@@ -0,0 +1,170 @@
+//! overrides of the settings of the configuration file by environment
+//! variables
+//!
+//! The name of a variable is the path of the setting in the configuration
+//! file, upper cased with `__` between the keys, behind the `JORMUNGANDR__`
+//! prefix: `JORMUNGANDR__REST__LISTEN` sets `rest.listen`. The items of a
+//! list are given by their index, e.g. `JORMUNGANDR__LOG__0__LEVEL`, the
+//! index following the last item adding one. The values are parsed as YAML,
+//! like in the configuration file.
+
+use serde_yaml::{Mapping, Value};
+use std::env;
+
+pub const ENV_PREFIX: &str = "JORMUNGANDR__";
+
+/// set the settings given by the environment variables in `config`, the
+/// content of the configuration file. Returns the name of the variable not
+/// matching the structure of the configuration, if any.
+pub fn apply_env_overrides(config: &mut Value) -> Result<(), String> {
+    let vars = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    apply_overrides(config, vars)
+}
+
+fn apply_overrides<I>(config: &mut Value, vars: I) -> Result<(), String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut overrides = Vec::new();
+    for (name, value) in vars {
+        if !name.starts_with(ENV_PREFIX) {
+            continue;
+        }
+        let path = name[ENV_PREFIX.len()..]
+            .split("__")
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        if path.iter().any(String::is_empty) {
+            return Err(name);
+        }
+        overrides.push((path, name, value));
+    }
+    // the items of the lists are added in the order of their indexes, which
+    // are compared as numbers: `10` comes after `9`
+    overrides.sort_by_cached_key(|(path, _, _)| {
+        path.iter()
+            .map(|key| (key.parse::<usize>().ok(), key.clone()))
+            .collect::<Vec<_>>()
+    });
+
+    for (path, name, value) in overrides {
+        let value = serde_yaml::from_str(&value).unwrap_or(Value::String(value));
+        set(config, &path, value).map_err(|()| name)?;
+    }
+    Ok(())
+}
+
+fn set(target: &mut Value, path: &[String], value: Value) -> Result<(), ()> {
+    let (key, path) = match path.split_first() {
+        None => {
+            *target = value;
+            return Ok(());
+        }
+        Some(split) => split,
+    };
+
+    if target.is_null() {
+        *target = match key.parse::<usize>() {
+            Ok(_) => Value::Sequence(Vec::new()),
+            Err(_) => Value::Mapping(Mapping::new()),
+        };
+    }
+    match target {
+        Value::Mapping(mapping) => {
+            let key = Value::String(key.clone());
+            if !mapping.contains_key(&key) {
+                mapping.insert(key.clone(), Value::Null);
+            }
+            set(mapping.get_mut(&key).unwrap(), path, value)
+        }
+        Value::Sequence(sequence) => {
+            let index = key.parse::<usize>().map_err(|_| ())?;
+            if index == sequence.len() {
+                sequence.push(Value::Null);
+            }
+            set(sequence.get_mut(index).ok_or(())?, path, value)
+        }
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(config: &str, vars: &[(&str, &str)]) -> Result<Value, String> {
+        let mut config = serde_yaml::from_str(config).unwrap();
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        apply_overrides(&mut config, vars).map(|()| config)
+    }
+
+    fn yaml(value: &str) -> Value {
+        serde_yaml::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn nested_settings_are_set() {
+        let config = apply(
+            "rest:\n  listen: 127.0.0.1:8443\n",
+            &[
+                ("JORMUNGANDR__REST__LISTEN", "0.0.0.0:8443"),
+                ("JORMUNGANDR__P2P__TOPICS_OF_INTEREST__BLOCKS", "high"),
+                ("OTHER__REST__LISTEN", "ignored"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            yaml("rest:\n  listen: 0.0.0.0:8443\np2p:\n  topics_of_interest:\n    blocks: high\n")
+        );
+    }
+
+    #[test]
+    fn list_items_are_set_in_the_order_of_their_indexes() {
+        let mut vars = (0..12)
+            .map(|i| (format!("JORMUNGANDR__LOG__{}__LEVEL", i), i.to_string()))
+            .collect::<Vec<_>>();
+        vars.reverse();
+        let mut config = yaml("log:\n  - level: info\n");
+        apply_overrides(&mut config, vars).unwrap();
+        let levels = (0..12)
+            .map(|i| format!("  - level: {}\n", i))
+            .collect::<String>();
+        assert_eq!(config, yaml(&format!("log:\n{}", levels)));
+    }
+
+    #[test]
+    fn index_past_the_end_of_the_list_is_refused() {
+        let result = apply("log: []\n", &[("JORMUNGANDR__LOG__1__LEVEL", "info")]);
+        assert_eq!(result, Err("JORMUNGANDR__LOG__1__LEVEL".to_owned()));
+    }
+
+    #[test]
+    fn empty_key_is_refused() {
+        let result = apply("{}", &[("JORMUNGANDR__REST____LISTEN", "0.0.0.0:8443")]);
+        assert_eq!(result, Err("JORMUNGANDR__REST____LISTEN".to_owned()));
+    }
+
+    #[test]
+    fn values_are_parsed_as_yaml() {
+        let config = apply(
+            "{}",
+            &[
+                ("JORMUNGANDR__A", "42"),
+                ("JORMUNGANDR__B", "true"),
+                ("JORMUNGANDR__C", "[1, 2]"),
+                ("JORMUNGANDR__D", "0.0.0.0:8443"),
+                ("JORMUNGANDR__E", "not: [valid"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config["a"], Value::Number(42.into()));
+        assert_eq!(config["b"], Value::Bool(true));
+        assert_eq!(config["c"], yaml("[1, 2]"));
+        assert_eq!(config["d"], Value::String("0.0.0.0:8443".to_owned()));
+        assert_eq!(config["e"], Value::String("not: [valid".to_owned()));
+    }
+}
@@ -0,0 +1,96 @@
+//! the `include` setting of the configuration file
+//!
+//! The files listed in `include` are merged in that order, each one
+//! overriding the settings of the previous ones, and the including file
+//! overrides them all. The mappings are merged key by key, any other value
+//! (including the lists) replaces the one it overrides. The paths are
+//! relative to the directory of the including file, which may include
+//! files itself.
+
+use super::Error;
+use serde_yaml::Value;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+const INCLUDE_KEY: &str = "include";
+
+/// read the configuration file at `path` merged with the files it includes
+pub fn load_with_includes(path: &Path) -> Result<Value, Error> {
+    load(path, &mut Vec::new())
+}
+
+fn load(path: &Path, including: &mut Vec<PathBuf>) -> Result<Value, Error> {
+    let mut config: Value = serde_yaml::from_reader(File::open(path)?)?;
+
+    let includes = match &mut config {
+        Value::Mapping(mapping) => mapping.remove(&Value::String(INCLUDE_KEY.to_owned())),
+        _ => None,
+    };
+    let includes = match includes {
+        None | Some(Value::Null) => return Ok(config),
+        Some(Value::Sequence(includes)) => includes,
+        Some(_) => return Err(invalid_include(path)),
+    };
+
+    // the cycles are found on the canonical paths, the same file may be
+    // reached through different relative paths
+    including.push(path.canonicalize()?);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = Value::Null;
+    for include in includes {
+        let include = match include {
+            Value::String(include) => dir.join(include),
+            _ => return Err(invalid_include(path)),
+        };
+        let included = load_include(&include, including).map_err(|e| match e {
+            Error::ConfigIo { source } => Error::IncludeIo {
+                path: include.display().to_string(),
+                source,
+            },
+            Error::Config { source } => Error::Include {
+                path: include.display().to_string(),
+                source,
+            },
+            e => e,
+        })?;
+        merge(&mut merged, included);
+    }
+    including.pop();
+
+    merge(&mut merged, config);
+    Ok(merged)
+}
+
+fn load_include(include: &Path, including: &mut Vec<PathBuf>) -> Result<Value, Error> {
+    if including.contains(&include.canonicalize()?) {
+        return Err(Error::IncludeCycle {
+            path: include.display().to_string(),
+        });
+    }
+    load(include, including)
+}
+
+fn invalid_include(path: &Path) -> Error {
+    Error::InvalidInclude {
+        path: path.display().to_string(),
+    }
+}
+
+/// merge `overriding` into `target`
+fn merge(target: &mut Value, overriding: Value) {
+    match (target, overriding) {
+        (Value::Mapping(target), Value::Mapping(overriding)) => {
+            for (key, value) in overriding {
+                match target.get_mut(&key) {
+                    Some(target) => merge(target, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, overriding) => *target = overriding,
+    }
+}
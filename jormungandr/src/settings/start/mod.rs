@@ -1,20 +1,52 @@
+mod check;
 pub mod config;
+mod env_overrides;
+mod include;
 pub mod network;
 
-use self::config::{Config, Leadership};
-pub use self::config::{Cors, Rest};
+pub use self::check::check_config;
+use self::config::{
+    Cache, ChainSelectionRule, ClockDrift, Config, DiskSpace, Intercom, Leadership, ListenMode,
+    Maintenance, Notifications, OnFailure, Readiness, Runtime, StakeSnapshots, Supervision,
+    Watchdog,
+};
+pub use self::config::{Cors, Rest, StorageBackend};
+use self::env_overrides::apply_env_overrides;
+use self::include::load_with_includes;
 use self::network::Protocol;
+use crate::blockcfg::HeaderHash;
+use crate::blockchain::{ChainSelection, Density, LongestChain};
+use crate::network::p2p::{Id, LayersConfig, Role};
 use crate::rest::Error as RestError;
+use crate::secure::owner_only;
 use crate::settings::logging::{LogFormat, LogOutput, LogSettings, LogSettingsEntry};
 use crate::settings::{command_arguments::*, Block0Info};
-use jormungandr_lib::interfaces::Mempool;
+use crate::start_up::LedgerDumpFormat;
+use crate::utils::task::{RestartPolicy, RuntimeThreads};
+use jormungandr_lib::crypto::{
+    node_id::{PrivateId, PrivateIdError},
+    passphrase::Passphrase,
+};
+use jormungandr_lib::interfaces::{FragmentLimits, Mempool};
+use serde_yaml::Value;
 use slog::{FilterLevel, Logger};
-use std::{fs::File, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 const DEFAULT_FILTER_LEVEL: FilterLevel = FilterLevel::Info;
 const DEFAULT_LOG_FORMAT: LogFormat = LogFormat::Plain;
 const DEFAULT_LOG_OUTPUT: LogOutput = LogOutput::Stderr;
 const DEFAULT_NO_BLOCKCHAIN_UPDATES_WARNING_INTERVAL: u64 = 1800; // 30 min
+const DEFAULT_PRUNING_DEPTH: u64 = 2160;
+const DEFAULT_PRUNING_INTERVAL: u64 = 3600; // 1 hour
+const DEFAULT_SLOW_STORAGE_OPERATION: u64 = 1000; // 1 second
+const SECRETS_PASSPHRASE_ENV: &str = "JORMUNGANDR_SECRETS_PASSPHRASE";
+const HSM_PIN_ENV: &str = "JORMUNGANDR_HSM_PIN";
 
 custom_error! {pub Error
    ConfigIo { source: std::io::Error } = "Cannot read the node configuration file: {source}",
@@ -22,20 +54,96 @@ custom_error! {pub Error
    Rest { source: RestError } = "The Rest configuration is invalid: {source}",
    ExpectedBlock0Info = "Cannot start the node without the information to retrieve the genesis block",
    TooMuchBlock0Info = "Use only `--genesis-block-hash' or `--genesis-block'",
+   IncludeIo { path: String, source: std::io::Error } = "Cannot read the configuration file {path} included by the node configuration: {source}",
+   Include { path: String, source: serde_yaml::Error } = "Error while parsing the configuration file {path} included by the node configuration: {source}",
+   InvalidInclude { path: String } = "In the configuration file {path}, `include` must be a list of paths",
+   IncludeCycle { path: String } = "The configuration file {path} includes itself",
+   EnvOverride { variable: String } = "The environment variable {variable} does not match the structure of the node configuration",
    ListenAddressNotValid = "In the node configuration file, the `p2p.listen_address` value is not a valid address. Use format `/ip4/x.x.x.x/tcp/4920",
+   InvalidWebhookUrl { url: String } = "In the node configuration file, the webhook URL {url} is not a valid HTTP or HTTPS URL",
+   ClientOnlyWithAddress = "In the node configuration file, a node with `p2p.listen: none` cannot have a `p2p.public_address` or a `p2p.listen_address`",
+   CoreWithoutRelays = "In the node configuration file, a `core` node (`p2p.role`) needs its relays in `p2p.trusted_peers`",
+   InvalidTrustedPeersUrl { url: String } = "In the node configuration file, the `p2p.trusted_peers_url` URL {url} is not a valid HTTPS URL",
+   TooMuchPublicId = "In the node configuration file, use only `p2p.public_id` or `p2p.private_id_file`",
+   PrivateIdIo { path: String, source: std::io::Error } = "Cannot read the private node ID file {path}: {source}",
+   PrivateIdInvalid { path: String, source: PrivateIdError } = "The private node ID file {path} is invalid: {source}",
+   PrivateIdPermissions { path: String } = "The private node ID file {path} is accessible by other users, restrict it to its owner with `chmod 600`",
+   SecretsPassphraseIo { path: String, source: std::io::Error } = "Cannot read the passphrase file {path}: {source}",
+   SecretsPassphrasePermissions { path: String } = "The passphrase file {path} is accessible by other users, restrict it to its owner with `chmod 600`",
+   HsmPinIo { path: String, source: std::io::Error } = "Cannot read the PIN file {path} of `leadership.hsm`: {source}",
+   HsmPinPermissions { path: String } = "The PIN file {path} of `leadership.hsm` is accessible by other users, restrict it to its owner with `chmod 600`",
+   TooMuchEnclave = "In the node configuration file, use only `leadership.remote_enclave` or `leadership.hsm`",
+   InvalidDiskSpaceThresholds = "In the node configuration file, `disk_space.hard_threshold` cannot be above `disk_space.soft_threshold`",
+   ZeroDuration { setting: &'static str } = "In the node configuration file, `{setting}` cannot be 0",
+   ZeroLayerLength { setting: &'static str } = "In the node configuration file, `{setting}` cannot be 0, a layer keeps and gossips at least one node",
+   UnknownRuntimeTask { task: String } = "In the node configuration file, `runtime.tasks` names `{task}`, which is not a task running in a runtime of its own",
+   TooManyRuntimeThreads { setting: String } = "In the node configuration file, `{setting}` cannot be above 32768",
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
-    pub storage: Option<PathBuf>,
+    pub storage: Option<Storage>,
     pub block_0: Block0Info,
+    /// the local copy of the genesis block given with its hash
+    pub block0_path: Option<PathBuf>,
     pub secrets: Vec<PathBuf>,
+    /// the passphrase of the encrypted secret files
+    pub secrets_passphrase: Option<Passphrase>,
+    /// the user PIN of the token of `leadership.hsm`
+    pub hsm_pin: Option<Passphrase>,
     pub rest: Option<Rest>,
     pub mempool: Mempool,
     pub leadership: Leadership,
+    pub cache: Cache,
     pub explorer: bool,
     pub no_blockchain_updates_warning_interval: std::time::Duration,
+    pub max_rollback_depth: Option<u64>,
+    pub chain_selection: Arc<dyn ChainSelection>,
+    pub export_chain: Option<PathBuf>,
+    pub import_chain: Option<PathBuf>,
+    pub storage_check: bool,
+    pub dump_ledger: Option<LedgerDump>,
+    pub force_resync: bool,
+    pub rebuild_index: bool,
+    pub check_index: bool,
+    pub restart_policy: RestartPolicy,
+    pub intercom: Intercom,
+    pub watchdog: Watchdog,
+    pub clock_drift: ClockDrift,
+    pub disk_space: DiskSpace,
+    pub readiness: Readiness,
+    pub notifications: Notifications,
+    pub maintenance: Maintenance,
+    pub stake_snapshots: Option<StakeSnapshots>,
+    pub runtime_threads: RuntimeThreads,
+    /// the threads of the runtimes of the named tasks
+    pub task_runtime_threads: HashMap<String, RuntimeThreads>,
+}
+
+/// the blockchain storage on disk
+pub struct Storage {
+    pub path: PathBuf,
+    pub backend: StorageBackend,
+    pub cache_capacity: Option<u64>,
+    pub flush_interval: Option<std::time::Duration>,
+    pub pruning: Option<Pruning>,
+    pub index: bool,
+    pub slow_operation_warning: std::time::Duration,
+}
+
+pub struct Pruning {
+    pub depth: u64,
+    pub interval: std::time::Duration,
+}
+
+/// the export of the ledger state requested on the command line
+#[derive(Clone)]
+pub struct LedgerDump {
+    pub dir: PathBuf,
+    /// the block to export the ledger state of, the tip if not set
+    pub at: Option<HeaderHash>,
+    pub format: LedgerDumpFormat,
 }
 
 pub struct RawSettings {
@@ -45,8 +153,15 @@ pub struct RawSettings {
 
 impl RawSettings {
     pub fn load(command_line: CommandLine) -> Result<Self, Error> {
-        let config = if let Some(node_config) = &command_line.start_arguments.node_config {
-            Some(serde_yaml::from_reader(File::open(node_config)?)?)
+        let node_config = &command_line.start_arguments.node_config;
+        let mut config = if let Some(node_config) = node_config {
+            load_with_includes(node_config)?
+        } else {
+            Value::Null
+        };
+        apply_env_overrides(&mut config).map_err(|variable| Error::EnvOverride { variable })?;
+        let config = if node_config.is_some() || !config.is_null() {
+            Some(serde_yaml::from_value(config)?)
         } else {
             None
         };
@@ -56,6 +171,11 @@ impl RawSettings {
         })
     }
 
+    /// the content of the configuration file, if one was given
+    pub fn config(&self) -> Option<&Config> {
+        self.config.as_ref()
+    }
+
     pub fn log_settings(&self) -> LogSettings {
         let mut entries = Vec::new();
 
@@ -65,6 +185,8 @@ impl RawSettings {
                     level: entry.level.clone().unwrap_or(DEFAULT_FILTER_LEVEL),
                     format: entry.format.clone().unwrap_or(DEFAULT_LOG_FORMAT),
                     output: entry.output.clone().unwrap_or(DEFAULT_LOG_OUTPUT),
+                    modules: entry.modules.clone(),
+                    rotation: entry.rotation.clone(),
                 })
             });
         }
@@ -78,6 +200,8 @@ impl RawSettings {
                 level: cmd_level.unwrap_or(DEFAULT_FILTER_LEVEL),
                 format: cmd_format.unwrap_or(DEFAULT_LOG_FORMAT),
                 output: cmd_output.unwrap_or(DEFAULT_LOG_OUTPUT),
+                modules: BTreeMap::new(),
+                rotation: None,
             });
         }
 
@@ -86,6 +210,8 @@ impl RawSettings {
                 level: DEFAULT_FILTER_LEVEL,
                 format: DEFAULT_LOG_FORMAT,
                 output: DEFAULT_LOG_OUTPUT,
+                modules: BTreeMap::new(),
+                rotation: None,
             });
         }
 
@@ -97,14 +223,18 @@ impl RawSettings {
         let config_rest_opt = self.config.as_ref().and_then(|cfg| cfg.rest.as_ref());
         match (config_rest_opt, cmd_listen_opt) {
             (Some(config_rest), Some(cmd_listen)) => Some(Rest {
-                listen: cmd_listen,
+                listen: Some(cmd_listen),
                 ..config_rest.clone()
             }),
             (Some(config_rest), None) => Some(config_rest.clone()),
             (None, Some(cmd_listen)) => Some(Rest {
-                listen: cmd_listen,
+                listen: Some(cmd_listen),
+                unix_socket: None,
                 pkcs12: None,
                 cors: None,
+                admin_token: None,
+                query_timeout: None,
+                admin_timeout: None,
             }),
             (None, None) => None,
         }
@@ -124,14 +254,7 @@ impl RawSettings {
         let command_arguments = &command_line.start_arguments;
         let network = generate_network(&command_arguments, &config)?;
 
-        let storage = match (
-            command_arguments.storage.as_ref(),
-            config.as_ref().map_or(None, |cfg| cfg.storage.as_ref()),
-        ) {
-            (Some(path), _) => Some(path.clone()),
-            (None, Some(path)) => Some(path.clone()),
-            (None, None) => None,
-        };
+        let storage = generate_storage(&command_arguments, &config);
 
         let mut secrets = command_arguments.secret.clone();
         if let Some(secret_files) = config.as_ref().map(|cfg| cfg.secret_files.clone()) {
@@ -144,6 +267,57 @@ impl RawSettings {
                 "Node started without path to the stored secret keys (not a stake pool or a BFT leader)"
             );
         };
+        let secrets_passphrase = read_passphrase(
+            command_arguments.secrets_passphrase_file.as_ref(),
+            SECRETS_PASSPHRASE_ENV,
+            |path, source| Error::SecretsPassphraseIo { path, source },
+            |path| Error::SecretsPassphrasePermissions { path },
+        )?;
+
+        let leadership = config
+            .as_ref()
+            .map_or(Leadership::default(), |cfg| cfg.leadership.clone());
+        if leadership.remote_enclave.is_some() && leadership.hsm.is_some() {
+            return Err(Error::TooMuchEnclave);
+        }
+        let hsm_pin = match &leadership.hsm {
+            Some(hsm) => read_passphrase(
+                hsm.pin_file.as_ref(),
+                HSM_PIN_ENV,
+                |path, source| Error::HsmPinIo { path, source },
+                |path| Error::HsmPinPermissions { path },
+            )?,
+            None => None,
+        };
+
+        let notifications = config
+            .as_ref()
+            .map_or(Notifications::default(), |cfg| cfg.notifications.clone());
+        if let Some(webhook) = notifications
+            .webhooks
+            .iter()
+            .find(|webhook| webhook.uri().is_none())
+        {
+            return Err(Error::InvalidWebhookUrl {
+                url: webhook.url.clone(),
+            });
+        }
+        let disk_space = config
+            .as_ref()
+            .map_or(DiskSpace::default(), |cfg| cfg.disk_space.clone());
+        check_disk_space(&disk_space)?;
+        let clock_drift = config
+            .as_ref()
+            .map_or(ClockDrift::default(), |cfg| cfg.clock_drift.clone());
+        check_nonzero(clock_drift.interval, "clock_drift.interval")?;
+        let watchdog = config
+            .as_ref()
+            .map_or(Watchdog::default(), |cfg| cfg.watchdog.clone());
+        check_nonzero(watchdog.timeout, "watchdog.timeout")?;
+        let runtime = config
+            .as_ref()
+            .map_or(Runtime::default(), |cfg| cfg.runtime.clone());
+        check_runtime(&runtime)?;
 
         let block_0 = match (
             &command_arguments.block_0_path,
@@ -165,15 +339,19 @@ impl RawSettings {
         Ok(Settings {
             storage,
             block_0,
+            block0_path: config.as_ref().and_then(|cfg| cfg.block0_path.clone()),
             network,
             secrets,
+            secrets_passphrase,
+            hsm_pin,
             rest,
             mempool: config
                 .as_ref()
                 .map_or(Mempool::default(), |cfg| cfg.mempool.clone()),
-            leadership: config
+            leadership,
+            cache: config
                 .as_ref()
-                .map_or(Leadership::default(), |cfg| cfg.leadership.clone()),
+                .map_or(Cache::default(), |cfg| cfg.cache.clone()),
             explorer,
             no_blockchain_updates_warning_interval: config
                 .as_ref()
@@ -182,10 +360,172 @@ impl RawSettings {
                 .unwrap_or(std::time::Duration::from_secs(
                     DEFAULT_NO_BLOCKCHAIN_UPDATES_WARNING_INTERVAL,
                 )),
+            max_rollback_depth: config.as_ref().and_then(|cfg| cfg.max_rollback_depth),
+            chain_selection: match config.as_ref().map(|cfg| cfg.chain_selection) {
+                Some(ChainSelectionRule::Density) => Arc::new(Density),
+                Some(ChainSelectionRule::LongestChain) | None => Arc::new(LongestChain),
+            },
+            export_chain: command_arguments.export_chain.clone(),
+            import_chain: command_arguments.import_chain.clone(),
+            storage_check: command_arguments.storage_check,
+            dump_ledger: command_arguments.dump_ledger.clone().map(|dir| LedgerDump {
+                dir,
+                at: command_arguments.dump_ledger_at,
+                format: command_arguments.dump_ledger_format,
+            }),
+            force_resync: command_arguments.force_resync,
+            rebuild_index: command_arguments.rebuild_index,
+            check_index: command_arguments.check_index,
+            restart_policy: config
+                .as_ref()
+                .map_or(Supervision::default(), |cfg| cfg.supervision.clone())
+                .into(),
+            intercom: config
+                .as_ref()
+                .map_or(Intercom::default(), |cfg| cfg.intercom.clone()),
+            watchdog,
+            clock_drift,
+            disk_space,
+            readiness: config
+                .as_ref()
+                .map_or(Readiness::default(), |cfg| cfg.readiness.clone()),
+            notifications,
+            maintenance: config
+                .as_ref()
+                .map_or(Maintenance::default(), |cfg| cfg.maintenance.clone()),
+            stake_snapshots: config.as_ref().and_then(|cfg| cfg.stake_snapshots.clone()),
+            runtime_threads: config::RuntimeThreads {
+                worker_threads: runtime.worker_threads,
+                blocking_threads: runtime.blocking_threads,
+            }
+            .into(),
+            task_runtime_threads: runtime
+                .tasks
+                .into_iter()
+                .map(|(task, threads)| (task, threads.into()))
+                .collect(),
         })
     }
 }
 
+impl From<config::RuntimeThreads> for RuntimeThreads {
+    fn from(threads: config::RuntimeThreads) -> Self {
+        RuntimeThreads {
+            core_threads: threads.worker_threads.map(|n| n.get()),
+            blocking_threads: threads.blocking_threads.map(|n| n.get()),
+        }
+    }
+}
+
+impl From<Supervision> for RestartPolicy {
+    fn from(supervision: Supervision) -> Self {
+        match supervision.on_failure {
+            OnFailure::Shutdown => RestartPolicy::Shutdown,
+            OnFailure::Restart => RestartPolicy::Restart {
+                max_restarts: supervision.max_restarts,
+                window: supervision.restart_window.into(),
+            },
+        }
+    }
+}
+
+fn generate_storage(
+    command_arguments: &StartArguments,
+    config: &Option<Config>,
+) -> Option<Storage> {
+    let config_storage = config.as_ref().and_then(|cfg| cfg.storage.as_ref());
+    let path = command_arguments
+        .storage
+        .as_ref()
+        .or_else(|| config_storage.and_then(|storage| storage.path()))?
+        .clone();
+
+    match config_storage {
+        Some(config::Storage::Settings(settings)) => Some(Storage {
+            path,
+            backend: settings.backend,
+            cache_capacity: settings.cache_capacity,
+            flush_interval: settings.flush_interval.map(Into::into),
+            pruning: settings.pruning.as_ref().map(|pruning| Pruning {
+                depth: pruning.depth.unwrap_or(DEFAULT_PRUNING_DEPTH),
+                interval: pruning
+                    .interval
+                    .map(Into::into)
+                    .unwrap_or(std::time::Duration::from_secs(DEFAULT_PRUNING_INTERVAL)),
+            }),
+            index: settings.index,
+            slow_operation_warning: settings.slow_operation_warning.map(Into::into).unwrap_or(
+                std::time::Duration::from_millis(DEFAULT_SLOW_STORAGE_OPERATION),
+            ),
+        }),
+        Some(config::Storage::Path(_)) | None => Some(Storage {
+            path,
+            backend: StorageBackend::default(),
+            cache_capacity: None,
+            flush_interval: None,
+            pruning: None,
+            index: false,
+            slow_operation_warning: std::time::Duration::from_millis(
+                DEFAULT_SLOW_STORAGE_OPERATION,
+            ),
+        }),
+    }
+}
+
+/// read the private ID of the node from its file and derive the node ID
+/// from it, refusing the files other users can access as the ID of a node
+/// is not to be impersonated
+pub fn read_private_id(path: &Path) -> Result<Id, Error> {
+    let io_error = |source| Error::PrivateIdIo {
+        path: path.display().to_string(),
+        source,
+    };
+    let metadata = std::fs::metadata(path).map_err(io_error)?;
+    if !owner_only(&metadata) {
+        return Err(Error::PrivateIdPermissions {
+            path: path.display().to_string(),
+        });
+    }
+    let private_id: PrivateId = std::fs::read_to_string(path)
+        .map_err(io_error)?
+        .parse()
+        .map_err(|source| Error::PrivateIdInvalid {
+            path: path.display().to_string(),
+            source,
+        })?;
+    Ok(private_id
+        .public_id()
+        .to_string()
+        .parse()
+        .expect("the derived node ID is a valid node ID"))
+}
+
+/// a passphrase or a PIN, from its file or else from the environment
+/// `variable`. The variable is then removed so the processes started by the
+/// node do not inherit it.
+fn read_passphrase(
+    path: Option<&PathBuf>,
+    variable: &str,
+    io_error: impl Fn(String, std::io::Error) -> Error,
+    permissions_error: impl Fn(String) -> Error,
+) -> Result<Option<Passphrase>, Error> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let passphrase = env::var(variable).ok();
+            env::remove_var(variable);
+            return Ok(passphrase.map(|passphrase| Passphrase::new(passphrase.into_bytes())));
+        }
+    };
+    let io_error = |source| io_error(path.display().to_string(), source);
+    let metadata = std::fs::metadata(path).map_err(io_error)?;
+    if !owner_only(&metadata) {
+        return Err(permissions_error(path.display().to_string()));
+    }
+    let passphrase = std::fs::read(path).map_err(io_error)?;
+    Ok(Some(Passphrase::new(passphrase)))
+}
+
 fn generate_network(
     command_arguments: &StartArguments,
     config: &Option<Config>,
@@ -204,9 +544,34 @@ fn generate_network(
         p2p.trusted_peers = Some(command_arguments.trusted_peer.clone())
     }
 
+    if let Some(timeout) = p2p.pull_inactivity_timeout {
+        check_nonzero(timeout, "p2p.pull_inactivity_timeout")?;
+    }
+    check_nonzero(
+        p2p.subscription_limits.throttled_window,
+        "p2p.subscription_limits.throttled_window",
+    )?;
+    check_layers(&p2p.layers)?;
+
+    // a node to which the peers cannot connect runs as a client only
+    let client_only = match p2p.listen {
+        ListenMode::None => {
+            if p2p.public_address.is_some() || p2p.listen_address.is_some() {
+                return Err(Error::ClientOnlyWithAddress);
+            }
+            true
+        }
+        ListenMode::Address => p2p.public_address.is_none() && p2p.listen_address.is_none(),
+    };
+
     let mut profile = poldercast::NodeProfileBuilder::new();
 
-    if let Some(id) = p2p.public_id {
+    let public_id = match (p2p.public_id, &p2p.private_id_file) {
+        (Some(_), Some(_)) => return Err(Error::TooMuchPublicId),
+        (None, Some(path)) => Some(read_private_id(path)?),
+        (public_id, None) => public_id,
+    };
+    if let Some(id) = public_id {
         profile.id(id.into());
     };
 
@@ -225,8 +590,32 @@ fn generate_network(
         profile.add_subscription(sub);
     }
 
+    if p2p.role == Role::Core
+        && p2p.trusted_peers.as_ref().map_or(true, Vec::is_empty)
+        && p2p.trusted_peers_url.is_none()
+    {
+        return Err(Error::CoreWithoutRelays);
+    }
+
+    let trusted_peers_url = match p2p.trusted_peers_url.as_ref() {
+        None => None,
+        Some(source) => match source.uri() {
+            Some(uri) => Some(network::TrustedPeersUrl {
+                uri,
+                public_key: source.public_key.clone(),
+            }),
+            None => {
+                return Err(Error::InvalidTrustedPeersUrl {
+                    url: source.url.clone(),
+                })
+            }
+        },
+    };
+
     let network = network::Configuration {
         profile: profile.build(),
+        role: p2p.role,
+        client_only,
         listen_address: match &p2p.listen_address {
             None => None,
             Some(v) => {
@@ -244,20 +633,415 @@ fn generate_network(
             .into_iter()
             .map(Into::into)
             .collect(),
+        trusted_peers_url,
+        trusted_checkpoints: p2p
+            .trusted_checkpoints
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect(),
         protocol: Protocol::Grpc,
         policy: p2p.policy.clone(),
+        layers: p2p.layers.clone(),
         max_connections: p2p
             .max_connections
             .unwrap_or(network::DEFAULT_MAX_CONNECTIONS),
-        timeout: std::time::Duration::from_secs(15),
+        connect_timeout: p2p
+            .connect_timeout
+            .map(|d| d.into())
+            .unwrap_or(network::DEFAULT_CONNECT_TIMEOUT),
+        request_timeout: p2p
+            .request_timeout
+            .map(|d| d.into())
+            .unwrap_or(network::DEFAULT_REQUEST_TIMEOUT),
         allow_private_addresses: p2p.allow_private_addresses,
+        compression: p2p.compression,
+        block_priority: p2p.block_priority.unwrap_or(true),
+        block_cache_capacity: p2p.block_cache_capacity.unwrap_or(128),
+        pull_inactivity_timeout: p2p
+            .pull_inactivity_timeout
+            .map(|d| d.into())
+            .unwrap_or(std::time::Duration::from_secs(30)),
+        block_fetch_peers: p2p.block_fetch_peers.unwrap_or(3).max(1),
+        subscription_limits: p2p.subscription_limits.clone(),
         max_unreachable_nodes_to_connect_per_event: p2p.max_unreachable_nodes_to_connect_per_event,
         gossip_interval: p2p
             .gossip_interval
             .map(|d| d.into())
             .unwrap_or(std::time::Duration::from_secs(10)),
         topology_force_reset_interval: p2p.topology_force_reset_interval.map(|d| d.into()),
+        topology_file: p2p.topology_file.clone(),
+        address_book: p2p.address_book.clone(),
+        audit_log: p2p.audit_log.clone(),
+        fragment_limits: config.as_ref().map_or_else(FragmentLimits::default, |cfg| {
+            cfg.mempool.fragment_limits.clone()
+        }),
     };
 
     Ok(network)
 }
+
+/// refuse a duration of 0, the interval timers panic on it
+fn check_nonzero<D>(duration: D, setting: &'static str) -> Result<(), Error>
+where
+    D: Into<std::time::Duration>,
+{
+    if duration.into() == std::time::Duration::from_secs(0) {
+        return Err(Error::ZeroDuration { setting });
+    }
+    Ok(())
+}
+
+/// refuse the layers keeping or gossiping no node, they would leave the
+/// node out of the topology
+fn check_layers(layers: &LayersConfig) -> Result<(), Error> {
+    let check_length = |length: usize, setting: &'static str| {
+        if length == 0 {
+            Err(Error::ZeroLayerLength { setting })
+        } else {
+            Ok(())
+        }
+    };
+    if let Some(rings) = &layers.rings {
+        check_length(rings.view_length, "p2p.layers.rings.view_length")?;
+    }
+    if let Some(vicinity) = &layers.vicinity {
+        check_length(vicinity.view_length, "p2p.layers.vicinity.view_length")?;
+        check_length(vicinity.gossip_length, "p2p.layers.vicinity.gossip_length")?;
+    }
+    if let Some(cyclon) = &layers.cyclon {
+        check_length(cyclon.view_length, "p2p.layers.cyclon.view_length")?;
+        check_length(cyclon.gossip_length, "p2p.layers.cyclon.gossip_length")?;
+    }
+    Ok(())
+}
+
+/// the tasks spawned in a runtime of their own, the ones `runtime.tasks`
+/// may name
+const RUNTIME_TASKS: &[&str] = &[
+    "block",
+    "blockchain_stuck_notifier",
+    "cache_purge",
+    "clock_drift",
+    "disk_space",
+    "explorer",
+    "fragment",
+    "index_backfill",
+    "kes_evolution",
+    "leaders",
+    "leadership",
+    "maintenance",
+    "network",
+    "notifier",
+    "pruning",
+    "reload",
+    "rewards",
+    "signals",
+    "stake_snapshot",
+    "sync",
+];
+
+/// the most threads of each kind in a runtime, tokio panics above
+const MAX_RUNTIME_THREADS: usize = 1 << 15;
+
+fn check_runtime(runtime: &Runtime) -> Result<(), Error> {
+    let check_threads = |threads: Option<NonZeroUsize>, setting: String| match threads {
+        Some(threads) if threads.get() > MAX_RUNTIME_THREADS => {
+            Err(Error::TooManyRuntimeThreads { setting })
+        }
+        _ => Ok(()),
+    };
+    check_threads(runtime.worker_threads, "runtime.worker_threads".to_owned())?;
+    check_threads(
+        runtime.blocking_threads,
+        "runtime.blocking_threads".to_owned(),
+    )?;
+    for (task, threads) in &runtime.tasks {
+        if !RUNTIME_TASKS.contains(&task.as_str()) {
+            return Err(Error::UnknownRuntimeTask { task: task.clone() });
+        }
+        check_threads(
+            threads.worker_threads,
+            format!("runtime.tasks.{}.worker_threads", task),
+        )?;
+        check_threads(
+            threads.blocking_threads,
+            format!("runtime.tasks.{}.blocking_threads", task),
+        )?;
+    }
+    Ok(())
+}
+
+fn check_disk_space(disk_space: &DiskSpace) -> Result<(), Error> {
+    check_nonzero(disk_space.interval, "disk_space.interval")?;
+    if disk_space.hard_threshold > disk_space.soft_threshold {
+        return Err(Error::InvalidDiskSpaceThresholds);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn config(yaml: &str) -> Option<Config> {
+        Some(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    fn network_of(yaml: &str) -> Result<network::Configuration, Error> {
+        let arguments = StartArguments::from_iter(&["jormungandr"]);
+        generate_network(&arguments, &config(yaml))
+    }
+
+    #[test]
+    fn node_without_address_is_client_only() {
+        let network = network_of("p2p:\n  listen: none\n").unwrap();
+        assert!(network.client_only);
+        let network = network_of("p2p: {}\n").unwrap();
+        assert!(network.client_only);
+        let network = network_of("p2p:\n  public_address: /ip4/127.0.0.1/tcp/8299\n").unwrap();
+        assert!(!network.client_only);
+    }
+
+    #[test]
+    fn client_only_node_with_address_is_refused() {
+        for p2p in &[
+            "  listen: none\n  public_address: /ip4/127.0.0.1/tcp/8299\n",
+            "  listen: none\n  listen_address: /ip4/127.0.0.1/tcp/8299\n",
+        ] {
+            match network_of(&format!("p2p:\n{}", p2p)) {
+                Err(Error::ClientOnlyWithAddress) => {}
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("client-only node with an address accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn subscription_throttlings_are_counted_over_a_window() {
+        let network = network_of("p2p:\n  subscription_limits:\n    throttled_window: 1m\n");
+        assert_eq!(
+            std::time::Duration::from(network.unwrap().subscription_limits.throttled_window),
+            std::time::Duration::from_secs(60)
+        );
+        match network_of("p2p:\n  subscription_limits:\n    throttled_window: 0s\n") {
+            Err(Error::ZeroDuration { setting }) => {
+                assert_eq!(setting, "p2p.subscription_limits.throttled_window")
+            }
+            other => panic!(
+                "unexpected result {:?}",
+                other.map(|_| ()).map_err(|e| e.to_string())
+            ),
+        }
+    }
+
+    #[test]
+    fn pull_inactivity_timeout_of_zero_is_refused() {
+        assert!(network_of("p2p:\n  pull_inactivity_timeout: 1m\n").is_ok());
+        match network_of("p2p:\n  pull_inactivity_timeout: 0s\n") {
+            Err(Error::ZeroDuration { setting }) => {
+                assert_eq!(setting, "p2p.pull_inactivity_timeout")
+            }
+            other => panic!(
+                "unexpected result {:?}",
+                other.map(|_| ()).map_err(|e| e.to_string())
+            ),
+        }
+    }
+
+    #[test]
+    fn layers_of_length_zero_are_refused() {
+        let layers = "p2p:\n  layers:\n    rings:\n      view_length: 4\n    cyclon:\n";
+        let network = network_of(&format!(
+            "{}      view_length: 128\n      gossip_length: 10\n",
+            layers
+        ));
+        assert_eq!(network.unwrap().layers.cyclon.unwrap().view_length, 128);
+        for (cyclon, setting) in &[
+            (
+                "      view_length: 0\n      gossip_length: 10\n",
+                "p2p.layers.cyclon.view_length",
+            ),
+            (
+                "      view_length: 128\n      gossip_length: 0\n",
+                "p2p.layers.cyclon.gossip_length",
+            ),
+        ] {
+            match network_of(&format!("{}{}", layers, cyclon)) {
+                Err(Error::ZeroLayerLength { setting: refused }) => assert_eq!(refused, *setting),
+                other => panic!(
+                    "unexpected result {:?}",
+                    other.map(|_| ()).map_err(|e| e.to_string())
+                ),
+            }
+        }
+    }
+
+    fn passphrase_of(path: &PathBuf) -> Result<Option<Passphrase>, Error> {
+        read_passphrase(
+            Some(path),
+            SECRETS_PASSPHRASE_ENV,
+            |path, source| Error::SecretsPassphraseIo { path, source },
+            |path| Error::SecretsPassphrasePermissions { path },
+        )
+    }
+
+    fn passphrase_file(name: &str, mode: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-settings-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, "secret passphrase\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        path
+    }
+
+    #[test]
+    fn passphrase_is_read_from_an_owner_only_file() {
+        let path = passphrase_file("passphrase", 0o600);
+        let passphrase = passphrase_of(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            passphrase.unwrap().unwrap().as_bytes(),
+            b"secret passphrase"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passphrase_file_other_users_can_access_is_refused() {
+        let path = passphrase_file("shared-passphrase", 0o640);
+        let passphrase = passphrase_of(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        match passphrase {
+            Err(Error::SecretsPassphrasePermissions { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("passphrase file other users can access read"),
+        }
+    }
+
+    const PRIVATE_ID: &str = "5f5dd8a7e3e2f6bd7fa7b0c8c0b2a4d0e21d8b95e0e6fb3e5ac0bd4ab1e0c3a1\n";
+
+    #[test]
+    fn node_id_is_derived_from_the_private_id_file() {
+        let path = passphrase_file("private-id", 0o600);
+        std::fs::write(&path, PRIVATE_ID).unwrap();
+        let network = network_of(&format!("p2p:\n  private_id_file: {}\n", path.display()));
+        std::fs::remove_file(&path).unwrap();
+
+        let private_id: PrivateId = PRIVATE_ID.parse().unwrap();
+        assert_eq!(
+            network.unwrap().public_id().to_string(),
+            private_id.public_id().to_string()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_id_file_other_users_can_access_is_refused() {
+        let path = passphrase_file("shared-private-id", 0o644);
+        std::fs::write(&path, PRIVATE_ID).unwrap();
+        let id = read_private_id(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        match id {
+            Err(Error::PrivateIdPermissions { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("private ID file other users can access read"),
+        }
+    }
+
+    const HSM: &str = "leadership:
+  hsm:
+    module: /usr/lib/softhsm/libsofthsm2.so
+    slot: 1
+    keys:
+      - label: leader
+        public_key: ed25519_pk1xuqdxht6f0kkh0lf3ck3gfyvnpk33s09du92w6740mfmxl6hsfpsp8grmk
+";
+
+    #[test]
+    fn hsm_is_configured_with_default_timeouts() {
+        let hsm = config(HSM).unwrap().leadership.hsm.unwrap();
+
+        assert_eq!(hsm.slot, 1);
+        assert_eq!(hsm.pin_file, None);
+        assert_eq!(hsm.keys.len(), 1);
+        assert_eq!(hsm.keys[0].label, "leader");
+        assert_eq!(
+            std::time::Duration::from(hsm.timeout),
+            std::time::Duration::from_secs(2)
+        );
+        assert_eq!(
+            std::time::Duration::from(hsm.latency_warning),
+            std::time::Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn hsm_configuration_with_unknown_fields_is_refused() {
+        let yaml = format!("{}    mechanism: ecdsa\n", HSM);
+        assert!(serde_yaml::from_str::<Config>(&yaml).is_err());
+    }
+
+    fn runtime(yaml: &str) -> Result<(), Error> {
+        check_runtime(&config(yaml).unwrap().runtime)
+    }
+
+    #[test]
+    fn runtime_of_unknown_task_is_refused() {
+        assert!(runtime("runtime:\n  tasks:\n    network:\n      worker_threads: 4\n").is_ok());
+        match runtime("runtime:\n  tasks:\n    netwrok:\n      worker_threads: 4\n") {
+            Err(Error::UnknownRuntimeTask { task }) => assert_eq!(task, "netwrok"),
+            other => panic!("unexpected result {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn runtime_threads_are_limited() {
+        assert!(runtime("runtime:\n  blocking_threads: 32768\n").is_ok());
+        match runtime("runtime:\n  worker_threads: 32769\n") {
+            Err(Error::TooManyRuntimeThreads { setting }) => {
+                assert_eq!(setting, "runtime.worker_threads")
+            }
+            other => panic!("unexpected result {:?}", other.map_err(|e| e.to_string())),
+        }
+        match runtime("runtime:\n  tasks:\n    block:\n      blocking_threads: 100000\n") {
+            Err(Error::TooManyRuntimeThreads { setting }) => {
+                assert_eq!(setting, "runtime.tasks.block.blocking_threads")
+            }
+            other => panic!("unexpected result {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    fn disk_space(yaml: &str) -> Result<(), Error> {
+        check_disk_space(&config(yaml).unwrap().disk_space)
+    }
+
+    #[test]
+    fn disk_space_checks_are_refused_without_interval() {
+        assert!(disk_space("disk_space:\n  interval: 1m\n").is_ok());
+        match disk_space("disk_space:\n  interval: 0s\n") {
+            Err(Error::ZeroDuration { setting }) => assert_eq!(setting, "disk_space.interval"),
+            other => panic!("unexpected result {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn disk_space_hard_threshold_cannot_be_above_the_soft_one() {
+        match disk_space("disk_space:\n  soft_threshold: 1024\n  hard_threshold: 2048\n") {
+            Err(Error::InvalidDiskSpaceThresholds) => {}
+            other => panic!("unexpected result {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use crate::network::p2p::{Id, PolicyConfig};
+use crate::{
+    blockcfg::HeaderHash,
+    network::p2p::{Id, LayersConfig, PolicyConfig, Role},
+    settings::start::config::SubscriptionLimits,
+};
+use chain_crypto::{Ed25519, PublicKey};
+use jormungandr_lib::interfaces::FragmentLimits;
 use poldercast::NodeProfile;
-use std::{net::SocketAddr, str, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, str, time::Duration};
 
 /// Protocol to use for a connection.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -16,8 +22,11 @@ pub struct Peer {
     pub connection: SocketAddr,
     /// Network protocol to use for this connection.
     pub protocol: Protocol,
-    /// a timeout in case of inactivity or timout between request.
-    pub timeout: Duration,
+    /// how long the connection may take to be established, the handshake
+    /// and the subscriptions included
+    pub connect_timeout: Duration,
+    /// how long the peer may take to respond to a request
+    pub request_timeout: Duration,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,6 +48,11 @@ pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
 
 const DEFAULT_TIMEOUT_MICROSECONDS: u64 = 500_000;
 
+/// The timeouts of the connections and the requests of the peers used
+/// unless the corresponding configuration options are specified.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
 ///
 /// The network static configuration settings
 #[derive(Clone)]
@@ -50,28 +64,77 @@ pub struct Configuration {
 
     pub profile: NodeProfile,
 
+    pub role: Role,
+
+    /// the node does not listen for the connections of the peers, nor
+    /// advertises itself in the gossips
+    pub client_only: bool,
+
     /// list of trusted addresses
     pub trusted_peers: Vec<TrustedPeer>,
 
+    /// the signed list of trusted peers fetched at startup
+    pub trusted_peers_url: Option<TrustedPeersUrl>,
+
+    /// blocks of the chain known in advance
+    pub trusted_checkpoints: Vec<TrustedCheckpoint>,
+
     /// the protocol to utilise for the p2p network
     pub protocol: Protocol,
 
     /// Maximum allowed number of peer connections.
     pub max_connections: usize,
 
-    /// the default value for the timeout for inactive connection
-    pub timeout: Duration,
+    /// how long the connection to a peer may take to be established
+    pub connect_timeout: Duration,
+
+    /// how long a peer may take to respond to a request
+    pub request_timeout: Duration,
 
     pub policy: PolicyConfig,
 
+    pub layers: LayersConfig,
+
     /// Whether to allow non-public IP addresses in gossip
     pub allow_private_addresses: bool,
 
+    /// whether the connections to the peers supporting it are compressed
+    pub compression: bool,
+
+    /// whether the fragments sent to a peer wait for the block
+    /// announcements queued for it
+    pub block_priority: bool,
+
+    /// the number of recent blocks kept in memory to serve the peers
+    pub block_cache_capacity: usize,
+
+    /// how long a peer streaming a pull may send nothing
+    pub pull_inactivity_timeout: Duration,
+
+    /// the number of peers the blocks of a fetch are split across
+    pub block_fetch_peers: usize,
+
+    /// the rates of the items a peer may send on its subscriptions
+    pub subscription_limits: SubscriptionLimits,
+
     pub max_unreachable_nodes_to_connect_per_event: Option<usize>,
 
     pub gossip_interval: Duration,
 
     pub topology_force_reset_interval: Option<Duration>,
+
+    /// the nodes of the topology saved on shutdown and loaded on start
+    pub topology_file: Option<PathBuf>,
+
+    /// the nodes connected to, saved periodically and on shutdown
+    pub address_book: Option<PathBuf>,
+
+    /// the file the connections are recorded in
+    pub audit_log: Option<PathBuf>,
+
+    /// the limits of the fragments received from the peers, from the
+    /// mempool settings
+    pub fragment_limits: FragmentLimits,
 }
 
 #[derive(Clone)]
@@ -89,12 +152,37 @@ impl From<super::config::TrustedPeer> for TrustedPeer {
     }
 }
 
+#[derive(Clone)]
+pub struct TrustedPeersUrl {
+    pub uri: hyper::Uri,
+    pub public_key: PublicKey<Ed25519>,
+}
+
+/// the blocks leading to the last trusted checkpoint are only checked to be
+/// linked to each other when bootstrapping, the chain is then verified by
+/// the hashes of the checkpoints
+#[derive(Clone)]
+pub struct TrustedCheckpoint {
+    pub chain_length: u32,
+    pub hash: HeaderHash,
+}
+
+impl From<super::config::TrustedCheckpoint> for TrustedCheckpoint {
+    fn from(checkpoint: super::config::TrustedCheckpoint) -> Self {
+        TrustedCheckpoint {
+            chain_length: checkpoint.chain_length,
+            hash: checkpoint.hash.into_hash(),
+        }
+    }
+}
+
 impl Peer {
     pub fn new(connection: SocketAddr, protocol: Protocol) -> Self {
         Peer {
             connection,
             protocol,
-            timeout: Duration::from_micros(DEFAULT_TIMEOUT_MICROSECONDS),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
         }
     }
     pub fn address(&self) -> SocketAddr {
@@ -121,9 +209,22 @@ impl Configuration {
         (*self.profile.id()).into()
     }
 
+    /// the peer at the address, with the configured timeouts
+    pub fn peer(&self, connection: SocketAddr) -> Peer {
+        Peer {
+            connection,
+            protocol: self.protocol,
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+        }
+    }
+
     /// Returns the listener configuration, if the options defining it
-    /// were set.
+    /// were set and the node is not a client only.
     pub fn listen(&self) -> Option<Listen> {
+        if self.client_only {
+            return None;
+        }
         self.listen_address
             .or(self
                 .profile
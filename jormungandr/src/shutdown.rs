@@ -0,0 +1,152 @@
+//! graceful shutdown of the node on SIGTERM or SIGINT, or when the service
+//! control manager stops the node run as a Windows service
+//!
+//! The signal ends the `signals` task, and so makes the node shut down, in
+//! place of having the process killed in the middle of a write. The node
+//! then refuses the fragments and the blocks of its REST API and its leaders
+//! (its state being `ShuttingDown`), saves its topology, stops its network
+//! task along its connections, flushes its fragment logs and its storage and
+//! exits with the code
+//! the shells use for the processes killed by a signal: 128 + the signal
+//! number, 130 for SIGINT and 143 for SIGTERM.
+
+use crate::{
+    blockchain::Blockchain,
+    fragment::Logs,
+    intercom::{self, NetworkMsg},
+    lifecycle::Lifecycle,
+    utils::{async_msg::MessageBox, task::Services},
+};
+use futures::{future, prelude::*};
+use jormungandr_lib::interfaces::NodeState;
+use slog::Logger;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{runtime::current_thread, timer::Timeout};
+
+/// time left to the network task to save the topology, and then to the
+/// fragment logs and to the storage to be flushed, before the node exits
+/// regardless
+const SHUTDOWN_STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the termination signal received by the node, if any
+#[derive(Clone, Default)]
+pub struct Termination(Arc<Mutex<Option<i32>>>);
+
+impl Termination {
+    pub fn signal(&self) -> Option<i32> {
+        *self.0.lock().unwrap()
+    }
+
+    /// 0 unless the node was asked to stop by a signal
+    pub fn exit_code(&self) -> i32 {
+        self.signal().map_or(0, |signal| 128 + signal)
+    }
+}
+
+/// complete when SIGTERM or SIGINT is received, recording it in
+/// `termination`. The future does not complete if the signals cannot be
+/// listened to, the node keeps running without the graceful shutdown.
+#[cfg(unix)]
+pub fn wait_for_signal(
+    logger: Logger,
+    termination: Termination,
+) -> impl Future<Item = (), Error = ()> {
+    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+    let err_logger = logger.clone();
+    Signal::new(SIGTERM)
+        .flatten_stream()
+        .select(Signal::new(SIGINT).flatten_stream())
+        .into_future()
+        .map_err(|(e, _)| e)
+        .then(move |result| match result {
+            Ok((Some(signal), _)) => {
+                info!(logger, "termination signal received"; "signal" => signal);
+                *termination.0.lock().unwrap() = Some(signal);
+                future::Either::A(future::ok(()))
+            }
+            Ok((None, _)) => future::Either::B(future::empty()),
+            Err(e) => {
+                error!(err_logger, "cannot receive the termination signals"; "reason" => %e);
+                future::Either::B(future::empty())
+            }
+        })
+}
+
+/// complete when the service control manager asks the node to stop
+#[cfg(windows)]
+pub fn wait_for_signal(
+    logger: Logger,
+    _termination: Termination,
+) -> impl Future<Item = (), Error = ()> {
+    crate::service_manager::windows::stop_requested()
+        .map(move |()| info!(logger, "stop requested by the service control manager"))
+}
+
+/// the parts of the node to stop, once one of its tasks finished
+pub struct Shutdown {
+    pub lifecycle: Lifecycle,
+    pub network_task: MessageBox<NetworkMsg>,
+    pub fragment_logs: Logs,
+    pub blockchain: Blockchain,
+    pub logger: Logger,
+}
+
+impl Shutdown {
+    pub fn run(mut self, services: &mut Services) {
+        self.lifecycle.set(NodeState::ShuttingDown);
+
+        let mut runtime = match current_thread::Runtime::new() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!(self.logger, "cannot shut down gracefully"; "reason" => %e);
+                return;
+            }
+        };
+
+        let (reply_handle, reply_future) =
+            intercom::unary_reply::<(), intercom::Error>(self.logger.clone());
+        match self
+            .network_task
+            .try_send(NetworkMsg::Shutdown(reply_handle))
+        {
+            Ok(()) => {
+                let saved = runtime.block_on(Timeout::new(reply_future, SHUTDOWN_STEP_TIMEOUT));
+                if saved.is_err() {
+                    warn!(
+                        self.logger,
+                        "the network task did not save the topology in time"
+                    );
+                }
+            }
+            Err(e) => {
+                warn!(self.logger, "cannot ask the network task to save the topology"; "reason" => %e)
+            }
+        }
+        services.shutdown("network");
+
+        let flushed = runtime.block_on(Timeout::new(
+            self.fragment_logs.flush(),
+            SHUTDOWN_STEP_TIMEOUT,
+        ));
+        if flushed.is_err() {
+            warn!(self.logger, "the fragment logs were not flushed in time");
+        }
+
+        let flushed = runtime.block_on(Timeout::new(
+            self.blockchain.storage().flush(),
+            SHUTDOWN_STEP_TIMEOUT,
+        ));
+        if let Err(e) = flushed {
+            error!(self.logger, "cannot flush the storage"; "reason" => %e);
+        }
+        if let Some(index) = self.blockchain.index() {
+            if let Err(e) = index.flush() {
+                error!(self.logger, "cannot flush the chain index"; "reason" => %e);
+            }
+        }
+    }
+}
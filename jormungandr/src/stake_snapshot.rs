@@ -0,0 +1,225 @@
+//! the stake distribution written at the epoch transitions
+//!
+//! The leaders of an epoch are elected with the stake distribution taken at
+//! the transition to the epoch, and the rewards of the epoch are shared
+//! between the delegators of a pool after it. The snapshot task writes the
+//! distribution, with the stake of each delegator, when the main chain enters
+//! an epoch, for the pool operators to compute their payouts from.
+//!
+//! A file is written for each epoch, as `stake-<epoch>.json` or
+//! `stake-<epoch>.csv` in the configured directory, and replaced if the main
+//! chain enters the epoch again on a branch diverging before the epoch. The
+//! epochs of the BFT consensus have no stake distribution and no file, and no
+//! file is written while the storage volume is almost full. The files are
+//! written on the blocking threads of the runtime.
+
+use crate::{
+    blockcfg::Leadership,
+    blockchain::EpochTransition,
+    disk_space::DiskGuard,
+    settings::start::config::{SnapshotFormat, StakeSnapshots},
+    utils::{
+        async_msg::MessageQueue,
+        fs::temp_path,
+        task::{self, TokioServiceInfo},
+    },
+};
+use chain_impl_mockchain::leadership::LeadershipConsensus;
+use futures::future::{self, Either};
+use jormungandr_lib::interfaces::StakeSnapshot;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tokio::prelude::*;
+
+/// the transitions waiting while a snapshot is written
+pub const TRANSITIONS_CAPACITY: usize = 4;
+
+/// the stake distribution of the epoch of the given leadership, none for
+/// the BFT consensus
+pub fn snapshot(epoch: u32, leadership: &Leadership) -> Option<StakeSnapshot> {
+    match leadership.consensus() {
+        LeadershipConsensus::GenesisPraos(genesis_praos) => {
+            Some(StakeSnapshot::new(epoch, genesis_praos.distribution()))
+        }
+        _ => None,
+    }
+}
+
+pub struct Snapshots {
+    config: StakeSnapshots,
+    disk_guard: DiskGuard,
+}
+
+impl Snapshots {
+    pub fn new(config: StakeSnapshots, disk_guard: DiskGuard) -> Self {
+        Snapshots { config, disk_guard }
+    }
+
+    pub fn run(
+        self,
+        info: TokioServiceInfo,
+        transitions: MessageQueue<EpochTransition>,
+    ) -> impl Future<Item = (), Error = ()> {
+        let logger = info.logger().clone();
+        let config = self.config;
+        let disk_guard = self.disk_guard;
+
+        transitions.for_each(move |transition| {
+            let epoch = transition.epoch;
+            let snapshot = match snapshot(epoch, transition.tip.epoch_leadership_schedule()) {
+                Some(snapshot) => snapshot,
+                None => return Either::A(future::ok(())),
+            };
+            if disk_guard.is_low() {
+                warn!(
+                    logger,
+                    "the storage volume is almost full, not writing the stake snapshot";
+                    "epoch" => epoch,
+                );
+                return Either::A(future::ok(()));
+            }
+            let path = file_path(&config, epoch);
+            let format = config.format;
+            let logger = logger.clone();
+            Either::B(
+                task::blocking(move || write(&path, &snapshot, format).map(|()| path)).then(
+                    move |result| {
+                        match result {
+                            Ok(path) => info!(
+                                logger,
+                                "stake snapshot written";
+                                "epoch" => epoch,
+                                "path" => %path.display(),
+                            ),
+                            Err(e) => error!(
+                                logger,
+                                "cannot write the stake snapshot";
+                                "epoch" => epoch,
+                                "reason" => %e,
+                            ),
+                        }
+                        Ok(())
+                    },
+                ),
+            )
+        })
+    }
+}
+
+fn file_path(config: &StakeSnapshots, epoch: u32) -> PathBuf {
+    let extension = match config.format {
+        SnapshotFormat::Json => "json",
+        SnapshotFormat::Csv => "csv",
+    };
+    config
+        .directory
+        .join(format!("stake-{}.{}", epoch, extension))
+}
+
+/// write the snapshot to a temporary file first, a file of the directory is
+/// never seen partly written. The temporary file is removed if the write
+/// fails.
+fn write(path: &Path, snapshot: &StakeSnapshot, format: SnapshotFormat) -> io::Result<()> {
+    let content = match format {
+        SnapshotFormat::Json => serde_json::to_string_pretty(snapshot)?,
+        SnapshotFormat::Csv => snapshot.to_csv(),
+    };
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let tmp_path = temp_path(path);
+    let result = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jormungandr_lib::interfaces::Value;
+    use std::collections::BTreeMap;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "jormungandr-stake-snapshot-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn config(directory: PathBuf, format: SnapshotFormat) -> StakeSnapshots {
+        StakeSnapshots { directory, format }
+    }
+
+    fn empty_snapshot(epoch: u32) -> StakeSnapshot {
+        StakeSnapshot {
+            epoch,
+            unassigned: Value::from(5),
+            dangling: Value::from(0),
+            stake_pools: BTreeMap::new(),
+        }
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn file_of_an_epoch_is_named_after_the_format() {
+        let dir = PathBuf::from("/var/lib/jormungandr/stake");
+        assert_eq!(
+            file_path(&config(dir.clone(), SnapshotFormat::Json), 12),
+            dir.join("stake-12.json")
+        );
+        assert_eq!(
+            file_path(&config(dir.clone(), SnapshotFormat::Csv), 12),
+            dir.join("stake-12.csv")
+        );
+    }
+
+    #[test]
+    fn snapshot_is_written_and_replaced_in_a_new_directory() {
+        let dir = temp_dir("written");
+        let path = file_path(&config(dir.clone(), SnapshotFormat::Json), 3);
+
+        write(&path, &empty_snapshot(3), SnapshotFormat::Json).unwrap();
+        let mut replacement = empty_snapshot(3);
+        replacement.unassigned = Value::from(7);
+        write(&path, &replacement, SnapshotFormat::Json).unwrap();
+
+        let written: StakeSnapshot = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written, replacement);
+        assert_eq!(files(&dir), vec!["stake-3.json"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temporary_file() {
+        let dir = temp_dir("failed");
+        let path = file_path(&config(dir.clone(), SnapshotFormat::Csv), 3);
+        // a directory cannot be replaced by the file
+        fs::create_dir_all(path.join("taken")).unwrap();
+
+        assert!(write(&path, &empty_snapshot(3), SnapshotFormat::Csv).is_err());
+        assert_eq!(files(&dir), vec!["stake-3.csv"]);
+        assert!(path.is_dir());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
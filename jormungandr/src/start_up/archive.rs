@@ -0,0 +1,219 @@
+//! export of the local chain to an archive, and import of an archive in
+//! place of the network bootstrap
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{self, ArchiveError, ArchiveReader, ArchiveWriter, Blockchain, Ref, Storage, Tip},
+    network::{bootstrap::handle_block, BootstrapError},
+    utils::fs::temp_path,
+};
+use chain_core::property::Block as _;
+use chain_storage::error::Error as StorageError;
+use slog::Logger;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::{prelude::*, runtime::Runtime};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("runtime initialization failed")]
+    RuntimeInit { source: io::Error },
+    #[error("cannot open the chain archive {path}")]
+    Open { path: PathBuf, source: io::Error },
+    #[error("invalid chain archive")]
+    Archive {
+        #[from]
+        source: ArchiveError,
+    },
+    #[error("storage error while exporting the chain")]
+    Storage {
+        #[from]
+        source: StorageError,
+    },
+    #[error("the chain archive starts at block0 {archive}, the node at {node}")]
+    Block0Mismatch {
+        archive: HeaderHash,
+        node: HeaderHash,
+    },
+    #[error("the chain archive does not start with the block0")]
+    MissingBlock0,
+    #[error("block {0} of the chain archive is invalid")]
+    InvalidBlock(HeaderHash, #[source] BootstrapError),
+    #[error("failed to select the new tip")]
+    ChainSelectionFailed { source: blockchain::Error },
+}
+
+/// write the chain, from the block0 up to the current tip, to the given file
+pub fn export_chain(
+    blockchain: Blockchain,
+    tip: Tip,
+    block0_hash: HeaderHash,
+    path: &Path,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let runtime = Runtime::new().map_err(|source| Error::RuntimeInit { source })?;
+
+    let storage = blockchain.storage().clone();
+    let path_buf = path.to_owned();
+    let export = tip.get_ref().and_then(move |tip_ref| {
+        write_archive(storage, block0_hash, tip_ref.hash(), path_buf, |_, _| ())
+    });
+
+    let blocks = runtime.block_on_all(export)?;
+    info!(logger, "chain exported"; "path" => %path.display(), "blocks" => blocks);
+    Ok(())
+}
+
+/// write the chain, from the block0 up to `tip`, to the given file. The
+/// archive is written to a temporary file first and then renamed, so an
+/// interrupted write leaves the previous archive intact, and the temporary
+/// file is removed if the write fails. `progress` is
+/// called with the blocks written and the blocks to write after each block.
+/// Returns the number of blocks written.
+pub fn write_archive<P>(
+    storage: Storage,
+    block0_hash: HeaderHash,
+    tip: HeaderHash,
+    path: PathBuf,
+    mut progress: P,
+) -> impl Future<Item = u64, Error = Error>
+where
+    P: FnMut(u64, u64) + Send + 'static,
+{
+    let tmp_path = temp_path(&path);
+    let rename_tmp_path = tmp_path.clone();
+    let cleanup_tmp_path = tmp_path.clone();
+
+    future::result(File::create(&tmp_path).map_err(|source| Error::Open {
+        path: tmp_path,
+        source,
+    }))
+    .and_then(move |file| Ok(ArchiveWriter::new(BufWriter::new(file), &block0_hash)?))
+    .and_then(move |mut writer| {
+        storage
+            .get(block0_hash)
+            .join3(
+                storage.get_block_info(tip),
+                storage.stream_from_to(block0_hash, tip),
+            )
+            .map_err(Error::from)
+            .and_then(move |(block0, tip_info, stream)| {
+                let block0 = block0.ok_or(StorageError::BlockNotFound)?;
+                // the depth of the block0 is 0
+                let total = tip_info.ok_or(StorageError::BlockNotFound)?.depth + 1;
+                writer.write_block(&block0)?;
+                progress(1, total);
+                Ok((writer, stream, progress, total))
+            })
+    })
+    .and_then(|(writer, stream, mut progress, total)| {
+        stream
+            .map_err(Error::from)
+            .fold((writer, 1), move |(mut writer, written), block| {
+                writer.write_block(&block)?;
+                progress(written + 1, total);
+                Ok::<_, Error>((writer, written + 1))
+            })
+            .map(|(writer, _)| writer)
+    })
+    .and_then(|writer| writer.finish().map_err(Error::from))
+    .and_then(move |blocks| {
+        fs::rename(&rename_tmp_path, &path).map_err(|source| Error::Open { path, source })?;
+        Ok(blocks)
+    })
+    .map_err(move |e| {
+        let _ = fs::remove_file(&cleanup_tmp_path);
+        e
+    })
+}
+
+/// read the block0 from the given archive, for a node starting from
+/// the block0 hash only
+pub fn read_block0(path: &Path, block0_hash: HeaderHash) -> Result<Block, Error> {
+    let mut reader = open_archive(path, block0_hash)?;
+    read_archived_block0(&mut reader, block0_hash)
+}
+
+/// validate and apply the blocks of the given archive, then select the tip
+/// of the archived chain
+///
+/// The blocks already in the storage are skipped so an interrupted import
+/// can be resumed.
+pub fn import_chain(
+    blockchain: Blockchain,
+    tip: Tip,
+    block0_hash: HeaderHash,
+    path: &Path,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let runtime = Runtime::new().map_err(|source| Error::RuntimeInit { source })?;
+
+    let mut reader = open_archive(path, block0_hash)?;
+    read_archived_block0(&mut reader, block0_hash)?;
+
+    info!(logger, "importing the chain"; "path" => %path.display());
+
+    let fold_logger = logger.clone();
+    let select_logger = logger.clone();
+    let select_blockchain = blockchain.clone();
+    let import = stream::iter_result(reader)
+        .map_err(Error::from)
+        .fold(
+            (None, 0u64),
+            move |(last, imported): (Option<Arc<Ref>>, u64), block: Block| {
+                let hash = block.id();
+                handle_block(blockchain.clone(), block, fold_logger.clone()).then(move |result| {
+                    match result {
+                        Ok(block_ref) => Ok((Some(block_ref), imported + 1)),
+                        Err(BootstrapError::BlockAlreadyPresent(_)) => Ok((last, imported)),
+                        Err(e) => Err(Error::InvalidBlock(hash, e)),
+                    }
+                })
+            },
+        )
+        .and_then(move |(last, imported)| match last {
+            Some(block_ref) => future::Either::A(
+                blockchain::process_new_ref(select_logger, select_blockchain, tip, block_ref)
+                    .map_err(|source| Error::ChainSelectionFailed { source })
+                    .map(move |_| imported),
+            ),
+            None => future::Either::B(future::ok(imported)),
+        });
+
+    let imported = runtime.block_on_all(import)?;
+    info!(logger, "chain imported"; "blocks" => imported);
+    Ok(())
+}
+
+fn open_archive(
+    path: &Path,
+    block0_hash: HeaderHash,
+) -> Result<ArchiveReader<BufReader<File>>, Error> {
+    let file = File::open(path).map_err(|source| Error::Open {
+        path: path.to_owned(),
+        source,
+    })?;
+    let reader = ArchiveReader::new(BufReader::new(file))?;
+    if *reader.block0_hash() != block0_hash {
+        return Err(Error::Block0Mismatch {
+            archive: *reader.block0_hash(),
+            node: block0_hash,
+        });
+    }
+    Ok(reader)
+}
+
+fn read_archived_block0<R: Read>(
+    reader: &mut ArchiveReader<R>,
+    block0_hash: HeaderHash,
+) -> Result<Block, Error> {
+    match reader.next().transpose()? {
+        Some(block0) if block0.id() == block0_hash => Ok(block0),
+        _ => Err(Error::MissingBlock0),
+    }
+}
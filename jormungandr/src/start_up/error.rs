@@ -1,5 +1,7 @@
+use super::{archive, ledger_dump, storage_check};
 use crate::{
-    blockcfg, blockchain, explorer, network, secure,
+    blockcfg::{self, HeaderHash},
+    blockchain, explorer, leadership, network, reload, secure,
     settings::{self, logging},
 };
 use chain_storage::error::Error as StorageError;
@@ -7,7 +9,10 @@ use std::io;
 
 custom_error! {pub ErrorKind
    SQLite = "SQLite file",
-   Block0 = "Block0"
+   Block0 = "Block0",
+   Resync = "the removal of the stored blockchain",
+   RebuildIndex = "the removal of the chain index",
+   AuditLog = "the audit log of the P2P connections"
 }
 
 custom_error! {pub Error
@@ -21,8 +26,18 @@ custom_error! {pub Error
     FetchBlock0 { source: network::FetchBlockError } = "Error fetching the genesis block from the network",
     NetworkBootstrapError { source: network::BootstrapError } = "Error while loading the blockchain from the network",
     NodeSecrets { source: secure::NodeSecretFromFileError} = "Error while loading the node's secrets.",
+    Hsm { source: leadership::HsmError } = "Error while opening the hardware security module",
+    TrustedPeers { source: network::peer_list::Error } = "Error while fetching the trusted peers of the `core` node",
     Block0InFuture = "Block 0 is set to start in the future",
     ExplorerBootstrapError { source: explorer::error::Error } = "Error while loading the explorer from storage",
+    ChainArchive { source: archive::Error } = "Error while exporting or importing the chain archive",
+    ChainIndex { source: blockchain::IndexError } = "Error while loading the chain index",
+    StorageCheck { source: storage_check::Error } = "The chain in the storage is corrupted",
+    StorageMigration { source: blockchain::MigrationError } = "Error while upgrading the storage",
+    Reload { source: reload::Error } = "Error while preparing the reload of the configuration",
+    LedgerDump { source: ledger_dump::Error } = "Error while exporting the ledger state",
+    Block0FileMismatch { path: String, expected: HeaderHash, found: HeaderHash } = "The genesis block in {path} has the hash {found}, not the genesis block hash {expected}",
+    Block0Mismatch { path: String, block0: HeaderHash, tip: HeaderHash } = "The storage {path} holds the blockchain of another genesis block: its tip {tip} does not descend from the block0 {block0}. Use another storage directory, or `--force-resync` to empty this one",
 }
 
 impl Error {
@@ -41,6 +56,16 @@ impl Error {
             Error::FetchBlock0 { .. } => 9,
             Error::NetworkBootstrapError { .. } => 10,
             Error::ExplorerBootstrapError { .. } => 11,
+            Error::ChainArchive { .. } => 12,
+            Error::ChainIndex { .. } => 13,
+            Error::StorageCheck { .. } => 14,
+            Error::StorageMigration { .. } => 15,
+            Error::Reload { .. } => 16,
+            Error::Block0Mismatch { .. } => 17,
+            Error::LedgerDump { .. } => 18,
+            Error::Block0FileMismatch { .. } => 19,
+            Error::Hsm { .. } => 20,
+            Error::TrustedPeers { .. } => 21,
         }
     }
 }
@@ -0,0 +1,289 @@
+//! export of the ledger state at a block of the storage, in place of
+//! starting the node
+//!
+//! The UTxO set, the accounts and the delegation of the accounts are written
+//! to `utxos`, `accounts` and `delegation` files in the given directory, with
+//! the `.json` or `.csv` extension of the format. The CSV rows of the
+//! accounts have their delegation too, as `pool:parts` separated by spaces.
+
+use crate::{
+    blockcfg::{HeaderHash, Ledger},
+    blockchain::{self, Blockchain, Tip},
+};
+use chain_crypto::PublicKey;
+use chain_impl_mockchain::account::AccountAlg;
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::{AccountState, UTxOInfo},
+};
+use serde::Serialize;
+use slog::Logger;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+use tokio::{prelude::*, runtime::Runtime};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("runtime initialization failed")]
+    RuntimeInit { source: io::Error },
+    #[error("cannot get the ledger state of the block")]
+    Blockchain { source: blockchain::Error },
+    #[error("the block {0} is not in the storage")]
+    BlockNotFound(HeaderHash),
+    #[error("cannot write {path}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerDumpFormat {
+    Json,
+    Csv,
+}
+
+impl LedgerDumpFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            LedgerDumpFormat::Json => "json",
+            LedgerDumpFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for LedgerDumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(LedgerDumpFormat::Json),
+            "csv" => Ok(LedgerDumpFormat::Csv),
+            other => Err(format!("unknown ledger dump format '{}'", other)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Account {
+    id: String,
+    #[serde(flatten)]
+    state: AccountState,
+}
+
+#[derive(Serialize)]
+struct Delegation {
+    account: String,
+    pool: Hash,
+    /// the parts of the stake of the account delegated to the pool, out of
+    /// the sum of the parts of all its pools
+    parts: u8,
+}
+
+/// write the ledger state at `at`, or at the tip if not given, to the files
+/// of the given directory
+pub fn dump_ledger(
+    mut blockchain: Blockchain,
+    tip: Tip,
+    at: Option<HeaderHash>,
+    dir: &Path,
+    format: LedgerDumpFormat,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let runtime = Runtime::new().map_err(|source| Error::RuntimeInit { source })?;
+
+    let get_ref = tip.get_ref::<Error>().and_then(move |tip_ref| {
+        let hash = at.unwrap_or_else(|| tip_ref.hash());
+        blockchain
+            .get_ref(hash)
+            .map_err(|source| Error::Blockchain { source })
+            .and_then(move |block_ref| block_ref.ok_or(Error::BlockNotFound(hash)))
+    });
+    let block_ref = runtime.block_on_all(get_ref)?;
+    let ledger = block_ref.ledger();
+
+    fs::create_dir_all(dir).map_err(|source| Error::Write {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let utxos = write_utxos(&ledger, dir, format)?;
+    let (accounts, delegations) = write_accounts(&ledger, dir, format)?;
+    info!(
+        logger,
+        "ledger state exported";
+        "block" => %block_ref.hash(),
+        "chain_length" => u32::from(block_ref.chain_length()),
+        "directory" => %dir.display(),
+        "utxos" => utxos,
+        "accounts" => accounts,
+        "delegations" => delegations,
+    );
+    Ok(())
+}
+
+fn write_utxos(ledger: &Ledger, dir: &Path, format: LedgerDumpFormat) -> Result<usize, Error> {
+    let utxos: Vec<UTxOInfo> = ledger.utxos().map(UTxOInfo::from).collect();
+    write_file(dir, "utxos", format, |out| match format {
+        LedgerDumpFormat::Json => Ok(serde_json::to_writer_pretty(out, &utxos)?),
+        LedgerDumpFormat::Csv => {
+            writeln!(out, "transaction_id,index_in_transaction,address,value")?;
+            for utxo in utxos.iter() {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    utxo.transaction_id(),
+                    utxo.index_in_transaction(),
+                    utxo.address(),
+                    utxo.associated_fund()
+                )?;
+            }
+            Ok(())
+        }
+    })?;
+    Ok(utxos.len())
+}
+
+fn write_accounts(
+    ledger: &Ledger,
+    dir: &Path,
+    format: LedgerDumpFormat,
+) -> Result<(usize, usize), Error> {
+    let accounts: Vec<Account> = ledger
+        .accounts()
+        .iter()
+        .map(|(id, state)| Account {
+            id: PublicKey::<AccountAlg>::from(id.clone()).to_string(),
+            state: AccountState::from(state),
+        })
+        .collect();
+    let delegations = delegations(&accounts);
+
+    write_file(dir, "accounts", format, |out| match format {
+        LedgerDumpFormat::Json => Ok(serde_json::to_writer_pretty(out, &accounts)?),
+        LedgerDumpFormat::Csv => write_accounts_csv(out, &accounts),
+    })?;
+    write_file(dir, "delegation", format, |out| match format {
+        LedgerDumpFormat::Json => Ok(serde_json::to_writer_pretty(out, &delegations)?),
+        LedgerDumpFormat::Csv => write_delegations_csv(out, &delegations),
+    })?;
+    Ok((accounts.len(), delegations.len()))
+}
+
+/// the delegation of the accounts, one entry by account and pool
+fn delegations(accounts: &[Account]) -> Vec<Delegation> {
+    accounts
+        .iter()
+        .flat_map(|account| {
+            account
+                .state
+                .delegation()
+                .pools()
+                .into_iter()
+                .map(move |(pool, parts)| Delegation {
+                    account: account.id.clone(),
+                    pool,
+                    parts,
+                })
+        })
+        .collect()
+}
+
+fn write_accounts_csv<W: Write>(out: &mut W, accounts: &[Account]) -> io::Result<()> {
+    writeln!(out, "id,value,counter,delegation")?;
+    for account in accounts.iter() {
+        let delegation: Vec<String> = account
+            .state
+            .delegation()
+            .pools()
+            .iter()
+            .map(|(pool, parts)| format!("{}:{}", pool, parts))
+            .collect();
+        writeln!(
+            out,
+            "{},{},{},{}",
+            account.id,
+            account.state.value(),
+            account.state.counter(),
+            delegation.join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+fn write_delegations_csv<W: Write>(out: &mut W, delegations: &[Delegation]) -> io::Result<()> {
+    writeln!(out, "account,pool,parts")?;
+    for delegation in delegations.iter() {
+        writeln!(
+            out,
+            "{},{},{}",
+            delegation.account, delegation.pool, delegation.parts
+        )?;
+    }
+    Ok(())
+}
+
+fn write_file<F>(dir: &Path, name: &str, format: LedgerDumpFormat, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let path = dir.join(name).with_extension(format.extension());
+    File::create(&path)
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()
+        })
+        .map_err(|source| Error::Write { path, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_1: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const POOL_2: &str = "0202020202020202020202020202020202020202020202020202020202020202";
+
+    fn account(id: &str, value: u64, pools: serde_json::Value) -> Account {
+        let state = serde_json::json!({
+            "delegation": { "pools": pools },
+            "value": value,
+            "counter": 2,
+        });
+        Account {
+            id: id.to_owned(),
+            state: serde_json::from_value(state).unwrap(),
+        }
+    }
+
+    fn accounts() -> Vec<Account> {
+        vec![
+            account("alice", 100, serde_json::json!([[POOL_1, 1], [POOL_2, 3]])),
+            account("bob", 50, serde_json::json!([])),
+        ]
+    }
+
+    #[test]
+    fn accounts_csv_has_the_delegation() {
+        let mut out = Vec::new();
+        write_accounts_csv(&mut out, &accounts()).unwrap();
+        let expected = format!(
+            "id,value,counter,delegation\nalice,100,2,{}:1 {}:3\nbob,50,2,\n",
+            POOL_1, POOL_2
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn delegation_csv_has_a_row_by_pool() {
+        let delegations = delegations(&accounts());
+        let mut out = Vec::new();
+        write_delegations_csv(&mut out, &delegations).unwrap();
+        let expected = format!(
+            "account,pool,parts\nalice,{},1\nalice,{},3\n",
+            POOL_1, POOL_2
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
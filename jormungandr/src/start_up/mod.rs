@@ -1,18 +1,38 @@
+mod archive;
 mod error;
+mod ledger_dump;
+mod storage_check;
 
+pub use self::archive::{export_chain, import_chain, write_archive};
 pub use self::error::{Error, ErrorKind};
+pub use self::ledger_dump::{dump_ledger, LedgerDumpFormat};
+pub use self::storage_check::check_storage;
 use crate::{
-    blockcfg::Block,
-    blockchain::{Blockchain, Branch, ErrorKind as BlockchainError, Tip},
-    network,
-    settings::start::Settings,
+    blockcfg::{Block, HeaderHash},
+    blockchain::{
+        migrate_storage, BlockStore, Blockchain, Branch, ChainIndex, ChainSelection,
+        ErrorKind as BlockchainError, IoStats, SledBlockStore, SqliteBlockStore, Tip,
+        MAIN_BRANCH_TAG,
+    },
+    log, network,
+    settings::start::{Settings, StorageBackend},
 };
-use chain_storage::{memory::MemoryBlockStore, store::BlockStore};
-use chain_storage_sqlite::SQLiteBlockStore;
+use chain_storage::{memory::MemoryBlockStore, store::BlockStore as _};
 use slog::Logger;
-use std::time::Duration;
+use std::{fs, io, path::Path, sync::Arc, time::Duration};
 
-pub type NodeStorage = Box<dyn BlockStore<Block = Block> + Send + Sync>;
+pub type NodeStorage = Box<dyn BlockStore>;
+
+const SQLITE_FILE: &str = "blocks.sqlite";
+/// the files SQLite keeps next to the database, the write-ahead log and its
+/// index, or the rollback journal
+const SQLITE_SIDE_FILES: &[&str] = &[
+    "blocks.sqlite-wal",
+    "blocks.sqlite-shm",
+    "blocks.sqlite-journal",
+];
+const SLED_DIR: &str = "blocks.sled";
+const INDEX_DIR: &str = "index.sled";
 
 /// prepare the block storage from the given settings
 ///
@@ -22,16 +42,67 @@ pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorag
             info!(logger, "storing blockchain in memory");
             Ok(Box::new(MemoryBlockStore::new()))
         }
-        Some(dir) => {
-            std::fs::create_dir_all(dir).map_err(|err| Error::IO {
-                source: err,
-                reason: ErrorKind::SQLite,
-            })?;
-            let mut sqlite = dir.clone();
-            sqlite.push("blocks.sqlite");
-            info!(logger, "storing blockchain in '{:?}'", sqlite);
-            Ok(Box::new(SQLiteBlockStore::new(sqlite)))
+        Some(storage) => {
+            let dir = &storage.path;
+            migrate_storage(dir, logger)?;
+            match storage.backend {
+                StorageBackend::Sqlite => {
+                    std::fs::create_dir_all(dir).map_err(|err| Error::IO {
+                        source: err,
+                        reason: ErrorKind::SQLite,
+                    })?;
+                    let mut sqlite = dir.clone();
+                    sqlite.push(SQLITE_FILE);
+                    info!(logger, "storing blockchain in '{:?}'", sqlite);
+                    Ok(Box::new(SqliteBlockStore::open(sqlite)))
+                }
+                StorageBackend::Sled => {
+                    let mut sled = dir.clone();
+                    sled.push(SLED_DIR);
+                    info!(logger, "storing blockchain in '{:?}' with sled", sled);
+                    let store = SledBlockStore::open(
+                        &sled,
+                        storage.cache_capacity,
+                        storage.flush_interval,
+                    )?;
+                    Ok(Box::new(store))
+                }
+            }
+        }
+    }
+}
+
+/// open the index of the chain if enabled, it is kept next to the storage
+pub fn prepare_index(
+    setting: &Settings,
+    block0: &Block,
+    logger: &Logger,
+) -> Result<Option<ChainIndex>, Error> {
+    use crate::blockcfg::Block0DataSource as _;
+
+    match &setting.storage {
+        Some(storage) if storage.index => {
+            let mut dir = storage.path.clone();
+            dir.push(INDEX_DIR);
+            if setting.rebuild_index && dir.exists() {
+                warn!(
+                    logger,
+                    "removing the chain index to rebuild it";
+                    "path" => %dir.display(),
+                );
+                fs::remove_dir_all(&dir).map_err(|source| Error::IO {
+                    source,
+                    reason: ErrorKind::RebuildIndex,
+                })?;
+            }
+            info!(
+                logger,
+                "indexing the transactions and addresses in '{:?}'", dir
+            );
+            let index = ChainIndex::open(&dir, block0.discrimination()?)?;
+            Ok(Some(index))
         }
+        _ => Ok(None),
     }
 }
 
@@ -41,7 +112,9 @@ pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorag
 /// 1. we have the block_0 given as parameter of the settings: easy, we read it;
 /// 2. we have the block_0 hash only:
 ///     1. check the storage if we don't have it already there;
-///     2. check the network nodes we know about
+///     2. read the chain archive to import, if any;
+///     3. read the local copy of `block0_path`, if it is readable;
+///     4. check the network nodes we know about
 pub fn prepare_block_0(
     settings: &Settings,
     storage: &NodeStorage,
@@ -50,17 +123,8 @@ pub fn prepare_block_0(
     use crate::settings::Block0Info;
     match &settings.block_0 {
         Block0Info::Path(path) => {
-            use chain_core::property::Deserialize as _;
             debug!(logger, "parsing block0 from file path `{:?}'", path);
-            let f = std::fs::File::open(path).map_err(|err| Error::IO {
-                source: err,
-                reason: ErrorKind::Block0,
-            })?;
-            let reader = std::io::BufReader::new(f);
-            Block::deserialize(reader).map_err(|err| Error::ParseError {
-                source: err,
-                reason: ErrorKind::Block0,
-            })
+            read_block0(path)
         }
         Block0Info::Hash(block0_id) => {
             if storage.block_exists(&block0_id)? {
@@ -70,6 +134,14 @@ pub fn prepare_block_0(
                 );
                 let (block0, _block0_info) = storage.get_block(block0_id)?;
                 Ok(block0)
+            } else if let Some(path) = &settings.import_chain {
+                debug!(
+                    logger,
+                    "retrieving block0 from the chain archive with hash {}", block0_id
+                );
+                archive::read_block0(path, *block0_id).map_err(|e| e.into())
+            } else if let Some(block0) = read_local_block0(settings, *block0_id, logger)? {
+                Ok(block0)
             } else {
                 debug!(
                     logger,
@@ -81,22 +153,294 @@ pub fn prepare_block_0(
     }
 }
 
+fn read_block0(path: &Path) -> Result<Block, Error> {
+    use chain_core::property::Deserialize as _;
+    let f = std::fs::File::open(path).map_err(|err| Error::IO {
+        source: err,
+        reason: ErrorKind::Block0,
+    })?;
+    let reader = std::io::BufReader::new(f);
+    Block::deserialize(reader).map_err(|err| Error::ParseError {
+        source: err,
+        reason: ErrorKind::Block0,
+    })
+}
+
+/// the genesis block of `block0_path`, none if the file cannot be read so
+/// the block is fetched from the network. A file holding another block is
+/// an error.
+fn read_local_block0(
+    settings: &Settings,
+    block0_id: HeaderHash,
+    logger: &Logger,
+) -> Result<Option<Block>, Error> {
+    use chain_core::property::Block as _;
+
+    let path = match &settings.block0_path {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    debug!(logger, "parsing block0 from the local copy `{:?}'", path);
+    let block0 = match read_block0(path) {
+        Ok(block0) => block0,
+        Err(e) => {
+            warn!(
+                logger,
+                "cannot read the local copy of block0, fetching it from the network";
+                "path" => %path.display(),
+                "reason" => %e,
+            );
+            return Ok(None);
+        }
+    };
+    if block0.id() != block0_id {
+        return Err(Error::Block0FileMismatch {
+            path: path.display().to_string(),
+            expected: block0_id,
+            found: block0.id(),
+        });
+    }
+    Ok(Some(block0))
+}
+
+/// check that the blockchain in the storage starts at the block0, so a
+/// storage directory of another network is not silently mixed with this one.
+///
+/// With `--force-resync`, a storage holding another blockchain is emptied and
+/// opened again, otherwise the node refuses to start.
+pub fn check_block0(
+    settings: &Settings,
+    storage: NodeStorage,
+    block0: &Block,
+    logger: &Logger,
+) -> Result<NodeStorage, Error> {
+    use chain_core::property::Block as _;
+
+    let block0_id = block0.id();
+    let tip = match other_blockchain_tip(&storage, &block0_id)? {
+        None => return Ok(storage),
+        Some(tip) => tip,
+    };
+    let dir = match &settings.storage {
+        Some(storage) => storage.path.clone(),
+        None => return Ok(storage),
+    };
+    if !settings.force_resync {
+        return Err(Error::Block0Mismatch {
+            path: dir.display().to_string(),
+            block0: block0_id,
+            tip,
+        });
+    }
+
+    warn!(
+        logger,
+        "the storage holds the blockchain of another block0, emptying it";
+        "storage" => dir.display().to_string(),
+        "tip" => %tip,
+    );
+    // the stores are closed before their files are removed
+    std::mem::drop(storage);
+    remove_blockchain(&dir).map_err(|source| Error::IO {
+        source,
+        reason: ErrorKind::Resync,
+    })?;
+    prepare_storage(settings, logger)
+}
+
+/// the tip of the blockchain held by the storage if it does not start at
+/// the block0
+fn other_blockchain_tip(
+    storage: &NodeStorage,
+    block0_id: &HeaderHash,
+) -> Result<Option<HeaderHash>, Error> {
+    let tip = match storage.get_tag(MAIN_BRANCH_TAG)? {
+        // an empty storage, the blockchain starts at the block0
+        None => return Ok(None),
+        Some(tip) => tip,
+    };
+    // the block0 is never pruned, the stored blockchain holds it if it
+    // starts at it
+    if storage.block_exists(block0_id)? {
+        return Ok(None);
+    }
+    Ok(Some(tip))
+}
+
+/// remove the block stores and the index from the storage directory, the
+/// other files of the directory are kept
+fn remove_blockchain(dir: &Path) -> io::Result<()> {
+    let names = [SQLITE_FILE, SLED_DIR, INDEX_DIR];
+    for name in names.iter().chain(SQLITE_SIDE_FILES) {
+        let path = dir.join(name);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// the latencies of the operations on the storage, the slow operations on
+/// the storage on disk being logged
+pub fn storage_io_stats(settings: &Settings, logger: &Logger) -> IoStats {
+    IoStats::new(
+        settings
+            .storage
+            .as_ref()
+            .map(|storage| storage.slow_operation_warning),
+        logger.new(o!(log::KEY_TASK => "storage")),
+    )
+}
+
 pub fn load_blockchain(
     block0: Block,
     storage: NodeStorage,
+    io_stats: IoStats,
     block_cache_ttl: Duration,
+    block_cache_capacity: Option<usize>,
+    max_rollback_depth: Option<u64>,
+    chain_selection: Arc<dyn ChainSelection>,
+    index: Option<ChainIndex>,
 ) -> Result<(Blockchain, Tip), Error> {
     use tokio::prelude::*;
 
-    let mut blockchain = Blockchain::new(storage, block_cache_ttl);
+    let mut blockchain = Blockchain::new(
+        storage,
+        io_stats,
+        block_cache_ttl,
+        block_cache_capacity,
+        max_rollback_depth,
+        chain_selection,
+        index,
+    );
 
     let main_branch: Branch = match blockchain.load_from_block0(block0.clone()).wait() {
         Err(error) => match error.kind() {
-            BlockchainError::Block0AlreadyInStorage => blockchain.load_from_storage(block0).wait(),
+            BlockchainError::Block0AlreadyInStorage => {
+                blockchain.load_from_storage(block0.clone()).wait()
+            }
             _ => Err(error),
         },
         Ok(branch) => Ok(branch),
     }?;
 
-    Ok((blockchain, Tip::new(main_branch)))
+    // the index is brought to the tip by the backfill, once the node is started
+    let tip = Tip::new(main_branch);
+
+    Ok((blockchain, tip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, ContentsBuilder, Header};
+    use chain_core::property::Block as _;
+    use chain_impl_mockchain::block;
+    use chain_storage::store::BlockStore as _;
+    use std::{convert::Infallible, path::PathBuf};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jormungandr-start-up-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// a genesis block, blocks of different epochs having different hashes
+    fn genesis(epoch: u32) -> Block {
+        block::builder(
+            BlockVersion::Genesis,
+            ContentsBuilder::new().into(),
+            |hdr| {
+                let header: Result<Header, Infallible> = Ok(hdr
+                    .set_genesis()
+                    .set_date(BlockDate { epoch, slot_id: 0 })
+                    .to_unsigned_header()
+                    .unwrap()
+                    .generalize());
+                header
+            },
+        )
+        .unwrap()
+    }
+
+    fn storage(blocks: &[&Block]) -> NodeStorage {
+        let mut storage = MemoryBlockStore::new();
+        for block in blocks {
+            storage.put_block(block).unwrap();
+        }
+        if let Some(tip) = blocks.last() {
+            storage.put_tag(MAIN_BRANCH_TAG, &tip.id()).unwrap();
+        }
+        Box::new(storage)
+    }
+
+    #[test]
+    fn blockchain_and_side_files_are_removed() {
+        let dir = temp_dir("resync");
+        for name in [SQLITE_FILE].iter().chain(SQLITE_SIDE_FILES) {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        for name in &[SLED_DIR, INDEX_DIR] {
+            fs::create_dir_all(dir.join(name).join("db")).unwrap();
+        }
+        fs::write(dir.join("topology"), b"").unwrap();
+
+        remove_blockchain(&dir).unwrap();
+
+        let left = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(left, vec!["topology"]);
+        // an emptied directory is emptied again
+        remove_blockchain(&dir).unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn empty_storage_starts_at_the_block0() {
+        let block0 = genesis(0);
+        assert_eq!(
+            other_blockchain_tip(&storage(&[]), &block0.id()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn storage_holding_the_block0_starts_at_it() {
+        let block0 = genesis(0);
+        let storage = storage(&[&block0]);
+        assert_eq!(other_blockchain_tip(&storage, &block0.id()).unwrap(), None);
+    }
+
+    #[test]
+    fn storage_of_another_block0_is_found() {
+        let block0 = genesis(0);
+        let other = genesis(1);
+        let storage = storage(&[&other]);
+        assert_eq!(
+            other_blockchain_tip(&storage, &block0.id()).unwrap(),
+            Some(other.id())
+        );
+    }
+
+    #[test]
+    fn block0_mismatch_quotes_the_resync_option() {
+        let error = Error::Block0Mismatch {
+            path: "storage".to_owned(),
+            block0: genesis(0).id(),
+            tip: genesis(1).id(),
+        };
+        assert!(error
+            .to_string()
+            .ends_with("or `--force-resync` to empty this one"));
+    }
 }
@@ -0,0 +1,215 @@
+//! verification of the chain held in the storage, from the block0 up to the
+//! tip, in place of starting the node
+
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{self, Blockchain, IoStats, LongestChain, Storage, MAIN_BRANCH_TAG},
+    network::{bootstrap::handle_block, BootstrapError},
+    start_up::NodeStorage,
+};
+use chain_core::property::Block as _;
+use chain_storage::error::Error as StorageError;
+use slog::Logger;
+use std::{io, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{prelude::*, runtime::Runtime};
+
+/// number of checked blocks between two progress reports
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("runtime initialization failed")]
+    RuntimeInit { source: io::Error },
+    #[error("storage error while checking the chain")]
+    Storage {
+        #[from]
+        source: StorageError,
+    },
+    #[error("the storage holds no chain")]
+    NoTip,
+    #[error("the tip {0} of the stored chain is missing from the storage")]
+    MissingTip(HeaderHash),
+    #[error("the stored chain starts at {found}, not at the block0 {block0}")]
+    Block0Mismatch {
+        found: HeaderHash,
+        block0: HeaderHash,
+    },
+    #[error("the block0 cannot be applied")]
+    Block0 { source: blockchain::Error },
+    #[error("block {hash} at chain length {chain_length} is corrupted")]
+    CorruptBlock {
+        hash: HeaderHash,
+        chain_length: u64,
+        source: Corruption,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum Corruption {
+    #[error("the block is missing from the storage")]
+    Missing,
+    #[error("the stored content of the block hashes to {0}")]
+    HashMismatch(HeaderHash),
+    #[error("the stored block follows {found} instead of {expected}")]
+    ParentMismatch {
+        found: HeaderHash,
+        expected: HeaderHash,
+    },
+    #[error("the block is invalid on top of its parent")]
+    Invalid(#[source] BootstrapError),
+}
+
+/// walk the stored chain from the block0 up to the tip, checking the hash
+/// and the parent of every block and validating it again on top of the
+/// ledger of its parent. Stops at the first corrupted block.
+pub fn check_storage(
+    block0: Block,
+    storage: NodeStorage,
+    io_stats: IoStats,
+    block_cache_ttl: Duration,
+    block_cache_capacity: Option<usize>,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let runtime = Runtime::new().map_err(|source| Error::RuntimeInit { source })?;
+
+    // all the blocks are already in the storage: validating them again only
+    // puts their `Ref` in the cache
+    let mut blockchain = Blockchain::new(
+        storage,
+        io_stats,
+        block_cache_ttl,
+        block_cache_capacity,
+        None,
+        Arc::new(LongestChain),
+        None,
+    );
+    let block0_hash = block0.id();
+    let storage = blockchain.storage().clone();
+    let walk_storage = storage.clone();
+
+    info!(logger, "checking the chain in the storage");
+
+    let check_logger = logger.clone();
+    let check = storage
+        .get_tag(MAIN_BRANCH_TAG.to_owned())
+        .map_err(Error::from)
+        .and_then(|tip| tip.ok_or(Error::NoTip))
+        .and_then(move |tip| main_chain(walk_storage, tip))
+        .and_then(move |hashes| {
+            match hashes.first() {
+                Some(found) if *found != block0_hash => {
+                    return future::Either::A(future::err(Error::Block0Mismatch {
+                        found: *found,
+                        block0: block0_hash,
+                    }))
+                }
+                _ => {}
+            }
+            let check_blocks = check_block(storage.clone(), 0, block0_hash, None)
+                .and_then(move |_| {
+                    blockchain
+                        .load_block0_from_storage(block0)
+                        .map_err(|source| Error::Block0 { source })
+                        .map(move |_| blockchain)
+                })
+                .and_then(move |blockchain| {
+                    stream::iter_ok(hashes.into_iter().zip(0u64..).skip(1)).fold(
+                        block0_hash,
+                        move |parent, (hash, chain_length)| {
+                            if chain_length % PROGRESS_INTERVAL == 0 {
+                                info!(check_logger, "checked blocks"; "chain_length" => chain_length);
+                            }
+                            let blockchain = blockchain.clone();
+                            let logger = check_logger.clone();
+                            check_block(storage.clone(), chain_length, hash, Some(parent))
+                                .and_then(move |block| {
+                                    handle_block(blockchain, block, logger).map_err(move |e| {
+                                        Error::CorruptBlock {
+                                            hash,
+                                            chain_length,
+                                            source: Corruption::Invalid(e),
+                                        }
+                                    })
+                                })
+                                .map(move |_| hash)
+                        },
+                    )
+                });
+            future::Either::B(check_blocks)
+        });
+
+    let tip = runtime.block_on_all(check)?;
+    info!(logger, "the chain in the storage is valid"; "tip" => %tip);
+    Ok(())
+}
+
+/// the hashes of the blocks of the main chain, from the block0 up to `tip`,
+/// as recorded in the metadata of the storage
+fn main_chain(
+    storage: Storage,
+    tip: HeaderHash,
+) -> impl Future<Item = Vec<HeaderHash>, Error = Error> {
+    future::loop_fn(
+        (tip, None, Vec::new()),
+        move |(hash, chain_length, mut hashes): (HeaderHash, Option<u64>, Vec<HeaderHash>)| {
+            storage
+                .get_block_info(hash)
+                .map_err(Error::from)
+                .and_then(move |info| {
+                    let info = info.ok_or_else(|| match chain_length {
+                        None => Error::MissingTip(hash),
+                        Some(chain_length) => Error::CorruptBlock {
+                            hash,
+                            chain_length,
+                            source: Corruption::Missing,
+                        },
+                    })?;
+                    hashes.push(hash);
+                    if info.depth == 0 {
+                        hashes.reverse();
+                        Ok(future::Loop::Break(hashes))
+                    } else {
+                        Ok(future::Loop::Continue((
+                            info.parent_id(),
+                            Some(info.depth - 1),
+                            hashes,
+                        )))
+                    }
+                })
+        },
+    )
+}
+
+/// read the block from the storage, checking it is stored under its own
+/// hash and that it follows `parent`
+fn check_block(
+    storage: Storage,
+    chain_length: u64,
+    hash: HeaderHash,
+    parent: Option<HeaderHash>,
+) -> impl Future<Item = Block, Error = Error> {
+    let corrupt = move |source| Error::CorruptBlock {
+        hash,
+        chain_length,
+        source,
+    };
+    storage
+        .get(hash)
+        .map_err(Error::from)
+        .and_then(move |block| {
+            let block = block.ok_or_else(|| corrupt(Corruption::Missing))?;
+            if block.id() != hash {
+                return Err(corrupt(Corruption::HashMismatch(block.id())));
+            }
+            match parent {
+                Some(expected) if block.parent_id() != expected => {
+                    Err(corrupt(Corruption::ParentMismatch {
+                        found: block.parent_id(),
+                        expected,
+                    }))
+                }
+                _ => Ok(block),
+            }
+        })
+}
@@ -1,9 +1,29 @@
+use crate::blockcfg::HeaderHash;
+use crate::network::p2p::comm::ConnectionStats;
+use crate::utils::async_msg::{QueueMonitor, QueueStats};
+use jormungandr_lib::interfaces::{EpochProduction, MissedSlots, PoolStats};
 use jormungandr_lib::time::SecondsSinceUnixEpoch;
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 const SLOT_START_TIME_UNDEFINED: u64 = u64::max_value();
+const CLOCK_DRIFT_UNDEFINED: i64 = i64::min_value();
+const STORAGE_SIZE_UNDEFINED: u64 = u64::max_value();
+const DISK_SPACE_UNDEFINED: u64 = u64::max_value();
+const EPOCH_UNDEFINED: u64 = u64::max_value();
+
+/// number of epochs for which the block production is kept
+const POOL_EPOCHS_KEPT: usize = 10;
+
+/// blocks that are not fetched by any peer within this delay are not
+/// accounted in the propagation delay
+const PROPAGATION_TRACKING_TIMEOUT: Duration = Duration::from_secs(600);
+/// the protocol versions of the peers counted apart, the other ones being
+/// counted together
+const UNSUPPORTED_VERSIONS_KEPT: usize = 16;
+const UNSUPPORTED_VERSION_MAX_LEN: usize = 16;
 
 #[derive(Clone, Debug, Default)]
 pub struct StatsCounter {
@@ -16,6 +36,125 @@ struct StatsCounterImpl {
     block_recv_cnt: AtomicUsize,
     start_time: Instant,
     slot_start_time: AtomicU64,
+    /// how far the local clock was ahead of the time servers at the last
+    /// sampling, in milliseconds
+    clock_drift_ms: AtomicI64,
+    pool: Mutex<PoolCounters>,
+    /// the message queues of the tasks, by task name
+    queues: Mutex<Vec<(&'static str, QueueMonitor)>>,
+    /// the messages processed by the tasks, by task name and variant
+    messages: Mutex<BTreeMap<(&'static str, &'static str), MessageStats>>,
+    /// the fragments rejected before reaching the pool, by rejection code
+    fragments_rejected: Mutex<BTreeMap<&'static str, u64>>,
+    /// the messages of the peers over the limits, by kind of message
+    limit_violations: Mutex<BTreeMap<&'static str, u64>>,
+    /// the nodes dropped from the gossips of the peers, by reason
+    gossip_rejections: Mutex<BTreeMap<&'static str, u64>>,
+    /// the peers throttled over the rate limits, by subscription
+    subscription_throttles: Mutex<BTreeMap<&'static str, u64>>,
+    /// the handshakes failed on the protocol version of the peer, by version
+    unsupported_versions: Mutex<BTreeMap<String, u64>>,
+    /// the connections negotiated with the peers, by protocol version
+    peer_versions: Mutex<BTreeMap<u16, u64>>,
+    /// the bytes of the compressed connections, by `CompressedBytes`
+    compressed_bytes: [AtomicU64; 4],
+    /// the blocks requested by the peers found in the cache of the network
+    block_cache_hits: AtomicU64,
+    block_cache_misses: AtomicU64,
+    /// the connections of the peers at the last gossip round
+    peer_connections: Mutex<ConnectionStats>,
+    /// the node does not listen for the connections of the peers
+    client_only: AtomicBool,
+    /// the runs of the maintenance actions, by action
+    maintenance: Mutex<BTreeMap<&'static str, MaintenanceStats>>,
+    /// the space taken by the storage on the disk at the last maintenance,
+    /// in bytes
+    storage_size: AtomicU64,
+    /// the space left on the storage volume at the last check, in bytes
+    disk_space_available: AtomicU64,
+    /// the epoch of the last transition of the main chain
+    epoch_entered: AtomicU64,
+    /// the transitions to an epoch the main chain was in on another branch
+    epoch_reentries: AtomicU64,
+    /// the switches to a preferred branch refused for rolling back too many
+    /// blocks
+    deep_reorgs_refused: AtomicU64,
+}
+
+/// the messages of a variant processed by a task
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessageStats {
+    pub count: u64,
+    /// the total time spent processing the messages
+    pub latency: Duration,
+}
+
+/// the runs of a maintenance action
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaintenanceStats {
+    /// when the action started, if it is running
+    pub running_since: Option<Instant>,
+    /// the steps done and the steps to do of the running action, for the
+    /// actions reporting their progress
+    pub progress: Option<(u64, u64)>,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// the duration of the last completed run
+    pub last_duration: Duration,
+    /// when the last run completed
+    pub last_completed: Option<SecondsSinceUnixEpoch>,
+}
+
+/// reasons for which a leader did not create a block for a slot it was
+/// elected for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedSlotReason {
+    TipBehind,
+    TooLate,
+    EnclaveError,
+    Timeout,
+    Syncing,
+    ClockDrift,
+    StaleTip,
+}
+
+/// the bytes counted on the compressed connections, on the wire or before
+/// the compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressedBytes {
+    ReceivedWire,
+    ReceivedRaw,
+    SentWire,
+    SentRaw,
+}
+
+impl CompressedBytes {
+    const ALL: [CompressedBytes; 4] = [
+        CompressedBytes::ReceivedWire,
+        CompressedBytes::ReceivedRaw,
+        CompressedBytes::SentWire,
+        CompressedBytes::SentRaw,
+    ];
+
+    /// the direction and the layer of the bytes
+    fn labels(self) -> (&'static str, &'static str) {
+        match self {
+            CompressedBytes::ReceivedWire => ("received", "wire"),
+            CompressedBytes::ReceivedRaw => ("received", "raw"),
+            CompressedBytes::SentWire => ("sent", "wire"),
+            CompressedBytes::SentRaw => ("sent", "raw"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolCounters {
+    epochs: BTreeMap<u32, EpochProduction>,
+    missed_slots: MissedSlots,
+    /// creation time of our blocks no peer has fetched yet
+    unpropagated: HashMap<HeaderHash, Instant>,
+    propagation_total: Duration,
+    propagation_count: u32,
 }
 
 impl Default for StatsCounterImpl {
@@ -25,6 +164,27 @@ impl Default for StatsCounterImpl {
             block_recv_cnt: AtomicUsize::default(),
             start_time: Instant::now(),
             slot_start_time: AtomicU64::new(SLOT_START_TIME_UNDEFINED),
+            clock_drift_ms: AtomicI64::new(CLOCK_DRIFT_UNDEFINED),
+            pool: Mutex::default(),
+            queues: Mutex::default(),
+            messages: Mutex::default(),
+            fragments_rejected: Mutex::default(),
+            limit_violations: Mutex::default(),
+            gossip_rejections: Mutex::default(),
+            subscription_throttles: Mutex::default(),
+            unsupported_versions: Mutex::default(),
+            peer_versions: Mutex::default(),
+            compressed_bytes: Default::default(),
+            block_cache_hits: AtomicU64::default(),
+            block_cache_misses: AtomicU64::default(),
+            peer_connections: Mutex::default(),
+            client_only: AtomicBool::default(),
+            maintenance: Mutex::default(),
+            storage_size: AtomicU64::new(STORAGE_SIZE_UNDEFINED),
+            disk_space_available: AtomicU64::new(DISK_SPACE_UNDEFINED),
+            epoch_entered: AtomicU64::new(EPOCH_UNDEFINED),
+            epoch_reentries: AtomicU64::default(),
+            deep_reorgs_refused: AtomicU64::default(),
         }
     }
 }
@@ -66,4 +226,430 @@ impl StatsCounter {
         }
         .map(SecondsSinceUnixEpoch::from_secs)
     }
+
+    pub fn add_block_cache_hits(&self, count: u64) {
+        self.stats
+            .block_cache_hits
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_block_cache_misses(&self, count: u64) {
+        self.stats
+            .block_cache_misses
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn block_cache_hits(&self) -> u64 {
+        self.stats.block_cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn block_cache_misses(&self) -> u64 {
+        self.stats.block_cache_misses.load(Ordering::Relaxed)
+    }
+
+    pub fn set_peer_connections(&self, connections: ConnectionStats) {
+        *self.stats.peer_connections.lock().unwrap() = connections;
+    }
+
+    pub fn peer_connections(&self) -> ConnectionStats {
+        *self.stats.peer_connections.lock().unwrap()
+    }
+
+    pub fn set_client_only(&self, client_only: bool) {
+        self.stats.client_only.store(client_only, Ordering::Relaxed);
+    }
+
+    pub fn client_only(&self) -> bool {
+        self.stats.client_only.load(Ordering::Relaxed)
+    }
+
+    pub fn set_clock_drift_ms(&self, drift_ms: i64) {
+        self.stats.clock_drift_ms.store(drift_ms, Ordering::Relaxed)
+    }
+
+    /// none until the clock is measured against the time servers
+    pub fn clock_drift_ms(&self) -> Option<i64> {
+        match self.stats.clock_drift_ms.load(Ordering::Relaxed) {
+            CLOCK_DRIFT_UNDEFINED => None,
+            drift_ms => Some(drift_ms),
+        }
+    }
+
+    pub fn add_slots_scheduled(&self, epoch: u32, count: u64) {
+        self.with_pool(|pool| pool.epoch(epoch).scheduled += count)
+    }
+
+    /// record a block created by one of our leaders, its propagation delay
+    /// is measured until a peer first fetches it
+    pub fn add_block_produced(&self, epoch: u32, id: HeaderHash) {
+        self.with_pool(|pool| {
+            pool.epoch(epoch).produced += 1;
+            let now = Instant::now();
+            pool.unpropagated
+                .retain(|_, created| now.duration_since(*created) < PROPAGATION_TRACKING_TIMEOUT);
+            pool.unpropagated.insert(id, now);
+        })
+    }
+
+    pub fn add_slot_missed(&self, reason: MissedSlotReason) {
+        self.with_pool(|pool| {
+            let missed = &mut pool.missed_slots;
+            let counter = match reason {
+                MissedSlotReason::TipBehind => &mut missed.tip_behind,
+                MissedSlotReason::TooLate => &mut missed.too_late,
+                MissedSlotReason::EnclaveError => &mut missed.enclave_error,
+                MissedSlotReason::Timeout => &mut missed.timeout,
+                MissedSlotReason::Syncing => &mut missed.syncing,
+                MissedSlotReason::ClockDrift => &mut missed.clock_drift,
+                MissedSlotReason::StaleTip => &mut missed.stale_tip,
+            };
+            *counter += 1;
+        })
+    }
+
+    /// to be called when a peer fetches blocks from the node
+    pub fn blocks_fetched(&self, ids: &[HeaderHash]) {
+        self.with_pool(|pool| {
+            if pool.unpropagated.is_empty() {
+                return;
+            }
+            for id in ids {
+                if let Some(created) = pool.unpropagated.remove(id) {
+                    pool.propagation_total += created.elapsed();
+                    pool.propagation_count += 1;
+                }
+            }
+        })
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.with_pool(|pool| PoolStats {
+            epochs: pool.epochs.values().cloned().collect(),
+            avg_propagation_delay_ms: if pool.propagation_count == 0 {
+                None
+            } else {
+                Some((pool.propagation_total / pool.propagation_count).as_millis() as u64)
+            },
+            missed_slots: pool.missed_slots.clone(),
+        })
+    }
+
+    pub fn add_queue(&self, task: &'static str, monitor: QueueMonitor) {
+        self.stats
+            .queues
+            .lock()
+            .expect("queue stats poisoned")
+            .push((task, monitor))
+    }
+
+    pub fn queue_stats(&self) -> Vec<(&'static str, QueueStats)> {
+        self.stats
+            .queues
+            .lock()
+            .expect("queue stats poisoned")
+            .iter()
+            .map(|(task, monitor)| (*task, monitor.stats()))
+            .collect()
+    }
+
+    /// record a message of the given variant processed by the task
+    pub fn add_message_processed(
+        &self,
+        task: &'static str,
+        variant: &'static str,
+        latency: Duration,
+    ) {
+        let mut messages = self.stats.messages.lock().expect("message stats poisoned");
+        let stats = messages.entry((task, variant)).or_default();
+        stats.count += 1;
+        stats.latency += latency;
+    }
+
+    pub fn message_stats(&self) -> Vec<(&'static str, &'static str, MessageStats)> {
+        self.stats
+            .messages
+            .lock()
+            .expect("message stats poisoned")
+            .iter()
+            .map(|(&(task, variant), stats)| (task, variant, *stats))
+            .collect()
+    }
+
+    /// record a fragment rejected with the given code before reaching the pool
+    pub fn add_fragment_rejected(&self, code: &'static str) {
+        *self
+            .stats
+            .fragments_rejected
+            .lock()
+            .expect("rejection stats poisoned")
+            .entry(code)
+            .or_default() += 1;
+    }
+
+    pub fn fragments_rejected(&self) -> Vec<(&'static str, u64)> {
+        self.stats
+            .fragments_rejected
+            .lock()
+            .expect("rejection stats poisoned")
+            .iter()
+            .map(|(code, count)| (*code, *count))
+            .collect()
+    }
+
+    /// record a message of a peer over the limits, the peer being disconnected
+    pub fn add_limit_violation(&self, message: &'static str) {
+        *self
+            .stats
+            .limit_violations
+            .lock()
+            .expect("limit violation stats poisoned")
+            .entry(message)
+            .or_default() += 1;
+    }
+
+    pub fn limit_violations(&self) -> Vec<(&'static str, u64)> {
+        self.stats
+            .limit_violations
+            .lock()
+            .expect("limit violation stats poisoned")
+            .iter()
+            .map(|(message, count)| (*message, *count))
+            .collect()
+    }
+
+    /// record a node dropped from the gossip of a peer
+    pub fn add_gossip_rejection(&self, reason: &'static str) {
+        *self
+            .stats
+            .gossip_rejections
+            .lock()
+            .expect("gossip rejection stats poisoned")
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn gossip_rejections(&self) -> Vec<(&'static str, u64)> {
+        self.stats
+            .gossip_rejections
+            .lock()
+            .expect("gossip rejection stats poisoned")
+            .iter()
+            .map(|(reason, count)| (*reason, *count))
+            .collect()
+    }
+
+    /// record a peer throttled over the rate limit of a subscription
+    pub fn add_subscription_throttle(&self, subscription: &'static str) {
+        *self
+            .stats
+            .subscription_throttles
+            .lock()
+            .expect("subscription throttle stats poisoned")
+            .entry(subscription)
+            .or_default() += 1;
+    }
+
+    pub fn subscription_throttles(&self) -> Vec<(&'static str, u64)> {
+        self.stats
+            .subscription_throttles
+            .lock()
+            .expect("subscription throttle stats poisoned")
+            .iter()
+            .map(|(subscription, count)| (*subscription, *count))
+            .collect()
+    }
+
+    /// record a handshake failed on the protocol version reported by the
+    /// peer. The version comes from the peer, it is cut to a few characters
+    /// safe in a metric label and the versions past the first ones seen are
+    /// counted as `other`.
+    pub fn add_unsupported_version(&self, version: &str) {
+        let version: String = version
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+            .take(UNSUPPORTED_VERSION_MAX_LEN)
+            .collect();
+        let mut versions = self
+            .stats
+            .unsupported_versions
+            .lock()
+            .expect("unsupported version stats poisoned");
+        let version =
+            if versions.contains_key(&version) || versions.len() < UNSUPPORTED_VERSIONS_KEPT {
+                version
+            } else {
+                "other".to_owned()
+            };
+        *versions.entry(version).or_default() += 1;
+    }
+
+    pub fn unsupported_versions(&self) -> Vec<(String, u64)> {
+        self.stats
+            .unsupported_versions
+            .lock()
+            .expect("unsupported version stats poisoned")
+            .iter()
+            .map(|(version, count)| (version.clone(), *count))
+            .collect()
+    }
+
+    pub fn add_compressed_bytes(&self, counted: CompressedBytes, bytes: u64) {
+        self.stats.compressed_bytes[counted as usize].fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// the bytes of the compressed connections, by direction and by layer
+    pub fn compressed_bytes(&self) -> Vec<(&'static str, &'static str, u64)> {
+        CompressedBytes::ALL
+            .iter()
+            .map(|counted| {
+                let (direction, layer) = counted.labels();
+                let bytes = self.stats.compressed_bytes[*counted as usize].load(Ordering::Relaxed);
+                (direction, layer, bytes)
+            })
+            .collect()
+    }
+
+    /// record a connection negotiated with a peer, in either direction
+    pub fn add_peer_version(&self, version: u16) {
+        *self
+            .stats
+            .peer_versions
+            .lock()
+            .expect("peer version stats poisoned")
+            .entry(version)
+            .or_default() += 1;
+    }
+
+    pub fn peer_versions(&self) -> Vec<(u16, u64)> {
+        self.stats
+            .peer_versions
+            .lock()
+            .expect("peer version stats poisoned")
+            .iter()
+            .map(|(version, count)| (*version, *count))
+            .collect()
+    }
+
+    pub fn maintenance_started(&self, action: &'static str) {
+        let mut maintenance = self
+            .stats
+            .maintenance
+            .lock()
+            .expect("maintenance stats poisoned");
+        let stats = maintenance.entry(action).or_default();
+        stats.running_since = Some(Instant::now());
+        stats.progress = None;
+    }
+
+    pub fn maintenance_progress(&self, action: &'static str, done: u64, total: u64) {
+        let mut maintenance = self
+            .stats
+            .maintenance
+            .lock()
+            .expect("maintenance stats poisoned");
+        maintenance.entry(action).or_default().progress = Some((done, total));
+    }
+
+    pub fn maintenance_completed(&self, action: &'static str, duration: Duration, success: bool) {
+        let mut maintenance = self
+            .stats
+            .maintenance
+            .lock()
+            .expect("maintenance stats poisoned");
+        let stats = maintenance.entry(action).or_default();
+        stats.running_since = None;
+        stats.progress = None;
+        stats.last_duration = duration;
+        stats.last_completed = Some(SecondsSinceUnixEpoch::now());
+        if success {
+            stats.succeeded += 1;
+        } else {
+            stats.failed += 1;
+        }
+    }
+
+    pub fn maintenance_stats(&self) -> Vec<(&'static str, MaintenanceStats)> {
+        self.stats
+            .maintenance
+            .lock()
+            .expect("maintenance stats poisoned")
+            .iter()
+            .map(|(action, stats)| (*action, *stats))
+            .collect()
+    }
+
+    pub fn set_storage_size(&self, bytes: u64) {
+        self.stats.storage_size.store(bytes, Ordering::Relaxed)
+    }
+
+    /// none until measured by the maintenance
+    pub fn storage_size(&self) -> Option<u64> {
+        match self.stats.storage_size.load(Ordering::Relaxed) {
+            STORAGE_SIZE_UNDEFINED => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    pub fn set_disk_space_available(&self, bytes: u64) {
+        self.stats
+            .disk_space_available
+            .store(bytes, Ordering::Relaxed)
+    }
+
+    /// none until the storage volume is checked
+    pub fn disk_space_available(&self) -> Option<u64> {
+        match self.stats.disk_space_available.load(Ordering::Relaxed) {
+            DISK_SPACE_UNDEFINED => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    pub fn add_epoch_transition(&self, epoch: u32, reentered: bool) {
+        self.stats
+            .epoch_entered
+            .store(u64::from(epoch), Ordering::Relaxed);
+        if reentered {
+            self.stats.epoch_reentries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// none until the main chain enters an epoch
+    pub fn epoch_entered(&self) -> Option<u32> {
+        match self.stats.epoch_entered.load(Ordering::Relaxed) {
+            EPOCH_UNDEFINED => None,
+            epoch => Some(epoch as u32),
+        }
+    }
+
+    pub fn epoch_reentries(&self) -> u64 {
+        self.stats.epoch_reentries.load(Ordering::Relaxed)
+    }
+
+    pub fn add_deep_reorg_refused(&self) {
+        self.stats.deep_reorgs_refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn deep_reorgs_refused(&self) -> u64 {
+        self.stats.deep_reorgs_refused.load(Ordering::Relaxed)
+    }
+
+    fn with_pool<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut PoolCounters) -> R,
+    {
+        f(&mut self.stats.pool.lock().expect("pool stats poisoned"))
+    }
+}
+
+impl PoolCounters {
+    fn epoch(&mut self, epoch: u32) -> &mut EpochProduction {
+        while self.epochs.len() >= POOL_EPOCHS_KEPT && !self.epochs.contains_key(&epoch) {
+            let oldest = *self.epochs.keys().next().expect("epochs is not empty");
+            self.epochs.remove(&oldest);
+        }
+        self.epochs.entry(epoch).or_insert_with(|| EpochProduction {
+            epoch,
+            ..EpochProduction::default()
+        })
+    }
 }
@@ -4,23 +4,59 @@
 use futures::prelude::*;
 use futures::sync::mpsc::{self, Receiver, Sender};
 pub use futures::sync::mpsc::{SendError, TrySendError};
+use std::sync::{
+    atomic::{AtomicIsize, AtomicU64, Ordering},
+    Arc, Mutex,
+};
 
 /// The output end of an in-memory FIFO channel.
 #[derive(Debug)]
-pub struct MessageBox<Msg>(Sender<Msg>);
+pub struct MessageBox<Msg>(Sender<Msg>, QueueMonitor);
 
 /// The input end of an in-memory FIFO channel.
 /// This can be read asynchronously in a Tokio task using its
 /// Stream implementation.
 #[derive(Debug)]
-pub struct MessageQueue<Msg>(Receiver<Msg>);
+pub struct MessageQueue<Msg>(Receiver<Msg>, QueueMonitor);
+
+/// A handle on the input end of a channel which can be cloned, so a task
+/// started again after a failure reads the messages left in the channel.
+/// Only one of the handles is read at a time.
+#[derive(Debug)]
+pub struct SharedQueue<Msg>(Arc<Mutex<MessageQueue<Msg>>>);
+
+/// The counters of the messages going through a channel, shared by its ends.
+#[derive(Debug, Clone)]
+pub struct QueueMonitor(Arc<QueueCounters>);
+
+#[derive(Debug)]
+struct QueueCounters {
+    capacity: usize,
+    // a message may be read before its sender accounts for it
+    depth: AtomicIsize,
+    received: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A snapshot of the counters of a channel.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStats {
+    pub capacity: usize,
+    /// the number of messages sent and not read yet
+    pub depth: usize,
+    /// the number of messages read since the channel was created
+    pub received: u64,
+    /// the number of messages not sent because the channel was full
+    pub dropped: u64,
+}
 
 /// Constructs an in-memory channel and returns the output and input halves.
 /// The parameter specifies the number of messages that are allowed
 /// to be pending in the channel.
 pub fn channel<Msg>(buffer: usize) -> (MessageBox<Msg>, MessageQueue<Msg>) {
     let (tx, rx) = mpsc::channel(buffer);
-    (MessageBox(tx), MessageQueue(rx))
+    let monitor = QueueMonitor::new(buffer);
+    (MessageBox(tx, monitor.clone()), MessageQueue(rx, monitor))
 }
 
 impl<Msg> MessageBox<Msg> {
@@ -34,7 +70,18 @@ impl<Msg> MessageBox<Msg> {
     /// If the channel is full or the receiving MessageQueue has been dropped,
     /// an error is returned in `Err`.
     pub fn try_send(&mut self, a: Msg) -> Result<(), TrySendError<Msg>> {
-        self.0.try_send(a)
+        match self.0.try_send(a) {
+            Ok(()) => {
+                self.1.sent();
+                Ok(())
+            }
+            Err(e) => {
+                if e.is_full() {
+                    self.1.dropped();
+                }
+                Err(e)
+            }
+        }
     }
 
     /// Polls the channel to determine if there is guaranteed to be capacity
@@ -42,6 +89,10 @@ impl<Msg> MessageBox<Msg> {
     pub fn poll_ready(&mut self) -> Poll<(), SendError<()>> {
         self.0.poll_ready()
     }
+
+    pub fn monitor(&self) -> &QueueMonitor {
+        &self.1
+    }
 }
 
 impl<Msg> Sink for MessageBox<Msg> {
@@ -49,7 +100,11 @@ impl<Msg> Sink for MessageBox<Msg> {
     type SinkError = SendError<Msg>;
 
     fn start_send(&mut self, msg: Msg) -> StartSend<Msg, SendError<Msg>> {
-        self.0.start_send(msg)
+        let polled = self.0.start_send(msg)?;
+        if polled.is_ready() {
+            self.1.sent();
+        }
+        Ok(polled)
     }
 
     fn poll_complete(&mut self) -> Poll<(), SendError<Msg>> {
@@ -65,12 +120,69 @@ impl<Msg> Stream for MessageQueue<Msg> {
     type Item = Msg;
     type Error = ();
     fn poll(&mut self) -> Poll<Option<Msg>, ()> {
-        self.0.poll()
+        let polled = self.0.poll()?;
+        if let Async::Ready(Some(_)) = polled {
+            self.1.received();
+        }
+        Ok(polled)
+    }
+}
+
+impl<Msg> MessageQueue<Msg> {
+    pub fn into_shared(self) -> SharedQueue<Msg> {
+        SharedQueue(Arc::new(Mutex::new(self)))
+    }
+}
+
+impl<Msg> Stream for SharedQueue<Msg> {
+    type Item = Msg;
+    type Error = ();
+    fn poll(&mut self) -> Poll<Option<Msg>, ()> {
+        self.0.lock().unwrap().poll()
+    }
+}
+
+impl<Msg> Clone for SharedQueue<Msg> {
+    fn clone(&self) -> Self {
+        SharedQueue(self.0.clone())
     }
 }
 
 impl<Msg> Clone for MessageBox<Msg> {
     fn clone(&self) -> Self {
-        MessageBox(self.0.clone())
+        MessageBox(self.0.clone(), self.1.clone())
+    }
+}
+
+impl QueueMonitor {
+    pub fn new(capacity: usize) -> Self {
+        QueueMonitor(Arc::new(QueueCounters {
+            capacity,
+            depth: AtomicIsize::new(0),
+            received: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn sent(&self) {
+        self.0.depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn received(&self) {
+        self.0.depth.fetch_sub(1, Ordering::Relaxed);
+        self.0.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            capacity: self.0.capacity,
+            depth: self.0.depth.load(Ordering::Relaxed).max(0) as usize,
+            received: self.0.received.load(Ordering::Relaxed),
+            dropped: self.0.dropped.load(Ordering::Relaxed),
+        }
     }
 }
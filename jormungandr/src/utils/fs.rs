@@ -0,0 +1,40 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// a temporary file next to `path` to write its new content to before it is
+/// renamed over `path`. The name is unique to the write, so two writes of the
+/// same file by this node or by another process do not mix their content.
+pub fn temp_path(path: &Path) -> PathBuf {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_paths_are_unique_next_to_the_file() {
+        let path = Path::new("/var/lib/jormungandr/address_book.json");
+        let first = temp_path(path);
+        let second = temp_path(path);
+
+        assert_ne!(first, second);
+        for temp in &[first, second] {
+            assert_eq!(temp.parent(), path.parent());
+            let name = temp.file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("address_book.json."));
+            assert!(name.ends_with(".tmp"));
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod async_msg;
 pub mod borrow;
+pub mod fs;
+pub mod sntp;
 pub mod task;
+pub mod watchdog;
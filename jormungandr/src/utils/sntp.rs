@@ -0,0 +1,267 @@
+//! a minimal SNTP client (RFC 4330), measuring how far the clock of the node
+//! is from the one of a time server
+//!
+//! The slots are computed from the local clock: a node whose clock drifts
+//! misses the slots its leaders are elected for, or creates blocks its peers
+//! see as coming from the future.
+//!
+//! A response is only accepted from the address of the server and carrying
+//! the transmit timestamp of the request as its originate timestamp, the
+//! other datagrams received are dropped.
+
+use super::task::blocking;
+use futures::future::{self, Loop};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs as _},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::UdpSocket, prelude::*};
+
+const NTP_PACKET_SIZE: usize = 48;
+
+/// the seconds from the NTP epoch, in 1900, to the unix epoch
+const NTP_UNIX_OFFSET_SECS: i64 = 2_208_988_800;
+
+/// leap indicator 0, version 4, client mode
+const CLIENT_REQUEST_HEADER: u8 = 0b00_100_011;
+
+const SERVER_MODE: u8 = 4;
+
+/// the local clock measured against the time server
+#[derive(Debug, Clone, Copy)]
+pub struct ClockOffset {
+    /// how far the local clock is ahead of the server, negative if it is
+    /// behind
+    pub offset_ms: i64,
+    /// the time the request and the response spent on the network
+    pub round_trip_ms: i64,
+}
+
+/// query the time server, `pool.ntp.org:123` for instance
+pub fn query(
+    server: &str,
+    timeout: Duration,
+) -> impl Future<Item = ClockOffset, Error = io::Error> {
+    let server = server.to_owned();
+    // the name of the server is resolved by the blocking resolver of the
+    // system
+    blocking(move || resolve(&server))
+        .and_then(|server_addr| {
+            let local = if server_addr.is_ipv4() {
+                SocketAddr::from(([0, 0, 0, 0], 0))
+            } else {
+                SocketAddr::from(([0u16; 8], 0))
+            };
+            UdpSocket::bind(&local).map(|socket| (socket, server_addr))
+        })
+        .and_then(|(socket, server_addr)| {
+            let sent_at = SystemTime::now();
+            let transmit = ntp_timestamp(sent_at);
+            let mut request = vec![0; NTP_PACKET_SIZE];
+            request[0] = CLIENT_REQUEST_HEADER;
+            request[40..48].copy_from_slice(&transmit);
+            socket
+                .send_dgram(request, &server_addr)
+                .and_then(move |(socket, _)| receive_response(socket, server_addr, transmit))
+                .map(move |response| (sent_at, SystemTime::now(), response))
+        })
+        .timeout(timeout)
+        .map_err(|e| {
+            e.into_inner().unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::TimedOut, "no response from the time server")
+            })
+        })
+        .and_then(|(sent_at, received_at, response)| {
+            parse_response(sent_at, received_at, &response)
+        })
+}
+
+/// the first datagram answering the request with the `transmit` timestamp
+fn receive_response(
+    socket: UdpSocket,
+    server_addr: SocketAddr,
+    transmit: [u8; 8],
+) -> impl Future<Item = Vec<u8>, Error = io::Error> {
+    future::loop_fn(socket, move |socket| {
+        socket.recv_dgram(vec![0; NTP_PACKET_SIZE]).map(
+            move |(socket, mut response, len, source)| {
+                response.truncate(len);
+                if is_response_to(&transmit, server_addr, source, &response) {
+                    Loop::Break(response)
+                } else {
+                    Loop::Continue(socket)
+                }
+            },
+        )
+    })
+}
+
+/// whether the datagram received from `source` answers the request with
+/// the `transmit` timestamp sent to `server_addr`
+fn is_response_to(
+    transmit: &[u8; 8],
+    server_addr: SocketAddr,
+    source: SocketAddr,
+    response: &[u8],
+) -> bool {
+    source == server_addr && response.len() >= NTP_PACKET_SIZE && response[24..32] == transmit[..]
+}
+
+fn resolve(server: &str) -> io::Result<SocketAddr> {
+    server.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "the time server address does not resolve",
+        )
+    })
+}
+
+fn parse_response(
+    sent_at: SystemTime,
+    received_at: SystemTime,
+    response: &[u8],
+) -> io::Result<ClockOffset> {
+    let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+    if response.len() < NTP_PACKET_SIZE {
+        return Err(invalid("truncated response"));
+    }
+    if response[0] & 0b111 != SERVER_MODE {
+        return Err(invalid("not a response of a server"));
+    }
+    // a stratum of 0 is a kiss-o'-death: the server refuses to serve
+    if response[1] == 0 {
+        return Err(invalid("the time server refused the request"));
+    }
+    let server_received = ntp_timestamp_ms(&response[32..40]);
+    let server_sent = ntp_timestamp_ms(&response[40..48]);
+    let client_sent = unix_ms(sent_at);
+    let client_received = unix_ms(received_at);
+
+    let server_ahead = ((server_received - client_sent) + (server_sent - client_received)) / 2;
+    Ok(ClockOffset {
+        offset_ms: -server_ahead,
+        round_trip_ms: (client_received - client_sent) - (server_sent - server_received),
+    })
+}
+
+/// an NTP timestamp, in milliseconds since the unix epoch
+fn ntp_timestamp_ms(bytes: &[u8]) -> i64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    (i64::from(seconds) - NTP_UNIX_OFFSET_SECS) * 1000 + ((i64::from(fraction) * 1000) >> 32)
+}
+
+/// the NTP timestamp of a time after the unix epoch
+fn ntp_timestamp(time: SystemTime) -> [u8; 8] {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = (since.as_secs() as i64 + NTP_UNIX_OFFSET_SECS) as u32;
+    let fraction = ((u64::from(since.subsec_nanos()) << 32) / 1_000_000_000) as u32;
+    let mut timestamp = [0; 8];
+    timestamp[..4].copy_from_slice(&seconds.to_be_bytes());
+    timestamp[4..].copy_from_slice(&fraction.to_be_bytes());
+    timestamp
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::UdpSocket as StdUdpSocket, thread};
+
+    fn at_ms(ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    /// the response of a server which received the request at `received`
+    /// and answered it at `sent`
+    fn response(originate: [u8; 8], received: SystemTime, sent: SystemTime) -> Vec<u8> {
+        let mut response = vec![0; NTP_PACKET_SIZE];
+        response[0] = 0b00_100_100;
+        response[1] = 2;
+        response[24..32].copy_from_slice(&originate);
+        response[32..40].copy_from_slice(&ntp_timestamp(received));
+        response[40..48].copy_from_slice(&ntp_timestamp(sent));
+        response
+    }
+
+    #[test]
+    fn ntp_timestamps_round_trip() {
+        let time = at_ms(1_581_331_283_593);
+        // the fraction of the timestamp rounds the milliseconds down
+        let round_trip = ntp_timestamp_ms(&ntp_timestamp(time));
+        assert!((unix_ms(time) - round_trip).abs() <= 1);
+    }
+
+    #[test]
+    fn offset_of_a_clock_ahead_of_the_server() {
+        // the local clock is 500ms ahead, the network takes 20ms each way
+        // and the server takes 10ms to answer
+        let response = response([0; 8], at_ms(1_000_020 - 500), at_ms(1_000_030 - 500));
+        let offset = parse_response(at_ms(1_000_000), at_ms(1_000_050), &response).unwrap();
+        assert_eq!(offset.offset_ms, 500);
+        assert_eq!(offset.round_trip_ms, 40);
+    }
+
+    #[test]
+    fn kiss_of_death_is_refused() {
+        let mut response = response([0; 8], at_ms(1_000), at_ms(1_000));
+        response[1] = 0;
+        assert!(parse_response(at_ms(1_000), at_ms(1_000), &response).is_err());
+    }
+
+    #[test]
+    fn client_request_is_not_a_response() {
+        let mut response = response([0; 8], at_ms(1_000), at_ms(1_000));
+        response[0] = CLIENT_REQUEST_HEADER;
+        assert!(parse_response(at_ms(1_000), at_ms(1_000), &response).is_err());
+    }
+
+    #[test]
+    fn only_the_response_of_the_server_to_the_request_is_accepted() {
+        let server = SocketAddr::from(([127, 0, 0, 1], 123));
+        let other = SocketAddr::from(([127, 0, 0, 1], 124));
+        let transmit = ntp_timestamp(at_ms(1_000));
+        let answer = response(transmit, at_ms(1_000), at_ms(1_000));
+        let stale = response(ntp_timestamp(at_ms(900)), at_ms(900), at_ms(900));
+
+        assert!(is_response_to(&transmit, server, server, &answer));
+        assert!(!is_response_to(&transmit, server, other, &answer));
+        assert!(!is_response_to(&transmit, server, server, &stale));
+        assert!(!is_response_to(&transmit, server, server, &answer[..40]));
+    }
+
+    #[test]
+    fn spoofed_datagrams_are_dropped() {
+        let server = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let answering = thread::spawn(move || {
+            let mut request = [0; NTP_PACKET_SIZE];
+            let (_, client) = server.recv_from(&mut request).unwrap();
+            let mut originate = [0; 8];
+            originate.copy_from_slice(&request[40..48]);
+            let now = SystemTime::now();
+            // a datagram from another address, then a stale response
+            let spoofer = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+            spoofer
+                .send_to(&response(originate, now, now), client)
+                .unwrap();
+            server.send_to(&response([0; 8], now, now), client).unwrap();
+            let now = SystemTime::now() + Duration::from_secs(3);
+            server
+                .send_to(&response(originate, now, now), client)
+                .unwrap();
+        });
+
+        let query = query(&server_addr.to_string(), Duration::from_secs(5));
+        let offset = tokio::runtime::current_thread::block_on_all(query).unwrap();
+        answering.join().unwrap();
+        assert!(offset.offset_ms < -2_000, "offset {}", offset.offset_ms);
+    }
+}
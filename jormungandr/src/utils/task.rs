@@ -5,26 +5,67 @@
 //! modules utilized in jormungandr.
 //!
 
-use crate::utils::async_msg::{self, MessageBox};
+use crate::utils::{
+    async_msg::{self, MessageBox, QueueMonitor},
+    watchdog::Watchdog,
+};
+use futures::future::{self, Loop};
 use slog::Logger;
 use std::{
     any::Any,
+    collections::{HashMap, VecDeque},
     panic::{catch_unwind, AssertUnwindSafe},
-    sync::mpsc::{self, Receiver, Sender},
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
     thread,
     time::{Duration, Instant},
 };
-use tokio::prelude::{stream, Future, IntoFuture, Stream};
+use tokio::prelude::{stream, Async, Future, IntoFuture, Stream};
 use tokio::runtime::{self, Runtime, TaskExecutor};
-
-// Limit on the length of a task message queue
-const MESSAGE_QUEUE_LEN: usize = 1000;
+use tokio::timer::Interval;
 
 /// hold onto the different services created
 pub struct Services {
     logger: Logger,
     services: Vec<Service>,
     finish_listener: ServiceFinishListener,
+    restart_policy: RestartPolicy,
+    watchdog: Option<Watchdog>,
+    runtime_threads: RuntimeThreads,
+    task_runtime_threads: HashMap<String, RuntimeThreads>,
+}
+
+/// the number of threads of the runtime of a service, the tokio defaults
+/// being used for the ones not set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuntimeThreads {
+    /// the worker threads, one per CPU core by default
+    pub core_threads: Option<usize>,
+    /// the threads of the blocking pool, 100 by default
+    pub blocking_threads: Option<usize>,
+}
+
+impl RuntimeThreads {
+    /// the numbers set in `self`, or else in `defaults`
+    fn or(self, defaults: RuntimeThreads) -> Self {
+        RuntimeThreads {
+            core_threads: self.core_threads.or(defaults.core_threads),
+            blocking_threads: self.blocking_threads.or(defaults.blocking_threads),
+        }
+    }
+}
+
+/// what happens when a supervised service fails, returning an error or
+/// panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// the node shuts down
+    Shutdown,
+    /// the service is started again, unless it already failed `max_restarts`
+    /// times within `window` in which case the node shuts down
+    Restart { max_restarts: u32, window: Duration },
 }
 
 /// wrap up a service
@@ -43,7 +84,6 @@ pub struct Service {
     up_time: Instant,
 
     /// the tokio Runtime running the service in
-    #[allow(dead_code)]
     runtime: Option<Runtime>,
 }
 
@@ -66,7 +106,7 @@ pub struct TokioServiceInfo {
     executor: TaskExecutor,
 }
 
-pub struct TaskMessageBox<Msg>(Sender<Msg>);
+pub struct TaskMessageBox<Msg>(SyncSender<Msg>, QueueMonitor);
 
 /// Input for the different task with input service
 ///
@@ -87,6 +127,73 @@ impl Services {
             logger: logger,
             services: Vec::new(),
             finish_listener: ServiceFinishListener::new(),
+            restart_policy: RestartPolicy::Shutdown,
+            watchdog: None,
+            runtime_threads: RuntimeThreads::default(),
+            task_runtime_threads: HashMap::new(),
+        }
+    }
+
+    /// set the policy of the services spawned from now on with
+    /// `spawn_future_supervised` or `spawn_future_with_inputs`
+    pub fn set_restart_policy(&mut self, restart_policy: RestartPolicy) {
+        self.restart_policy = restart_policy;
+    }
+
+    /// set the threads of the runtimes of the services spawned from now on
+    /// with `spawn_future` and the like, the ones of `tasks` overriding
+    /// `defaults` for the services of the given names
+    pub fn set_runtime_threads(
+        &mut self,
+        defaults: RuntimeThreads,
+        tasks: HashMap<String, RuntimeThreads>,
+    ) {
+        self.runtime_threads = defaults;
+        self.task_runtime_threads = tasks;
+    }
+
+    /// watch the services spawned from now on with the watchdog
+    pub fn set_watchdog(&mut self, watchdog: Watchdog) {
+        self.watchdog = Some(watchdog);
+    }
+
+    /// watch the progress of the service reading the given queue, for the
+    /// queues not created by `spawn_with_inputs` or `spawn_future_with_inputs`
+    pub fn watch_queue(&self, name: &'static str, monitor: QueueMonitor) {
+        if let Some(watchdog) = self.watchdog.as_ref() {
+            watchdog.watch_queue(name, monitor)
+        }
+    }
+
+    fn build_runtime(&self, name: &'static str) -> Runtime {
+        let threads = self
+            .task_runtime_threads
+            .get(name)
+            .map_or(self.runtime_threads, |threads| {
+                threads.or(self.runtime_threads)
+            });
+        let mut builder = runtime::Builder::new();
+        builder.keep_alive(None).name_prefix(name);
+        if let Some(core_threads) = threads.core_threads {
+            builder.core_threads(core_threads);
+        }
+        if let Some(blocking_threads) = threads.blocking_threads {
+            builder.blocking_threads(blocking_threads);
+        }
+        builder.build().unwrap()
+    }
+
+    /// check in with the watchdog from the runtime of the service, which
+    /// stops when all the threads of the runtime are blocked
+    fn check_in_periodically(&self, name: &'static str, runtime: &mut Runtime, logger: &Logger) {
+        if let Some(watchdog) = self.watchdog.as_ref() {
+            let heartbeat = watchdog.heartbeat(name);
+            let logger = logger.clone();
+            runtime.spawn(
+                Interval::new_interval(watchdog.timeout() / 4)
+                    .for_each(move |_| Ok(heartbeat.check_in()))
+                    .map_err(move |e| error!(logger, "watchdog timer error"; "reason" => %e)),
+            );
         }
     }
 
@@ -133,17 +240,30 @@ impl Services {
     /// the service will stop once there is no more input to read: the function
     /// will be called one last time with `Input::Shutdown` and then will return
     ///
-    pub fn spawn_with_inputs<F, Msg>(&mut self, name: &'static str, mut f: F) -> TaskMessageBox<Msg>
+    /// at most `capacity` inputs are queued, the messages sent beyond that
+    /// are refused
+    pub fn spawn_with_inputs<F, Msg>(
+        &mut self,
+        name: &'static str,
+        capacity: usize,
+        mut f: F,
+    ) -> TaskMessageBox<Msg>
     where
         F: FnMut(&ThreadServiceInfo, Input<Msg>) -> (),
         F: Send + 'static,
         Msg: Send + 'static,
     {
-        let (tx, rx) = mpsc::channel::<Msg>();
+        let (tx, rx) = mpsc::sync_channel::<Msg>(capacity);
+        let monitor = QueueMonitor::new(capacity);
+        let queue_monitor = monitor.clone();
+        self.watch_queue(name, monitor.clone());
 
         self.spawn(name, move |info| loop {
             match rx.recv() {
-                Ok(msg) => f(&info, Input::Input(msg)),
+                Ok(msg) => {
+                    queue_monitor.received();
+                    f(&info, Input::Input(msg))
+                }
                 Err(err) => {
                     warn!(
                         info.logger,
@@ -158,7 +278,7 @@ impl Services {
             }
         });
 
-        TaskMessageBox(tx)
+        TaskMessageBox(tx, monitor)
     }
 
     /// Spawn the given Future in a new dedicated runtime
@@ -167,11 +287,7 @@ impl Services {
         F: FnOnce(TokioServiceInfo) -> T,
         T: Future<Item = (), Error = ()> + Send + 'static,
     {
-        let mut runtime = runtime::Builder::new()
-            .keep_alive(None)
-            .name_prefix(name)
-            .build()
-            .unwrap();
+        let mut runtime = self.build_runtime(name);
 
         let executor = runtime.executor();
 
@@ -180,6 +296,7 @@ impl Services {
             .logger
             .new(o!(crate::log::KEY_TASK => name))
             .into_erased();
+        self.check_in_periodically(name, &mut runtime, &logger);
         let future_service_info = TokioServiceInfo {
             name,
             up_time: now,
@@ -201,12 +318,79 @@ impl Services {
         self.services.push(task);
     }
 
+    /// Spawn the future returned by `f` in a new dedicated runtime, calling
+    /// `f` again to start the service anew when the future fails, if the
+    /// restart policy allows it. The service ends, and the node shuts down,
+    /// when the future completes successfully.
+    ///
+    /// The futures spawned by the failed service on the executor keep
+    /// running, `f` must not start another service holding the same
+    /// resources (e.g. listening on a socket).
+    pub fn spawn_future_supervised<F, T>(&mut self, name: &'static str, mut f: F)
+    where
+        F: FnMut(TokioServiceInfo) -> T,
+        F: Send + 'static,
+        T: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        let mut runtime = self.build_runtime(name);
+
+        let executor = runtime.executor();
+
+        let now = Instant::now();
+        let logger = self
+            .logger
+            .new(o!(crate::log::KEY_TASK => name))
+            .into_erased();
+        self.check_in_periodically(name, &mut runtime, &logger);
+        let restart_policy = self.restart_policy;
+
+        let finish_notifier = self.finish_listener.notifier();
+        let future = future::loop_fn(VecDeque::new(), move |mut failures| {
+            let future_service_info = TokioServiceInfo {
+                name,
+                up_time: Instant::now(),
+                logger: logger.clone(),
+                executor: executor.clone(),
+            };
+            let logger = logger.clone();
+            // This AssertUnwindSafe is safe, because after a panic `f` is
+            // only called again when the restart policy allows it
+            let run = catch_unwind(AssertUnwindSafe(|| f(future_service_info)))
+                .map(|future| AssertUnwindSafe(future).catch_unwind());
+            future::result(run).flatten().then(move |result| {
+                match result {
+                    Ok(Ok(())) => return Ok(Loop::Break(())),
+                    Ok(Err(())) => error!(logger, "Task failed"),
+                    Err(error) => log_service_panic(&logger, &*error),
+                }
+                if restart_policy.allows_restart(&mut failures, Instant::now()) {
+                    warn!(logger, "Restarting the task"; "failures" => failures.len());
+                    Ok(Loop::Continue(failures))
+                } else {
+                    crit!(
+                        logger,
+                        "The task cannot be restarted, shutting down the node"
+                    );
+                    Ok(Loop::Break(()))
+                }
+            })
+        })
+        .then(|_: Result<(), ()>| Ok(finish_notifier.notify()));
+
+        runtime.spawn(future);
+
+        let task = Service::new_runtime(name, runtime, now);
+        self.services.push(task);
+    }
+
     /// Spawn a tokio service that will await messages and will be executed
-    /// sequentially for every received inputs
+    /// sequentially for every received inputs, at most `capacity` of them
+    /// being queued
     pub fn spawn_future_with_inputs<F, Msg, T>(
         &mut self,
         name: &'static str,
-        mut f: F,
+        capacity: usize,
+        f: F,
     ) -> MessageBox<Msg>
     where
         F: FnMut(&TokioServiceInfo, Input<Msg>) -> T,
@@ -215,12 +399,23 @@ impl Services {
         T: IntoFuture<Item = (), Error = ()> + Send + 'static,
         <T as futures::IntoFuture>::Future: Send,
     {
-        let (msg_box, msg_queue) = async_msg::channel(MESSAGE_QUEUE_LEN);
-        self.spawn_future(name, move |future_service_info| {
+        let (msg_box, msg_queue) = async_msg::channel(capacity);
+        self.watch_queue(name, msg_box.monitor().clone());
+        let msg_queue = msg_queue.into_shared();
+        let f = Arc::new(Mutex::new(f));
+        // a restarted service reads the messages left in the queue, with the
+        // state kept by `f`. A panic of `f` poisons the lock, the restarted
+        // service goes on with the state left by the panic
+        self.spawn_future_supervised(name, move |future_service_info| {
+            let f = f.clone();
             msg_queue
+                .clone()
                 .map(Input::Input)
                 .chain(stream::once(Ok(Input::Shutdown)))
-                .for_each(move |input| f(&future_service_info, input))
+                .for_each(move |input| {
+                    let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                    (&mut *f)(&future_service_info, input)
+                })
         });
         msg_box
     }
@@ -229,6 +424,45 @@ impl Services {
     pub fn wait_any_finished(&self) {
         self.finish_listener.wait_any_finished();
     }
+
+    /// stop the runtime of the service, dropping its futures along the
+    /// connections and the sockets they hold. The services running in a
+    /// thread are left running.
+    pub fn shutdown(&mut self, name: &'static str) {
+        for service in self
+            .services
+            .iter_mut()
+            .filter(|service| service.name == name)
+        {
+            if let Some(runtime) = service.runtime.take() {
+                info!(self.logger, "stopping task"; crate::log::KEY_TASK => name);
+                let _ = runtime.shutdown_now().wait();
+            }
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// record a failure at `now` in the failures of the service, returning
+    /// if it can be restarted
+    fn allows_restart(&self, failures: &mut VecDeque<Instant>, now: Instant) -> bool {
+        match *self {
+            RestartPolicy::Shutdown => false,
+            RestartPolicy::Restart {
+                max_restarts,
+                window,
+            } => {
+                while failures
+                    .front()
+                    .map_or(false, |failure| now.duration_since(*failure) > window)
+                {
+                    failures.pop_front();
+                }
+                failures.push_back(now);
+                failures.len() <= max_restarts as usize
+            }
+        }
+    }
 }
 
 impl ThreadServiceInfo {
@@ -332,13 +566,30 @@ impl Service {
 
 impl<Msg> Clone for TaskMessageBox<Msg> {
     fn clone(&self) -> Self {
-        TaskMessageBox(self.0.clone())
+        TaskMessageBox(self.0.clone(), self.1.clone())
     }
 }
 
 impl<Msg> TaskMessageBox<Msg> {
-    pub fn send_to(&self, a: Msg) {
-        self.0.send(a).unwrap()
+    /// send the message if the queue of the service is not full, the
+    /// message is returned in the error otherwise
+    pub fn try_send_to(&self, a: Msg) -> Result<(), TrySendError<Msg>> {
+        match self.0.try_send(a) {
+            Ok(()) => {
+                self.1.sent();
+                Ok(())
+            }
+            Err(e) => {
+                if let TrySendError::Full(_) = e {
+                    self.1.dropped();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub fn monitor(&self) -> &QueueMonitor {
+        &self.1
     }
 }
 
@@ -382,6 +633,25 @@ impl ServiceFinishNotifier {
     }
 }
 
+/// run the blocking IO in a blocking section of the thread pool of the
+/// runtime, so the other tasks of the runtime keep running on its other
+/// threads, waiting for one of its blocking threads to be free. Outside of
+/// a thread pool, the IO runs on the thread of the task.
+pub fn blocking<F, T, E>(f: F) -> impl Future<Item = T, Error = E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    let mut f = Some(f);
+    future::poll_fn(move || {
+        let run = || f.take().expect("blocking IO polled after completion")();
+        match tokio_threadpool::blocking(run) {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => f.take().expect("blocking IO polled after completion")().map(Async::Ready),
+        }
+    })
+}
+
 fn log_service_panic(logger: &Logger, error: &dyn Any) {
     let reason_logger = error
         .downcast_ref::<&str>()
@@ -389,3 +659,71 @@ fn log_service_panic(logger: &Logger, error: &dyn Any) {
     let panic_logger = reason_logger.as_ref().unwrap_or(logger);
     crit!(panic_logger, "Task panicked");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn restart(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy::Restart {
+            max_restarts,
+            window: WINDOW,
+        }
+    }
+
+    #[test]
+    fn shutdown_policy_never_restarts() {
+        let mut failures = VecDeque::new();
+        assert!(!RestartPolicy::Shutdown.allows_restart(&mut failures, Instant::now()));
+    }
+
+    #[test]
+    fn restarts_up_to_max_restarts_within_the_window() {
+        let policy = restart(3);
+        let start = Instant::now();
+        let mut failures = VecDeque::new();
+        for i in 0..3 {
+            let now = start + Duration::from_secs(i);
+            assert!(policy.allows_restart(&mut failures, now));
+        }
+        assert!(!policy.allows_restart(&mut failures, start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn failures_out_of_the_window_are_forgotten() {
+        let policy = restart(2);
+        let start = Instant::now();
+        let mut failures = VecDeque::new();
+        assert!(policy.allows_restart(&mut failures, start));
+        assert!(policy.allows_restart(&mut failures, start + Duration::from_secs(1)));
+
+        // the first failure is out of the window, the second one is not
+        let now = start + WINDOW + Duration::from_millis(500);
+        assert!(policy.allows_restart(&mut failures, now));
+        assert_eq!(failures.len(), 2);
+
+        // all the failures are out of the window
+        let now = now + WINDOW + Duration::from_secs(1);
+        assert!(policy.allows_restart(&mut failures, now));
+        assert_eq!(failures.len(), 1);
+    }
+
+    #[test]
+    fn blocking_io_runs_in_and_out_of_a_thread_pool() {
+        let mut runtime = Runtime::new().unwrap();
+        let result: Result<u32, ()> = runtime.block_on(blocking(|| Ok(1)));
+        assert_eq!(result, Ok(1));
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result: Result<u32, ()> = runtime.block_on(blocking(|| Err(())));
+        assert_eq!(result, Err(()));
+    }
+
+    #[test]
+    fn no_restart_allowed_with_zero_max_restarts() {
+        let mut failures = VecDeque::new();
+        assert!(!restart(0).allows_restart(&mut failures, Instant::now()));
+    }
+}
@@ -0,0 +1,195 @@
+//! detection of the services which stopped making progress
+//!
+//! The tokio services check in periodically from their runtime, and the
+//! services processing inputs are expected to read their queue while
+//! messages are waiting in it. A service doing neither within the timeout is
+//! reported as stuck along the state of all the queues, and the process is
+//! aborted if configured so, for the service manager to restart the node.
+//! The watchdog of the service manager, if any, is fed as long as no service
+//! is stuck.
+
+use crate::{service_manager, utils::async_msg::QueueMonitor};
+use slog::Logger;
+use std::{
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// time left to the asynchronous log drains to write the diagnostic before
+/// the process is aborted
+const ABORT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct Watchdog {
+    services: Arc<Mutex<Vec<WatchedService>>>,
+    timeout: Duration,
+}
+
+/// the last time a service checked in
+#[derive(Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+struct WatchedService {
+    name: &'static str,
+    heartbeat: Option<Heartbeat>,
+    queue: Option<QueueProgress>,
+    stuck: bool,
+}
+
+struct QueueProgress {
+    monitor: QueueMonitor,
+    received: u64,
+    since: Instant,
+}
+
+impl Watchdog {
+    /// start checking the services in a dedicated thread, so the detection
+    /// does not depend on the runtimes of the services
+    pub fn start(logger: Logger, timeout: Duration, abort: bool) -> Self {
+        let watchdog = Watchdog {
+            services: Arc::new(Mutex::new(Vec::new())),
+            timeout,
+        };
+        let services = watchdog.services.clone();
+        let check_interval = match service_manager::watchdog_interval() {
+            Some(interval) => (timeout / 4).min(interval / 2),
+            None => timeout / 4,
+        };
+        thread::Builder::new()
+            .name("watchdog".to_owned())
+            .spawn(move || loop {
+                thread::sleep(check_interval);
+                let mut services = services.lock().unwrap();
+                if check(&logger, &mut services, timeout) && abort {
+                    crit!(logger, "aborting the node, a task is stuck");
+                    thread::sleep(ABORT_GRACE_PERIOD);
+                    process::abort();
+                }
+                if services.iter().all(|service| !service.stuck) {
+                    service_manager::feed_watchdog();
+                }
+            })
+            .unwrap_or_else(|err| panic!("Cannot spawn thread: {}", err));
+        watchdog
+    }
+
+    /// the interval the services are expected to check in within
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// watch the service through the returned heartbeat
+    pub fn heartbeat(&self, name: &'static str) -> Heartbeat {
+        let heartbeat = Heartbeat(Arc::new(Mutex::new(Instant::now())));
+        self.with_service(name, |service| {
+            service.heartbeat = Some(heartbeat.clone());
+            service.stuck = false;
+        });
+        heartbeat
+    }
+
+    /// watch the service reading the given queue
+    pub fn watch_queue(&self, name: &'static str, monitor: QueueMonitor) {
+        self.with_service(name, |service| {
+            service.queue = Some(QueueProgress {
+                received: monitor.stats().received,
+                monitor,
+                since: Instant::now(),
+            })
+        })
+    }
+
+    fn with_service<F>(&self, name: &'static str, f: F)
+    where
+        F: FnOnce(&mut WatchedService),
+    {
+        let mut services = self.services.lock().unwrap();
+        let index = match services.iter().position(|service| service.name == name) {
+            Some(index) => index,
+            None => {
+                services.push(WatchedService {
+                    name,
+                    heartbeat: None,
+                    queue: None,
+                    stuck: false,
+                });
+                services.len() - 1
+            }
+        };
+        f(&mut services[index])
+    }
+}
+
+impl Heartbeat {
+    pub fn check_in(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+}
+
+/// log the services newly stuck and the ones responding again, returns
+/// whether a service got stuck since the last check
+fn check(logger: &Logger, services: &mut [WatchedService], timeout: Duration) -> bool {
+    let now = Instant::now();
+    let mut newly_stuck = false;
+    for service in services.iter_mut() {
+        let reason = service.stuck_reason(now, timeout);
+        match (reason, service.stuck) {
+            (Some(reason), false) => {
+                error!(
+                    logger,
+                    "task not responding";
+                    "stuck_task" => service.name,
+                    "reason" => reason,
+                );
+                newly_stuck = true;
+            }
+            (None, true) => info!(logger, "task responding again"; "stuck_task" => service.name),
+            _ => {}
+        }
+        service.stuck = reason.is_some();
+    }
+
+    if newly_stuck {
+        for service in services.iter() {
+            if let Some(queue) = service.queue.as_ref() {
+                let stats = queue.monitor.stats();
+                error!(
+                    logger,
+                    "queue state";
+                    "queue" => service.name,
+                    "depth" => stats.depth,
+                    "capacity" => stats.capacity,
+                    "dropped" => stats.dropped,
+                    "unread_for" => %humantime::format_duration(now.duration_since(queue.since)),
+                );
+            }
+        }
+    }
+    newly_stuck
+}
+
+impl WatchedService {
+    fn stuck_reason(&mut self, now: Instant, timeout: Duration) -> Option<&'static str> {
+        if let Some(queue) = self.queue.as_mut() {
+            let stats = queue.monitor.stats();
+            if stats.depth == 0 || stats.received != queue.received {
+                queue.received = stats.received;
+                queue.since = now;
+            } else if now.duration_since(queue.since) > timeout {
+                return Some("messages are waiting in its queue and none was read");
+            }
+        }
+        match self.heartbeat.as_ref() {
+            Some(heartbeat) if heartbeat.elapsed() > timeout => {
+                Some("its runtime did not check in")
+            }
+            _ => None,
+        }
+    }
+}